@@ -0,0 +1,15 @@
+-- Up migration
+CREATE TABLE dead_letters (
+    id BIGSERIAL PRIMARY KEY,
+    queue_name VARCHAR(255) NOT NULL,
+    msg_id BIGINT NOT NULL,
+    read_ct INTEGER NOT NULL,
+    enqueued_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    message JSONB NOT NULL,
+    reason TEXT NOT NULL,
+    dead_lettered_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    replayed_at TIMESTAMP WITH TIME ZONE,
+    replayed_msg_id BIGINT
+);
+
+CREATE INDEX idx_dead_letters_pending ON dead_letters(id) WHERE replayed_at IS NULL;
@@ -0,0 +1,13 @@
+-- Up migration
+CREATE TABLE rollout_instances (
+    rollout_id VARCHAR(255) NOT NULL,
+    position INTEGER NOT NULL,
+    inst_id VARCHAR(255) NOT NULL,
+    namespace VARCHAR(255) NOT NULL,
+    state VARCHAR(32) NOT NULL DEFAULT 'pending',
+    msg_id BIGINT,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (rollout_id, inst_id)
+);
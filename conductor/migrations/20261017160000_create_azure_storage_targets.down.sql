@@ -0,0 +1,2 @@
+-- Down migration
+DROP TABLE azure_storage_targets;
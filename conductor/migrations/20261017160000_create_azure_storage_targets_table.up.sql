@@ -0,0 +1,7 @@
+-- Up migration
+CREATE TABLE azure_storage_targets (
+    namespace VARCHAR(255) PRIMARY KEY,
+    storage_account VARCHAR(255) NOT NULL,
+    resource_group_prefix VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
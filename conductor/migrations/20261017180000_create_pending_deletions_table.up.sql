@@ -0,0 +1,8 @@
+-- Up migration
+CREATE TABLE pending_deletions (
+    namespace VARCHAR(255) PRIMARY KEY,
+    msg_id BIGINT NOT NULL,
+    was_stopped BOOLEAN NOT NULL,
+    delete_after TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Down migration
+ALTER TABLE deleted_instances DROP COLUMN backups_path;
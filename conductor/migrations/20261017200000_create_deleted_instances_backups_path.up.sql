@@ -0,0 +1,2 @@
+-- Up migration
+ALTER TABLE deleted_instances ADD COLUMN backups_path TEXT;
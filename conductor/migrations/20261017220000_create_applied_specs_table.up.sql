@@ -0,0 +1,6 @@
+-- Up migration
+CREATE TABLE applied_specs (
+    namespace VARCHAR(255) PRIMARY KEY,
+    spec_hash VARCHAR(32) NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
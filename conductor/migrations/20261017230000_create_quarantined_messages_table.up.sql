@@ -0,0 +1,15 @@
+-- Up migration
+CREATE TABLE quarantined_messages (
+    id BIGSERIAL PRIMARY KEY,
+    queue_name VARCHAR(255) NOT NULL,
+    msg_id BIGINT NOT NULL,
+    read_ct INTEGER NOT NULL,
+    enqueued_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    payload JSONB NOT NULL,
+    error TEXT NOT NULL,
+    quarantined_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    replayed_at TIMESTAMP WITH TIME ZONE,
+    replayed_msg_id BIGINT
+);
+
+CREATE INDEX idx_quarantined_messages_pending ON quarantined_messages(id) WHERE replayed_at IS NULL;
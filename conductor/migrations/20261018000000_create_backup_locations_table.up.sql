@@ -0,0 +1,7 @@
+-- Up migration
+CREATE TABLE backup_locations (
+    namespace VARCHAR(255) PRIMARY KEY,
+    aws_region VARCHAR(255) NOT NULL,
+    backup_archive_bucket VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Down migration
+DROP TABLE namespace_mappings;
@@ -0,0 +1,8 @@
+-- Up migration
+CREATE TABLE namespace_mappings (
+    requested_namespace VARCHAR(255) NOT NULL,
+    instance_id VARCHAR(255) NOT NULL,
+    namespace VARCHAR(63) NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (requested_namespace, instance_id)
+);
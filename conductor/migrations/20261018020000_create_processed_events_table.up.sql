@@ -0,0 +1,20 @@
+-- Up migration
+CREATE TABLE processed_events (
+    id BIGSERIAL PRIMARY KEY,
+    queue_name VARCHAR(255) NOT NULL,
+    msg_id BIGINT NOT NULL,
+    read_ct INTEGER NOT NULL,
+    namespace VARCHAR(255) NOT NULL,
+    org_id VARCHAR(255) NOT NULL,
+    inst_id VARCHAR(255) NOT NULL,
+    event_type VARCHAR(64) NOT NULL,
+    outcome VARCHAR(16) NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    error TEXT,
+    processed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_processed_events_namespace ON processed_events(namespace, processed_at);
+CREATE INDEX idx_processed_events_inst_id ON processed_events(inst_id, processed_at);
+CREATE INDEX idx_processed_events_msg_id ON processed_events(msg_id);
+CREATE INDEX idx_processed_events_processed_at ON processed_events(processed_at);
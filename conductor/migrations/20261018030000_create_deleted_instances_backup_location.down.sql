@@ -0,0 +1,3 @@
+-- Down migration
+ALTER TABLE deleted_instances DROP COLUMN backup_archive_bucket;
+ALTER TABLE deleted_instances DROP COLUMN aws_region;
@@ -0,0 +1,3 @@
+-- Up migration
+ALTER TABLE deleted_instances ADD COLUMN aws_region VARCHAR(255);
+ALTER TABLE deleted_instances ADD COLUMN backup_archive_bucket VARCHAR(255);
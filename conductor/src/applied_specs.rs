@@ -0,0 +1,89 @@
+use crate::errors::ConductorError;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+// Sort the keys of objects, serde_json keeps them in insertion order
+fn sorted(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), sorted(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.iter().map(sorted).collect()),
+        value => value.clone(),
+    }
+}
+
+// The CoreDB as hashed, serialized with sorted keys so identical CoreDBs hash the same
+fn canonical(coredb: &Value) -> String {
+    sorted(coredb).to_string()
+}
+
+/// Whether the CoreDB is the one last applied to the namespace, so a retried event does not
+/// patch the CoreDB again
+pub async fn is_last_applied(
+    db_pool: &PgPool,
+    namespace: &str,
+    coredb: &Value,
+) -> Result<bool, ConductorError> {
+    let row = sqlx::query(
+        "SELECT spec_hash = md5($2) AS identical FROM applied_specs WHERE namespace = $1",
+    )
+    .bind(namespace)
+    .bind(canonical(coredb))
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::AppliedSpecError(e.to_string()))?;
+    Ok(row.is_some_and(|row| row.get("identical")))
+}
+
+/// Record the hash of the CoreDB applied to the namespace
+pub async fn record_applied(
+    db_pool: &PgPool,
+    namespace: &str,
+    coredb: &Value,
+) -> Result<(), ConductorError> {
+    sqlx::query(
+        "INSERT INTO applied_specs (namespace, spec_hash) VALUES ($1, md5($2)) \
+         ON CONFLICT (namespace) DO UPDATE \
+         SET spec_hash = EXCLUDED.spec_hash, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(namespace)
+    .bind(canonical(coredb))
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::AppliedSpecError(e.to_string()))?;
+    Ok(())
+}
+
+/// Forget the CoreDB applied to the namespace, when the namespace is deleted or the CoreDB
+/// is patched by an event which does not apply a whole spec (hibernation, undelete, resize,
+/// upgrade), so the next Create or Update event is applied again
+pub async fn delete_applied(db_pool: &PgPool, namespace: &str) -> Result<(), ConductorError> {
+    sqlx::query("DELETE FROM applied_specs WHERE namespace = $1")
+        .bind(namespace)
+        .execute(db_pool)
+        .await
+        .map_err(|e| ConductorError::AppliedSpecError(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical() {
+        let a: Value =
+            serde_json::from_str(r#"{"spec": {"replicas": 1, "storage": "10Gi"}}"#).unwrap();
+        let b: Value =
+            serde_json::from_str(r#"{"spec": {"storage": "10Gi", "replicas": 1}}"#).unwrap();
+        assert_eq!(canonical(&a), canonical(&b));
+    }
+}
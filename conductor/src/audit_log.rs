@@ -0,0 +1,241 @@
+use crate::errors::ConductorError;
+use crate::types::CRUDevent;
+use chrono::{DateTime, FixedOffset};
+use pgmq::Message;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::cell::RefCell;
+use std::future::Future;
+use std::time::Duration;
+
+pub const OUTCOME_SUCCEEDED: &str = "succeeded";
+// Processing failed with an error which is retried once the message is visible again
+pub const OUTCOME_REQUEUED: &str = "requeued";
+pub const OUTCOME_FAILED: &str = "failed";
+
+tokio::task_local! {
+    // The outcome and error of a failure handled while processing a message
+    static HANDLED_FAILURE: RefCell<Option<(&'static str, String)>>;
+}
+
+// The most events a query returns
+const MAX_LIMIT: i64 = 1000;
+
+/// A message conductor processed, with how processing it went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessedEvent {
+    pub id: i64,
+    pub queue_name: String,
+    pub msg_id: i64,
+    pub read_ct: i32,
+    pub namespace: String,
+    pub org_id: String,
+    pub inst_id: String,
+    pub event_type: String,
+    pub outcome: String,
+    pub duration_ms: i64,
+    pub error: Option<String>,
+    pub processed_at: String,
+}
+
+/// Filters of the processed events to list, newest first. Times are RFC 3339 timestamps, a
+/// query with another time does not deserialize.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProcessedEventsQuery {
+    pub namespace: Option<String>,
+    pub org_id: Option<String>,
+    pub inst_id: Option<String>,
+    pub msg_id: Option<i64>,
+    pub since: Option<DateTime<FixedOffset>>,
+    pub until: Option<DateTime<FixedOffset>>,
+    pub limit: Option<i64>,
+}
+
+impl ProcessedEventsQuery {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(100).clamp(1, MAX_LIMIT)
+    }
+}
+
+/// What is recorded about a message, taken before processing consumes it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessingRecord {
+    pub queue_name: String,
+    pub msg_id: i64,
+    pub read_ct: i32,
+    pub namespace: String,
+    pub org_id: String,
+    pub inst_id: String,
+    pub event_type: String,
+}
+
+impl ProcessingRecord {
+    pub fn new(queue_name: &str, read_msg: &Message<CRUDevent>) -> Self {
+        let event = &read_msg.message;
+        ProcessingRecord {
+            queue_name: queue_name.to_owned(),
+            msg_id: read_msg.msg_id,
+            read_ct: read_msg.read_ct,
+            namespace: event.namespace.clone(),
+            org_id: event.org_id.clone(),
+            inst_id: event.inst_id.clone(),
+            event_type: format!("{:?}", event.event_type),
+        }
+    }
+}
+
+/// Note a failure which was handled without failing the processing of the message, e.g. by
+/// requeueing or archiving it. Does nothing outside of `track_failures`.
+pub fn note_failure(outcome: &'static str, error: &str) {
+    let _ = HANDLED_FAILURE.try_with(|failure| {
+        *failure.borrow_mut() = Some((outcome, error.to_owned()));
+    });
+}
+
+/// Run the processing of a message, returns its output with the last failure noted while it ran
+pub async fn track_failures<F: Future>(fut: F) -> (F::Output, Option<(&'static str, String)>) {
+    HANDLED_FAILURE
+        .scope(RefCell::new(None), async {
+            let output = fut.await;
+            let failure = HANDLED_FAILURE.with(|failure| failure.borrow_mut().take());
+            (output, failure)
+        })
+        .await
+}
+
+/// Record the outcome of processing a message, the error is the one processing failed with
+pub async fn record_processed(
+    db_pool: &PgPool,
+    record: &ProcessingRecord,
+    duration: Duration,
+    outcome: &str,
+    error: Option<&str>,
+) -> Result<(), ConductorError> {
+    sqlx::query(
+        "INSERT INTO processed_events (queue_name, msg_id, read_ct, namespace, org_id, inst_id, \
+         event_type, outcome, duration_ms, error) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&record.queue_name)
+    .bind(record.msg_id)
+    .bind(record.read_ct)
+    .bind(&record.namespace)
+    .bind(&record.org_id)
+    .bind(&record.inst_id)
+    .bind(&record.event_type)
+    .bind(outcome)
+    .bind(duration.as_millis() as i64)
+    .bind(error)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::AuditLogError(e.to_string()))?;
+    Ok(())
+}
+
+/// List processed events matching the query, newest first
+pub async fn list_processed(
+    db_pool: &PgPool,
+    query: &ProcessedEventsQuery,
+) -> Result<Vec<ProcessedEvent>, ConductorError> {
+    let rows = sqlx::query(
+        "SELECT id, queue_name, msg_id, read_ct, namespace, org_id, inst_id, event_type, \
+         outcome, duration_ms, error, processed_at::text AS processed_at \
+         FROM processed_events \
+         WHERE ($1::text IS NULL OR namespace = $1) \
+         AND ($2::text IS NULL OR org_id = $2) \
+         AND ($3::text IS NULL OR inst_id = $3) \
+         AND ($4::bigint IS NULL OR msg_id = $4) \
+         AND ($5::text IS NULL OR processed_at >= $5::timestamptz) \
+         AND ($6::text IS NULL OR processed_at < $6::timestamptz) \
+         ORDER BY id DESC LIMIT $7",
+    )
+    .bind(&query.namespace)
+    .bind(&query.org_id)
+    .bind(&query.inst_id)
+    .bind(query.msg_id)
+    .bind(query.since.map(|since| since.to_rfc3339()))
+    .bind(query.until.map(|until| until.to_rfc3339()))
+    .bind(query.limit())
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| ConductorError::AuditLogError(e.to_string()))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| ProcessedEvent {
+            id: row.get("id"),
+            queue_name: row.get("queue_name"),
+            msg_id: row.get("msg_id"),
+            read_ct: row.get("read_ct"),
+            namespace: row.get("namespace"),
+            org_id: row.get("org_id"),
+            inst_id: row.get("inst_id"),
+            event_type: row.get("event_type"),
+            outcome: row.get("outcome"),
+            duration_ms: row.get("duration_ms"),
+            error: row.get("error"),
+            processed_at: row.get("processed_at"),
+        })
+        .collect())
+}
+
+/// Delete the processed events older than the retention, returns how many were deleted
+pub async fn prune_processed(db_pool: &PgPool, retention: Duration) -> Result<u64, ConductorError> {
+    let result = sqlx::query(
+        "DELETE FROM processed_events \
+         WHERE processed_at < CURRENT_TIMESTAMP - make_interval(secs => $1)",
+    )
+    .bind(retention.as_secs() as f64)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::AuditLogError(e.to_string()))?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_limit() {
+        let query: ProcessedEventsQuery =
+            serde_json::from_value(serde_json::json!({"namespace": "org-acme-inst-db"})).unwrap();
+        assert_eq!(query.namespace.as_deref(), Some("org-acme-inst-db"));
+        assert_eq!(query.limit(), 100);
+        let query = ProcessedEventsQuery {
+            limit: Some(1_000_000),
+            ..Default::default()
+        };
+        assert_eq!(query.limit(), MAX_LIMIT);
+        let query = ProcessedEventsQuery {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(query.limit(), 1);
+
+        let query: ProcessedEventsQuery =
+            serde_json::from_value(serde_json::json!({"since": "2026-10-17T02:00:00Z"})).unwrap();
+        assert!(query.since.is_some());
+        let invalid = serde_json::from_value::<ProcessedEventsQuery>(
+            serde_json::json!({"since": "yesterday"}),
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_track_failures() {
+        let (output, failure) = track_failures(async { 1 }).await;
+        assert_eq!(output, 1);
+        assert_eq!(failure, None);
+
+        let (_, failure) = track_failures(async {
+            note_failure(OUTCOME_REQUEUED, "timed out");
+            note_failure(OUTCOME_FAILED, "invalid spec");
+        })
+        .await;
+        assert_eq!(failure, Some((OUTCOME_FAILED, "invalid spec".to_owned())));
+
+        // Outside of a tracked task, failures are not noted
+        note_failure(OUTCOME_FAILED, "ignored");
+    }
+}
@@ -2,14 +2,26 @@
 use aws_config::SdkConfig;
 use aws_sdk_cloudformation::{
     config::Region,
-    types::{Capability, Parameter, StackStatus},
+    types::{Capability, Parameter, Stack, StackEvent, StackStatus, Tag},
     Client,
 };
-use log::{error, info};
+use log::{error, info, warn};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use crate::errors::ConductorError;
 
+const TEMPLATE_NAME: &str = "conductor-cf-template-v3.yaml";
+
+// The template used with EKS Pod Identity. Its role trusts pods.eks.amazonaws.com instead of
+// the OIDC provider of the cluster, and it creates the Pod Identity association of the
+// service account, so it takes the name of the cluster as an additional parameter.
+const POD_IDENTITY_TEMPLATE_NAME: &str = "conductor-cf-template-pod-identity-v1.yaml";
+
+// Stacks are tagged with the template they were created or last updated from, since the
+// template URL of a stack can not be described
+const TEMPLATE_TAG: &str = "conductor-template";
+
 #[derive(Clone)]
 pub struct CloudFormationParams {
     pub backups_bucket_name: String,
@@ -19,11 +31,21 @@ pub struct CloudFormationParams {
     pub role_name: String,
     pub namespace: String,
     pub service_account_name: String,
+    /// The EKS cluster to create the Pod Identity association in, None when the role is
+    /// assumed through IRSA
+    pub cluster_name: Option<String>,
 }
 
 impl CloudFormationParams {
+    fn template_name(&self) -> &'static str {
+        match self.cluster_name {
+            Some(_) => POD_IDENTITY_TEMPLATE_NAME,
+            None => TEMPLATE_NAME,
+        }
+    }
+
     fn parameters(self) -> Vec<Parameter> {
-        vec![
+        let mut parameters = vec![
             Parameter::builder()
                 .parameter_key("BackupsBucketName")
                 .parameter_value(self.backups_bucket_name)
@@ -52,10 +74,175 @@ impl CloudFormationParams {
                 .parameter_key("ServiceAccountName")
                 .parameter_value(self.service_account_name)
                 .build(),
-        ]
+        ];
+        if let Some(cluster_name) = self.cluster_name {
+            parameters.push(
+                Parameter::builder()
+                    .parameter_key("ClusterName")
+                    .parameter_value(cluster_name)
+                    .build(),
+            );
+        }
+        parameters
+    }
+
+    // The keys of the parameters of an existing stack which differ from the desired ones
+    fn drifted_parameters(&self, current: &[Parameter]) -> Vec<String> {
+        let current: BTreeMap<_, _> = current
+            .iter()
+            .map(|p| (p.parameter_key.as_deref(), p.parameter_value.as_deref()))
+            .collect();
+        self.clone()
+            .parameters()
+            .iter()
+            .filter(|p| {
+                current.get(&p.parameter_key.as_deref()).copied().flatten()
+                    != p.parameter_value.as_deref()
+            })
+            .filter_map(|p| p.parameter_key.clone())
+            .collect()
+    }
+}
+
+fn template_url(
+    cloudformation_template_bucket: &str,
+    aws_region: &str,
+    template_name: &str,
+) -> String {
+    // If region is us-east-1, we don't need to specify the region in the template url
+    if aws_region == "us-east-1" {
+        format!(
+            "https://{}.s3.amazonaws.com/{}",
+            cloudformation_template_bucket, template_name
+        )
+    } else {
+        format!(
+            "https://{}.s3.{}.amazonaws.com/{}",
+            cloudformation_template_bucket, aws_region, template_name
+        )
     }
 }
 
+fn template_tag(template_name: &str) -> Tag {
+    Tag::builder()
+        .key(TEMPLATE_TAG)
+        .value(template_name)
+        .build()
+}
+
+// How many times a stack which failed to be deleted is deleted again, before the failure is
+// reported to control plane
+const MAX_DELETE_ATTEMPTS: usize = 2;
+
+/// Where a stack is in its lifecycle, from its status
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackPhase {
+    /// The stack does not exist, or was deleted
+    Absent,
+    /// The stack is being created or updated
+    InProgress,
+    /// A failed creation or update of the stack is being rolled back
+    RollingBack,
+    /// The stack was created or updated, or a failed update was rolled back
+    Ready,
+    /// The stack failed to be created, it has to be deleted before it can be created again
+    CreateFailed,
+    /// The stack failed to be updated and could not be rolled back
+    UpdateFailed,
+    /// The stack is being deleted
+    Deleting,
+    /// Some resources of the stack could not be deleted
+    DeleteFailed,
+}
+
+impl StackPhase {
+    pub fn from_status(status: Option<&StackStatus>) -> Self {
+        let Some(status) = status else {
+            return StackPhase::Absent;
+        };
+        match status.as_str() {
+            "DELETE_COMPLETE" => StackPhase::Absent,
+            "CREATE_COMPLETE"
+            | "UPDATE_COMPLETE"
+            | "UPDATE_ROLLBACK_COMPLETE"
+            | "IMPORT_COMPLETE"
+            | "IMPORT_ROLLBACK_COMPLETE" => StackPhase::Ready,
+            "CREATE_FAILED" | "ROLLBACK_COMPLETE" | "ROLLBACK_FAILED" => StackPhase::CreateFailed,
+            "DELETE_IN_PROGRESS" => StackPhase::Deleting,
+            "DELETE_FAILED" => StackPhase::DeleteFailed,
+            status if status.ends_with("ROLLBACK_IN_PROGRESS") => StackPhase::RollingBack,
+            status if status.ends_with("_IN_PROGRESS") => StackPhase::InProgress,
+            _ => StackPhase::UpdateFailed,
+        }
+    }
+
+    fn of(stack: Option<&Stack>) -> Self {
+        StackPhase::from_status(stack.and_then(|stack| stack.stack_status.as_ref()))
+    }
+
+    /// Whether the stack is changing, it is checked again once the event is requeued
+    pub fn is_transitioning(&self) -> bool {
+        matches!(
+            self,
+            StackPhase::InProgress | StackPhase::RollingBack | StackPhase::Deleting
+        )
+    }
+}
+
+// Whether the stack has to be updated to match the desired parameters and template
+fn stack_drift(stack: &Stack, params: &CloudFormationParams) -> Vec<String> {
+    let mut drift = params.drifted_parameters(stack.parameters.as_deref().unwrap_or_default());
+    let template = stack
+        .tags
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|tag| tag.key.as_deref() == Some(TEMPLATE_TAG))
+        .and_then(|tag| tag.value.as_deref());
+    if template != Some(params.template_name()) {
+        drift.push("template".to_string());
+    }
+    drift
+}
+
+// The reason of the first resource which failed in the latest operation on the stack. Events
+// are listed newest first, the latest operation started with the newest stack event in
+// progress which is not a rollback or a cleanup.
+fn first_failure(events: &[StackEvent], stack_name: &str) -> Option<String> {
+    let operation_start = |event: &StackEvent| {
+        event.logical_resource_id.as_deref() == Some(stack_name)
+            && event.resource_status.as_ref().is_some_and(|status| {
+                let status = status.as_str();
+                status.ends_with("_IN_PROGRESS")
+                    && !status.contains("ROLLBACK")
+                    && !status.contains("CLEANUP")
+            })
+    };
+    let operation_len = events
+        .iter()
+        .position(operation_start)
+        .map_or(events.len(), |start| start + 1);
+    events[..operation_len]
+        .iter()
+        .rfind(|event| {
+            event
+                .resource_status
+                .as_ref()
+                .is_some_and(|status| status.as_str().ends_with("_FAILED"))
+                && event
+                    .resource_status_reason
+                    .as_deref()
+                    .is_some_and(|reason| !reason.contains("cancelled"))
+        })
+        .map(|event| {
+            format!(
+                "{}: {}",
+                event.logical_resource_id.as_deref().unwrap_or_default(),
+                event.resource_status_reason.as_deref().unwrap_or_default()
+            )
+        })
+}
+
 pub struct AWSConfigState {
     pub cf_client: Arc<Client>,
     pub cf_config: Arc<SdkConfig>,
@@ -71,23 +258,125 @@ impl AWSConfigState {
         }
     }
 
-    pub async fn does_stack_exist(&self, stack_name: &str) -> bool {
-        let describe_stacks_result = self
+    // Describe a stack, None when it does not exist
+    async fn describe_stack(&self, stack_name: &str) -> Result<Option<Stack>, ConductorError> {
+        match self
             .cf_client
             .describe_stacks()
             .stack_name(stack_name)
             .send()
-            .await;
+            .await
+        {
+            Ok(result) => Ok(result.stacks.and_then(|stacks| stacks.into_iter().next())),
+            Err(err) if format!("{:?}", err).contains("does not exist") => Ok(None),
+            Err(err) => {
+                error!("Error describing stack: {:?}", err);
+                Err(ConductorError::AwsError(Box::new(err.into())))
+            }
+        }
+    }
 
-        match describe_stacks_result {
-            Ok(result) => {
-                info!("Stack {:?} exists", stack_name);
-                result.stacks.is_some()
+    // How many times the deletion of a stack failed, from the events of the stack itself
+    async fn delete_failures(&self, stack_name: &str) -> usize {
+        match self
+            .cf_client
+            .describe_stack_events()
+            .stack_name(stack_name)
+            .send()
+            .await
+        {
+            Ok(output) => output
+                .stack_events
+                .unwrap_or_default()
+                .iter()
+                .filter(|event| {
+                    event.logical_resource_id.as_deref() == Some(stack_name)
+                        && event
+                            .resource_status
+                            .as_ref()
+                            .is_some_and(|status| status.as_str() == "DELETE_FAILED")
+                })
+                .count(),
+            Err(err) => {
+                warn!("Error describing events of stack {}: {:?}", stack_name, err);
+                0
+            }
+        }
+    }
+
+    // Why a stack failed: the reason of the first resource which failed, or of the stack
+    async fn failure_reason(&self, stack_name: &str, stack: Option<&Stack>) -> String {
+        let stack_reason = stack
+            .and_then(|stack| stack.stack_status_reason.clone())
+            .unwrap_or_else(|| "unknown reason".to_string());
+        let events = match self
+            .cf_client
+            .describe_stack_events()
+            .stack_name(stack_name)
+            .send()
+            .await
+        {
+            Ok(output) => output.stack_events.unwrap_or_default(),
+            Err(err) => {
+                warn!("Error describing events of stack {}: {:?}", stack_name, err);
+                return stack_reason;
+            }
+        };
+        first_failure(&events, stack_name).unwrap_or(stack_reason)
+    }
+
+    // The error reported to control plane for a stack which failed
+    async fn stack_failed(&self, stack_name: &str, stack: Option<&Stack>) -> ConductorError {
+        let status = stack
+            .and_then(|stack| stack.stack_status.as_ref())
+            .map(|status| status.as_str().to_string())
+            .unwrap_or_default();
+        let reason = self.failure_reason(stack_name, stack).await;
+        error!(
+            "Stack {} failed in status {}: {}",
+            stack_name, status, reason
+        );
+        ConductorError::CloudFormationStackFailed {
+            stack_name: stack_name.to_string(),
+            status,
+            reason,
+        }
+    }
+
+    // Check a stack which was created or updated. A stack which is still changing is checked
+    // again once the event is requeued, a stack which failed to be created is deleted, so it
+    // is created again when control plane retries the event.
+    async fn settle_stack(&self, stack_name: &str) -> Result<(), ConductorError> {
+        let stack = self.describe_stack(stack_name).await?;
+        match StackPhase::of(stack.as_ref()) {
+            StackPhase::Ready => Ok(()),
+            // Checked again once the event is requeued
+            phase if phase.is_transitioning() => Ok(()),
+            StackPhase::CreateFailed => {
+                let err = self.stack_failed(stack_name, stack.as_ref()).await;
+                self.delete_stack(stack_name).await?;
+                Err(err)
             }
-            Err(_) => false,
+            _ => Err(self.stack_failed(stack_name, stack.as_ref()).await),
         }
     }
 
+    async fn delete_stack(&self, stack_name: &str) -> Result<(), ConductorError> {
+        self.cf_client
+            .delete_stack()
+            .stack_name(stack_name)
+            .send()
+            .await
+            .map_err(|err| {
+                error!("Error deleting stack: {:?}", err);
+                ConductorError::AwsError(Box::new(err.into()))
+            })?;
+        info!("Deleting stack {}", stack_name);
+        Ok(())
+    }
+
+    /// Create the stack, or update it when its parameters or template differ from the
+    /// desired ones, e.g. after the read or write paths of an instance changed
     pub async fn create_cloudformation_stack(
         &self,
         stack_name: &str,
@@ -95,21 +384,12 @@ impl AWSConfigState {
         cloudformation_template_bucket: String,
         aws_region: String,
     ) -> Result<(), ConductorError> {
-        // If region is us-east-1, we don't need to specify the region in the template url
-        let template_url = if aws_region == "us-east-1" {
-            format!(
-                "https://{}.s3.amazonaws.com/{}",
-                cloudformation_template_bucket, "conductor-cf-template-v3.yaml"
-            )
-        } else {
-            format!(
-                "https://{}.s3.{}.amazonaws.com/{}",
-                cloudformation_template_bucket, aws_region, "conductor-cf-template-v3.yaml"
-            )
-        };
-
+        let template_name = params.template_name();
+        let template_url =
+            template_url(&cloudformation_template_bucket, &aws_region, template_name);
         let parameters = params.clone().parameters();
-        if !self.does_stack_exist(stack_name).await {
+
+        let Some(stack) = self.describe_stack(stack_name).await? else {
             // todo(nhudson): We need to add tags to the stack
             // get with @sjmiller609 to figure out how we want
             // to tag these CF stacks.
@@ -120,50 +400,104 @@ impl AWSConfigState {
                 .template_url(template_url)
                 .set_parameters(Some(parameters))
                 .capabilities(Capability::CapabilityNamedIam)
+                .tags(template_tag(template_name))
                 .send()
                 .await;
 
-            match create_stack_result {
+            return match create_stack_result {
                 Ok(result) => {
                     info!("Created stack: {:?}", result.stack_id);
-                    Ok(())
+                    self.settle_stack(stack_name).await
                 }
                 Err(err) => {
                     error!("Error creating stack: {:?}", err);
                     Err(ConductorError::AwsError(Box::new(err.into())))
                 }
+            };
+        };
+
+        // Stacks which are changing are checked again once the event is requeued
+        match StackPhase::of(Some(&stack)) {
+            StackPhase::Ready => {}
+            phase if phase.is_transitioning() => {
+                info!("Stack {} is {:?}, no-op", stack_name, phase);
+                return Ok(());
             }
-        } else {
+            StackPhase::CreateFailed => {
+                let err = self.stack_failed(stack_name, Some(&stack)).await;
+                self.delete_stack(stack_name).await?;
+                return Err(err);
+            }
+            _ => return Err(self.stack_failed(stack_name, Some(&stack)).await),
+        }
+
+        let drift = stack_drift(&stack, params);
+        if drift.is_empty() {
             info!("Stack {:?} already exists, no-op", stack_name);
-            Ok(())
+            return Ok(());
+        }
+        info!(
+            "Updating stack {}, drifted: {}",
+            stack_name,
+            drift.join(", ")
+        );
+        let update_stack_result = self
+            .cf_client
+            .update_stack()
+            .stack_name(stack_name)
+            .template_url(template_url)
+            .set_parameters(Some(parameters))
+            .capabilities(Capability::CapabilityNamedIam)
+            .tags(template_tag(template_name))
+            .send()
+            .await;
+
+        match update_stack_result {
+            Ok(result) => {
+                info!("Updated stack: {:?}", result.stack_id);
+                self.settle_stack(stack_name).await
+            }
+            // Parameters which only differ in how they are described do not change the stack
+            Err(err) if format!("{:?}", err).contains("No updates are to be performed") => {
+                info!("Stack {:?} is up to date, no-op", stack_name);
+                Ok(())
+            }
+            Err(err) => {
+                error!("Error updating stack: {:?}", err);
+                Err(ConductorError::AwsError(Box::new(err.into())))
+            }
         }
     }
 
+    /// Delete the stack, deleting it again when it failed to be deleted. Returns the phase of
+    /// the stack, which is Deleting until it is deleted, or transitioning when it can not be
+    /// deleted yet. The stack is checked again once the event is requeued.
     pub async fn delete_cloudformation_stack(
         &self,
         stack_name: &str,
-    ) -> Result<(), ConductorError> {
-        if self.does_stack_exist(stack_name).await {
-            let delete_stack_result = self
-                .cf_client
-                .delete_stack()
-                .stack_name(stack_name)
-                .send()
-                .await;
-
-            match delete_stack_result {
-                Ok(_) => {
-                    info!("Deleted stack: {:?}", stack_name);
-                    Ok(())
-                }
-                Err(err) => {
-                    error!("Error deleting stack: {:?}", err);
-                    Err(ConductorError::AwsError(Box::new(err.into())))
+    ) -> Result<StackPhase, ConductorError> {
+        let stack = self.describe_stack(stack_name).await?;
+        match StackPhase::of(stack.as_ref()) {
+            StackPhase::Absent => {
+                info!("Stack {:?} doesn't exist, no-op", stack_name);
+                Ok(StackPhase::Absent)
+            }
+            phase if phase.is_transitioning() => {
+                info!("Stack {} is {:?}, not deleting yet", stack_name, phase);
+                Ok(phase)
+            }
+            StackPhase::DeleteFailed
+                if self.delete_failures(stack_name).await >= MAX_DELETE_ATTEMPTS =>
+            {
+                Err(self.stack_failed(stack_name, stack.as_ref()).await)
+            }
+            phase => {
+                if phase == StackPhase::DeleteFailed {
+                    warn!("Stack {} failed to be deleted, retrying", stack_name);
                 }
+                self.delete_stack(stack_name).await?;
+                Ok(StackPhase::Deleting)
             }
-        } else {
-            info!("Stack {:?} doesn't exist, no-op", stack_name);
-            Ok(())
         }
     }
 
@@ -212,3 +546,125 @@ impl AWSConfigState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> CloudFormationParams {
+        CloudFormationParams {
+            backups_bucket_name: "backups".to_string(),
+            storage_bucket_name: "storage".to_string(),
+            read_path_prefix: "v2/org-acme-inst-db".to_string(),
+            write_path_prefix: "v2/org-acme-inst-db".to_string(),
+            role_name: "org-acme-inst-db-iam".to_string(),
+            namespace: "org-acme-inst-db".to_string(),
+            service_account_name: "org-acme-inst-db".to_string(),
+            cluster_name: None,
+        }
+    }
+
+    #[test]
+    fn test_stack_drift() {
+        let stack = Stack::builder()
+            .set_parameters(Some(params().parameters()))
+            .tags(template_tag(TEMPLATE_NAME))
+            .build();
+        assert!(stack_drift(&stack, &params()).is_empty());
+
+        let moved = CloudFormationParams {
+            read_path_prefix: "v2/org-acme-inst-other".to_string(),
+            ..params()
+        };
+        assert_eq!(stack_drift(&stack, &moved), vec!["ReadPathPrefix"]);
+
+        // Stacks created before they were tagged with their template are updated once
+        let untagged = Stack::builder()
+            .set_parameters(Some(params().parameters()))
+            .build();
+        assert_eq!(stack_drift(&untagged, &params()), vec!["template"]);
+
+        // Switching an instance to Pod Identity updates its stack to the other template
+        let pod_identity = CloudFormationParams {
+            cluster_name: Some("data-1".to_string()),
+            ..params()
+        };
+        assert_eq!(
+            stack_drift(&stack, &pod_identity),
+            vec!["ClusterName", "template"]
+        );
+    }
+
+    #[test]
+    fn test_stack_phase() {
+        let phase = |status: &str| StackPhase::from_status(Some(&StackStatus::from(status)));
+        assert_eq!(StackPhase::from_status(None), StackPhase::Absent);
+        assert_eq!(phase("DELETE_COMPLETE"), StackPhase::Absent);
+        assert_eq!(phase("CREATE_IN_PROGRESS"), StackPhase::InProgress);
+        assert_eq!(
+            phase("UPDATE_COMPLETE_CLEANUP_IN_PROGRESS"),
+            StackPhase::InProgress
+        );
+        assert_eq!(phase("ROLLBACK_IN_PROGRESS"), StackPhase::RollingBack);
+        assert_eq!(
+            phase("UPDATE_ROLLBACK_IN_PROGRESS"),
+            StackPhase::RollingBack
+        );
+        assert_eq!(phase("UPDATE_ROLLBACK_COMPLETE"), StackPhase::Ready);
+        assert_eq!(phase("ROLLBACK_COMPLETE"), StackPhase::CreateFailed);
+        assert_eq!(phase("UPDATE_ROLLBACK_FAILED"), StackPhase::UpdateFailed);
+        assert_eq!(phase("DELETE_IN_PROGRESS"), StackPhase::Deleting);
+        assert_eq!(phase("DELETE_FAILED"), StackPhase::DeleteFailed);
+        assert!(phase("DELETE_IN_PROGRESS").is_transitioning());
+        assert!(!phase("DELETE_FAILED").is_transitioning());
+    }
+
+    fn event(logical_id: &str, status: &str, reason: Option<&str>) -> StackEvent {
+        StackEvent::builder()
+            .logical_resource_id(logical_id)
+            .resource_status(status.into())
+            .set_resource_status_reason(reason.map(String::from))
+            .build()
+    }
+
+    #[test]
+    fn test_first_failure() {
+        let stack = "org-acme-inst-db-cf";
+        // Newest first: an update which failed, after a create which failed earlier
+        let events = vec![
+            event(stack, "UPDATE_ROLLBACK_COMPLETE", None),
+            event(
+                stack,
+                "UPDATE_ROLLBACK_IN_PROGRESS",
+                Some("resources failed"),
+            ),
+            event("Policy", "UPDATE_FAILED", Some("Resource update cancelled")),
+            event("Role", "UPDATE_FAILED", Some("role is invalid")),
+            event(stack, "UPDATE_IN_PROGRESS", Some("User Initiated")),
+            event(stack, "CREATE_COMPLETE", None),
+            event("Bucket", "CREATE_FAILED", Some("bucket exists")),
+            event(stack, "CREATE_IN_PROGRESS", Some("User Initiated")),
+        ];
+        assert_eq!(
+            first_failure(&events, stack),
+            Some("Role: role is invalid".to_string())
+        );
+        assert_eq!(first_failure(&events[..2], stack), None);
+        assert_eq!(
+            first_failure(&events[5..], stack),
+            Some("Bucket: bucket exists".to_string())
+        );
+    }
+
+    #[test]
+    fn test_template_url() {
+        assert_eq!(
+            template_url("templates", "us-east-1", TEMPLATE_NAME),
+            "https://templates.s3.amazonaws.com/conductor-cf-template-v3.yaml"
+        );
+        assert_eq!(
+            template_url("templates", "us-west-2", TEMPLATE_NAME),
+            "https://templates.s3.us-west-2.amazonaws.com/conductor-cf-template-v3.yaml"
+        );
+    }
+}
@@ -1,2 +1,3 @@
 pub mod azure_error;
+pub mod storage_targets;
 pub mod uami_builder;
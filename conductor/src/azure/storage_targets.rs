@@ -0,0 +1,157 @@
+use crate::errors::ConductorError;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::collections::BTreeMap;
+
+/// The storage account an instance's backups are written to, and the prefix of the resource
+/// group its managed identity is created in
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct AzureStorageTarget {
+    pub storage_account: String,
+    pub resource_group_prefix: String,
+}
+
+/// A target of the mapping, settings which are not set fall back to AZURE_STORAGE_ACCOUNT
+/// and AZURE_RESOURCE_GROUP_PREFIX
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct AzureStorageMappingTarget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_account: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_group_prefix: Option<String>,
+}
+
+/// Spreads instances across storage accounts and resource groups, so large data planes do
+/// not hit the request limits of a single storage account. Targets of an organization take
+/// precedence over the ones of a data plane.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct AzureStorageMapping {
+    #[serde(default)]
+    pub orgs: BTreeMap<String, AzureStorageMappingTarget>,
+    #[serde(default)]
+    pub data_planes: BTreeMap<String, AzureStorageMappingTarget>,
+}
+
+impl AzureStorageMapping {
+    /// The target of a new instance
+    pub fn select(
+        &self,
+        org_id: &str,
+        data_plane_id: &str,
+        default: &AzureStorageTarget,
+    ) -> AzureStorageTarget {
+        let Some(target) = self
+            .orgs
+            .get(org_id)
+            .or_else(|| self.data_planes.get(data_plane_id))
+        else {
+            return default.clone();
+        };
+        AzureStorageTarget {
+            storage_account: target
+                .storage_account
+                .clone()
+                .unwrap_or_else(|| default.storage_account.clone()),
+            resource_group_prefix: target
+                .resource_group_prefix
+                .clone()
+                .unwrap_or_else(|| default.resource_group_prefix.clone()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let targets = self.orgs.iter().chain(self.data_planes.iter());
+        for (key, target) in targets {
+            let fields = [&target.storage_account, &target.resource_group_prefix];
+            if fields.iter().any(|field| field.as_deref() == Some("")) {
+                return Err(format!(
+                    "AZURE_STORAGE_MAPPING target of {} has an empty setting",
+                    key
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The target of an instance. The target is recorded the first time an instance is seen, so
+/// changes to the mapping do not move the backups of existing instances. It is kept after the
+/// instance is deleted, restores read the backups left behind from there.
+pub async fn instance_storage_target(
+    db_pool: &PgPool,
+    namespace: &str,
+    candidate: &AzureStorageTarget,
+) -> Result<AzureStorageTarget, ConductorError> {
+    sqlx::query(
+        "INSERT INTO azure_storage_targets (namespace, storage_account, resource_group_prefix) \
+         VALUES ($1, $2, $3) ON CONFLICT (namespace) DO NOTHING",
+    )
+    .bind(namespace)
+    .bind(&candidate.storage_account)
+    .bind(&candidate.resource_group_prefix)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::AzureStorageTargetError(e.to_string()))?;
+
+    recorded_storage_target(db_pool, namespace)
+        .await?
+        .ok_or_else(|| {
+            ConductorError::AzureStorageTargetError(format!(
+                "storage target of {} not found",
+                namespace
+            ))
+        })
+}
+
+/// The target recorded for an instance, None for instances which were not seen yet
+pub async fn recorded_storage_target(
+    db_pool: &PgPool,
+    namespace: &str,
+) -> Result<Option<AzureStorageTarget>, ConductorError> {
+    let row = sqlx::query(
+        "SELECT storage_account, resource_group_prefix FROM azure_storage_targets \
+         WHERE namespace = $1",
+    )
+    .bind(namespace)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::AzureStorageTargetError(e.to_string()))?;
+    Ok(row.map(|row| AzureStorageTarget {
+        storage_account: row.get("storage_account"),
+        resource_group_prefix: row.get("resource_group_prefix"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let mapping: AzureStorageMapping = serde_json::from_str(
+            r#"{
+                "orgs": {"org_big": {"storage_account": "tembobig", "resource_group_prefix": "cdb-big"}},
+                "data_planes": {"eus-2": {"storage_account": "temboeus2"}}
+            }"#,
+        )
+        .unwrap();
+        assert!(mapping.validate().is_ok());
+        let default = AzureStorageTarget {
+            storage_account: "tembo".to_string(),
+            resource_group_prefix: "cdb-plat-eus".to_string(),
+        };
+
+        assert_eq!(
+            mapping.select("org_big", "eus-2", &default).storage_account,
+            "tembobig"
+        );
+        let target = mapping.select("org_acme", "eus-2", &default);
+        assert_eq!(target.storage_account, "temboeus2");
+        assert_eq!(target.resource_group_prefix, "cdb-plat-eus");
+        assert_eq!(mapping.select("org_acme", "eus-1", &default), default);
+
+        let invalid: AzureStorageMapping =
+            serde_json::from_str(r#"{"orgs": {"org_acme": {"storage_account": ""}}}"#).unwrap();
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::errors::ConductorError;
+use crate::types::{BackupLocation, CRUDevent, Event};
+use sqlx::{PgPool, Row};
+
+/// The region and bucket the backups of an instance are written to
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceBackupLocation {
+    pub aws_region: String,
+    pub backup_archive_bucket: String,
+}
+
+impl InstanceBackupLocation {
+    /// The location asked for by an event, settings which are not set fall back to AWS_REGION
+    /// and BACKUP_ARCHIVE_BUCKET
+    pub fn with_override(&self, location: &BackupLocation) -> Self {
+        let setting = |setting: &Option<String>, default: &String| {
+            setting
+                .clone()
+                .filter(|setting| !setting.is_empty())
+                .unwrap_or_else(|| default.clone())
+        };
+        InstanceBackupLocation {
+            aws_region: setting(&location.region, &self.aws_region),
+            backup_archive_bucket: setting(&location.bucket, &self.backup_archive_bucket),
+        }
+    }
+}
+
+/// The location of the instance of an event. The location an instance is created or restored
+/// with is recorded once the instance was applied, so the events which follow, e.g. Delete,
+/// use it without carrying it. Instances which were created without a location use the
+/// defaults of conductor.
+pub async fn instance_backup_location(
+    db_pool: &PgPool,
+    event: &CRUDevent,
+    default: &InstanceBackupLocation,
+) -> Result<InstanceBackupLocation, ConductorError> {
+    let row = sqlx::query(
+        "SELECT aws_region, backup_archive_bucket FROM backup_locations WHERE namespace = $1",
+    )
+    .bind(&event.namespace)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::BackupLocationError(e.to_string()))?;
+    Ok(match (row, &event.backup_location, &event.event_type) {
+        (Some(row), _, _) => InstanceBackupLocation {
+            aws_region: row.get("aws_region"),
+            backup_archive_bucket: row.get("backup_archive_bucket"),
+        },
+        (None, Some(location), Event::Create | Event::Restore) => default.with_override(location),
+        (None, _, _) => default.clone(),
+    })
+}
+
+/// Record the location of an instance which was created or restored. The location of an
+/// existing instance is not changed.
+pub async fn record_backup_location(
+    db_pool: &PgPool,
+    namespace: &str,
+    location: &InstanceBackupLocation,
+) -> Result<(), ConductorError> {
+    sqlx::query(
+        "INSERT INTO backup_locations (namespace, aws_region, backup_archive_bucket) \
+         VALUES ($1, $2, $3) ON CONFLICT (namespace) DO NOTHING",
+    )
+    .bind(namespace)
+    .bind(&location.aws_region)
+    .bind(&location.backup_archive_bucket)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::BackupLocationError(e.to_string()))?;
+    Ok(())
+}
+
+/// Forget the location of a deleted instance
+pub async fn delete_backup_location(
+    db_pool: &PgPool,
+    namespace: &str,
+) -> Result<(), ConductorError> {
+    sqlx::query("DELETE FROM backup_locations WHERE namespace = $1")
+        .bind(namespace)
+        .execute(db_pool)
+        .await
+        .map_err(|e| ConductorError::BackupLocationError(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_override() {
+        let default = InstanceBackupLocation {
+            aws_region: "us-east-1".to_string(),
+            backup_archive_bucket: "tembo-backups".to_string(),
+        };
+        let location = default.with_override(&BackupLocation {
+            region: Some("eu-central-1".to_string()),
+            bucket: Some("tembo-backups-eu".to_string()),
+        });
+        assert_eq!(location.aws_region, "eu-central-1");
+        assert_eq!(location.backup_archive_bucket, "tembo-backups-eu");
+
+        let location = default.with_override(&BackupLocation {
+            region: Some("".to_string()),
+            bucket: Some("tembo-backups-us".to_string()),
+        });
+        assert_eq!(location.aws_region, "us-east-1");
+        assert_eq!(location.backup_archive_bucket, "tembo-backups-us");
+
+        let location = default.with_override(&BackupLocation::default());
+        assert_eq!(location, default);
+    }
+}
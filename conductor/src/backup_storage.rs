@@ -0,0 +1,132 @@
+use crate::errors::ConductorError;
+use crate::types::CustomBackupStorage;
+use controller::apis::coredb_types::{
+    CoreDBSpec, S3Credentials, S3CredentialsAccessKeyId, S3CredentialsRegion,
+    S3CredentialsSecretAccessKey,
+};
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client};
+use log::info;
+
+/// The Secret holding the credentials of the backups bucket of the customer, in the namespace
+/// of the instance
+pub const BACKUP_CREDENTIALS_SECRET: &str = "backup-storage-credentials";
+
+const ACCESS_KEY_ID: &str = "ACCESS_KEY_ID";
+const SECRET_ACCESS_KEY: &str = "ACCESS_SECRET_KEY";
+const REGION: &str = "REGION";
+
+/// Store the credentials of the backups bucket of the customer in the namespace of the instance
+pub async fn apply_backup_credentials(
+    client: Client,
+    namespace: &str,
+    storage: &CustomBackupStorage,
+) -> Result<(), ConductorError> {
+    let secret_api: Api<Secret> = Api::namespaced(client, namespace);
+    let mut string_data = serde_json::Map::new();
+    string_data.insert(ACCESS_KEY_ID.into(), storage.access_key_id.clone().into());
+    string_data.insert(
+        SECRET_ACCESS_KEY.into(),
+        storage.secret_access_key.clone().into(),
+    );
+    if let Some(region) = &storage.region {
+        string_data.insert(REGION.into(), region.clone().into());
+    }
+    let secret = serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": {
+            "name": BACKUP_CREDENTIALS_SECRET,
+            "namespace": namespace,
+        },
+        "type": "Opaque",
+        "stringData": string_data,
+    });
+    info!("Applying backup storage credentials in {}", namespace);
+    secret_api
+        .patch(
+            BACKUP_CREDENTIALS_SECRET,
+            &PatchParams::apply("conductor").force(),
+            &Patch::Apply(&secret),
+        )
+        .await
+        .map_err(ConductorError::KubeError)?;
+    Ok(())
+}
+
+/// Back up the instance to the bucket of the customer, with the credentials of
+/// [`apply_backup_credentials`] instead of the role of the instance
+pub fn use_custom_backup_storage(
+    storage: &CustomBackupStorage,
+    namespace: &str,
+    coredb_spec: &mut CoreDBSpec,
+) {
+    let path = storage
+        .path
+        .clone()
+        .unwrap_or_else(|| format!("v2/{}", namespace));
+    let backup = &mut coredb_spec.backup;
+    backup.destinationPath = Some(format!(
+        "s3://{}/{}",
+        storage.bucket,
+        path.trim_matches('/')
+    ));
+    backup.endpoint_url = Some(storage.endpoint_url.clone());
+    // S3 compatible stores do not all support server side encryption
+    backup.encryption = None;
+    backup.s3_credentials = Some(S3Credentials {
+        access_key_id: Some(S3CredentialsAccessKeyId {
+            key: ACCESS_KEY_ID.to_string(),
+            name: BACKUP_CREDENTIALS_SECRET.to_string(),
+        }),
+        secret_access_key: Some(S3CredentialsSecretAccessKey {
+            key: SECRET_ACCESS_KEY.to_string(),
+            name: BACKUP_CREDENTIALS_SECRET.to_string(),
+        }),
+        region: storage.region.as_ref().map(|_| S3CredentialsRegion {
+            key: REGION.to_string(),
+            name: BACKUP_CREDENTIALS_SECRET.to_string(),
+        }),
+        ..Default::default()
+    });
+    backup.google_credentials = None;
+    backup.azure_credentials = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_use_custom_backup_storage() {
+        let storage = CustomBackupStorage {
+            endpoint_url: "https://minio.acme.com".to_string(),
+            bucket: "acme-backups".to_string(),
+            path: None,
+            region: Some("eu-west-1".to_string()),
+            access_key_id: "AKIA".to_string(),
+            secret_access_key: "secret".to_string(),
+        };
+        let mut spec = CoreDBSpec::default();
+        use_custom_backup_storage(&storage, "org-acme-inst-db", &mut spec);
+
+        assert_eq!(
+            spec.backup.destinationPath.as_deref(),
+            Some("s3://acme-backups/v2/org-acme-inst-db")
+        );
+        assert_eq!(
+            spec.backup.endpoint_url.as_deref(),
+            Some("https://minio.acme.com")
+        );
+        assert_eq!(spec.backup.encryption, None);
+        let credentials = spec.backup.s3_credentials.unwrap();
+        assert_eq!(credentials.inherit_from_iam_role, None);
+        assert_eq!(
+            credentials.secret_access_key.unwrap().name,
+            BACKUP_CREDENTIALS_SECRET
+        );
+        assert_eq!(credentials.region.unwrap().key, REGION);
+        assert!(!format!("{:?}", storage).contains("\"secret\""));
+    }
+}
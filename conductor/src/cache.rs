@@ -0,0 +1,311 @@
+use crate::errors::ConductorError;
+use crate::types::ConnectionInfo;
+use controller::apis::coredb_types::CoreDB;
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    runtime::{
+        reflector::{store::Writer, ObjectRef, Store},
+        watcher, WatchStreamExt,
+    },
+    Api, Client, Resource, ResourceExt,
+};
+use log::{debug, error, info};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use std::{fmt::Debug, hash::Hash, time::Duration};
+
+// How long to wait before restarting a failed watch
+const WATCH_RESTART_DELAY_SEC: u64 = 5;
+
+// Only the Secrets conductor reads are cached: the <name>-connection Secrets of the operator
+// and the <name>-app Secrets of CNPG
+const CONNECTION_SECRET_SELECTOR: &str = "app=coredb";
+const CNPG_SECRET_SELECTOR: &str = "cnpg.io/cluster";
+
+/// ResourceCache keeps watch-based copies of the CoreDBs and instance Secrets of the data
+/// plane, so reads do not go to the API server for every message and status update.
+///
+/// Reads fall back to the API server for objects not in the cache, e.g. before the initial
+/// list completed or right after an object was created.
+#[derive(Clone)]
+pub struct ResourceCache {
+    client: Client,
+    coredbs: Store<CoreDB>,
+    connection_secrets: Store<Secret>,
+    cnpg_secrets: Store<Secret>,
+    connections: ConnectionCache,
+}
+
+/// Connection info of instances by namespace, kept for a short time so sweeps over many
+/// instances do not read their Secrets again for every message. A zero TTL caches nothing.
+#[derive(Clone, Default)]
+pub struct ConnectionCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, (Instant, ConnectionInfo)>>>,
+}
+
+impl ConnectionCache {
+    pub fn new(ttl: Duration) -> Self {
+        ConnectionCache {
+            ttl,
+            entries: Arc::default(),
+        }
+    }
+
+    pub fn get(&self, namespace: &str, now: Instant) -> Option<ConnectionInfo> {
+        let mut entries = self.entries.lock().expect("connection cache lock");
+        match entries.get(namespace) {
+            Some((cached_at, conn_info)) if now.duration_since(*cached_at) < self.ttl => {
+                Some(conn_info.clone())
+            }
+            Some(_) => {
+                entries.remove(namespace);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, namespace: &str, conn_info: &ConnectionInfo, now: Instant) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.entries
+            .lock()
+            .expect("connection cache lock")
+            .insert(namespace.to_string(), (now, conn_info.clone()));
+    }
+
+    pub fn invalidate(&self, namespace: &str) {
+        self.entries
+            .lock()
+            .expect("connection cache lock")
+            .remove(namespace);
+    }
+}
+
+impl ResourceCache {
+    /// A cache which is never populated, all reads go to the API server
+    pub fn new(client: Client) -> Self {
+        ResourceCache {
+            client,
+            coredbs: Writer::default().as_reader(),
+            connection_secrets: Writer::default().as_reader(),
+            cnpg_secrets: Writer::default().as_reader(),
+            connections: ConnectionCache::default(),
+        }
+    }
+
+    /// Start watching CoreDBs and instance Secrets in all namespaces, the cache is kept up to
+    /// date in the background until the process exits
+    pub fn start(client: Client) -> Self {
+        info!("Starting resource cache");
+        let coredbs = Writer::default();
+        let connection_secrets = Writer::default();
+        let cnpg_secrets = Writer::default();
+        let cache = ResourceCache {
+            client: client.clone(),
+            coredbs: coredbs.as_reader(),
+            connection_secrets: connection_secrets.as_reader(),
+            cnpg_secrets: cnpg_secrets.as_reader(),
+            connections: ConnectionCache::default(),
+        };
+        tokio::spawn(reflect(
+            Api::<CoreDB>::all(client.clone()),
+            watcher::Config::default(),
+            coredbs,
+        ));
+        tokio::spawn(reflect(
+            Api::<Secret>::all(client.clone()),
+            watcher::Config::default().labels(CONNECTION_SECRET_SELECTOR),
+            connection_secrets,
+        ));
+        tokio::spawn(reflect(
+            Api::<Secret>::all(client),
+            watcher::Config::default().labels(CNPG_SECRET_SELECTOR),
+            cnpg_secrets,
+        ));
+        cache
+    }
+
+    /// Cache connection info for `ttl`
+    pub fn with_connection_ttl(mut self, ttl: Duration) -> Self {
+        self.connections = ConnectionCache::new(ttl);
+        self
+    }
+
+    pub fn connections(&self) -> &ConnectionCache {
+        &self.connections
+    }
+
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    pub async fn get_coredb(&self, namespace: &str, name: &str) -> Result<CoreDB, ConductorError> {
+        if let Some(coredb) = self.coredbs.get(&ObjectRef::new(name).within(namespace)) {
+            return Ok(coredb.as_ref().clone());
+        }
+        debug!("CoreDB {}/{} not cached, getting it", namespace, name);
+        let coredb_api: Api<CoreDB> = Api::namespaced(self.client.clone(), namespace);
+        Ok(coredb_api.get(name).await?)
+    }
+
+    pub async fn get_secret_opt(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<Secret>, ConductorError> {
+        let object_ref = ObjectRef::new(name).within(namespace);
+        if let Some(secret) = self
+            .connection_secrets
+            .get(&object_ref)
+            .or_else(|| self.cnpg_secrets.get(&object_ref))
+        {
+            return Ok(Some(secret.as_ref().clone()));
+        }
+        debug!("Secret {}/{} not cached, getting it", namespace, name);
+        let secret_api: Api<Secret> = Api::namespaced(self.client.clone(), namespace);
+        Ok(secret_api.get_opt(name).await?)
+    }
+}
+
+// Feed a watch of the api into the store, restarting the watch when it fails
+async fn reflect<K>(api: Api<K>, config: watcher::Config, mut writer: Writer<K>)
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+    K::DynamicType: Default + Eq + Hash + Clone,
+{
+    loop {
+        // Managed fields are never read from the cache, leave them out to save memory
+        let stream = watcher(api.clone(), config.clone())
+            .map_ok(|event| event.modify(|object| object.managed_fields_mut().clear()))
+            .map_ok(|event| {
+                writer.apply_watcher_event(&event);
+                event
+            })
+            .applied_objects();
+        futures::pin_mut!(stream);
+        while let Some(result) = stream.next().await {
+            if let Err(e) = result {
+                error!("Error watching {}: {}", K::kind(&Default::default()), e);
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(WATCH_RESTART_DELAY_SEC)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use controller::apis::coredb_types::CoreDBSpec;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn secret(namespace: &str, name: &str) -> Secret {
+        Secret {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                ..ObjectMeta::default()
+            },
+            ..Secret::default()
+        }
+    }
+
+    // A client which counts its requests, and answers every one of them with a Secret
+    fn counting_client(requests: Arc<AtomicUsize>) -> Client {
+        let service = tower::service_fn(move |_: http::Request<hyper::Body>| {
+            let requests = requests.clone();
+            async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let body = serde_json::to_vec(&secret("org-acme-inst-db", "db-app")).unwrap();
+                Ok::<_, std::convert::Infallible>(http::Response::new(hyper::Body::from(body)))
+            }
+        });
+        Client::new(service, "default")
+    }
+
+    #[tokio::test]
+    async fn test_resource_cache_reads() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let mut coredbs = Writer::default();
+        let mut connection_secrets = Writer::default();
+        let cache = ResourceCache {
+            coredbs: coredbs.as_reader(),
+            connection_secrets: connection_secrets.as_reader(),
+            ..ResourceCache::new(counting_client(requests.clone()))
+        };
+
+        let mut coredb = CoreDB::new("db", CoreDBSpec::default());
+        coredb.metadata.namespace = Some("org-acme-inst-db".to_string());
+        coredbs.apply_watcher_event(&watcher::Event::Applied(coredb));
+        let connection_secret = secret("org-acme-inst-db", "db-connection");
+        connection_secrets.apply_watcher_event(&watcher::Event::Applied(connection_secret.clone()));
+
+        // Cached objects are read without a request to the API server
+        let coredb = cache.get_coredb("org-acme-inst-db", "db").await.unwrap();
+        assert_eq!(coredb.name_any(), "db");
+        let cached = cache
+            .get_secret_opt("org-acme-inst-db", "db-connection")
+            .await
+            .unwrap();
+        assert_eq!(cached.unwrap().name_any(), "db-connection");
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        // Objects which are not cached are read from the API server
+        let fetched = cache
+            .get_secret_opt("org-acme-inst-db", "db-app")
+            .await
+            .unwrap();
+        assert_eq!(fetched.unwrap().name_any(), "db-app");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Deleted objects are no longer served from the cache
+        connection_secrets.apply_watcher_event(&watcher::Event::Deleted(connection_secret));
+        cache
+            .get_secret_opt("org-acme-inst-db", "db-connection")
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    fn conn_info(password: &str) -> ConnectionInfo {
+        ConnectionInfo {
+            host: "org-acme-inst-db.data-1.use1.tembo.io".to_string(),
+            pooler_host: None,
+            port: 5432,
+            user: "postgres".to_string(),
+            password: password.to_string(),
+            app_user: "app".to_string(),
+            app_password: "app".to_string(),
+            encryption: None,
+        }
+    }
+
+    #[test]
+    fn test_connection_cache() {
+        let cache = ConnectionCache::new(Duration::from_secs(30));
+        let now = Instant::now();
+        assert!(cache.get("org-acme-inst-db", now).is_none());
+
+        cache.insert("org-acme-inst-db", &conn_info("secret"), now);
+        let cached = cache.get("org-acme-inst-db", now + Duration::from_secs(10));
+        assert_eq!(cached.unwrap().password, "secret");
+        assert!(cache
+            .get("org-acme-inst-db", now + Duration::from_secs(30))
+            .is_none());
+
+        cache.insert("org-acme-inst-db", &conn_info("secret"), now);
+        cache.invalidate("org-acme-inst-db");
+        assert!(cache.get("org-acme-inst-db", now).is_none());
+
+        let disabled = ConnectionCache::new(Duration::ZERO);
+        disabled.insert("org-acme-inst-db", &conn_info("secret"), now);
+        assert!(disabled.get("org-acme-inst-db", now).is_none());
+    }
+}
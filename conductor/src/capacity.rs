@@ -0,0 +1,225 @@
+use crate::errors::ConductorError;
+use controller::apis::coredb_types::CoreDBSpec;
+use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::ListParams;
+use kube::{Api, Client};
+use std::collections::{BTreeMap, HashMap};
+
+// Decimal and binary suffixes of Kubernetes quantities, and the multiplier of each
+const QUANTITY_SUFFIXES: [(&str, f64); 13] = [
+    ("Ki", 1024.0),
+    ("Mi", 1_048_576.0),
+    ("Gi", 1_073_741_824.0),
+    ("Ti", 1_099_511_627_776.0),
+    ("Pi", 1_125_899_906_842_624.0),
+    ("Ei", 1_152_921_504_606_846_976.0),
+    ("m", 0.001),
+    ("k", 1e3),
+    ("M", 1e6),
+    ("G", 1e9),
+    ("T", 1e12),
+    ("P", 1e15),
+    ("E", 1e18),
+];
+
+/// The value of a Kubernetes quantity in its base unit, cores or bytes
+pub fn parse_quantity(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let (number, multiplier) = QUANTITY_SUFFIXES
+        .iter()
+        .find_map(|(suffix, multiplier)| {
+            quantity
+                .strip_suffix(suffix)
+                .map(|number| (number, *multiplier))
+        })
+        .unwrap_or((quantity, 1.0));
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .map(|number| number * multiplier)
+}
+
+/// CPU in millicores and memory in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Resources {
+    pub cpu_millis: i64,
+    pub memory_bytes: i64,
+}
+
+impl Resources {
+    fn from_quantities(quantities: Option<&BTreeMap<String, Quantity>>) -> Self {
+        let quantity = |name: &str| {
+            quantities
+                .and_then(|quantities| quantities.get(name))
+                .and_then(|quantity| parse_quantity(&quantity.0))
+                .unwrap_or_default()
+        };
+        Resources {
+            cpu_millis: (quantity("cpu") * 1000.0).ceil() as i64,
+            memory_bytes: quantity("memory").ceil() as i64,
+        }
+    }
+
+    fn saturating_sub(self, other: Resources) -> Self {
+        Resources {
+            cpu_millis: (self.cpu_millis - other.cpu_millis).max(0),
+            memory_bytes: (self.memory_bytes - other.memory_bytes).max(0),
+        }
+    }
+
+    fn add(self, other: Resources) -> Self {
+        Resources {
+            cpu_millis: self.cpu_millis + other.cpu_millis,
+            memory_bytes: self.memory_bytes + other.memory_bytes,
+        }
+    }
+
+    // How many times the request fits in these resources
+    fn fits(&self, request: &Resources) -> i64 {
+        let fits = |free: i64, requested: i64| match requested {
+            0 => i64::MAX,
+            requested => free / requested,
+        };
+        fits(self.cpu_millis, request.cpu_millis).min(fits(self.memory_bytes, request.memory_bytes))
+    }
+}
+
+/// The resources a Postgres pod of an instance requests, the limits when requests are not set
+pub fn requested_resources(spec: &CoreDBSpec) -> Resources {
+    let resources = &spec.resources;
+    Resources::from_quantities(resources.requests.as_ref().or(resources.limits.as_ref()))
+}
+
+/// Whether the pods of an instance can be scheduled on the free resources of the nodes. Returns
+/// the reason when they can not.
+pub fn check_fits(free: &[Resources], request: &Resources, replicas: i32) -> Option<String> {
+    let pods: i64 = free
+        .iter()
+        .map(|node| node.fits(request))
+        .fold(0, i64::saturating_add);
+    if pods >= replicas.max(1) as i64 {
+        return None;
+    }
+    let largest = free
+        .iter()
+        .copied()
+        .max_by_key(|node| (node.cpu_millis, node.memory_bytes))
+        .unwrap_or_default();
+    Some(format!(
+        "{} pods requesting {}m cpu and {} bytes of memory do not fit in the cluster, the node with the most free cpu has {}m cpu and {} bytes of memory free",
+        replicas.max(1),
+        request.cpu_millis,
+        request.memory_bytes,
+        largest.cpu_millis,
+        largest.memory_bytes
+    ))
+}
+
+// Nodes new pods can be scheduled on. Taints are not checked, instances may tolerate them.
+fn is_schedulable(node: &Node) -> bool {
+    let Some(spec) = &node.spec else {
+        return false;
+    };
+    let ready = node
+        .status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+        });
+    ready && !spec.unschedulable.unwrap_or(false)
+}
+
+/// The resources left on each schedulable node: its allocatable resources minus the requests
+/// of the pods running on it
+pub async fn free_resources(client: Client) -> Result<Vec<Resources>, ConductorError> {
+    let nodes = Api::<Node>::all(client.clone())
+        .list(&ListParams::default())
+        .await?;
+    let pods = Api::<Pod>::all(client)
+        .list(&ListParams::default().fields("status.phase!=Succeeded,status.phase!=Failed"))
+        .await?;
+
+    let mut requested: HashMap<String, Resources> = HashMap::new();
+    for pod in pods.items {
+        let Some(spec) = pod.spec else {
+            continue;
+        };
+        let Some(node_name) = spec.node_name else {
+            continue;
+        };
+        let pod_requests = spec
+            .containers
+            .iter()
+            .filter_map(|container| container.resources.as_ref())
+            .map(|resources| Resources::from_quantities(resources.requests.as_ref()))
+            .fold(Resources::default(), Resources::add);
+        let node = requested.entry(node_name).or_default();
+        *node = node.add(pod_requests);
+    }
+
+    Ok(nodes
+        .items
+        .iter()
+        .filter(|node| is_schedulable(node))
+        .map(|node| {
+            let allocatable = Resources::from_quantities(
+                node.status
+                    .as_ref()
+                    .and_then(|status| status.allocatable.as_ref()),
+            );
+            let name = node.metadata.name.clone().unwrap_or_default();
+            allocatable.saturating_sub(requested.get(&name).copied().unwrap_or_default())
+        })
+        .collect())
+}
+
+/// Whether a new instance can be scheduled, the reason when it can not
+pub async fn check_capacity(
+    client: Client,
+    spec: &CoreDBSpec,
+) -> Result<Option<String>, ConductorError> {
+    let free = free_resources(client).await?;
+    Ok(check_fits(&free, &requested_resources(spec), spec.replicas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("500m"), Some(0.5));
+        assert_eq!(parse_quantity("2"), Some(2.0));
+        assert_eq!(parse_quantity("512Mi"), Some(536_870_912.0));
+        assert_eq!(parse_quantity("1G"), Some(1e9));
+        assert_eq!(parse_quantity("lots"), None);
+    }
+
+    #[test]
+    fn test_check_fits() {
+        let request = Resources {
+            cpu_millis: 1000,
+            memory_bytes: 2 << 30,
+        };
+        let free = vec![
+            Resources {
+                cpu_millis: 1500,
+                memory_bytes: 8 << 30,
+            },
+            Resources {
+                cpu_millis: 4000,
+                memory_bytes: 1 << 30,
+            },
+        ];
+        assert_eq!(check_fits(&free, &request, 1), None);
+        let reason = check_fits(&free, &request, 2).unwrap();
+        assert!(reason.contains("2 pods requesting 1000m cpu"));
+        assert!(check_fits(&[], &request, 1).is_some());
+        assert_eq!(check_fits(&free, &Resources::default(), 3), None);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::errors::ConductorError;
+use crate::types::ConnectionInfo;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Oaep, RsaPublicKey};
+use sha2::Sha256;
+
+/// How the passwords of a ConnectionInfo are encrypted: RSA-OAEP with SHA-256, base64 encoded
+pub const ENCRYPTION_RSA_OAEP_SHA256: &str = "rsa-oaep-sha256";
+
+/// Encrypts the passwords of the connection info conductor reports, with the public key of
+/// control plane, so they are not stored in plaintext in the data plane events queue
+#[derive(Clone, Debug)]
+pub struct ConnectionEncryptor {
+    public_key: RsaPublicKey,
+}
+
+impl ConnectionEncryptor {
+    /// Read a PEM encoded RSA public key, either SubjectPublicKeyInfo or PKCS#1
+    pub fn from_pem(pem: &str) -> Result<Self, ConductorError> {
+        let public_key = RsaPublicKey::from_public_key_pem(pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+            .map_err(|e| ConductorError::ConnectionEncryptionError(e.to_string()))?;
+        Ok(ConnectionEncryptor { public_key })
+    }
+
+    /// The encryptor of CONNECTION_ENCRYPTION_PUBLIC_KEY, None when it is not set and
+    /// passwords are reported in plaintext
+    pub fn from_env() -> Result<Option<Self>, ConductorError> {
+        match std::env::var("CONNECTION_ENCRYPTION_PUBLIC_KEY") {
+            Ok(pem) if !pem.trim().is_empty() => Self::from_pem(&pem).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn encrypt_password(&self, password: &str) -> Result<String, ConductorError> {
+        let ciphertext = self
+            .public_key
+            .encrypt(
+                &mut rand::thread_rng(),
+                Oaep::new::<Sha256>(),
+                password.as_bytes(),
+            )
+            .map_err(|e| ConductorError::ConnectionEncryptionError(e.to_string()))?;
+        Ok(STANDARD.encode(ciphertext))
+    }
+
+    pub fn encrypt(&self, conn_info: ConnectionInfo) -> Result<ConnectionInfo, ConductorError> {
+        if conn_info.encryption.is_some() {
+            return Ok(conn_info);
+        }
+        Ok(ConnectionInfo {
+            password: self.encrypt_password(&conn_info.password)?,
+            app_password: self.encrypt_password(&conn_info.app_password)?,
+            encryption: Some(ENCRYPTION_RSA_OAEP_SHA256.to_string()),
+            ..conn_info
+        })
+    }
+}
+
+/// The connection info to report, encrypted when an encryptor is configured
+pub fn encrypt_connection(
+    encryptor: Option<&ConnectionEncryptor>,
+    conn_info: ConnectionInfo,
+) -> Result<ConnectionInfo, ConductorError> {
+    match encryptor {
+        Some(encryptor) => encryptor.encrypt(conn_info),
+        None => Ok(conn_info),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::{EncodePublicKey, LineEnding};
+    use rsa::RsaPrivateKey;
+
+    #[test]
+    fn test_encrypt_connection() {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let pem = private_key
+            .to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        let encryptor = ConnectionEncryptor::from_pem(&pem).unwrap();
+        let conn_info = ConnectionInfo {
+            host: "org-acme-inst-db.data-1.use1.tembo.io".to_string(),
+            pooler_host: None,
+            port: 5432,
+            user: "postgres".to_string(),
+            password: "postgres-password".to_string(),
+            app_user: "app".to_string(),
+            app_password: "app-password".to_string(),
+            encryption: None,
+        };
+
+        let plaintext = encrypt_connection(None, conn_info.clone()).unwrap();
+        assert_eq!(plaintext.password, "postgres-password");
+
+        let encrypted = encrypt_connection(Some(&encryptor), conn_info).unwrap();
+        assert_eq!(
+            encrypted.encryption.as_deref(),
+            Some(ENCRYPTION_RSA_OAEP_SHA256)
+        );
+        assert_eq!(encrypted.user, "postgres");
+        let decrypted = private_key
+            .decrypt(
+                Oaep::new::<Sha256>(),
+                &STANDARD.decode(&encrypted.app_password).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(decrypted, b"app-password");
+
+        assert!(ConnectionEncryptor::from_pem("not a key").is_err());
+    }
+}
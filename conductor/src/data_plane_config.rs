@@ -0,0 +1,554 @@
+use crate::azure::storage_targets::{AzureStorageMapping, AzureStorageTarget};
+use crate::errors::ConductorError;
+use crate::gcp::workload_identity_federation::GcpFederation;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+pub const AWS_IDENTITY_MODE_IRSA: &str = "irsa";
+pub const AWS_IDENTITY_MODE_POD_IDENTITY: &str = "pod-identity";
+pub const GCP_IDENTITY_MODE_GKE: &str = "gke";
+pub const GCP_IDENTITY_MODE_FEDERATION: &str = "federation";
+
+/// Settings which differ between data planes. They are read from the environment and, when
+/// CONTROL_PLANE_CONFIG_URL is set, overridden by the configuration the control plane serves
+/// for this data plane.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DataPlaneConfig {
+    pub data_plane_basedomain: String,
+    pub backup_archive_bucket: String,
+    pub storage_archive_bucket: String,
+    pub cf_template_bucket: String,
+    pub is_cloud_formation: bool,
+    pub aws_region: String,
+    // How instances assume their IAM role on AWS: "irsa" annotates their service account with
+    // the role ARN, "pod-identity" creates an EKS Pod Identity association in eks_cluster_name
+    pub aws_identity_mode: String,
+    pub eks_cluster_name: String,
+    pub is_gcp: bool,
+    pub gcp_project_id: String,
+    pub gcp_project_number: String,
+    // The Cloud KMS key backups are encrypted with on GCP, for customer-managed encryption keys
+    pub gcp_kms_key_name: String,
+    // How instances authenticate to GCS: "gke" binds their service account through GKE
+    // workload identity, "federation" through the Workload Identity Federation pool and
+    // provider trusting the tokens of the cluster, for data planes which do not run on GKE
+    pub gcp_identity_mode: String,
+    pub gcp_workload_identity_pool: String,
+    pub gcp_workload_identity_provider: String,
+    pub is_azure: bool,
+    pub azure_storage_account: String,
+    pub azure_subscription_id: String,
+    // This is necessary for working with multiple resource groups. Example format: cdb-plat-eus-dev
+    pub azure_resource_group_prefix: String,
+    pub azure_region: String,
+    // Storage accounts and resource groups of instances by organization or data plane,
+    // instead of AZURE_STORAGE_ACCOUNT and AZURE_RESOURCE_GROUP_PREFIX
+    #[serde(default)]
+    pub azure_storage_mapping: AzureStorageMapping,
+    pub is_loadbalancer_public: bool,
+    // Instances are hibernated for this long before Delete events destroy them, so accidental
+    // deletes can be undone. 0 deletes instances right away.
+    pub delete_grace_period_hours: u64,
+    // Whether new instances are checked to fit on the nodes of the cluster before they are
+    // applied. Clusters which scale their nodes up on demand leave it disabled.
+    pub check_cluster_capacity: bool,
+}
+
+impl DataPlaneConfig {
+    pub fn from_env() -> Self {
+        Self {
+            data_plane_basedomain: from_env_default("DATA_PLANE_BASEDOMAIN", ""),
+            backup_archive_bucket: from_env_default("BACKUP_ARCHIVE_BUCKET", ""),
+            storage_archive_bucket: from_env_default("STORAGE_ARCHIVE_BUCKET", ""),
+            cf_template_bucket: from_env_default("CF_TEMPLATE_BUCKET", ""),
+            is_cloud_formation: from_env_default("IS_CLOUD_FORMATION", "true")
+                .parse()
+                .expect("error parsing IS_CLOUD_FORMATION"),
+            aws_region: from_env_default("AWS_REGION", "us-east-1"),
+            aws_identity_mode: from_env_default("AWS_IDENTITY_MODE", AWS_IDENTITY_MODE_IRSA),
+            eks_cluster_name: from_env_default("EKS_CLUSTER_NAME", ""),
+            is_gcp: from_env_default("IS_GCP", "false")
+                .parse()
+                .expect("error parsing IS_GCP"),
+            gcp_project_id: from_env_default("GCP_PROJECT_ID", ""),
+            gcp_project_number: from_env_default("GCP_PROJECT_NUMBER", ""),
+            gcp_kms_key_name: from_env_default("GCP_KMS_KEY_NAME", ""),
+            gcp_identity_mode: from_env_default("GCP_IDENTITY_MODE", GCP_IDENTITY_MODE_GKE),
+            gcp_workload_identity_pool: from_env_default("GCP_WORKLOAD_IDENTITY_POOL", ""),
+            gcp_workload_identity_provider: from_env_default("GCP_WORKLOAD_IDENTITY_PROVIDER", ""),
+            is_azure: from_env_default("IS_AZURE", "false")
+                .parse()
+                .expect("error parsing IS_AZURE"),
+            azure_storage_account: from_env_default("AZURE_STORAGE_ACCOUNT", ""),
+            azure_subscription_id: from_env_default("AZURE_SUBSCRIPTION_ID", ""),
+            azure_resource_group_prefix: from_env_default("AZURE_RESOURCE_GROUP_PREFIX", ""),
+            azure_region: from_env_default("AZURE_REGION", ""),
+            azure_storage_mapping: serde_json::from_str(&from_env_default(
+                "AZURE_STORAGE_MAPPING",
+                "{}",
+            ))
+            .expect("error parsing AZURE_STORAGE_MAPPING"),
+            is_loadbalancer_public: from_env_default("IS_LOADBALANCER_PUBLIC", "true")
+                .parse()
+                .expect("error parsing IS_LOADBALANCER_PUBLIC"),
+            delete_grace_period_hours: from_env_default("DELETE_GRACE_PERIOD_HOURS", "0")
+                .parse()
+                .expect("error parsing DELETE_GRACE_PERIOD_HOURS"),
+            check_cluster_capacity: from_env_default("CHECK_CLUSTER_CAPACITY", "false")
+                .parse()
+                .expect("error parsing CHECK_CLUSTER_CAPACITY"),
+        }
+    }
+
+    /// The EKS cluster to create Pod Identity associations in, None when instances assume
+    /// their IAM role through IRSA
+    pub fn pod_identity_cluster(&self) -> Option<String> {
+        (self.aws_identity_mode == AWS_IDENTITY_MODE_POD_IDENTITY)
+            .then(|| self.eks_cluster_name.clone())
+    }
+
+    /// The Workload Identity Federation pool and provider instances authenticate to GCS
+    /// through, None when they use GKE workload identity
+    pub fn gcp_federation(&self) -> Option<GcpFederation> {
+        (self.gcp_identity_mode == GCP_IDENTITY_MODE_FEDERATION).then(|| GcpFederation {
+            project_number: self.gcp_project_number.clone(),
+            pool: self.gcp_workload_identity_pool.clone(),
+            provider: self.gcp_workload_identity_provider.clone(),
+        })
+    }
+
+    /// The storage account and resource group prefix of instances which are not mapped
+    pub fn default_azure_storage_target(&self) -> AzureStorageTarget {
+        AzureStorageTarget {
+            storage_account: self.azure_storage_account.clone(),
+            resource_group_prefix: self.azure_resource_group_prefix.clone(),
+        }
+    }
+
+    /// Override settings with the ones present in the configuration from the control plane
+    pub fn merge(&self, overrides: &Value) -> Result<Self, ConductorError> {
+        let Value::Object(overrides) = overrides else {
+            return Err(ConductorError::DataPlaneConfigError(
+                "data plane configuration must be a JSON object".to_string(),
+            ));
+        };
+        let mut merged = serde_json::to_value(self)?;
+        if let Value::Object(merged) = &mut merged {
+            for (key, value) in overrides {
+                if merged.contains_key(key) {
+                    merged.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let required = [
+            ("DATA_PLANE_BASEDOMAIN", &self.data_plane_basedomain),
+            ("BACKUP_ARCHIVE_BUCKET", &self.backup_archive_bucket),
+            ("STORAGE_ARCHIVE_BUCKET", &self.storage_archive_bucket),
+        ];
+        for (name, value) in required {
+            if value.is_empty() {
+                return Err(format!("{} must be set", name));
+            }
+        }
+
+        // CF_TEMPLATE_BUCKET is required when IS_CLOUD_FORMATION is enabled
+        if self.is_cloud_formation && self.cf_template_bucket.is_empty() {
+            return Err(
+                "CF_TEMPLATE_BUCKET is required when IS_CLOUD_FORMATION is true".to_string(),
+            );
+        }
+
+        // EKS_CLUSTER_NAME is required to create Pod Identity associations
+        match self.aws_identity_mode.as_str() {
+            AWS_IDENTITY_MODE_IRSA => {}
+            AWS_IDENTITY_MODE_POD_IDENTITY => {
+                if self.is_cloud_formation && self.eks_cluster_name.is_empty() {
+                    return Err(
+                        "EKS_CLUSTER_NAME is required when AWS_IDENTITY_MODE is pod-identity"
+                            .to_string(),
+                    );
+                }
+            }
+            mode => {
+                return Err(format!(
+                    "AWS_IDENTITY_MODE must be {} or {}, got {}",
+                    AWS_IDENTITY_MODE_IRSA, AWS_IDENTITY_MODE_POD_IDENTITY, mode
+                ))
+            }
+        }
+
+        // Only allow for setting one of IS_CLOUD_FORMATION, IS_GCP, or IS_AZURE to true
+        let cloud_providers = [self.is_cloud_formation, self.is_gcp, self.is_azure]
+            .iter()
+            .filter(|&&x| x)
+            .count();
+        if cloud_providers > 1 {
+            return Err(
+                "Only one of IS_CLOUD_FORMATION, IS_GCP, or IS_AZURE can be set to true"
+                    .to_string(),
+            );
+        }
+
+        // GCP_PROJECT_ID and GCP_PROJECT_NUMBER are required when IS_GCP is true
+        if self.is_gcp && (self.gcp_project_id.is_empty() || self.gcp_project_number.is_empty()) {
+            return Err(
+                "GCP_PROJECT_ID and GCP_PROJECT_NUMBER must be set if IS_GCP is true".to_string(),
+            );
+        }
+
+        // GCP_WORKLOAD_IDENTITY_POOL and GCP_WORKLOAD_IDENTITY_PROVIDER are required to
+        // bind instances through Workload Identity Federation
+        match self.gcp_identity_mode.as_str() {
+            GCP_IDENTITY_MODE_GKE => {}
+            GCP_IDENTITY_MODE_FEDERATION => {
+                if self.is_gcp
+                    && (self.gcp_workload_identity_pool.is_empty()
+                        || self.gcp_workload_identity_provider.is_empty())
+                {
+                    return Err("GCP_WORKLOAD_IDENTITY_POOL and GCP_WORKLOAD_IDENTITY_PROVIDER are required when GCP_IDENTITY_MODE is federation".to_string());
+                }
+            }
+            mode => {
+                return Err(format!(
+                    "GCP_IDENTITY_MODE must be {} or {}, got {}",
+                    GCP_IDENTITY_MODE_GKE, GCP_IDENTITY_MODE_FEDERATION, mode
+                ))
+            }
+        }
+
+        // GCP_KMS_KEY_NAME is the resource name of a Cloud KMS key
+        if !self.gcp_kms_key_name.is_empty()
+            && (!self.gcp_kms_key_name.starts_with("projects/")
+                || !self.gcp_kms_key_name.contains("/cryptoKeys/"))
+        {
+            return Err(format!(
+                "GCP_KMS_KEY_NAME must be formatted as projects/<project>/locations/<location>/keyRings/<key ring>/cryptoKeys/<key>, got {}",
+                self.gcp_kms_key_name
+            ));
+        }
+
+        validate_azure_environment(
+            self.is_azure,
+            &self.azure_storage_account,
+            &self.azure_subscription_id,
+            &self.azure_resource_group_prefix,
+            &self.azure_region,
+        )?;
+        self.azure_storage_mapping.validate()
+    }
+}
+
+/// Load the data plane configuration at startup. When CONTROL_PLANE_CONFIG_URL is set, the
+/// configuration is fetched from the control plane and re-fetched every
+/// CONTROL_PLANE_CONFIG_REFRESH_SEC seconds, so changes are picked up without a restart.
+pub async fn load_data_plane_config() -> Result<Arc<RwLock<DataPlaneConfig>>, ConductorError> {
+    let env_config = DataPlaneConfig::from_env();
+    let Ok(url) = env::var("CONTROL_PLANE_CONFIG_URL") else {
+        env_config
+            .validate()
+            .map_err(ConductorError::DataPlaneConfigError)?;
+        return Ok(Arc::new(RwLock::new(env_config)));
+    };
+
+    let http_client = reqwest::Client::new();
+    let token = env::var("CONTROL_PLANE_CONFIG_TOKEN").ok();
+    let config = fetch_data_plane_config(&http_client, &url, token.as_deref(), &env_config).await?;
+    info!("Loaded data plane configuration from {}", url);
+    let config = Arc::new(RwLock::new(config));
+
+    let refresh_interval_sec: u64 = from_env_default("CONTROL_PLANE_CONFIG_REFRESH_SEC", "60")
+        .parse()
+        .expect("error parsing CONTROL_PLANE_CONFIG_REFRESH_SEC");
+    if refresh_interval_sec > 0 {
+        let config = config.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(refresh_interval_sec)).await;
+                match fetch_data_plane_config(&http_client, &url, token.as_deref(), &env_config)
+                    .await
+                {
+                    Ok(latest) => {
+                        let mut current = config.write().expect("data plane config lock");
+                        if *current != latest {
+                            info!("Data plane configuration changed, reloading");
+                            *current = latest;
+                        }
+                    }
+                    Err(err) => {
+                        error!(
+                            "Failed to refresh data plane configuration, keeping the current one: {}",
+                            err
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    Ok(config)
+}
+
+async fn fetch_data_plane_config(
+    http_client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    env_config: &DataPlaneConfig,
+) -> Result<DataPlaneConfig, ConductorError> {
+    let mut request = http_client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let overrides: Value = request
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| ConductorError::DataPlaneConfigError(e.to_string()))?
+        .json()
+        .await
+        .map_err(|e| ConductorError::DataPlaneConfigError(e.to_string()))?;
+
+    let config = env_config.merge(&overrides)?;
+    config
+        .validate()
+        .map_err(ConductorError::DataPlaneConfigError)?;
+    Ok(config)
+}
+
+fn from_env_default(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_owned())
+}
+
+/// Validates that all required Azure environment variables are set when IS_AZURE is true.
+/// Returns Ok(()) if all variables are present and non-empty, or an error message listing missing variables.
+fn validate_azure_environment(
+    is_azure: bool,
+    azure_storage_account: &str,
+    azure_subscription_id: &str,
+    azure_resource_group_prefix: &str,
+    azure_region: &str,
+) -> Result<(), String> {
+    if !is_azure {
+        return Ok(());
+    }
+
+    let required_vars = [
+        ("AZURE_STORAGE_ACCOUNT", azure_storage_account),
+        ("AZURE_SUBSCRIPTION_ID", azure_subscription_id),
+        ("AZURE_RESOURCE_GROUP_PREFIX", azure_resource_group_prefix),
+        ("AZURE_REGION", azure_region),
+    ];
+
+    let missing_vars: Vec<&str> = required_vars
+        .iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(name, _)| *name)
+        .collect();
+
+    if missing_vars.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "The following required Azure environment variables are empty: {}",
+            missing_vars.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn aws_config() -> DataPlaneConfig {
+        DataPlaneConfig {
+            data_plane_basedomain: "data-1.use1.tembo.io".to_string(),
+            backup_archive_bucket: "backups".to_string(),
+            storage_archive_bucket: "storage".to_string(),
+            cf_template_bucket: "templates".to_string(),
+            is_cloud_formation: true,
+            aws_region: "us-east-1".to_string(),
+            aws_identity_mode: "irsa".to_string(),
+            eks_cluster_name: "".to_string(),
+            is_gcp: false,
+            gcp_project_id: "".to_string(),
+            gcp_project_number: "".to_string(),
+            gcp_kms_key_name: "".to_string(),
+            gcp_identity_mode: "gke".to_string(),
+            gcp_workload_identity_pool: "".to_string(),
+            gcp_workload_identity_provider: "".to_string(),
+            is_azure: false,
+            azure_storage_account: "".to_string(),
+            azure_subscription_id: "".to_string(),
+            azure_resource_group_prefix: "".to_string(),
+            azure_region: "".to_string(),
+            azure_storage_mapping: AzureStorageMapping::default(),
+            is_loadbalancer_public: true,
+            delete_grace_period_hours: 0,
+            check_cluster_capacity: false,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let cfg = aws_config();
+
+        let merged = cfg
+            .merge(&json!({
+                "backup_archive_bucket": "new-backups",
+                "is_loadbalancer_public": false,
+                "unknown_setting": "ignored"
+            }))
+            .unwrap();
+        assert_eq!(merged.backup_archive_bucket, "new-backups");
+        assert!(!merged.is_loadbalancer_public);
+        assert_eq!(merged.storage_archive_bucket, cfg.storage_archive_bucket);
+
+        assert!(cfg.merge(&json!({"is_gcp": "yes"})).is_err());
+        assert!(cfg.merge(&json!(["not", "an", "object"])).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(aws_config().validate().is_ok());
+
+        let cfg = DataPlaneConfig {
+            backup_archive_bucket: "".to_string(),
+            ..aws_config()
+        };
+        assert_eq!(
+            cfg.validate().unwrap_err(),
+            "BACKUP_ARCHIVE_BUCKET must be set"
+        );
+
+        let cfg = DataPlaneConfig {
+            cf_template_bucket: "".to_string(),
+            ..aws_config()
+        };
+        assert!(cfg.validate().is_err());
+
+        let cfg = DataPlaneConfig {
+            is_gcp: true,
+            ..aws_config()
+        };
+        assert!(cfg
+            .validate()
+            .unwrap_err()
+            .contains("Only one of IS_CLOUD_FORMATION"));
+
+        let cfg = DataPlaneConfig {
+            is_cloud_formation: false,
+            is_gcp: true,
+            ..aws_config()
+        };
+        assert!(cfg.validate().unwrap_err().contains("GCP_PROJECT_ID"));
+
+        let cfg = DataPlaneConfig {
+            gcp_kms_key_name: "backups".to_string(),
+            ..aws_config()
+        };
+        assert!(cfg.validate().unwrap_err().contains("GCP_KMS_KEY_NAME"));
+    }
+
+    #[test]
+    fn test_aws_identity_mode() {
+        assert_eq!(aws_config().pod_identity_cluster(), None);
+
+        let cfg = DataPlaneConfig {
+            aws_identity_mode: "pod-identity".to_string(),
+            ..aws_config()
+        };
+        assert!(cfg.validate().unwrap_err().contains("EKS_CLUSTER_NAME"));
+
+        let cfg = DataPlaneConfig {
+            eks_cluster_name: "data-1".to_string(),
+            ..cfg
+        };
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.pod_identity_cluster().as_deref(), Some("data-1"));
+
+        let cfg = DataPlaneConfig {
+            aws_identity_mode: "static".to_string(),
+            ..aws_config()
+        };
+        assert!(cfg.validate().unwrap_err().contains("AWS_IDENTITY_MODE"));
+    }
+
+    #[test]
+    fn test_gcp_identity_mode() {
+        let gcp_config = DataPlaneConfig {
+            is_cloud_formation: false,
+            is_gcp: true,
+            gcp_project_id: "tembo-data".to_string(),
+            gcp_project_number: "123456789".to_string(),
+            ..aws_config()
+        };
+        assert!(gcp_config.validate().is_ok());
+        assert_eq!(gcp_config.gcp_federation(), None);
+
+        let cfg = DataPlaneConfig {
+            gcp_identity_mode: "federation".to_string(),
+            ..gcp_config.clone()
+        };
+        assert!(cfg
+            .validate()
+            .unwrap_err()
+            .contains("GCP_WORKLOAD_IDENTITY_POOL"));
+
+        let cfg = DataPlaneConfig {
+            gcp_workload_identity_pool: "data-1".to_string(),
+            gcp_workload_identity_provider: "eks".to_string(),
+            ..cfg
+        };
+        assert!(cfg.validate().is_ok());
+        assert_eq!(
+            cfg.gcp_federation(),
+            Some(GcpFederation {
+                project_number: "123456789".to_string(),
+                pool: "data-1".to_string(),
+                provider: "eks".to_string(),
+            })
+        );
+
+        let cfg = DataPlaneConfig {
+            gcp_identity_mode: "keys".to_string(),
+            ..gcp_config
+        };
+        assert!(cfg.validate().unwrap_err().contains("GCP_IDENTITY_MODE"));
+    }
+
+    #[test]
+    fn test_azure_validation() {
+        // Test when Azure is disabled
+        assert!(validate_azure_environment(false, "", "", "", "").is_ok());
+
+        // Test when Azure is enabled and all variables are present
+        assert!(validate_azure_environment(
+            true,
+            "storage_account",
+            "subscription_id",
+            "resource_group",
+            "region"
+        )
+        .is_ok());
+
+        // Test when Azure is enabled and variables are missing
+        let err =
+            validate_azure_environment(true, "", "subscription_id", "resource_group", "region")
+                .unwrap_err();
+        assert!(err.contains("AZURE_STORAGE_ACCOUNT"));
+
+        // Test multiple missing variables
+        let err = validate_azure_environment(true, "", "", "resource_group", "").unwrap_err();
+        assert!(err.contains("AZURE_STORAGE_ACCOUNT"));
+        assert!(err.contains("AZURE_SUBSCRIPTION_ID"));
+        assert!(err.contains("AZURE_REGION"));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::errors::ConductorError;
+use log::info;
+use pgmq::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+/// A message which could not be processed, kept with its failure context so it can be
+/// inspected and replayed into its queue instead of being lost in the archive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub queue_name: String,
+    pub msg_id: i64,
+    pub read_ct: i32,
+    pub enqueued_at: String,
+    pub message: Value,
+    pub reason: String,
+    pub dead_lettered_at: String,
+    pub replayed_at: Option<String>,
+    pub replayed_msg_id: Option<i64>,
+}
+
+/// Record a message in the dead letter table, it still has to be removed from its queue
+pub async fn dead_letter<T: Serialize>(
+    db_pool: &PgPool,
+    queue_name: &str,
+    read_msg: &Message<T>,
+    reason: &str,
+) -> Result<i64, ConductorError> {
+    let message = serde_json::to_string(&read_msg.message)?;
+    let row = sqlx::query(
+        "INSERT INTO dead_letters (queue_name, msg_id, read_ct, enqueued_at, message, reason) \
+         VALUES ($1, $2, $3, $4::text::timestamptz, $5::jsonb, $6) RETURNING id",
+    )
+    .bind(queue_name)
+    .bind(read_msg.msg_id)
+    .bind(read_msg.read_ct)
+    .bind(read_msg.enqueued_at.to_rfc3339())
+    .bind(message)
+    .bind(reason)
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?;
+    Ok(row.get("id"))
+}
+
+/// List dead letters, oldest first. Replayed messages are only included when asked for.
+pub async fn list_dead_letters(
+    db_pool: &PgPool,
+    include_replayed: bool,
+) -> Result<Vec<DeadLetter>, ConductorError> {
+    let rows = sqlx::query(
+        "SELECT id, queue_name, msg_id, read_ct, enqueued_at::text AS enqueued_at, \
+         message::text AS message, reason, dead_lettered_at::text AS dead_lettered_at, \
+         replayed_at::text AS replayed_at, replayed_msg_id \
+         FROM dead_letters WHERE $1 OR replayed_at IS NULL ORDER BY id",
+    )
+    .bind(include_replayed)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?;
+
+    rows.iter()
+        .map(|row| {
+            let message: String = row.get("message");
+            Ok(DeadLetter {
+                id: row.get("id"),
+                queue_name: row.get("queue_name"),
+                msg_id: row.get("msg_id"),
+                read_ct: row.get("read_ct"),
+                enqueued_at: row.get("enqueued_at"),
+                message: serde_json::from_str(&message)?,
+                reason: row.get("reason"),
+                dead_lettered_at: row.get("dead_lettered_at"),
+                replayed_at: row.get("replayed_at"),
+                replayed_msg_id: row.get("replayed_msg_id"),
+            })
+        })
+        .collect()
+}
+
+/// Send a dead letter back to the queue it came from, as a new message with a fresh
+/// read count. Returns the id of the new message, or None when the dead letter does not
+/// exist or was already replayed. Sending and marking it replayed happen in one transaction,
+/// so a dead letter is never replayed twice.
+pub async fn replay_dead_letter(db_pool: &PgPool, id: i64) -> Result<Option<i64>, ConductorError> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?;
+
+    let Some(row) = sqlx::query(
+        "SELECT queue_name, message::text AS message FROM dead_letters \
+         WHERE id = $1 AND replayed_at IS NULL FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?
+    else {
+        return Ok(None);
+    };
+    let queue_name: String = row.get("queue_name");
+    let message: String = row.get("message");
+
+    let msg_id: i64 = sqlx::query_scalar("SELECT pgmq.send($1, $2::jsonb)")
+        .bind(&queue_name)
+        .bind(message)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?;
+    sqlx::query(
+        "UPDATE dead_letters SET replayed_at = CURRENT_TIMESTAMP, replayed_msg_id = $2 WHERE id = $1",
+    )
+    .bind(id)
+    .bind(msg_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ConductorError::DeadLetterError(e.to_string()))?;
+    info!(
+        "Replayed dead letter {} into queue {} as message {}",
+        id, queue_name, msg_id
+    );
+    Ok(Some(msg_id))
+}
@@ -0,0 +1,226 @@
+use crate::backup_location::InstanceBackupLocation;
+use crate::errors::ConductorError;
+use crate::types::{BackupLocation, CRUDevent};
+use log::info;
+use sqlx::{PgPool, Row};
+
+const KNOWN_PREFIXES: [&str; 3] = ["s3://", "gs://", "https://"];
+
+/// The path of backups within the backups bucket, e.g. `v2/org-acme-inst-db` for
+/// `s3://backups/v2/org-acme-inst-db`. On Azure the path is preceded by the storage account.
+pub fn path_in_bucket(backups_path: &str, bucket: &str) -> String {
+    let path = KNOWN_PREFIXES
+        .iter()
+        .find_map(|prefix| backups_path.strip_prefix(prefix))
+        .unwrap_or(backups_path);
+    match path.split_once(&format!("{}/", bucket)) {
+        Some((_, path)) => path.to_string(),
+        None => path.to_string(),
+    }
+}
+
+/// Where the backups of a deleted instance are
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeletedBackups {
+    /// The path of the backups within the backups bucket
+    pub path: String,
+    /// The region and bucket of the backups, None for instances deleted before it was recorded
+    pub location: Option<InstanceBackupLocation>,
+}
+
+/// Record where the backups of a deleted instance are, they are kept after the instance is
+/// deleted so it can be restored
+pub async fn record_backups_path(
+    db_pool: &PgPool,
+    namespace: &str,
+    backups_path: &str,
+    location: &InstanceBackupLocation,
+) -> Result<(), ConductorError> {
+    sqlx::query(
+        "UPDATE deleted_instances \
+         SET backups_path = $2, aws_region = $3, backup_archive_bucket = $4 \
+         WHERE namespace = $1",
+    )
+    .bind(namespace)
+    .bind(backups_path)
+    .bind(&location.aws_region)
+    .bind(&location.backup_archive_bucket)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::DeletedInstanceError(e.to_string()))?;
+    Ok(())
+}
+
+/// The backups of a deleted instance, None when the instance was not deleted or its backups
+/// were not recorded
+pub async fn deleted_backups(
+    db_pool: &PgPool,
+    namespace: &str,
+) -> Result<Option<DeletedBackups>, ConductorError> {
+    let row = sqlx::query(
+        "SELECT backups_path, aws_region, backup_archive_bucket FROM deleted_instances \
+         WHERE namespace = $1",
+    )
+    .bind(namespace)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::DeletedInstanceError(e.to_string()))?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let Some(path) = row.get::<Option<String>, _>("backups_path") else {
+        return Ok(None);
+    };
+    let aws_region: Option<String> = row.get("aws_region");
+    let backup_archive_bucket: Option<String> = row.get("backup_archive_bucket");
+    Ok(Some(DeletedBackups {
+        path,
+        location: aws_region.zip(backup_archive_bucket).map(
+            |(aws_region, backup_archive_bucket)| InstanceBackupLocation {
+                aws_region,
+                backup_archive_bucket,
+            },
+        ),
+    }))
+}
+
+// Whether the location asked for by a Restore event is the one of the backups it reads
+fn is_compatible(requested: &BackupLocation, location: &InstanceBackupLocation) -> bool {
+    let matches = |setting: &Option<String>, value: &String| {
+        setting
+            .as_ref()
+            .filter(|setting| !setting.is_empty())
+            .is_none_or(|setting| setting == value)
+    };
+    matches(&requested.region, &location.aws_region)
+        && matches(&requested.bucket, &location.backup_archive_bucket)
+}
+
+/// The `org-<org>-inst-` prefix of the namespaces of an organization
+fn org_prefix(namespace: &str) -> Option<&str> {
+    if !namespace.starts_with("org-") {
+        return None;
+    }
+    let end = namespace.find("-inst-")? + "-inst-".len();
+    Some(&namespace[..end])
+}
+
+/// Restore events of a deleted instance, whose restore.serverName is the namespace of the
+/// deleted instance, read the backups it left behind. Paths set on the event are kept. Only
+/// deleted instances of the organization of the event are restored. The restored instance
+/// backs up to the region and bucket of the deleted instance, which it reads from.
+pub async fn resolve_deleted_instance_restore(
+    db_pool: &PgPool,
+    event: &mut CRUDevent,
+) -> Result<(), ConductorError> {
+    let Some(restore) = event
+        .spec
+        .as_mut()
+        .and_then(|spec| spec.restore.as_mut())
+        .filter(|restore| restore.backups_path.is_none())
+    else {
+        return Ok(());
+    };
+    let Some(backups) = deleted_backups(db_pool, &restore.server_name).await? else {
+        return Ok(());
+    };
+    let same_org =
+        org_prefix(&event.namespace).is_some_and(|prefix| restore.server_name.starts_with(prefix));
+    if !same_org {
+        return Err(ConductorError::RestoreSourceRejected(format!(
+            "{} can not restore from {}, it is not an instance of the same organization",
+            event.namespace, restore.server_name
+        )));
+    }
+    if let Some(location) = &backups.location {
+        if let Some(requested) = event
+            .backup_location
+            .as_ref()
+            .filter(|requested| !is_compatible(requested, location))
+        {
+            return Err(ConductorError::RestoreSourceRejected(format!(
+                "{} can not back up to {:?}, the backups of {} are in bucket {} of {}",
+                event.namespace,
+                requested,
+                restore.server_name,
+                location.backup_archive_bucket,
+                location.aws_region
+            )));
+        }
+        event.backup_location = Some(BackupLocation {
+            region: Some(location.aws_region.clone()),
+            bucket: Some(location.backup_archive_bucket.clone()),
+        });
+    }
+    info!(
+        "Restoring {} from the backups of deleted instance {} in {}",
+        event.namespace, restore.server_name, backups.path
+    );
+    restore.backups_path = Some(backups.path.clone());
+    if event.backups_read_path.is_none() {
+        event.backups_read_path = Some(backups.path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_compatible() {
+        let location = InstanceBackupLocation {
+            aws_region: "eu-central-1".to_string(),
+            backup_archive_bucket: "tembo-backups-eu".to_string(),
+        };
+        assert!(is_compatible(&BackupLocation::default(), &location));
+        assert!(is_compatible(
+            &BackupLocation {
+                region: Some("eu-central-1".to_string()),
+                bucket: Some("".to_string()),
+            },
+            &location
+        ));
+        assert!(!is_compatible(
+            &BackupLocation {
+                region: None,
+                bucket: Some("tembo-backups".to_string()),
+            },
+            &location
+        ));
+    }
+
+    #[test]
+    fn test_org_prefix() {
+        assert_eq!(org_prefix("org-acme-inst-db"), Some("org-acme-inst-"));
+        assert_eq!(
+            org_prefix("org-acme-inst-db-inst-2"),
+            Some("org-acme-inst-")
+        );
+        assert_eq!(org_prefix("org-acme"), None);
+        assert_eq!(org_prefix("acme-inst-db"), None);
+    }
+
+    #[test]
+    fn test_path_in_bucket() {
+        assert_eq!(
+            path_in_bucket("s3://backups/v2/org-acme-inst-db", "backups"),
+            "v2/org-acme-inst-db"
+        );
+        assert_eq!(
+            path_in_bucket("gs://backups/v2/org-acme-inst-db", "backups"),
+            "v2/org-acme-inst-db"
+        );
+        assert_eq!(
+            path_in_bucket(
+                "https://tembo.blob.core.windows.net/backups/v2/org-acme-inst-db",
+                "backups"
+            ),
+            "v2/org-acme-inst-db"
+        );
+        assert_eq!(
+            path_in_bucket("v2/org-acme-inst-db", "backups"),
+            "v2/org-acme-inst-db"
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use crate::azure;
+use crate::types::{ErrorCode, ErrorDetails};
+use aws_sdk_cloudformation::error::ProvideErrorMetadata;
 use aws_sdk_cloudformation::Error as CFError;
 use azure::azure_error::AzureError;
+use azure_core::error::ErrorKind as AzureErrorKind;
+use azure_core::Error as AzureSDKError;
 use google_cloud_storage::http::Error as GcsError;
 use kube;
 use pgmq::errors::PgmqError;
@@ -62,4 +66,251 @@ pub enum ConductorError {
 
     #[error("Error with Azure SDK {0}")]
     AzureError(#[from] AzureError),
+
+    /// Invalid or unreachable data plane configuration
+    #[error("Data plane configuration error: {0}")]
+    DataPlaneConfigError(String),
+
+    /// Recording, listing or replaying dead letters failed
+    #[error("Dead letter error: {0}")]
+    DeadLetterError(String),
+
+    /// Recording, listing or pruning processed events failed
+    #[error("Audit log error: {0}")]
+    AuditLogError(String),
+
+    /// Recording, listing or replaying quarantined messages failed
+    #[error("Quarantine error: {0}")]
+    QuarantineError(String),
+
+    /// A CloudFormation stack ended in a failed status
+    #[error("CloudFormation stack {stack_name} failed in status {status}: {reason}")]
+    CloudFormationStackFailed {
+        stack_name: String,
+        status: String,
+        reason: String,
+    },
+
+    /// Recording or reading the progress of a rollout failed
+    #[error("Rollout error: {0}")]
+    RolloutError(String),
+
+    /// CONNECTION_ENCRYPTION_PUBLIC_KEY is invalid, or encrypting a password with it failed
+    #[error("Connection encryption error: {0}")]
+    ConnectionEncryptionError(String),
+
+    /// The namespace of an instance belongs to another instance
+    #[error("Namespace collision: {0}")]
+    NamespaceCollision(String),
+
+    /// Recording or reading the namespace of a remapped instance failed
+    #[error("Namespace mapping error: {0}")]
+    NamespaceMappingError(String),
+
+    /// The major version upgrade of an instance could not be started
+    #[error("Upgrade error: {0}")]
+    UpgradeError(String),
+
+    /// Recording or reading the backup location of an instance failed
+    #[error("Backup location error: {0}")]
+    BackupLocationError(String),
+
+    /// Recording or reading the storage account of an Azure instance failed
+    #[error("Azure storage target error: {0}")]
+    AzureStorageTargetError(String),
+
+    /// Recording or reading the hash of the last applied spec failed
+    #[error("Applied spec error: {0}")]
+    AppliedSpecError(String),
+
+    /// Recording or reading the backups of a deleted instance failed
+    #[error("Deleted instance error: {0}")]
+    DeletedInstanceError(String),
+
+    /// A restore reads the backups of an instance of another organization
+    #[error("Restore source rejected: {0}")]
+    RestoreSourceRejected(String),
+
+    /// Recording or reading the scheduled deletion of an instance failed
+    #[error("Pending deletion error: {0}")]
+    PendingDeletionError(String),
+
+    /// Inspecting or managing the messages of a queue failed
+    #[error("Queue admin error: {0}")]
+    QueueAdminError(String),
+
+    /// The shards of the control plane events queue are misconfigured
+    #[error("Queue shard error: {0}")]
+    QueueShardError(String),
+}
+
+impl ConductorError {
+    /// Classify the error so control plane can show an actionable message
+    /// and only automatically retry where it is safe to do so
+    pub fn error_details(&self) -> ErrorDetails {
+        let code = match self {
+            ConductorError::JsonParsingError(_) => ErrorCode::InvalidSpec,
+            ConductorError::KubeError(kube::Error::Api(resp)) => classify_status(resp.code),
+            ConductorError::GcsError(GcsError::Response(resp)) => classify_status(resp.code),
+            ConductorError::AwsError(err) => classify_aws(err),
+            ConductorError::AzureError(AzureError::AzureSDKError(err)) => classify_azure(err),
+            ConductorError::NamespaceCollision(_) => ErrorCode::NamespaceCollision,
+            ConductorError::UpgradeError(_) => ErrorCode::InvalidSpec,
+            ConductorError::RestoreSourceRejected(_) => ErrorCode::InvalidSpec,
+            ConductorError::CloudFormationStackFailed { .. } => ErrorCode::CloudFormationFailed,
+            ConductorError::NoOutputsFound | ConductorError::PostgresConnectionInfoNotFound => {
+                ErrorCode::Timeout
+            }
+            _ => ErrorCode::Internal,
+        };
+        let retryable = match self {
+            // Conflicts, denied requests while RBAC is still propagating and server errors
+            // of the Kubernetes API are transient, so they are always requeued
+            ConductorError::KubeError(kube::Error::Api(resp))
+                if matches!(resp.code, 403 | 409 | 500..=599) =>
+            {
+                true
+            }
+            _ => matches!(code, ErrorCode::Timeout | ErrorCode::Internal),
+        };
+        ErrorDetails {
+            code,
+            retryable,
+            message: self.to_string(),
+            fields: vec![],
+        }
+    }
+}
+
+// Derive an error code from the HTTP status code of a failed request
+fn classify_status(status: u16) -> ErrorCode {
+    match status {
+        401 | 403 => ErrorCode::CloudPermissionError,
+        400 | 422 => ErrorCode::InvalidSpec,
+        408 | 429 | 504 => ErrorCode::Timeout,
+        _ => ErrorCode::Internal,
+    }
+}
+
+// Derive an error code from the typed error, or the error code returned by the AWS API
+fn classify_aws(err: &CFError) -> ErrorCode {
+    match err {
+        CFError::LimitExceededException(_) => ErrorCode::QuotaExceeded,
+        CFError::InsufficientCapabilitiesException(_) => ErrorCode::CloudPermissionError,
+        CFError::Unhandled(unhandled) => match unhandled.code() {
+            Some("AccessDenied" | "AccessDeniedException") => ErrorCode::CloudPermissionError,
+            Some("ValidationError" | "ValidationException") => ErrorCode::InvalidSpec,
+            Some("Throttling" | "ThrottlingException") => ErrorCode::Timeout,
+            _ => ErrorCode::Internal,
+        },
+        _ => ErrorCode::Internal,
+    }
+}
+
+// Derive an error code from the error code returned by the Azure API, falling back to the
+// HTTP status code of the response
+fn classify_azure(err: &AzureSDKError) -> ErrorCode {
+    match err.kind() {
+        AzureErrorKind::HttpResponse { status, error_code } => match error_code.as_deref() {
+            Some("AuthorizationFailed") => ErrorCode::CloudPermissionError,
+            Some("QuotaExceeded") => ErrorCode::QuotaExceeded,
+            _ => classify_status(u16::from(*status)),
+        },
+        _ => ErrorCode::Internal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kube_api_error(code: u16, message: &str) -> ConductorError {
+        ConductorError::KubeError(kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: message.to_string(),
+            reason: "".to_string(),
+            code,
+        }))
+    }
+
+    #[test]
+    fn test_error_details() {
+        let details = kube_api_error(422, "spec.replicas: Invalid value").error_details();
+        assert_eq!(details.code, ErrorCode::InvalidSpec);
+        assert!(!details.retryable);
+
+        let details = kube_api_error(504, "gateway timeout").error_details();
+        assert_eq!(details.code, ErrorCode::Timeout);
+        assert!(details.retryable);
+
+        let details = ConductorError::NoOutputsFound.error_details();
+        assert_eq!(details.code, ErrorCode::Timeout);
+        assert!(details.retryable);
+
+        let details = ConductorError::CloudFormationStackFailed {
+            stack_name: "org-acme-inst-db-cf".to_string(),
+            status: "ROLLBACK_COMPLETE".to_string(),
+            reason: "Role: Resource creation cancelled, invalid permission".to_string(),
+        }
+        .error_details();
+        assert_eq!(details.code, ErrorCode::CloudFormationFailed);
+        assert!(!details.retryable);
+
+        let details = ConductorError::EventIDFormat.error_details();
+        assert_eq!(details.code, ErrorCode::Internal);
+        assert!(details.retryable);
+        assert_eq!(details.message, "Error formatting event ID");
+    }
+
+    #[test]
+    fn test_error_details_kube_transient() {
+        // Forbidden, conflicting and failed requests to the Kubernetes API are requeued
+        let details = kube_api_error(403, "forbidden").error_details();
+        assert_eq!(details.code, ErrorCode::CloudPermissionError);
+        assert!(details.retryable);
+
+        let details = kube_api_error(403, "exceeded quota: compute-resources").error_details();
+        assert!(details.retryable);
+
+        let details = kube_api_error(409, "the object has been modified").error_details();
+        assert_eq!(details.code, ErrorCode::Internal);
+        assert!(details.retryable);
+
+        for code in [500, 502, 503] {
+            let details = kube_api_error(code, "invalid permission").error_details();
+            assert_eq!(details.code, ErrorCode::Internal);
+            assert!(details.retryable);
+        }
+
+        let details = kube_api_error(400, "permission denied").error_details();
+        assert_eq!(details.code, ErrorCode::InvalidSpec);
+        assert!(!details.retryable);
+    }
+
+    #[test]
+    fn test_error_details_cloud_sdk() {
+        let err = CFError::LimitExceededException(
+            aws_sdk_cloudformation::types::error::LimitExceededException::builder()
+                .message("invalid permission")
+                .build(),
+        );
+        let details = ConductorError::AwsError(Box::new(err)).error_details();
+        assert_eq!(details.code, ErrorCode::QuotaExceeded);
+        assert!(!details.retryable);
+
+        let err = AzureErrorKind::HttpResponse {
+            status: azure_core::StatusCode::Forbidden,
+            error_code: Some("AuthorizationFailed".to_string()),
+        }
+        .into_error();
+        let details = ConductorError::AzureError(AzureError::AzureSDKError(err)).error_details();
+        assert_eq!(details.code, ErrorCode::CloudPermissionError);
+        assert!(!details.retryable);
+
+        // Without a typed error code, the message is not inspected
+        let err = AzureErrorKind::Other.into_error();
+        let details = ConductorError::AzureError(AzureError::AzureSDKError(err)).error_details();
+        assert_eq!(details.code, ErrorCode::Internal);
+        assert!(details.retryable);
+    }
 }
@@ -0,0 +1,150 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, oneshot::error::TryRecvError};
+
+/// Orders the events processed concurrently. An event waits for the previous event of each
+/// of its namespaces to complete, so each instance sees its events in the order they were
+/// read, while events of other namespaces are processed in parallel.
+#[derive(Debug, Default)]
+pub struct NamespaceOrdering {
+    tails: HashMap<String, oneshot::Receiver<()>>,
+}
+
+/// What an event waits for before it is processed, and what it holds until it completed
+#[derive(Debug)]
+pub struct OrderingTicket {
+    previous: Vec<oneshot::Receiver<()>>,
+    done: Vec<oneshot::Sender<()>>,
+}
+
+impl NamespaceOrdering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue an event behind the previous events of `namespaces`
+    pub fn enqueue(&mut self, namespaces: &[String]) -> OrderingTicket {
+        self.tails
+            .retain(|_, done| matches!(done.try_recv(), Err(TryRecvError::Empty)));
+        let mut ticket = OrderingTicket {
+            previous: vec![],
+            done: vec![],
+        };
+        let namespaces: HashSet<&String> = namespaces.iter().collect();
+        for namespace in namespaces {
+            if let Some(previous) = self.tails.remove(namespace) {
+                ticket.previous.push(previous);
+            }
+            let (done_tx, done_rx) = oneshot::channel::<()>();
+            self.tails.insert(namespace.clone(), done_rx);
+            ticket.done.push(done_tx);
+        }
+        ticket
+    }
+}
+
+impl OrderingTicket {
+    /// Resolves once the previous events of the namespaces completed. The events which follow
+    /// are released when the ticket is dropped.
+    pub async fn wait(&mut self) {
+        for previous in self.previous.drain(..) {
+            let _ = previous.await;
+        }
+    }
+}
+
+/// The messages which are waiting or being processed. A message waiting behind earlier
+/// events of its namespace can become visible again, it is only processed once.
+#[derive(Clone, Debug, Default)]
+pub struct InFlight {
+    messages: Arc<Mutex<HashSet<(usize, i64)>>>,
+}
+
+impl InFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// False when the message of the queue is already in flight
+    pub fn insert(&self, queue: usize, msg_id: i64) -> bool {
+        self.messages
+            .lock()
+            .expect("in flight lock")
+            .insert((queue, msg_id))
+    }
+
+    pub fn remove(&self, queue: usize, msg_id: i64) {
+        self.messages
+            .lock()
+            .expect("in flight lock")
+            .remove(&(queue, msg_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn namespaces(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_ordering_within_namespace() {
+        let mut ordering = NamespaceOrdering::new();
+        let mut first = ordering.enqueue(&namespaces(&["org-a-inst-a"]));
+        let mut second = ordering.enqueue(&namespaces(&["org-a-inst-a"]));
+        let mut third = ordering.enqueue(&namespaces(&["org-a-inst-a"]));
+        assert!(first.wait().now_or_never().is_some());
+        assert!(second.wait().now_or_never().is_none());
+
+        drop(first);
+        assert!(second.wait().now_or_never().is_some());
+        assert!(third.wait().now_or_never().is_none());
+        drop(second);
+        assert!(third.wait().now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_parallel_across_namespaces() {
+        let mut ordering = NamespaceOrdering::new();
+        let _first = ordering.enqueue(&namespaces(&["org-a-inst-a"]));
+        let mut other = ordering.enqueue(&namespaces(&["org-b-inst-b"]));
+        assert!(other.wait().now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_ordering_on_resolved_namespace() {
+        let mut ordering = NamespaceOrdering::new();
+        // The first event was read before its namespace was remapped
+        let first = ordering.enqueue(&namespaces(&["org-a-inst-a"]));
+        let mut second = ordering.enqueue(&namespaces(&["org-a-inst-a", "org-a-inst-a-1f2e3d"]));
+        let mut third = ordering.enqueue(&namespaces(&["org-a-inst-a-1f2e3d"]));
+        assert!(second.wait().now_or_never().is_none());
+
+        drop(first);
+        assert!(second.wait().now_or_never().is_some());
+        assert!(third.wait().now_or_never().is_none());
+        drop(second);
+        assert!(third.wait().now_or_never().is_some());
+
+        // Completed events are forgotten
+        drop(third);
+        let mut next = ordering.enqueue(&namespaces(&["org-a-inst-a-1f2e3d"]));
+        assert!(next.wait().now_or_never().is_some());
+        assert_eq!(ordering.tails.len(), 1);
+    }
+
+    #[test]
+    fn test_in_flight_dedup() {
+        let in_flight = InFlight::new();
+        assert!(in_flight.insert(0, 1));
+        assert!(!in_flight.clone().insert(0, 1));
+        // The same message id on another queue is another message
+        assert!(in_flight.insert(1, 1));
+
+        in_flight.remove(0, 1);
+        assert!(in_flight.insert(0, 1));
+    }
+}
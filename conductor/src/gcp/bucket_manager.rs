@@ -1,4 +1,7 @@
-use crate::gcp::{client::GcpStorageClient, iam_builder::IamBindingBuilder};
+use crate::gcp::{
+    client::GcpStorageClient, iam_builder::IamBindingBuilder,
+    workload_identity_federation::GcpFederation,
+};
 use google_cloud_storage::http::buckets::{Binding, Condition, Policy};
 use google_cloud_storage::http::Error as GcsError;
 use log::{info, warn};
@@ -13,6 +16,7 @@ const GCP_STORAGE_ROLE: &str = "projects/{}/roles/TemboInstanceGCSRole";
 /// to/from GCP storage bucket IAM policies.
 pub struct BucketIamManager {
     gcp_client: GcpStorageClient,
+    federation: Option<GcpFederation>,
 }
 
 impl BucketIamManager {
@@ -22,7 +26,21 @@ impl BucketIamManager {
     ///
     /// * `gcp_client` - An instance of `GcpStorageClient` used for interacting with GCP storage.
     pub fn new(gcp_client: GcpStorageClient) -> Self {
-        Self { gcp_client }
+        Self {
+            gcp_client,
+            federation: None,
+        }
+    }
+
+    /// Binds service accounts through a Workload Identity Federation pool instead of GKE
+    /// workload identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `federation` - The pool the service accounts are principals of, None for GKE.
+    pub fn with_federation(mut self, federation: Option<GcpFederation>) -> Self {
+        self.federation = federation;
+        self
     }
 
     /// Adds a service account binding to the specified buckets' IAM policies.
@@ -250,6 +268,9 @@ impl BucketIamManager {
     ///
     /// Returns a formatted string representing the member.
     fn create_member_string(&self, namespace: &str, service_account: &str) -> String {
+        if let Some(federation) = &self.federation {
+            return federation.member(namespace, service_account);
+        }
         let project_id = self.gcp_client.get_project_id();
         let project_number = self.gcp_client.get_project_number();
         format!(
@@ -267,7 +288,6 @@ impl BucketIamManager {
     /// # Returns
     ///
     /// Returns a `Condition` instance for the specified bucket.
-
     fn create_bucket_condition(&self, bucket_name: &str, instance_name: &str) -> Condition {
         Condition {
             title: "allow-bucket-and-path".to_string(),
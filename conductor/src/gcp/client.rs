@@ -1,6 +1,10 @@
 use google_cloud_storage::client::{Client, ClientConfig};
 use google_cloud_storage::http::buckets::{
-    get_iam_policy::GetIamPolicyRequest, set_iam_policy::SetIamPolicyRequest, Policy,
+    get::GetBucketRequest,
+    get_iam_policy::GetIamPolicyRequest,
+    patch::{BucketPatchConfig, PatchBucketRequest},
+    set_iam_policy::SetIamPolicyRequest,
+    Encryption, Policy,
 };
 use google_cloud_storage::http::Error as GcsError;
 
@@ -89,4 +93,53 @@ impl GcpStorageClient {
         };
         self.client.set_iam_policy(&request).await
     }
+
+    /// Retrieves the Cloud KMS key objects of the bucket are encrypted with by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GcsError` if the bucket cannot be retrieved.
+    pub async fn get_default_kms_key(&self, bucket_name: &str) -> Result<Option<String>, GcsError> {
+        let request = GetBucketRequest {
+            bucket: bucket_name.to_string(),
+            ..Default::default()
+        };
+        let bucket = self.client.get_bucket(&request).await?;
+        Ok(bucket
+            .encryption
+            .map(|encryption| encryption.default_kms_key_name))
+    }
+
+    /// Sets the Cloud KMS key objects of the bucket are encrypted with by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `kms_key_name` - The resource name of the Cloud KMS key.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GcsError` if the bucket cannot be updated.
+    pub async fn set_default_kms_key(
+        &self,
+        bucket_name: &str,
+        kms_key_name: &str,
+    ) -> Result<(), GcsError> {
+        let request = PatchBucketRequest {
+            bucket: bucket_name.to_string(),
+            metadata: Some(BucketPatchConfig {
+                encryption: Some(Encryption {
+                    default_kms_key_name: kms_key_name.to_string(),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.client.patch_bucket(&request).await?;
+        Ok(())
+    }
 }
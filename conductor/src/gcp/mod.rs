@@ -1,3 +1,4 @@
 pub mod bucket_manager;
 pub mod client;
 pub mod iam_builder;
+pub mod workload_identity_federation;
@@ -0,0 +1,130 @@
+use crate::errors::ConductorError;
+use controller::apis::coredb_types::{GoogleCredentials, GoogleCredentialsApplicationCredentials};
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client};
+use log::info;
+use serde_json::{json, Value};
+
+/// The Secret holding the credential configuration of the instance, in the namespace of the
+/// instance
+pub const FEDERATION_CREDENTIALS_SECRET: &str = "gcp-federation-credentials";
+const FEDERATION_CREDENTIALS_KEY: &str = "credentials.json";
+
+// The token of the service account of the instance, which is exchanged for a GCP access token
+const SERVICE_ACCOUNT_TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// The Workload Identity Federation pool and provider trusting the service account tokens of
+/// the cluster, for data planes which do not run on GKE
+#[derive(Clone, Debug, PartialEq)]
+pub struct GcpFederation {
+    pub project_number: String,
+    pub pool: String,
+    pub provider: String,
+}
+
+impl GcpFederation {
+    /// The principal of a Kubernetes service account in the pool, the subject of its tokens
+    pub fn member(&self, namespace: &str, service_account: &str) -> String {
+        format!(
+            "principal://iam.googleapis.com/projects/{}/locations/global/workloadIdentityPools/{}/subject/system:serviceaccount:{}:{}",
+            self.project_number, self.pool, namespace, service_account
+        )
+    }
+
+    /// The credential configuration the instance authenticates to GCS with, exchanging the
+    /// token of its service account through the provider
+    pub fn credential_config(&self) -> Value {
+        json!({
+            "type": "external_account",
+            "audience": format!(
+                "//iam.googleapis.com/projects/{}/locations/global/workloadIdentityPools/{}/providers/{}",
+                self.project_number, self.pool, self.provider
+            ),
+            "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+            "token_url": "https://sts.googleapis.com/v1/token",
+            "credential_source": {
+                "file": SERVICE_ACCOUNT_TOKEN_PATH,
+                "format": {"type": "text"},
+            },
+        })
+    }
+}
+
+/// Store the credential configuration of the instance in its namespace. It holds no secret,
+/// the Secret is how backups read Google credentials.
+pub async fn apply_federation_credentials(
+    client: Client,
+    namespace: &str,
+    federation: &GcpFederation,
+) -> Result<(), ConductorError> {
+    let secret_api: Api<Secret> = Api::namespaced(client, namespace);
+    let secret = json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": {
+            "name": FEDERATION_CREDENTIALS_SECRET,
+            "namespace": namespace,
+        },
+        "type": "Opaque",
+        "stringData": {
+            FEDERATION_CREDENTIALS_KEY: federation.credential_config().to_string(),
+        },
+    });
+    info!("Applying GCP federation credentials in {}", namespace);
+    secret_api
+        .patch(
+            FEDERATION_CREDENTIALS_SECRET,
+            &PatchParams::apply("conductor").force(),
+            &Patch::Apply(&secret),
+        )
+        .await
+        .map_err(ConductorError::KubeError)?;
+    Ok(())
+}
+
+/// The Google credentials of the backups of an instance authenticating through federation
+pub fn federation_google_credentials() -> GoogleCredentials {
+    GoogleCredentials {
+        application_credentials: Some(GoogleCredentialsApplicationCredentials {
+            key: FEDERATION_CREDENTIALS_KEY.to_string(),
+            name: FEDERATION_CREDENTIALS_SECRET.to_string(),
+        }),
+        gke_environment: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_federation() {
+        let federation = GcpFederation {
+            project_number: "123456789".to_string(),
+            pool: "data-1".to_string(),
+            provider: "eks".to_string(),
+        };
+        assert_eq!(
+            federation.member("org-acme-inst-db", "org-acme-inst-db"),
+            "principal://iam.googleapis.com/projects/123456789/locations/global/workloadIdentityPools/data-1/subject/system:serviceaccount:org-acme-inst-db:org-acme-inst-db"
+        );
+
+        let config = federation.credential_config();
+        assert_eq!(
+            config["audience"],
+            "//iam.googleapis.com/projects/123456789/locations/global/workloadIdentityPools/data-1/providers/eks"
+        );
+        assert_eq!(
+            config["credential_source"]["file"],
+            SERVICE_ACCOUNT_TOKEN_PATH
+        );
+
+        let credentials = federation_google_credentials();
+        assert_eq!(credentials.gke_environment, None);
+        assert_eq!(
+            credentials.application_credentials.unwrap().key,
+            FEDERATION_CREDENTIALS_KEY
+        );
+    }
+}
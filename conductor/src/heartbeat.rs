@@ -0,0 +1,113 @@
+use chrono::Utc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// When the conductor loop last made progress. The loop beats each time it goes around, a
+/// loop which did not beat for longer than `max_lag` is stuck, e.g. on a wedged Kubernetes
+/// client or pgmq connection, and the pod should be restarted.
+#[derive(Clone, Debug)]
+pub struct LoopHeartbeat {
+    // Unix timestamp in milliseconds
+    last_beat_ms: Arc<AtomicI64>,
+    max_lag: Duration,
+}
+
+impl LoopHeartbeat {
+    /// The loop has `max_lag` to beat for the first time, a zero `max_lag` never fails
+    pub fn new(max_lag: Duration) -> Self {
+        LoopHeartbeat {
+            last_beat_ms: Arc::new(AtomicI64::new(Utc::now().timestamp_millis())),
+            max_lag,
+        }
+    }
+
+    pub fn beat(&self) {
+        self.last_beat_ms
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// The time since the last beat, at `now_ms` in milliseconds since the Unix epoch
+    pub fn lag_at(&self, now_ms: i64) -> Duration {
+        let lag_ms = now_ms - self.last_beat_ms.load(Ordering::Relaxed);
+        Duration::from_millis(lag_ms.max(0) as u64)
+    }
+
+    pub fn lag(&self) -> Duration {
+        self.lag_at(Utc::now().timestamp_millis())
+    }
+
+    pub fn is_stuck(&self) -> bool {
+        !self.max_lag.is_zero() && self.lag() > self.max_lag
+    }
+
+    /// Wait for a permit, beating every `interval` meanwhile
+    pub async fn acquire_beating(
+        &self,
+        permits: Arc<Semaphore>,
+        interval: Duration,
+    ) -> OwnedSemaphorePermit {
+        let acquire = permits.acquire_owned();
+        tokio::pin!(acquire);
+        let mut beats = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                permit = &mut acquire => return permit.expect("semaphore is never closed"),
+                _ = beats.tick() => self.beat(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loop_heartbeat() {
+        let heartbeat = LoopHeartbeat::new(Duration::from_secs(300));
+        heartbeat.beat();
+        let now_ms = Utc::now().timestamp_millis();
+        assert!(heartbeat.lag_at(now_ms) < Duration::from_secs(1));
+        assert!(heartbeat.lag_at(now_ms + 301_000) > Duration::from_secs(300));
+        assert_eq!(heartbeat.lag_at(now_ms - 60_000), Duration::ZERO);
+        assert!(!heartbeat.is_stuck());
+
+        let stuck = LoopHeartbeat::new(Duration::from_millis(1));
+        stuck.last_beat_ms.store(now_ms - 60_000, Ordering::Relaxed);
+        assert!(stuck.is_stuck());
+
+        let disabled = LoopHeartbeat::new(Duration::ZERO);
+        disabled
+            .last_beat_ms
+            .store(now_ms - 60_000, Ordering::Relaxed);
+        assert!(!disabled.is_stuck());
+    }
+
+    #[tokio::test]
+    async fn test_beats_while_waiting_for_permit() {
+        let heartbeat = LoopHeartbeat::new(Duration::from_millis(200));
+        let permits = Arc::new(Semaphore::new(1));
+        let busy = permits.clone().acquire_owned().await.unwrap();
+
+        let waiting = {
+            let heartbeat = heartbeat.clone();
+            let permits = permits.clone();
+            tokio::spawn(async move {
+                heartbeat
+                    .acquire_beating(permits, Duration::from_millis(20))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(!heartbeat.is_stuck());
+        assert!(!waiting.is_finished());
+
+        drop(busy);
+        let permit = waiting.await.unwrap();
+        assert_eq!(permits.available_permits(), 0);
+        drop(permit);
+        assert_eq!(permits.available_permits(), 1);
+    }
+}
@@ -0,0 +1,374 @@
+use futures::future::BoxFuture;
+use http::{Method, Request, Response, StatusCode};
+use hyper::Body;
+use kube::client::ClientBuilder;
+use kube::{Client, Config};
+use log::warn;
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use opentelemetry::KeyValue;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::buffer::Buffer;
+use tower::{BoxError, Layer, Service, ServiceExt};
+
+// How many requests wait for the inner service at most, the rest wait for a slot
+const BUFFER_SIZE: usize = 1024;
+
+/// How many requests per second conductor sends to the API server, and how retries back off
+#[derive(Debug, Clone, PartialEq)]
+pub struct KubeClientConfig {
+    /// Requests per second sent on average, 0 does not limit them
+    pub qps: f64,
+    /// Requests sent at once after a quiet period
+    pub burst: u32,
+    /// Retries of a request which got a 429 or 5xx response
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled on each retry
+    pub backoff: Duration,
+    /// Longest wait between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for KubeClientConfig {
+    fn default() -> Self {
+        KubeClientConfig {
+            qps: 20.0,
+            burst: 40,
+            max_retries: 3,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl KubeClientConfig {
+    /// The wait before a retry, the API server may ask for a longer one with `Retry-After`
+    fn backoff(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self
+            .backoff
+            .saturating_mul(2_u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        retry_after.map_or(backoff, |retry_after| {
+            retry_after.max(backoff).min(self.max_backoff)
+        })
+    }
+}
+
+/// A token bucket, refilled at `qps` tokens per second up to `burst` tokens
+#[derive(Debug)]
+pub struct RateLimiter {
+    qps: f64,
+    burst: f64,
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(qps: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            qps,
+            burst,
+            bucket: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Take a token, returns how long to wait before the request may be sent
+    pub fn reserve(&self, now: Instant) -> Duration {
+        if self.qps <= 0.0 {
+            return Duration::ZERO;
+        }
+        let mut bucket = self.bucket.lock().expect("rate limiter lock");
+        let (tokens, last) = *bucket;
+        let elapsed = now.saturating_duration_since(last).as_secs_f64();
+        // Tokens go negative while requests wait, each waits for its own token
+        let tokens = (tokens + elapsed * self.qps).min(self.burst) - 1.0;
+        *bucket = (tokens, now.max(last));
+        if tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-tokens / self.qps)
+        }
+    }
+
+    async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Requests, retries and latency of the requests to the API server by verb
+#[derive(Clone)]
+pub struct KubeClientMetrics {
+    requests: Counter<u64>,
+    retries: Counter<u64>,
+    duration: Histogram<f64>,
+}
+
+impl KubeClientMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        KubeClientMetrics {
+            requests: meter
+                .u64_counter("conductor_kube_requests")
+                .with_description("Number of requests to the Kubernetes API server")
+                .init(),
+            retries: meter
+                .u64_counter("conductor_kube_retries")
+                .with_description("Number of requests to the Kubernetes API server retried")
+                .init(),
+            duration: meter
+                .f64_histogram("conductor_kube_request_duration_seconds")
+                .with_description("Time to get a response from the Kubernetes API server")
+                .init(),
+        }
+    }
+}
+
+/// Layer of the kube client which limits the rate of requests, retries the ones throttled or
+/// failed by the API server, and reports metrics by verb
+#[derive(Clone)]
+pub struct KubeClientLayer {
+    config: Arc<KubeClientConfig>,
+    limiter: Arc<RateLimiter>,
+    metrics: Option<KubeClientMetrics>,
+}
+
+impl KubeClientLayer {
+    pub fn new(config: KubeClientConfig, metrics: Option<KubeClientMetrics>) -> Self {
+        let limiter = Arc::new(RateLimiter::new(config.qps, config.burst));
+        KubeClientLayer {
+            config: Arc::new(config),
+            limiter,
+            metrics,
+        }
+    }
+}
+
+impl<S> Layer<S> for KubeClientLayer
+where
+    S: Service<Request<Body>> + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError> + Send + Sync,
+    S::Response: Send,
+{
+    type Service = KubeClientService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        KubeClientService {
+            // Retries send the request again, the buffer makes the inner service shareable
+            inner: Buffer::new(inner, BUFFER_SIZE),
+            layer: self.clone(),
+        }
+    }
+}
+
+pub struct KubeClientService<S>
+where
+    S: Service<Request<Body>>,
+{
+    inner: Buffer<S, Request<Body>>,
+    layer: KubeClientLayer,
+}
+
+impl<S, B> Service<Request<Body>> for KubeClientService<S>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError> + Send + Sync,
+    B: Send + 'static,
+{
+    type Response = Response<B>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Response<B>, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // The readied service sends the first attempt, retries wait for a clone to be ready
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let retry_inner = self.inner.clone();
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let KubeClientLayer {
+                config,
+                limiter,
+                metrics,
+            } = layer;
+            let (parts, body) = request.into_parts();
+            // Requests are sent again on retries, kube bodies are small so they are buffered
+            let body = hyper::body::to_bytes(body).await?;
+            let verb = verb(&parts.method, parts.uri.query());
+            let mut retry = 0;
+            loop {
+                let mut request = Request::new(Body::from(body.clone()));
+                *request.method_mut() = parts.method.clone();
+                *request.uri_mut() = parts.uri.clone();
+                *request.version_mut() = parts.version;
+                *request.headers_mut() = parts.headers.clone();
+                limiter.acquire().await;
+                let started = Instant::now();
+                let result = if retry == 0 {
+                    inner.call(request).await
+                } else {
+                    retry_inner.clone().oneshot(request).await
+                };
+                let status = result.as_ref().ok().map(|response| response.status());
+                if let Some(metrics) = &metrics {
+                    let cx = opentelemetry::Context::current();
+                    let attributes = [
+                        KeyValue::new("verb", verb),
+                        KeyValue::new("code", status.map_or(0, |s| s.as_u16()) as i64),
+                    ];
+                    metrics.requests.add(&cx, 1, &attributes);
+                    metrics
+                        .duration
+                        .record(&cx, started.elapsed().as_secs_f64(), &attributes[..1]);
+                }
+                let response = result?;
+                let status = response.status();
+                if retry >= config.max_retries || !should_retry(&parts.method, status) {
+                    return Ok(response);
+                }
+                let wait = config.backoff(retry, retry_after(&response));
+                warn!(
+                    "Kubernetes API server answered {} to {} {}, retrying in {:?}",
+                    status, parts.method, parts.uri, wait
+                );
+                if let Some(metrics) = &metrics {
+                    metrics.retries.add(
+                        &opentelemetry::Context::current(),
+                        1,
+                        &[KeyValue::new("verb", verb)],
+                    );
+                }
+                tokio::time::sleep(wait).await;
+                retry += 1;
+            }
+        })
+    }
+}
+
+// The Kubernetes verb of a request
+fn verb(method: &Method, query: Option<&str>) -> &'static str {
+    let watch = query.is_some_and(|query| query.split('&').any(|param| param == "watch=true"));
+    match *method {
+        Method::GET if watch => "watch",
+        Method::GET => "get",
+        Method::POST => "create",
+        Method::PUT => "update",
+        Method::PATCH => "patch",
+        Method::DELETE => "delete",
+        _ => "other",
+    }
+}
+
+// Throttled requests were not processed and are always retried. Server errors are only
+// retried for requests which can be sent twice without creating two objects.
+fn should_retry(method: &Method, status: StatusCode) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => *method != Method::POST,
+        _ => false,
+    }
+}
+
+fn retry_after<B>(response: &Response<B>) -> Option<Duration> {
+    response
+        .headers()
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Create a kube client from the environment whose requests go through the `KubeClientLayer`
+pub async fn kube_client(
+    config: KubeClientConfig,
+    metrics: Option<KubeClientMetrics>,
+) -> Result<Client, kube::Error> {
+    let kube_config = Config::infer().await.map_err(kube::Error::InferConfig)?;
+    let client = ClientBuilder::try_from(kube_config)?
+        .with_layer(&KubeClientLayer::new(config, metrics))
+        .build();
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(10.0, 2);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        // The burst is used up, requests wait 100ms for each token
+        assert_eq!(limiter.reserve(now), Duration::from_millis(100));
+        assert_eq!(limiter.reserve(now), Duration::from_millis(200));
+        // A second later the waiting requests got their tokens and the bucket is full again
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+
+        let unlimited = RateLimiter::new(0.0, 1);
+        for _ in 0..100 {
+            assert_eq!(unlimited.reserve(now), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = KubeClientConfig::default();
+        assert_eq!(config.backoff(0, None), Duration::from_millis(200));
+        assert_eq!(config.backoff(2, None), Duration::from_millis(800));
+        assert_eq!(config.backoff(10, None), Duration::from_secs(10));
+        assert_eq!(
+            config.backoff(0, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            config.backoff(0, Some(Duration::from_secs(60))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn test_should_retry() {
+        assert!(should_retry(&Method::POST, StatusCode::TOO_MANY_REQUESTS));
+        assert!(should_retry(&Method::GET, StatusCode::SERVICE_UNAVAILABLE));
+        assert!(should_retry(
+            &Method::PATCH,
+            StatusCode::INTERNAL_SERVER_ERROR
+        ));
+        assert!(!should_retry(
+            &Method::POST,
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(!should_retry(&Method::GET, StatusCode::NOT_FOUND));
+        assert!(!should_retry(&Method::PUT, StatusCode::CONFLICT));
+    }
+
+    #[test]
+    fn test_verb() {
+        assert_eq!(verb(&Method::GET, None), "get");
+        assert_eq!(
+            verb(&Method::GET, Some("watch=true&resourceVersion=1")),
+            "watch"
+        );
+        assert_eq!(verb(&Method::GET, Some("labelSelector=a")), "get");
+        assert_eq!(verb(&Method::PATCH, None), "patch");
+        assert_eq!(verb(&Method::DELETE, None), "delete");
+    }
+}
@@ -1,17 +1,43 @@
+pub mod applied_specs;
+pub mod audit_log;
 pub mod aws;
 pub mod azure;
+pub mod backup_location;
+pub mod backup_storage;
+pub mod cache;
+pub mod capacity;
 pub mod cloud;
+pub mod connection_encryption;
+pub mod data_plane_config;
+pub mod dead_letter;
+pub mod deleted_instances;
 pub mod errors;
+pub mod event_ordering;
 pub mod extensions;
 pub mod gcp;
+pub mod heartbeat;
+pub mod kube_client;
 pub mod metrics;
 pub mod monitoring;
+pub mod namespace_mapping;
+pub mod pending_deletion;
+pub mod quarantine;
+pub mod queue_admin;
+pub mod queue_shards;
+pub mod rollout;
 pub mod routes;
+pub mod spec_validation;
+pub mod status_delta;
+pub mod telemetry;
 pub mod types;
+pub mod upgrade;
 
 use crate::{
-    aws::cloudformation::{AWSConfigState, CloudFormationParams},
+    aws::cloudformation::{AWSConfigState, CloudFormationParams, StackPhase},
+    azure::storage_targets::AzureStorageTarget,
+    cache::ResourceCache,
     cloud::CloudProvider,
+    gcp::workload_identity_federation::GcpFederation,
 };
 use aws_sdk_cloudformation::config::Region;
 use controller::apis::coredb_types::{CoreDB, CoreDBSpec};
@@ -31,8 +57,9 @@ use kube::{Api, Client, ResourceExt};
 use log::{debug, info, warn};
 use serde_json::{from_str, to_string, Value};
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, BTreeMap},
     hash::{Hash, Hasher},
+    time::Instant,
 };
 
 pub type Result<T, E = ConductorError> = std::result::Result<T, E>;
@@ -60,7 +87,11 @@ pub async fn generate_spec(
 
             // Format the backups_path with the correct prefix
             if let Some(restore) = &mut spec.restore {
-                if let Some(backups_path) = &mut restore.backups_path {
+                if let Some(backups_path) = restore
+                    .backups_path
+                    .as_mut()
+                    .filter(|path| !is_other_cloud_path(path, prefix))
+                {
                     let clean_path = remove_known_prefixes(backups_path);
                     if clean_path.starts_with(backups_bucket) {
                         // If the path already includes the bucket, just add the prefix. The
@@ -102,10 +133,19 @@ pub async fn generate_spec(
     }))
 }
 
+const KNOWN_PREFIXES: [&str; 3] = ["s3://", "gs://", "https://"];
+
+// Backups kept with another cloud provider are restored from as is, the operator
+// picks the restore credentials for that provider
+fn is_other_cloud_path(path: &str, prefix: &str) -> bool {
+    KNOWN_PREFIXES
+        .iter()
+        .any(|known| *known != prefix && path.starts_with(known))
+}
+
 // Remove known prefixes from the backup path
 fn remove_known_prefixes(path: &str) -> &str {
-    let known_prefixes = ["s3://", "gs://", "https://"];
-    for prefix in &known_prefixes {
+    for prefix in &KNOWN_PREFIXES {
         if let Some(stripped) = path.strip_prefix(prefix) {
             return stripped;
         }
@@ -154,18 +194,16 @@ pub fn get_org_inst_id(coredb: &CoreDB) -> Result<types::OrgInstId, Box<Conducto
     })
 }
 
-pub async fn get_one(client: Client, namespace: &str) -> Result<CoreDB, ConductorError> {
-    let coredb_api: Api<CoreDB> = Api::namespaced(client, namespace);
-    let pg_instance = coredb_api.get(namespace).await?;
-    Ok(pg_instance)
+pub async fn get_one(cache: &ResourceCache, namespace: &str) -> Result<CoreDB, ConductorError> {
+    cache.get_coredb(namespace, namespace).await
 }
 
 // returns CoreDB when status is present, otherwise returns an error
 pub async fn get_coredb_error_without_status(
-    client: Client,
+    cache: &ResourceCache,
     namespace: &str,
 ) -> Result<CoreDB, ConductorError> {
-    let coredb = get_one(client, namespace).await?;
+    let coredb = get_one(cache, namespace).await?;
 
     if coredb.status.is_none() {
         Err(ConductorError::NoStatusReported)
@@ -252,16 +290,17 @@ pub async fn delete_namespace(client: Client, name: &str) -> Result<(), Conducto
     Ok(())
 }
 
-async fn get_secret_for_db(client: Client, name: &str) -> Result<(Secret, Secret), ConductorError> {
+async fn get_secret_for_db(
+    cache: &ResourceCache,
+    name: &str,
+) -> Result<(Secret, Secret), ConductorError> {
     // read secret <name>-connection
     let secret_name_cnpg_postgres = format!("{name}-connection");
     let secret_name_cnpg_app = format!("{name}-app");
 
-    let secret_api: Api<Secret> = Api::namespaced(client, name);
-
     // Get the <name>-connection secret
-    let postgres_user_secret = match secret_api
-        .get_opt(secret_name_cnpg_postgres.as_str())
+    let postgres_user_secret = match cache
+        .get_secret_opt(name, secret_name_cnpg_postgres.as_str())
         .await?
     {
         Some(secret) => {
@@ -275,7 +314,10 @@ async fn get_secret_for_db(client: Client, name: &str) -> Result<(Secret, Secret
     };
 
     // Get the <name>-app secret
-    let app_user_secret = match secret_api.get_opt(secret_name_cnpg_app.as_str()).await? {
+    let app_user_secret = match cache
+        .get_secret_opt(name, secret_name_cnpg_app.as_str())
+        .await?
+    {
         Some(secret) => {
             debug!("Found the secret {}", secret_name_cnpg_app);
             secret
@@ -313,12 +355,15 @@ fn get_field_value_from_secret(
 }
 
 pub async fn get_pg_conn(
-    client: Client,
+    cache: &ResourceCache,
     name: &str,
     basedomain: &str,
     spec: &CoreDBSpec,
 ) -> Result<types::ConnectionInfo, ConductorError> {
-    let (postgres_user_secret, app_user_secret) = get_secret_for_db(client, name).await?;
+    if let Some(conn_info) = cache.connections().get(name, Instant::now()) {
+        return Ok(conn_info);
+    }
+    let (postgres_user_secret, app_user_secret) = get_secret_for_db(cache, name).await?;
 
     let postgres_data =
         postgres_user_secret
@@ -356,7 +401,11 @@ pub async fn get_pg_conn(
         password: postgres_pw,
         app_user,
         app_password: app_pw,
+        encryption: None,
     };
+    cache
+        .connections()
+        .insert(name, &postgres_conn, Instant::now());
 
     Ok(postgres_conn)
 }
@@ -427,8 +476,119 @@ pub async fn restart_coredb(
     Ok(true)
 }
 
+// Labels and annotations identifying the instance, which can not be changed by tags
+const PROTECTED_TAG_KEYS: [&str; 6] = [
+    "tembo.io/org_id",
+    "tembo.io/organization_id",
+    "tembo.io/instance_id",
+    "tembo.io/entity_name",
+    "tembo.io/data_plane_id",
+    "tembo-pod-init.tembo.io/watch",
+];
+
+// Build the merge patch of the metadata for tags, where null values remove a key
+fn tags_patch(tags: &types::InstanceTags) -> Value {
+    let allowed = |map: &BTreeMap<String, Option<String>>| {
+        map.iter()
+            .filter(|(key, _)| {
+                let protected = PROTECTED_TAG_KEYS.contains(&key.as_str());
+                if protected {
+                    warn!("Ignoring tag on protected key {}", key);
+                }
+                !protected
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<BTreeMap<String, Option<String>>>()
+    };
+    serde_json::json!({
+        "metadata": {
+            "labels": allowed(&tags.labels),
+            "annotations": allowed(&tags.annotations),
+        }
+    })
+}
+
+// Apply the tags of an instance to its namespace and CoreDB. Only the metadata is
+// patched, so the CoreDB spec is not regenerated and the operator does not restart anything.
+pub async fn update_tags(
+    client: Client,
+    namespace: &str,
+    tags: &types::InstanceTags,
+) -> Result<(), ConductorError> {
+    let patch = Patch::Merge(tags_patch(tags));
+    let params = PatchParams::default();
+
+    let ns_api: Api<Namespace> = Api::all(client.clone());
+    ns_api
+        .patch(namespace, &params, &patch)
+        .await
+        .map_err(ConductorError::KubeError)?;
+
+    // The CoreDB is named after its namespace
+    let coredb_api: Api<CoreDB> = Api::namespaced(client, namespace);
+    coredb_api
+        .patch(namespace, &params, &patch)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    info!("Updated tags of namespace and CoreDB {}", namespace);
+    Ok(())
+}
+
+// Stop or start the CoreDB of an instance, returns whether it was stopped before
+pub async fn set_coredb_stopped(
+    client: Client,
+    namespace: &str,
+    stop: bool,
+) -> Result<bool, ConductorError> {
+    // The CoreDB is named after its namespace
+    let coredb_api: Api<CoreDB> = Api::namespaced(client, namespace);
+    let coredb = coredb_api
+        .get(namespace)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    let patch = Patch::Merge(serde_json::json!({ "spec": { "stop": stop } }));
+    coredb_api
+        .patch(namespace, &PatchParams::default(), &patch)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    info!("Set stop of CoreDB {} to {}", namespace, stop);
+    Ok(coredb.spec.stop)
+}
+
+// Build the merge patch of the spec for a resize, which only carries the storage and the
+// cpu and memory resources
+fn resize_patch(spec: &CoreDBSpec) -> Value {
+    serde_json::json!({
+        "spec": {
+            "storage": spec.storage,
+            "resources": spec.resources,
+        }
+    })
+}
+
+// Resize the CoreDB of an instance. Only the storage, cpu and memory are patched, the rest of
+// the spec, the cloud permissions and the backup configuration are left as they are.
+pub async fn resize_coredb(
+    client: Client,
+    namespace: &str,
+    spec: &CoreDBSpec,
+) -> Result<CoreDB, ConductorError> {
+    let patch = Patch::Merge(resize_patch(spec));
+    // The CoreDB is named after its namespace
+    let coredb_api: Api<CoreDB> = Api::namespaced(client, namespace);
+    let coredb = coredb_api
+        .patch(namespace, &PatchParams::default(), &patch)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    info!("Resized CoreDB {}", namespace);
+    Ok(coredb)
+}
+
 // Create a cloudformation stack for the database.
-// This will create an IAM role for the database to use to access the backup archive bucket
+// This will create an IAM role for the database to use to access the backup archive bucket.
+// When cluster_name is set, the role is associated to the service account of the database
+// with EKS Pod Identity instead of IRSA.
+#[allow(clippy::too_many_arguments)]
 pub async fn create_cloudformation(
     aws_region: String,
     backup_archive_bucket: String,
@@ -437,6 +597,7 @@ pub async fn create_cloudformation(
     read_path: Option<String>,
     write_path: Option<String>,
     cf_template_bucket: String,
+    cluster_name: Option<String>,
 ) -> Result<(), ConductorError> {
     // (todo: nhudson) - Create Cloudformation Stack only for Create event
     // Create new function that returns 3 enums of SUCCESS, ERROR, WAITING
@@ -459,6 +620,7 @@ pub async fn create_cloudformation(
         role_name: iam_role_name,
         namespace,
         service_account_name,
+        cluster_name,
     };
     aws_config_state
         .create_cloudformation_stack(
@@ -467,29 +629,23 @@ pub async fn create_cloudformation(
             cf_template_bucket,
             aws_region,
         )
-        .await
-        .map_err(ConductorError::from)?;
+        .await?;
     Ok(())
-
-    // We will need to setup a requeuing system at somepoint to query for status
-    // of the stack state.  If the stack is still in a CREATE_IN_PROGRESS or UPDATE_IN_PROGRESS
-    // or DELETE_IN_PROGRESS we will need to requeue the message back to the queue.
-    // We will also need to bubble up errors as an alert if there is a failure.
 }
 
-// Delete a cloudformation stack.
+// Delete a cloudformation stack. Returns whether the stack is deleted, a stack which is still
+// changing is deleted once the event is requeued.
 pub async fn delete_cloudformation(
     aws_region: String,
     namespace: &str,
-) -> Result<(), ConductorError> {
+) -> Result<bool, ConductorError> {
     let region = Region::new(aws_region);
     let aws_config_state = AWSConfigState::new(region).await;
     let stack_name = format!("{}-cf", namespace);
-    aws_config_state
+    let phase = aws_config_state
         .delete_cloudformation_stack(&stack_name)
-        .await
-        .map_err(ConductorError::from)?;
-    Ok(())
+        .await?;
+    Ok(phase == StackPhase::Absent)
 }
 
 pub struct StackOutputs {
@@ -520,8 +676,7 @@ async fn get_stack_outputs(
     // "cloudformation is not done yet" and return a more specific error
     let (role_name, role_arn) = aws_config_state
         .lookup_cloudformation_stack(&stack_name)
-        .await
-        .map_err(ConductorError::from)?;
+        .await?;
     let stack_outputs = StackOutputs {
         role_name,
         role_arn,
@@ -552,6 +707,8 @@ pub async fn create_gcp_storage_workload_identity_binding(
     backup_archive_bucket: &str,
     storage_archive_bucket: &str,
     namespace: &str,
+    federation: Option<GcpFederation>,
+    kms_key_name: Option<&str>,
 ) -> Result<(), ConductorError> {
     let service_account_name = namespace;
     let buckets = vec![backup_archive_bucket, storage_archive_bucket];
@@ -559,7 +716,26 @@ pub async fn create_gcp_storage_workload_identity_binding(
     // Create a new GCP Storage Client
     let gcp_storage_client =
         gcp::client::GcpStorageClient::new(gcp_project_id, gcp_project_number).await?;
-    let gcp_iam_manager = gcp::bucket_manager::BucketIamManager::new(gcp_storage_client);
+
+    // Encrypt the objects of the buckets with the customer-managed key by default, so
+    // everything the instance writes is covered and not only its backups
+    if let Some(kms_key_name) = kms_key_name {
+        for bucket_name in buckets.iter() {
+            let current = gcp_storage_client.get_default_kms_key(bucket_name).await?;
+            if current.as_deref() != Some(kms_key_name) {
+                gcp_storage_client
+                    .set_default_kms_key(bucket_name, kms_key_name)
+                    .await?;
+                info!(
+                    "Set the default KMS key of bucket {} to {}",
+                    bucket_name, kms_key_name
+                );
+            }
+        }
+    }
+
+    let gcp_iam_manager =
+        gcp::bucket_manager::BucketIamManager::new(gcp_storage_client).with_federation(federation);
 
     // Create a new workload identity binding to the bucket
     gcp_iam_manager
@@ -575,6 +751,7 @@ pub async fn delete_gcp_storage_workload_identity_binding(
     backup_archive_bucket: &str,
     storage_archive_bucket: &str,
     namespace: &str,
+    federation: Option<GcpFederation>,
 ) -> Result<(), ConductorError> {
     let service_account_name = namespace;
     let buckets = vec![backup_archive_bucket, storage_archive_bucket];
@@ -582,7 +759,8 @@ pub async fn delete_gcp_storage_workload_identity_binding(
     // Create a new GCP Storage Client
     let gcp_storage_client =
         gcp::client::GcpStorageClient::new(gcp_project_id, gcp_project_number).await?;
-    let gcp_iam_manager = gcp::bucket_manager::BucketIamManager::new(gcp_storage_client);
+    let gcp_iam_manager =
+        gcp::bucket_manager::BucketIamManager::new(gcp_storage_client).with_federation(federation);
 
     // Remove the workload identity binding from the bucket
     gcp_iam_manager
@@ -599,6 +777,7 @@ pub async fn create_azure_storage_workload_identity_binding(
     azure_storage_account: &str,
     azure_backup_container: &str,
     namespace: &str,
+    restore_target: Option<&AzureStorageTarget>,
 ) -> Result<String, ConductorError> {
     let credentials = get_credentials().await?;
 
@@ -628,6 +807,22 @@ pub async fn create_azure_storage_workload_identity_binding(
     )
     .await?;
 
+    // A restore reads the backups of its source from the storage account of the source
+    if let Some(target) =
+        restore_target.filter(|target| target.storage_account != azure_storage_account)
+    {
+        create_role_assignment(
+            azure_subscription_id,
+            &target.resource_group_prefix,
+            &target.storage_account,
+            azure_backup_container,
+            namespace,
+            &uami_principal_id,
+            credentials.clone(),
+        )
+        .await?;
+    }
+
     // Create Federated Credential for the UAMI
     create_federated_identity_credentials(
         azure_subscription_id,
@@ -850,6 +1045,35 @@ mod tests {
         assert!(result["spec"]["restore"].is_null());
     }
 
+    #[tokio::test]
+    async fn test_generate_spec_with_other_cloud_bucket() {
+        let spec = CoreDBSpec {
+            restore: Some(Restore {
+                backups_path: Some("s3://aws-bucket/v2/test-instance".to_string()),
+                ..Restore::default()
+            }),
+            ..CoreDBSpec::default()
+        };
+        let cloud_provider = CloudProvider::GCP;
+        let result = generate_spec(
+            "org-id",
+            "entity-name",
+            "instance-id",
+            "gcp_data_1_usc1",
+            "namespace",
+            "my-bucket",
+            None,
+            &spec,
+            &cloud_provider,
+        )
+        .await
+        .expect("Failed to generate spec");
+        assert_eq!(
+            result["spec"]["restore"]["backupsPath"].as_str().unwrap(),
+            "s3://aws-bucket/v2/test-instance"
+        );
+    }
+
     #[tokio::test]
     async fn test_generate_spec_with_non_matching_gcp_bucket() {
         let spec = CoreDBSpec {
@@ -971,4 +1195,54 @@ mod tests {
             expected_backups_path
         );
     }
+
+    #[test]
+    fn test_tags_patch() {
+        let tags = types::InstanceTags {
+            labels: BTreeMap::from([
+                ("tembo.io/tier".to_string(), Some("pro".to_string())),
+                ("tembo.io/environment".to_string(), None),
+                (
+                    "tembo.io/instance_id".to_string(),
+                    Some("inst_1".to_string()),
+                ),
+            ]),
+            annotations: BTreeMap::new(),
+        };
+        assert_eq!(
+            tags_patch(&tags),
+            serde_json::json!({
+                "metadata": {
+                    "labels": {
+                        "tembo.io/environment": null,
+                        "tembo.io/tier": "pro",
+                    },
+                    "annotations": {},
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_resize_patch() {
+        let spec: CoreDBSpec = serde_json::from_value(serde_json::json!({
+            "storage": "20Gi",
+            "resources": {
+                "limits": {"cpu": "2", "memory": "4Gi"},
+            },
+            "replicas": 2,
+        }))
+        .unwrap();
+        assert_eq!(
+            resize_patch(&spec),
+            serde_json::json!({
+                "spec": {
+                    "storage": "20Gi",
+                    "resources": {
+                        "limits": {"cpu": "2", "memory": "4Gi"},
+                    },
+                }
+            })
+        );
+    }
 }
@@ -1,19 +1,64 @@
 use actix_web::{web, App, HttpServer};
 use actix_web_opentelemetry::{PrometheusMetricsHandler, RequestTracing};
+use conductor::applied_specs::{delete_applied, is_last_applied, record_applied};
+use conductor::audit_log::{
+    note_failure, prune_processed, record_processed, track_failures, ProcessingRecord,
+    OUTCOME_FAILED, OUTCOME_REQUEUED, OUTCOME_SUCCEEDED,
+};
+use conductor::azure::storage_targets::{
+    instance_storage_target, recorded_storage_target, AzureStorageTarget,
+};
+use conductor::backup_location::{
+    delete_backup_location, instance_backup_location, record_backup_location,
+    InstanceBackupLocation,
+};
+use conductor::backup_storage::{apply_backup_credentials, use_custom_backup_storage};
+use conductor::cache::ResourceCache;
+use conductor::capacity::check_capacity;
+use conductor::connection_encryption::{encrypt_connection, ConnectionEncryptor};
+use conductor::data_plane_config::{load_data_plane_config, DataPlaneConfig};
+use conductor::dead_letter::dead_letter;
+use conductor::deleted_instances::{
+    path_in_bucket, record_backups_path, resolve_deleted_instance_restore,
+};
 use conductor::errors::ConductorError;
-use conductor::monitoring::CustomMetrics;
+use conductor::event_ordering::{InFlight, NamespaceOrdering};
+use conductor::gcp::workload_identity_federation::{
+    apply_federation_credentials, federation_google_credentials, GcpFederation,
+};
+use conductor::heartbeat::LoopHeartbeat;
+use conductor::kube_client::{kube_client, KubeClientConfig, KubeClientMetrics};
+use conductor::monitoring::{register_loop_lag, CustomMetrics};
+use conductor::namespace_mapping::{
+    delete_namespace_mapping, ordering_namespaces, resolve_namespace,
+};
+use conductor::pending_deletion::{pending_deletion, remove_pending_deletion, schedule_deletion};
+use conductor::quarantine::{parse_message, quarantine};
+use conductor::queue_shards::{parse_weights, shard_queue_names, WeightedRoundRobin};
 use conductor::{
     cloud::CloudProvider, create_azure_storage_workload_identity_binding, create_cloudformation,
     create_gcp_storage_workload_identity_binding, create_namespace, create_or_update, delete,
     delete_azure_storage_workload_identity_binding, delete_cloudformation,
     delete_gcp_storage_workload_identity_binding, delete_namespace, generate_cron_expression,
     generate_spec, get_coredb_error_without_status, get_one, get_pg_conn, lookup_role_arn,
-    restart_coredb, types,
+    resize_coredb, restart_coredb, set_coredb_stopped, types, update_tags,
 };
 
 use crate::metrics_reporter::run_metrics_reporter;
 use crate::status_reporter::run_status_reporter;
+use conductor::rollout::{
+    mark_dispatched, next_step, progress, record_result, rollout_states, InstanceState, Step,
+};
+use conductor::routes::admin::{archive, get_messages, get_queues, requeue, AdminConfig};
+use conductor::routes::audit_log::{get_instance_events, get_processed_events};
+use conductor::routes::dead_letters::{get_dead_letters, replay};
 use conductor::routes::health::background_threads_running;
+use conductor::routes::quarantine::{get_quarantined, replay_quarantined_message};
+use conductor::spec_validation::validate_coredb;
+use conductor::telemetry::{current_trace_context, end_span, event_span, in_span};
+use conductor::upgrade::{
+    get_upgrade_progress, start_upgrade, target_major_version, UpgradeProgress,
+};
 use controller::apis::coredb_types::{
     AzureCredentials, Backup, CoreDBSpec, GoogleCredentials, S3Credentials, ServiceAccountTemplate,
     VolumeSnapshot,
@@ -24,13 +69,16 @@ use kube::Client;
 use log::{debug, error, info, warn};
 use opentelemetry::sdk::export::metrics::aggregation;
 use opentelemetry::sdk::metrics::{controllers, processors, selectors};
+use opentelemetry::trace::FutureExt as _;
 use opentelemetry::{global, KeyValue};
 use pgmq::{Message, PGMQueueExt};
+use serde_json::Value;
 use sqlx::error::Error;
-use sqlx::postgres::PgPoolOptions;
+use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::env;
 use std::sync::{Arc, Mutex};
 use std::time;
+use tokio::sync::Semaphore;
 use types::{CRUDevent, Event};
 
 mod metrics_reporter;
@@ -44,107 +92,68 @@ const REQUEUE_VT_SEC_SHORT: i32 = 5;
 // that we would want to try again after awhile.
 const REQUEUE_VT_SEC_LONG: i32 = 300;
 
-async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
+// Amount of time to wait before checking again whether the instances of a rollout
+// were updated
+const ROLLOUT_POLL_VT_SEC: i32 = 30;
+
+// Amount of time to wait before checking again whether an instance was upgraded
+const UPGRADE_POLL_VT_SEC: i32 = 30;
+
+// How often processed events older than the retention are deleted from the audit log
+const AUDIT_LOG_PRUNE_INTERVAL: time::Duration = time::Duration::from_secs(3600);
+
+// How often the loop beats while it waits for a worker
+const HEARTBEAT_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+async fn run(
+    metrics: CustomMetrics,
+    cache: ResourceCache,
+    heartbeat: LoopHeartbeat,
+) -> Result<(), ConductorError> {
     let pg_conn_url =
         env::var("POSTGRES_QUEUE_CONNECTION").expect("POSTGRES_QUEUE_CONNECTION must be set");
     let control_plane_events_queue =
         env::var("CONTROL_PLANE_EVENTS_QUEUE").expect("CONTROL_PLANE_EVENTS_QUEUE must be set");
+    // Control plane events can be sharded by organization over several queues, which are
+    // read in turns so an organization with many events does not hold up the others
+    let control_plane_events_shards: usize =
+        from_env_default("CONTROL_PLANE_EVENTS_QUEUE_SHARDS", "0")
+            .parse()
+            .expect("error parsing CONTROL_PLANE_EVENTS_QUEUE_SHARDS");
+    let control_plane_events_queues =
+        shard_queue_names(&control_plane_events_queue, control_plane_events_shards);
+    let queue_weights = parse_weights(
+        &from_env_default("CONTROL_PLANE_EVENTS_QUEUE_WEIGHTS", ""),
+        control_plane_events_queues.len(),
+    )?;
     let metrics_events_queue =
         env::var("METRICS_EVENTS_QUEUE").expect("METRICS_EVENTS_QUEUE must be set");
     let data_plane_events_queue =
         env::var("DATA_PLANE_EVENTS_QUEUE").expect("DATA_PLANE_EVENTS_QUEUE must be set");
-    let data_plane_basedomain =
-        env::var("DATA_PLANE_BASEDOMAIN").expect("DATA_PLANE_BASEDOMAIN must be set");
-    let backup_archive_bucket =
-        env::var("BACKUP_ARCHIVE_BUCKET").expect("BACKUP_ARCHIVE_BUCKET must be set");
-    let storage_archive_bucket =
-        env::var("STORAGE_ARCHIVE_BUCKET").expect("STORAGE_ARCHIVE_BUCKET must be set");
-    let cf_template_bucket: String = env::var("CF_TEMPLATE_BUCKET")
-        .unwrap_or_else(|_| "".to_owned())
-        .parse()
-        .expect("error parsing CF_TEMPLATE_BUCKET");
     let max_read_ct: i32 = env::var("MAX_READ_CT")
         .unwrap_or_else(|_| "100".to_owned())
         .parse()
         .expect("error parsing MAX_READ_CT");
-    let is_cloud_formation: bool = env::var("IS_CLOUD_FORMATION")
-        .unwrap_or_else(|_| "true".to_owned())
-        .parse()
-        .expect("error parsing IS_CLOUD_FORMATION");
-    let aws_region: String = env::var("AWS_REGION")
-        .unwrap_or_else(|_| "us-east-1".to_owned())
-        .parse()
-        .expect("error parsing AWS_REGION");
-    let is_gcp: bool = env::var("IS_GCP")
-        .unwrap_or_else(|_| "false".to_owned())
-        .parse()
-        .expect("error parsing IS_GCP");
-    let gcp_project_id: String = env::var("GCP_PROJECT_ID")
-        .unwrap_or_else(|_| "".to_owned())
-        .parse()
-        .expect("error parsing GCP_PROJECT_ID");
-    let gcp_project_number: String = env::var("GCP_PROJECT_NUMBER")
-        .unwrap_or_else(|_| "".to_owned())
-        .parse()
-        .expect("error parsing GCP_PROJECT_NUMBER");
-    let is_azure: bool = env::var("IS_AZURE")
-        .unwrap_or_else(|_| "false".to_owned())
+    // How many messages are processed concurrently
+    let workers: usize = env::var("CONDUCTOR_WORKERS")
+        .unwrap_or_else(|_| "8".to_owned())
         .parse()
-        .expect("error parsing IS_AZURE");
-    let azure_storage_account: String = env::var("AZURE_STORAGE_ACCOUNT")
-        .unwrap_or_else(|_| "".to_owned())
+        .expect("error parsing CONDUCTOR_WORKERS");
+    // Major version upgrades which did not complete in this time are reported as failed
+    let upgrade_timeout: u64 = from_env_default("UPGRADE_TIMEOUT_SECONDS", "3600")
         .parse()
-        .expect("error parsing AZURE_STORAGE_ACCOUNT");
-    let azure_subscription_id: String = env::var("AZURE_SUBSCRIPTION_ID")
-        .unwrap_or_else(|_| "".to_owned())
+        .expect("error parsing UPGRADE_TIMEOUT_SECONDS");
+    let upgrade_timeout = chrono::Duration::seconds(upgrade_timeout as i64);
+    // Processed events are kept in the audit log for this many days, 0 keeps them forever
+    let audit_log_retention_days: u64 = from_env_default("AUDIT_LOG_RETENTION_DAYS", "30")
         .parse()
-        .expect("error parsing AZURE_SUBSCRIPTION_ID");
-    // This is necessary for working with multiple resource groups. Example format: cdb-plat-eus-dev
-    let azure_resource_group_prefix: String = env::var("AZURE_RESOURCE_GROUP_PREFIX")
-        .unwrap_or_else(|_| "".to_owned())
-        .parse()
-        .expect("error parsing AZURE_RESOURCE_GROUP_PREFIX");
-    let azure_region: String = env::var("AZURE_REGION")
-        .unwrap_or_else(|_| "".to_owned())
-        .parse()
-        .expect("error parsing AZURE_REGION");
-    let is_loadbalancer_public: bool = env::var("IS_LOADBALANCER_PUBLIC")
-        .unwrap_or_else(|_| "true".to_owned())
-        .parse()
-        .expect("error parsing IS_LOADBALANCER_PUBLIC");
+        .expect("error parsing AUDIT_LOG_RETENTION_DAYS");
 
-    // Error and exit if CF_TEMPLATE_BUCKET is not set when IS_CLOUD_FORMATION is enabled
-    if is_cloud_formation && cf_template_bucket.is_empty() {
-        panic!("CF_TEMPLATE_BUCKET is required when IS_CLOUD_FORMATION is true");
-    }
-
-    // Only allow for setting one of IS_CLOUD_FORMATION, IS_GCP, or IS_AZURE to true
-    let cloud_providers = [is_cloud_formation, is_gcp, is_azure]
-        .iter()
-        .filter(|&&x| x)
-        .count();
-    if cloud_providers > 1 {
-        panic!("Only one of IS_CLOUD_FORMATION, IS_GCP, or IS_AZURE can be set to true");
-    }
-
-    // Error and exit if IS_GCP is true and GCP_PROJECT_ID or GCP_PROJECT_NUMBER are not set
-    if is_gcp && (gcp_project_id.is_empty() || gcp_project_number.is_empty()) {
-        panic!("GCP_PROJECT_ID and GCP_PROJECT_NUMBER must be set if IS_GCP is true");
-    }
-
-    // Error and exit if IS_AZURE is true and any of the required Azure environment variables are not set
-    if let Err(err) = validate_azure_environment(
-        is_azure,
-        &azure_storage_account,
-        &azure_subscription_id,
-        &azure_resource_group_prefix,
-        &azure_region,
-    ) {
-        panic!("{}", err);
-    }
+    // Bucket names, domains and cloud flags, optionally fetched from the control plane
+    let data_plane_config = load_data_plane_config().await?;
 
     // Connect to pgmq
-    let queue = PGMQueueExt::new(pg_conn_url.clone(), 5).await?;
+    let queue = PGMQueueExt::new(pg_conn_url.clone(), workers as u32 + 1).await?;
     queue.init().await?;
 
     // enable pg_partman in the queue -- pass in the connection to the queue to execution
@@ -154,14 +163,19 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
         .expect("failed to init pg_partman extension in the queue");
 
     // Create queues if they do not exist
-    queue
-        .create_partitioned(&control_plane_events_queue)
-        .await?;
+    for control_plane_events_queue in &control_plane_events_queues {
+        queue.create_partitioned(control_plane_events_queue).await?;
+    }
     queue.create_partitioned(&data_plane_events_queue).await?;
     queue.create_partitioned(&metrics_events_queue).await?;
 
-    // Infer the runtime environment and try to create a Kubernetes Client
-    let client = Client::try_default().await?;
+    // Reads of CoreDBs and Secrets go through the shared cache
+    let client = cache.client();
+
+    let encryptor = ConnectionEncryptor::from_env()?;
+    if encryptor.is_some() {
+        info!("Encrypting the passwords of connection info with CONNECTION_ENCRYPTION_PUBLIC_KEY");
+    }
 
     // Connection Pool
     let db_pool = PgPoolOptions::new()
@@ -180,27 +194,89 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
 
     log::info!("Database migrations have been successfully applied.");
 
-    // Determine the cloud provider using the builder
-    let cloud_provider = CloudProvider::builder()
-        .gcp(is_gcp)
-        .aws(is_cloud_formation)
-        .azure(is_azure)
-        .build();
+    let ctx = Arc::new(EventContext {
+        metrics,
+        client,
+        cache,
+        queue,
+        db_pool,
+        control_plane_events_queues,
+        data_plane_events_queue,
+        max_read_ct,
+        encryptor,
+        upgrade_timeout,
+    });
+
+    // Messages are processed concurrently by up to `workers` tasks. Events for the same
+    // namespace wait for the previous one to complete, so each instance sees its events in
+    // the order they were read.
+    let permits = Arc::new(Semaphore::new(workers));
+    let in_flight = InFlight::new();
+    let mut ordering = NamespaceOrdering::new();
+    let mut scheduler = WeightedRoundRobin::new(&queue_weights);
+    let mut next_audit_log_prune = time::Instant::now();
 
     loop {
-        // Read from queue (check for new message)
-        // messages that don't fit a CRUDevent will error
+        // The loop is stuck when it does not come back here, e.g. on a wedged pgmq connection
+        heartbeat.beat();
+
+        if audit_log_retention_days > 0 && time::Instant::now() >= next_audit_log_prune {
+            next_audit_log_prune = time::Instant::now() + AUDIT_LOG_PRUNE_INTERVAL;
+            let db_pool = ctx.db_pool.clone();
+            let retention = time::Duration::from_secs(audit_log_retention_days * 24 * 3600);
+            tokio::spawn(async move {
+                match prune_processed(&db_pool, retention).await {
+                    Ok(deleted) => debug!("pruned {} processed events from the audit log", deleted),
+                    Err(err) => error!("failed to prune the audit log: {}", err),
+                }
+            });
+        }
+
+        // Workers busy with long events do not make the loop stuck, it beats while it waits
+        let permit = heartbeat
+            .acquire_beating(permits.clone(), HEARTBEAT_INTERVAL)
+            .await;
+
+        // Take a snapshot of the data plane configuration, so a reload applies from the next message
+        let config = data_plane_config
+            .read()
+            .expect("data plane config lock")
+            .clone();
+
+        // Read from the queue whose turn it is, or the next one with a message
+        // messages that don't fit a CRUDevent are quarantined below
         // set visibility timeout to 90 seconds
-        let read_msg = queue
-            .read::<CRUDevent>(&control_plane_events_queue, 90_i32)
-            .await?;
-        let read_msg: Message<CRUDevent> = match read_msg {
-            Some(message) => {
+        let mut next_msg = None;
+        for shard in scheduler.next_order() {
+            match ctx
+                .queue
+                .read::<Value>(&ctx.control_plane_events_queues[shard], 90_i32)
+                .await
+            {
+                Ok(Some(message)) => {
+                    next_msg = Some((shard, message));
+                    break;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    // Let the running tasks finish, so events are not processed out of order
+                    // once the loop is restarted
+                    drop(permit);
+                    let _ = permits.acquire_many(workers as u32).await;
+                    return Err(err.into());
+                }
+            }
+        }
+        let (shard, raw_msg): (usize, Message<Value>) = match next_msg {
+            Some((shard, message)) => {
                 info!(
-                    "msg_id: {}, enqueued_at: {}, vt: {}",
-                    message.msg_id, message.enqueued_at, message.vt
+                    "queue: {}, msg_id: {}, enqueued_at: {}, vt: {}",
+                    ctx.control_plane_events_queues[shard],
+                    message.msg_id,
+                    message.enqueued_at,
+                    message.vt
                 );
-                message
+                (shard, message)
             }
             None => {
                 debug!("no messages in queue");
@@ -209,216 +285,600 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
             }
         };
 
-        let org_id = &read_msg.message.org_id;
-        let instance_id = &read_msg.message.inst_id;
-        let namespace = read_msg.message.namespace.clone();
-        info!("{}: Using namespace {}", read_msg.msg_id, &namespace);
-
-        if read_msg.message.event_type != Event::Delete {
-            let namespace_already_deleted = match sqlx::query!(
-                "SELECT * FROM deleted_instances WHERE namespace = $1;",
-                &namespace
-            )
-            .fetch_optional(&db_pool)
-            .await
-            {
-                Ok(Some(_)) => true,
-                Ok(None) => false,
-                Err(e) => {
-                    error!("Database query error: {}", e);
-                    continue;
-                }
-            };
-
-            if namespace_already_deleted {
-                info!(
-                    "{}: Namespace {} marked as deleted, archiving message.",
-                    read_msg.msg_id, namespace
+        // A malformed message would fail on every read, it is set aside for triage
+        let read_msg: Message<CRUDevent> = match parse_message(&raw_msg) {
+            Ok(read_msg) => read_msg,
+            Err(err) => {
+                let queue_name = &ctx.control_plane_events_queues[shard];
+                error!(
+                    "{}: malformed message, quarantining: {}",
+                    raw_msg.msg_id, err
                 );
-                if let Err(e) = queue
-                    .archive(&control_plane_events_queue, read_msg.msg_id)
-                    .await
-                {
-                    error!("Failed to archive message: {}", e);
+                ctx.metrics
+                    .conductor_errors
+                    .add(&opentelemetry::Context::current(), 1, &[]);
+                match quarantine(&ctx.db_pool, queue_name, &raw_msg, &err.to_string()).await {
+                    Ok(id) => {
+                        info!("{}: quarantined as {}", raw_msg.msg_id, id);
+                        if let Err(err) = ctx.queue.archive(queue_name, raw_msg.msg_id).await {
+                            error!("{}: failed to archive: {}", raw_msg.msg_id, err);
+                        }
+                    }
+                    // The message is read again once its visibility timeout expires
+                    Err(err) => error!("{}: failed to quarantine: {}", raw_msg.msg_id, err),
                 }
                 continue;
             }
+        };
+
+        // A message waiting behind earlier events of its namespace can become visible again,
+        // it is only processed once
+        let msg_id = read_msg.msg_id;
+        if !in_flight.insert(shard, msg_id) {
+            debug!("{}: already waiting to be processed", msg_id);
+            continue;
         }
 
-        metrics
-            .conductor_total
-            .add(&opentelemetry::Context::current(), 1, &[]);
+        // Events are ordered on the namespace of the instance, which is not the requested one
+        // when it was remapped. The message is read again once its visibility timeout expires.
+        let namespaces = match ordering_namespaces(&ctx.db_pool, &read_msg.message).await {
+            Ok(namespaces) => namespaces,
+            Err(err) => {
+                error!("{}: failed to look up the namespace: {}", msg_id, err);
+                in_flight.remove(shard, msg_id);
+                continue;
+            }
+        };
+        let mut ticket = ordering.enqueue(&namespaces);
+
+        let ctx = ctx.clone();
+        let in_flight = in_flight.clone();
+        tokio::spawn(async move {
+            ticket.wait().await;
+            let control_plane_events_queue = &ctx.control_plane_events_queues[shard];
+            let trace_cx = event_span(&read_msg.message, msg_id);
+            let record = ProcessingRecord::new(control_plane_events_queue, &read_msg);
+            let started = time::Instant::now();
+            let (result, failure) = track_failures(
+                process_message(&ctx, config, control_plane_events_queue, read_msg)
+                    .with_context(trace_cx.clone()),
+            )
+            .await;
+            let duration = started.elapsed();
+            end_span(&trace_cx, &result);
+            // A message whose processing failed is not archived, it is processed again once
+            // it is visible again
+            let (outcome, error) = match &result {
+                Err(err) => (OUTCOME_REQUEUED, Some(err.to_string())),
+                Ok(_) => match failure {
+                    Some((outcome, error)) => (outcome, Some(error)),
+                    None => (OUTCOME_SUCCEEDED, None),
+                },
+            };
+            if let Err(err) = result {
+                ctx.metrics
+                    .conductor_errors
+                    .add(&opentelemetry::Context::current(), 1, &[]);
+                error!("{}: error processing message: {:?}", msg_id, err);
+            }
+            if let Err(err) =
+                record_processed(&ctx.db_pool, &record, duration, outcome, error.as_deref()).await
+            {
+                error!("{}: failed to record in the audit log: {}", msg_id, err);
+            }
+            in_flight.remove(shard, msg_id);
+            drop(ticket);
+            drop(permit);
+        });
+    }
+}
 
-        // note: messages are recycled on purpose
-        // but absurdly high read_ct means its probably never going to get processed
-        if read_msg.read_ct >= max_read_ct {
-            error!(
-                "{}: archived message with read_count >= `{}`: {:?}",
-                read_msg.msg_id, max_read_ct, read_msg
-            );
-            queue
-                .archive(&control_plane_events_queue, read_msg.msg_id)
+// What the tasks processing messages share
+struct EventContext {
+    metrics: CustomMetrics,
+    client: Client,
+    cache: ResourceCache,
+    queue: PGMQueueExt,
+    db_pool: PgPool,
+    // The base queue followed by its shards
+    control_plane_events_queues: Vec<String>,
+    data_plane_events_queue: String,
+    max_read_ct: i32,
+    // Encrypts the passwords of the connection info reported to control plane
+    encryptor: Option<ConnectionEncryptor>,
+    // How long since it was sent an Upgrade event waits for the upgrade to complete
+    upgrade_timeout: chrono::Duration,
+}
+
+// Process a message read from control_plane_events_queue, it is archived or requeued there
+async fn process_message(
+    ctx: &EventContext,
+    config: DataPlaneConfig,
+    control_plane_events_queue: &str,
+    mut read_msg: Message<CRUDevent>,
+) -> Result<(), ConductorError> {
+    let EventContext {
+        metrics,
+        client,
+        cache,
+        queue,
+        db_pool,
+        control_plane_events_queues: _,
+        data_plane_events_queue,
+        max_read_ct,
+        encryptor,
+        upgrade_timeout,
+    } = ctx;
+    let pod_identity_cluster = config.pod_identity_cluster();
+    let gcp_federation = config.gcp_federation();
+    let default_azure_target = config.default_azure_storage_target();
+    let DataPlaneConfig {
+        data_plane_basedomain,
+        backup_archive_bucket,
+        storage_archive_bucket,
+        cf_template_bucket,
+        is_cloud_formation,
+        aws_region,
+        aws_identity_mode: _,
+        eks_cluster_name: _,
+        is_gcp,
+        gcp_project_id,
+        gcp_project_number,
+        gcp_kms_key_name,
+        gcp_identity_mode: _,
+        gcp_workload_identity_pool: _,
+        gcp_workload_identity_provider: _,
+        is_azure,
+        azure_storage_account: _,
+        azure_subscription_id,
+        azure_resource_group_prefix: _,
+        azure_region,
+        azure_storage_mapping,
+        is_loadbalancer_public,
+        delete_grace_period_hours,
+        check_cluster_capacity,
+    } = config;
+
+    // Determine the cloud provider using the builder
+    let cloud_provider = CloudProvider::builder()
+        .gcp(is_gcp)
+        .aws(is_cloud_formation)
+        .azure(is_azure)
+        .build();
+
+    // A deleted instance is restored from the backups it left behind
+    if read_msg.message.event_type == Event::Restore {
+        match resolve_deleted_instance_restore(db_pool, &mut read_msg.message).await {
+            Ok(()) => {}
+            Err(err @ ConductorError::RestoreSourceRejected(_)) => {
+                error!("{}: {}", read_msg.msg_id, err);
+                handle_error(
+                    metrics,
+                    control_plane_events_queue,
+                    data_plane_events_queue,
+                    queue,
+                    db_pool,
+                    &read_msg,
+                    err,
+                )
                 .await?;
-            metrics
-                .conductor_errors
-                .add(&opentelemetry::Context::current(), 1, &[]);
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+    }
 
-            // this is what we'll send back to control-plane
-            let error_event = types::StateToControlPlane {
-                data_plane_id: read_msg.message.data_plane_id,
-                org_id: read_msg.message.org_id,
-                inst_id: read_msg.message.inst_id,
-                event_type: Event::Error,
-                spec: None,
-                status: None,
-                connection: None,
-            };
-            let msg_id = queue.send(&data_plane_events_queue, &error_event).await?;
-            error!(
-                "{}: sent error event to control-plane: {}",
-                read_msg.msg_id, msg_id
+    // The namespace of another instance is not adopted, the instance gets one of its own
+    let requested_namespace = read_msg.message.namespace.clone();
+    match resolve_namespace(db_pool, client.clone(), &read_msg.message).await {
+        Ok(namespace) => read_msg.message.namespace = namespace,
+        Err(err @ ConductorError::NamespaceCollision(_)) => {
+            error!("{}: {}", read_msg.msg_id, err);
+            handle_error(
+                metrics,
+                control_plane_events_queue,
+                data_plane_events_queue,
+                queue,
+                db_pool,
+                &read_msg,
+                err,
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    }
+
+    let org_id = &read_msg.message.org_id;
+    let instance_id = &read_msg.message.inst_id;
+    let namespace = read_msg.message.namespace.clone();
+    info!("{}: Using namespace {}", read_msg.msg_id, &namespace);
+
+    if read_msg.message.event_type != Event::Delete {
+        let namespace_already_deleted = match sqlx::query!(
+            "SELECT * FROM deleted_instances WHERE namespace = $1;",
+            &namespace
+        )
+        .fetch_optional(db_pool)
+        .await
+        {
+            Ok(Some(_)) => true,
+            Ok(None) => false,
+            Err(e) => {
+                error!("Database query error: {}", e);
+                return Ok(());
+            }
+        };
+
+        if namespace_already_deleted {
+            info!(
+                "{}: Namespace {} marked as deleted, archiving message.",
+                read_msg.msg_id, namespace
             );
-            continue;
+            if let Err(e) = queue
+                .archive(control_plane_events_queue, read_msg.msg_id)
+                .await
+            {
+                error!("Failed to archive message: {}", e);
+            }
+            rollout_failed(db_pool, &read_msg, "namespace was deleted").await;
+            return Ok(());
         }
+    }
+
+    metrics
+        .conductor_total
+        .add(&opentelemetry::Context::current(), 1, &[]);
+
+    // note: messages are recycled on purpose
+    // but absurdly high read_ct means its probably never going to get processed
+    // so it is moved to the dead letter table, where it can be inspected and replayed.
+    // Upgrade events are read again until the upgrade completes, they are bounded by
+    // the upgrade timeout instead.
+    let upgrading = read_msg.message.event_type == Event::Upgrade
+        && chrono::Utc::now() - read_msg.enqueued_at <= *upgrade_timeout;
+    if read_msg.read_ct >= *max_read_ct && !upgrading {
+        let reason = format!("message exceeded max read count of {}", max_read_ct);
+        let dead_letter_id =
+            dead_letter(db_pool, control_plane_events_queue, &read_msg, &reason).await?;
+        error!(
+            "{}: dead lettered message with read_count >= `{}` as {}: {:?}",
+            read_msg.msg_id, max_read_ct, dead_letter_id, read_msg
+        );
+        queue
+            .archive(control_plane_events_queue, read_msg.msg_id)
+            .await?;
+        metrics
+            .conductor_errors
+            .add(&opentelemetry::Context::current(), 1, &[]);
+        rollout_failed(db_pool, &read_msg, &reason).await;
+
+        // this is what we'll send back to control-plane
+        let error_event = types::StateToControlPlane {
+            version: types::EVENT_SCHEMA_VERSION,
+            data_plane_id: read_msg.message.data_plane_id,
+            org_id: read_msg.message.org_id,
+            inst_id: read_msg.message.inst_id,
+            event_type: Event::Error,
+            spec: None,
+            status: None,
+            connection: None,
+            error: Some(types::ErrorDetails {
+                code: types::ErrorCode::Timeout,
+                retryable: true,
+                message: reason,
+                fields: vec![],
+            }),
+            rollout: None,
+            delta: None,
+        };
+        let msg_id = queue.send(data_plane_events_queue, &error_event).await?;
+        error!(
+            "{}: sent error event to control-plane: {}",
+            read_msg.msg_id, msg_id
+        );
+        return Ok(());
+    }
+
+    // The rollout the event is part of, to record its outcome once it was processed
+    let rollout_instance = read_msg
+        .message
+        .rollout_id
+        .clone()
+        .map(|rollout_id| (rollout_id, read_msg.message.inst_id.clone()));
 
-        // Based on message_type in message, create, update, delete CoreDB
-        let event_msg: types::StateToControlPlane = match read_msg.message.event_type {
-            // every event is for a single namespace
-            Event::Create | Event::Update | Event::Restore | Event::Start | Event::Stop => {
-                info!("{}: Got create, restore or update event", read_msg.msg_id);
+    // Instances can back up to another region and bucket than the ones of the data plane
+    let default_location = InstanceBackupLocation {
+        aws_region,
+        backup_archive_bucket,
+    };
+    let location = instance_backup_location(db_pool, &read_msg.message, &default_location).await?;
+    let InstanceBackupLocation {
+        aws_region,
+        backup_archive_bucket,
+    } = location.clone();
+
+    // These events change or remove the Secrets of the instance
+    if matches!(
+        read_msg.message.event_type,
+        Event::Update | Event::Restart | Event::Restore | Event::Delete
+    ) {
+        cache.connections().invalidate(&namespace);
+    }
 
-                // (todo: nhudson) in the future move this to be more specific
-                // to the event that we are taking action on.  For now just create
-                // the stack without checking.
+    // Based on message_type in message, create, update, delete CoreDB
+    let event_msg: types::StateToControlPlane = match read_msg.message.event_type {
+        // every event is for a single namespace
+        Event::Create | Event::Update | Event::Restore | Event::Start | Event::Stop => {
+            info!("{}: Got create, restore or update event", read_msg.msg_id);
 
-                if read_msg.message.spec.is_none() {
-                    error!(
-                        "{}: spec is required on create and update events, archiving message",
-                        read_msg.msg_id
-                    );
-                    let _archived = queue
-                        .archive(&control_plane_events_queue, read_msg.msg_id)
-                        .await?;
-                    metrics
-                        .conductor_errors
-                        .add(&opentelemetry::Context::current(), 1, &[]);
-                    continue;
-                }
-                // spec.expect() should be safe here - since above we continue in loop when it is None
-                let msg_spec = read_msg.message.spec.clone().expect("message spec");
+            // (todo: nhudson) in the future move this to be more specific
+            // to the event that we are taking action on.  For now just create
+            // the stack without checking.
 
-                info!("{}: Creating cloudformation template", read_msg.msg_id);
+            if read_msg.message.spec.is_none() {
+                error!(
+                    "{}: spec is required on create and update events, archiving message",
+                    read_msg.msg_id
+                );
+                let _archived = queue
+                    .archive(control_plane_events_queue, read_msg.msg_id)
+                    .await?;
+                metrics
+                    .conductor_errors
+                    .add(&opentelemetry::Context::current(), 1, &[]);
+                return Ok(());
+            }
+            // spec.expect() should be safe here - since above we return when it is None
+            let msg_spec = read_msg.message.spec.clone().expect("message spec");
 
-                // Merge backup and service_account_template into spec
-                let mut coredb_spec = msg_spec;
+            info!("{}: Creating cloudformation template", read_msg.msg_id);
 
-                match init_cloud_perms(
+            // Merge backup and service_account_template into spec
+            let mut coredb_spec = msg_spec;
+
+            match in_span(
+                "cloud_permissions",
+                init_cloud_perms(
                     aws_region.clone(),
                     backup_archive_bucket.clone(),
                     storage_archive_bucket.clone(),
                     cf_template_bucket.clone(),
+                    pod_identity_cluster.clone(),
                     &read_msg,
                     &mut coredb_spec,
                     is_cloud_formation,
-                    &client,
+                    client,
                     is_loadbalancer_public,
-                )
-                .await
-                {
-                    Ok(arn) => {
-                        info!(
-                            "{}: CloudFormation stack outputs ready, got outputs.",
-                            read_msg.msg_id
+                ),
+            )
+            .await
+            {
+                Ok(arn) => {
+                    info!(
+                        "{}: CloudFormation stack outputs ready, got outputs.",
+                        read_msg.msg_id
+                    );
+                    arn
+                }
+                Err(err) => match err {
+                    ConductorError::NoOutputsFound => {
+                        info!("{}: CloudFormation stack outputs not ready, requeuing with short duration.", read_msg.msg_id);
+                        // Requeue the message for a short duration
+                        let _ = queue
+                            .set_vt::<CRUDevent>(
+                                control_plane_events_queue,
+                                read_msg.msg_id,
+                                REQUEUE_VT_SEC_SHORT,
+                            )
+                            .await?;
+                        metrics.conductor_requeues.add(
+                            &opentelemetry::Context::current(),
+                            1,
+                            &[KeyValue::new("queue_duration", "short")],
                         );
-                        arn
+                        return Ok(());
                     }
-                    Err(err) => match err {
-                        ConductorError::NoOutputsFound => {
-                            info!("{}: CloudFormation stack outputs not ready, requeuing with short duration.", read_msg.msg_id);
-                            // Requeue the message for a short duration
-                            let _ = queue
-                                .set_vt::<CRUDevent>(
-                                    &control_plane_events_queue,
-                                    read_msg.msg_id,
-                                    REQUEUE_VT_SEC_SHORT,
-                                )
-                                .await?;
-                            metrics.conductor_requeues.add(
-                                &opentelemetry::Context::current(),
-                                1,
-                                &[KeyValue::new("queue_duration", "short")],
-                            );
-                            continue;
-                        }
-                        _ => {
-                            error!(
-                                "{}: Failed to get stack outputs with error: {}",
-                                read_msg.msg_id, err
-                            );
-                            let _ = queue
-                                .set_vt::<CRUDevent>(
-                                    &control_plane_events_queue,
-                                    read_msg.msg_id,
-                                    REQUEUE_VT_SEC_LONG,
-                                )
-                                .await?;
-                            metrics.conductor_requeues.add(
-                                &opentelemetry::Context::current(),
-                                1,
-                                &[KeyValue::new("queue_duration", "long")],
-                            );
-                            continue;
-                        }
-                    },
-                };
+                    _ => {
+                        error!(
+                            "{}: Failed to get stack outputs with error: {}",
+                            read_msg.msg_id, err
+                        );
+                        handle_error(
+                            metrics,
+                            control_plane_events_queue,
+                            data_plane_events_queue,
+                            queue,
+                            db_pool,
+                            &read_msg,
+                            err,
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                },
+            };
 
+            in_span(
+                "gcp_workload_identity",
                 init_gcp_storage_workload_identity(
                     is_gcp,
                     gcp_project_id.clone(),
                     gcp_project_number.clone(),
+                    Some(gcp_kms_key_name.clone()).filter(|key| !key.is_empty()),
+                    gcp_federation.clone(),
                     &read_msg,
                     &mut coredb_spec,
                     backup_archive_bucket.clone(),
                     storage_archive_bucket.clone(),
-                )
-                .await?;
+                ),
+            )
+            .await?;
+
+            // A restore reads the backups of its source instance, which may be in another
+            // storage account. Sources which existed before their target was recorded were
+            // created with the default one.
+            let restore_azure_target = match (is_azure, &read_msg.message.event_type) {
+                (true, Event::Restore) => {
+                    let source = read_msg
+                        .message
+                        .spec
+                        .as_ref()
+                        .and_then(|spec| spec.restore.as_ref())
+                        .map(|restore| restore.server_name.as_str());
+                    match source {
+                        Some(source) => Some(
+                            recorded_storage_target(db_pool, source)
+                                .await?
+                                .unwrap_or_else(|| default_azure_target.clone()),
+                        ),
+                        None => None,
+                    }
+                }
+                _ => None,
+            };
 
+            // New instances are placed with the storage mapping, instances which existed
+            // before their target was recorded were created with the default one
+            let azure_target = match (is_azure, &read_msg.message.event_type) {
+                (false, _) => default_azure_target,
+                (true, Event::Create | Event::Restore) => {
+                    let candidate = azure_storage_mapping.select(
+                        org_id,
+                        &read_msg.message.data_plane_id,
+                        &default_azure_target,
+                    );
+                    instance_storage_target(db_pool, &namespace, &candidate).await?
+                }
+                (true, _) => {
+                    instance_storage_target(db_pool, &namespace, &default_azure_target).await?
+                }
+            };
+
+            in_span(
+                "azure_workload_identity",
                 init_azure_storage_workload_identity(
                     is_azure,
                     &read_msg,
                     &mut coredb_spec,
                     backup_archive_bucket.clone(),
-                    azure_storage_account.clone(),
+                    azure_target.storage_account.clone(),
                     azure_subscription_id.clone(),
-                    azure_resource_group_prefix.clone(),
+                    azure_target.resource_group_prefix.clone(),
                     azure_region.clone(),
+                    restore_azure_target.as_ref(),
+                ),
+            )
+            .await?;
+
+            info!("{}: Creating namespace", read_msg.msg_id);
+            // create Namespace
+            if let Err(err) = in_span(
+                "namespace",
+                create_namespace(client.clone(), &namespace, org_id, instance_id),
+            )
+            .await
+            {
+                error!("{}: Failed to create namespace: {}", read_msg.msg_id, err);
+                handle_error(
+                    metrics,
+                    control_plane_events_queue,
+                    data_plane_events_queue,
+                    queue,
+                    db_pool,
+                    &read_msg,
+                    err,
                 )
                 .await?;
+                return Ok(());
+            }
 
-                info!("{}: Creating namespace", read_msg.msg_id);
-                // create Namespace
-                create_namespace(client.clone(), &namespace, org_id, instance_id).await?;
-
-                info!("{}: Generating spec", read_msg.msg_id);
-                let stack_type = match coredb_spec.stack.as_ref() {
-                    Some(stack) => stack.name.clone(),
-                    None => String::from("NA"),
-                };
+            // The credential configuration backups read outside of GKE
+            if let (true, Some(federation)) = (is_gcp, &gcp_federation) {
+                if let Err(err) = in_span(
+                    "gcp_federation",
+                    apply_federation_credentials(client.clone(), &namespace, federation),
+                )
+                .await
+                {
+                    error!(
+                        "{}: Failed to apply GCP federation credentials: {}",
+                        read_msg.msg_id, err
+                    );
+                    handle_error(
+                        metrics,
+                        control_plane_events_queue,
+                        data_plane_events_queue,
+                        queue,
+                        db_pool,
+                        &read_msg,
+                        err,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
 
-                include_storage_configuration(
-                    storage_archive_bucket.clone(),
-                    &read_msg,
-                    &mut coredb_spec,
+            // Instances of customers bringing their own bucket back up there instead
+            if let Some(storage) = &read_msg.message.backup_storage {
+                info!(
+                    "{}: Backing up to {} at {}",
+                    read_msg.msg_id, storage.bucket, storage.endpoint_url
                 );
+                if let Err(err) = in_span(
+                    "backup_storage",
+                    apply_backup_credentials(client.clone(), &namespace, storage),
+                )
+                .await
+                {
+                    error!(
+                        "{}: Failed to apply backup storage credentials: {}",
+                        read_msg.msg_id, err
+                    );
+                    handle_error(
+                        metrics,
+                        control_plane_events_queue,
+                        data_plane_events_queue,
+                        queue,
+                        db_pool,
+                        &read_msg,
+                        err,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                use_custom_backup_storage(storage, &namespace, &mut coredb_spec);
+            }
 
-                // If cloud provider is Azure, we need to pass the storage account name to generate_spec
-                // so that the storage account URL can be generated for Azure restore scenarios
-                let azure_storage_account = match cloud_provider {
-                    CloudProvider::Azure => Some(azure_storage_account.as_str()),
-                    _ => None,
-                };
+            info!("{}: Generating spec", read_msg.msg_id);
+            let stack_type = match coredb_spec.stack.as_ref() {
+                Some(stack) => stack.name.clone(),
+                None => String::from("NA"),
+            };
+
+            include_storage_configuration(
+                storage_archive_bucket.clone(),
+                &read_msg,
+                &mut coredb_spec,
+            );
+
+            // If cloud provider is Azure, we need to pass the storage account name to generate_spec
+            // so that the storage account URL can be generated for Azure restore scenarios. The
+            // backups are restored from the storage account of the source instance.
+            let azure_storage_account = match cloud_provider {
+                CloudProvider::Azure => Some(
+                    restore_azure_target
+                        .as_ref()
+                        .unwrap_or(&azure_target)
+                        .storage_account
+                        .as_str(),
+                ),
+                _ => None,
+            };
 
-                let spec = generate_spec(
+            let spec = in_span(
+                "generate_spec",
+                generate_spec(
                     org_id,
                     &stack_type,
                     instance_id,
@@ -428,265 +888,737 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
                     azure_storage_account,
                     &coredb_spec,
                     &cloud_provider,
+                ),
+            )
+            .await;
+            let spec = match spec {
+                Ok(spec) => spec,
+                Err(err) => {
+                    error!("{}: Failed to generate spec: {}", read_msg.msg_id, err);
+                    handle_error(
+                        metrics,
+                        control_plane_events_queue,
+                        data_plane_events_queue,
+                        queue,
+                        db_pool,
+                        &read_msg,
+                        err,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            // An invalid spec would be rejected again on every retry, report it instead
+            let invalid_fields = validate_coredb(&spec);
+            if !invalid_fields.is_empty() {
+                error!(
+                    "{}: Generated CoreDB is invalid: {:?}",
+                    read_msg.msg_id, invalid_fields
+                );
+                report_invalid_spec(
+                    metrics,
+                    control_plane_events_queue,
+                    data_plane_events_queue,
+                    queue,
+                    db_pool,
+                    &read_msg,
+                    invalid_fields,
                 )
                 .await?;
+                return Ok(());
+            }
+
+            // A new instance which does not fit on the nodes would stay pending, report it
+            // instead. Instances which exist already use their share of the nodes.
+            if check_cluster_capacity
+                && matches!(read_msg.message.event_type, Event::Create | Event::Restore)
+                && get_one(cache, &namespace).await.is_err()
+            {
+                if let Some(reason) = check_capacity(client.clone(), &coredb_spec).await? {
+                    warn!(
+                        "{}: Instance does not fit in the cluster: {}",
+                        read_msg.msg_id, reason
+                    );
+                    report_insufficient_capacity(
+                        metrics,
+                        control_plane_events_queue,
+                        data_plane_events_queue,
+                        queue,
+                        &read_msg,
+                        reason,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
 
+            // Retried Create and Update events do not patch the CoreDB again, only its
+            // status is reported
+            let duplicate = matches!(read_msg.message.event_type, Event::Create | Event::Update)
+                && is_last_applied(db_pool, &namespace, &spec).await?
+                && get_one(cache, &namespace).await.is_ok();
+            if duplicate {
+                info!(
+                    "{}: Spec is identical to the last applied one, skipping apply",
+                    read_msg.msg_id
+                );
+            } else {
                 info!("{}: Creating or updating spec", read_msg.msg_id);
                 // create or update CoreDB
-                create_or_update(client.clone(), &namespace, spec).await?;
-
-                // get connection string values from secret
-
-                info!("{}: Getting connection info", read_msg.msg_id);
-                let conn_info = match get_pg_conn(
-                    client.clone(),
-                    &namespace,
-                    &data_plane_basedomain,
-                    &coredb_spec,
+                if let Err(err) = in_span(
+                    "apply",
+                    create_or_update(client.clone(), &namespace, spec.clone()),
                 )
                 .await
                 {
-                    Ok(conn_info) => conn_info,
-                    Err(err) => {
-                        match err {
-                            ConductorError::PostgresConnectionInfoNotFound => {
-                                info!(
-                                    "{}: Secret not ready, requeuing with short duration.",
-                                    read_msg.msg_id
-                                );
-                                // Requeue the message for a short duration
-                                let _ = queue
-                                    .set_vt::<CRUDevent>(
-                                        &control_plane_events_queue,
-                                        read_msg.msg_id,
-                                        REQUEUE_VT_SEC_SHORT,
-                                    )
-                                    .await?;
-                                metrics.conductor_requeues.add(
-                                    &opentelemetry::Context::current(),
-                                    1,
-                                    &[KeyValue::new("queue_duration", "short")],
-                                );
-                                continue;
-                            }
-                            _ => {
-                                error!(
-                                    "{}: Error getting Postgres connection information from secret: {}",
-                                    read_msg.msg_id, err
-                                );
-                                let _ = queue
-                                    .set_vt::<CRUDevent>(
-                                        &control_plane_events_queue,
-                                        read_msg.msg_id,
-                                        REQUEUE_VT_SEC_LONG,
-                                    )
-                                    .await?;
-                                metrics.conductor_errors.add(
-                                    &opentelemetry::Context::current(),
-                                    1,
-                                    &[],
-                                );
-                                continue;
-                            }
+                    error!(
+                        "{}: Failed to create or update CoreDB: {}",
+                        read_msg.msg_id, err
+                    );
+                    handle_error(
+                        metrics,
+                        control_plane_events_queue,
+                        data_plane_events_queue,
+                        queue,
+                        db_pool,
+                        &read_msg,
+                        err,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                record_applied(db_pool, &namespace, &spec).await?;
+            }
+            if let (Some(_), Event::Create | Event::Restore) = (
+                &read_msg.message.backup_location,
+                &read_msg.message.event_type,
+            ) {
+                record_backup_location(db_pool, &namespace, &location).await?;
+            }
+
+            // get connection string values from secret
+
+            info!("{}: Getting connection info", read_msg.msg_id);
+            let conn_info = match in_span(
+                "secrets",
+                get_pg_conn(cache, &namespace, &data_plane_basedomain, &coredb_spec),
+            )
+            .await
+            {
+                Ok(conn_info) => conn_info,
+                Err(err) => {
+                    match err {
+                        ConductorError::PostgresConnectionInfoNotFound => {
+                            info!(
+                                "{}: Secret not ready, requeuing with short duration.",
+                                read_msg.msg_id
+                            );
+                            // Requeue the message for a short duration
+                            let _ = queue
+                                .set_vt::<CRUDevent>(
+                                    control_plane_events_queue,
+                                    read_msg.msg_id,
+                                    REQUEUE_VT_SEC_SHORT,
+                                )
+                                .await?;
+                            metrics.conductor_requeues.add(
+                                &opentelemetry::Context::current(),
+                                1,
+                                &[KeyValue::new("queue_duration", "short")],
+                            );
+                            return Ok(());
+                        }
+                        _ => {
+                            error!(
+                                "{}: Error getting Postgres connection information from secret: {}",
+                                read_msg.msg_id, err
+                            );
+                            let _ = queue
+                                .set_vt::<CRUDevent>(
+                                    control_plane_events_queue,
+                                    read_msg.msg_id,
+                                    REQUEUE_VT_SEC_LONG,
+                                )
+                                .await?;
+                            metrics.conductor_errors.add(
+                                &opentelemetry::Context::current(),
+                                1,
+                                &[],
+                            );
+                            return Ok(());
                         }
                     }
-                };
+                }
+            };
 
-                info!("{}: Getting status", read_msg.msg_id);
+            info!("{}: Getting status", read_msg.msg_id);
 
-                let result = get_one(client.clone(), &namespace).await;
+            let result = get_one(cache, &namespace).await;
 
-                let current_spec = result?;
+            let current_spec = result?;
 
-                let spec_js = serde_json::to_string(&current_spec.spec).unwrap();
-                debug!("dbname: {}, current_spec: {:?}", &namespace, spec_js);
+            let spec_js = serde_json::to_string(&current_spec.spec).unwrap();
+            debug!("dbname: {}, current_spec: {:?}", &namespace, spec_js);
 
-                if is_cloud_formation && read_msg.message.event_type == Event::Stop {
-                    if let Some(status) = current_spec.clone().status {
-                        match status.running {
-                            false => {
-                                info!("{}: Deleting cloudformation stack", read_msg.msg_id);
-                                delete_cloudformation(aws_region.clone(), &namespace).await?;
-                            }
-                            true => {
+            if is_cloud_formation && read_msg.message.event_type == Event::Stop {
+                if let Some(status) = current_spec.clone().status {
+                    match status.running {
+                        false => {
+                            info!("{}: Deleting cloudformation stack", read_msg.msg_id);
+                            if !delete_cloudformation(aws_region.clone(), &namespace).await? {
                                 requeue_short(
-                                    &metrics,
-                                    &control_plane_events_queue,
-                                    &queue,
+                                    metrics,
+                                    control_plane_events_queue,
+                                    queue,
                                     &read_msg,
                                 )
                                 .await?;
-                                continue;
+                                return Ok(());
                             }
                         }
+                        true => {
+                            requeue_short(metrics, control_plane_events_queue, queue, &read_msg)
+                                .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+
+            let report_event = match read_msg.message.event_type {
+                Event::Create => Event::Created,
+                Event::Update => Event::Updated,
+                Event::Restore => Event::Restored,
+                Event::Start => Event::Started,
+                Event::Stop => Event::StopComplete,
+                _ => unreachable!(),
+            };
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: report_event,
+                spec: Some(current_spec.spec),
+                status: current_spec.status,
+                connection: Some(encrypt_connection(encryptor.as_ref(), conn_info)?),
+                error: None,
+                rollout: None,
+                delta: None,
+            }
+        }
+        Event::Delete => {
+            // During the grace period the instance is hibernated, and the event is requeued
+            // until the grace period ends or an Undelete event cancels the deletion
+            if delete_grace_period_hours > 0 {
+                let deletion = match pending_deletion(db_pool, &namespace).await? {
+                    Some(deletion) => deletion,
+                    None => {
+                        let was_stopped =
+                            match set_coredb_stopped(client.clone(), &namespace, true).await {
+                                Ok(was_stopped) => was_stopped,
+                                // Nothing to hibernate, the rest of the instance is still
+                                // only deleted once the grace period ends
+                                Err(ConductorError::KubeError(kube::Error::Api(resp)))
+                                    if resp.code == 404 =>
+                                {
+                                    true
+                                }
+                                Err(err) => {
+                                    error!(
+                                        "{}: Error hibernating instance before deletion: {}",
+                                        read_msg.msg_id, err
+                                    );
+                                    requeue_short(
+                                        metrics,
+                                        control_plane_events_queue,
+                                        queue,
+                                        &read_msg,
+                                    )
+                                    .await?;
+                                    return Ok(());
+                                }
+                            };
+                        delete_applied(db_pool, &namespace).await?;
+                        let deletion = schedule_deletion(
+                            db_pool,
+                            &namespace,
+                            read_msg.msg_id,
+                            was_stopped,
+                            chrono::Duration::hours(delete_grace_period_hours as i64),
+                        )
+                        .await?;
+                        info!(
+                            "{}: Instance {} hibernated, deleting it at {}",
+                            read_msg.msg_id, &namespace, deletion.delete_after
+                        );
+                        let scheduled_event = types::StateToControlPlane {
+                            version: types::EVENT_SCHEMA_VERSION,
+                            data_plane_id: read_msg.message.data_plane_id.clone(),
+                            org_id: read_msg.message.org_id.clone(),
+                            inst_id: read_msg.message.inst_id.clone(),
+                            event_type: Event::DeletionScheduled,
+                            spec: None,
+                            status: None,
+                            connection: None,
+                            error: None,
+                            rollout: None,
+                            delta: None,
+                        };
+                        queue
+                            .send(data_plane_events_queue, &scheduled_event)
+                            .await?;
+                        deletion
                     }
+                };
+                let remaining = deletion.remaining_secs(chrono::Utc::now());
+                if remaining > 0 {
+                    queue
+                        .set_vt::<CRUDevent>(control_plane_events_queue, read_msg.msg_id, remaining)
+                        .await?;
+                    return Ok(());
+                }
+                info!(
+                    "{}: Grace period of instance {} ended",
+                    read_msg.msg_id, &namespace
+                );
+            }
+
+            // The backups are kept, so the instance can be restored after it was deleted.
+            // Backups in the bucket of the customer are not in the backups bucket.
+            let backups_path = get_one(cache, &namespace)
+                .await
+                .ok()
+                .filter(|coredb| coredb.spec.backup.endpoint_url.is_none())
+                .and_then(|coredb| coredb.spec.backup.destinationPath)
+                .map(|path| path_in_bucket(&path, &backup_archive_bucket));
+
+            // delete CoreDB
+            info!("{}: Deleting instance {}", read_msg.msg_id, &namespace);
+            delete(client.clone(), &namespace, &namespace).await?;
+
+            // delete namespace
+            info!("{}: Deleting namespace {}", read_msg.msg_id, &namespace);
+            delete_namespace(client.clone(), &namespace).await?;
+
+            if is_cloud_formation {
+                info!("{}: Deleting cloudformation stack", read_msg.msg_id);
+                // Deleting the instance and its namespace again is a no-op
+                if !delete_cloudformation(aws_region.clone(), &namespace).await? {
+                    requeue_short(metrics, control_plane_events_queue, queue, &read_msg).await?;
+                    return Ok(());
+                }
+            }
+
+            if is_gcp {
+                info!(
+                    "{}: Deleting GCP storage workload identity binding",
+                    read_msg.msg_id
+                );
+                delete_gcp_storage_workload_identity_binding(
+                    &gcp_project_id,
+                    &gcp_project_number,
+                    &backup_archive_bucket,
+                    &storage_archive_bucket,
+                    &namespace,
+                    gcp_federation.clone(),
+                )
+                .await?;
+            }
+
+            if is_azure {
+                info!(
+                    "{}: Deleting Azure storage workload identity binding",
+                    read_msg.msg_id
+                );
+                let azure_target = recorded_storage_target(db_pool, &namespace)
+                    .await?
+                    .unwrap_or(default_azure_target);
+                // The target is kept, restores of the deleted instance read its backups from
+                // that storage account
+                delete_azure_storage_workload_identity_binding(
+                    &azure_subscription_id,
+                    &azure_target.resource_group_prefix,
+                    &namespace,
+                )
+                .await?;
+            }
+            delete_backup_location(db_pool, &namespace).await?;
+            delete_namespace_mapping(db_pool, &requested_namespace, instance_id).await?;
+
+            let insert_query = sqlx::query!(
+                "INSERT INTO deleted_instances (namespace) VALUES ($1) ON CONFLICT (namespace) DO NOTHING",
+                namespace
+            );
+
+            match insert_query.execute(db_pool).await {
+                Ok(_) => info!(
+                    "Namespace inserted into deleted_instances table or already exists: {}",
+                    &namespace
+                ),
+                Err(e) => error!(
+                    "Failed to insert namespace into deleted_instances table: {}",
+                    e
+                ),
+            }
+            if let Some(backups_path) = &backups_path {
+                record_backups_path(db_pool, &namespace, backups_path, &location).await?;
+            }
+            remove_pending_deletion(db_pool, &namespace).await?;
+            delete_applied(db_pool, &namespace).await?;
+
+            // report state
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: Event::Deleted,
+                spec: None,
+                status: None,
+                connection: None,
+                error: None,
+                rollout: None,
+                delta: None,
+            }
+        }
+        Event::Restart => {
+            // TODO: refactor to be more DRY
+            // Restart and Update events share a lot of the same code.
+            // move some operations after the Event match
+            info!("{}: handling instance restart", read_msg.msg_id);
+            let msg_enqueued_at = read_msg.enqueued_at;
+            match restart_coredb(client.clone(), &namespace, &namespace, msg_enqueued_at).await {
+                Ok(_) => {
+                    info!("{}: Instance requested to be restarted", read_msg.msg_id);
+                }
+                Err(_) => {
+                    error!("{}: Error restarting instance", read_msg.msg_id);
+                    requeue_short(metrics, control_plane_events_queue, queue, &read_msg).await?;
+                    return Ok(());
                 }
+            };
 
-                let report_event = match read_msg.message.event_type {
-                    Event::Create => Event::Created,
-                    Event::Update => Event::Updated,
-                    Event::Restore => Event::Restored,
-                    Event::Start => Event::Started,
-                    Event::Stop => Event::StopComplete,
-                    _ => unreachable!(),
-                };
-                types::StateToControlPlane {
-                    data_plane_id: read_msg.message.data_plane_id,
-                    org_id: read_msg.message.org_id,
-                    inst_id: read_msg.message.inst_id,
-                    event_type: report_event,
-                    spec: Some(current_spec.spec),
-                    status: current_spec.status,
-                    connection: Some(conn_info),
+            let result = get_coredb_error_without_status(cache, &namespace).await;
+
+            let current_resource = match result {
+                Ok(coredb) => {
+                    let as_json = serde_json::to_string(&coredb);
+                    debug!("dbname: {}, current: {:?}", &namespace, as_json);
+                    coredb
                 }
-            }
-            Event::Delete => {
-                // delete CoreDB
-                info!("{}: Deleting instance {}", read_msg.msg_id, &namespace);
-                delete(client.clone(), &namespace, &namespace).await?;
-
-                // delete namespace
-                info!("{}: Deleting namespace {}", read_msg.msg_id, &namespace);
-                delete_namespace(client.clone(), &namespace).await?;
-
-                if is_cloud_formation {
-                    info!("{}: Deleting cloudformation stack", read_msg.msg_id);
-                    delete_cloudformation(aws_region.clone(), &namespace).await?;
+                Err(_) => {
+                    requeue_short(metrics, control_plane_events_queue, queue, &read_msg).await?;
+                    return Ok(());
                 }
+            };
 
-                if is_gcp {
-                    info!(
-                        "{}: Deleting GCP storage workload identity binding",
-                        read_msg.msg_id
-                    );
-                    delete_gcp_storage_workload_identity_binding(
-                        &gcp_project_id,
-                        &gcp_project_number,
-                        &backup_archive_bucket,
-                        &storage_archive_bucket,
-                        &namespace,
-                    )
-                    .await?;
-                }
+            let conn_info = get_pg_conn(
+                cache,
+                &namespace,
+                &data_plane_basedomain,
+                &current_resource.spec,
+            )
+            .await;
 
-                if is_azure {
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: Event::Restarted,
+                spec: Some(current_resource.spec),
+                status: current_resource.status,
+                connection: conn_info
+                    .ok()
+                    .map(|conn_info| encrypt_connection(encryptor.as_ref(), conn_info))
+                    .transpose()?,
+                error: None,
+                rollout: None,
+                delta: None,
+            }
+        }
+        Event::Undelete => {
+            info!("{}: handling instance undelete", read_msg.msg_id);
+            match pending_deletion(db_pool, &namespace).await? {
+                Some(deletion) => {
+                    // Wake the instance up again, unless it was stopped before the deletion
+                    if let Err(err) =
+                        set_coredb_stopped(client.clone(), &namespace, deletion.was_stopped).await
+                    {
+                        error!("{}: Error undeleting instance: {}", read_msg.msg_id, err);
+                        requeue_short(metrics, control_plane_events_queue, queue, &read_msg)
+                            .await?;
+                        return Ok(());
+                    }
+                    delete_applied(db_pool, &namespace).await?;
+                    queue
+                        .archive(control_plane_events_queue, deletion.msg_id)
+                        .await?;
+                    remove_pending_deletion(db_pool, &namespace).await?;
                     info!(
-                        "{}: Deleting Azure storage workload identity binding",
-                        read_msg.msg_id
+                        "{}: Cancelled deletion of instance {}",
+                        read_msg.msg_id, &namespace
                     );
-                    delete_azure_storage_workload_identity_binding(
-                        &azure_subscription_id,
-                        &azure_resource_group_prefix,
-                        &namespace,
-                    )
-                    .await?;
                 }
+                None => warn!(
+                    "{}: No deletion of instance {} is scheduled",
+                    read_msg.msg_id, &namespace
+                ),
+            }
 
-                let insert_query = sqlx::query!(
-                    "INSERT INTO deleted_instances (namespace) VALUES ($1) ON CONFLICT (namespace) DO NOTHING",
-                    namespace
-                );
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: Event::Undeleted,
+                spec: None,
+                status: None,
+                connection: None,
+                error: None,
+                rollout: None,
+                delta: None,
+            }
+        }
+        Event::BatchUpdate => {
+            info!("{}: handling batch update", read_msg.msg_id);
+            return process_rollout(ctx, control_plane_events_queue, read_msg).await;
+        }
+        Event::UpdateTags => {
+            // Metadata-only changes skip spec generation, secrets and status waits
+            info!("{}: handling instance tags update", read_msg.msg_id);
+            let tags = read_msg.message.tags.clone().unwrap_or_default();
+            if let Err(err) = update_tags(client.clone(), &namespace, &tags).await {
+                error!("{}: Error updating instance tags: {}", read_msg.msg_id, err);
+                requeue_short(metrics, control_plane_events_queue, queue, &read_msg).await?;
+                return Ok(());
+            }
 
-                match insert_query.execute(&db_pool).await {
-                    Ok(_) => info!(
-                        "Namespace inserted into deleted_instances table or already exists: {}",
-                        &namespace
-                    ),
-                    Err(e) => error!(
-                        "Failed to insert namespace into deleted_instances table: {}",
-                        e
-                    ),
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: Event::TagsUpdated,
+                spec: None,
+                status: None,
+                connection: None,
+                error: None,
+                rollout: None,
+                delta: None,
+            }
+        }
+        Event::Resize => {
+            // Resizes only patch the storage, cpu and memory of the CoreDB. Cloud permissions
+            // and backups are not touched, and the status is reported without waiting.
+            info!("{}: handling instance resize", read_msg.msg_id);
+            let Some(spec) = read_msg.message.spec.as_ref() else {
+                error!(
+                    "{}: spec is required on resize events, archiving message",
+                    read_msg.msg_id
+                );
+                queue
+                    .archive(control_plane_events_queue, read_msg.msg_id)
+                    .await?;
+                metrics
+                    .conductor_errors
+                    .add(&opentelemetry::Context::current(), 1, &[]);
+                return Ok(());
+            };
+            let coredb = match resize_coredb(client.clone(), &namespace, spec).await {
+                Ok(coredb) => coredb,
+                Err(err) => {
+                    error!("{}: Error resizing instance: {}", read_msg.msg_id, err);
+                    requeue_short(metrics, control_plane_events_queue, queue, &read_msg).await?;
+                    return Ok(());
                 }
+            };
+            delete_applied(db_pool, &namespace).await?;
 
-                // report state
-                types::StateToControlPlane {
-                    data_plane_id: read_msg.message.data_plane_id,
-                    org_id: read_msg.message.org_id,
-                    inst_id: read_msg.message.inst_id,
-                    event_type: Event::Deleted,
-                    spec: None,
-                    status: None,
-                    connection: None,
-                }
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: Event::Resized,
+                spec: Some(coredb.spec),
+                status: coredb.status,
+                connection: None,
+                error: None,
+                rollout: None,
+                delta: None,
             }
-            Event::Restart => {
-                // TODO: refactor to be more DRY
-                // Restart and Update events share a lot of the same code.
-                // move some operations after the Event match
-                info!("{}: handling instance restart", read_msg.msg_id);
-                let msg_enqueued_at = read_msg.enqueued_at;
-                match restart_coredb(client.clone(), &namespace, &namespace, msg_enqueued_at).await
-                {
-                    Ok(_) => {
-                        info!("{}: Instance requested to be restarted", read_msg.msg_id);
+        }
+        Event::Upgrade => {
+            // The operator takes a backup and upgrades the Cluster once the image of the CoreDB
+            // has the target major version. The message is read again until the upgrade is over.
+            info!("{}: handling instance upgrade", read_msg.msg_id);
+            let Some(spec) = read_msg.message.spec.as_ref() else {
+                error!(
+                    "{}: spec is required on upgrade events, archiving message",
+                    read_msg.msg_id
+                );
+                queue
+                    .archive(control_plane_events_queue, read_msg.msg_id)
+                    .await?;
+                metrics
+                    .conductor_errors
+                    .add(&opentelemetry::Context::current(), 1, &[]);
+                return Ok(());
+            };
+            let target_major = match target_major_version(&spec.image) {
+                Ok(target_major) => target_major,
+                Err(err) => {
+                    error!("{}: Error upgrading instance: {}", read_msg.msg_id, err);
+                    return handle_error(
+                        metrics,
+                        control_plane_events_queue,
+                        data_plane_events_queue,
+                        queue,
+                        db_pool,
+                        &read_msg,
+                        err,
+                    )
+                    .await;
+                }
+            };
+            let (progress, coredb) =
+                match get_upgrade_progress(client.clone(), &namespace, target_major).await {
+                    Ok(progress) => progress,
+                    Err(err @ ConductorError::UpgradeError(_)) => {
+                        error!("{}: Error upgrading instance: {}", read_msg.msg_id, err);
+                        return handle_error(
+                            metrics,
+                            control_plane_events_queue,
+                            data_plane_events_queue,
+                            queue,
+                            db_pool,
+                            &read_msg,
+                            err,
+                        )
+                        .await;
                     }
-                    Err(_) => {
-                        error!("{}: Error restarting instance", read_msg.msg_id);
-                        requeue_short(&metrics, &control_plane_events_queue, &queue, &read_msg)
+                    Err(err) => {
+                        error!(
+                            "{}: Error getting upgrade progress: {}",
+                            read_msg.msg_id, err
+                        );
+                        requeue_short(metrics, control_plane_events_queue, queue, &read_msg)
                             .await?;
-                        continue;
+                        return Ok(());
                     }
                 };
-
-                let result = get_coredb_error_without_status(client.clone(), &namespace).await;
-
-                let current_resource = match result {
-                    Ok(coredb) => {
-                        let as_json = serde_json::to_string(&coredb);
-                        debug!("dbname: {}, current: {:?}", &namespace, as_json);
-                        coredb
-                    }
-                    Err(_) => {
-                        requeue_short(&metrics, &control_plane_events_queue, &queue, &read_msg)
+            let timed_out = chrono::Utc::now() - read_msg.enqueued_at > *upgrade_timeout;
+            let failure = match progress {
+                UpgradeProgress::Upgraded => None,
+                UpgradeProgress::Failed(reason) => Some(types::ErrorDetails {
+                    code: types::ErrorCode::Internal,
+                    retryable: false,
+                    message: reason,
+                    fields: vec![],
+                }),
+                _ if timed_out => Some(types::ErrorDetails {
+                    code: types::ErrorCode::Timeout,
+                    retryable: true,
+                    message: format!(
+                        "upgrade to Postgres {} did not complete in {} seconds",
+                        target_major,
+                        upgrade_timeout.num_seconds()
+                    ),
+                    fields: vec![],
+                }),
+                UpgradeProgress::Pending => {
+                    if let Err(err) = start_upgrade(client.clone(), &namespace, &spec.image).await {
+                        error!("{}: Error upgrading instance: {}", read_msg.msg_id, err);
+                        requeue_short(metrics, control_plane_events_queue, queue, &read_msg)
                             .await?;
-                        continue;
+                        return Ok(());
                     }
-                };
-
-                let conn_info = get_pg_conn(
-                    client.clone(),
-                    &namespace,
-                    &data_plane_basedomain,
-                    &current_resource.spec,
-                )
-                .await;
-
-                types::StateToControlPlane {
-                    data_plane_id: read_msg.message.data_plane_id,
-                    org_id: read_msg.message.org_id,
-                    inst_id: read_msg.message.inst_id,
-                    event_type: Event::Restarted,
-                    spec: Some(current_resource.spec),
-                    status: current_resource.status,
-                    connection: conn_info.ok(),
+                    delete_applied(db_pool, &namespace).await?;
+                    let _ = queue
+                        .set_vt::<CRUDevent>(
+                            control_plane_events_queue,
+                            read_msg.msg_id,
+                            UPGRADE_POLL_VT_SEC,
+                        )
+                        .await?;
+                    return Ok(());
                 }
-            }
-            _ => {
-                warn!("Unhandled event_type: {:?}", read_msg.message.event_type);
+                UpgradeProgress::InProgress => {
+                    debug!(
+                        "{}: waiting for {} to be upgraded to Postgres {}",
+                        read_msg.msg_id, namespace, target_major
+                    );
+                    let _ = queue
+                        .set_vt::<CRUDevent>(
+                            control_plane_events_queue,
+                            read_msg.msg_id,
+                            UPGRADE_POLL_VT_SEC,
+                        )
+                        .await?;
+                    return Ok(());
+                }
+            };
+            if let Some(failure) = &failure {
+                error!(
+                    "{}: Upgrade of {} failed: {}",
+                    read_msg.msg_id, namespace, failure.message
+                );
+                note_failure(OUTCOME_FAILED, &failure.message);
                 metrics
                     .conductor_errors
                     .add(&opentelemetry::Context::current(), 1, &[]);
-                continue;
             }
-        };
-
-        let msg_id = queue.send(&data_plane_events_queue, &event_msg).await?;
-        info!(
-            "{}: responded to control plane with message {}",
-            read_msg.msg_id, msg_id
-        );
 
-        // archive message from queue
-        let archived = queue
-            .archive(&control_plane_events_queue, read_msg.msg_id)
-            .await?;
+            types::StateToControlPlane {
+                version: types::EVENT_SCHEMA_VERSION,
+                data_plane_id: read_msg.message.data_plane_id,
+                org_id: read_msg.message.org_id,
+                inst_id: read_msg.message.inst_id,
+                event_type: match failure {
+                    Some(_) => Event::UpgradeFailed,
+                    None => Event::Upgraded,
+                },
+                spec: Some(coredb.spec),
+                status: coredb.status,
+                connection: None,
+                error: failure,
+                rollout: None,
+                delta: None,
+            }
+        }
+        _ => {
+            warn!("Unhandled event_type: {:?}", read_msg.message.event_type);
+            metrics
+                .conductor_errors
+                .add(&opentelemetry::Context::current(), 1, &[]);
+            return Ok(());
+        }
+    };
 
-        metrics
-            .conductor_completed
-            .add(&opentelemetry::Context::current(), 1, &[]);
+    let msg_id = queue.send(data_plane_events_queue, &event_msg).await?;
+    info!(
+        "{}: responded to control plane with message {}",
+        read_msg.msg_id, msg_id
+    );
 
-        info!("{}: archived: {:?}", read_msg.msg_id, archived);
+    if let Some((rollout_id, inst_id)) = &rollout_instance {
+        record_result(db_pool, rollout_id, inst_id, None).await?;
     }
+
+    // archive message from queue
+    let archived = queue
+        .archive(control_plane_events_queue, read_msg.msg_id)
+        .await?;
+
+    metrics
+        .conductor_completed
+        .add(&opentelemetry::Context::current(), 1, &[]);
+
+    info!("{}: archived: {:?}", read_msg.msg_id, archived);
+    Ok(())
 }
 
 fn include_storage_configuration(
@@ -734,6 +1666,300 @@ async fn requeue_short(
     Ok(())
 }
 
+// Record that the Update event of an instance of a rollout failed, so the rollout halts
+async fn rollout_failed(db_pool: &PgPool, read_msg: &Message<CRUDevent>, reason: &str) {
+    let Some(rollout_id) = &read_msg.message.rollout_id else {
+        return;
+    };
+    if let Err(err) =
+        record_result(db_pool, rollout_id, &read_msg.message.inst_id, Some(reason)).await
+    {
+        error!(
+            "{}: Failed to record failure of rollout {}: {}",
+            read_msg.msg_id, rollout_id, err
+        );
+    }
+}
+
+// Send Update events for the instances of a rollout as its policy allows, and report its
+// progress to control plane, until every instance was updated or one of them failed. The
+// events go to the queue the rollout was read from, the shard of its organization.
+async fn process_rollout(
+    ctx: &EventContext,
+    control_plane_events_queue: &str,
+    read_msg: Message<CRUDevent>,
+) -> Result<(), ConductorError> {
+    let EventContext {
+        metrics,
+        queue,
+        db_pool,
+        data_plane_events_queue,
+        ..
+    } = ctx;
+
+    let Some(rollout) = read_msg.message.rollout.clone() else {
+        error!(
+            "{}: rollout is required on batch update events, archiving message",
+            read_msg.msg_id
+        );
+        queue
+            .archive(control_plane_events_queue, read_msg.msg_id)
+            .await?;
+        metrics
+            .conductor_errors
+            .add(&opentelemetry::Context::current(), 1, &[]);
+        return Ok(());
+    };
+
+    let mut states = rollout_states(db_pool, &rollout).await?;
+    let step = next_step(rollout.policy, &states);
+    match &step {
+        Step::Wait => {
+            debug!(
+                "{}: rollout {} is waiting for updates to complete",
+                read_msg.msg_id, rollout.rollout_id
+            );
+            let _ = queue
+                .set_vt::<CRUDevent>(
+                    control_plane_events_queue,
+                    read_msg.msg_id,
+                    ROLLOUT_POLL_VT_SEC,
+                )
+                .await?;
+            return Ok(());
+        }
+        Step::Dispatch(positions) => {
+            for &position in positions {
+                let instance = &rollout.instances[position];
+                let update_event = CRUDevent {
+                    version: types::EVENT_SCHEMA_VERSION,
+                    data_plane_id: read_msg.message.data_plane_id.clone(),
+                    org_id: read_msg.message.org_id.clone(),
+                    inst_id: instance.inst_id.clone(),
+                    event_type: Event::Update,
+                    namespace: instance.namespace.clone(),
+                    backups_read_path: instance.backups_read_path.clone(),
+                    backups_write_path: instance.backups_write_path.clone(),
+                    spec: Some(instance.spec.clone()),
+                    tags: None,
+                    rollout: None,
+                    rollout_id: Some(rollout.rollout_id.clone()),
+                    backup_storage: instance.backup_storage.clone(),
+                    backup_location: None,
+                    trace_context: Some(current_trace_context()),
+                };
+                let msg_id = queue
+                    .send(control_plane_events_queue, &update_event)
+                    .await?;
+                mark_dispatched(db_pool, &rollout.rollout_id, &instance.inst_id, msg_id).await?;
+                states[position] = InstanceState::Dispatched;
+                info!(
+                    "{}: rollout {} sent update of instance {} as message {}",
+                    read_msg.msg_id, rollout.rollout_id, instance.inst_id, msg_id
+                );
+            }
+        }
+        Step::Done(status) => {
+            info!(
+                "{}: rollout {} is done: {:?}",
+                read_msg.msg_id, rollout.rollout_id, status
+            );
+        }
+    }
+
+    let progress_event = types::StateToControlPlane {
+        version: types::EVENT_SCHEMA_VERSION,
+        data_plane_id: read_msg.message.data_plane_id.clone(),
+        org_id: read_msg.message.org_id.clone(),
+        inst_id: read_msg.message.inst_id.clone(),
+        event_type: Event::RolloutProgress,
+        spec: None,
+        status: None,
+        connection: None,
+        error: None,
+        rollout: Some(progress(&rollout, &states, &step)),
+        delta: None,
+    };
+    let msg_id = queue.send(data_plane_events_queue, &progress_event).await?;
+    info!(
+        "{}: reported progress of rollout {} with message {}",
+        read_msg.msg_id, rollout.rollout_id, msg_id
+    );
+
+    // The batch update is sent again after each step, so MAX_READ_CT limits how long a
+    // single step can take rather than the whole rollout
+    if let Step::Dispatch(_) = step {
+        let next_msg_id = queue
+            .send(control_plane_events_queue, &read_msg.message)
+            .await?;
+        let _ = queue
+            .set_vt::<CRUDevent>(control_plane_events_queue, next_msg_id, ROLLOUT_POLL_VT_SEC)
+            .await?;
+    } else {
+        metrics
+            .conductor_completed
+            .add(&opentelemetry::Context::current(), 1, &[]);
+    }
+    queue
+        .archive(control_plane_events_queue, read_msg.msg_id)
+        .await?;
+    Ok(())
+}
+
+// Non-retryable errors are reported to control plane and the message is archived,
+// anything else is requeued with a long duration
+async fn handle_error(
+    metrics: &CustomMetrics,
+    control_plane_events_queue: &str,
+    data_plane_events_queue: &str,
+    queue: &PGMQueueExt,
+    db_pool: &PgPool,
+    read_msg: &Message<CRUDevent>,
+    err: ConductorError,
+) -> Result<(), ConductorError> {
+    let details = err.error_details();
+    if details.retryable {
+        note_failure(OUTCOME_REQUEUED, &err.to_string());
+        let _ = queue
+            .set_vt::<CRUDevent>(
+                control_plane_events_queue,
+                read_msg.msg_id,
+                REQUEUE_VT_SEC_LONG,
+            )
+            .await?;
+        metrics.conductor_requeues.add(
+            &opentelemetry::Context::current(),
+            1,
+            &[KeyValue::new("queue_duration", "long")],
+        );
+        return Ok(());
+    }
+
+    note_failure(OUTCOME_FAILED, &err.to_string());
+    queue
+        .archive(control_plane_events_queue, read_msg.msg_id)
+        .await?;
+    metrics
+        .conductor_errors
+        .add(&opentelemetry::Context::current(), 1, &[]);
+    rollout_failed(db_pool, read_msg, &details.message).await;
+
+    let error_event = types::StateToControlPlane {
+        version: types::EVENT_SCHEMA_VERSION,
+        data_plane_id: read_msg.message.data_plane_id.clone(),
+        org_id: read_msg.message.org_id.clone(),
+        inst_id: read_msg.message.inst_id.clone(),
+        event_type: Event::Error,
+        spec: None,
+        status: None,
+        connection: None,
+        error: Some(details),
+        rollout: None,
+        delta: None,
+    };
+    let msg_id = queue.send(data_plane_events_queue, &error_event).await?;
+    error!(
+        "{}: sent non-retryable error event to control-plane: {}",
+        read_msg.msg_id, msg_id
+    );
+    Ok(())
+}
+
+// Archive an event whose spec is invalid, and report the invalid fields to control plane
+async fn report_invalid_spec(
+    metrics: &CustomMetrics,
+    control_plane_events_queue: &str,
+    data_plane_events_queue: &str,
+    queue: &PGMQueueExt,
+    db_pool: &PgPool,
+    read_msg: &Message<CRUDevent>,
+    fields: Vec<types::FieldError>,
+) -> Result<(), ConductorError> {
+    queue
+        .archive(control_plane_events_queue, read_msg.msg_id)
+        .await?;
+    metrics
+        .conductor_errors
+        .add(&opentelemetry::Context::current(), 1, &[]);
+    let message = format!(
+        "invalid spec: {}",
+        fields
+            .iter()
+            .map(|field| format!("{}: {}", field.field, field.message))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    rollout_failed(db_pool, read_msg, &message).await;
+
+    let invalid_event = types::StateToControlPlane {
+        version: types::EVENT_SCHEMA_VERSION,
+        data_plane_id: read_msg.message.data_plane_id.clone(),
+        org_id: read_msg.message.org_id.clone(),
+        inst_id: read_msg.message.inst_id.clone(),
+        event_type: Event::Invalid,
+        spec: None,
+        status: None,
+        connection: None,
+        error: Some(types::ErrorDetails {
+            code: types::ErrorCode::InvalidSpec,
+            retryable: false,
+            message,
+            fields,
+        }),
+        rollout: None,
+        delta: None,
+    };
+    let msg_id = queue.send(data_plane_events_queue, &invalid_event).await?;
+    error!(
+        "{}: sent invalid spec event to control-plane: {}",
+        read_msg.msg_id, msg_id
+    );
+    Ok(())
+}
+
+// Archive an event whose instance does not fit in the cluster, and report it to control plane.
+// Control plane can send the event again once capacity was added.
+async fn report_insufficient_capacity(
+    metrics: &CustomMetrics,
+    control_plane_events_queue: &str,
+    data_plane_events_queue: &str,
+    queue: &PGMQueueExt,
+    read_msg: &Message<CRUDevent>,
+    reason: String,
+) -> Result<(), ConductorError> {
+    queue
+        .archive(control_plane_events_queue, read_msg.msg_id)
+        .await?;
+    metrics
+        .conductor_errors
+        .add(&opentelemetry::Context::current(), 1, &[]);
+
+    let capacity_event = types::StateToControlPlane {
+        version: types::EVENT_SCHEMA_VERSION,
+        data_plane_id: read_msg.message.data_plane_id.clone(),
+        org_id: read_msg.message.org_id.clone(),
+        inst_id: read_msg.message.inst_id.clone(),
+        event_type: Event::InsufficientCapacity,
+        spec: None,
+        status: None,
+        connection: None,
+        error: Some(types::ErrorDetails {
+            code: types::ErrorCode::InsufficientCapacity,
+            retryable: true,
+            message: reason,
+            fields: vec![],
+        }),
+        rollout: None,
+        delta: None,
+    };
+    let msg_id = queue.send(data_plane_events_queue, &capacity_event).await?;
+    error!(
+        "{}: sent insufficient capacity event to control-plane: {}",
+        read_msg.msg_id, msg_id
+    );
+    Ok(())
+}
+
 // https://github.com/rust-lang/rust-clippy/issues/6446
 // False positive because lock is dropped before await
 #[allow(clippy::await_holding_lock)]
@@ -751,6 +1977,8 @@ async fn main() -> std::io::Result<()> {
     .build();
 
     let exporter = opentelemetry_prometheus::exporter(controller).init();
+    #[cfg(feature = "telemetry")]
+    conductor::telemetry::init_tracer();
     let meter = global::meter("actix_web");
     let custom_metrics = CustomMetrics::new(&meter);
 
@@ -765,14 +1993,64 @@ async fn main() -> std::io::Result<()> {
     let status_reporter_enabled = from_env_default("WATCHER_ENABLED", "true");
     let metrics_reported_enabled = from_env_default("METRICS_REPORTER_ENABLED", "false");
 
-    if conductor_enabled != "false" {
+    // CoreDBs and Secrets are read from a watch-based cache shared by the conductor and the
+    // status reporter, instead of getting them from the API server for every message
+    let cache = if conductor_enabled != "false" || status_reporter_enabled != "false" {
+        let kube_client_config = KubeClientConfig {
+            qps: from_env_default("KUBE_CLIENT_QPS", "20")
+                .parse()
+                .expect("error parsing KUBE_CLIENT_QPS"),
+            burst: from_env_default("KUBE_CLIENT_BURST", "40")
+                .parse()
+                .expect("error parsing KUBE_CLIENT_BURST"),
+            max_retries: from_env_default("KUBE_CLIENT_MAX_RETRIES", "3")
+                .parse()
+                .expect("error parsing KUBE_CLIENT_MAX_RETRIES"),
+            ..KubeClientConfig::default()
+        };
+        let client = kube_client(kube_client_config, Some(KubeClientMetrics::new(&meter)))
+            .await
+            .expect("Failed to create Kubernetes client");
+        let connection_ttl: u64 = from_env_default("CONNECTION_CACHE_TTL_SECONDS", "30")
+            .parse()
+            .expect("error parsing CONNECTION_CACHE_TTL_SECONDS");
+        Some(
+            ResourceCache::start(client)
+                .with_connection_ttl(time::Duration::from_secs(connection_ttl)),
+        )
+    } else {
+        None
+    };
+
+    // The liveness probe fails when the conductor loop made no progress for this long
+    let heartbeat = (conductor_enabled != "false").then(|| {
+        let max_lag: u64 = from_env_default("CONDUCTOR_LOOP_MAX_LAG_SECONDS", "600")
+            .parse()
+            .expect("error parsing CONDUCTOR_LOOP_MAX_LAG_SECONDS");
+        let heartbeat = LoopHeartbeat::new(time::Duration::from_secs(max_lag));
+        if let Err(err) = register_loop_lag(&meter, heartbeat.clone()) {
+            error!("Failed to register conductor_loop_lag_seconds: {}", err);
+        }
+        heartbeat
+    });
+
+    if let Some(heartbeat) = heartbeat.clone() {
         info!("Starting conductor");
         background_threads_locked.push(tokio::spawn({
             let custom_metrics_copy = custom_metrics.clone();
+            let cache = cache
+                .clone()
+                .expect("Resource cache is started for the conductor");
 
             async move {
                 loop {
-                    match run(custom_metrics_copy.clone()).await {
+                    match run(
+                        custom_metrics_copy.clone(),
+                        cache.clone(),
+                        heartbeat.clone(),
+                    )
+                    .await
+                    {
                         Ok(_) => {}
                         Err(ConductorError::PgmqError(pgmq::errors::PgmqError::DatabaseError(
                             Error::PoolTimedOut,
@@ -805,9 +2083,10 @@ async fn main() -> std::io::Result<()> {
         info!("Starting status reporter");
         background_threads_locked.push(tokio::spawn({
             let custom_metrics_copy = custom_metrics.clone();
+            let cache = cache.expect("Resource cache is started for the status reporter");
             async move {
                 loop {
-                    match run_status_reporter(custom_metrics_copy.clone()).await {
+                    match run_status_reporter(custom_metrics_copy.clone(), cache.clone()).await {
                         Ok(_) => {}
                         Err(err) => {
                             custom_metrics_copy.clone().conductor_errors.add(
@@ -845,6 +2124,33 @@ async fn main() -> std::io::Result<()> {
 
     std::mem::drop(background_threads_locked);
 
+    // Dead letters and queued messages are managed through the queue database, the pool
+    // only connects once an admin route is used. The routes require ADMIN_API_TOKEN as a
+    // bearer token, and are not served when it is not set.
+    let admin = match env::var("ADMIN_API_TOKEN") {
+        Ok(token) if !token.is_empty() && conductor_enabled != "false" => {
+            let pg_conn_url = env::var("POSTGRES_QUEUE_CONNECTION")
+                .expect("POSTGRES_QUEUE_CONNECTION must be set");
+            let mut queues: Vec<String> = ["CONTROL_PLANE_EVENTS_QUEUE", "DATA_PLANE_EVENTS_QUEUE"]
+                .iter()
+                .map(|var| env::var(var).unwrap_or_else(|_| panic!("{var} must be set")))
+                .collect();
+            let shards: usize = from_env_default("CONTROL_PLANE_EVENTS_QUEUE_SHARDS", "0")
+                .parse()
+                .expect("error parsing CONTROL_PLANE_EVENTS_QUEUE_SHARDS");
+            queues.extend(shard_queue_names(&queues[0], shards).into_iter().skip(1));
+            let pool = PgPoolOptions::new()
+                .max_connections(1)
+                .connect_lazy(&pg_conn_url)
+                .expect("Failed to create admin PG pool");
+            Some((AdminConfig { token, queues }, pool))
+        }
+        _ => {
+            info!("ADMIN_API_TOKEN is not set, admin routes are disabled");
+            None
+        }
+    };
+
     let server_port = env::var("PORT")
         .unwrap_or_else(|_| String::from("8080"))
         .parse::<u16>()
@@ -854,12 +2160,45 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(web::Data::new(custom_metrics.clone()))
             .app_data(web::Data::new(background_threads.clone()))
+            .configure(|cfg| {
+                if let Some(heartbeat) = heartbeat.clone() {
+                    cfg.app_data(web::Data::new(heartbeat));
+                }
+            })
             .wrap(RequestTracing::new())
             .route(
                 "/metrics",
                 web::get().to(PrometheusMetricsHandler::new(exporter.clone())),
             )
             .service(web::scope("/health").service(background_threads_running))
+            .configure(|cfg| {
+                if let Some((admin_config, pool)) = admin.clone() {
+                    cfg.app_data(web::Data::new(admin_config))
+                        .app_data(web::Data::new(pool))
+                        .service(
+                            web::scope("/dead-letters")
+                                .service(get_dead_letters)
+                                .service(replay),
+                        )
+                        .service(
+                            web::scope("/quarantine")
+                                .service(get_quarantined)
+                                .service(replay_quarantined_message),
+                        )
+                        .service(
+                            web::scope("/audit-log")
+                                .service(get_processed_events)
+                                .service(get_instance_events),
+                        )
+                        .service(
+                            web::scope("/admin/queue")
+                                .service(get_queues)
+                                .service(get_messages)
+                                .service(archive)
+                                .service(requeue),
+                        );
+                }
+            })
     })
     .workers(1)
     .bind(("0.0.0.0", server_port))?
@@ -873,6 +2212,7 @@ async fn init_cloud_perms(
     backup_archive_bucket: String,
     storage_archive_bucket: String,
     cf_template_bucket: String,
+    pod_identity_cluster: Option<String>,
     read_msg: &Message<CRUDevent>,
     coredb_spec: &mut CoreDBSpec,
     is_cloud_formation: bool,
@@ -891,6 +2231,7 @@ async fn init_cloud_perms(
         read_msg.message.backups_read_path.clone(),
         read_msg.message.backups_write_path.clone(),
         cf_template_bucket,
+        pod_identity_cluster.clone(),
     )
     .await?;
 
@@ -898,15 +2239,21 @@ async fn init_cloud_perms(
     let role_arn = lookup_role_arn(aws_region, &read_msg.message.namespace).await?;
 
     info!("{}: Adding backup configuration to spec", read_msg.msg_id);
-    // Format ServiceAccountTemplate spec in CoreDBSpec
-    use std::collections::BTreeMap;
-    let mut annotations: BTreeMap<String, String> = BTreeMap::new();
-    annotations.insert("eks.amazonaws.com/role-arn".to_string(), role_arn.clone());
-    let service_account_template = ServiceAccountTemplate {
-        metadata: Some(ObjectMeta {
-            annotations: Some(annotations),
-            ..ObjectMeta::default()
-        }),
+    // Format ServiceAccountTemplate spec in CoreDBSpec. With Pod Identity the role is
+    // associated to the service account by the stack, it is not annotated with the role.
+    let service_account_template = match pod_identity_cluster {
+        Some(_) => ServiceAccountTemplate { metadata: None },
+        None => {
+            use std::collections::BTreeMap;
+            let mut annotations: BTreeMap<String, String> = BTreeMap::new();
+            annotations.insert("eks.amazonaws.com/role-arn".to_string(), role_arn.clone());
+            ServiceAccountTemplate {
+                metadata: Some(ObjectMeta {
+                    annotations: Some(annotations),
+                    ..ObjectMeta::default()
+                }),
+            }
+        }
     };
 
     // TODO: disable volumesnapshots for now until we can make them work with CNPG
@@ -946,10 +2293,13 @@ async fn init_cloud_perms(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn init_gcp_storage_workload_identity(
     is_gcp: bool,
     gcp_project_id: String,
     gcp_project_number: String,
+    gcp_kms_key_name: Option<String>,
+    gcp_federation: Option<GcpFederation>,
     read_msg: &Message<CRUDevent>,
     coredb_spec: &mut CoreDBSpec,
     backup_archive_bucket: String,
@@ -966,9 +2316,20 @@ async fn init_gcp_storage_workload_identity(
         &backup_archive_bucket,
         &storage_archive_bucket,
         &read_msg.message.namespace,
+        gcp_federation.clone(),
+        gcp_kms_key_name.as_deref(),
     )
     .await?;
 
+    // Outside of GKE, backups exchange the token of the service account for GCP credentials
+    let google_credentials = match gcp_federation {
+        Some(_) => federation_google_credentials(),
+        None => GoogleCredentials {
+            gke_environment: Some(true),
+            ..Default::default()
+        },
+    };
+
     // Generate Backup spec for CoreDB
     // TODO: disable volumesnapshots for now until we can make them work with CNPG
     // Enable VolumeSnapshots for all instances being created
@@ -991,11 +2352,10 @@ async fn init_gcp_storage_workload_identity(
         s3_credentials: None,
         azure_credentials: None,
         endpoint_url: None,
-        google_credentials: Some(GoogleCredentials {
-            gke_environment: Some(true),
-            ..Default::default()
-        }),
+        google_credentials: Some(google_credentials),
+        kms_key_name: gcp_kms_key_name,
         volume_snapshot,
+        ..Default::default()
     };
 
     coredb_spec.backup = backup;
@@ -1013,6 +2373,7 @@ async fn init_azure_storage_workload_identity(
     azure_subscription_id: String,
     azure_resource_group: String,
     azure_region: String,
+    restore_target: Option<&AzureStorageTarget>,
 ) -> Result<(), ConductorError> {
     if !is_azure {
         return Ok(());
@@ -1025,6 +2386,7 @@ async fn init_azure_storage_workload_identity(
         &azure_storage_account,
         &backup_archive_bucket,
         &read_msg.message.namespace,
+        restore_target,
     )
     .await?;
 
@@ -1073,6 +2435,7 @@ async fn init_azure_storage_workload_identity(
         endpoint_url: None,
         google_credentials: None,
         volume_snapshot,
+        ..Default::default()
     };
 
     coredb_spec.backup = backup;
@@ -1084,72 +2447,3 @@ async fn init_azure_storage_workload_identity(
 fn from_env_default(key: &str, default: &str) -> String {
     env::var(key).unwrap_or_else(|_| default.to_owned())
 }
-
-/// Validates that all required Azure environment variables are set when IS_AZURE is true.
-/// Returns Ok(()) if all variables are present and non-empty, or an error message listing missing variables.
-fn validate_azure_environment(
-    is_azure: bool,
-    azure_storage_account: &str,
-    azure_subscription_id: &str,
-    azure_resource_group_prefix: &str,
-    azure_region: &str,
-) -> Result<(), String> {
-    if !is_azure {
-        return Ok(());
-    }
-
-    let required_vars = [
-        ("AZURE_STORAGE_ACCOUNT", azure_storage_account),
-        ("AZURE_SUBSCRIPTION_ID", azure_subscription_id),
-        ("AZURE_RESOURCE_GROUP_PREFIX", azure_resource_group_prefix),
-        ("AZURE_REGION", azure_region),
-    ];
-
-    let missing_vars: Vec<&str> = required_vars
-        .iter()
-        .filter(|(_, value)| value.is_empty())
-        .map(|(name, _)| *name)
-        .collect();
-
-    if missing_vars.is_empty() {
-        Ok(())
-    } else {
-        Err(format!(
-            "The following required Azure environment variables are empty: {}",
-            missing_vars.join(", ")
-        ))
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_azure_validation() {
-        // Test when Azure is disabled
-        assert!(validate_azure_environment(false, "", "", "", "").is_ok());
-
-        // Test when Azure is enabled and all variables are present
-        assert!(validate_azure_environment(
-            true,
-            "storage_account",
-            "subscription_id",
-            "resource_group",
-            "region"
-        )
-        .is_ok());
-
-        // Test when Azure is enabled and variables are missing
-        let err =
-            validate_azure_environment(true, "", "subscription_id", "resource_group", "region")
-                .unwrap_err();
-        assert!(err.contains("AZURE_STORAGE_ACCOUNT"));
-
-        // Test multiple missing variables
-        let err = validate_azure_environment(true, "", "", "resource_group", "").unwrap_err();
-        assert!(err.contains("AZURE_STORAGE_ACCOUNT"));
-        assert!(err.contains("AZURE_SUBSCRIPTION_ID"));
-        assert!(err.contains("AZURE_REGION"));
-    }
-}
@@ -40,16 +40,30 @@ pub mod prometheus {
     where
         D: Deserializer<'de>,
     {
-        let raw_value: (f64, String) = Deserialize::deserialize(deserializer)?;
-        let timestamp = raw_value.0.trunc() as i64;
-        let parsed_float = raw_value.1.parse::<f64>().map_err(de::Error::custom)?;
-        Ok((timestamp, parsed_float as i64))
+        // Prometheus sends the sample value as a string, while metrics serialized
+        // by conductor itself (e.g. in a compressed batch) carry it as a number
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawValue {
+            Prometheus(f64, String),
+            Serialized(i64, i64),
+        }
+
+        match RawValue::deserialize(deserializer)? {
+            RawValue::Prometheus(timestamp, value) => {
+                let parsed_float = value.parse::<f64>().map_err(de::Error::custom)?;
+                Ok((timestamp.trunc() as i64, parsed_float as i64))
+            }
+            RawValue::Serialized(timestamp, value) => Ok((timestamp, value)),
+        }
     }
 }
 
 /// Data Plane metrics as packaged to be sent to Control Plane
 pub mod dataplane_metrics {
     use super::prometheus::MetricsResult;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -88,11 +102,74 @@ pub mod dataplane_metrics {
 
         result
     }
+
+    /// Encoding of the metrics of a [`CompressedMetricsBatch`]
+    pub const METRICS_BATCH_ENCODING: &str = "gzip+base64";
+
+    /// Many [`DataPlaneMetrics`] packed in a single queue message, as a gzipped JSON array
+    /// encoded in base64
+    #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    pub struct CompressedMetricsBatch {
+        pub encoding: String,
+        pub metrics: String,
+    }
+
+    impl CompressedMetricsBatch {
+        pub fn compress(metrics: &[DataPlaneMetrics]) -> anyhow::Result<Self> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            serde_json::to_writer(&mut encoder, metrics)?;
+            Ok(CompressedMetricsBatch {
+                encoding: METRICS_BATCH_ENCODING.to_string(),
+                metrics: STANDARD.encode(encoder.finish()?),
+            })
+        }
+
+        pub fn decompress(&self) -> anyhow::Result<Vec<DataPlaneMetrics>> {
+            if self.encoding != METRICS_BATCH_ENCODING {
+                anyhow::bail!("unsupported metrics batch encoding {}", self.encoding);
+            }
+            let compressed = STANDARD.decode(&self.metrics)?;
+            Ok(serde_json::from_reader(GzDecoder::new(
+                compressed.as_slice(),
+            ))?)
+        }
+    }
+
+    /// Pack metrics chunks into compressed batches whose uncompressed JSON is at most
+    /// `max_bytes`. A chunk larger than `max_bytes` is sent in a batch of its own.
+    pub fn compress_batches(
+        metrics: Vec<DataPlaneMetrics>,
+        max_bytes: usize,
+    ) -> anyhow::Result<Vec<CompressedMetricsBatch>> {
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+
+        for chunk in metrics {
+            let chunk_bytes = serde_json::to_vec(&chunk)?.len();
+            if !batch.is_empty() && batch_bytes + chunk_bytes > max_bytes {
+                batches.push(CompressedMetricsBatch::compress(&batch)?);
+                batch.clear();
+                batch_bytes = 0;
+            }
+            batch_bytes += chunk_bytes;
+            batch.push(chunk);
+        }
+
+        if !batch.is_empty() {
+            batches.push(CompressedMetricsBatch::compress(&batch)?);
+        }
+
+        Ok(batches)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::metrics::dataplane_metrics::{split_data_plane_metrics, DataPlaneMetrics};
+    use crate::metrics::dataplane_metrics::{
+        compress_batches, split_data_plane_metrics, DataPlaneMetrics,
+    };
     use crate::metrics::prometheus::{MetricLabels, Metrics, MetricsData, MetricsResult};
 
     const QUERY_RESPONSE: &str = r#"
@@ -439,4 +516,50 @@ mod tests {
             "First chunk size incorrect"
         );
     }
+
+    #[test]
+    fn test_compress_batches() {
+        let results: Vec<MetricsResult> = (0..2008)
+            .map(|i| MetricsResult {
+                metric: MetricLabels {
+                    instance_id: Some(format!("inst_{}", i)),
+                    pod: Some(format!("pod_{}", i)),
+                    server_name: None,
+                },
+                value: (i as i64, i as i64),
+            })
+            .collect();
+        let chunks = split_data_plane_metrics(
+            DataPlaneMetrics {
+                name: "test_metric".into(),
+                result: results,
+            },
+            500,
+        );
+        let chunk_bytes = chunks
+            .iter()
+            .map(|chunk| serde_json::to_vec(chunk).unwrap().len())
+            .max()
+            .unwrap();
+
+        // Two full chunks fit in a batch, the last one holds the remaining chunk
+        let batches = compress_batches(chunks.clone(), chunk_bytes * 2).unwrap();
+        assert_eq!(
+            batches.len(),
+            3,
+            "Expected 3 batches, got {}",
+            batches.len()
+        );
+        assert!(batches[0].metrics.len() < chunk_bytes);
+
+        let decompressed: Vec<DataPlaneMetrics> = batches
+            .iter()
+            .flat_map(|batch| batch.decompress().unwrap())
+            .collect();
+        assert_eq!(decompressed, chunks);
+
+        // Chunks larger than the limit are sent on their own
+        let batches = compress_batches(chunks, 1).unwrap();
+        assert_eq!(batches.len(), 5);
+    }
 }
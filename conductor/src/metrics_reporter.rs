@@ -1,5 +1,5 @@
 use anyhow::{bail, Context, Result};
-use conductor::metrics::dataplane_metrics::split_data_plane_metrics;
+use conductor::metrics::dataplane_metrics::{compress_batches, split_data_plane_metrics};
 use conductor::metrics::{dataplane_metrics::DataPlaneMetrics, prometheus::Metrics};
 use log::{error, info};
 use pgmq::PGMQueueExt;
@@ -51,12 +51,22 @@ pub async fn run_metrics_reporter() -> Result<()> {
         panic!("Configuration error: too many metrics, currently up to 60 metrics are supported.");
     }
 
+    // When set, the metrics of a polling cycle are sent in compressed batches of up to this
+    // many bytes of JSON instead of a message for each chunk of 1000 results
+    let batch_max_bytes: usize = from_env_default("METRICS_BATCH_MAX_BYTES", "0")
+        .parse()
+        .with_context(|| "METRICS_BATCH_MAX_BYTES must be a number of bytes")?;
+    if batch_max_bytes > 0 {
+        info!("metrics_reporter will send compressed batches of up to {batch_max_bytes} bytes");
+    }
+
     let mut sync_interval = interval(Duration::from_secs(polling_interval_seconds));
 
     loop {
         sync_interval.tick().await;
 
         let now = Instant::now();
+        let mut metrics_to_batch = Vec::new();
         for metric in &metrics {
             info!("Querying '{}' from {}", metric.name, metric.server);
 
@@ -94,16 +104,29 @@ pub async fn run_metrics_reporter() -> Result<()> {
                 batches, batch_size
             );
 
-            let mut i = 1;
-            for data_plane_metrics in &metrics_to_send {
+            if batch_max_bytes > 0 {
+                metrics_to_batch.extend(metrics_to_send);
+                continue;
+            }
+
+            for (i, data_plane_metrics) in metrics_to_send.iter().enumerate() {
                 queue
                     .send(&metrics_events_queue, data_plane_metrics)
                     .await?;
-                info!("Enqueued batch {}/{} to PGMQ", i, batches);
-                i += 1;
+                info!("Enqueued batch {}/{} to PGMQ", i + 1, batches);
             }
             info!("Processed metric in {:?}", now.elapsed());
         }
+
+        if !metrics_to_batch.is_empty() {
+            let compressed = compress_batches(metrics_to_batch, batch_max_bytes)?;
+            let batches = compressed.len();
+            for (i, batch) in compressed.iter().enumerate() {
+                queue.send(&metrics_events_queue, batch).await?;
+                info!("Enqueued compressed batch {}/{} to PGMQ", i + 1, batches);
+            }
+            info!("Processed metrics in {:?}", now.elapsed());
+        }
     }
 }
 
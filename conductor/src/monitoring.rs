@@ -1,4 +1,5 @@
-use opentelemetry::metrics::{Counter, Meter};
+use crate::heartbeat::LoopHeartbeat;
+use opentelemetry::metrics::{Counter, Meter, Result};
 
 #[derive(Clone)]
 pub struct CustomMetrics {
@@ -34,3 +35,14 @@ impl CustomMetrics {
         }
     }
 }
+
+/// Report the time since the conductor loop last made progress as `conductor_loop_lag_seconds`
+pub fn register_loop_lag(meter: &Meter, heartbeat: LoopHeartbeat) -> Result<()> {
+    let loop_lag = meter
+        .f64_observable_gauge("conductor_loop_lag_seconds")
+        .with_description("Seconds since the conductor loop last made progress")
+        .init();
+    meter.register_callback(move |cx| {
+        loop_lag.observe(cx, heartbeat.lag().as_secs_f64(), &[]);
+    })
+}
@@ -0,0 +1,208 @@
+use crate::errors::ConductorError;
+use crate::types::{CRUDevent, Event};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{Api, Client};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Row};
+
+const INSTANCE_ID_LABEL: &str = "tembo.io/instance_id";
+// Namespaces are DNS labels
+const MAX_NAMESPACE_LEN: usize = 63;
+// How many suffixed namespaces are tried before giving up
+const MAX_SUFFIX_ATTEMPTS: u32 = 8;
+
+/// The namespace an instance gets when the namespace it asked for belongs to another instance.
+/// The suffix is derived from the instance id, and the attempt when the suffixed namespace is
+/// taken as well, so the same instance always gets the same namespace, also across releases
+/// of conductor.
+pub fn suffixed_namespace(requested: &str, instance_id: &str, attempt: u32) -> String {
+    let digest = Sha256::digest(format!("{}/{}", instance_id, attempt));
+    let suffix: String = digest[..3].iter().map(|b| format!("{:02x}", b)).collect();
+    let mut prefix_len = requested.len().min(MAX_NAMESPACE_LEN - suffix.len() - 1);
+    while !requested.is_char_boundary(prefix_len) {
+        prefix_len -= 1;
+    }
+    format!(
+        "{}-{}",
+        requested[..prefix_len].trim_end_matches('-'),
+        suffix
+    )
+}
+
+// Who a namespace belongs to: None when it does not exist, Some(None) when it is not labeled
+async fn namespace_owner(
+    client: Client,
+    name: &str,
+) -> Result<Option<Option<String>>, ConductorError> {
+    let ns_api: Api<Namespace> = Api::all(client);
+    let namespace = ns_api.get_opt(name).await?;
+    Ok(namespace.map(|namespace| {
+        namespace
+            .metadata
+            .labels
+            .and_then(|labels| labels.get(INSTANCE_ID_LABEL).cloned())
+    }))
+}
+
+// Namespaces which do not exist, or exist for the instance, can be used by the instance.
+// Namespaces without the label were created before namespaces were labeled.
+async fn is_available(
+    client: Client,
+    name: &str,
+    instance_id: &str,
+) -> Result<bool, ConductorError> {
+    Ok(match namespace_owner(client, name).await? {
+        None | Some(None) => true,
+        Some(Some(owner)) => owner == instance_id,
+    })
+}
+
+async fn recorded_namespace(
+    db_pool: &PgPool,
+    requested: &str,
+    instance_id: &str,
+) -> Result<Option<String>, ConductorError> {
+    let row = sqlx::query(
+        "SELECT namespace FROM namespace_mappings \
+         WHERE requested_namespace = $1 AND instance_id = $2",
+    )
+    .bind(requested)
+    .bind(instance_id)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::NamespaceMappingError(e.to_string()))?;
+    Ok(row.map(|row| row.get("namespace")))
+}
+
+/// The namespaces events are ordered on: the namespace the event asks for, and the namespace
+/// it is mapped to when it was remapped. This does not resolve the namespace, an event read
+/// before its namespace is remapped is ordered on the requested namespace, which the events
+/// which follow are ordered on as well.
+pub async fn ordering_namespaces(
+    db_pool: &PgPool,
+    event: &CRUDevent,
+) -> Result<Vec<String>, ConductorError> {
+    let mut namespaces = vec![event.namespace.clone()];
+    if let Some(namespace) = recorded_namespace(db_pool, &event.namespace, &event.inst_id).await? {
+        if namespace != event.namespace {
+            namespaces.push(namespace);
+        }
+    }
+    Ok(namespaces)
+}
+
+/// The namespace of the instance of an event. The namespace the event asks for is used, unless
+/// it belongs to another instance: then Create and Restore events get a suffixed namespace,
+/// which is recorded so the events which follow use it, and other events fail instead of
+/// touching the namespace of another instance.
+pub async fn resolve_namespace(
+    db_pool: &PgPool,
+    client: Client,
+    event: &CRUDevent,
+) -> Result<String, ConductorError> {
+    let requested = &event.namespace;
+    let instance_id = &event.inst_id;
+    if let Some(namespace) = recorded_namespace(db_pool, requested, instance_id).await? {
+        return Ok(namespace);
+    }
+    if is_available(client.clone(), requested, instance_id).await? {
+        return Ok(requested.clone());
+    }
+    if !matches!(event.event_type, Event::Create | Event::Restore) {
+        return Err(ConductorError::NamespaceCollision(format!(
+            "namespace {} belongs to another instance",
+            requested
+        )));
+    }
+
+    warn!(
+        "Namespace {} belongs to another instance, remapping instance {}",
+        requested, instance_id
+    );
+    for attempt in 0..MAX_SUFFIX_ATTEMPTS {
+        let candidate = suffixed_namespace(requested, instance_id, attempt);
+        if !is_available(client.clone(), &candidate, instance_id).await? {
+            continue;
+        }
+        let recorded = sqlx::query(
+            "INSERT INTO namespace_mappings (requested_namespace, instance_id, namespace) \
+             VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(requested)
+        .bind(instance_id)
+        .bind(&candidate)
+        .execute(db_pool)
+        .await
+        .map_err(|e| ConductorError::NamespaceMappingError(e.to_string()))?;
+        // The candidate is mapped to another instance which did not create it yet
+        if recorded.rows_affected() == 0 {
+            if let Some(namespace) = recorded_namespace(db_pool, requested, instance_id).await? {
+                return Ok(namespace);
+            }
+            continue;
+        }
+        info!(
+            "Mapped namespace {} of instance {} to {}",
+            requested, instance_id, candidate
+        );
+        return Ok(candidate);
+    }
+    Err(ConductorError::NamespaceCollision(format!(
+        "no namespace available for {} after {} attempts",
+        requested, MAX_SUFFIX_ATTEMPTS
+    )))
+}
+
+/// Forget the namespace of a deleted instance
+pub async fn delete_namespace_mapping(
+    db_pool: &PgPool,
+    requested: &str,
+    instance_id: &str,
+) -> Result<(), ConductorError> {
+    sqlx::query(
+        "DELETE FROM namespace_mappings WHERE requested_namespace = $1 AND instance_id = $2",
+    )
+    .bind(requested)
+    .bind(instance_id)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::NamespaceMappingError(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suffixed_namespace() {
+        let namespace = suffixed_namespace("org-acme-inst-db", "inst_1", 0);
+        assert_eq!(
+            namespace,
+            suffixed_namespace("org-acme-inst-db", "inst_1", 0)
+        );
+        assert!(namespace.starts_with("org-acme-inst-db-"));
+        assert_eq!(namespace.len(), "org-acme-inst-db".len() + 7);
+        assert_ne!(
+            namespace,
+            suffixed_namespace("org-acme-inst-db", "inst_1", 1)
+        );
+        assert_ne!(
+            namespace,
+            suffixed_namespace("org-acme-inst-db", "inst_2", 0)
+        );
+
+        // The suffix does not depend on the build of conductor
+        assert_eq!(
+            suffixed_namespace("org-acme-inst-db", "inst_1", 0),
+            "org-acme-inst-db-c044a2"
+        );
+
+        let long = suffixed_namespace(&"a".repeat(63), "inst_1", 0);
+        assert_eq!(long.len(), 63);
+
+        let multibyte = suffixed_namespace(&"é".repeat(40), "inst_1", 0);
+        assert!(multibyte.len() <= 63);
+    }
+}
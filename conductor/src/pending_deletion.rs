@@ -0,0 +1,105 @@
+use crate::errors::ConductorError;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Row};
+
+/// A Delete event waiting for the grace period to end, the instance is hibernated meanwhile
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingDeletion {
+    // The Delete event, requeued until delete_after
+    pub msg_id: i64,
+    // Whether the instance was stopped before it was hibernated, restored on undelete
+    pub was_stopped: bool,
+    pub delete_after: DateTime<Utc>,
+}
+
+impl PendingDeletion {
+    /// The seconds until the instance is deleted, 0 once the grace period ended
+    pub fn remaining_secs(&self, now: DateTime<Utc>) -> i32 {
+        (self.delete_after - now)
+            .num_seconds()
+            .clamp(0, i32::MAX as i64) as i32
+    }
+}
+
+/// Schedule the deletion of an instance. Deleting an instance which is already scheduled
+/// keeps the end of its grace period.
+pub async fn schedule_deletion(
+    db_pool: &PgPool,
+    namespace: &str,
+    msg_id: i64,
+    was_stopped: bool,
+    grace_period: Duration,
+) -> Result<PendingDeletion, ConductorError> {
+    sqlx::query(
+        "INSERT INTO pending_deletions (namespace, msg_id, was_stopped, delete_after) \
+         VALUES ($1, $2, $3, $4) ON CONFLICT (namespace) DO NOTHING",
+    )
+    .bind(namespace)
+    .bind(msg_id)
+    .bind(was_stopped)
+    .bind(Utc::now() + grace_period)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::PendingDeletionError(e.to_string()))?;
+
+    pending_deletion(db_pool, namespace).await?.ok_or_else(|| {
+        ConductorError::PendingDeletionError(format!("deletion of {} not found", namespace))
+    })
+}
+
+/// The scheduled deletion of an instance, None when it is not scheduled
+pub async fn pending_deletion(
+    db_pool: &PgPool,
+    namespace: &str,
+) -> Result<Option<PendingDeletion>, ConductorError> {
+    let row = sqlx::query(
+        "SELECT msg_id, was_stopped, delete_after FROM pending_deletions WHERE namespace = $1",
+    )
+    .bind(namespace)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::PendingDeletionError(e.to_string()))?;
+    Ok(row.map(|row| PendingDeletion {
+        msg_id: row.get("msg_id"),
+        was_stopped: row.get("was_stopped"),
+        delete_after: row.get("delete_after"),
+    }))
+}
+
+/// Remove the scheduled deletion of an instance, once it was deleted or undeleted. Returns
+/// the deletion which was removed.
+pub async fn remove_pending_deletion(
+    db_pool: &PgPool,
+    namespace: &str,
+) -> Result<Option<PendingDeletion>, ConductorError> {
+    let row = sqlx::query(
+        "DELETE FROM pending_deletions WHERE namespace = $1 \
+         RETURNING msg_id, was_stopped, delete_after",
+    )
+    .bind(namespace)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| ConductorError::PendingDeletionError(e.to_string()))?;
+    Ok(row.map(|row| PendingDeletion {
+        msg_id: row.get("msg_id"),
+        was_stopped: row.get("was_stopped"),
+        delete_after: row.get("delete_after"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remaining_secs() {
+        let now = Utc::now();
+        let deletion = PendingDeletion {
+            msg_id: 1,
+            was_stopped: false,
+            delete_after: now + Duration::hours(24),
+        };
+        assert_eq!(deletion.remaining_secs(now), 24 * 3600);
+        assert_eq!(deletion.remaining_secs(now + Duration::hours(25)), 0);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::errors::ConductorError;
+use log::info;
+use pgmq::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+/// A message whose payload could not be read, kept with the error so it can be triaged and
+/// replayed into its queue once conductor or the payload is fixed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedMessage {
+    pub id: i64,
+    pub queue_name: String,
+    pub msg_id: i64,
+    pub read_ct: i32,
+    pub enqueued_at: String,
+    pub payload: Value,
+    pub error: String,
+    pub quarantined_at: String,
+    pub replayed_at: Option<String>,
+    pub replayed_msg_id: Option<i64>,
+}
+
+/// Read the payload of a message, the error is the reason to quarantine the message
+pub fn parse_message<T: serde::de::DeserializeOwned>(
+    raw_msg: &Message<Value>,
+) -> Result<Message<T>, serde_json::Error> {
+    Ok(Message {
+        msg_id: raw_msg.msg_id,
+        vt: raw_msg.vt,
+        enqueued_at: raw_msg.enqueued_at,
+        read_ct: raw_msg.read_ct,
+        message: serde_json::from_value(raw_msg.message.clone())?,
+    })
+}
+
+/// Record a message in the quarantine table, it still has to be removed from its queue
+pub async fn quarantine(
+    db_pool: &PgPool,
+    queue_name: &str,
+    raw_msg: &Message<Value>,
+    error: &str,
+) -> Result<i64, ConductorError> {
+    let row = sqlx::query(
+        "INSERT INTO quarantined_messages (queue_name, msg_id, read_ct, enqueued_at, payload, error) \
+         VALUES ($1, $2, $3, $4::text::timestamptz, $5::text::jsonb, $6) RETURNING id",
+    )
+    .bind(queue_name)
+    .bind(raw_msg.msg_id)
+    .bind(raw_msg.read_ct)
+    .bind(raw_msg.enqueued_at.to_rfc3339())
+    .bind(raw_msg.message.to_string())
+    .bind(error)
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| ConductorError::QuarantineError(e.to_string()))?;
+    Ok(row.get("id"))
+}
+
+/// List quarantined messages, oldest first. Replayed messages are only included when asked for.
+pub async fn list_quarantined(
+    db_pool: &PgPool,
+    include_replayed: bool,
+) -> Result<Vec<QuarantinedMessage>, ConductorError> {
+    let rows = sqlx::query(
+        "SELECT id, queue_name, msg_id, read_ct, enqueued_at::text AS enqueued_at, \
+         payload::text AS payload, error, quarantined_at::text AS quarantined_at, \
+         replayed_at::text AS replayed_at, replayed_msg_id \
+         FROM quarantined_messages WHERE $1 OR replayed_at IS NULL ORDER BY id",
+    )
+    .bind(include_replayed)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| ConductorError::QuarantineError(e.to_string()))?;
+
+    rows.iter()
+        .map(|row| {
+            let payload: String = row.get("payload");
+            Ok(QuarantinedMessage {
+                id: row.get("id"),
+                queue_name: row.get("queue_name"),
+                msg_id: row.get("msg_id"),
+                read_ct: row.get("read_ct"),
+                enqueued_at: row.get("enqueued_at"),
+                payload: serde_json::from_str(&payload)?,
+                error: row.get("error"),
+                quarantined_at: row.get("quarantined_at"),
+                replayed_at: row.get("replayed_at"),
+                replayed_msg_id: row.get("replayed_msg_id"),
+            })
+        })
+        .collect()
+}
+
+/// Send a quarantined message back to the queue it came from, as a new message. Returns the
+/// id of the new message, or None when the quarantined message does not exist or was already
+/// replayed. A message which still can not be read is quarantined again.
+pub async fn replay_quarantined(db_pool: &PgPool, id: i64) -> Result<Option<i64>, ConductorError> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .map_err(|e| ConductorError::QuarantineError(e.to_string()))?;
+
+    let Some(row) = sqlx::query(
+        "SELECT queue_name, payload::text AS payload FROM quarantined_messages \
+         WHERE id = $1 AND replayed_at IS NULL FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ConductorError::QuarantineError(e.to_string()))?
+    else {
+        return Ok(None);
+    };
+    let queue_name: String = row.get("queue_name");
+    let payload: String = row.get("payload");
+
+    let msg_id: i64 = sqlx::query_scalar("SELECT pgmq.send($1, $2::jsonb)")
+        .bind(&queue_name)
+        .bind(payload)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ConductorError::QuarantineError(e.to_string()))?;
+    sqlx::query(
+        "UPDATE quarantined_messages SET replayed_at = CURRENT_TIMESTAMP, replayed_msg_id = $2 \
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(msg_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ConductorError::QuarantineError(e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ConductorError::QuarantineError(e.to_string()))?;
+    info!(
+        "Replayed quarantined message {} into queue {} as message {}",
+        id, queue_name, msg_id
+    );
+    Ok(Some(msg_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CRUDevent;
+    use chrono::Utc;
+
+    #[test]
+    fn test_parse_message() {
+        let raw_msg = |message: Value| Message {
+            msg_id: 7,
+            vt: Utc::now(),
+            enqueued_at: Utc::now(),
+            read_ct: 1,
+            message,
+        };
+
+        let valid = raw_msg(serde_json::json!({
+            "data_plane_id": "org_acme_dp",
+            "org_id": "org_acme",
+            "inst_id": "inst_db",
+            "event_type": "Delete",
+            "namespace": "org-acme-inst-db",
+            "backups_read_path": null,
+            "backups_write_path": null,
+            "spec": null,
+        }));
+        let parsed: Message<CRUDevent> = parse_message(&valid).unwrap();
+        assert_eq!(parsed.msg_id, 7);
+        assert_eq!(parsed.message.namespace, "org-acme-inst-db");
+
+        let malformed = raw_msg(serde_json::json!({"event_type": "Delete"}));
+        assert!(parse_message::<CRUDevent>(&malformed).is_err());
+    }
+}
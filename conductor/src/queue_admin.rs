@@ -0,0 +1,163 @@
+use crate::errors::ConductorError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use std::collections::BTreeMap;
+
+/// The state of a queue, for on-call to inspect during incidents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub queue_name: String,
+    /// Messages in the queue, including the ones being processed
+    pub queue_length: i64,
+    /// Messages which are being processed or wait to be retried
+    pub invisible: i64,
+    pub oldest_msg_age_sec: Option<i32>,
+    pub newest_msg_age_sec: Option<i32>,
+    pub by_event_type: BTreeMap<String, i64>,
+}
+
+/// A message in a queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub msg_id: i64,
+    pub read_ct: i32,
+    pub enqueued_at: String,
+    pub vt: String,
+    pub message: Value,
+}
+
+fn error(e: sqlx::Error) -> ConductorError {
+    ConductorError::QueueAdminError(e.to_string())
+}
+
+// pgmq stores the messages of a queue in pgmq.q_<queue_name>. Queue names come from the
+// configuration of conductor, they are still checked since they end up in the query text.
+fn queue_table(queue_name: &str) -> Result<String, ConductorError> {
+    if queue_name.is_empty()
+        || !queue_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(ConductorError::QueueAdminError(format!(
+            "invalid queue name {}",
+            queue_name
+        )));
+    }
+    Ok(format!("pgmq.q_{}", queue_name.to_lowercase()))
+}
+
+pub async fn queue_stats(pool: &PgPool, queue_name: &str) -> Result<QueueStats, ConductorError> {
+    let table = queue_table(queue_name)?;
+    let row = sqlx::query(&format!(
+        "SELECT count(*) AS queue_length, \
+         count(*) FILTER (WHERE vt > clock_timestamp()) AS invisible, \
+         (EXTRACT(epoch FROM clock_timestamp() - min(enqueued_at)))::int AS oldest_msg_age_sec, \
+         (EXTRACT(epoch FROM clock_timestamp() - max(enqueued_at)))::int AS newest_msg_age_sec \
+         FROM {table}"
+    ))
+    .fetch_one(pool)
+    .await
+    .map_err(error)?;
+
+    let by_event_type = sqlx::query(&format!(
+        "SELECT coalesce(message->>'event_type', 'unknown') AS event_type, count(*) AS count \
+         FROM {table} GROUP BY 1"
+    ))
+    .fetch_all(pool)
+    .await
+    .map_err(error)?
+    .iter()
+    .map(|row| (row.get("event_type"), row.get("count")))
+    .collect();
+
+    Ok(QueueStats {
+        queue_name: queue_name.to_string(),
+        queue_length: row.get("queue_length"),
+        invisible: row.get("invisible"),
+        oldest_msg_age_sec: row.get("oldest_msg_age_sec"),
+        newest_msg_age_sec: row.get("newest_msg_age_sec"),
+        by_event_type,
+    })
+}
+
+/// The oldest messages of a queue, optionally only the ones of one namespace
+pub async fn list_messages(
+    pool: &PgPool,
+    queue_name: &str,
+    namespace: Option<&str>,
+    limit: i64,
+) -> Result<Vec<QueuedMessage>, ConductorError> {
+    let table = queue_table(queue_name)?;
+    let rows = sqlx::query(&format!(
+        "SELECT msg_id, read_ct, enqueued_at::text AS enqueued_at, vt::text AS vt, \
+         message::text AS message FROM {table} \
+         WHERE $1::text IS NULL OR message->>'namespace' = $1 \
+         ORDER BY msg_id LIMIT $2"
+    ))
+    .bind(namespace)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(error)?;
+
+    rows.iter()
+        .map(|row| {
+            let message: String = row.get("message");
+            Ok(QueuedMessage {
+                msg_id: row.get("msg_id"),
+                read_ct: row.get("read_ct"),
+                enqueued_at: row.get("enqueued_at"),
+                vt: row.get("vt"),
+                message: serde_json::from_str(&message)?,
+            })
+        })
+        .collect()
+}
+
+/// Move a message to the archive of its queue, returns false when it is not in the queue
+pub async fn archive_message(
+    pool: &PgPool,
+    queue_name: &str,
+    msg_id: i64,
+) -> Result<bool, ConductorError> {
+    queue_table(queue_name)?;
+    sqlx::query_scalar("SELECT pgmq.archive($1, $2)")
+        .bind(queue_name)
+        .bind(msg_id)
+        .fetch_one(pool)
+        .await
+        .map_err(error)
+}
+
+/// Make a message visible right away, so it is read again without waiting for its
+/// visibility timeout. Returns false when it is not in the queue.
+pub async fn requeue_message(
+    pool: &PgPool,
+    queue_name: &str,
+    msg_id: i64,
+) -> Result<bool, ConductorError> {
+    queue_table(queue_name)?;
+    let row = sqlx::query("SELECT msg_id FROM pgmq.set_vt($1, $2, 0)")
+        .bind(queue_name)
+        .bind(msg_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(error)?;
+    Ok(row.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_table() {
+        assert_eq!(
+            queue_table("saas_queue_CP").unwrap(),
+            "pgmq.q_saas_queue_cp"
+        );
+        assert!(queue_table("queue; DROP TABLE x").is_err());
+        assert!(queue_table("").is_err());
+    }
+}
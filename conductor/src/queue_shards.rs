@@ -0,0 +1,128 @@
+use crate::errors::ConductorError;
+
+/// The control plane events queues consumed by conductor. Without shards it is the base queue
+/// alone. With shards it is the base queue, still read for events of control planes which do
+/// not shard them, followed by `<base>_<shard>` for each shard.
+pub fn shard_queue_names(base: &str, shards: usize) -> Vec<String> {
+    std::iter::once(base.to_string())
+        .chain((0..shards).map(|shard| format!("{}_{}", base, shard)))
+        .collect()
+}
+
+/// The shard receiving the events of an organization. Control plane sends the events of an
+/// organization to `<base>_<shard>`, so they keep their order. The hash is the 64 bit FNV-1a
+/// hash of the organization id, which is stable across releases and languages.
+pub fn shard_for_org(org_id: &str, shards: usize) -> usize {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    let hash = org_id.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    (hash % shards.max(1) as u64) as usize
+}
+
+/// Parse the comma separated weights of the queues, every queue has a weight of 1 when unset
+pub fn parse_weights(weights: &str, queues: usize) -> Result<Vec<u32>, ConductorError> {
+    if weights.trim().is_empty() {
+        return Ok(vec![1; queues]);
+    }
+    let weights = weights
+        .split(',')
+        .map(|weight| weight.trim().parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|e| ConductorError::QueueShardError(format!("invalid queue weight: {}", e)))?;
+    if weights.len() != queues {
+        return Err(ConductorError::QueueShardError(format!(
+            "expected {} queue weights, got {}",
+            queues,
+            weights.len()
+        )));
+    }
+    if weights.iter().all(|weight| *weight == 0) {
+        return Err(ConductorError::QueueShardError(
+            "at least one queue weight must be positive".to_string(),
+        ));
+    }
+    Ok(weights)
+}
+
+/// Smooth weighted round robin over the queues: over a round, each queue comes first as many
+/// times as its weight, spread out evenly. A queue with a weight of 0 is only read when the
+/// others are empty.
+pub struct WeightedRoundRobin {
+    weights: Vec<i64>,
+    current: Vec<i64>,
+}
+
+impl WeightedRoundRobin {
+    pub fn new(weights: &[u32]) -> Self {
+        WeightedRoundRobin {
+            weights: weights.iter().map(|weight| *weight as i64).collect(),
+            current: vec![0; weights.len()],
+        }
+    }
+
+    /// The order to read the queues in for the next message, the queue whose turn it is comes
+    /// first and the others follow in case it is empty
+    pub fn next_order(&mut self) -> Vec<usize> {
+        let total: i64 = self.weights.iter().sum();
+        for (current, weight) in self.current.iter_mut().zip(&self.weights) {
+            *current += weight;
+        }
+        let first = (0..self.current.len())
+            .max_by_key(|i| (self.current[*i], std::cmp::Reverse(*i)))
+            .unwrap_or_default();
+        self.current[first] -= total;
+
+        let queues = self.current.len();
+        std::iter::once(first)
+            .chain((1..queues).map(|offset| (first + offset) % queues))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_queue_names() {
+        assert_eq!(shard_queue_names("events", 0), vec!["events"]);
+        assert_eq!(
+            shard_queue_names("events", 2),
+            vec!["events", "events_0", "events_1"]
+        );
+    }
+
+    #[test]
+    fn test_shard_for_org() {
+        assert_eq!(shard_for_org("", 8), (0xcbf29ce484222325_u64 % 8) as usize);
+        assert_eq!(
+            shard_for_org("org_acme", 4),
+            shard_for_org("org_acme", 4),
+            "the shard of an organization is stable"
+        );
+        assert_eq!(shard_for_org("org_acme", 0), 0);
+        let shards: std::collections::HashSet<usize> = (0..100)
+            .map(|i| shard_for_org(&format!("org_{}", i), 4))
+            .collect();
+        assert_eq!(shards.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_weights() {
+        assert_eq!(parse_weights("", 3).unwrap(), vec![1, 1, 1]);
+        assert_eq!(parse_weights("1, 2,0", 3).unwrap(), vec![1, 2, 0]);
+        assert!(parse_weights("1,2", 3).is_err());
+        assert!(parse_weights("1,x,1", 3).is_err());
+        assert!(parse_weights("0,0", 2).is_err());
+    }
+
+    #[test]
+    fn test_weighted_round_robin() {
+        let mut scheduler = WeightedRoundRobin::new(&[1, 2, 0]);
+        let firsts: Vec<usize> = (0..6).map(|_| scheduler.next_order()[0]).collect();
+        assert_eq!(firsts, vec![1, 0, 1, 1, 0, 1]);
+        assert_eq!(scheduler.next_order(), vec![1, 2, 0]);
+    }
+}
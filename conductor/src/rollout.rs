@@ -0,0 +1,264 @@
+use crate::errors::ConductorError;
+use crate::types::{Rollout, RolloutPolicy, RolloutProgress, RolloutStatus};
+use sqlx::{PgPool, Row};
+
+/// The state of an instance of a rollout, recorded in the rollout_instances table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstanceState {
+    /// no Update event was sent for the instance yet
+    Pending,
+    /// an Update event was sent and is being processed
+    Dispatched,
+    Succeeded,
+    Failed,
+}
+
+impl InstanceState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InstanceState::Pending => "pending",
+            InstanceState::Dispatched => "dispatched",
+            InstanceState::Succeeded => "succeeded",
+            InstanceState::Failed => "failed",
+        }
+    }
+
+    fn parse(state: &str) -> Result<Self, ConductorError> {
+        match state {
+            "pending" => Ok(InstanceState::Pending),
+            "dispatched" => Ok(InstanceState::Dispatched),
+            "succeeded" => Ok(InstanceState::Succeeded),
+            "failed" => Ok(InstanceState::Failed),
+            _ => Err(ConductorError::RolloutError(format!(
+                "unknown instance state {}",
+                state
+            ))),
+        }
+    }
+}
+
+/// What to do next for a rollout
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    /// send Update events for the instances at these positions
+    Dispatch(Vec<usize>),
+    /// wait for the dispatched instances to be updated
+    Wait,
+    /// the rollout is over
+    Done(RolloutStatus),
+}
+
+/// Decide the next step of a rollout from the states of its instances, in rollout order.
+/// Once an instance failed no more instances are dispatched, the rollout halts when the
+/// instances which were already dispatched are done.
+pub fn next_step(policy: RolloutPolicy, states: &[InstanceState]) -> Step {
+    let dispatched = states.contains(&InstanceState::Dispatched);
+    if states.contains(&InstanceState::Failed) {
+        return match dispatched {
+            true => Step::Wait,
+            false => Step::Done(RolloutStatus::Halted),
+        };
+    }
+    if dispatched {
+        return Step::Wait;
+    }
+    let pending: Vec<usize> = states
+        .iter()
+        .enumerate()
+        .filter(|(_, state)| **state == InstanceState::Pending)
+        .map(|(position, _)| position)
+        .collect();
+    match (policy, pending.first()) {
+        (_, None) => Step::Done(RolloutStatus::Completed),
+        (RolloutPolicy::Sequential, Some(&position)) => Step::Dispatch(vec![position]),
+        // The canary is the first instance, it is the only one pending before it succeeded
+        (RolloutPolicy::CanaryFirst, Some(0)) => Step::Dispatch(vec![0]),
+        (RolloutPolicy::CanaryFirst, Some(_)) => Step::Dispatch(pending),
+    }
+}
+
+/// Summarize the states of the instances of a rollout for control plane
+pub fn progress(rollout: &Rollout, states: &[InstanceState], step: &Step) -> RolloutProgress {
+    let inst_ids = |wanted: &[InstanceState]| {
+        rollout
+            .instances
+            .iter()
+            .zip(states)
+            .filter(|(_, state)| wanted.contains(state))
+            .map(|(instance, _)| instance.inst_id.clone())
+            .collect()
+    };
+    RolloutProgress {
+        rollout_id: rollout.rollout_id.clone(),
+        status: match step {
+            Step::Done(status) => *status,
+            _ => RolloutStatus::InProgress,
+        },
+        total: rollout.instances.len(),
+        succeeded: inst_ids(&[InstanceState::Succeeded]),
+        failed: inst_ids(&[InstanceState::Failed]),
+        in_progress: inst_ids(&[InstanceState::Dispatched]),
+    }
+}
+
+/// Record the instances of a rollout the first time it is processed, and return their
+/// states in rollout order
+pub async fn rollout_states(
+    db_pool: &PgPool,
+    rollout: &Rollout,
+) -> Result<Vec<InstanceState>, ConductorError> {
+    for (position, instance) in rollout.instances.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO rollout_instances (rollout_id, position, inst_id, namespace) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (rollout_id, inst_id) DO NOTHING",
+        )
+        .bind(&rollout.rollout_id)
+        .bind(position as i32)
+        .bind(&instance.inst_id)
+        .bind(&instance.namespace)
+        .execute(db_pool)
+        .await
+        .map_err(|e| ConductorError::RolloutError(e.to_string()))?;
+    }
+
+    let rows = sqlx::query(
+        "SELECT inst_id, state FROM rollout_instances WHERE rollout_id = $1 ORDER BY position",
+    )
+    .bind(&rollout.rollout_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| ConductorError::RolloutError(e.to_string()))?;
+
+    rollout
+        .instances
+        .iter()
+        .map(|instance| {
+            let row = rows
+                .iter()
+                .find(|row| row.get::<String, _>("inst_id") == instance.inst_id)
+                .ok_or_else(|| {
+                    ConductorError::RolloutError(format!(
+                        "instance {} of rollout {} not found",
+                        instance.inst_id, rollout.rollout_id
+                    ))
+                })?;
+            InstanceState::parse(row.get("state"))
+        })
+        .collect()
+}
+
+/// Record that an Update event was sent for an instance of a rollout
+pub async fn mark_dispatched(
+    db_pool: &PgPool,
+    rollout_id: &str,
+    inst_id: &str,
+    msg_id: i64,
+) -> Result<(), ConductorError> {
+    set_state(
+        db_pool,
+        rollout_id,
+        inst_id,
+        InstanceState::Dispatched,
+        Some(msg_id),
+        None,
+    )
+    .await
+}
+
+/// Record the outcome of the Update event of an instance of a rollout
+pub async fn record_result(
+    db_pool: &PgPool,
+    rollout_id: &str,
+    inst_id: &str,
+    error: Option<&str>,
+) -> Result<(), ConductorError> {
+    let state = match error {
+        Some(_) => InstanceState::Failed,
+        None => InstanceState::Succeeded,
+    };
+    set_state(db_pool, rollout_id, inst_id, state, None, error).await
+}
+
+async fn set_state(
+    db_pool: &PgPool,
+    rollout_id: &str,
+    inst_id: &str,
+    state: InstanceState,
+    msg_id: Option<i64>,
+    error: Option<&str>,
+) -> Result<(), ConductorError> {
+    sqlx::query(
+        "UPDATE rollout_instances SET state = $3, msg_id = coalesce($4, msg_id), error = $5, \
+         updated_at = CURRENT_TIMESTAMP WHERE rollout_id = $1 AND inst_id = $2",
+    )
+    .bind(rollout_id)
+    .bind(inst_id)
+    .bind(state.as_str())
+    .bind(msg_id)
+    .bind(error)
+    .execute(db_pool)
+    .await
+    .map_err(|e| ConductorError::RolloutError(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InstanceState::*;
+
+    #[test]
+    fn test_next_step_sequential() {
+        let policy = RolloutPolicy::Sequential;
+        assert_eq!(
+            next_step(policy, &[Pending, Pending, Pending]),
+            Step::Dispatch(vec![0])
+        );
+        assert_eq!(
+            next_step(policy, &[Dispatched, Pending, Pending]),
+            Step::Wait
+        );
+        assert_eq!(
+            next_step(policy, &[Succeeded, Pending, Pending]),
+            Step::Dispatch(vec![1])
+        );
+        assert_eq!(
+            next_step(policy, &[Succeeded, Succeeded, Succeeded]),
+            Step::Done(RolloutStatus::Completed)
+        );
+        assert_eq!(
+            next_step(policy, &[Succeeded, Failed, Pending]),
+            Step::Done(RolloutStatus::Halted)
+        );
+    }
+
+    #[test]
+    fn test_next_step_canary_first() {
+        let policy = RolloutPolicy::CanaryFirst;
+        assert_eq!(
+            next_step(policy, &[Pending, Pending, Pending]),
+            Step::Dispatch(vec![0])
+        );
+        assert_eq!(
+            next_step(policy, &[Dispatched, Pending, Pending]),
+            Step::Wait
+        );
+        assert_eq!(
+            next_step(policy, &[Succeeded, Pending, Pending]),
+            Step::Dispatch(vec![1, 2])
+        );
+        assert_eq!(
+            next_step(policy, &[Failed, Pending, Pending]),
+            Step::Done(RolloutStatus::Halted)
+        );
+        // Instances which were dispatched with the one that failed are waited for
+        assert_eq!(
+            next_step(policy, &[Succeeded, Failed, Dispatched]),
+            Step::Wait
+        );
+        assert_eq!(
+            next_step(policy, &[Succeeded, Failed, Succeeded]),
+            Step::Done(RolloutStatus::Halted)
+        );
+    }
+}
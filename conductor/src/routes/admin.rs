@@ -0,0 +1,196 @@
+use crate::queue_admin::{archive_message, list_messages, queue_stats, requeue_message};
+use actix_web::{dev::Payload, error, get, post, web, FromRequest, HttpRequest, HttpResponse};
+use log::{error, info};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::future::{ready, Ready};
+
+/// Settings of the admin routes, they are only served when a token is configured
+#[derive(Clone)]
+pub struct AdminConfig {
+    pub token: String,
+    /// The queues which can be inspected and managed
+    pub queues: Vec<String>,
+}
+
+/// Extracting this from a request checks it has the admin token as a bearer token
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(config) = req.app_data::<web::Data<AdminConfig>>() else {
+            return ready(Err(error::ErrorNotFound("Not found.")));
+        };
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if constant_time_eq(token.as_bytes(), config.token.as_bytes()) => {
+                ready(Ok(Admin))
+            }
+            _ => ready(Err(error::ErrorUnauthorized("Invalid admin token."))),
+        }
+    }
+}
+
+// Compare tokens without returning early, so the time taken does not leak how much matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn find_queue<'a>(config: &'a AdminConfig, queue_name: &str) -> Option<&'a str> {
+    config
+        .queues
+        .iter()
+        .find(|queue| queue.as_str() == queue_name)
+        .map(String::as_str)
+}
+
+#[get("")]
+pub async fn get_queues(
+    _: Admin,
+    config: web::Data<AdminConfig>,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let mut stats = Vec::with_capacity(config.queues.len());
+    for queue_name in &config.queues {
+        match queue_stats(&db_pool, queue_name).await {
+            Ok(queue) => stats.push(queue),
+            Err(e) => {
+                error!("Failed to get stats of queue {}: {}", queue_name, e);
+                return HttpResponse::InternalServerError().body("Failed to get queue stats.");
+            }
+        }
+    }
+    HttpResponse::Ok().json(stats)
+}
+
+#[derive(Deserialize)]
+pub struct MessagesParams {
+    namespace: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+#[get("/{queue_name}/messages")]
+pub async fn get_messages(
+    _: Admin,
+    config: web::Data<AdminConfig>,
+    db_pool: web::Data<PgPool>,
+    queue_name: web::Path<String>,
+    params: web::Query<MessagesParams>,
+) -> HttpResponse {
+    let Some(queue_name) = find_queue(&config, &queue_name) else {
+        return HttpResponse::NotFound().body(format!("Queue {} not found.", queue_name));
+    };
+    let limit = params.limit.clamp(1, 1000);
+    match list_messages(&db_pool, queue_name, params.namespace.as_deref(), limit).await {
+        Ok(messages) => HttpResponse::Ok().json(messages),
+        Err(e) => {
+            error!("Failed to list messages of queue {}: {}", queue_name, e);
+            HttpResponse::InternalServerError().body("Failed to list messages.")
+        }
+    }
+}
+
+#[post("/{queue_name}/messages/{msg_id}/archive")]
+pub async fn archive(
+    _: Admin,
+    config: web::Data<AdminConfig>,
+    db_pool: web::Data<PgPool>,
+    path: web::Path<(String, i64)>,
+) -> HttpResponse {
+    let (queue_name, msg_id) = path.into_inner();
+    let Some(queue_name) = find_queue(&config, &queue_name) else {
+        return HttpResponse::NotFound().body(format!("Queue {} not found.", queue_name));
+    };
+    match archive_message(&db_pool, queue_name, msg_id).await {
+        Ok(true) => {
+            info!("Archived message {} of queue {}", msg_id, queue_name);
+            HttpResponse::Ok().json(serde_json::json!({ "msg_id": msg_id }))
+        }
+        Ok(false) => HttpResponse::NotFound().body(format!(
+            "Message {} not found in queue {}.",
+            msg_id, queue_name
+        )),
+        Err(e) => {
+            error!("Failed to archive message {}: {}", msg_id, e);
+            HttpResponse::InternalServerError().body("Failed to archive message.")
+        }
+    }
+}
+
+#[post("/{queue_name}/messages/{msg_id}/requeue")]
+pub async fn requeue(
+    _: Admin,
+    config: web::Data<AdminConfig>,
+    db_pool: web::Data<PgPool>,
+    path: web::Path<(String, i64)>,
+) -> HttpResponse {
+    let (queue_name, msg_id) = path.into_inner();
+    let Some(queue_name) = find_queue(&config, &queue_name) else {
+        return HttpResponse::NotFound().body(format!("Queue {} not found.", queue_name));
+    };
+    match requeue_message(&db_pool, queue_name, msg_id).await {
+        Ok(true) => {
+            info!("Requeued message {} of queue {}", msg_id, queue_name);
+            HttpResponse::Ok().json(serde_json::json!({ "msg_id": msg_id }))
+        }
+        Ok(false) => HttpResponse::NotFound().body(format!(
+            "Message {} not found in queue {}.",
+            msg_id, queue_name
+        )),
+        Err(e) => {
+            error!("Failed to requeue message {}: {}", msg_id, e);
+            HttpResponse::InternalServerError().body("Failed to requeue message.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn config() -> AdminConfig {
+        AdminConfig {
+            token: "secret".to_string(),
+            queues: vec!["saas_queue".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_token() {
+        let req = TestRequest::default()
+            .app_data(web::Data::new(config()))
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_http_request();
+        assert!(Admin::extract(&req).await.is_ok());
+
+        for header in ["Bearer secre", "Bearer secrets", "secret"] {
+            let req = TestRequest::default()
+                .app_data(web::Data::new(config()))
+                .insert_header(("Authorization", header))
+                .to_http_request();
+            assert!(Admin::extract(&req).await.is_err());
+        }
+
+        let req = TestRequest::default().to_http_request();
+        assert!(Admin::extract(&req).await.is_err());
+    }
+
+    #[test]
+    fn test_find_queue() {
+        assert_eq!(find_queue(&config(), "saas_queue"), Some("saas_queue"));
+        assert_eq!(find_queue(&config(), "other_queue"), None);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::audit_log::{list_processed, ProcessedEventsQuery};
+use crate::routes::admin::Admin;
+use actix_web::{get, web, HttpResponse, Responder};
+use log::error;
+use sqlx::PgPool;
+
+#[get("")]
+pub async fn get_processed_events(
+    _: Admin,
+    db_pool: web::Data<PgPool>,
+    query: web::Query<ProcessedEventsQuery>,
+) -> impl Responder {
+    match list_processed(&db_pool, &query).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => {
+            error!("Failed to list processed events: {}", e);
+            HttpResponse::InternalServerError().body("Failed to list processed events.")
+        }
+    }
+}
+
+#[get("/instances/{inst_id}")]
+pub async fn get_instance_events(
+    _: Admin,
+    db_pool: web::Data<PgPool>,
+    inst_id: web::Path<String>,
+    query: web::Query<ProcessedEventsQuery>,
+) -> impl Responder {
+    let query = ProcessedEventsQuery {
+        inst_id: Some(inst_id.into_inner()),
+        ..query.into_inner()
+    };
+    match list_processed(&db_pool, &query).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => {
+            error!("Failed to list processed events: {}", e);
+            HttpResponse::InternalServerError().body("Failed to list processed events.")
+        }
+    }
+}
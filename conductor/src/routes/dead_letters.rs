@@ -0,0 +1,41 @@
+use crate::dead_letter::{list_dead_letters, replay_dead_letter};
+use crate::routes::admin::Admin;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use log::error;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    #[serde(default)]
+    include_replayed: bool,
+}
+
+#[get("")]
+pub async fn get_dead_letters(
+    _: Admin,
+    db_pool: web::Data<PgPool>,
+    params: web::Query<ListParams>,
+) -> impl Responder {
+    match list_dead_letters(&db_pool, params.include_replayed).await {
+        Ok(dead_letters) => HttpResponse::Ok().json(dead_letters),
+        Err(e) => {
+            error!("Failed to list dead letters: {}", e);
+            HttpResponse::InternalServerError().body("Failed to list dead letters.")
+        }
+    }
+}
+
+#[post("/{id}/replay")]
+pub async fn replay(_: Admin, db_pool: web::Data<PgPool>, id: web::Path<i64>) -> impl Responder {
+    let id = id.into_inner();
+    match replay_dead_letter(&db_pool, id).await {
+        Ok(Some(msg_id)) => HttpResponse::Ok().json(serde_json::json!({ "msg_id": msg_id })),
+        Ok(None) => HttpResponse::NotFound()
+            .body(format!("Dead letter {} not found or already replayed.", id)),
+        Err(e) => {
+            error!("Failed to replay dead letter {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to replay dead letter.")
+        }
+    }
+}
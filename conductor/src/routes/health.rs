@@ -1,9 +1,11 @@
+use crate::heartbeat::LoopHeartbeat;
 use actix_web::{get, web, HttpResponse, Responder};
 use std::sync::{Arc, Mutex};
 
 #[get("/lively")]
 pub async fn background_threads_running(
     background_threads: web::Data<Arc<Mutex<Vec<tokio::task::JoinHandle<()>>>>>,
+    heartbeat: Option<web::Data<LoopHeartbeat>>,
 ) -> impl Responder {
     let background_threads = match background_threads.lock() {
         Ok(threads) => threads,
@@ -18,5 +20,14 @@ pub async fn background_threads_running(
                 .body("One or more background tasks are not running.");
         }
     }
+    // Only set when the conductor loop runs in this pod
+    if let Some(heartbeat) = heartbeat {
+        if heartbeat.is_stuck() {
+            return HttpResponse::InternalServerError().body(format!(
+                "The conductor loop made no progress for {} seconds.",
+                heartbeat.lag().as_secs()
+            ));
+        }
+    }
     HttpResponse::Ok().json("ok")
 }
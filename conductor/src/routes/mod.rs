@@ -1 +1,5 @@
+pub mod admin;
+pub mod audit_log;
+pub mod dead_letters;
 pub mod health;
+pub mod quarantine;
@@ -0,0 +1,47 @@
+use crate::quarantine::{list_quarantined, replay_quarantined};
+use crate::routes::admin::Admin;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use log::error;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    #[serde(default)]
+    include_replayed: bool,
+}
+
+#[get("")]
+pub async fn get_quarantined(
+    _: Admin,
+    db_pool: web::Data<PgPool>,
+    params: web::Query<ListParams>,
+) -> impl Responder {
+    match list_quarantined(&db_pool, params.include_replayed).await {
+        Ok(messages) => HttpResponse::Ok().json(messages),
+        Err(e) => {
+            error!("Failed to list quarantined messages: {}", e);
+            HttpResponse::InternalServerError().body("Failed to list quarantined messages.")
+        }
+    }
+}
+
+#[post("/{id}/replay")]
+pub async fn replay_quarantined_message(
+    _: Admin,
+    db_pool: web::Data<PgPool>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let id = id.into_inner();
+    match replay_quarantined(&db_pool, id).await {
+        Ok(Some(msg_id)) => HttpResponse::Ok().json(serde_json::json!({ "msg_id": msg_id })),
+        Ok(None) => HttpResponse::NotFound().body(format!(
+            "Quarantined message {} not found or already replayed.",
+            id
+        )),
+        Err(e) => {
+            error!("Failed to replay quarantined message {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to replay quarantined message.")
+        }
+    }
+}
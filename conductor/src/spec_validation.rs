@@ -0,0 +1,144 @@
+use crate::types::FieldError;
+use controller::apis::coredb_types::{CoreDB, CoreDBSpec};
+use controller::apis::postgres_parameters::invalid_pg_configs;
+use controller::postgres_versions::postgres_version_of;
+use controller::ttl::parse_ttl;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+const QUANTITY_SUFFIXES: [&str; 14] = [
+    "Ki", "Mi", "Gi", "Ti", "Pi", "Ei", "k", "M", "G", "T", "P", "E", "m", "",
+];
+
+fn field_error(field: &str, message: impl Into<String>) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        message: message.into(),
+    }
+}
+
+// A Kubernetes object name, as required for the CoreDB and its Cluster
+fn is_dns_label(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+// A positive Kubernetes quantity like 10Gi
+fn is_positive_quantity(quantity: &str) -> bool {
+    QUANTITY_SUFFIXES.iter().any(|suffix| {
+        quantity
+            .strip_suffix(suffix)
+            .and_then(|number| number.parse::<f64>().ok())
+            .is_some_and(|number| number.is_finite() && number > 0.0)
+    })
+}
+
+/// Validate a generated CoreDB before it is applied, against the CRD schema and the
+/// constraints of the operator. Returns an error for each invalid field, empty when the
+/// CoreDB is valid.
+pub fn validate_coredb(coredb: &Value) -> Vec<FieldError> {
+    let mut errors = vec![];
+
+    let name = coredb["metadata"]["name"].as_str().unwrap_or_default();
+    if !is_dns_label(name) {
+        errors.push(field_error(
+            "metadata.name",
+            format!("{:?} is not a valid DNS label", name),
+        ));
+    }
+
+    let spec: CoreDBSpec = match serde_json::from_value(coredb["spec"].clone()) {
+        Ok(spec) => spec,
+        Err(err) => {
+            errors.push(field_error("spec", err.to_string()));
+            return errors;
+        }
+    };
+
+    if spec.replicas < 0 {
+        errors.push(field_error("spec.replicas", "must not be negative"));
+    }
+    if !is_positive_quantity(&spec.storage.0) {
+        errors.push(field_error(
+            "spec.storage",
+            format!("{} is not a positive quantity", spec.storage.0),
+        ));
+    }
+    if let Some(ttl) = &spec.ttl {
+        if let Err(err) = parse_ttl(ttl) {
+            errors.push(field_error("spec.ttl", err));
+        }
+    }
+
+    let mut app_services = BTreeSet::new();
+    for (i, appsvc) in spec.app_services.iter().flatten().enumerate() {
+        if !app_services.insert(appsvc.name.as_str()) {
+            errors.push(field_error(
+                &format!("spec.appServices[{}].name", i),
+                format!("{} is used by another appService", appsvc.name),
+            ));
+        }
+    }
+
+    let major = postgres_version_of(&CoreDB::new(name, spec.clone())).map(|v| v.major);
+    for invalid in invalid_pg_configs(spec.runtime_config.as_deref().unwrap_or_default(), major) {
+        errors.push(field_error(
+            &format!("spec.runtime_config[{}]", invalid.name),
+            format!("invalid value {}: {}", invalid.value, invalid.reason),
+        ));
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_coredb() {
+        let valid = serde_json::json!({
+            "metadata": {"name": "org-acme-inst-db"},
+            "spec": {"storage": "10Gi", "replicas": 1, "ttl": "7d"},
+        });
+        assert_eq!(validate_coredb(&valid), vec![]);
+
+        let invalid = serde_json::json!({
+            "metadata": {"name": "Org_Acme"},
+            "spec": {
+                "storage": "0Gi",
+                "replicas": -1,
+                "ttl": "soon",
+                "appServices": [
+                    {"name": "api", "image": "postgrest/postgrest"},
+                    {"name": "api", "image": "postgrest/postgrest"},
+                ],
+            },
+        });
+        let fields: Vec<String> = validate_coredb(&invalid)
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "metadata.name",
+                "spec.replicas",
+                "spec.storage",
+                "spec.ttl",
+                "spec.appServices[1].name",
+            ]
+        );
+
+        let malformed = serde_json::json!({
+            "metadata": {"name": "org-acme-inst-db"},
+            "spec": {"replicas": "two"},
+        });
+        assert_eq!(validate_coredb(&malformed)[0].field, "spec");
+    }
+}
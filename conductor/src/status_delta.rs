@@ -0,0 +1,144 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A JSON merge patch (RFC 7386) which turns `old` into `new`, None when they are equal.
+/// Fields removed from `new` are null in the patch.
+pub fn merge_patch_diff(old: &Value, new: &Value) -> Option<Value> {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut patch = Map::new();
+            for (key, new_value) in new {
+                let diff = match old.get(key) {
+                    Some(old_value) => merge_patch_diff(old_value, new_value),
+                    None => Some(new_value.clone()),
+                };
+                if let Some(diff) = diff {
+                    patch.insert(key.clone(), diff);
+                }
+            }
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                patch.insert(key.clone(), Value::Null);
+            }
+            (!patch.is_empty()).then_some(Value::Object(patch))
+        }
+        (old, new) if old == new => None,
+        (_, new) => Some(new.clone()),
+    }
+}
+
+/// How the state of an instance is reported to control plane
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusReport {
+    /// The full state, the first time an instance is reported and on each full sync
+    Full,
+    /// A merge patch of the state last reported
+    Delta(Value),
+    /// The state did not change since it was last reported
+    Unchanged,
+}
+
+struct LastReport {
+    state: Value,
+    full_sync_at: Instant,
+}
+
+/// The state last reported to control plane for each instance, to report only what changed.
+/// The full state of an instance is reported again once `full_sync_interval` passed since it
+/// was last reported in full, so control plane recovers from missed deltas.
+pub struct StatusTracker {
+    full_sync_interval: Duration,
+    last_reports: HashMap<String, LastReport>,
+}
+
+impl StatusTracker {
+    /// A zero `full_sync_interval` reports the full state every time
+    pub fn new(full_sync_interval: Duration) -> Self {
+        StatusTracker {
+            full_sync_interval,
+            last_reports: HashMap::new(),
+        }
+    }
+
+    /// How to report the state of the instance in `namespace`
+    pub fn report(&self, namespace: &str, state: &Value, now: Instant) -> StatusReport {
+        if self.full_sync_interval.is_zero() {
+            return StatusReport::Full;
+        }
+        match self.last_reports.get(namespace) {
+            Some(last) if now.duration_since(last.full_sync_at) < self.full_sync_interval => {
+                match merge_patch_diff(&last.state, state) {
+                    Some(delta) => StatusReport::Delta(delta),
+                    None => StatusReport::Unchanged,
+                }
+            }
+            _ => StatusReport::Full,
+        }
+    }
+
+    /// Record the state which was reported for the instance in `namespace`
+    pub fn reported(&mut self, namespace: &str, state: Value, full: bool, now: Instant) {
+        if self.full_sync_interval.is_zero() {
+            return;
+        }
+        let full_sync_at = match self.last_reports.get(namespace) {
+            Some(last) if !full => last.full_sync_at,
+            _ => now,
+        };
+        self.last_reports.insert(
+            namespace.to_string(),
+            LastReport {
+                state,
+                full_sync_at,
+            },
+        );
+    }
+
+    /// Forget a deleted instance
+    pub fn forget(&mut self, namespace: &str) {
+        self.last_reports.remove(namespace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch_diff() {
+        let old = json!({"status": {"running": false, "extensions": ["pgmq"], "storage": "10Gi"}});
+        let new = json!({"status": {"running": true, "extensions": ["pgmq"]}});
+        assert_eq!(
+            merge_patch_diff(&old, &new),
+            Some(json!({"status": {"running": true, "storage": null}}))
+        );
+        assert_eq!(merge_patch_diff(&new, &new), None);
+    }
+
+    #[test]
+    fn test_status_tracker() {
+        let now = Instant::now();
+        let mut tracker = StatusTracker::new(Duration::from_secs(3600));
+        let state = json!({"status": {"running": false}});
+        assert_eq!(tracker.report("ns", &state, now), StatusReport::Full);
+        tracker.reported("ns", state.clone(), true, now);
+        assert_eq!(tracker.report("ns", &state, now), StatusReport::Unchanged);
+
+        let running = json!({"status": {"running": true}});
+        assert_eq!(
+            tracker.report("ns", &running, now),
+            StatusReport::Delta(json!({"status": {"running": true}}))
+        );
+        tracker.reported("ns", running.clone(), false, now);
+
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(tracker.report("ns", &running, later), StatusReport::Full);
+
+        tracker.forget("ns");
+        assert_eq!(tracker.report("ns", &running, now), StatusReport::Full);
+
+        let tracker = StatusTracker::new(Duration::ZERO);
+        assert_eq!(tracker.report("ns", &state, now), StatusReport::Full);
+    }
+}
@@ -1,18 +1,26 @@
+use conductor::cache::ResourceCache;
+use conductor::connection_encryption::{encrypt_connection, ConnectionEncryptor};
 use conductor::errors::ConductorError;
 use controller::apis::coredb_types::CoreDB;
 use futures::TryStreamExt;
 use kube::runtime::{watcher, WatchStreamExt};
-use kube::{Api, Client};
-use log::{error, info, warn};
+use kube::Api;
+use log::{debug, error, info, warn};
 use pgmq::PGMQueueExt;
 use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use conductor::monitoring::CustomMetrics;
+use conductor::status_delta::{StatusReport, StatusTracker};
 use conductor::types::Event;
 use conductor::{get_data_plane_id_from_coredb, get_org_inst_id, get_pg_conn, types};
 
+use crate::from_env_default;
+
 pub async fn run_status_reporter(
     _metrics: CustomMetrics,
+    cache: ResourceCache,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Move to config
     let pg_conn_url =
@@ -20,15 +28,25 @@ pub async fn run_status_reporter(
     // Connect to pgmq
     let queue = PGMQueueExt::new(pg_conn_url.clone(), 1).await?;
 
+    // When set, only the changes to the state of an instance are reported, and its full state
+    // is reported again once this many seconds passed since it was last reported in full
+    let full_sync_interval_secs: u64 =
+        from_env_default("STATUS_FULL_SYNC_INTERVAL_SECONDS", "0").parse()?;
+    let tracker = Arc::new(Mutex::new(StatusTracker::new(Duration::from_secs(
+        full_sync_interval_secs,
+    ))));
+    let encryptor = ConnectionEncryptor::from_env()?;
+
     // Get a kubernetes watcher on all changes in coredb resources
-    let client = Client::try_default().await?;
-    let coredb_api: Api<CoreDB> = Api::all(client.clone());
+    let coredb_api: Api<CoreDB> = Api::all(cache.client());
 
     watcher(coredb_api, watcher::Config::default())
         .applied_objects()
         .try_for_each(move |coredb| {
-            let client = client.clone();
+            let cache = cache.clone();
             let queue = queue.clone();
+            let tracker = tracker.clone();
+            let encryptor = encryptor.clone();
             async move {
                 info!(
                     "Detected change in coredb: {}",
@@ -38,7 +56,8 @@ pub async fn run_status_reporter(
                         .as_ref()
                         .expect("CoreDB should always have a name")
                 );
-                match send_status_update(client, &queue, coredb).await {
+                match send_status_update(&cache, &queue, &tracker, encryptor.as_ref(), coredb).await
+                {
                     Ok(_) => {}
                     Err(e) => {
                         error!("Error sending status update: {}", e);
@@ -54,8 +73,10 @@ pub async fn run_status_reporter(
 // Used for sending ad-hoc status updates to the control plane.
 // This can be triggered when a change in a CoreDB's status is detected.
 async fn send_status_update(
-    client: Client,
+    cache: &ResourceCache,
     response_queue: &PGMQueueExt,
+    tracker: &Mutex<StatusTracker>,
+    encryptor: Option<&ConnectionEncryptor>,
     coredb: CoreDB,
 ) -> Result<(), ConductorError> {
     let coredb_name = &coredb
@@ -68,6 +89,12 @@ async fn send_status_update(
         .namespace
         .as_ref()
         .expect("CoreDB should always have a namespace");
+    if coredb.metadata.deletion_timestamp.is_some() {
+        tracker
+            .lock()
+            .expect("status tracker lock")
+            .forget(namespace);
+    }
     // Could be nice to move these env reads into a config struct
     let data_plane_basedomain = match env::var("DATA_PLANE_BASEDOMAIN") {
         Ok(domain) => domain,
@@ -99,7 +126,7 @@ async fn send_status_update(
         }
     };
 
-    let conn_info = match get_pg_conn(client, namespace, &data_plane_basedomain, &coredb.spec).await
+    let conn_info = match get_pg_conn(cache, namespace, &data_plane_basedomain, &coredb.spec).await
     {
         Ok(conn_info) => conn_info,
         Err(_) => {
@@ -107,21 +134,65 @@ async fn send_status_update(
             return Ok(());
         }
     };
-    let response = types::StateToControlPlane {
+    let state = serde_json::json!({
+        "spec": coredb.spec,
+        "status": coredb.status,
+        "connection": conn_info,
+    });
+    let now = Instant::now();
+    let report = tracker
+        .lock()
+        .expect("status tracker lock")
+        .report(namespace, &state, now);
+    let mut response = types::StateToControlPlane {
+        version: types::EVENT_SCHEMA_VERSION,
         data_plane_id,
         org_id: org_inst.org_id.clone(),
         inst_id: org_inst.inst_id.clone(),
         event_type: Event::Updated,
         spec: Some(coredb.spec.clone()),
         status: coredb.status.clone(),
-        connection: Some(conn_info),
+        connection: Some(encrypt_connection(encryptor, conn_info)?),
+        error: None,
+        rollout: None,
+        delta: None,
+    };
+    let full = match report {
+        StatusReport::Full => true,
+        StatusReport::Delta(mut delta) => {
+            // The delta is computed on the plaintext connection info, changes to it are
+            // reported with the encrypted connection info as a whole
+            if let Some(connection) = delta.get_mut("connection") {
+                *connection = serde_json::to_value(&response.connection)?;
+            }
+            response.event_type = Event::StatusDelta;
+            response.spec = None;
+            response.status = None;
+            response.connection = None;
+            response.delta = Some(delta);
+            false
+        }
+        StatusReport::Unchanged => {
+            debug!(
+                "{}.{}: State did not change since it was last reported, skipping status update",
+                org_inst.org_id, org_inst.inst_id
+            );
+            return Ok(());
+        }
     };
     let msg_id = response_queue
         .send(&data_plane_events_queue, &response)
         .await?;
+    tracker
+        .lock()
+        .expect("status tracker lock")
+        .reported(namespace, state, full, now);
     info!(
-        "{}.{}: Sent ad hoc update to control plane, message_id: {}",
-        org_inst.org_id, org_inst.inst_id, msg_id
+        "{}.{}: Sent ad hoc {} to control plane, message_id: {}",
+        org_inst.org_id,
+        org_inst.inst_id,
+        if full { "update" } else { "status delta" },
+        msg_id
     );
     Ok(())
 }
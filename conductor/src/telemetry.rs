@@ -0,0 +1,118 @@
+use crate::types::CRUDevent;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::trace::{FutureExt, TraceContextExt, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+
+const TRACER_NAME: &str = "conductor";
+
+/// Export spans to the OpenTelemetry collector at OPENTELEMETRY_ENDPOINT_URL. Spans are not
+/// exported when conductor is built without the telemetry feature.
+#[cfg(feature = "telemetry")]
+pub fn init_tracer() {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let otlp_endpoint = std::env::var("OPENTELEMETRY_ENDPOINT_URL")
+        .expect("Need a otel tracing collector configured");
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(otlp_endpoint),
+        )
+        .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
+            opentelemetry::sdk::Resource::new(vec![KeyValue::new("service.name", "conductor")]),
+        ))
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)
+        .expect("Failed to install the OpenTelemetry trace pipeline");
+}
+
+/// The W3C trace context of the current span, to propagate it to the events conductor sends
+pub fn current_trace_context() -> HashMap<String, String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Context::current(), &mut carrier);
+    carrier
+}
+
+/// Start the span of processing an event, child of the span control plane sent the event in
+/// when the event carries its trace context. Stages of processing the event run with the
+/// returned context to be part of the same trace.
+pub fn event_span(event: &CRUDevent, msg_id: i64) -> Context {
+    let parent = match &event.trace_context {
+        Some(carrier) => TraceContextPropagator::new().extract(carrier),
+        None => Context::new(),
+    };
+    let span = global::tracer(TRACER_NAME).start_with_context("process_event", &parent);
+    let cx = parent.with_span(span);
+    let span = cx.span();
+    span.set_attribute(KeyValue::new("msg_id", msg_id));
+    span.set_attribute(KeyValue::new(
+        "event_type",
+        format!("{:?}", event.event_type),
+    ));
+    span.set_attribute(KeyValue::new("namespace", event.namespace.clone()));
+    span.set_attribute(KeyValue::new("org_id", event.org_id.clone()));
+    span.set_attribute(KeyValue::new("inst_id", event.inst_id.clone()));
+    cx
+}
+
+/// End the span of a context, recording the error the span ended with
+pub fn end_span<T, E: Display>(cx: &Context, result: &Result<T, E>) {
+    let span = cx.span();
+    if let Err(err) = result {
+        span.add_event(
+            "error",
+            vec![KeyValue::new("error.message", err.to_string())],
+        );
+    }
+    span.end();
+}
+
+/// Run a stage of processing an event in a span, child of the current span
+pub async fn in_span<T, E, F>(name: &'static str, stage: F) -> Result<T, E>
+where
+    E: Display,
+    F: Future<Output = Result<T, E>>,
+{
+    let parent = Context::current();
+    let span = global::tracer(TRACER_NAME).start_with_context(name, &parent);
+    let cx = parent.with_span(span);
+    let result = stage.with_context(cx.clone()).await;
+    end_span(&cx, &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn test_trace_context_roundtrip() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let _guard = Context::new()
+            .with_remote_span_context(span_context)
+            .attach();
+        let carrier = current_trace_context();
+        assert_eq!(
+            carrier.get("traceparent").map(String::as_str),
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+        );
+
+        let extracted = TraceContextPropagator::new().extract(&carrier);
+        assert_eq!(
+            extracted.span().span_context().trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+    }
+}
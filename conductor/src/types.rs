@@ -1,11 +1,26 @@
-use serde::{Deserialize, Serialize};
+use log::warn;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::types;
 use controller::apis::coredb_types::{CoreDBSpec, CoreDBStatus};
 
+/// The version of the CRUDevent and StateToControlPlane payloads, so control plane and
+/// conductor can be deployed independently. Payloads without a version are version 1, they
+/// were sent before payloads were versioned.
+pub const EVENT_SCHEMA_VERSION: u32 = 2;
+
+fn legacy_version() -> u32 {
+    1
+}
+
 /// incoming message from control plane
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(remote = "Self")]
 pub struct CRUDevent {
+    #[serde(default = "legacy_version")]
+    pub version: u32,
     pub data_plane_id: String,
     pub org_id: String,
     pub inst_id: String,
@@ -14,6 +29,166 @@ pub struct CRUDevent {
     pub backups_read_path: Option<String>,
     pub backups_write_path: Option<String>,
     pub spec: Option<CoreDBSpec>,
+    // only set on UpdateTags events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<InstanceTags>,
+    // only set on BatchUpdate events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<Rollout>,
+    // set on the Update events conductor sends for the instances of a rollout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout_id: Option<String>,
+    // set on Create, Update and Restore events of instances backing up to their own bucket
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_storage: Option<CustomBackupStorage>,
+    // set on Create and Restore events of instances backing up to another region or bucket than
+    // the ones of the data plane
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_location: Option<BackupLocation>,
+    // W3C trace context (traceparent, tracestate) of the span the event was sent in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_context: Option<HashMap<String, String>>,
+}
+
+// Upgrade a CRUDevent payload of an older version to the current one. Fields added since
+// version 1 are optional, payloads of newer versions are read as far as they are understood.
+fn upgrade_crud_event(mut payload: Value) -> Result<Value, String> {
+    let Value::Object(fields) = &mut payload else {
+        return Err("CRUDevent must be a JSON object".to_string());
+    };
+    let version = match fields.get("version") {
+        None | Some(Value::Null) => legacy_version(),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format!("invalid CRUDevent version {}", version))?,
+    };
+    if version > EVENT_SCHEMA_VERSION {
+        warn!(
+            "CRUDevent version {} is newer than {}, ignoring unknown fields",
+            version, EVENT_SCHEMA_VERSION
+        );
+        return Ok(payload);
+    }
+    // Version 1 to 2: the version field was introduced
+    fields.insert("version".to_string(), Value::from(EVENT_SCHEMA_VERSION));
+    Ok(payload)
+}
+
+impl<'de> Deserialize<'de> for CRUDevent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let payload =
+            upgrade_crud_event(Value::deserialize(deserializer)?).map_err(D::Error::custom)?;
+        CRUDevent::deserialize(payload).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for CRUDevent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CRUDevent::serialize(self, serializer)
+    }
+}
+
+/// metadata-only changes to an instance, like its billing tier or environment,
+/// applied to the labels and annotations of its namespace and CoreDB.
+/// A key set to null is removed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct InstanceTags {
+    #[serde(default)]
+    pub labels: BTreeMap<String, Option<String>>,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, Option<String>>,
+}
+
+/// the region and bucket of the data plane to store the backups of an instance in, instead of
+/// AWS_REGION and BACKUP_ARCHIVE_BUCKET
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BackupLocation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+}
+
+/// an S3 compatible bucket of the customer to store the backups of an instance in,
+/// instead of the backups bucket of the data plane
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct CustomBackupStorage {
+    pub endpoint_url: String,
+    pub bucket: String,
+    // path of the backups in the bucket, v2/<namespace> by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+// Events are logged, the secret access key is not
+impl std::fmt::Debug for CustomBackupStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomBackupStorage")
+            .field("endpoint_url", &self.endpoint_url)
+            .field("bucket", &self.bucket)
+            .field("path", &self.path)
+            .field("region", &self.region)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// spec changes to apply to several instances of an organization, in order.
+/// Conductor sends an Update event for each instance as the rollout progresses,
+/// and stops sending them once an instance failed to be updated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rollout {
+    // identifies the rollout, processing a BatchUpdate event again with the
+    // same id resumes the rollout where it stopped
+    pub rollout_id: String,
+    pub policy: RolloutPolicy,
+    pub instances: Vec<RolloutInstance>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RolloutPolicy {
+    /// instances are updated one at a time, in order
+    Sequential,
+    /// the first instance is updated alone, then all others at once
+    CanaryFirst,
+}
+
+/// an instance of a rollout and the spec it is updated to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RolloutInstance {
+    pub inst_id: String,
+    pub namespace: String,
+    pub backups_read_path: Option<String>,
+    pub backups_write_path: Option<String>,
+    pub spec: CoreDBSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_storage: Option<CustomBackupStorage>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RolloutStatus {
+    InProgress,
+    /// every instance was updated
+    Completed,
+    /// an instance failed to be updated, the instances after it were not updated
+    Halted,
+}
+
+/// aggregate progress of a rollout, reported to control plane
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RolloutProgress {
+    pub rollout_id: String,
+    pub status: RolloutStatus,
+    pub total: usize,
+    pub succeeded: Vec<String>,
+    pub failed: Vec<String>,
+    pub in_progress: Vec<String>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -33,12 +208,29 @@ pub enum Event {
     Started,
     Restore,
     Restored,
+    UpdateTags,
+    TagsUpdated,
+    Resize,
+    Resized,
+    Invalid,
+    DeletionScheduled,
+    Undelete,
+    Undeleted,
+    BatchUpdate,
+    RolloutProgress,
+    StatusDelta,
+    InsufficientCapacity,
+    Upgrade,
+    Upgraded,
+    UpgradeFailed,
 }
 
 /// message returned to control plane
 /// reports state of data plane
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StateToControlPlane {
+    #[serde(default = "legacy_version")]
+    pub version: u32,
     pub data_plane_id: String, // unique identifier for the data plane
     pub event_type: Event,
     pub org_id: String,
@@ -46,6 +238,56 @@ pub struct StateToControlPlane {
     pub spec: Option<CoreDBSpec>,
     pub status: Option<CoreDBStatus>,
     pub connection: Option<types::ConnectionInfo>,
+    // only set on Error events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetails>,
+    // only set on RolloutProgress events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<RolloutProgress>,
+    // only set on StatusDelta events, a JSON merge patch of the spec, status and connection
+    // last reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<Value>,
+}
+
+/// machine-readable classification of an error reported to control plane
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ErrorCode {
+    /// the data plane is missing permissions in the cloud provider
+    CloudPermissionError,
+    /// a cloud provider or Kubernetes quota was exceeded
+    QuotaExceeded,
+    /// the requested spec was rejected
+    InvalidSpec,
+    /// the operation did not complete in time
+    Timeout,
+    /// a CloudFormation stack of the instance ended in a failed status
+    CloudFormationFailed,
+    /// the nodes of the cluster do not have the resources the instance requests
+    InsufficientCapacity,
+    /// the namespace of the instance belongs to another instance
+    NamespaceCollision,
+    /// any other error
+    Internal,
+}
+
+/// details reported to control plane alongside an Error event
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ErrorDetails {
+    pub code: ErrorCode,
+    // whether the control plane can safely retry the event
+    pub retryable: bool,
+    pub message: String,
+    // only set on Invalid events, the fields of the spec which were rejected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+/// a field of the spec which was rejected, e.g. `spec.storage`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
 }
 
 #[derive(Debug)]
@@ -54,7 +296,7 @@ pub struct OrgInstId {
     pub inst_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionInfo {
     pub host: String,
     pub pooler_host: Option<String>,
@@ -63,4 +305,43 @@ pub struct ConnectionInfo {
     pub password: String,
     pub app_user: String,
     pub app_password: String,
+    // set when the passwords are encrypted with the public key of control plane, e.g.
+    // rsa-oaep-sha256
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crud_event_versions() {
+        let legacy = serde_json::json!({
+            "data_plane_id": "aws_data_1_use1",
+            "org_id": "org_acme",
+            "inst_id": "inst_db",
+            "event_type": "Delete",
+            "namespace": "org-acme-inst-db",
+            "backups_read_path": null,
+            "backups_write_path": null,
+            "spec": null,
+        });
+        let event: CRUDevent = serde_json::from_value(legacy.clone()).unwrap();
+        assert_eq!(event.version, EVENT_SCHEMA_VERSION);
+        assert_eq!(event.event_type, Event::Delete);
+
+        let serialized = serde_json::to_value(&event).unwrap();
+        assert_eq!(serialized["version"], EVENT_SCHEMA_VERSION);
+
+        let mut newer = legacy.clone();
+        newer["version"] = serde_json::json!(EVENT_SCHEMA_VERSION + 1);
+        newer["unknown_field"] = serde_json::json!("ignored");
+        let event: CRUDevent = serde_json::from_value(newer).unwrap();
+        assert_eq!(event.version, EVENT_SCHEMA_VERSION + 1);
+
+        let mut invalid = legacy;
+        invalid["version"] = serde_json::json!("two");
+        assert!(serde_json::from_value::<CRUDevent>(invalid).is_err());
+    }
 }
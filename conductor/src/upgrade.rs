@@ -0,0 +1,218 @@
+use crate::errors::ConductorError;
+use controller::apis::coredb_types::CoreDB;
+use controller::cloudnativepg::clusters::Cluster;
+use controller::defaults::parse_postgres_major_version;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client};
+use log::info;
+
+// The phase of a CNPG Cluster whose instances are all up and replicating
+const CLUSTER_HEALTHY_PHASE: &str = "Cluster in healthy state";
+
+/// How far the major version upgrade of an instance went
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpgradeProgress {
+    /// The CoreDB does not have the target version yet
+    Pending,
+    /// The operator is taking the pre-operation backup or upgrading the Cluster
+    InProgress,
+    /// The Cluster runs the target version and is healthy
+    Upgraded,
+    /// The Cluster reported a failure while it was upgraded
+    Failed(String),
+}
+
+/// The Postgres major version of an image, e.g. 16 for `quay.io/tembo/standard-cnpg:16.2.0-1`
+pub fn target_major_version(image: &str) -> Result<i32, ConductorError> {
+    parse_postgres_major_version(image)
+        .map_err(|e| ConductorError::UpgradeError(format!("{}: {}", image, e)))
+}
+
+/// Major versions are only upgraded, an image of an older major version than the one of the
+/// CoreDB is rejected
+pub fn check_upgrade(target_major: i32, coredb: &CoreDB) -> Result<(), ConductorError> {
+    match parse_postgres_major_version(&coredb.spec.image) {
+        Ok(current_major) if current_major > target_major => {
+            Err(ConductorError::UpgradeError(format!(
+                "downgrading from Postgres {} to {} is not supported",
+                current_major, target_major
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Derive the progress of an upgrade to the target major version from the CoreDB and the
+/// CNPG Cluster the operator manages for it
+pub fn upgrade_progress(
+    target_major: i32,
+    coredb: &CoreDB,
+    cluster: Option<&Cluster>,
+) -> UpgradeProgress {
+    if parse_postgres_major_version(&coredb.spec.image).ok() != Some(target_major) {
+        return UpgradeProgress::Pending;
+    }
+    let Some(cluster) = cluster else {
+        return UpgradeProgress::InProgress;
+    };
+    // The operator only changes the image of the Cluster once the pre-operation backup completed
+    let cluster_major = cluster
+        .spec
+        .image_name
+        .as_deref()
+        .and_then(|image| parse_postgres_major_version(image).ok());
+    if cluster_major != Some(target_major) {
+        return UpgradeProgress::InProgress;
+    }
+    let status = cluster.status.as_ref();
+    let phase = status.and_then(|s| s.phase.as_deref()).unwrap_or_default();
+    if phase.to_lowercase().contains("fail") {
+        let reason = status
+            .and_then(|s| s.phase_reason.as_deref())
+            .unwrap_or_default();
+        return UpgradeProgress::Failed(format!("{} {}", phase, reason).trim().to_owned());
+    }
+    let running = coredb.status.as_ref().is_some_and(|s| s.running);
+    if phase == CLUSTER_HEALTHY_PHASE && running {
+        UpgradeProgress::Upgraded
+    } else {
+        UpgradeProgress::InProgress
+    }
+}
+
+/// Set the image of the CoreDB of an instance to the one of the target version, the operator
+/// takes a backup and upgrades the Cluster once it sees the new major version
+pub async fn start_upgrade(
+    client: Client,
+    namespace: &str,
+    image: &str,
+) -> Result<CoreDB, ConductorError> {
+    let patch = Patch::Merge(serde_json::json!({
+        "spec": {
+            "image": image,
+        }
+    }));
+    // The CoreDB is named after its namespace
+    let coredb_api: Api<CoreDB> = Api::namespaced(client, namespace);
+    let coredb = coredb_api
+        .patch(namespace, &PatchParams::default(), &patch)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    info!("Upgrading CoreDB {} to {}", namespace, image);
+    Ok(coredb)
+}
+
+/// Get the progress of the upgrade of an instance to the target major version
+pub async fn get_upgrade_progress(
+    client: Client,
+    namespace: &str,
+    target_major: i32,
+) -> Result<(UpgradeProgress, CoreDB), ConductorError> {
+    let coredb_api: Api<CoreDB> = Api::namespaced(client.clone(), namespace);
+    let coredb = coredb_api
+        .get(namespace)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    check_upgrade(target_major, &coredb)?;
+    let cluster_api: Api<Cluster> = Api::namespaced(client, namespace);
+    let cluster = cluster_api
+        .get_opt(namespace)
+        .await
+        .map_err(ConductorError::KubeError)?;
+    let progress = upgrade_progress(target_major, &coredb, cluster.as_ref());
+    Ok((progress, coredb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use controller::apis::coredb_types::{CoreDBSpec, CoreDBStatus};
+    use controller::cloudnativepg::clusters::{ClusterSpec, ClusterStatus};
+
+    const PG15: &str = "quay.io/tembo/standard-cnpg:15.3.0-1-0c19c7e";
+    const PG16: &str = "quay.io/tembo/standard-cnpg:16.2.0-1-a6f1a8e";
+
+    fn coredb(image: &str, running: bool) -> CoreDB {
+        let mut coredb = CoreDB::new(
+            "org-acme-inst-db",
+            CoreDBSpec {
+                image: image.to_string(),
+                ..CoreDBSpec::default()
+            },
+        );
+        coredb.status = Some(CoreDBStatus {
+            running,
+            ..CoreDBStatus::default()
+        });
+        coredb
+    }
+
+    fn cluster(image: &str, phase: &str) -> Cluster {
+        let mut cluster = Cluster::new(
+            "org-acme-inst-db",
+            ClusterSpec {
+                image_name: Some(image.to_string()),
+                ..ClusterSpec::default()
+            },
+        );
+        cluster.status = Some(ClusterStatus {
+            phase: Some(phase.to_string()),
+            ..ClusterStatus::default()
+        });
+        cluster
+    }
+
+    #[test]
+    fn test_upgrade_progress() {
+        let healthy = CLUSTER_HEALTHY_PHASE;
+        assert_eq!(
+            upgrade_progress(16, &coredb(PG15, true), Some(&cluster(PG15, healthy))),
+            UpgradeProgress::Pending
+        );
+        // Waiting on the pre-operation backup
+        assert_eq!(
+            upgrade_progress(16, &coredb(PG16, true), Some(&cluster(PG15, healthy))),
+            UpgradeProgress::InProgress
+        );
+        assert_eq!(
+            upgrade_progress(16, &coredb(PG16, false), None),
+            UpgradeProgress::InProgress
+        );
+        assert_eq!(
+            upgrade_progress(
+                16,
+                &coredb(PG16, false),
+                Some(&cluster(PG16, "Upgrading Postgres major version"))
+            ),
+            UpgradeProgress::InProgress
+        );
+        assert_eq!(
+            upgrade_progress(16, &coredb(PG16, true), Some(&cluster(PG16, healthy))),
+            UpgradeProgress::Upgraded
+        );
+        assert_eq!(
+            upgrade_progress(
+                16,
+                &coredb(PG16, false),
+                Some(&cluster(PG16, "Major version upgrade failed"))
+            ),
+            UpgradeProgress::Failed("Major version upgrade failed".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_check_upgrade() {
+        assert!(check_upgrade(16, &coredb(PG15, true)).is_ok());
+        assert!(check_upgrade(16, &coredb(PG16, true)).is_ok());
+        assert!(matches!(
+            check_upgrade(15, &coredb(PG16, true)),
+            Err(ConductorError::UpgradeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_target_major_version() {
+        assert_eq!(target_major_version(PG16).unwrap(), 16);
+        assert!(target_major_version("postgres").is_err());
+    }
+}
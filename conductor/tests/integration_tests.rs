@@ -21,6 +21,7 @@ mod test {
     };
     use pgmq::{Message, PGMQueueExt};
 
+    use conductor::cache::ResourceCache;
     use conductor::get_coredb_error_without_status;
     use conductor::types::{self, StateToControlPlane};
     use controller::extensions::types::{Extension, ExtensionInstallLocation};
@@ -138,6 +139,7 @@ mod test {
                 name: "aggs_for_vecs".to_owned(),
                 description: Some("aggs_for_vecs extension".to_owned()),
                 locations: vec![install_location],
+                post_install_sql: None,
             }]),
             "storage": Some("1Gi".to_owned()),
             "replicas": Some(1),
@@ -148,12 +150,14 @@ mod test {
             "metrics": Some(PostgresMetrics{
                 queries: Some(query_config),
                 enabled: true,
-                image: "default-image-value".to_string()
+                image: "default-image-value".to_string(),
+                queries_config_map_ref: None,
             })
         });
         let mut spec: CoreDBSpec = serde_json::from_value(spec_js).unwrap();
 
         let msg = types::CRUDevent {
+            version: types::EVENT_SCHEMA_VERSION,
             namespace: namespace.clone(),
             backups_read_path: None,
             backups_write_path: None,
@@ -162,6 +166,12 @@ mod test {
             inst_id: "inst_02s4UKVbRy34SAYVSwZq2H".to_owned(),
             event_type: types::Event::Create,
             spec: Some(spec.clone()),
+            tags: None,
+            rollout: None,
+            rollout_id: None,
+            backup_storage: None,
+            backup_location: None,
+            trace_context: None,
         };
 
         // println!("Message: {:?}", msg);
@@ -247,12 +257,14 @@ mod test {
             name: "pg_jsonschema".to_owned(),
             description: Some("fake description".to_string()),
             locations: vec![install_location],
+            post_install_sql: None,
         });
         let num_expected_extensions = spec.extensions.len();
         // Get the current CoreDB spec
         let current_coredb = coredb_resource.clone();
         // println!("Updated spec: {:?}", spec.clone());
         let msg = types::CRUDevent {
+            version: types::EVENT_SCHEMA_VERSION,
             namespace: namespace.clone(),
             backups_read_path: None,
             backups_write_path: None,
@@ -261,6 +273,12 @@ mod test {
             inst_id: "inst_02s4UKVbRy34SAYVSwZq2H".to_owned(),
             event_type: types::Event::Update,
             spec: Some(spec.clone()),
+            tags: None,
+            rollout: None,
+            rollout_id: None,
+            backup_storage: None,
+            backup_location: None,
+            trace_context: None,
         };
         let msg_id = queue.send(&myqueue, &msg).await;
         println!("Update msg_id: {msg_id:?}");
@@ -325,6 +343,7 @@ mod test {
         // pod restarts correctly.
 
         let msg = types::CRUDevent {
+            version: types::EVENT_SCHEMA_VERSION,
             namespace: namespace.clone(),
             backups_read_path: None,
             backups_write_path: None,
@@ -333,6 +352,12 @@ mod test {
             inst_id: "inst_02s4UKVbRy34SAYVSwZq2H".to_owned(),
             event_type: types::Event::Restart,
             spec: Some(spec.clone()),
+            tags: None,
+            rollout: None,
+            rollout_id: None,
+            backup_storage: None,
+            backup_location: None,
+            trace_context: None,
         };
         let msg_id = queue.send(&myqueue, &msg).await;
         println!("Restart msg_id: {:?}", msg_id);
@@ -344,10 +369,12 @@ mod test {
                 panic!("CNPG pod did not restart after about 300 seconds");
             }
             thread::sleep(time::Duration::from_secs(10));
-            let current_coredb =
-                get_coredb_error_without_status(client.clone(), &namespace.clone())
-                    .await
-                    .unwrap();
+            let current_coredb = get_coredb_error_without_status(
+                &ResourceCache::new(client.clone()),
+                &namespace.clone(),
+            )
+            .await
+            .unwrap();
             if let Some(status) = current_coredb.status {
                 if status.running {
                     is_ready = true;
@@ -377,6 +404,7 @@ mod test {
 
         // delete the instance
         let msg = types::CRUDevent {
+            version: types::EVENT_SCHEMA_VERSION,
             namespace: namespace.clone(),
             backups_write_path: None,
             backups_read_path: None,
@@ -385,6 +413,12 @@ mod test {
             inst_id: "inst_02s4UKVbRy34SAYVSwZq2H".to_owned(),
             event_type: types::Event::Delete,
             spec: None,
+            tags: None,
+            rollout: None,
+            rollout_id: None,
+            backup_storage: None,
+            backup_location: None,
+            trace_context: None,
         };
         // println!("DELETE msg: {:?}", msg);
         let msg_id = queue.send(&myqueue, &msg).await;
@@ -520,30 +554,26 @@ mod test {
         false
     }
 
-    use conductor::aws::cloudformation::AWSConfigState;
+    use conductor::aws::cloudformation::{AWSConfigState, StackPhase};
     async fn check_cf_stack_deletion(acs: &AWSConfigState, stack_name: &str) -> bool {
         let max_duration = Duration::from_secs(5 * 60); // 5 minutes
         let check_interval = Duration::from_secs(30); // Check every 30 seconds
 
         let start_time = tokio::time::Instant::now();
         while tokio::time::Instant::now() - start_time < max_duration {
-            let exists = acs.does_stack_exist(stack_name).await;
-            println!("Checking if CF stack {} exists: {}", stack_name, exists);
-
-            if !exists {
-                println!(
-                    "CF stack {} does not exist, we assume it's deleted",
-                    stack_name
-                );
-                return true;
-            } else {
-                match acs.delete_cloudformation_stack(stack_name).await {
-                    Ok(_) => {
-                        println!("CF stack {} was deleted", stack_name);
-                    }
-                    Err(e) => {
-                        panic!("Failed to delete CloudFormation stack: {:?}", e);
-                    }
+            match acs.delete_cloudformation_stack(stack_name).await {
+                Ok(StackPhase::Absent) => {
+                    println!(
+                        "CF stack {} does not exist, we assume it's deleted",
+                        stack_name
+                    );
+                    return true;
+                }
+                Ok(phase) => {
+                    println!("CF stack {} is {:?}", stack_name, phase);
+                }
+                Err(e) => {
+                    panic!("Failed to delete CloudFormation stack: {:?}", e);
                 }
             }
 
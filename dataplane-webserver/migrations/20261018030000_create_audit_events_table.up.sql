@@ -0,0 +1,14 @@
+-- Up migration
+CREATE TABLE audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    org_id VARCHAR(255) NOT NULL,
+    instance_id VARCHAR(255) NOT NULL,
+    subject VARCHAR(255),
+    method VARCHAR(16) NOT NULL,
+    operation TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status SMALLINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_audit_events_instance ON audit_events(org_id, instance_id, id DESC);
@@ -0,0 +1,210 @@
+use crate::alerts::types::{Alert, AlertSeverity, AlertState};
+use crate::config::Config;
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use thiserror::Error;
+
+pub mod types;
+
+// Labels which are returned as fields of an alert rather than in its labels
+const ALERT_FIELDS: [&str; 4] = ["__name__", "alertname", "alertstate", "severity"];
+
+#[derive(Error, Debug)]
+pub enum AlertError {
+    #[error("Failed to query Prometheus: {0}")]
+    Request(String),
+
+    #[error("Prometheus returned an error: {0}")]
+    Prometheus(String),
+}
+
+/// Map the severity label of an alert to a severity, rules use a few spellings of each
+pub fn normalize_severity(severity: Option<&str>) -> AlertSeverity {
+    match severity.map(str::to_lowercase).as_deref() {
+        Some("critical" | "page" | "error" | "high") => AlertSeverity::Critical,
+        Some("warning" | "warn" | "medium") => AlertSeverity::Warning,
+        Some("info" | "informational" | "notice" | "low") => AlertSeverity::Info,
+        _ => AlertSeverity::Unknown,
+    }
+}
+
+fn metric_labels(sample: &Value) -> BTreeMap<String, String> {
+    sample["metric"]
+        .as_object()
+        .map(|metric| {
+            metric
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// The value of an instant vector sample, `[<timestamp>, "<value>"]`
+fn sample_value(sample: &Value) -> Option<f64> {
+    sample["value"][1].as_str()?.parse().ok()
+}
+
+/// Build the alerts from the samples of ALERTS, with the time each became active from the
+/// samples of ALERTS_FOR_STATE, which have the same labels except alertstate. Alerts are
+/// sorted by severity, then name.
+pub fn parse_alerts(alerts: &[Value], for_state: &[Value]) -> Vec<Alert> {
+    let active_since: Vec<(BTreeMap<String, String>, f64)> = for_state
+        .iter()
+        .filter_map(|sample| {
+            let mut labels = metric_labels(sample);
+            labels.remove("__name__");
+            Some((labels, sample_value(sample)?))
+        })
+        .collect();
+
+    let mut parsed: Vec<Alert> = alerts
+        .iter()
+        .filter_map(|sample| {
+            let mut labels = metric_labels(sample);
+            let name = labels.get("alertname")?.clone();
+            let state = match labels.get("alertstate").map(String::as_str) {
+                Some("firing") => AlertState::Firing,
+                Some("pending") => AlertState::Pending,
+                _ => return None,
+            };
+            let severity = normalize_severity(labels.get("severity").map(String::as_str));
+
+            labels.remove("__name__");
+            labels.remove("alertstate");
+            let since = active_since
+                .iter()
+                .find(|(for_labels, _)| *for_labels == labels)
+                .and_then(|(_, since)| Utc.timestamp_opt(*since as i64, 0).single());
+
+            labels.retain(|name, _| !ALERT_FIELDS.contains(&name.as_str()));
+            Some(Alert {
+                name,
+                state,
+                severity,
+                labels,
+                active_since: since,
+            })
+        })
+        .collect();
+    parsed.sort_by(|a, b| (a.severity, &a.name).cmp(&(b.severity, &b.name)));
+    parsed
+}
+
+async fn instant_query(
+    cfg: &Config,
+    http_client: &reqwest::Client,
+    query: &str,
+) -> Result<Vec<Value>, AlertError> {
+    let timeout = format!("{}ms", cfg.prometheus_timeout_ms);
+    let query_url = format!("{}/api/v1/query", cfg.prometheus_url.trim_end_matches('/'));
+    let response = http_client
+        .get(&query_url)
+        .query(&[("query", query), ("timeout", &timeout)])
+        .timeout(Duration::from_millis(
+            cfg.prometheus_timeout_ms as u64 + 500,
+        ))
+        .send()
+        .await
+        .map_err(|e| AlertError::Request(e.to_string()))?;
+    let status = response.status();
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| AlertError::Prometheus(e.to_string()))?;
+    if !status.is_success() {
+        return Err(AlertError::Prometheus(format!(
+            "{}: {}",
+            status, body["error"]
+        )));
+    }
+    Ok(body["data"]["result"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// The firing alerts of an instance, and the pending ones when requested
+pub async fn get_alerts(
+    cfg: &Config,
+    http_client: &reqwest::Client,
+    namespace: &str,
+    include_pending: bool,
+) -> Result<Vec<Alert>, AlertError> {
+    // Namespaces are validated by the lookup of the instance, they can be used in selectors
+    let state_selector = match include_pending {
+        true => "",
+        false => r#", alertstate="firing""#,
+    };
+    let alerts = instant_query(
+        cfg,
+        http_client,
+        &format!(r#"ALERTS{{namespace="{}"{}}}"#, namespace, state_selector),
+    )
+    .await?;
+    let for_state = instant_query(
+        cfg,
+        http_client,
+        &format!(r#"ALERTS_FOR_STATE{{namespace="{}"}}"#, namespace),
+    )
+    .await?;
+    Ok(parse_alerts(&alerts, &for_state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_severity() {
+        assert_eq!(
+            normalize_severity(Some("critical")),
+            AlertSeverity::Critical
+        );
+        assert_eq!(normalize_severity(Some("Page")), AlertSeverity::Critical);
+        assert_eq!(normalize_severity(Some("warn")), AlertSeverity::Warning);
+        assert_eq!(normalize_severity(Some("info")), AlertSeverity::Info);
+        assert_eq!(normalize_severity(Some("urgent")), AlertSeverity::Unknown);
+        assert_eq!(normalize_severity(None), AlertSeverity::Unknown);
+    }
+
+    #[test]
+    fn test_parse_alerts() {
+        let alerts = vec![
+            json!({"metric": {"__name__": "ALERTS", "alertname": "PgHighConnections",
+                "alertstate": "firing", "severity": "warning", "namespace": "org-foo-inst-bar"},
+                "value": [1717243200, "1"]}),
+            json!({"metric": {"__name__": "ALERTS", "alertname": "PgDiskFull",
+                "alertstate": "pending", "severity": "CRITICAL", "namespace": "org-foo-inst-bar",
+                "pod": "org-foo-inst-bar-1"},
+                "value": [1717243200, "1"]}),
+            json!({"metric": {"__name__": "ALERTS", "alertstate": "firing"},
+                "value": [1717243200, "1"]}),
+        ];
+        let for_state = vec![json!({"metric": {"__name__": "ALERTS_FOR_STATE",
+            "alertname": "PgHighConnections", "severity": "warning",
+            "namespace": "org-foo-inst-bar"},
+            "value": [1717243200, "1717239600"]})];
+
+        let parsed = parse_alerts(&alerts, &for_state);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name, "PgDiskFull");
+        assert_eq!(parsed[0].severity, AlertSeverity::Critical);
+        assert_eq!(parsed[0].state, AlertState::Pending);
+        assert_eq!(parsed[0].active_since, None);
+        assert_eq!(
+            parsed[0].labels.get("pod").map(String::as_str),
+            Some("org-foo-inst-bar-1")
+        );
+        assert_eq!(parsed[1].name, "PgHighConnections");
+        assert_eq!(
+            parsed[1].active_since,
+            Utc.timestamp_opt(1_717_239_600, 0).single()
+        );
+        assert!(!parsed[1].labels.contains_key("severity"));
+        assert!(parsed[1].labels.contains_key("namespace"));
+    }
+}
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// The severity of an alert, from its `severity` label
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSeverity {
+    Critical,
+    Warning,
+    Info,
+    /// The alert has no severity label, or one which is not recognized
+    Unknown,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    /// The alert condition is met, but not for long enough to fire yet
+    Pending,
+    Firing,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Alert {
+    /// The name of the alerting rule
+    pub name: String,
+    pub state: AlertState,
+    pub severity: AlertSeverity,
+    /// The labels of the alert, other than its name, state and severity
+    pub labels: BTreeMap<String, String>,
+    /// Since when the alert condition is met
+    pub active_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AlertsQuery {
+    /// Also return alerts which are pending
+    #[serde(default)]
+    pub include_pending: bool,
+}
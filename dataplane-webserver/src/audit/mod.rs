@@ -0,0 +1,382 @@
+use crate::audit::types::{AuditEvent, AuditEventsQuery};
+use crate::config::Config;
+use crate::routes::secrets::requester;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::Error;
+use futures::future::{ready, LocalBoxFuture, Ready};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{Api, Client};
+use log::{error, info};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use thiserror::Error;
+
+pub mod types;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+const ORGANIZATION_LABEL: &str = "tembo.io/organization_id";
+const INSTANCE_LABEL: &str = "tembo.io/instance_id";
+// The org of calls to a namespace without the labels of an instance
+const UNKNOWN_ORG: &str = "unknown";
+// The instance of calls changing all instances of an organization
+const ALL_INSTANCES: &str = "*";
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// The table of audit events, in the database of AUDIT_DATABASE_URL
+#[derive(Clone)]
+pub struct AuditLog {
+    pool: PgPool,
+}
+
+impl AuditLog {
+    /// Connect to the audit database and migrate it, audit logging is disabled when
+    /// AUDIT_DATABASE_URL is empty
+    pub async fn from_config(cfg: &Config) -> Result<Option<Self>, AuditError> {
+        if cfg.audit_database_url.is_empty() {
+            return Ok(None);
+        }
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(&cfg.audit_database_url)
+            .await?;
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(|e| AuditError::Database(e.into()))?;
+        Ok(Some(Self { pool }))
+    }
+
+    async fn record(&self, event: &AuditEvent) -> Result<(), AuditError> {
+        sqlx::query(
+            "INSERT INTO audit_events (org_id, instance_id, subject, method, operation, path, status) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(&event.org_id)
+        .bind(&event.instance_id)
+        .bind(&event.subject)
+        .bind(&event.method)
+        .bind(&event.operation)
+        .bind(&event.path)
+        .bind(event.status)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The audit events of an instance, newest first
+    pub async fn events(
+        &self,
+        org_id: &str,
+        instance_id: &str,
+        query: &AuditEventsQuery,
+    ) -> Result<Vec<AuditEvent>, AuditError> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(AuditError::InvalidRequest(format!(
+                "The limit must be between 1 and {}",
+                MAX_LIMIT
+            )));
+        }
+        Ok(sqlx::query_as::<_, AuditEvent>(
+            "SELECT id, org_id, instance_id, subject, method, operation, path, status, created_at \
+             FROM audit_events \
+             WHERE org_id = $1 AND instance_id = $2 AND ($3::int8 IS NULL OR id < $3) \
+             ORDER BY id DESC LIMIT $4",
+        )
+        .bind(org_id)
+        .bind(instance_id)
+        .bind(query.before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+/// The instance of a call which is audited: calls which change an instance, and those which
+/// read its secrets or download its backups
+#[derive(Debug, PartialEq)]
+pub enum AuditedInstance {
+    /// The org and instance IDs of a call to the API
+    Ids(String, String),
+    /// The org ID of a call changing all instances of the organization, like its backup
+    /// encryption, recorded with `*` as the instance ID
+    Org(String),
+    /// The namespace of a call to the deprecated `/{namespace}` routes
+    Namespace(String),
+}
+
+/// The instance of a call, when it is audited
+pub fn audited_instance(method: &Method, path: &str) -> Option<AuditedInstance> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let (instance, rest) = match segments.as_slice() {
+        ["api", "v1", "orgs", org_id, "instances", instance_id, rest @ ..] => (
+            AuditedInstance::Ids(org_id.to_string(), instance_id.to_string()),
+            rest,
+        ),
+        ["api", "v1", "orgs", org_id, "backup-encryption"] => {
+            (AuditedInstance::Org(org_id.to_string()), &segments[4..])
+        }
+        [namespace, "metrics" | "secrets", ..] => (
+            AuditedInstance::Namespace(namespace.to_string()),
+            &segments[1..],
+        ),
+        _ => return None,
+    };
+    let reads_data = matches!(rest, ["secrets", _] | ["backups", _, "download"]);
+    if (method == Method::GET && !reads_data) || method == Method::OPTIONS || method == Method::HEAD
+    {
+        return None;
+    }
+    Some(instance)
+}
+
+// The org and instance IDs of an instance namespace, from its labels
+async fn namespace_ids(namespace: &str) -> Option<(String, String)> {
+    let client = Client::try_default().await.ok()?;
+    let namespaces: Api<Namespace> = Api::all(client);
+    let labels = namespaces.get_opt(namespace).await.ok()??.metadata.labels?;
+    Some((
+        labels.get(ORGANIZATION_LABEL)?.clone(),
+        labels.get(INSTANCE_LABEL)?.clone(),
+    ))
+}
+
+/// Middleware recording audited calls with their outcome, in the log with the `audit` target
+/// and in the audit database when it is configured
+#[derive(Clone)]
+pub struct Audit {
+    audit_log: Option<AuditLog>,
+}
+
+impl Audit {
+    pub fn new(audit_log: Option<AuditLog>) -> Self {
+        Self { audit_log }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Audit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuditMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuditMiddleware {
+            service,
+            audit_log: self.audit_log.clone(),
+        }))
+    }
+}
+
+pub struct AuditMiddleware<S> {
+    service: S,
+    audit_log: Option<AuditLog>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuditMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(instance) = audited_instance(req.method(), req.path()) else {
+            return Box::pin(self.service.call(req));
+        };
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let operation = req.match_pattern().unwrap_or_else(|| path.clone());
+        let subject = requester(req.request());
+        let audit_log = self.audit_log.clone();
+
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let response = response.await;
+            let (org_id, instance_id) = match instance {
+                AuditedInstance::Ids(org_id, instance_id) => (org_id, instance_id),
+                AuditedInstance::Org(org_id) => (org_id, ALL_INSTANCES.to_string()),
+                // Calls are still audited when the namespace has no IDs, by its name
+                AuditedInstance::Namespace(namespace) => namespace_ids(&namespace)
+                    .await
+                    .unwrap_or_else(|| (UNKNOWN_ORG.to_string(), namespace)),
+            };
+            // Errors of the handlers are responses too, like 403 for non-admins
+            let status = match &response {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            let event = AuditEvent {
+                id: 0,
+                org_id,
+                instance_id,
+                subject,
+                method,
+                operation,
+                path,
+                status: status.as_u16() as i16,
+                created_at: chrono::Utc::now(),
+            };
+            info!(
+                target: "audit",
+                "{} {} by {} of {}/{}: {}",
+                event.method,
+                event.path,
+                event.subject.as_deref().unwrap_or("unknown"),
+                event.org_id,
+                event.instance_id,
+                event.status
+            );
+            if let Some(audit_log) = audit_log {
+                if let Err(e) = audit_log.record(&event).await {
+                    error!("Failed to record audit event of {}: {}", event.path, e);
+                }
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_audited_instance() {
+        let instance = Some(AuditedInstance::Ids(
+            "org_1".to_string(),
+            "inst_2".to_string(),
+        ));
+        assert_eq!(
+            audited_instance(
+                &Method::DELETE,
+                "/api/v1/orgs/org_1/instances/inst_2/backups/b1"
+            ),
+            instance
+        );
+        assert_eq!(
+            audited_instance(
+                &Method::PATCH,
+                "/api/v1/orgs/org_1/instances/inst_2/secrets/app-role"
+            ),
+            instance
+        );
+        assert_eq!(
+            audited_instance(
+                &Method::GET,
+                "/api/v1/orgs/org_1/instances/inst_2/secrets/app-role"
+            ),
+            instance
+        );
+        assert_eq!(
+            audited_instance(
+                &Method::GET,
+                "/api/v1/orgs/org_1/instances/inst_2/backups/b1/download"
+            ),
+            instance
+        );
+        assert_eq!(
+            audited_instance(&Method::GET, "/api/v1/orgs/org_1/instances/inst_2/secrets"),
+            None
+        );
+        assert_eq!(
+            audited_instance(&Method::GET, "/api/v1/orgs/org_1/instances/inst_2/backups"),
+            None
+        );
+        assert_eq!(audited_instance(&Method::POST, "/health/ready"), None);
+        // The backup encryption of all instances of an organization
+        let org = Some(AuditedInstance::Org("org_1".to_string()));
+        assert_eq!(
+            audited_instance(&Method::PUT, "/api/v1/orgs/org_1/backup-encryption"),
+            org
+        );
+        assert_eq!(
+            audited_instance(&Method::DELETE, "/api/v1/orgs/org_1/backup-encryption"),
+            org
+        );
+        assert_eq!(
+            audited_instance(&Method::GET, "/api/v1/orgs/org_1/backup-encryption"),
+            None
+        );
+        // The deprecated routes by namespace
+        assert_eq!(
+            audited_instance(&Method::GET, "/org-foo-inst-bar/secrets/app-role"),
+            Some(AuditedInstance::Namespace("org-foo-inst-bar".to_string()))
+        );
+        assert_eq!(
+            audited_instance(&Method::GET, "/org-foo-inst-bar/secrets"),
+            None
+        );
+        assert_eq!(
+            audited_instance(&Method::GET, "/org-foo-inst-bar/metrics/query_range"),
+            None
+        );
+    }
+
+    // Collects the events logged with the audit target
+    struct AuditCapture;
+
+    static AUDITED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    impl log::Log for AuditCapture {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "audit"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                AUDITED.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[actix_web::test]
+    async fn test_audit_backup_encryption() {
+        static CAPTURE: AuditCapture = AuditCapture;
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(log::LevelFilter::Info);
+
+        let app = init_service(
+            App::new().wrap(Audit::new(None)).service(
+                web::scope("/api/v1/orgs/{org_id}")
+                    .route("/backup-encryption", web::put().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+        let req = TestRequest::put()
+            .uri("/api/v1/orgs/org_1/backup-encryption")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let audited = AUDITED.lock().unwrap();
+        assert!(audited.contains(
+            &"PUT /api/v1/orgs/org_1/backup-encryption by unknown of org_1/*: 200".to_string()
+        ));
+    }
+}
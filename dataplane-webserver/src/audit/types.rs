@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A data API call which changed an instance or read its secrets
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub org_id: String,
+    pub instance_id: String,
+    /// The user ID of the requester, from the subject of the bearer token
+    pub subject: Option<String>,
+    pub method: String,
+    /// The route of the call, like /api/v1/orgs/{org_id}/instances/{instance_id}/backups
+    pub operation: String,
+    pub path: String,
+    /// The HTTP status of the response, the outcome of the call
+    pub status: i16,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AuditEventsQuery {
+    /// Only events older than this event, to page through them
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,194 @@
+use crate::backups::types::BaseBackup;
+use crate::backups::{BackupError, BackupStore};
+use chrono::{DateTime, Utc};
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind, ListParams};
+use kube::{Api, Client};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// CNPG with Barman stores the base backups of a cluster under
+// <destinationPath>/<cluster name>/base/<backup ID>/, along with a backup.info file
+const BASE_DIRECTORY: &str = "base";
+
+fn api_resource(group: &str, version: &str, kind: &str) -> ApiResource {
+    ApiResource::from_gvk(&GroupVersionKind::gvk(group, version, kind))
+}
+
+/// Split an object storage destination path like `s3://bucket/some/path` into its bucket and
+/// the prefix of the objects in it
+pub fn parse_destination_path(destination_path: &str) -> Option<(String, String)> {
+    let path = destination_path.strip_prefix("s3://")?;
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    if bucket.is_empty() {
+        return None;
+    }
+    Some((bucket.to_string(), prefix.trim_matches('/').to_string()))
+}
+
+/// Group the objects under the base directory of a cluster into its base backups, with the
+/// total size and the time of the last object of each, newest first
+pub fn group_base_backups(
+    objects: &[(String, i64, Option<DateTime<Utc>>)],
+    base_prefix: &str,
+) -> Vec<BaseBackup> {
+    let mut backups: BTreeMap<&str, BaseBackup> = BTreeMap::new();
+    for (key, size, last_modified) in objects {
+        let Some((backup_id, file)) = key
+            .strip_prefix(base_prefix)
+            .and_then(|rest| rest.split_once('/'))
+        else {
+            continue;
+        };
+        if backup_id.is_empty() || file.is_empty() {
+            continue;
+        }
+        let backup = backups.entry(backup_id).or_insert_with(|| BaseBackup {
+            backup_id: backup_id.to_string(),
+            ..BaseBackup::default()
+        });
+        backup.size_bytes += size;
+        backup.last_modified = backup.last_modified.max(*last_modified);
+    }
+    let mut backups: Vec<BaseBackup> = backups.into_values().collect();
+    backups.sort_by(|a, b| b.backup_id.cmp(&a.backup_id));
+    backups
+}
+
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    value
+        .as_str()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+// Add the phase and times of the CNPG Backup of each base backup, by its backup ID
+fn merge_backup_statuses(backups: &mut [BaseBackup], statuses: &[Value]) {
+    for status in statuses {
+        let Some(backup_id) = status["backupId"].as_str() else {
+            continue;
+        };
+        if let Some(backup) = backups.iter_mut().find(|b| b.backup_id == backup_id) {
+            backup.phase = status["phase"].as_str().map(str::to_string);
+            backup.started_at = timestamp(&status["startedAt"]);
+            backup.stopped_at = timestamp(&status["stoppedAt"]);
+        }
+    }
+}
+
+/// The base backups of an instance, from the object storage of its CoreDB and the CNPG
+/// Backups of its namespace, and the earliest point in time it can be recovered to
+pub async fn list_base_backups(
+    kubernetes_client: Client,
+    store: &BackupStore,
+    namespace: &str,
+) -> Result<(Vec<BaseBackup>, Option<DateTime<Utc>>), BackupError> {
+    // The CoreDB and its CNPG Cluster are named after the namespace
+    let coredbs: Api<DynamicObject> = Api::namespaced_with(
+        kubernetes_client.clone(),
+        namespace,
+        &api_resource("coredb.io", "v1alpha1", "CoreDB"),
+    );
+    let coredb = coredbs
+        .get_opt(namespace)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .ok_or_else(|| BackupError::NotFound(namespace.to_string()))?;
+    let Some((bucket, prefix)) = coredb.data["spec"]["backup"]["destinationPath"]
+        .as_str()
+        .and_then(parse_destination_path)
+    else {
+        return Ok((vec![], None));
+    };
+
+    let base_prefix = match prefix.is_empty() {
+        true => format!("{}/{}/", namespace, BASE_DIRECTORY),
+        false => format!("{}/{}/{}/", prefix, namespace, BASE_DIRECTORY),
+    };
+    let objects = store.list_objects_in(&bucket, &base_prefix).await?;
+    let mut backups = group_base_backups(&objects, &base_prefix);
+
+    let cnpg_backups: Api<DynamicObject> = Api::namespaced_with(
+        kubernetes_client.clone(),
+        namespace,
+        &api_resource("postgresql.cnpg.io", "v1", "Backup"),
+    );
+    let statuses: Vec<Value> = cnpg_backups
+        .list(&ListParams::default())
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .into_iter()
+        .map(|backup| backup.data["status"].clone())
+        .collect();
+    merge_backup_statuses(&mut backups, &statuses);
+
+    let clusters: Api<DynamicObject> = Api::namespaced_with(
+        kubernetes_client,
+        namespace,
+        &api_resource("postgresql.cnpg.io", "v1", "Cluster"),
+    );
+    let first_recoverability_point = clusters
+        .get_opt(namespace)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .and_then(|cluster| timestamp(&cluster.data["status"]["firstRecoverabilityPoint"]));
+
+    Ok((backups, first_recoverability_point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_destination_path() {
+        assert_eq!(
+            parse_destination_path("s3://backups/coredb/coredb/"),
+            Some(("backups".to_string(), "coredb/coredb".to_string()))
+        );
+        assert_eq!(
+            parse_destination_path("s3://backups"),
+            Some(("backups".to_string(), "".to_string()))
+        );
+        assert_eq!(parse_destination_path("gs://backups/coredb"), None);
+        assert_eq!(parse_destination_path("s3:///coredb"), None);
+    }
+
+    #[test]
+    fn test_group_base_backups() {
+        let t1 = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let t2 = Utc.timestamp_opt(1_717_246_800, 0).unwrap();
+        let base = "coredb/org-foo-inst-bar/base/";
+        let objects = vec![
+            (format!("{}20240601T120000/backup.info", base), 1, Some(t1)),
+            (format!("{}20240601T120000/data.tar", base), 1000, Some(t2)),
+            (format!("{}20240602T120000/data.tar", base), 2000, Some(t2)),
+            (
+                "coredb/org-foo-inst-bar/wals/0000/0001".to_string(),
+                16,
+                Some(t2),
+            ),
+            (format!("{}stray", base), 1, Some(t2)),
+        ];
+        let mut backups = group_base_backups(&objects, base);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].backup_id, "20240602T120000");
+        assert_eq!(backups[1].backup_id, "20240601T120000");
+        assert_eq!(backups[1].size_bytes, 1001);
+        assert_eq!(backups[1].last_modified, Some(t2));
+
+        merge_backup_statuses(
+            &mut backups,
+            &[json!({
+                "backupId": "20240601T120000",
+                "phase": "completed",
+                "startedAt": "2024-06-01T12:00:00Z",
+                "stoppedAt": "2024-06-01T12:10:00Z"
+            })],
+        );
+        assert_eq!(backups[1].phase.as_deref(), Some("completed"));
+        assert_eq!(backups[1].started_at, Some(t1));
+        assert_eq!(backups[0].phase, None);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::backups::types::{ArtifactEncryption, BackupEncryption};
+use crate::backups::BackupError;
+use k8s_openapi::api::core::v1::{ConfigMap, EnvVar, Namespace};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{Patch, PatchParams, PostParams};
+use kube::{Api, Client};
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+// The encryption keys of all organizations are stored in one ConfigMap of the namespace of
+// the webserver, by organization ID. Neither KMS key IDs nor public keys are secrets.
+const ENCRYPTION_CONFIGMAP: &str = "temback-encryption";
+const ORGANIZATION_LABEL: &str = "tembo.io/organization_id";
+/// The first release of temback with `--encrypt-kms-key` and `--encrypt-recipient`
+pub const MIN_ENCRYPTION_TEMBACK_VERSION: (u64, u64, u64) = (0, 3, 0);
+
+lazy_static! {
+    // A key ID, ARN, alias or alias ARN
+    static ref KMS_KEY_ID: Regex = Regex::new(
+        r"^(arn:aws[a-z-]*:kms:[a-z0-9-]+:[0-9]{12}:(key/[0-9a-f-]{36}|alias/[A-Za-z0-9/_-]{1,250})|[0-9a-f-]{36}|alias/[A-Za-z0-9/_-]{1,250})$"
+    )
+    .unwrap();
+    // Bech32 encoded X25519 recipients of age
+    static ref AGE_PUBLIC_KEY: Regex = Regex::new(r"^age1[02-9ac-hj-np-z]{58}$").unwrap();
+}
+
+/// Check exactly one of a KMS key and a public key is set, and that it is valid
+pub fn validate_encryption(encryption: &BackupEncryption) -> Result<(), BackupError> {
+    match (&encryption.kms_key_id, &encryption.public_key) {
+        (Some(kms_key_id), None) if KMS_KEY_ID.is_match(kms_key_id) => Ok(()),
+        (Some(kms_key_id), None) => Err(BackupError::InvalidRequest(format!(
+            "Invalid KMS key: {}",
+            kms_key_id
+        ))),
+        (None, Some(public_key)) if AGE_PUBLIC_KEY.is_match(public_key) => Ok(()),
+        (None, Some(_)) => Err(BackupError::InvalidRequest(
+            "The public key must be an age public key".to_string(),
+        )),
+        _ => Err(BackupError::InvalidRequest(
+            "Either kms_key_id or public_key is required".to_string(),
+        )),
+    }
+}
+
+// The version of a temback image tagged with a release, like quay.io/tembo/temback:v0.3.1
+fn image_version(image: &str) -> Option<(u64, u64, u64)> {
+    let name = image.split('@').next()?;
+    let (_, tag) = name.rsplit('/').next()?.split_once(':')?;
+    let release = tag.trim_start_matches('v').split(['-', '+']).next()?;
+    let mut parts = release.split('.').map(|part| part.parse::<u64>().ok());
+    Some((
+        parts.next()??,
+        parts.next()??,
+        parts.next().unwrap_or(Some(0))?,
+    ))
+}
+
+/// Check temback of an image can encrypt backups. Images tagged with an older release are
+/// refused, so backups of organizations which set a key are never taken unencrypted. Other
+/// tags, like latest, are assumed to be newer builds.
+pub fn check_encryption_support(image: &str) -> Result<(), BackupError> {
+    match image_version(image) {
+        Some(version) if version < MIN_ENCRYPTION_TEMBACK_VERSION => {
+            let (major, minor, patch) = MIN_ENCRYPTION_TEMBACK_VERSION;
+            Err(BackupError::UnsupportedImage(format!(
+                "{} is older than temback {}.{}.{}",
+                image, major, minor, patch
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The arguments making temback encrypt an artifact before uploading it, it records the key
+/// in the status file of the artifact. The key is passed through the environment, as it may
+/// contain characters container arguments would expand.
+pub fn encryption_args(encryption: &BackupEncryption) -> Vec<String> {
+    if encryption.kms_key_id.is_some() {
+        vec![
+            "--encrypt-kms-key".to_string(),
+            "$(ENCRYPTION_KMS_KEY_ID)".to_string(),
+        ]
+    } else if encryption.public_key.is_some() {
+        vec![
+            "--encrypt-recipient".to_string(),
+            "$(ENCRYPTION_PUBLIC_KEY)".to_string(),
+        ]
+    } else {
+        vec![]
+    }
+}
+
+/// The environment variables referenced by the encryption arguments of temback
+pub fn encryption_env(encryption: &BackupEncryption) -> Vec<EnvVar> {
+    [
+        ("ENCRYPTION_KMS_KEY_ID", &encryption.kms_key_id),
+        ("ENCRYPTION_PUBLIC_KEY", &encryption.public_key),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| {
+        Some(EnvVar {
+            name: name.to_string(),
+            value: Some(value.clone()?),
+            ..EnvVar::default()
+        })
+    })
+    .collect()
+}
+
+/// The encryption of an artifact recorded in its status file, None for artifacts stored
+/// unencrypted or before temback recorded it
+pub fn parse_artifact_encryption(status: &[u8]) -> Result<Option<ArtifactEncryption>, BackupError> {
+    let status: serde_json::Value = serde_json::from_slice(status)
+        .map_err(|e| BackupError::ObjectStore(format!("Invalid status file: {}", e)))?;
+    match status.get("encryption") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(encryption) => serde_json::from_value(encryption.clone())
+            .map(Some)
+            .map_err(|e| BackupError::ObjectStore(format!("Invalid status file: {}", e))),
+    }
+}
+
+fn parse_encryptions(configmap: &ConfigMap) -> BTreeMap<String, BackupEncryption> {
+    configmap
+        .data
+        .iter()
+        .flatten()
+        .filter_map(|(org_id, encryption)| {
+            Some((org_id.clone(), serde_json::from_str(encryption).ok()?))
+        })
+        .collect()
+}
+
+/// The encryption keys of all organizations which set one, by organization ID
+pub async fn get_encryptions(
+    kubernetes_client: Client,
+) -> Result<BTreeMap<String, BackupEncryption>, BackupError> {
+    let configmaps: Api<ConfigMap> = Api::default_namespaced(kubernetes_client);
+    Ok(configmaps
+        .get_opt(ENCRYPTION_CONFIGMAP)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .as_ref()
+        .map(parse_encryptions)
+        .unwrap_or_default())
+}
+
+/// The encryption key of the backups of an organization, None when they are not encrypted
+pub async fn get_encryption(
+    kubernetes_client: Client,
+    org_id: &str,
+) -> Result<Option<BackupEncryption>, BackupError> {
+    Ok(get_encryptions(kubernetes_client).await?.remove(org_id))
+}
+
+// Merge a change into the keys of the organizations, creating the ConfigMap with the first one
+async fn patch_encryptions(
+    kubernetes_client: Client,
+    data: serde_json::Value,
+) -> Result<(), BackupError> {
+    let configmaps: Api<ConfigMap> = Api::default_namespaced(kubernetes_client);
+    let patch = json!({ "data": data });
+    match configmaps
+        .patch(
+            ENCRYPTION_CONFIGMAP,
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(e)) if e.code == 404 => {
+            let configmap = ConfigMap {
+                metadata: ObjectMeta {
+                    name: Some(ENCRYPTION_CONFIGMAP.to_string()),
+                    ..ObjectMeta::default()
+                },
+                data: serde_json::from_value(data).ok(),
+                ..ConfigMap::default()
+            };
+            configmaps
+                .create(&PostParams::default(), &configmap)
+                .await
+                .map_err(|e| BackupError::Kube(e.to_string()))?;
+            Ok(())
+        }
+        Err(e) => Err(BackupError::Kube(e.to_string())),
+    }
+}
+
+/// Set the key the backups of an organization are encrypted with, from their next backup
+pub async fn put_encryption(
+    kubernetes_client: Client,
+    org_id: &str,
+    encryption: &BackupEncryption,
+) -> Result<(), BackupError> {
+    validate_encryption(encryption)?;
+    let value = serde_json::to_string(encryption).map_err(|e| BackupError::Kube(e.to_string()))?;
+    patch_encryptions(kubernetes_client, json!({ org_id: value })).await?;
+    info!("Set backup encryption key of {}", org_id);
+    Ok(())
+}
+
+/// Stop encrypting the backups of an organization, the stored ones stay encrypted
+pub async fn delete_encryption(kubernetes_client: Client, org_id: &str) -> Result<(), BackupError> {
+    if get_encryption(kubernetes_client.clone(), org_id)
+        .await?
+        .is_none()
+    {
+        return Err(BackupError::NotFound(org_id.to_string()));
+    }
+    // A null value removes the key from the ConfigMap
+    patch_encryptions(kubernetes_client, json!({ org_id: null })).await?;
+    info!("Removed backup encryption key of {}", org_id);
+    Ok(())
+}
+
+/// The organization an instance belongs to, from the labels of its namespace
+pub async fn namespace_organization(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<Option<String>, BackupError> {
+    let namespaces: Api<Namespace> = Api::all(kubernetes_client);
+    Ok(namespaces
+        .get_opt(namespace)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .and_then(|namespace| namespace.metadata.labels)
+        .and_then(|mut labels| labels.remove(ORGANIZATION_LABEL)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backups::types::EncryptionMethod;
+
+    const PUBLIC_KEY: &str = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+
+    fn encryption(kms_key_id: Option<&str>, public_key: Option<&str>) -> BackupEncryption {
+        BackupEncryption {
+            kms_key_id: kms_key_id.map(str::to_string),
+            public_key: public_key.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_validate_encryption() {
+        assert!(validate_encryption(&encryption(
+            Some("arn:aws:kms:us-east-1:123456789012:key/1234abcd-12ab-34cd-56ef-1234567890ab"),
+            None
+        ))
+        .is_ok());
+        assert!(validate_encryption(&encryption(Some("alias/tembo-backups"), None)).is_ok());
+        assert!(validate_encryption(&encryption(None, Some(PUBLIC_KEY))).is_ok());
+        assert!(matches!(
+            validate_encryption(&encryption(None, None)),
+            Err(BackupError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            validate_encryption(&encryption(Some("alias/tembo-backups"), Some(PUBLIC_KEY))),
+            Err(BackupError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            validate_encryption(&encryption(Some("$(RW_URI)"), None)),
+            Err(BackupError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            validate_encryption(&encryption(None, Some("ssh-ed25519 AAAA"))),
+            Err(BackupError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_encryption_args() {
+        let kms = encryption(Some("alias/tembo-backups"), None);
+        assert_eq!(
+            encryption_args(&kms),
+            ["--encrypt-kms-key", "$(ENCRYPTION_KMS_KEY_ID)"]
+        );
+        let env = encryption_env(&kms);
+        assert_eq!(env.len(), 1);
+        assert_eq!(env[0].name, "ENCRYPTION_KMS_KEY_ID");
+        assert_eq!(env[0].value.as_deref(), Some("alias/tembo-backups"));
+
+        let public_key = encryption(None, Some(PUBLIC_KEY));
+        assert_eq!(
+            encryption_args(&public_key),
+            ["--encrypt-recipient", "$(ENCRYPTION_PUBLIC_KEY)"]
+        );
+        assert_eq!(encryption_env(&public_key)[0].name, "ENCRYPTION_PUBLIC_KEY");
+    }
+
+    #[test]
+    fn test_check_encryption_support() {
+        for image in [
+            "quay.io/tembo/temback:latest",
+            "quay.io/tembo/temback",
+            "quay.io/tembo/temback:v0.3.0",
+            "quay.io/tembo/temback:0.4",
+            "quay.io/tembo/temback:v1.0.0-rc.1",
+            "localhost:5000/temback@sha256:0123456789abcdef",
+        ] {
+            assert!(check_encryption_support(image).is_ok(), "{}", image);
+        }
+        for image in ["quay.io/tembo/temback:v0.2.9", "localhost:5000/temback:0.1"] {
+            assert!(matches!(
+                check_encryption_support(image),
+                Err(BackupError::UnsupportedImage(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_artifact_encryption() {
+        let status = br#"{"state":"completed","encryption":{"method":"kms","key_id":"alias/tembo-backups"}}"#;
+        assert_eq!(
+            parse_artifact_encryption(status).unwrap(),
+            Some(ArtifactEncryption {
+                method: EncryptionMethod::Kms,
+                key_id: "alias/tembo-backups".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_artifact_encryption(br#"{"state":"completed"}"#).unwrap(),
+            None
+        );
+        assert!(parse_artifact_encryption(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_encryptions() {
+        let configmap = ConfigMap {
+            data: Some(BTreeMap::from([
+                (
+                    "org_1".to_string(),
+                    r#"{"kms_key_id":"alias/tembo-backups","public_key":null}"#.to_string(),
+                ),
+                ("org_2".to_string(), "invalid".to_string()),
+            ])),
+            ..ConfigMap::default()
+        };
+        let encryptions = parse_encryptions(&configmap);
+        assert_eq!(encryptions.len(), 1);
+        assert_eq!(
+            encryptions["org_1"],
+            encryption(Some("alias/tembo-backups"), None)
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::backups::encryption::{check_encryption_support, encryption_args, encryption_env};
+use crate::backups::types::{BackupEncryption, BackupJob, BackupMode, BackupRequest, JobPhase};
+use crate::backups::{BackupError, BackupStore};
+use chrono::Utc;
+use k8s_openapi::api::batch::v1::{Job, JobSpec};
+use k8s_openapi::api::core::v1::{
+    Container, EnvVar, EnvVarSource, PodSpec, PodTemplateSpec, SecretKeySelector,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::PostParams;
+use kube::{Api, Client};
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+const DUMP_APP: &str = "temback-dump";
+const DATABASE_ANNOTATION: &str = "tembo.io/backup-database";
+// Finished on-demand backup jobs are cleaned up after a day, their artifact stays listed
+const FINISHED_JOB_TTL_SECONDS: i32 = 86400;
+
+lazy_static! {
+    // Names of databases, schemas and tables, or pg_dump patterns of them. Container arguments
+    // expand $(VAR), so these are limited to identifier characters.
+    static ref DUMP_OBJECT_NAME: Regex = Regex::new(r"^[A-Za-z_*][A-Za-z0-9_.*]{0,127}$").unwrap();
+}
+
+/// Check the database, schema and tables of a backup request are valid names
+pub fn validate_backup_request(request: &BackupRequest) -> Result<(), BackupError> {
+    let names = request
+        .database
+        .iter()
+        .chain(request.schema.iter())
+        .chain(request.include_tables.iter())
+        .chain(request.exclude_tables.iter());
+    for name in names {
+        if !DUMP_OBJECT_NAME.is_match(name) {
+            return Err(BackupError::InvalidRequest(format!(
+                "Invalid name: {}",
+                name
+            )));
+        }
+    }
+    if request.database.is_none()
+        && (request.schema.is_some()
+            || !request.include_tables.is_empty()
+            || !request.exclude_tables.is_empty())
+    {
+        return Err(BackupError::InvalidRequest(
+            "A database is required to filter schemas or tables".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// The arguments of temback, dumping all databases unless filtered. Every backup stores a
+// manifest of the tables it dumped and the WAL position it dumped them at; an incremental one
+// only dumps the tables changed since the manifest of $(BASE_MANIFEST_URI).
+fn backup_args(request: &BackupRequest, incremental: bool) -> Vec<String> {
+    let mut args = vec![
+        "backup".to_string(),
+        "--uri".to_string(),
+        "$(RW_URI)".to_string(),
+        "--to".to_string(),
+        "$(BACKUP_URI)".to_string(),
+        "--manifest".to_string(),
+    ];
+    if incremental {
+        args.extend([
+            "--incremental-from".to_string(),
+            "$(BASE_MANIFEST_URI)".to_string(),
+        ]);
+    }
+    if let Some(database) = &request.database {
+        args.extend(["--dbname".to_string(), database.clone()]);
+    }
+    if let Some(schema) = &request.schema {
+        args.extend(["--schema".to_string(), schema.clone()]);
+    }
+    for table in &request.include_tables {
+        args.extend(["--table".to_string(), table.clone()]);
+    }
+    for table in &request.exclude_tables {
+        args.extend(["--exclude-table".to_string(), table.clone()]);
+    }
+    args
+}
+
+/// The `RW_URI` environment variable of temback containers, the URI of the primary of an
+/// instance with the credentials of its connection secret
+pub fn rw_uri_env(namespace: &str) -> EnvVar {
+    EnvVar {
+        name: "RW_URI".to_string(),
+        value_from: Some(EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                name: Some(format!("{}-connection", namespace)),
+                key: "rw_uri".to_string(),
+                ..SecretKeySelector::default()
+            }),
+            ..EnvVarSource::default()
+        }),
+        ..EnvVar::default()
+    }
+}
+
+/// The spec of a Job dumping an instance with temback into its directory of the backups
+/// bucket, incrementally from a manifest when given one and encrypted with the key of the
+/// organization when it set one. Like restore jobs, it runs with the service account of the
+/// instance.
+pub fn backup_job_spec(
+    image: &str,
+    backup_uri: &str,
+    base_manifest_uri: Option<&str>,
+    encryption: Option<&BackupEncryption>,
+    namespace: &str,
+    request: &BackupRequest,
+    labels: BTreeMap<String, String>,
+) -> JobSpec {
+    let mut args = backup_args(request, base_manifest_uri.is_some());
+    let mut env = vec![
+        EnvVar {
+            name: "BACKUP_URI".to_string(),
+            value: Some(backup_uri.to_string()),
+            ..EnvVar::default()
+        },
+        rw_uri_env(namespace),
+    ];
+    if let Some(base_manifest_uri) = base_manifest_uri {
+        env.push(EnvVar {
+            name: "BASE_MANIFEST_URI".to_string(),
+            value: Some(base_manifest_uri.to_string()),
+            ..EnvVar::default()
+        });
+    }
+    if let Some(encryption) = encryption {
+        args.extend(encryption_args(encryption));
+        env.extend(encryption_env(encryption));
+    }
+    JobSpec {
+        backoff_limit: Some(1),
+        template: PodTemplateSpec {
+            metadata: Some(ObjectMeta {
+                labels: Some(labels),
+                ..ObjectMeta::default()
+            }),
+            spec: Some(PodSpec {
+                restart_policy: Some("Never".to_string()),
+                service_account_name: Some(namespace.to_string()),
+                containers: vec![Container {
+                    name: "backup".to_string(),
+                    image: Some(image.to_string()),
+                    args: Some(args),
+                    env: Some(env),
+                    ..Container::default()
+                }],
+                ..PodSpec::default()
+            }),
+        },
+        ..JobSpec::default()
+    }
+}
+
+/// The progress of a temback job
+pub fn job_phase(job: &Job) -> JobPhase {
+    let Some(status) = job.status.as_ref() else {
+        return JobPhase::Pending;
+    };
+    let failed = status
+        .conditions
+        .iter()
+        .flatten()
+        .any(|condition| condition.type_ == "Failed" && condition.status == "True");
+    if status.succeeded.unwrap_or(0) > 0 {
+        JobPhase::Succeeded
+    } else if failed || status.failed.unwrap_or(0) > 0 {
+        JobPhase::Failed
+    } else if status.active.unwrap_or(0) > 0 {
+        JobPhase::Running
+    } else {
+        JobPhase::Pending
+    }
+}
+
+/// Start a Job taking a logical backup of an instance, of one database or some of its
+/// tables when requested. Incremental backups build on the newest backup of the chain of the
+/// newest full backup and are stored with that chain, or are taken in full when it has no
+/// manifest. Filtered dumps are always taken in full.
+pub async fn start_backup(
+    kubernetes_client: Client,
+    store: &BackupStore,
+    image: &str,
+    encryption: Option<&BackupEncryption>,
+    namespace: &str,
+    request: &BackupRequest,
+) -> Result<BackupJob, BackupError> {
+    validate_backup_request(request)?;
+    if encryption.is_some() {
+        check_encryption_support(image)?;
+    }
+    let base = match request.mode {
+        BackupMode::Incremental if request.database.is_none() => {
+            store.latest_manifest(namespace).await?
+        }
+        _ => None,
+    };
+    let mode = if base.is_some() {
+        BackupMode::Incremental
+    } else {
+        BackupMode::Full
+    };
+    let job_id = format!("backup-{}", Utc::now().format("%Y%m%d%H%M%S%3f"));
+    let labels = BTreeMap::from([
+        ("app".to_string(), DUMP_APP.to_string()),
+        ("coredb.io/name".to_string(), namespace.to_string()),
+    ]);
+    // Filtered dumps are kept apart from the backups the instance is restored from, and
+    // incremental backups with the full backup they were taken on top of
+    let backup_uri = match (&request.database, &base) {
+        (Some(_), _) => store.filtered_uri(namespace),
+        (None, Some((artifact, _))) => store.incremental_uri(
+            namespace,
+            artifact.incremental_base.as_ref().unwrap_or(&artifact.name),
+        ),
+        (None, None) => store.namespace_uri(namespace),
+    };
+    let mut spec = backup_job_spec(
+        image,
+        &backup_uri,
+        base.as_ref().map(|(_, manifest_uri)| manifest_uri.as_str()),
+        encryption,
+        namespace,
+        request,
+        labels.clone(),
+    );
+    spec.ttl_seconds_after_finished = Some(FINISHED_JOB_TTL_SECONDS);
+    let job = Job {
+        metadata: ObjectMeta {
+            name: Some(job_id.clone()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(labels),
+            annotations: request.database.as_ref().map(|database| {
+                BTreeMap::from([(DATABASE_ANNOTATION.to_string(), database.clone())])
+            }),
+            ..ObjectMeta::default()
+        },
+        spec: Some(spec),
+        status: None,
+    };
+
+    let jobs: Api<Job> = Api::namespaced(kubernetes_client, namespace);
+    let job = jobs
+        .create(&PostParams::default(), &job)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?;
+    info!(
+        "Started {:?} backup job {} of {}, database {}",
+        mode,
+        job_id,
+        namespace,
+        request.database.as_deref().unwrap_or("all")
+    );
+    Ok(BackupJob {
+        job_id,
+        namespace: namespace.to_string(),
+        database: request.database.clone(),
+        mode,
+        incremental_from: base.map(|(artifact, _)| artifact.name),
+        status: job_phase(&job),
+        started_at: job
+            .status
+            .as_ref()
+            .and_then(|s| s.start_time.clone())
+            .map(|t| t.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(database: Option<&str>, schema: Option<&str>, tables: &[&str]) -> BackupRequest {
+        BackupRequest {
+            database: database.map(str::to_string),
+            schema: schema.map(str::to_string),
+            include_tables: tables.iter().map(|t| t.to_string()).collect(),
+            exclude_tables: vec![],
+            mode: BackupMode::Full,
+        }
+    }
+
+    #[test]
+    fn test_validate_backup_request() {
+        assert!(validate_backup_request(&BackupRequest::default()).is_ok());
+        assert!(validate_backup_request(&request(Some("app"), Some("public"), &[])).is_ok());
+        assert!(validate_backup_request(&request(Some("app"), None, &["public.orders_*"])).is_ok());
+        assert!(matches!(
+            validate_backup_request(&request(None, Some("public"), &[])),
+            Err(BackupError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            validate_backup_request(&request(Some("$(RW_URI)"), None, &[])),
+            Err(BackupError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            validate_backup_request(&request(Some("app"), None, &["orders; drop"])),
+            Err(BackupError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_backup_args() {
+        assert_eq!(
+            backup_args(&BackupRequest::default(), false),
+            vec![
+                "backup",
+                "--uri",
+                "$(RW_URI)",
+                "--to",
+                "$(BACKUP_URI)",
+                "--manifest"
+            ]
+        );
+        let mut filtered = request(Some("app"), Some("public"), &["public.orders"]);
+        filtered.exclude_tables = vec!["public.audit".to_string()];
+        assert_eq!(
+            backup_args(&filtered, false)[6..],
+            [
+                "--dbname",
+                "app",
+                "--schema",
+                "public",
+                "--table",
+                "public.orders",
+                "--exclude-table",
+                "public.audit"
+            ]
+        );
+        assert_eq!(
+            backup_args(&filtered, true)[6..8],
+            ["--incremental-from", "$(BASE_MANIFEST_URI)"]
+        );
+    }
+
+    #[test]
+    fn test_incremental_backup_job_spec() {
+        let spec = backup_job_spec(
+            "temback:latest",
+            "s3://backups/temback/org-acme-inst-db/",
+            Some("s3://backups/temback/org-acme-inst-db/temback-1.tar.gz.manifest.json"),
+            None,
+            "org-acme-inst-db",
+            &BackupRequest::default(),
+            BTreeMap::new(),
+        );
+        let container = &spec.template.spec.unwrap().containers[0];
+        assert!(container
+            .args
+            .as_ref()
+            .unwrap()
+            .contains(&"--incremental-from".to_string()));
+        assert!(container
+            .env
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|env| env.name == "BASE_MANIFEST_URI"));
+    }
+
+    #[test]
+    fn test_encrypted_backup_job_spec() {
+        let encryption = BackupEncryption {
+            kms_key_id: Some("alias/tembo-backups".to_string()),
+            public_key: None,
+        };
+        let spec = backup_job_spec(
+            "temback:latest",
+            "s3://backups/temback/org-acme-inst-db/",
+            None,
+            Some(&encryption),
+            "org-acme-inst-db",
+            &BackupRequest::default(),
+            BTreeMap::new(),
+        );
+        let container = &spec.template.spec.unwrap().containers[0];
+        assert_eq!(
+            container.args.as_ref().unwrap()[6..],
+            ["--encrypt-kms-key", "$(ENCRYPTION_KMS_KEY_ID)"]
+        );
+        assert_eq!(
+            container.env.as_ref().unwrap()[2].value.as_deref(),
+            Some("alias/tembo-backups")
+        );
+    }
+}
@@ -0,0 +1,822 @@
+use crate::backups::encryption::parse_artifact_encryption;
+use crate::backups::types::{ArtifactEncryption, BackupArtifact, BackupDownload};
+use crate::config::Config;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::types::ChecksumMode;
+use aws_sdk_s3::Client;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use lazy_static::lazy_static;
+use log::{error, info};
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub mod base_backups;
+pub mod encryption;
+pub mod job;
+pub mod restore;
+pub mod schedule;
+pub mod types;
+
+lazy_static! {
+    static ref BACKUP_NAME: Regex = Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._-]*$").unwrap();
+}
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("Object storage error: {0}")]
+    ObjectStore(String),
+
+    #[error("Backup not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid backup name: {0}")]
+    InvalidName(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Refusing to delete the only remaining backup: {0}")]
+    LastBackup(String),
+
+    #[error("Refusing to delete a backup incremental backups build on: {0}")]
+    HasIncrementals(String),
+
+    #[error("The temback image cannot encrypt backups: {0}")]
+    UnsupportedImage(String),
+}
+
+// temback stores the status of each artifact next to it, including the key it was encrypted
+// with, as <artifact>.status.json
+const STATUS_SUFFIX: &str = ".status.json";
+// and, with --manifest, the tables it dumped and the WAL position it dumped them at as
+// <artifact>.manifest.json, which incremental backups build on
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+// Dumps of a single database, schema or tables are stored apart from the backups of the whole
+// instance, under <prefix>/<namespace>/filtered/, so they are never restored in place of a
+// backup of the instance nor kept as its last backup
+const FILTERED_DIR: &str = "filtered/";
+// Incremental backups are stored under <prefix>/<namespace>/incremental/<base>/, where <base>
+// is the full backup they were taken on top of. An incremental backup is restored after its
+// base and the incremental backups stored before it, which are kept as long as it is.
+const INCREMENTAL_DIR: &str = "incremental/";
+
+// The key, size and last modification time of an object
+type StoredObject = (String, i64, Option<DateTime<Utc>>);
+
+/// Temback artifacts of each instance, stored in the bucket under `<prefix>/<namespace>/`
+#[derive(Clone, Debug)]
+pub struct BackupStore {
+    client: Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl BackupStore {
+    /// Returns None when no backups bucket is configured
+    pub async fn from_config(cfg: &Config) -> Option<Self> {
+        if cfg.temback_bucket.is_empty() {
+            return None;
+        }
+        let sdk_config = aws_config::load_from_env().await;
+        Some(Self {
+            client: Client::new(&sdk_config),
+            bucket: cfg.temback_bucket.clone(),
+            prefix: cfg.temback_prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    fn namespace_prefix(&self, namespace: &str) -> String {
+        format!("{}/{}/", self.prefix, namespace)
+    }
+
+    /// The URI of the directory of the backup artifacts of an instance, for the jobs taking them
+    pub fn namespace_uri(&self, namespace: &str) -> String {
+        format!("s3://{}/{}", self.bucket, self.namespace_prefix(namespace))
+    }
+
+    /// The URI of the directory of the filtered dumps of an instance, for the jobs taking them
+    pub fn filtered_uri(&self, namespace: &str) -> String {
+        format!("{}{}", self.namespace_uri(namespace), FILTERED_DIR)
+    }
+
+    /// The URI of the directory of the incremental backups taken on top of a full backup of an
+    /// instance, for the jobs taking them
+    pub fn incremental_uri(&self, namespace: &str, base: &str) -> String {
+        format!(
+            "{}{}{}/",
+            self.namespace_uri(namespace),
+            INCREMENTAL_DIR,
+            base
+        )
+    }
+
+    /// The URI of an object in the directory of an instance
+    pub fn object_uri(&self, namespace: &str, name: &str) -> String {
+        format!("{}{}", self.namespace_uri(namespace), name)
+    }
+
+    /// The URI of a full or incremental backup of an instance, for the jobs restoring it
+    pub fn artifact_uri(&self, namespace: &str, artifact: &BackupArtifact) -> String {
+        self.object_uri(namespace, &artifact_path(artifact))
+    }
+
+    /// List the full and incremental backups of an instance, newest first
+    pub async fn list(&self, namespace: &str) -> Result<Vec<BackupArtifact>, BackupError> {
+        let prefix = self.namespace_prefix(namespace);
+        Ok(instance_artifacts(
+            &prefix,
+            self.list_objects(&prefix).await?,
+        ))
+    }
+
+    /// List the dumps of a single database, schema or tables of an instance, newest first
+    pub async fn list_filtered(&self, namespace: &str) -> Result<Vec<BackupArtifact>, BackupError> {
+        let prefix = format!("{}{}", self.namespace_prefix(namespace), FILTERED_DIR);
+        Ok(artifacts_in(&prefix, self.list_objects(&prefix).await?))
+    }
+
+    // The key of a backup artifact or filtered dump of an instance, and whether it is a
+    // filtered dump
+    async fn artifact_key(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<(String, bool), BackupError> {
+        if !is_valid_backup_name(name) {
+            return Err(BackupError::InvalidName(name.to_string()));
+        }
+        let prefix = self.namespace_prefix(namespace);
+        if let Some(artifact) = self.list(namespace).await?.iter().find(|a| a.name == name) {
+            return Ok((format!("{}{}", prefix, artifact_path(artifact)), false));
+        }
+        if self
+            .list_filtered(namespace)
+            .await?
+            .iter()
+            .any(|a| a.name == name)
+        {
+            return Ok((format!("{}{}{}", prefix, FILTERED_DIR, name), true));
+        }
+        Err(BackupError::NotFound(name.to_string()))
+    }
+
+    /// The newest backup of the newest full backup chain of an instance, when it is stored
+    /// with a manifest, and the URI of its manifest, for incremental backups to build on
+    pub async fn latest_manifest(
+        &self,
+        namespace: &str,
+    ) -> Result<Option<(BackupArtifact, String)>, BackupError> {
+        let prefix = self.namespace_prefix(namespace);
+        let objects = self.list_objects(&prefix).await?;
+        let names: Vec<String> = objects
+            .iter()
+            .filter_map(|(key, _, _)| key.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        let artifacts = instance_artifacts(&prefix, objects);
+        Ok(
+            latest_manifest_artifact(&artifacts, &names).map(|artifact| {
+                let manifest_uri = self.object_uri(
+                    namespace,
+                    &format!("{}{}", artifact_path(&artifact), MANIFEST_SUFFIX),
+                );
+                (artifact, manifest_uri)
+            }),
+        )
+    }
+
+    /// Delete a backup artifact or filtered dump of an instance and its status file. The only
+    /// remaining full backup of an instance is never deleted, nor the backups incremental
+    /// backups build on.
+    pub async fn delete(&self, namespace: &str, name: &str) -> Result<(), BackupError> {
+        let (key, filtered) = self.artifact_key(namespace, name).await?;
+        if !filtered {
+            check_deletable(&self.list(namespace).await?, name)?;
+        }
+        self.delete_object(&key).await?;
+        // Deleting a missing object succeeds, artifacts stored without a status file or
+        // manifest included
+        self.delete_object(&format!("{}{}", key, STATUS_SUFFIX))
+            .await?;
+        self.delete_object(&format!("{}{}", key, MANIFEST_SUFFIX))
+            .await
+    }
+
+    /// The key a backup artifact of an instance was encrypted with, from its status file.
+    /// Artifacts stored without a status file are not encrypted.
+    pub async fn artifact_encryption(
+        &self,
+        namespace: &str,
+        artifact: &BackupArtifact,
+    ) -> Result<Option<ArtifactEncryption>, BackupError> {
+        self.encryption_of(&format!(
+            "{}{}",
+            self.namespace_prefix(namespace),
+            artifact_path(artifact)
+        ))
+        .await
+    }
+
+    async fn encryption_of(&self, key: &str) -> Result<Option<ArtifactEncryption>, BackupError> {
+        let key = format!("{}{}", key, STATUS_SUFFIX);
+        let status = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await
+        {
+            Ok(status) => status,
+            Err(SdkError::ServiceError(e)) if e.err().is_no_such_key() => return Ok(None),
+            Err(e) => return Err(BackupError::ObjectStore(e.to_string())),
+        };
+        let body = status
+            .body
+            .collect()
+            .await
+            .map_err(|e| BackupError::ObjectStore(e.to_string()))?;
+        parse_artifact_encryption(&body.into_bytes())
+    }
+
+    /// A pre-signed URL to download a backup artifact or filtered dump of an instance, valid
+    /// for `expires_in`, along with its size, checksums and encryption
+    pub async fn presigned_download(
+        &self,
+        namespace: &str,
+        name: &str,
+        expires_in: std::time::Duration,
+    ) -> Result<BackupDownload, BackupError> {
+        let (key, _) = self.artifact_key(namespace, name).await?;
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+            .map_err(|e| BackupError::ObjectStore(e.to_string()))?;
+
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| BackupError::InvalidRequest(e.to_string()))?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .presigned(presigning_config)
+            .await
+            .map_err(|e| BackupError::ObjectStore(e.to_string()))?;
+
+        Ok(BackupDownload {
+            name: name.to_string(),
+            url: presigned.uri().to_string(),
+            expires_at: Utc::now()
+                + Duration::from_std(expires_in).unwrap_or_else(|_| Duration::zero()),
+            size_bytes: head.content_length(),
+            etag: head.e_tag().map(unquote_etag),
+            checksum_sha256: head.checksum_sha256().map(str::to_string),
+            encryption: self.encryption_of(&key).await?,
+        })
+    }
+
+    /// Delete all backup artifacts and filtered dumps older than the retention period, along
+    /// with their status files and manifests, returns how many were deleted. The newest full
+    /// backup of each instance is kept, and a full backup with its incremental backups only
+    /// expire together.
+    pub async fn sweep(&self, retention: Duration) -> Result<usize, BackupError> {
+        let cutoff = Utc::now() - retention;
+        let prefix = format!("{}/", self.prefix);
+        let objects = self.list_objects(&prefix).await?;
+        let mut deleted = 0;
+        for key in expired_artifacts(&prefix, objects, cutoff) {
+            self.delete_object(&key).await?;
+            self.delete_object(&format!("{}{}", key, STATUS_SUFFIX))
+                .await?;
+            self.delete_object(&format!("{}{}", key, MANIFEST_SUFFIX))
+                .await?;
+            info!("Deleted backup {} past its retention period", key);
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<StoredObject>, BackupError> {
+        self.list_objects_in(&self.bucket, prefix).await
+    }
+
+    /// List the objects under a prefix of a bucket, e.g. the one of the base backups
+    pub async fn list_objects_in(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<Vec<StoredObject>, BackupError> {
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let response = self
+                .client
+                .list_objects_v2()
+                .bucket(bucket)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|e| BackupError::ObjectStore(e.to_string()))?;
+            for object in response.contents().unwrap_or_default() {
+                let Some(key) = object.key() else {
+                    continue;
+                };
+                let last_modified = object
+                    .last_modified()
+                    .and_then(|t| Utc.timestamp_opt(t.secs(), t.subsec_nanos()).single());
+                objects.push((key.to_string(), object.size(), last_modified));
+            }
+            if !response.is_truncated() {
+                break;
+            }
+            continuation_token = response.next_continuation_token().map(str::to_string);
+        }
+        Ok(objects)
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), BackupError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| BackupError::ObjectStore(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Periodically delete backup artifacts older than TEMBACK_RETENTION_DAYS
+pub async fn run_retention_sweeper(store: BackupStore, cfg: Config) {
+    let retention = Duration::days(cfg.temback_retention_days);
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        cfg.temback_retention_sweep_interval_sec,
+    ));
+    loop {
+        interval.tick().await;
+        match store.sweep(retention).await {
+            Ok(deleted) => info!("Backup retention sweep deleted {} backups", deleted),
+            Err(e) => error!("Backup retention sweep failed: {}", e),
+        }
+    }
+}
+
+pub fn is_valid_backup_name(name: &str) -> bool {
+    BACKUP_NAME.is_match(name) && !name.contains("..")
+}
+
+fn check_deletable(artifacts: &[BackupArtifact], name: &str) -> Result<(), BackupError> {
+    let Some(artifact) = artifacts.iter().find(|a| a.name == name) else {
+        return Err(BackupError::NotFound(name.to_string()));
+    };
+    if artifacts.iter().any(|a| builds_on(a, artifact)) {
+        return Err(BackupError::HasIncrementals(name.to_string()));
+    }
+    let full_backups = artifacts
+        .iter()
+        .filter(|a| a.incremental_base.is_none())
+        .count();
+    if artifact.incremental_base.is_none() && full_backups == 1 {
+        return Err(BackupError::LastBackup(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Whether restoring an incremental backup needs another backup: the full backup it was
+/// taken on top of, or an incremental backup of the same base stored before it
+pub fn builds_on(artifact: &BackupArtifact, other: &BackupArtifact) -> bool {
+    match (&artifact.incremental_base, &other.incremental_base) {
+        (Some(base), None) => *base == other.name,
+        (Some(base), Some(other_base)) => {
+            base == other_base && other.last_modified < artifact.last_modified
+        }
+        (None, _) => false,
+    }
+}
+
+// The path of a full or incremental backup in the directory of its instance
+fn artifact_path(artifact: &BackupArtifact) -> String {
+    match &artifact.incremental_base {
+        Some(base) => format!("{}{}/{}", INCREMENTAL_DIR, base, artifact.name),
+        None => artifact.name.clone(),
+    }
+}
+
+// The backup artifacts among the objects of an instance's directory, newest first
+fn artifacts_in(prefix: &str, objects: Vec<StoredObject>) -> Vec<BackupArtifact> {
+    let mut artifacts: Vec<BackupArtifact> = objects
+        .into_iter()
+        .filter_map(|(key, size, last_modified)| {
+            let name = key.strip_prefix(prefix)?;
+            // Only artifacts directly in the instance's directory, not their status files
+            // or manifests
+            if name.is_empty()
+                || name.contains('/')
+                || name.ends_with(STATUS_SUFFIX)
+                || name.ends_with(MANIFEST_SUFFIX)
+            {
+                return None;
+            }
+            Some(backup_artifact(name, size, last_modified, Utc::now()))
+        })
+        .collect();
+    artifacts.sort_by_key(|a| Reverse(a.last_modified));
+    artifacts
+}
+
+// The full backups and the incremental backups among the objects of an instance's directory,
+// newest first
+fn instance_artifacts(prefix: &str, objects: Vec<StoredObject>) -> Vec<BackupArtifact> {
+    let incremental_prefix = format!("{}{}", prefix, INCREMENTAL_DIR);
+    let mut by_base: BTreeMap<String, Vec<StoredObject>> = BTreeMap::new();
+    for object in &objects {
+        if let Some((base, _)) = object
+            .0
+            .strip_prefix(&incremental_prefix)
+            .and_then(|name| name.split_once('/'))
+        {
+            by_base
+                .entry(base.to_string())
+                .or_default()
+                .push(object.clone());
+        }
+    }
+    let mut artifacts = artifacts_in(prefix, objects);
+    for (base, objects) in by_base {
+        let base_prefix = format!("{}{}/", incremental_prefix, base);
+        artifacts.extend(
+            artifacts_in(&base_prefix, objects)
+                .into_iter()
+                .map(|artifact| BackupArtifact {
+                    incremental_base: Some(base.clone()),
+                    ..artifact
+                }),
+        );
+    }
+    artifacts.sort_by_key(|a| Reverse(a.last_modified));
+    artifacts
+}
+
+// The keys of the artifacts and filtered dumps last modified before the cutoff, among the
+// objects of all instances' directories. A full backup and its incremental backups expire
+// together, once the newest of them does. The newest full backup of each instance is never
+// expired, so an instance which stopped taking backups can still be restored.
+fn expired_artifacts(
+    prefix: &str,
+    objects: Vec<StoredObject>,
+    cutoff: DateTime<Utc>,
+) -> Vec<String> {
+    let mut by_namespace: BTreeMap<String, Vec<StoredObject>> = BTreeMap::new();
+    for object in objects {
+        let Some((namespace, _)) = object
+            .0
+            .strip_prefix(prefix)
+            .and_then(|name| name.split_once('/'))
+        else {
+            continue;
+        };
+        by_namespace
+            .entry(namespace.to_string())
+            .or_default()
+            .push(object);
+    }
+    let is_expired = |artifact: &BackupArtifact| artifact.last_modified.is_some_and(|t| t < cutoff);
+    let mut expired = Vec::new();
+    for (namespace, objects) in by_namespace {
+        let namespace_prefix = format!("{}{}/", prefix, namespace);
+        let filtered_prefix = format!("{}{}", namespace_prefix, FILTERED_DIR);
+        let artifacts = instance_artifacts(&namespace_prefix, objects.clone());
+        let newest_full = artifacts
+            .iter()
+            .find(|artifact| artifact.incremental_base.is_none())
+            .map(|artifact| artifact.name.clone());
+        // The backups of each chain, by the name of the full backup it starts from
+        let mut chains: BTreeMap<&str, Vec<&BackupArtifact>> = BTreeMap::new();
+        for artifact in &artifacts {
+            let base = artifact
+                .incremental_base
+                .as_deref()
+                .unwrap_or(&artifact.name);
+            chains.entry(base).or_default().push(artifact);
+        }
+        for (base, chain) in chains {
+            if Some(base) == newest_full.as_deref() || !chain.iter().all(|a| is_expired(a)) {
+                continue;
+            }
+            expired.extend(
+                chain
+                    .into_iter()
+                    .map(|artifact| format!("{}{}", namespace_prefix, artifact_path(artifact))),
+            );
+        }
+        expired.extend(
+            artifacts_in(&filtered_prefix, objects)
+                .into_iter()
+                .filter(is_expired)
+                .map(|artifact| format!("{}{}", filtered_prefix, artifact.name)),
+        );
+    }
+    expired
+}
+
+// The newest backup of the chain of the newest full backup, of artifacts listed newest
+// first, when it has a manifest next to it. Manifests left behind by a deleted artifact are
+// ignored.
+fn latest_manifest_artifact(
+    artifacts: &[BackupArtifact],
+    names: &[String],
+) -> Option<BackupArtifact> {
+    let base = artifacts.iter().find(|a| a.incremental_base.is_none())?;
+    let newest = artifacts
+        .iter()
+        .find(|a| a.incremental_base.as_ref() == Some(&base.name))
+        .unwrap_or(base);
+    let manifest = format!("{}{}", artifact_path(newest), MANIFEST_SUFFIX);
+    names.contains(&manifest).then(|| newest.clone())
+}
+
+// S3 returns ETags within double quotes
+fn unquote_etag(etag: &str) -> String {
+    etag.trim_matches('"').to_string()
+}
+
+fn backup_artifact(
+    name: &str,
+    size: i64,
+    last_modified: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> BackupArtifact {
+    BackupArtifact {
+        name: name.to_string(),
+        size_bytes: size,
+        last_modified,
+        age_seconds: last_modified.map(|last_modified| (now - last_modified).num_seconds()),
+        incremental_base: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_backup_name() {
+        assert!(is_valid_backup_name("temback-20240601T120000.tar.gz"));
+        assert!(!is_valid_backup_name(""));
+        assert!(!is_valid_backup_name(
+            "../org-other-inst-prod/backup.tar.gz"
+        ));
+        assert!(!is_valid_backup_name("nested/backup.tar.gz"));
+        assert!(!is_valid_backup_name(".hidden"));
+    }
+
+    fn incremental(name: &str, base: &str, last_modified: DateTime<Utc>) -> BackupArtifact {
+        BackupArtifact {
+            incremental_base: Some(base.to_string()),
+            ..backup_artifact(name, 1024, Some(last_modified), last_modified)
+        }
+    }
+
+    #[test]
+    fn test_check_deletable() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let artifacts = vec![
+            backup_artifact("temback-2.tar.gz", 1024, Some(now), now),
+            backup_artifact("temback-1.tar.gz", 1024, Some(now), now),
+        ];
+        assert!(check_deletable(&artifacts, "temback-1.tar.gz").is_ok());
+        assert!(matches!(
+            check_deletable(&artifacts, "temback-3.tar.gz"),
+            Err(BackupError::NotFound(_))
+        ));
+        assert!(matches!(
+            check_deletable(&artifacts[..1], "temback-2.tar.gz"),
+            Err(BackupError::LastBackup(_))
+        ));
+
+        // Only the newest incremental backup of a chain can be deleted, then its base
+        let artifacts = vec![
+            incremental("temback-4.tar.gz", "temback-2.tar.gz", now),
+            incremental(
+                "temback-3.tar.gz",
+                "temback-2.tar.gz",
+                now - Duration::hours(1),
+            ),
+            backup_artifact(
+                "temback-2.tar.gz",
+                1024,
+                Some(now - Duration::hours(2)),
+                now,
+            ),
+            backup_artifact(
+                "temback-1.tar.gz",
+                1024,
+                Some(now - Duration::hours(3)),
+                now,
+            ),
+        ];
+        assert!(check_deletable(&artifacts, "temback-4.tar.gz").is_ok());
+        assert!(matches!(
+            check_deletable(&artifacts, "temback-3.tar.gz"),
+            Err(BackupError::HasIncrementals(_))
+        ));
+        assert!(matches!(
+            check_deletable(&artifacts, "temback-2.tar.gz"),
+            Err(BackupError::HasIncrementals(_))
+        ));
+        assert!(check_deletable(&artifacts, "temback-1.tar.gz").is_ok());
+        // Incremental backups do not count as a remaining backup
+        assert!(matches!(
+            check_deletable(&artifacts[..3], "temback-2.tar.gz"),
+            Err(BackupError::HasIncrementals(_))
+        ));
+        assert!(check_deletable(&artifacts[..3], "temback-4.tar.gz").is_ok());
+    }
+
+    #[test]
+    fn test_instance_artifacts() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let objects = vec![
+            (
+                "temback/org-a/temback-1.tar.gz".to_string(),
+                1024,
+                Some(now - Duration::hours(2)),
+            ),
+            (
+                "temback/org-a/incremental/temback-1.tar.gz/temback-2.tar.gz".to_string(),
+                64,
+                Some(now - Duration::hours(1)),
+            ),
+            (
+                "temback/org-a/incremental/temback-1.tar.gz/temback-2.tar.gz.status.json"
+                    .to_string(),
+                64,
+                Some(now - Duration::hours(1)),
+            ),
+            (
+                "temback/org-a/filtered/temback-3.tar.gz".to_string(),
+                64,
+                Some(now),
+            ),
+        ];
+        let artifacts = instance_artifacts("temback/org-a/", objects);
+        assert_eq!(
+            artifacts
+                .iter()
+                .map(|a| (a.name.as_str(), a.incremental_base.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("temback-2.tar.gz", Some("temback-1.tar.gz")),
+                ("temback-1.tar.gz", None),
+            ]
+        );
+        assert_eq!(
+            artifact_path(&artifacts[0]),
+            "incremental/temback-1.tar.gz/temback-2.tar.gz"
+        );
+        assert_eq!(artifact_path(&artifacts[1]), "temback-1.tar.gz");
+    }
+
+    #[test]
+    fn test_latest_manifest_artifact() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let artifacts = vec![
+            incremental("temback-4.tar.gz", "temback-2.tar.gz", now),
+            incremental(
+                "temback-3.tar.gz",
+                "temback-2.tar.gz",
+                now - Duration::hours(1),
+            ),
+            backup_artifact(
+                "temback-2.tar.gz",
+                1024,
+                Some(now - Duration::hours(2)),
+                now,
+            ),
+            backup_artifact(
+                "temback-1.tar.gz",
+                1024,
+                Some(now - Duration::hours(3)),
+                now,
+            ),
+        ];
+        let names: Vec<String> = [
+            "incremental/temback-2.tar.gz/temback-4.tar.gz.manifest.json",
+            "incremental/temback-2.tar.gz/temback-3.tar.gz.manifest.json",
+            "temback-2.tar.gz.manifest.json",
+            "temback-1.tar.gz.manifest.json",
+            "temback-0.tar.gz.manifest.json",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+        // Incremental backups build on the newest backup of the newest chain
+        assert_eq!(
+            latest_manifest_artifact(&artifacts, &names).map(|a| a.name),
+            Some("temback-4.tar.gz".to_string())
+        );
+        assert_eq!(latest_manifest_artifact(&artifacts, &names[1..]), None);
+        assert_eq!(
+            latest_manifest_artifact(&artifacts[2..], &names).map(|a| a.name),
+            Some("temback-2.tar.gz".to_string())
+        );
+        assert_eq!(latest_manifest_artifact(&artifacts[2..], &names[3..]), None);
+        assert_eq!(latest_manifest_artifact(&[], &names), None);
+    }
+
+    #[test]
+    fn test_expired_artifacts() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let cutoff = now - Duration::days(30);
+        let old = Some(now - Duration::days(40));
+        let older = Some(now - Duration::days(50));
+        let objects = vec![
+            ("temback/org-a/temback-2.tar.gz".to_string(), 1024, old),
+            (
+                "temback/org-a/temback-2.tar.gz.status.json".to_string(),
+                64,
+                old,
+            ),
+            ("temback/org-a/temback-1.tar.gz".to_string(), 1024, older),
+            (
+                "temback/org-b/temback-3.tar.gz".to_string(),
+                1024,
+                Some(now),
+            ),
+            ("temback/org-b/temback-2.tar.gz".to_string(), 1024, old),
+            ("temback/org-c/temback-1.tar.gz".to_string(), 1024, older),
+            // Filtered dumps expire, even the newest one, and are not the newest artifact
+            (
+                "temback/org-c/filtered/temback-2.tar.gz".to_string(),
+                1024,
+                old,
+            ),
+            (
+                "temback/org-c/filtered/temback-3.tar.gz".to_string(),
+                1024,
+                Some(now),
+            ),
+            // Chains expire with their newest backup, and the newest chain is kept
+            ("temback/org-d/temback-1.tar.gz".to_string(), 1024, older),
+            (
+                "temback/org-d/incremental/temback-1.tar.gz/temback-2.tar.gz".to_string(),
+                64,
+                old,
+            ),
+            ("temback/org-d/temback-3.tar.gz".to_string(), 1024, older),
+            (
+                "temback/org-d/incremental/temback-3.tar.gz/temback-4.tar.gz".to_string(),
+                64,
+                Some(now),
+            ),
+            ("temback/org-d/temback-5.tar.gz".to_string(), 1024, old),
+            (
+                "temback/org-d/incremental/temback-5.tar.gz/temback-6.tar.gz".to_string(),
+                64,
+                old,
+            ),
+        ];
+        assert_eq!(
+            expired_artifacts("temback/", objects, cutoff),
+            vec![
+                "temback/org-a/temback-1.tar.gz".to_string(),
+                "temback/org-b/temback-2.tar.gz".to_string(),
+                "temback/org-c/filtered/temback-2.tar.gz".to_string(),
+                "temback/org-d/incremental/temback-1.tar.gz/temback-2.tar.gz".to_string(),
+                "temback/org-d/temback-1.tar.gz".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unquote_etag() {
+        assert_eq!(
+            unquote_etag("\"9b2cf535f27731c974343645a3985328\""),
+            "9b2cf535f27731c974343645a3985328"
+        );
+        assert_eq!(unquote_etag("9b2cf535f27731c9-2"), "9b2cf535f27731c9-2");
+    }
+
+    #[test]
+    fn test_backup_artifact_age() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let artifact = backup_artifact("temback.tar.gz", 1024, Some(now - Duration::hours(2)), now);
+        assert_eq!(artifact.age_seconds, Some(7200));
+        assert_eq!(artifact.size_bytes, 1024);
+
+        let artifact = backup_artifact("temback.tar.gz", 1024, None, now);
+        assert_eq!(artifact.age_seconds, None);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::backups::job::{job_phase, rw_uri_env};
+use crate::backups::types::{BackupArtifact, EncryptionMethod, RestoreJob};
+use crate::backups::{builds_on, is_valid_backup_name, BackupError, BackupStore};
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::batch::v1::{Job, JobSpec};
+use k8s_openapi::api::core::v1::{Container, EnvVar, PodSpec, PodTemplateSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::PostParams;
+use kube::{Api, Client};
+use log::info;
+use std::collections::BTreeMap;
+
+const RESTORE_APP: &str = "temback-restore";
+const SOURCE_NAMESPACE_ANNOTATION: &str = "tembo.io/restore-source-namespace";
+const BACKUP_NAME_ANNOTATION: &str = "tembo.io/restore-backup-name";
+// Finished restore jobs are kept for a week, so their outcome can be queried
+const FINISHED_JOB_TTL_SECONDS: i32 = 7 * 86400;
+
+/// Choose the backup artifact to restore, by its name or as the newest one stored at or
+/// before a point in time. The artifacts are sorted newest first.
+pub fn select_backup(
+    artifacts: &[BackupArtifact],
+    backup_name: Option<&str>,
+    point_in_time: Option<DateTime<Utc>>,
+) -> Result<BackupArtifact, BackupError> {
+    match (backup_name, point_in_time) {
+        (Some(name), None) => {
+            if !is_valid_backup_name(name) {
+                return Err(BackupError::InvalidName(name.to_string()));
+            }
+            artifacts
+                .iter()
+                .find(|a| a.name == name)
+                .cloned()
+                .ok_or_else(|| BackupError::NotFound(name.to_string()))
+        }
+        (None, Some(point_in_time)) => artifacts
+            .iter()
+            .find(|a| a.last_modified.is_some_and(|t| t <= point_in_time))
+            .cloned()
+            .ok_or_else(|| BackupError::NotFound(format!("no backup before {}", point_in_time))),
+        _ => Err(BackupError::InvalidRequest(
+            "Either backup_name or point_in_time is required".to_string(),
+        )),
+    }
+}
+
+/// The backups to restore in order for a backup: the full backup itself, or the full backup
+/// an incremental backup was taken on top of followed by the incremental backups of the same
+/// base stored before it, and the incremental backup itself
+pub fn restore_chain(
+    artifacts: &[BackupArtifact],
+    artifact: &BackupArtifact,
+) -> Result<Vec<BackupArtifact>, BackupError> {
+    let Some(base) = &artifact.incremental_base else {
+        return Ok(vec![artifact.clone()]);
+    };
+    if !artifacts
+        .iter()
+        .any(|a| a.incremental_base.is_none() && a.name == *base)
+    {
+        return Err(BackupError::NotFound(format!(
+            "{}, the base of {}",
+            base, artifact.name
+        )));
+    }
+    // The artifacts are sorted newest first
+    let mut chain: Vec<BackupArtifact> = artifacts
+        .iter()
+        .filter(|a| builds_on(artifact, a))
+        .rev()
+        .cloned()
+        .collect();
+    chain.push(artifact.clone());
+    Ok(chain)
+}
+
+fn restore_container(
+    name: &str,
+    image: &str,
+    backup_uri: &str,
+    target_namespace: &str,
+) -> Container {
+    Container {
+        name: name.to_string(),
+        image: Some(image.to_string()),
+        args: Some(vec![
+            "restore".to_string(),
+            "--from".to_string(),
+            "$(BACKUP_URI)".to_string(),
+            "--uri".to_string(),
+            "$(RW_URI)".to_string(),
+        ]),
+        env: Some(vec![
+            EnvVar {
+                name: "BACKUP_URI".to_string(),
+                value: Some(backup_uri.to_string()),
+                ..EnvVar::default()
+            },
+            rw_uri_env(target_namespace),
+        ]),
+        ..Container::default()
+    }
+}
+
+// The Job restores the artifacts with temback in order, connecting to the primary of the
+// target instance with the credentials of its connection secret: all but the last are
+// restored by init containers, which run one after the other. It runs with the service
+// account of the instance, which can read the backups bucket.
+fn restore_job(
+    job_id: &str,
+    image: &str,
+    backup_uris: &[String],
+    source_namespace: &str,
+    target_namespace: &str,
+    backup_name: &str,
+) -> Job {
+    let labels = BTreeMap::from([
+        ("app".to_string(), RESTORE_APP.to_string()),
+        ("coredb.io/name".to_string(), target_namespace.to_string()),
+    ]);
+    let annotations = BTreeMap::from([
+        (
+            SOURCE_NAMESPACE_ANNOTATION.to_string(),
+            source_namespace.to_string(),
+        ),
+        (BACKUP_NAME_ANNOTATION.to_string(), backup_name.to_string()),
+    ]);
+    let (backup_uri, earlier_uris) = backup_uris
+        .split_last()
+        .expect("A restore job restores at least one backup");
+
+    Job {
+        metadata: ObjectMeta {
+            name: Some(job_id.to_string()),
+            namespace: Some(target_namespace.to_string()),
+            labels: Some(labels.clone()),
+            annotations: Some(annotations),
+            ..ObjectMeta::default()
+        },
+        spec: Some(JobSpec {
+            // A failed restore may have changed the instance, it is not attempted again
+            backoff_limit: Some(0),
+            ttl_seconds_after_finished: Some(FINISHED_JOB_TTL_SECONDS),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels),
+                    ..ObjectMeta::default()
+                }),
+                spec: Some(PodSpec {
+                    restart_policy: Some("Never".to_string()),
+                    service_account_name: Some(target_namespace.to_string()),
+                    init_containers: (!earlier_uris.is_empty()).then(|| {
+                        earlier_uris
+                            .iter()
+                            .enumerate()
+                            .map(|(i, uri)| {
+                                restore_container(
+                                    &format!("restore-{}", i),
+                                    image,
+                                    uri,
+                                    target_namespace,
+                                )
+                            })
+                            .collect()
+                    }),
+                    containers: vec![restore_container(
+                        "restore",
+                        image,
+                        backup_uri,
+                        target_namespace,
+                    )],
+                    ..PodSpec::default()
+                }),
+            },
+            ..JobSpec::default()
+        }),
+        status: None,
+    }
+}
+
+fn restore_job_summary(job: &Job) -> RestoreJob {
+    let annotations = job.metadata.annotations.clone().unwrap_or_default();
+    let status = job.status.as_ref();
+    let completed_at = status.and_then(|s| s.completion_time.clone()).or_else(|| {
+        status
+            .and_then(|s| s.conditions.as_ref())
+            .and_then(|conditions| conditions.iter().find(|c| c.type_ == "Failed"))
+            .and_then(|c| c.last_transition_time.clone())
+    });
+    RestoreJob {
+        job_id: job.metadata.name.clone().unwrap_or_default(),
+        backup_name: annotations
+            .get(BACKUP_NAME_ANNOTATION)
+            .cloned()
+            .unwrap_or_default(),
+        source_namespace: annotations
+            .get(SOURCE_NAMESPACE_ANNOTATION)
+            .cloned()
+            .unwrap_or_default(),
+        target_namespace: job.metadata.namespace.clone().unwrap_or_default(),
+        status: job_phase(job),
+        started_at: status.and_then(|s| s.start_time.clone()).map(|t| t.0),
+        completed_at: completed_at.map(|t| t.0),
+    }
+}
+
+/// Start a Job restoring a backup artifact of the source instance into the target instance,
+/// after the backups it builds on when it is an incremental backup. temback decrypts
+/// artifacts encrypted with a KMS key, those encrypted with a public key of the customer can
+/// only be downloaded.
+pub async fn start_restore(
+    kubernetes_client: Client,
+    store: &BackupStore,
+    image: &str,
+    source_namespace: &str,
+    target_namespace: &str,
+    chain: &[BackupArtifact],
+) -> Result<RestoreJob, BackupError> {
+    let artifact = chain
+        .last()
+        .ok_or_else(|| BackupError::InvalidRequest("No backup to restore".to_string()))?;
+    for member in chain {
+        if store
+            .artifact_encryption(source_namespace, member)
+            .await?
+            .is_some_and(|encryption| encryption.method == EncryptionMethod::PublicKey)
+        {
+            return Err(BackupError::InvalidRequest(format!(
+                "Backup {} is encrypted with a public key, download it to restore it",
+                member.name
+            )));
+        }
+    }
+    let job_id = format!("restore-{}", Utc::now().format("%Y%m%d%H%M%S%3f"));
+    let backup_uris: Vec<String> = chain
+        .iter()
+        .map(|member| store.artifact_uri(source_namespace, member))
+        .collect();
+    let job = restore_job(
+        &job_id,
+        image,
+        &backup_uris,
+        source_namespace,
+        target_namespace,
+        &artifact.name,
+    );
+    let jobs: Api<Job> = Api::namespaced(kubernetes_client, target_namespace);
+    let job = jobs
+        .create(&PostParams::default(), &job)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?;
+    info!(
+        "Started restore job {} of backup {} of {} into {}, {} backups in all",
+        job_id,
+        artifact.name,
+        source_namespace,
+        target_namespace,
+        chain.len()
+    );
+    Ok(restore_job_summary(&job))
+}
+
+/// Get a restore job of an instance, by its ID
+pub async fn get_restore(
+    kubernetes_client: Client,
+    namespace: &str,
+    job_id: &str,
+) -> Result<RestoreJob, BackupError> {
+    let jobs: Api<Job> = Api::namespaced(kubernetes_client, namespace);
+    let job = jobs
+        .get_opt(job_id)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?;
+    // Only restore jobs can be queried, not the other Jobs of the namespace
+    match job {
+        Some(job)
+            if job
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get("app"))
+                .is_some_and(|app| app == RESTORE_APP) =>
+        {
+            Ok(restore_job_summary(&job))
+        }
+        _ => Err(BackupError::NotFound(job_id.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backups::types::JobPhase;
+    use chrono::{Duration, TimeZone};
+    use k8s_openapi::api::batch::v1::{JobCondition, JobStatus};
+
+    fn artifact(name: &str, last_modified: DateTime<Utc>) -> BackupArtifact {
+        BackupArtifact {
+            name: name.to_string(),
+            size_bytes: 1024,
+            last_modified: Some(last_modified),
+            age_seconds: None,
+            incremental_base: None,
+        }
+    }
+
+    #[test]
+    fn test_select_backup() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let artifacts = vec![
+            artifact("temback-2.tar.gz", now - Duration::hours(1)),
+            artifact("temback-1.tar.gz", now - Duration::days(1)),
+        ];
+
+        let selected = select_backup(&artifacts, Some("temback-1.tar.gz"), None).unwrap();
+        assert_eq!(selected.name, "temback-1.tar.gz");
+        assert!(matches!(
+            select_backup(&artifacts, Some("temback-3.tar.gz"), None),
+            Err(BackupError::NotFound(_))
+        ));
+        assert!(matches!(
+            select_backup(&artifacts, Some("../temback-1.tar.gz"), None),
+            Err(BackupError::InvalidName(_))
+        ));
+
+        let selected = select_backup(&artifacts, None, Some(now)).unwrap();
+        assert_eq!(selected.name, "temback-2.tar.gz");
+        let selected = select_backup(&artifacts, None, Some(now - Duration::hours(2))).unwrap();
+        assert_eq!(selected.name, "temback-1.tar.gz");
+        assert!(matches!(
+            select_backup(&artifacts, None, Some(now - Duration::days(2))),
+            Err(BackupError::NotFound(_))
+        ));
+
+        assert!(matches!(
+            select_backup(&artifacts, None, None),
+            Err(BackupError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            select_backup(&artifacts, Some("temback-1.tar.gz"), Some(now)),
+            Err(BackupError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_restore_chain() {
+        let now = Utc.timestamp_opt(1_717_243_200, 0).unwrap();
+        let incremental = |name: &str, base: &str, last_modified| BackupArtifact {
+            incremental_base: Some(base.to_string()),
+            ..artifact(name, last_modified)
+        };
+        let artifacts = vec![
+            incremental("temback-5.tar.gz", "temback-2.tar.gz", now),
+            incremental(
+                "temback-4.tar.gz",
+                "temback-2.tar.gz",
+                now - Duration::hours(1),
+            ),
+            incremental(
+                "temback-3.tar.gz",
+                "temback-1.tar.gz",
+                now - Duration::hours(2),
+            ),
+            artifact("temback-2.tar.gz", now - Duration::hours(3)),
+        ];
+        let names = |chain: Vec<BackupArtifact>| -> Vec<String> {
+            chain.into_iter().map(|a| a.name).collect()
+        };
+        assert_eq!(
+            names(restore_chain(&artifacts, &artifacts[0]).unwrap()),
+            vec!["temback-2.tar.gz", "temback-4.tar.gz", "temback-5.tar.gz"]
+        );
+        assert_eq!(
+            names(restore_chain(&artifacts, &artifacts[1]).unwrap()),
+            vec!["temback-2.tar.gz", "temback-4.tar.gz"]
+        );
+        assert_eq!(
+            names(restore_chain(&artifacts, &artifacts[3]).unwrap()),
+            vec!["temback-2.tar.gz"]
+        );
+        // The base of an incremental backup is required
+        assert!(matches!(
+            restore_chain(&artifacts, &artifacts[2]),
+            Err(BackupError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_restore_job_chain() {
+        let uris: Vec<String> = ["base", "incremental-1", "incremental-2"]
+            .iter()
+            .map(|name| format!("s3://backups/temback/org-foo-inst-bar/{}", name))
+            .collect();
+        let job = restore_job(
+            "restore-1",
+            "quay.io/tembo/temback:latest",
+            &uris,
+            "org-foo-inst-bar",
+            "org-foo-inst-bar",
+            "incremental-2",
+        );
+        let pod = job.spec.unwrap().template.spec.unwrap();
+        let backup_uri = |container: &Container| {
+            container.env.as_ref().unwrap()[0]
+                .value
+                .clone()
+                .unwrap_or_default()
+        };
+        let init_containers = pod.init_containers.unwrap();
+        assert_eq!(
+            init_containers.iter().map(backup_uri).collect::<Vec<_>>(),
+            uris[..2]
+        );
+        assert_eq!(backup_uri(&pod.containers[0]), uris[2]);
+    }
+
+    #[test]
+    fn test_job_phase() {
+        let mut job = restore_job(
+            "restore-1",
+            "quay.io/tembo/temback:latest",
+            &["s3://backups/temback/org-foo-inst-bar/temback-1.tar.gz".to_string()],
+            "org-foo-inst-bar",
+            "org-foo-inst-baz",
+            "temback-1.tar.gz",
+        );
+        assert_eq!(job_phase(&job), JobPhase::Pending);
+
+        job.status = Some(JobStatus {
+            active: Some(1),
+            ..JobStatus::default()
+        });
+        assert_eq!(job_phase(&job), JobPhase::Running);
+
+        job.status = Some(JobStatus {
+            succeeded: Some(1),
+            ..JobStatus::default()
+        });
+        assert_eq!(job_phase(&job), JobPhase::Succeeded);
+
+        job.status = Some(JobStatus {
+            conditions: Some(vec![JobCondition {
+                type_: "Failed".to_string(),
+                status: "True".to_string(),
+                ..JobCondition::default()
+            }]),
+            ..JobStatus::default()
+        });
+        assert_eq!(job_phase(&job), JobPhase::Failed);
+
+        let summary = restore_job_summary(&job);
+        assert_eq!(summary.backup_name, "temback-1.tar.gz");
+        assert_eq!(summary.source_namespace, "org-foo-inst-bar");
+        assert_eq!(summary.target_namespace, "org-foo-inst-baz");
+    }
+}
@@ -0,0 +1,348 @@
+use crate::backups::encryption::{
+    check_encryption_support, get_encryptions, namespace_organization,
+};
+use crate::backups::job::backup_job_spec;
+use crate::backups::types::{BackupEncryption, BackupRequest, BackupSchedule};
+use crate::backups::{BackupError, BackupStore};
+use crate::config::Config;
+use k8s_openapi::api::batch::v1::{CronJob, CronJobSpec, JobTemplateSpec};
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams};
+use kube::{Api, Client};
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+
+// The schedule of an instance is stored in a ConfigMap of its namespace, which the
+// reconciler turns into a CronJob taking the backups
+const SCHEDULE_CONFIGMAP: &str = "temback-schedule";
+const SCHEDULE_APP: &str = "temback-schedule";
+const SCHEDULE_KEY: &str = "schedule";
+const BACKUP_CRONJOB: &str = "temback-backup";
+const BACKUP_APP: &str = "temback-backup";
+const FIELD_MANAGER: &str = "dataplane-webserver";
+// Jobs of scheduled backups are kept long enough to inspect the last few
+const JOBS_HISTORY_LIMIT: i32 = 3;
+
+/// A cron schedule of five fields, taking backups at most once an hour: the minute field must
+/// be a single minute
+pub fn is_valid_schedule(schedule: &str) -> bool {
+    let fields: Vec<&str> = schedule.split_whitespace().collect();
+    fields.len() == 5
+        && fields[0].parse::<u8>().is_ok_and(|minute| minute < 60)
+        && fields.iter().all(|field| {
+            field
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "*/,-".contains(c))
+        })
+}
+
+fn labels(app: &str, namespace: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app".to_string(), app.to_string()),
+        ("coredb.io/name".to_string(), namespace.to_string()),
+    ])
+}
+
+fn schedule_configmap(namespace: &str, schedule: &str) -> ConfigMap {
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(SCHEDULE_CONFIGMAP.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(labels(SCHEDULE_APP, namespace)),
+            ..ObjectMeta::default()
+        },
+        data: Some(BTreeMap::from([(
+            SCHEDULE_KEY.to_string(),
+            schedule.to_string(),
+        )])),
+        ..ConfigMap::default()
+    }
+}
+
+// The CronJob dumps all databases of the instance, encrypted with the key of its organization
+fn backup_cronjob(
+    image: &str,
+    backup_uri: &str,
+    encryption: Option<&BackupEncryption>,
+    namespace: &str,
+    schedule: &str,
+) -> CronJob {
+    let labels = labels(BACKUP_APP, namespace);
+    CronJob {
+        metadata: ObjectMeta {
+            name: Some(BACKUP_CRONJOB.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(labels.clone()),
+            ..ObjectMeta::default()
+        },
+        spec: Some(CronJobSpec {
+            schedule: schedule.to_string(),
+            time_zone: Some("Etc/UTC".to_string()),
+            // A backup still running when the next one is due is not run twice
+            concurrency_policy: Some("Forbid".to_string()),
+            successful_jobs_history_limit: Some(JOBS_HISTORY_LIMIT),
+            failed_jobs_history_limit: Some(JOBS_HISTORY_LIMIT),
+            job_template: JobTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels.clone()),
+                    ..ObjectMeta::default()
+                }),
+                spec: Some(backup_job_spec(
+                    image,
+                    backup_uri,
+                    None,
+                    encryption,
+                    namespace,
+                    &BackupRequest::default(),
+                    labels,
+                )),
+            },
+            ..CronJobSpec::default()
+        }),
+        status: None,
+    }
+}
+
+fn configmap_schedule(configmap: &ConfigMap) -> Option<String> {
+    configmap.data.as_ref()?.get(SCHEDULE_KEY).cloned()
+}
+
+/// The backup schedule of an instance, with the times of its last backups
+pub async fn get_schedule(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<BackupSchedule, BackupError> {
+    let configmaps: Api<ConfigMap> = Api::namespaced(kubernetes_client.clone(), namespace);
+    let schedule = configmaps
+        .get_opt(SCHEDULE_CONFIGMAP)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .as_ref()
+        .and_then(configmap_schedule)
+        .ok_or_else(|| BackupError::NotFound(namespace.to_string()))?;
+
+    // The CronJob only exists once the schedule was reconciled
+    let cronjobs: Api<CronJob> = Api::namespaced(kubernetes_client, namespace);
+    let status = cronjobs
+        .get_opt(BACKUP_CRONJOB)
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .and_then(|cronjob| cronjob.status);
+    Ok(BackupSchedule {
+        schedule,
+        last_scheduled_at: status
+            .as_ref()
+            .and_then(|s| s.last_schedule_time.clone())
+            .map(|t| t.0),
+        last_successful_at: status
+            .as_ref()
+            .and_then(|s| s.last_successful_time.clone())
+            .map(|t| t.0),
+    })
+}
+
+/// Create or update the backup schedule of an instance
+pub async fn put_schedule(
+    kubernetes_client: Client,
+    namespace: &str,
+    schedule: &str,
+) -> Result<BackupSchedule, BackupError> {
+    if !is_valid_schedule(schedule) {
+        return Err(BackupError::InvalidRequest(format!(
+            "Invalid schedule: {}",
+            schedule
+        )));
+    }
+    let configmaps: Api<ConfigMap> = Api::namespaced(kubernetes_client.clone(), namespace);
+    configmaps
+        .patch(
+            SCHEDULE_CONFIGMAP,
+            &PatchParams::apply(FIELD_MANAGER).force(),
+            &Patch::Apply(schedule_configmap(namespace, schedule)),
+        )
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?;
+    info!("Set backup schedule of {} to {}", namespace, schedule);
+    get_schedule(kubernetes_client, namespace).await
+}
+
+/// Remove the backup schedule of an instance, its CronJob is deleted by the reconciler
+pub async fn delete_schedule(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<(), BackupError> {
+    let configmaps: Api<ConfigMap> = Api::namespaced(kubernetes_client, namespace);
+    match configmaps
+        .delete(SCHEDULE_CONFIGMAP, &DeleteParams::default())
+        .await
+    {
+        Ok(_) => {
+            info!("Removed backup schedule of {}", namespace);
+            Ok(())
+        }
+        Err(kube::Error::Api(e)) if e.code == 404 => {
+            Err(BackupError::NotFound(namespace.to_string()))
+        }
+        Err(e) => Err(BackupError::Kube(e.to_string())),
+    }
+}
+
+/// Make the backup CronJobs of all instances match their schedules, returns how many
+/// instances have a schedule
+pub async fn reconcile_schedules(
+    kubernetes_client: Client,
+    store: &BackupStore,
+    image: &str,
+) -> Result<usize, BackupError> {
+    let configmaps: Api<ConfigMap> = Api::all(kubernetes_client.clone());
+    let schedules: BTreeMap<String, String> = configmaps
+        .list(&ListParams::default().labels(&format!("app={}", SCHEDULE_APP)))
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+        .into_iter()
+        .filter(|configmap| configmap.metadata.name.as_deref() == Some(SCHEDULE_CONFIGMAP))
+        .filter_map(|configmap| {
+            let schedule = configmap_schedule(&configmap)?;
+            Some((configmap.metadata.namespace?, schedule))
+        })
+        .collect();
+    // Applied on each reconciliation, so changes of the keys apply to the next backups
+    let encryptions = get_encryptions(kubernetes_client.clone()).await?;
+
+    for (namespace, schedule) in &schedules {
+        if !is_valid_schedule(schedule) {
+            warn!(
+                "Ignoring invalid backup schedule of {}: {}",
+                namespace, schedule
+            );
+            continue;
+        }
+        let encryption = if encryptions.is_empty() {
+            None
+        } else {
+            // Never take unencrypted backups of an organization which set a key, nor of an
+            // instance whose organization is unknown
+            match namespace_organization(kubernetes_client.clone(), namespace).await {
+                Ok(Some(org_id)) => encryptions.get(&org_id),
+                Ok(None) => {
+                    error!("Failed to find the organization of {}: no label", namespace);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to find the organization of {}: {}", namespace, e);
+                    continue;
+                }
+            }
+        };
+        if let (Some(_), Err(e)) = (encryption, check_encryption_support(image)) {
+            error!("Not scheduling unencrypted backups of {}: {}", namespace, e);
+            continue;
+        }
+        let cronjob = backup_cronjob(
+            image,
+            &store.namespace_uri(namespace),
+            encryption,
+            namespace,
+            schedule,
+        );
+        let cronjobs: Api<CronJob> = Api::namespaced(kubernetes_client.clone(), namespace);
+        if let Err(e) = cronjobs
+            .patch(
+                BACKUP_CRONJOB,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(cronjob),
+            )
+            .await
+        {
+            error!("Failed to apply backup CronJob of {}: {}", namespace, e);
+        }
+    }
+
+    // The CronJobs of instances whose schedule was removed
+    let cronjobs: Api<CronJob> = Api::all(kubernetes_client.clone());
+    for cronjob in cronjobs
+        .list(&ListParams::default().labels(&format!("app={}", BACKUP_APP)))
+        .await
+        .map_err(|e| BackupError::Kube(e.to_string()))?
+    {
+        let Some(namespace) = cronjob.metadata.namespace else {
+            continue;
+        };
+        if schedules.contains_key(&namespace) {
+            continue;
+        }
+        let cronjobs: Api<CronJob> = Api::namespaced(kubernetes_client.clone(), &namespace);
+        match cronjobs
+            .delete(BACKUP_CRONJOB, &DeleteParams::background())
+            .await
+        {
+            Ok(_) => info!("Deleted backup CronJob of {}", namespace),
+            Err(e) => error!("Failed to delete backup CronJob of {}: {}", namespace, e),
+        }
+    }
+    Ok(schedules.len())
+}
+
+/// Periodically reconcile the backup schedules of all instances
+pub async fn run_schedule_reconciler(store: BackupStore, cfg: Config) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        cfg.temback_schedule_reconcile_interval_sec,
+    ));
+    loop {
+        interval.tick().await;
+        let kubernetes_client = match Client::try_default().await {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to create Kubernetes client: {}", e);
+                continue;
+            }
+        };
+        match reconcile_schedules(kubernetes_client, &store, &cfg.temback_image).await {
+            Ok(scheduled) => info!("Reconciled backup schedules of {} instances", scheduled),
+            Err(e) => error!("Backup schedule reconciliation failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_schedule() {
+        assert!(is_valid_schedule("0 3 * * *"));
+        assert!(is_valid_schedule("30 */6 * * MON-FRI"));
+        assert!(!is_valid_schedule("* * * * *"));
+        assert!(!is_valid_schedule("*/5 * * * *"));
+        assert!(!is_valid_schedule("60 3 * * *"));
+        assert!(!is_valid_schedule("0 3 * *"));
+        assert!(!is_valid_schedule("@daily"));
+        assert!(!is_valid_schedule("0 3 * * * ; rm"));
+        assert!(!is_valid_schedule("0 3 * * $(id)"));
+    }
+
+    #[test]
+    fn test_backup_cronjob() {
+        let cronjob = backup_cronjob(
+            "quay.io/tembo/temback:latest",
+            "s3://backups/temback/org-foo-inst-bar/",
+            None,
+            "org-foo-inst-bar",
+            "0 3 * * *",
+        );
+        let spec = cronjob.spec.unwrap();
+        assert_eq!(spec.schedule, "0 3 * * *");
+        let pod = spec.job_template.spec.unwrap().template.spec.unwrap();
+        assert_eq!(
+            pod.service_account_name.as_deref(),
+            Some("org-foo-inst-bar")
+        );
+        let env = pod.containers[0].env.clone().unwrap();
+        assert_eq!(
+            env[0].value.as_deref(),
+            Some("s3://backups/temback/org-foo-inst-bar/")
+        );
+        assert_eq!(env[1].name, "RW_URI");
+    }
+}
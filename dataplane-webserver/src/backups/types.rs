@@ -0,0 +1,194 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BackupArtifact {
+    /// The file name of the backup artifact
+    pub name: String,
+    /// The size of the backup artifact in bytes
+    pub size_bytes: i64,
+    /// When the backup artifact was stored
+    pub last_modified: Option<DateTime<Utc>>,
+    /// The age of the backup artifact in seconds
+    pub age_seconds: Option<i64>,
+    /// The full backup an incremental backup was taken on top of. It is restored after this
+    /// full backup and the incremental backups taken on top of it before it.
+    pub incremental_base: Option<String>,
+}
+
+/// A base backup of an instance, taken by CNPG to its object storage
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct BaseBackup {
+    /// The ID of the base backup, its directory in object storage
+    pub backup_id: String,
+    /// The total size of the files of the base backup in bytes
+    pub size_bytes: i64,
+    /// When the last file of the base backup was stored
+    pub last_modified: Option<DateTime<Utc>>,
+    /// The phase of the CNPG Backup, when it is still in the cluster
+    pub phase: Option<String>,
+    /// When the base backup was started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the base backup completed
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+/// The backups an instance can be restored from
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct InstanceBackups {
+    /// The base backups of the instance, newest first
+    pub base_backups: Vec<BaseBackup>,
+    /// The earliest point in time the instance can be recovered to from its base backups
+    pub first_recoverability_point: Option<DateTime<Utc>>,
+    /// The temback artifacts of the instance, full and incremental backups, newest first
+    pub artifacts: Vec<BackupArtifact>,
+    /// The dumps of a single database, schema or tables of the instance, newest first. They
+    /// are downloaded, not restored.
+    pub filtered_artifacts: Vec<BackupArtifact>,
+}
+
+/// A restore of a backup artifact into an instance. Either the backup or a point in time is
+/// chosen, in which case the newest backup stored at or before it is restored.
+#[derive(Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct RestoreRequest {
+    /// The file name of the backup artifact to restore
+    pub backup_name: Option<String>,
+    /// Restore the newest backup artifact stored at or before this time
+    pub point_in_time: Option<DateTime<Utc>>,
+    /// The Tembo Cloud Instance ID of the instance to restore into, in the same organization.
+    /// Defaults to the instance the backup belongs to.
+    pub target_instance_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobPhase {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct RestoreJob {
+    /// The ID of the restore job, its progress is queried on the target instance
+    pub job_id: String,
+    /// The file name of the backup artifact being restored
+    pub backup_name: String,
+    /// The namespace of the instance the backup belongs to
+    pub source_namespace: String,
+    /// The namespace of the instance the backup is restored into
+    pub target_namespace: String,
+    pub status: JobPhase,
+    /// When the restore job was started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the restore job succeeded or failed
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A time-limited URL to download a backup artifact directly from object storage
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BackupDownload {
+    /// The file name of the backup artifact
+    pub name: String,
+    /// The pre-signed URL of the backup artifact
+    pub url: String,
+    /// When the URL stops being valid
+    pub expires_at: DateTime<Utc>,
+    /// The size of the backup artifact in bytes
+    pub size_bytes: i64,
+    /// The ETag of the backup artifact, the MD5 of its content unless uploaded in parts
+    pub etag: Option<String>,
+    /// The base64 encoded SHA-256 of the backup artifact, when it was stored with one
+    pub checksum_sha256: Option<String>,
+    /// The key the backup artifact was encrypted with before upload, if any
+    pub encryption: Option<ArtifactEncryption>,
+}
+
+/// The key temback encrypts the backup artifacts of an organization with before uploading
+/// them, either an AWS KMS key or an age public key supplied by the customer
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct BackupEncryption {
+    /// The ID, ARN or alias of an AWS KMS key the service accounts of the instances can use
+    pub kms_key_id: Option<String>,
+    /// An age public key. Artifacts encrypted with it are only restored by the customer.
+    pub public_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionMethod {
+    Kms,
+    PublicKey,
+}
+
+/// How a backup artifact was encrypted, as recorded by temback in its status file
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ArtifactEncryption {
+    pub method: EncryptionMethod,
+    /// The KMS key or public key the backup artifact was encrypted with
+    pub key_id: String,
+}
+
+/// The recurring schedule of the logical backups of an instance
+#[derive(Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BackupScheduleRequest {
+    /// When to take backups, in cron format, e.g. `0 3 * * *` for every day at 03:00 UTC
+    pub schedule: String,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BackupSchedule {
+    /// When backups are taken, in cron format
+    pub schedule: String,
+    /// When the last scheduled backup was started
+    pub last_scheduled_at: Option<DateTime<Utc>>,
+    /// When the last scheduled backup completed successfully
+    pub last_successful_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupMode {
+    /// Dump all requested tables
+    #[default]
+    Full,
+    /// Only dump the tables changed since the last backup, per its manifest
+    Incremental,
+}
+
+/// An on-demand logical backup of an instance, of all its databases by default or of one
+/// database, schema or set of tables. Table names may use pg_dump patterns like `public.*`.
+#[derive(Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct BackupRequest {
+    /// Only dump this database
+    pub database: Option<String>,
+    /// Only dump this schema of the database
+    pub schema: Option<String>,
+    /// Only dump these tables
+    #[serde(default)]
+    pub include_tables: Vec<String>,
+    /// Do not dump these tables
+    #[serde(default)]
+    pub exclude_tables: Vec<String>,
+    /// Incremental backups are taken in full when there is no previous backup to build on
+    #[serde(default)]
+    pub mode: BackupMode,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BackupJob {
+    /// The ID of the backup job
+    pub job_id: String,
+    /// The namespace of the instance being backed up
+    pub namespace: String,
+    /// The database being dumped, all databases when empty
+    pub database: Option<String>,
+    pub mode: BackupMode,
+    /// The backup artifact an incremental backup builds on
+    pub incremental_from: Option<String>,
+    pub status: JobPhase,
+    /// When the backup job was started
+    pub started_at: Option<DateTime<Utc>>,
+}
@@ -1,10 +1,30 @@
+use crate::metrics::templates::{parse_templates, QueryTemplate};
 use log::error;
+use std::collections::BTreeMap;
 use std::env;
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub prometheus_url: String,
     pub prometheus_timeout_ms: i32,
+    pub prometheus_cache_ttl_ms: u64,
+    pub metrics_query_templates: BTreeMap<String, QueryTemplate>,
+    pub metrics_stream_min_interval_sec: u64,
+    pub metrics_stream_max_duration_sec: u64,
+    pub postgres_timeout_ms: u64,
+    pub loki_url: String,
+    pub loki_timeout_ms: u64,
+    pub trunk_registry_url: String,
+    pub temback_bucket: String,
+    pub temback_prefix: String,
+    pub temback_download_url_ttl_sec: u64,
+    pub temback_image: String,
+    pub temback_retention_days: i64,
+    pub temback_retention_sweep_interval_sec: u64,
+    pub temback_schedule_reconcile_interval_sec: u64,
+    pub rate_limit_requests_per_minute: u32,
+    pub rate_limit_burst: u32,
+    pub audit_database_url: String,
 }
 
 impl Default for Config {
@@ -28,6 +48,205 @@ impl Default for Config {
                     500
                 }
             },
+
+            // Identical queries are answered from a cache for this long, 0 disables the cache
+            prometheus_cache_ttl_ms: match from_env_default("PROMETHEUS_CACHE_TTL_MS", "5000")
+                .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable PROMETHEUS_CACHE_TTL_MS must convert into u64: {}",
+                        e
+                    );
+                    5000
+                }
+            },
+
+            // Named queries added to the default cpu, memory, connections and replication_lag
+            // templates, as a JSON object of templates by name
+            metrics_query_templates: parse_templates(&from_env_default(
+                "METRICS_QUERY_TEMPLATES",
+                "",
+            )),
+
+            // Metrics streams sample at most this often, whatever interval clients ask for
+            metrics_stream_min_interval_sec: match from_env_default(
+                "METRICS_STREAM_MIN_INTERVAL_SEC",
+                "2",
+            )
+            .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable METRICS_STREAM_MIN_INTERVAL_SEC must convert into u64: {}",
+                        e
+                    );
+                    2
+                }
+            },
+
+            // Metrics streams are closed after this long, clients reconnect to continue
+            metrics_stream_max_duration_sec: match from_env_default(
+                "METRICS_STREAM_MAX_DURATION_SEC",
+                "3600",
+            )
+            .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable METRICS_STREAM_MAX_DURATION_SEC must convert into u64: {}",
+                        e
+                    );
+                    3600
+                }
+            },
+
+            // Queries run on instances, like their query insights, time out after this long
+            postgres_timeout_ms: match from_env_default("POSTGRES_TIMEOUT_MS", "5000")
+                .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable POSTGRES_TIMEOUT_MS must convert into u64: {}",
+                        e
+                    );
+                    5000
+                }
+            },
+
+            // The logs of instances are queried from this Loki
+            loki_url: from_env_default(
+                "LOKI_URL",
+                "http://loki-gateway.monitoring.svc.cluster.local",
+            ),
+
+            loki_timeout_ms: match from_env_default("LOKI_TIMEOUT_MS", "5000").parse::<u64>() {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable LOKI_TIMEOUT_MS must convert into u64: {}",
+                        e
+                    );
+                    5000
+                }
+            },
+
+            // Available extensions are listed from this trunk registry, or an in-cluster cache
+            // of it for data planes without egress to the internet
+            trunk_registry_url: from_env_default(
+                "TRUNK_REGISTRY_URL",
+                "https://registry.pgtrunk.io",
+            ),
+
+            // Backups are listed from and deleted in this bucket, empty disables the backup routes
+            temback_bucket: from_env_default("TEMBACK_BUCKET", ""),
+            temback_prefix: from_env_default("TEMBACK_PREFIX", "temback"),
+
+            // Pre-signed backup download URLs are valid for this long
+            temback_download_url_ttl_sec: match from_env_default(
+                "TEMBACK_DOWNLOAD_URL_TTL_SEC",
+                "900",
+            )
+            .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable TEMBACK_DOWNLOAD_URL_TTL_SEC must convert into u64: {}",
+                        e
+                    );
+                    900
+                }
+            },
+
+            // Restore and scheduled backup jobs run temback from this image, which must be
+            // temback 0.3.0 or later to encrypt backups
+            temback_image: from_env_default("TEMBACK_IMAGE", "quay.io/tembo/temback:latest"),
+
+            // Backups older than this are deleted automatically, 0 disables the retention sweeper
+            temback_retention_days: match from_env_default("TEMBACK_RETENTION_DAYS", "0")
+                .parse::<i64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable TEMBACK_RETENTION_DAYS must convert into i64: {}",
+                        e
+                    );
+                    0
+                }
+            },
+
+            temback_retention_sweep_interval_sec: match from_env_default(
+                "TEMBACK_RETENTION_SWEEP_INTERVAL_SEC",
+                "3600",
+            )
+            .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable TEMBACK_RETENTION_SWEEP_INTERVAL_SEC must convert into u64: {}",
+                        e
+                    );
+                    3600
+                }
+            },
+
+            // Backup CronJobs are made to match the backup schedules of the instances this often
+            temback_schedule_reconcile_interval_sec: match from_env_default(
+                "TEMBACK_SCHEDULE_RECONCILE_INTERVAL_SEC",
+                "60",
+            )
+            .parse::<u64>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable TEMBACK_SCHEDULE_RECONCILE_INTERVAL_SEC must convert into u64: {}",
+                        e
+                    );
+                    60
+                }
+            },
+
+            // Requests to an instance are limited to this many per minute, 0 disables
+            // rate limiting
+            rate_limit_requests_per_minute: match from_env_default(
+                "RATE_LIMIT_REQUESTS_PER_MINUTE",
+                "300",
+            )
+            .parse::<u32>()
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable RATE_LIMIT_REQUESTS_PER_MINUTE must convert into u32: {}",
+                        e
+                    );
+                    300
+                }
+            },
+
+            // Requests allowed at once before the rate limit applies, like a dashboard loading
+            // all of its charts
+            rate_limit_burst: match from_env_default("RATE_LIMIT_BURST", "60").parse::<u32>() {
+                Ok(n) => n,
+                Err(e) => {
+                    error!(
+                        "Environment variable RATE_LIMIT_BURST must convert into u32: {}",
+                        e
+                    );
+                    60
+                }
+            },
+
+            // Audited calls are recorded in this Postgres database, empty only logs them
+            audit_database_url: from_env_default("AUDIT_DATABASE_URL", ""),
         }
     }
 }
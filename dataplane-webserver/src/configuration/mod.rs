@@ -0,0 +1,167 @@
+use crate::configuration::types::{EffectiveSetting, PgSetting, SettingSource};
+use crate::exec::{psql, PsqlError};
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind};
+use kube::{Api, Client};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub mod types;
+
+// pg_settings as a single line of JSON, values can contain any separator psql would use
+const SETTINGS_SQL: &str = "SELECT coalesce(json_agg(json_build_object(\
+     'name', name, 'setting', setting, 'unit', unit, 'source', source, \
+     'pending_restart', pending_restart) ORDER BY name), '[]') FROM pg_settings;";
+
+#[derive(Error, Debug)]
+pub enum ConfigurationError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Instance not found: {0}")]
+    NotFound(String),
+
+    #[error("No running primary for {0}")]
+    Unavailable(String),
+
+    #[error("psql error: {0}")]
+    Psql(String),
+}
+
+impl From<PsqlError> for ConfigurationError {
+    fn from(e: PsqlError) -> Self {
+        match e {
+            PsqlError::Kube(e) => ConfigurationError::Kube(e),
+            PsqlError::Unavailable(namespace) => ConfigurationError::Unavailable(namespace),
+            PsqlError::Psql(e) => ConfigurationError::Psql(e),
+        }
+    }
+}
+
+// The name and value of each config of a list of PgConfig in a CoreDB spec
+fn spec_configs(configs: &Value) -> BTreeMap<String, String> {
+    configs
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|config| {
+            Some((
+                config["name"].as_str()?.to_string(),
+                config["value"].as_str()?.to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Annotate the settings of an instance with their source: user overrides in the
+/// runtime_config of its CoreDB spec take precedence over the postgres_config of its stack,
+/// which the operator writes into the configuration file like its own settings
+pub fn effective_settings(coredb_spec: &Value, settings: Vec<PgSetting>) -> Vec<EffectiveSetting> {
+    let overrides = spec_configs(&coredb_spec["runtime_config"]);
+    let stack = spec_configs(&coredb_spec["stack"]["postgres_config"]);
+    settings
+        .into_iter()
+        .map(|setting| {
+            let (source, configured_value) = match setting.source.as_str() {
+                "configuration file" => {
+                    match (overrides.get(&setting.name), stack.get(&setting.name)) {
+                        (Some(value), _) => (SettingSource::UserOverride, Some(value.clone())),
+                        (None, Some(value)) => (SettingSource::Stack, Some(value.clone())),
+                        (None, None) => (SettingSource::Operator, None),
+                    }
+                }
+                "database" | "user" | "database user" => (SettingSource::DatabaseOrRole, None),
+                "default" => (SettingSource::PostgresDefault, None),
+                _ => (SettingSource::Other, None),
+            };
+            EffectiveSetting {
+                name: setting.name,
+                value: setting.setting,
+                unit: setting.unit,
+                source,
+                configured_value,
+                pending_restart: setting.pending_restart,
+            }
+        })
+        .collect()
+}
+
+/// The effective settings of the primary of an instance, from pg_settings
+pub async fn get_configuration(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<Vec<EffectiveSetting>, ConfigurationError> {
+    // The CoreDB of an instance is named after its namespace
+    let coredbs: Api<DynamicObject> = Api::namespaced_with(
+        kubernetes_client.clone(),
+        namespace,
+        &ApiResource::from_gvk(&GroupVersionKind::gvk("coredb.io", "v1alpha1", "CoreDB")),
+    );
+    let coredb = coredbs
+        .get_opt(namespace)
+        .await
+        .map_err(|e| ConfigurationError::Kube(e.to_string()))?
+        .ok_or_else(|| ConfigurationError::NotFound(namespace.to_string()))?;
+
+    let output = psql(kubernetes_client, namespace, SETTINGS_SQL).await?;
+    let settings: Vec<PgSetting> = serde_json::from_str(output.trim())
+        .map_err(|e| ConfigurationError::Psql(format!("Unexpected output: {}", e)))?;
+    Ok(effective_settings(&coredb.data["spec"], settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn setting(name: &str, setting: &str, source: &str) -> PgSetting {
+        PgSetting {
+            name: name.to_string(),
+            setting: Some(setting.to_string()),
+            unit: None,
+            source: source.to_string(),
+            pending_restart: false,
+        }
+    }
+
+    #[test]
+    fn test_effective_settings() {
+        let spec = json!({
+            "runtime_config": [{"name": "work_mem", "value": "64MB"}],
+            "stack": {"postgres_config": [
+                {"name": "work_mem", "value": "16MB"},
+                {"name": "checkpoint_timeout", "value": "30min"}]}
+        });
+        let settings = effective_settings(
+            &spec,
+            vec![
+                setting("checkpoint_timeout", "1800", "configuration file"),
+                setting("max_wal_senders", "10", "configuration file"),
+                setting("search_path", "app", "database"),
+                setting("wal_level", "replica", "default"),
+                setting("work_mem", "65536", "configuration file"),
+            ],
+        );
+        let sources: Vec<_> = settings.iter().map(|s| s.source).collect();
+        assert_eq!(
+            sources,
+            vec![
+                SettingSource::Stack,
+                SettingSource::Operator,
+                SettingSource::DatabaseOrRole,
+                SettingSource::PostgresDefault,
+                SettingSource::UserOverride,
+            ]
+        );
+        assert_eq!(settings[0].configured_value.as_deref(), Some("30min"));
+        assert_eq!(settings[4].configured_value.as_deref(), Some("64MB"));
+        assert_eq!(settings[4].value.as_deref(), Some("65536"));
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let output = r#"[{"name" : "work_mem", "setting" : "4096", "unit" : "kB", "source" : "default", "pending_restart" : false}]"#;
+        let settings: Vec<PgSetting> = serde_json::from_str(output).unwrap();
+        assert_eq!(settings[0].unit.as_deref(), Some("kB"));
+    }
+}
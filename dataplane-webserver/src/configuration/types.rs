@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Where the effective value of a setting comes from
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    /// The runtime_config of the instance
+    UserOverride,
+    /// The postgres_config of the stack of the instance
+    Stack,
+    /// Set in the configuration by the operator or CNPG, like max_wal_senders
+    Operator,
+    /// Set for a database or role with ALTER DATABASE or ALTER ROLE
+    DatabaseOrRole,
+    /// The built-in default of Postgres
+    PostgresDefault,
+    /// Any other source, like the command line or the session of the query
+    Other,
+}
+
+/// A row of pg_settings, as returned by the query
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PgSetting {
+    pub name: String,
+    pub setting: Option<String>,
+    pub unit: Option<String>,
+    pub source: String,
+    pub pending_restart: bool,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct EffectiveSetting {
+    pub name: String,
+    /// The effective value, in the unit of the setting
+    pub value: Option<String>,
+    pub unit: Option<String>,
+    pub source: SettingSource,
+    /// The value configured in the spec of the instance, when it is the user override or
+    /// stack default
+    pub configured_value: Option<String>,
+    /// A changed value only takes effect once Postgres restarts
+    pub pending_restart: bool,
+}
@@ -0,0 +1,119 @@
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{AttachParams, ListParams};
+use kube::{Api, Client};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[derive(Error, Debug)]
+pub enum PsqlError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("No running primary for {0}")]
+    Unavailable(String),
+
+    #[error("psql error: {0}")]
+    Psql(String),
+}
+
+/// Run SQL on the primary of an instance with psql, as the postgres user of its container,
+/// and return its unaligned output. The SQL is sent on stdin, so passwords are not part of
+/// the exec request.
+pub async fn psql(
+    kubernetes_client: Client,
+    namespace: &str,
+    sql: &str,
+) -> Result<String, PsqlError> {
+    psql_database(kubernetes_client, namespace, "postgres", sql).await
+}
+
+/// Run SQL like [psql], connected to another database of the instance
+pub async fn psql_database(
+    kubernetes_client: Client,
+    namespace: &str,
+    database: &str,
+    sql: &str,
+) -> Result<String, PsqlError> {
+    let pods: Api<Pod> = Api::namespaced(kubernetes_client, namespace);
+    let lp = ListParams::default().labels(&format!(
+        "cnpg.io/cluster={},cnpg.io/instanceRole=primary",
+        namespace
+    ));
+    let primary = pods
+        .list(&lp)
+        .await
+        .map_err(|e| PsqlError::Kube(e.to_string()))?
+        .items
+        .into_iter()
+        .find(|pod| {
+            pod.status
+                .as_ref()
+                .is_some_and(|status| status.phase.as_deref() == Some("Running"))
+        })
+        .and_then(|pod| pod.metadata.name)
+        .ok_or_else(|| PsqlError::Unavailable(namespace.to_string()))?;
+
+    let conninfo = format!(
+        "dbname={} application_name=tembo-data-api",
+        conninfo_value(database)
+    );
+    let command = vec![
+        "psql",
+        conninfo.as_str(),
+        "-v",
+        "ON_ERROR_STOP=1",
+        "--no-align",
+        "--tuples-only",
+    ];
+    let attach_params = AttachParams::default()
+        .container("postgres")
+        .stdin(true)
+        .stderr(true);
+    let mut process = pods
+        .exec(&primary, command, &attach_params)
+        .await
+        .map_err(|e| PsqlError::Kube(e.to_string()))?;
+
+    let mut stdin = process
+        .stdin()
+        .ok_or_else(|| PsqlError::Kube("No stdin to psql".to_string()))?;
+    stdin
+        .write_all(sql.as_bytes())
+        .await
+        .map_err(|e| PsqlError::Kube(e.to_string()))?;
+    // psql runs the SQL once its input is closed
+    drop(stdin);
+
+    let mut stdout = String::new();
+    if let Some(mut reader) = process.stdout() {
+        reader.read_to_string(&mut stdout).await.unwrap_or_default();
+    }
+    let mut stderr = String::new();
+    if let Some(mut reader) = process.stderr() {
+        reader.read_to_string(&mut stderr).await.unwrap_or_default();
+    }
+    let status = match process.take_status() {
+        Some(status) => status.await,
+        None => None,
+    };
+    match status.and_then(|status| status.status).as_deref() {
+        Some("Success") => Ok(stdout),
+        _ => Err(PsqlError::Psql(stderr.trim().to_string())),
+    }
+}
+
+// Quote a value of a libpq connection string
+fn conninfo_value(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conninfo_value() {
+        assert_eq!(conninfo_value("postgres"), "'postgres'");
+        assert_eq!(conninfo_value(r"it's a\db"), r"'it\'s a\\db'");
+    }
+}
@@ -0,0 +1,427 @@
+use crate::extensions::types::{
+    AvailableExtension, EnableExtensionRequest, ExtensionLocation, InstanceExtension,
+    InstanceExtensions, TrunkInstallState,
+};
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind, Patch, PatchParams};
+use kube::{Api, Client};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+pub mod types;
+
+lazy_static! {
+    static ref EXTENSION_NAME: Regex = Regex::new(r"^[a-z0-9_-]{1,63}$").unwrap();
+    static ref IDENTIFIER: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]{0,62}$").unwrap();
+}
+
+const DEFAULT_DATABASE: &str = "postgres";
+
+#[derive(Error, Debug)]
+pub enum ExtensionError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Instance not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid extension request: {0}")]
+    InvalidRequest(String),
+
+    #[error("The instance was changed concurrently: {0}")]
+    Conflict(String),
+
+    #[error("Trunk registry error: {0}")]
+    Trunk(String),
+}
+
+// The latest version of each project, as listed by the trunk registry
+#[derive(Deserialize)]
+struct TrunkProject {
+    name: String,
+    description: Option<String>,
+    version: String,
+    postgres_versions: Option<Vec<i32>>,
+    extensions: Vec<TrunkProjectExtension>,
+}
+
+#[derive(Deserialize)]
+struct TrunkProjectExtension {
+    extension_name: String,
+}
+
+fn coredb_api(kubernetes_client: Client, namespace: &str) -> Api<DynamicObject> {
+    Api::namespaced_with(
+        kubernetes_client,
+        namespace,
+        &ApiResource::from_gvk(&GroupVersionKind::gvk("coredb.io", "v1alpha1", "CoreDB")),
+    )
+}
+
+/// The extensions and trunk installs in the spec of a CoreDB, with their state from its status
+pub fn instance_extensions(coredb: &Value) -> (Vec<InstanceExtension>, Vec<TrunkInstallState>) {
+    let empty = vec![];
+    let status_extensions = coredb["status"]["extensions"].as_array().unwrap_or(&empty);
+    let extensions = coredb["spec"]["extensions"]
+        .as_array()
+        .unwrap_or(&empty)
+        .iter()
+        .filter_map(|extension| {
+            let name = extension["name"].as_str()?;
+            let status = status_extensions.iter().find(|s| s["name"] == name);
+            let locations = extension["locations"]
+                .as_array()
+                .unwrap_or(&empty)
+                .iter()
+                .map(|location| {
+                    let database = location["database"]
+                        .as_str()
+                        .unwrap_or(DEFAULT_DATABASE)
+                        .to_string();
+                    let location_status =
+                        status
+                            .and_then(|s| s["locations"].as_array())
+                            .and_then(|locations| {
+                                locations
+                                    .iter()
+                                    .find(|l| l["database"] == database.as_str())
+                            });
+                    ExtensionLocation {
+                        schema: location["schema"].as_str().map(str::to_string),
+                        version: location["version"].as_str().map(str::to_string),
+                        enabled: location["enabled"].as_bool().unwrap_or(false),
+                        enabled_in_status: location_status.and_then(|l| l["enabled"].as_bool()),
+                        error_message: location_status
+                            .and_then(|l| l["error_message"].as_str())
+                            .map(str::to_string),
+                        database,
+                    }
+                })
+                .collect();
+            Some(InstanceExtension {
+                name: name.to_string(),
+                description: extension["description"].as_str().map(str::to_string),
+                locations,
+            })
+        })
+        .collect();
+
+    let status_installs = coredb["status"]["trunk_installs"]
+        .as_array()
+        .unwrap_or(&empty);
+    let trunk_installs = coredb["spec"]["trunk_installs"]
+        .as_array()
+        .unwrap_or(&empty)
+        .iter()
+        .filter_map(|install| {
+            let name = install["name"].as_str()?;
+            let status = status_installs.iter().find(|s| s["name"] == name);
+            Some(TrunkInstallState {
+                name: name.to_string(),
+                version: install["version"].as_str().map(str::to_string),
+                installed: status.is_some_and(|s| {
+                    s["error"] == false && s["loading"] != true && s["installed_to_pods"].is_array()
+                }),
+                error_message: status
+                    .and_then(|s| s["error_message"].as_str())
+                    .map(str::to_string),
+            })
+        })
+        .collect();
+    (extensions, trunk_installs)
+}
+
+fn validate_request(request: &EnableExtensionRequest) -> Result<(), ExtensionError> {
+    let names = [Some(&request.name), request.trunk_project.as_ref()];
+    if let Some(name) = names
+        .into_iter()
+        .flatten()
+        .find(|name| !EXTENSION_NAME.is_match(name))
+    {
+        return Err(ExtensionError::InvalidRequest(format!(
+            "Invalid extension name: {}",
+            name
+        )));
+    }
+    let identifiers = [request.database.as_ref(), request.schema.as_ref()];
+    if let Some(identifier) = identifiers
+        .into_iter()
+        .flatten()
+        .find(|identifier| !IDENTIFIER.is_match(identifier))
+    {
+        return Err(ExtensionError::InvalidRequest(format!(
+            "Invalid database or schema name: {}",
+            identifier
+        )));
+    }
+    Ok(())
+}
+
+/// The extensions and trunk installs of a CoreDB spec with an extension enabled in a database.
+/// Its trunk project is installed when it is published to the registry, extensions shipped
+/// with the Postgres image are only enabled.
+pub fn enable_in_spec(
+    spec: &Value,
+    request: &EnableExtensionRequest,
+    trunk_project: Option<&AvailableExtension>,
+) -> (Value, Value) {
+    let mut extensions = spec["extensions"].as_array().cloned().unwrap_or_default();
+    let mut trunk_installs = spec["trunk_installs"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    if let Some(project) = trunk_project {
+        if !trunk_installs
+            .iter()
+            .any(|i| i["name"] == project.name.as_str())
+        {
+            trunk_installs.push(json!({
+                "name": project.name,
+                "version": request.version.clone().unwrap_or_else(|| project.version.clone()),
+            }));
+        }
+    }
+
+    let database = request.database.as_deref().unwrap_or(DEFAULT_DATABASE);
+    let location = json!({
+        "database": database,
+        "enabled": true,
+        "schema": request.schema,
+        "version": Value::Null,
+    });
+    match extensions
+        .iter_mut()
+        .find(|extension| extension["name"] == request.name.as_str())
+    {
+        Some(extension) => {
+            let locations = extension["locations"]
+                .as_array_mut()
+                .map(std::mem::take)
+                .unwrap_or_default();
+            let mut locations: Vec<Value> = locations
+                .into_iter()
+                .filter(|l| l["database"].as_str().unwrap_or(DEFAULT_DATABASE) != database)
+                .collect();
+            locations.push(location);
+            extension["locations"] = Value::Array(locations);
+        }
+        None => extensions.push(json!({
+            "name": request.name,
+            "locations": [location],
+        })),
+    }
+    (Value::Array(extensions), Value::Array(trunk_installs))
+}
+
+/// The latest version of each trunk project of the registry
+pub async fn list_available(
+    http_client: &reqwest::Client,
+    registry_url: &str,
+) -> Result<Vec<AvailableExtension>, ExtensionError> {
+    let url = format!(
+        "{}/api/v1/trunk-projects",
+        registry_url.trim_end_matches('/')
+    );
+    let projects: Vec<TrunkProject> = http_client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| ExtensionError::Trunk(e.to_string()))?
+        .json()
+        .await
+        .map_err(|e| ExtensionError::Trunk(e.to_string()))?;
+    Ok(projects
+        .into_iter()
+        .map(|project| AvailableExtension {
+            name: project.name,
+            description: project.description,
+            version: project.version,
+            postgres_versions: project.postgres_versions,
+            extension_names: project
+                .extensions
+                .into_iter()
+                .map(|e| e.extension_name)
+                .collect(),
+        })
+        .collect())
+}
+
+/// The extensions of an instance, and the ones which can be installed into it
+pub async fn get_extensions(
+    kubernetes_client: Client,
+    http_client: &reqwest::Client,
+    registry_url: &str,
+    namespace: &str,
+) -> Result<InstanceExtensions, ExtensionError> {
+    // The CoreDB is named after its namespace
+    let coredb = coredb_api(kubernetes_client, namespace)
+        .get_opt(namespace)
+        .await
+        .map_err(|e| ExtensionError::Kube(e.to_string()))?
+        .ok_or_else(|| ExtensionError::NotFound(namespace.to_string()))?;
+    let (extensions, trunk_installs) = instance_extensions(&coredb.data);
+    let available = match list_available(http_client, registry_url).await {
+        Ok(available) => available,
+        Err(e) => {
+            warn!("Failed to list available extensions: {}", e);
+            vec![]
+        }
+    };
+    Ok(InstanceExtensions {
+        extensions,
+        trunk_installs,
+        available,
+    })
+}
+
+/// Request an extension to be enabled in an instance, by patching its CoreDB. The operator
+/// installs and enables it.
+pub async fn enable_extension(
+    kubernetes_client: Client,
+    http_client: &reqwest::Client,
+    registry_url: &str,
+    namespace: &str,
+    request: &EnableExtensionRequest,
+) -> Result<InstanceExtension, ExtensionError> {
+    validate_request(request)?;
+    let available = list_available(http_client, registry_url).await?;
+    let project_name = request.trunk_project.as_deref().unwrap_or(&request.name);
+    let trunk_project = available.iter().find(|p| p.name == project_name);
+    if request.trunk_project.is_some() && trunk_project.is_none() {
+        return Err(ExtensionError::InvalidRequest(format!(
+            "Trunk project not found: {}",
+            project_name
+        )));
+    }
+
+    let coredbs = coredb_api(kubernetes_client, namespace);
+    let coredb = coredbs
+        .get_opt(namespace)
+        .await
+        .map_err(|e| ExtensionError::Kube(e.to_string()))?
+        .ok_or_else(|| ExtensionError::NotFound(namespace.to_string()))?;
+    let (extensions, trunk_installs) = enable_in_spec(&coredb.data["spec"], request, trunk_project);
+
+    // Lists are replaced by merge patches, the resource version makes sure no other change
+    // to them is lost
+    let patch = json!({
+        "metadata": {"resourceVersion": coredb.metadata.resource_version},
+        "spec": {"extensions": extensions, "trunk_installs": trunk_installs},
+    });
+    let patched = match coredbs
+        .patch(namespace, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        Ok(patched) => patched,
+        Err(kube::Error::Api(e)) if e.code == 409 => {
+            return Err(ExtensionError::Conflict(namespace.to_string()))
+        }
+        Err(e) => return Err(ExtensionError::Kube(e.to_string())),
+    };
+    info!(
+        "Requested extension {} to be enabled in {} of {}",
+        request.name,
+        request.database.as_deref().unwrap_or(DEFAULT_DATABASE),
+        namespace
+    );
+    let (extensions, _) = instance_extensions(&patched.data);
+    extensions
+        .into_iter()
+        .find(|extension| extension.name == request.name)
+        .ok_or_else(|| ExtensionError::Kube(format!("{} missing after patch", request.name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str, database: Option<&str>) -> EnableExtensionRequest {
+        EnableExtensionRequest {
+            name: name.to_string(),
+            database: database.map(str::to_string),
+            ..EnableExtensionRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_instance_extensions() {
+        let coredb = json!({
+            "spec": {
+                "extensions": [{"name": "vector", "description": "vector search", "locations": [
+                    {"database": "app", "enabled": true, "schema": "public", "version": null}]}],
+                "trunk_installs": [{"name": "pgvector", "version": "0.7.0"}],
+            },
+            "status": {
+                "extensions": [{"name": "vector", "locations": [
+                    {"database": "app", "enabled": true, "error": false, "error_message": null}]}],
+                "trunk_installs": [{"name": "pgvector", "version": "0.7.0", "error": false,
+                    "loading": false, "error_message": null, "installed_to_pods": ["inst-1"]}],
+            },
+        });
+        let (extensions, trunk_installs) = instance_extensions(&coredb);
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0].locations[0].database, "app");
+        assert_eq!(extensions[0].locations[0].enabled_in_status, Some(true));
+        assert_eq!(trunk_installs.len(), 1);
+        assert!(trunk_installs[0].installed);
+    }
+
+    #[test]
+    fn test_enable_in_spec() {
+        let spec = json!({
+            "extensions": [{"name": "vector", "locations": [
+                {"database": "postgres", "enabled": false, "schema": "public", "version": null}]}],
+            "trunk_installs": [{"name": "pgvector", "version": "0.7.0"}],
+        });
+        let pgvector = AvailableExtension {
+            name: "pgvector".to_string(),
+            version: "0.8.0".to_string(),
+            ..AvailableExtension::default()
+        };
+
+        // An existing extension is enabled in another database, its trunk project is kept
+        let (extensions, trunk_installs) =
+            enable_in_spec(&spec, &request("vector", Some("app")), Some(&pgvector));
+        assert_eq!(extensions[0]["locations"].as_array().unwrap().len(), 2);
+        assert_eq!(extensions[0]["locations"][1]["database"], "app");
+        assert_eq!(trunk_installs.as_array().unwrap().len(), 1);
+        assert_eq!(trunk_installs[0]["version"], "0.7.0");
+
+        // An existing location is replaced
+        let (extensions, _) = enable_in_spec(&spec, &request("vector", None), Some(&pgvector));
+        assert_eq!(extensions[0]["locations"].as_array().unwrap().len(), 1);
+        assert_eq!(extensions[0]["locations"][0]["enabled"], true);
+
+        // A new extension from trunk is installed at the latest version
+        let pg_cron = AvailableExtension {
+            name: "pg_cron".to_string(),
+            version: "1.6.2".to_string(),
+            ..AvailableExtension::default()
+        };
+        let (extensions, trunk_installs) =
+            enable_in_spec(&spec, &request("pg_cron", None), Some(&pg_cron));
+        assert_eq!(extensions[1]["name"], "pg_cron");
+        assert_eq!(
+            trunk_installs[1],
+            json!({"name": "pg_cron", "version": "1.6.2"})
+        );
+
+        // Extensions of the Postgres image are only enabled
+        let (extensions, trunk_installs) =
+            enable_in_spec(&spec, &request("pg_stat_statements", None), None);
+        assert_eq!(extensions[1]["name"], "pg_stat_statements");
+        assert_eq!(trunk_installs.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_request() {
+        assert!(validate_request(&request("pg_cron", Some("app"))).is_ok());
+        assert!(validate_request(&request("pg cron", None)).is_err());
+        assert!(validate_request(&request("pg_cron", Some("app; drop"))).is_err());
+    }
+}
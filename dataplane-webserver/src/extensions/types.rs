@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// An extension in the spec of the instance, with where it is enabled
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct InstanceExtension {
+    pub name: String,
+    pub description: Option<String>,
+    pub locations: Vec<ExtensionLocation>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct ExtensionLocation {
+    pub database: String,
+    pub schema: Option<String>,
+    pub version: Option<String>,
+    /// Whether the extension is requested to be enabled in this database
+    pub enabled: bool,
+    /// Whether the extension is enabled in this database, per the status of the instance
+    pub enabled_in_status: Option<bool>,
+    pub error_message: Option<String>,
+}
+
+/// A trunk project installed into the instance
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct TrunkInstallState {
+    pub name: String,
+    pub version: Option<String>,
+    /// Whether the trunk project is installed, per the status of the instance
+    pub installed: bool,
+    pub error_message: Option<String>,
+}
+
+/// A trunk project which can be installed into the instance
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct AvailableExtension {
+    /// The name of the trunk project
+    pub name: String,
+    pub description: Option<String>,
+    /// The latest version of the trunk project
+    pub version: String,
+    /// The Postgres major versions the trunk project is published for
+    pub postgres_versions: Option<Vec<i32>>,
+    /// The extensions the trunk project provides
+    pub extension_names: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct InstanceExtensions {
+    pub extensions: Vec<InstanceExtension>,
+    pub trunk_installs: Vec<TrunkInstallState>,
+    /// The trunk projects of the registry, empty when the registry can not be reached
+    pub available: Vec<AvailableExtension>,
+}
+
+/// Enable an extension in a database of the instance, installing its trunk project first
+#[derive(Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct EnableExtensionRequest {
+    /// The name of the extension, as in CREATE EXTENSION
+    pub name: String,
+    /// The trunk project providing the extension, defaults to the extension name
+    pub trunk_project: Option<String>,
+    /// The version of the trunk project, defaults to the latest
+    pub version: Option<String>,
+    /// The database to enable the extension in, defaults to postgres
+    pub database: Option<String>,
+    /// The schema to enable the extension in
+    pub schema: Option<String>,
+}
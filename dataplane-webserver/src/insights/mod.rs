@@ -0,0 +1,444 @@
+use crate::config::Config;
+use crate::insights::types::{
+    ConnectionGroup, ConnectionStats, PoolerConnections, QueryOrder, QueryStats, TopQueriesQuery,
+};
+use crate::pooler::types::PoolerStats;
+use crate::pooler::{get_pooler_stats, PoolerError};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use log::warn;
+use sqlx::postgres::{PgConnectOptions, PgConnection};
+use sqlx::{ConnectOptions, Row};
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+pub mod types;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+// The SQLSTATE of undefined_table, when the pg_stat_statements extension is not created
+const UNDEFINED_TABLE: &str = "42P01";
+const APPLICATION_NAME: &str = "tembo-data-api";
+// The statements pg_stat_statements replaces the constants of with parameters
+const NORMALIZED_STATEMENTS: [&str; 8] = [
+    "select", "insert", "update", "delete", "merge", "with", "values", "table",
+];
+
+#[derive(Error, Debug)]
+pub enum InsightsError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("No connection secret for {0}")]
+    Unavailable(String),
+
+    #[error("pg_stat_statements is not enabled for {0}")]
+    NotEnabled(String),
+
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+fn order_column(order: QueryOrder) -> &'static str {
+    match order {
+        QueryOrder::TotalTime => "s.total_exec_time",
+        QueryOrder::MeanTime => "s.mean_exec_time",
+        QueryOrder::Calls => "s.calls",
+        QueryOrder::Rows => "s.rows",
+    }
+}
+
+fn top_queries_sql(order: QueryOrder) -> String {
+    format!(
+        "SELECT s.queryid, s.query, d.datname, r.rolname, s.calls, s.total_exec_time, \
+         s.mean_exec_time, s.rows \
+         FROM pg_stat_statements s \
+         JOIN pg_database d ON d.oid = s.dbid \
+         LEFT JOIN pg_roles r ON r.oid = s.userid \
+         ORDER BY {} DESC LIMIT $1",
+        order_column(order)
+    )
+}
+
+/// Replace the literals of a query with `?`, remove its comments and collapse whitespace.
+/// pg_stat_statements replaces the constants of most statements with parameters, but not
+/// those of utility statements like `ALTER ROLE ... PASSWORD '...'`.
+pub fn redact_query(query: &str) -> String {
+    let chars: Vec<char> = query.chars().collect();
+    let mut redacted = String::with_capacity(query.len());
+    let mut i = 0;
+    let push_space = |redacted: &mut String| {
+        if !redacted.is_empty() && !redacted.ends_with(' ') {
+            redacted.push(' ');
+        }
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                push_space(&mut redacted);
+            }
+            '/' if next == Some('*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                push_space(&mut redacted);
+                continue;
+            }
+            '\'' => {
+                // Quotes are escaped by doubling them, or with a backslash in E'' strings
+                let mut previous = redacted.chars().rev();
+                let escapes = matches!(previous.next(), Some('E' | 'e'))
+                    && !previous
+                        .next()
+                        .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                if escapes {
+                    redacted.pop();
+                }
+                i += 1;
+                while i < chars.len() {
+                    match chars[i] {
+                        '\\' if escapes => i += 1,
+                        '\'' if chars.get(i + 1) == Some(&'\'') => i += 1,
+                        '\'' => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                redacted.push('?');
+            }
+            '"' => {
+                // Quoted identifiers are kept
+                redacted.push(c);
+                i += 1;
+                while i < chars.len() {
+                    redacted.push(chars[i]);
+                    if chars[i] == '"' {
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+            '$' if next.is_some_and(|n| n.is_ascii_digit()) => {
+                // Parameters are kept
+                redacted.push(c);
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    redacted.push(chars[i]);
+                    i += 1;
+                }
+                continue;
+            }
+            '$' => {
+                // Dollar quoted strings, $$...$$ or $tag$...$tag$
+                let tag_len = chars[i + 1..]
+                    .iter()
+                    .position(|c| !(c.is_alphanumeric() || *c == '_'))
+                    .filter(|len| chars.get(i + 1 + len) == Some(&'$'));
+                match tag_len {
+                    Some(len) => {
+                        let tag: String = chars[i..i + len + 2].iter().collect();
+                        let body: String = chars[i + len + 2..].iter().collect();
+                        let end = body
+                            .find(&tag)
+                            .map(|end| body[..end].chars().count() + tag.chars().count())
+                            .unwrap_or(body.chars().count());
+                        i += len + 2 + end;
+                        redacted.push('?');
+                        continue;
+                    }
+                    None => redacted.push(c),
+                }
+            }
+            c if c.is_ascii_digit()
+                && !redacted
+                    .chars()
+                    .last()
+                    .is_some_and(|last| last.is_alphanumeric() || last == '_') =>
+            {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                redacted.push('?');
+                continue;
+            }
+            c if c.is_whitespace() => push_space(&mut redacted),
+            c => redacted.push(c),
+        }
+        i += 1;
+    }
+    redacted.trim().to_string()
+}
+
+// Whether a redacted query is a utility statement, whose constants pg_stat_statements
+// keeps as they were sent
+fn is_utility_statement(redacted: &str) -> bool {
+    let keyword = redacted
+        .trim_start_matches('(')
+        .split(|c: char| !c.is_alphabetic())
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    !NORMALIZED_STATEMENTS.contains(&keyword.as_str())
+}
+
+// The text of a query to return. Utility statements are always redacted, as they can carry
+// passwords and other secrets.
+fn query_text(query: String, redact: bool) -> String {
+    let redacted = redact_query(&query);
+    match redact || is_utility_statement(&redacted) {
+        true => redacted,
+        false => query,
+    }
+}
+
+async fn connection_uri(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<String, InsightsError> {
+    let secrets: Api<Secret> = Api::namespaced(kubernetes_client, namespace);
+    let secret = secrets
+        .get_opt(&format!("{}-connection", namespace))
+        .await
+        .map_err(|e| InsightsError::Kube(e.to_string()))?
+        .ok_or_else(|| InsightsError::Unavailable(namespace.to_string()))?;
+    secret
+        .data
+        .as_ref()
+        .and_then(|data| data.get("rw_uri"))
+        .and_then(|uri| String::from_utf8(uri.0.clone()).ok())
+        .ok_or_else(|| InsightsError::Unavailable(namespace.to_string()))
+}
+
+// Connect to the primary of an instance with the credentials of its connection secret
+async fn connect(
+    kubernetes_client: Client,
+    cfg: &Config,
+    namespace: &str,
+) -> Result<PgConnection, InsightsError> {
+    let uri = connection_uri(kubernetes_client, namespace).await?;
+    let timeout = Duration::from_millis(cfg.postgres_timeout_ms);
+    let options = PgConnectOptions::from_str(&uri)
+        .map_err(|e| InsightsError::Database(e.to_string()))?
+        .application_name(APPLICATION_NAME)
+        .options([("statement_timeout", cfg.postgres_timeout_ms.to_string())]);
+    tokio::time::timeout(timeout, options.connect())
+        .await
+        .map_err(|_| InsightsError::Database("Timed out connecting".to_string()))?
+        .map_err(|e| InsightsError::Database(e.to_string()))
+}
+
+/// The top statements of an instance from pg_stat_statements
+pub async fn top_queries(
+    kubernetes_client: Client,
+    cfg: &Config,
+    namespace: &str,
+    request: &TopQueriesQuery,
+) -> Result<Vec<QueryStats>, InsightsError> {
+    let limit = request.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(InsightsError::InvalidRequest(format!(
+            "The limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    let mut connection = connect(kubernetes_client, cfg, namespace).await?;
+    let rows = match sqlx::query(&top_queries_sql(request.order_by))
+        .bind(limit)
+        .fetch_all(&mut connection)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(UNDEFINED_TABLE) => {
+            return Err(InsightsError::NotEnabled(namespace.to_string()))
+        }
+        Err(e) => return Err(InsightsError::Database(e.to_string())),
+    };
+
+    rows.iter()
+        .map(|row| {
+            let query: String = row.try_get("query")?;
+            Ok(QueryStats {
+                query_id: row.try_get("queryid")?,
+                query: query_text(query, request.redact),
+                database: row.try_get("datname")?,
+                user: row.try_get("rolname")?,
+                calls: row.try_get("calls")?,
+                total_exec_time_ms: row.try_get("total_exec_time")?,
+                mean_exec_time_ms: row.try_get("mean_exec_time")?,
+                rows: row.try_get("rows")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()
+        .map_err(|e| InsightsError::Database(e.to_string()))
+}
+
+// Connections of clients grouped by state, user and database, without the one of this query
+const CONNECTIONS_SQL: &str = "SELECT state, usename, datname, count(*) AS connections \
+     FROM pg_stat_activity \
+     WHERE backend_type = 'client backend' AND pid <> pg_backend_pid() \
+     GROUP BY state, usename, datname \
+     ORDER BY connections DESC, usename, datname";
+
+/// Sum the client and server connections of all pools of all pooler pods
+pub fn pooler_connections(stats: &[PoolerStats]) -> PoolerConnections {
+    stats.iter().flat_map(|pod| pod.pools.iter()).fold(
+        PoolerConnections::default(),
+        |mut total, pool| {
+            total.client_active += pool.cl_active;
+            total.client_waiting += pool.cl_waiting;
+            total.server_active += pool.sv_active;
+            total.server_idle += pool.sv_idle;
+            total.server_used += pool.sv_used;
+            total
+        },
+    )
+}
+
+/// The current connections of an instance from pg_stat_activity, and those of its pooler
+/// when it is enabled
+pub async fn connection_stats(
+    kubernetes_client: Client,
+    http_client: &reqwest::Client,
+    cfg: &Config,
+    namespace: &str,
+) -> Result<ConnectionStats, InsightsError> {
+    let mut connection = connect(kubernetes_client.clone(), cfg, namespace).await?;
+    let max_connections: i64 =
+        sqlx::query_scalar("SELECT current_setting('max_connections')::int8")
+            .fetch_one(&mut connection)
+            .await
+            .map_err(|e| InsightsError::Database(e.to_string()))?;
+    let groups = sqlx::query(CONNECTIONS_SQL)
+        .fetch_all(&mut connection)
+        .await
+        .map_err(|e| InsightsError::Database(e.to_string()))?
+        .iter()
+        .map(|row| {
+            Ok(ConnectionGroup {
+                state: row.try_get("state")?,
+                user: row.try_get("usename")?,
+                database: row.try_get("datname")?,
+                connections: row.try_get("connections")?,
+            })
+        })
+        .collect::<Result<Vec<_>, sqlx::Error>>()
+        .map_err(|e| InsightsError::Database(e.to_string()))?;
+
+    // The stats of the instance are returned even when those of its pooler are not
+    let pooler = match get_pooler_stats(kubernetes_client, http_client, namespace).await {
+        Ok(stats) => Some(pooler_connections(&stats)),
+        Err(PoolerError::NotEnabled(_)) => None,
+        Err(e) => {
+            warn!("Failed to get pooler connections of {}: {}", namespace, e);
+            None
+        }
+    };
+
+    Ok(ConnectionStats {
+        max_connections,
+        total: groups.iter().map(|group| group.connections).sum(),
+        groups,
+        pooler,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_query() {
+        assert_eq!(
+            redact_query("SELECT * FROM orders WHERE id = $1"),
+            "SELECT * FROM orders WHERE id = $1"
+        );
+        assert_eq!(
+            redact_query("ALTER ROLE app PASSWORD 'it''s secret'"),
+            "ALTER ROLE app PASSWORD ?"
+        );
+        assert_eq!(
+            redact_query("select e'a\\'b', 42, 3.14 from t1 -- comment\nwhere x = 'y'"),
+            "select ?, ?, ? from t1 where x = ?"
+        );
+        assert_eq!(
+            redact_query("/* app:web */ SELECT \"col 1\"\n\n  FROM \"t'2\""),
+            "SELECT \"col 1\" FROM \"t'2\""
+        );
+        assert_eq!(
+            redact_query("DO $body$ BEGIN PERFORM 1; END $body$"),
+            "DO ?"
+        );
+        assert_eq!(
+            redact_query("SELECT name FROM t WHERE name LIKE'x%'"),
+            "SELECT name FROM t WHERE name LIKE?"
+        );
+        // Unterminated dollar quotes are redacted to the end
+        assert_eq!(redact_query("SELECT $$x$$, $tag$ y"), "SELECT ?, ?");
+    }
+
+    #[test]
+    fn test_query_text() {
+        let select = "SELECT * FROM t WHERE a = $1 AND b = 'x' -- web".to_string();
+        assert_eq!(query_text(select.clone(), false), select);
+        assert_eq!(
+            query_text(select, true),
+            "SELECT * FROM t WHERE a = $1 AND b = ?"
+        );
+        assert_eq!(
+            query_text("(select 1) union (select $1)".to_string(), false),
+            "(select 1) union (select $1)"
+        );
+        assert_eq!(
+            query_text(
+                "/* setup */ ALTER ROLE app PASSWORD 'secret'".to_string(),
+                false
+            ),
+            "ALTER ROLE app PASSWORD ?"
+        );
+        assert_eq!(
+            query_text("CREATE USER etl WITH PASSWORD 'x'".to_string(), false),
+            "CREATE USER etl WITH PASSWORD ?"
+        );
+    }
+
+    #[test]
+    fn test_top_queries_sql() {
+        assert!(top_queries_sql(QueryOrder::default()).contains("ORDER BY s.total_exec_time DESC"));
+        assert!(top_queries_sql(QueryOrder::Calls).contains("ORDER BY s.calls DESC"));
+    }
+
+    #[test]
+    fn test_pooler_connections() {
+        let pod = |cl_active, sv_idle| PoolerStats {
+            pools: vec![crate::pooler::types::PoolStats {
+                cl_active,
+                cl_waiting: 1,
+                sv_idle,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            pooler_connections(&[pod(4, 6), pod(2, 3)]),
+            PoolerConnections {
+                client_active: 6,
+                client_waiting: 2,
+                server_idle: 9,
+                ..Default::default()
+            }
+        );
+        assert_eq!(pooler_connections(&[]), PoolerConnections::default());
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What the top queries are ranked by
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryOrder {
+    #[default]
+    TotalTime,
+    MeanTime,
+    Calls,
+    Rows,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TopQueriesQuery {
+    #[serde(default)]
+    pub order_by: QueryOrder,
+    pub limit: Option<i64>,
+    /// Replace the literals left in the query texts and strip their comments. The texts of
+    /// utility statements are always redacted.
+    #[serde(default)]
+    pub redact: bool,
+}
+
+/// The statistics of a statement, from pg_stat_statements
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct QueryStats {
+    pub query_id: Option<i64>,
+    pub query: String,
+    pub database: String,
+    pub user: Option<String>,
+    /// Times the statement was executed
+    pub calls: i64,
+    /// Time spent executing the statement, in milliseconds
+    pub total_exec_time_ms: f64,
+    /// Average time spent executing the statement, in milliseconds
+    pub mean_exec_time_ms: f64,
+    /// Rows retrieved or affected by the statement
+    pub rows: i64,
+}
+
+/// Client connections of an instance with the same state, user and database
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ConnectionGroup {
+    /// active, idle, idle in transaction, ... or none when the backend does not report it
+    pub state: Option<String>,
+    pub user: Option<String>,
+    pub database: Option<String>,
+    pub connections: i64,
+}
+
+/// Connections of the pooler of an instance, summed over its pods and pools
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct PoolerConnections {
+    /// Client connections linked to a server connection
+    pub client_active: i64,
+    /// Client connections waiting for a server connection
+    pub client_waiting: i64,
+    /// Server connections linked to a client connection
+    pub server_active: i64,
+    /// Server connections available for use
+    pub server_idle: i64,
+    /// Server connections idle for longer than server_check_delay
+    pub server_used: i64,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ConnectionStats {
+    /// The max_connections setting of the instance
+    pub max_connections: i64,
+    /// Client connections to the instance, the sum of the groups
+    pub total: i64,
+    pub groups: Vec<ConnectionGroup>,
+    /// The connections of the pooler, when it is enabled
+    pub pooler: Option<PoolerConnections>,
+}
@@ -1,4 +1,16 @@
+pub mod alerts;
+pub mod audit;
+pub mod backups;
 pub mod config;
+pub mod configuration;
+pub mod exec;
+pub mod extensions;
+pub mod insights;
+pub mod logs;
 pub mod metrics;
+pub mod pooler;
+pub mod rate_limit;
+pub mod roles;
 pub mod routes;
 pub mod secrets;
+pub mod status;
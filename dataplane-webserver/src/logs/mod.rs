@@ -0,0 +1,125 @@
+use crate::config::Config;
+use crate::logs::types::LogQuery;
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+use log::error;
+use reqwest::{Client, Response};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+pub mod query_validator;
+pub mod types;
+
+// The lines returned when no limit is requested, and the most which can be requested
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 5000;
+
+// The time range returned when no start is requested, and the longest which can be requested
+const DEFAULT_RANGE_SECONDS: u64 = 3600;
+const MAX_RANGE_SECONDS: u64 = 7 * 86400;
+
+async fn loki_response(response: Response) -> HttpResponse {
+    let status_code = response.status();
+    if status_code == StatusCode::OK {
+        return match response.json::<Value>().await {
+            Ok(json_response) => HttpResponse::Ok().json(json_response),
+            Err(e) => {
+                error!("Failed to parse Loki response: {}", e);
+                HttpResponse::InternalServerError().json("Failed to parse Loki response")
+            }
+        };
+    }
+
+    // Loki answers errors in plain text
+    let body = response.text().await.unwrap_or_default();
+    match status_code {
+        StatusCode::BAD_REQUEST => {
+            HttpResponse::BadRequest().json(format!("Loki reported the query is invalid: {}", body))
+        }
+        StatusCode::GATEWAY_TIMEOUT | StatusCode::SERVICE_UNAVAILABLE => {
+            HttpResponse::GatewayTimeout().json("Loki timeout")
+        }
+        _ => {
+            error!("{:?}: {}", status_code, body);
+            HttpResponse::InternalServerError().json("Loki returned an unexpected status code")
+        }
+    }
+}
+
+// Loki takes times as nanosecond unix timestamps
+fn nanoseconds(seconds: u64) -> String {
+    format!("{}000000000", seconds)
+}
+
+pub async fn query_loki(
+    cfg: Data<Config>,
+    http_client: Data<Client>,
+    log_query: LogQuery,
+    namespace: String,
+) -> HttpResponse {
+    let query = match query_validator::check_query_only_accesses_namespace(
+        log_query.query.as_deref(),
+        log_query.container.as_deref(),
+        &namespace,
+    ) {
+        Ok(value) => value,
+        Err(http_response) => return http_response,
+    };
+
+    let end = log_query.end.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs()
+    });
+    let start = log_query
+        .start
+        .unwrap_or_else(|| end.saturating_sub(DEFAULT_RANGE_SECONDS));
+    if end < start {
+        return HttpResponse::BadRequest()
+            .json("End time must be greater than or equal to start time");
+    }
+    if end - start > MAX_RANGE_SECONDS {
+        return HttpResponse::BadRequest()
+            .json("Time range is too long. Please query less than 7 days of logs at once.");
+    }
+
+    let limit = log_query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return HttpResponse::BadRequest()
+            .json(format!("Limit must be between 1 and {} lines", MAX_LIMIT));
+    }
+    // Loki returns the lines closest to the start of the range first, unless asked backward
+    let direction = if log_query.tail {
+        "backward"
+    } else {
+        "forward"
+    };
+
+    let query_url = format!(
+        "{}/loki/api/v1/query_range",
+        cfg.loki_url.trim_end_matches('/')
+    );
+    let query_params = [
+        ("query", query),
+        ("start", nanoseconds(start)),
+        ("end", nanoseconds(end)),
+        ("limit", limit.to_string()),
+        ("direction", direction.to_string()),
+    ];
+
+    let response = http_client
+        .get(&query_url)
+        .query(&query_params)
+        .timeout(Duration::from_millis(cfg.loki_timeout_ms))
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => loki_response(response).await,
+        Err(e) => {
+            error!("Failed to query Loki: {}", e);
+            HttpResponse::GatewayTimeout().json("Failed to query Loki")
+        }
+    }
+}
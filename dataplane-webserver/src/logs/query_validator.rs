@@ -0,0 +1,257 @@
+use actix_web::HttpResponse;
+use log::{info, warn};
+
+// https://grafana.com/docs/loki/latest/query/log_queries/
+// A log query starts with a stream selector, which selects the log streams by their labels,
+// followed by a pipeline which filters and formats the lines of these streams.
+// Example: {namespace="org-foo-inst-bar", container="postgres"} |= "ERROR"
+// We require the stream selector to have a label namespace matching the instance
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelMatcher {
+    pub name: String,
+    pub op: String,
+    pub value: String,
+    // The value as written in the query, with its quotes
+    raw_value: String,
+}
+
+impl LabelMatcher {
+    pub fn equal(name: &str, value: &str) -> Self {
+        LabelMatcher {
+            name: name.to_string(),
+            op: "=".to_string(),
+            value: value.to_string(),
+            raw_value: format!("{:?}", value),
+        }
+    }
+}
+
+// Parse a quoted string at the start of the input, returns its value and the remaining input
+fn parse_string(input: &str) -> Result<(String, String, &str), &'static str> {
+    let mut chars = input.char_indices();
+    let quote = match chars.next() {
+        Some((_, quote)) if quote == '"' || quote == '`' => quote,
+        _ => return Err("Label values must be quoted"),
+    };
+    let mut value = String::new();
+    let mut escaped = false;
+    for (i, c) in chars {
+        if escaped {
+            value.push(c);
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            let end = i + c.len_utf8();
+            return Ok((value, input[..end].to_string(), &input[end..]));
+        } else {
+            value.push(c);
+        }
+    }
+    Err("Unterminated string")
+}
+
+/// Parse the stream selector at the start of a log query, returns its label matchers and
+/// the pipeline after it
+pub fn parse_stream_selector(query: &str) -> Result<(Vec<LabelMatcher>, &str), &'static str> {
+    let mut rest = query
+        .trim_start()
+        .strip_prefix('{')
+        .ok_or("Only log queries starting with a stream selector are allowed")?;
+    let mut matchers = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((matchers, after));
+        }
+        let name_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if name_len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err("Invalid label name");
+        }
+        let name = &rest[..name_len];
+        rest = rest[name_len..].trim_start();
+        let op = ["=~", "!~", "!=", "="]
+            .into_iter()
+            .find(|op| rest.starts_with(op))
+            .ok_or("Invalid label matcher")?;
+        rest = rest[op.len()..].trim_start();
+        let (value, raw_value, after) = parse_string(rest)?;
+        matchers.push(LabelMatcher {
+            name: name.to_string(),
+            op: op.to_string(),
+            value,
+            raw_value,
+        });
+        rest = after.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after;
+        } else if !rest.starts_with('}') {
+            return Err("Invalid stream selector");
+        }
+    }
+}
+
+// The pipeline may not hold another stream selector, braces are only allowed within strings,
+// e.g. in the template of a line_format
+fn pipeline_has_selector(pipeline: &str) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    for c in pipeline.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '`' => quote = Some(c),
+            None if c == '{' || c == '}' => return true,
+            None => {}
+        }
+    }
+    false
+}
+
+fn validate_query(
+    query: &str,
+    namespace: &str,
+) -> Result<(Vec<LabelMatcher>, String), &'static str> {
+    let (matchers, pipeline) = parse_stream_selector(query)?;
+    if pipeline_has_selector(pipeline) {
+        return Err("Only one stream selector is allowed");
+    }
+    let authorized_query = matchers
+        .iter()
+        .any(|m| m.name == "namespace" && m.op == "=" && m.value == namespace);
+    if !authorized_query {
+        return Err("Must include namespace in the stream selector");
+    }
+    Ok((matchers, pipeline.to_string()))
+}
+
+// Container names end up inside of the query, they are limited to the characters of
+// Kubernetes names
+fn is_valid_container(container: &str) -> bool {
+    !container.is_empty()
+        && container.len() <= 63
+        && container
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+// Returns the query to send to Loki if it's valid, selecting the logs of the namespace by
+// default and only the ones of the container when it is set
+// otherwise returns an error in the form of HttpResponse
+pub fn check_query_only_accesses_namespace(
+    query: Option<&str>,
+    container: Option<&str>,
+    namespace: &str,
+) -> Result<String, HttpResponse> {
+    let (mut matchers, pipeline) = match query {
+        None => (
+            vec![LabelMatcher::equal("namespace", namespace)],
+            String::new(),
+        ),
+        Some(query) => match validate_query(query, namespace) {
+            Ok(validated) => {
+                info!(
+                    "Authorized request: namespace '{}', query '{}'",
+                    namespace, query
+                );
+                validated
+            }
+            Err(e) => {
+                warn!(
+                    "Unauthorized request: namespace '{}', query '{}': {}",
+                    namespace, query, e
+                );
+                return Err(HttpResponse::Forbidden().json(e));
+            }
+        },
+    };
+    if let Some(container) = container {
+        if !is_valid_container(container) {
+            return Err(HttpResponse::BadRequest().json("Invalid container"));
+        }
+        matchers.push(LabelMatcher::equal("container", container));
+    }
+
+    let selector = matchers
+        .iter()
+        .map(|m| format!("{}{}{}", m.name, m.op, m.raw_value))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok(format!("{{{}}}{}", selector, pipeline))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMESPACE: &str = "org-foo-inst-bar";
+
+    fn check(query: Option<&str>, container: Option<&str>) -> Option<String> {
+        check_query_only_accesses_namespace(query, container, NAMESPACE).ok()
+    }
+
+    #[test]
+    fn test_parse_stream_selector() {
+        let (matchers, pipeline) =
+            parse_stream_selector(r#"{namespace="a\"b", pod=~`db-.*`} |= "x""#).unwrap();
+        assert_eq!(matchers.len(), 2);
+        assert_eq!(matchers[0].value, "a\"b");
+        assert_eq!(matchers[1].op, "=~");
+        assert_eq!(matchers[1].value, "db-.*");
+        assert_eq!(pipeline, r#" |= "x""#);
+
+        assert!(parse_stream_selector("rate({namespace=\"a\"}[5m])").is_err());
+        assert!(parse_stream_selector("{namespace=a}").is_err());
+        assert!(parse_stream_selector("{namespace=\"a\"").is_err());
+        assert!(parse_stream_selector("{namespace\"a\"}").is_err());
+    }
+
+    #[test]
+    fn test_authorized_queries() {
+        assert_eq!(
+            check(None, None).unwrap(),
+            r#"{namespace="org-foo-inst-bar"}"#
+        );
+        assert_eq!(
+            check(None, Some("postgres")).unwrap(),
+            r#"{namespace="org-foo-inst-bar", container="postgres"}"#
+        );
+        assert_eq!(
+            check(
+                Some(r#"{namespace="org-foo-inst-bar",pod=~"org-foo-inst-bar-1"} |= "ERROR""#),
+                Some("postgres")
+            )
+            .unwrap(),
+            r#"{namespace="org-foo-inst-bar", pod=~"org-foo-inst-bar-1", container="postgres"} |= "ERROR""#
+        );
+        // Braces within strings of the pipeline are allowed
+        assert!(check(
+            Some(r#"{namespace="org-foo-inst-bar"} | json | line_format "{{.msg}}""#),
+            None
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn test_unauthorized_queries() {
+        assert!(check(Some(r#"{namespace="org-other-inst-bar"}"#), None).is_none());
+        assert!(check(Some(r#"{namespace=~"org-foo-inst-bar|org-other"}"#), None).is_none());
+        assert!(check(Some(r#"{container="postgres"}"#), None).is_none());
+        assert!(check(
+            Some(r#"sum(count_over_time({namespace="org-foo-inst-bar"}[5m]))"#),
+            None
+        )
+        .is_none());
+        assert!(check(
+            Some(r#"{namespace="org-foo-inst-bar"} or {namespace="org-other"}"#),
+            None
+        )
+        .is_none());
+        assert!(check(None, Some(r#"postgres"} or {namespace=~".+"#)).is_none());
+    }
+}
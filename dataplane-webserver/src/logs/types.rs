@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Default)]
+pub struct LogQuery {
+    /// A LogQL log query, defaults to all the logs of the instance
+    pub query: Option<String>,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    /// Only return the logs of this container, e.g. postgres
+    pub container: Option<String>,
+    pub limit: Option<u32>,
+    /// Return the most recent lines within the limit, instead of the oldest ones
+    #[serde(default)]
+    pub tail: bool,
+}
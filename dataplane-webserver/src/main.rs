@@ -2,15 +2,40 @@ use actix_web::{middleware, web, App, HttpServer};
 
 use actix_cors::Cors;
 
+use dataplane_webserver::alerts::types::{Alert, AlertSeverity, AlertState};
+use dataplane_webserver::audit::types::AuditEvent;
+use dataplane_webserver::audit::{Audit, AuditLog};
+use dataplane_webserver::backups::schedule::run_schedule_reconciler;
+use dataplane_webserver::backups::types::{
+    ArtifactEncryption, BackupArtifact, BackupDownload, BackupEncryption, BackupJob, BackupMode,
+    BackupRequest, BackupSchedule, BackupScheduleRequest, BaseBackup, EncryptionMethod,
+    InstanceBackups, JobPhase, RestoreJob, RestoreRequest,
+};
+use dataplane_webserver::backups::{run_retention_sweeper, BackupStore};
+use dataplane_webserver::configuration::types::{EffectiveSetting, SettingSource};
+use dataplane_webserver::extensions::types::{
+    AvailableExtension, EnableExtensionRequest, ExtensionLocation, InstanceExtension,
+    InstanceExtensions, TrunkInstallState,
+};
+use dataplane_webserver::insights::types::{
+    ConnectionGroup, ConnectionStats, PoolerConnections, QueryOrder, QueryStats,
+};
+use dataplane_webserver::pooler::types::{DatabaseStats, PoolStats, PoolerStats};
+use dataplane_webserver::rate_limit::RateLimit;
+use dataplane_webserver::roles::types::{Role, RoleCredentials, RolePrivileges, RoleRequest};
 use dataplane_webserver::secrets::types::{AvailableSecret, PasswordString};
+use dataplane_webserver::status::types::{HealthStatus, HealthSummary, PoolerHealth};
 use dataplane_webserver::{
     config,
     routes::health::{lively, ready},
     routes::root,
 };
-use log::info;
+use log::{error, info};
 
-use dataplane_webserver::routes::{metrics, secrets};
+use dataplane_webserver::routes::{
+    alerts, audit, backups, configuration, extensions, insights, logs, metrics, pooler, roles,
+    secrets, status,
+};
 use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
 use utoipa::{Modify, OpenApi};
 use utoipa_redoc::{Redoc, Servable};
@@ -31,6 +56,28 @@ async fn main() -> std::io::Result<()> {
         .build()
         .expect("Failed to create HTTP client");
 
+    // Stored temback artifacts are managed in object storage, when a bucket is configured
+    let backup_store = BackupStore::from_config(&cfg).await;
+    if let Some(store) = backup_store.clone() {
+        if cfg.temback_retention_days > 0 {
+            actix_web::rt::spawn(run_retention_sweeper(store.clone(), cfg.clone()));
+        }
+        actix_web::rt::spawn(run_schedule_reconciler(store, cfg.clone()));
+    }
+
+    // The data API is still served when the audit database is unavailable, audit events are
+    // then only logged
+    let audit_log = match AuditLog::from_config(&cfg).await {
+        Ok(audit_log) => audit_log,
+        Err(e) => {
+            error!("Failed to connect to the audit database: {}", e);
+            None
+        }
+    };
+
+    // Shared by all workers, so clients are limited across them
+    let rate_limit = RateLimit::from_config(&cfg);
+
     #[derive(OpenApi)]
     #[openapi(
         paths(
@@ -39,12 +86,85 @@ async fn main() -> std::io::Result<()> {
               secrets::get_secret_v1,
               secrets::get_secret_names_v1,
               secrets::update_postgres_password,
+              backups::list_backups,
+              backups::create_backup,
+              backups::delete_backup,
+              backups::download_backup,
+              backups::restore_backup,
+              backups::get_restore_job,
+              backups::get_backup_schedule,
+              backups::put_backup_schedule,
+              backups::delete_backup_schedule,
+              backups::get_backup_encryption,
+              backups::put_backup_encryption,
+              backups::delete_backup_encryption,
+              pooler::get_stats,
+              roles::get_roles,
+              roles::post_role,
+              roles::remove_role,
+              extensions::list_extensions,
+              extensions::post_extension,
+              logs::query_logs,
+              alerts::list_alerts,
+              insights::get_top_queries,
+              insights::get_connections,
+              audit::list_audit_events,
+              status::get_health_summary,
+              configuration::get_settings,
               metrics::query_range,
               metrics::query,
+              metrics::list_templates,
+              metrics::template_query_range,
+              metrics::template_query,
+              metrics::stream,
+              metrics::label_values,
+              metrics::series,
         ),
         components(schemas(
             AvailableSecret,
-            PasswordString
+            PasswordString,
+            ArtifactEncryption,
+            BackupArtifact,
+            BackupDownload,
+            BackupEncryption,
+            BackupJob,
+            BackupMode,
+            BackupRequest,
+            BackupSchedule,
+            BackupScheduleRequest,
+            BaseBackup,
+            EncryptionMethod,
+            InstanceBackups,
+            RestoreRequest,
+            RestoreJob,
+            JobPhase,
+            PoolerStats,
+            PoolStats,
+            DatabaseStats,
+            Role,
+            RoleCredentials,
+            RolePrivileges,
+            RoleRequest,
+            InstanceExtensions,
+            InstanceExtension,
+            ExtensionLocation,
+            TrunkInstallState,
+            AvailableExtension,
+            EnableExtensionRequest,
+            Alert,
+            AlertSeverity,
+            AlertState,
+            QueryOrder,
+            QueryStats,
+            ConnectionStats,
+            ConnectionGroup,
+            PoolerConnections,
+            AuditEvent,
+            HealthSummary,
+            HealthStatus,
+            PoolerHealth,
+            EffectiveSetting,
+            SettingSource
         )),
         modifiers(&SecurityAddon),
         security(("jwt_token" = [])),
@@ -93,6 +213,12 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(web::Data::new(cfg.clone()))
             .app_data(web::Data::new(http_client.clone()))
+            .app_data(web::Data::new(backup_store.clone()))
+            .app_data(web::Data::new(audit_log.clone()))
+            // Rate limited calls are not audited, they never reach the routes
+            .wrap(Audit::new(audit_log.clone()))
+            // Inside of CORS, so rate limited responses have its headers
+            .wrap(rate_limit.clone())
             .wrap(cors)
             .wrap(middleware::Logger::default())
             .service(web::scope("/").service(root::ok))
@@ -101,12 +227,48 @@ async fn main() -> std::io::Result<()> {
                     .service(secrets::get_secret_names_v1)
                     .service(secrets::get_secret_v1)
                     .service(secrets::update_postgres_password)
+                    .service(backups::list_backups)
+                    .service(backups::create_backup)
+                    .service(backups::delete_backup)
+                    .service(backups::download_backup)
+                    .service(backups::restore_backup)
+                    .service(backups::get_restore_job)
+                    .service(backups::get_backup_schedule)
+                    .service(backups::put_backup_schedule)
+                    .service(backups::delete_backup_schedule)
+                    .service(pooler::get_stats)
+                    .service(roles::get_roles)
+                    .service(roles::post_role)
+                    .service(roles::remove_role)
+                    .service(extensions::list_extensions)
+                    .service(extensions::post_extension)
+                    .service(logs::query_logs)
+                    .service(alerts::list_alerts)
+                    .service(insights::get_top_queries)
+                    .service(insights::get_connections)
+                    .service(audit::list_audit_events)
+                    .service(status::get_health_summary)
+                    .service(configuration::get_settings)
+            )
+            .service(
+                web::scope("/api/v1/orgs/{org_id}")
+                    .service(backups::get_backup_encryption)
+                    .service(backups::put_backup_encryption)
+                    .service(backups::delete_backup_encryption),
             )
             .service(
                 web::scope("/{namespace}/metrics")
                     .service(metrics::query_range)
-                    .service(metrics::query),
+                    .service(metrics::query)
+                    .service(metrics::list_templates)
+                    .service(metrics::template_query_range)
+                    .service(metrics::template_query)
+                    .service(metrics::stream)
+                    .service(metrics::label_values)
+                    .service(metrics::series),
             )
+            // The deprecated routes by namespace are served until 2027-04-01, clients have moved
+            // to /api/v1/orgs/{org_id}/instances/{instance_id}/secrets by then
             .service(
                 web::scope("/{namespace}/secrets")
                     .service(secrets::get_secret)
@@ -132,3 +132,27 @@ pub fn check_query_only_accesses_namespace<T: PromQuery>(
     }
     Ok(query_str.to_string())
 }
+
+// Returns an error in the form of HttpResponse unless the selector is a series selector, like
+// the match[] parameters of the metadata API, with the namespace label
+pub fn check_series_selector(selector: &str, namespace: &String) -> Result<(), HttpResponse> {
+    match parser::parse(selector) {
+        Ok(Expr::VectorSelector(vector_selector)) => {
+            if validate_vector_selector(namespace, &vector_selector) {
+                Ok(())
+            } else {
+                warn!(
+                    "Unauthorized request: namespace '{}', selector '{}'",
+                    namespace, selector
+                );
+                Err(HttpResponse::Forbidden()
+                    .json("Must include namespace in all series selectors"))
+            }
+        }
+        Ok(_) => Err(HttpResponse::BadRequest().json("match[] must be a series selector")),
+        Err(e) => {
+            error!("Selector parse error: {}", e);
+            Err(HttpResponse::UnprocessableEntity().json("Failed to parse series selector"))
+        }
+    }
+}
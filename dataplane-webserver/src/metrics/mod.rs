@@ -4,24 +4,51 @@ use actix_web::http::StatusCode;
 use actix_web::web::{Data, Query};
 use actix_web::HttpResponse;
 use log::error;
-use reqwest::{Client, Response};
+use moka::future::Cache;
+use reqwest::{Client, RequestBuilder, Response};
 use serde_json::Value;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 pub mod expression_validator;
+pub mod stream;
+pub mod templates;
 pub mod types;
 
-async fn prometheus_response(response: Response) -> HttpResponse {
+const QUERY_CACHE_MAX_ENTRIES: u64 = 10_000;
+
+// Prometheus responses which are not a successful query result
+enum PrometheusFailure {
+    Request(String),
+    Parse(String),
+    Status(StatusCode, Value),
+}
+
+async fn read_response(response: Response) -> Result<Value, PrometheusFailure> {
     let status_code = response.status();
-    let json_response: Value = match response.json().await {
-        Ok(response) => response,
-        Err(e) => {
+    let json_response: Value = response
+        .json()
+        .await
+        .map_err(|e| PrometheusFailure::Parse(e.to_string()))?;
+    match status_code {
+        StatusCode::OK => Ok(json_response),
+        _ => Err(PrometheusFailure::Status(status_code, json_response)),
+    }
+}
+
+fn failure_response(failure: &PrometheusFailure) -> HttpResponse {
+    let (status_code, json_response) = match failure {
+        PrometheusFailure::Request(e) => {
+            error!("Failed to query Prometheus: {}", e);
+            return HttpResponse::GatewayTimeout().json("Failed to query Prometheus");
+        }
+        PrometheusFailure::Parse(e) => {
             error!("Failed to parse Prometheus response: {}", e);
             return HttpResponse::InternalServerError().json("Failed to parse Prometheus response");
         }
+        PrometheusFailure::Status(status_code, json_response) => (*status_code, json_response),
     };
 
     match status_code {
-        StatusCode::OK => HttpResponse::Ok().json(json_response),
         StatusCode::BAD_REQUEST => {
             HttpResponse::BadRequest().json("Prometheus reported the query is malformed")
         }
@@ -39,13 +66,74 @@ async fn prometheus_response(response: Response) -> HttpResponse {
             }
         }
         _ => {
-            error!("{:?}: {:?}", status_code, &json_response);
+            error!("{:?}: {:?}", status_code, json_response);
             HttpResponse::InternalServerError()
                 .json("Prometheus returned an unexpected status code")
         }
     }
 }
 
+// Results of queries by their normalized query and time range, shared by all workers
+static QUERY_CACHE: OnceLock<Option<Cache<String, Arc<Value>>>> = OnceLock::new();
+
+fn query_cache(cfg: &Config) -> Option<&'static Cache<String, Arc<Value>>> {
+    QUERY_CACHE
+        .get_or_init(|| {
+            (cfg.prometheus_cache_ttl_ms > 0).then(|| {
+                Cache::builder()
+                    .max_capacity(QUERY_CACHE_MAX_ENTRIES)
+                    .time_to_live(Duration::from_millis(cfg.prometheus_cache_ttl_ms))
+                    .build()
+            })
+        })
+        .as_ref()
+}
+
+/// Whitespace does not change the meaning of a query, only its cache key
+pub fn normalize_query(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The time of queries which default to now, rounded down to the cache TTL while caching so
+// that clients asking for now at about the same time share a result. The end of a range is
+// only rounded when its start is at least one TTL earlier, so it is never before the start.
+fn default_time(cfg: &Config, start: Option<u64>) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    round_time(now, cfg.prometheus_cache_ttl_ms / 1000, start)
+}
+
+fn round_time(now: u64, ttl_sec: u64, start: Option<u64>) -> u64 {
+    match ttl_sec {
+        0 => now,
+        ttl_sec if start.is_some_and(|start| now < start.saturating_add(ttl_sec)) => now,
+        ttl_sec => now - now % ttl_sec,
+    }
+}
+
+// Send a query unless its result is cached. Concurrent requests for the same key wait for
+// the first one, so Prometheus runs each query once per TTL. Only successful results are
+// cached.
+async fn send_cached(cfg: &Config, request: RequestBuilder, key: String) -> HttpResponse {
+    let fetch = async move {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PrometheusFailure::Request(e.to_string()))?;
+        read_response(response).await.map(Arc::new)
+    };
+    let result = match query_cache(cfg) {
+        Some(cache) => cache.try_get_with(key, fetch).await,
+        None => fetch.await.map_err(Arc::new),
+    };
+    match result {
+        Ok(json_response) => HttpResponse::Ok().json(json_response.as_ref()),
+        Err(failure) => failure_response(&failure),
+    }
+}
+
 pub async fn query_prometheus_instant(
     cfg: Data<Config>,
     http_client: Data<Client>,
@@ -59,12 +147,9 @@ pub async fn query_prometheus_instant(
             Err(http_response) => return http_response,
         };
 
-    let time = instant_query.time.unwrap_or_else(|| {
-        SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs()
-    });
+    let time = instant_query
+        .time
+        .unwrap_or_else(|| default_time(&cfg, None));
 
     let timeout = format!("{}ms", cfg.prometheus_timeout_ms);
     let query_url = format!("{}/api/v1/query", cfg.prometheus_url.trim_end_matches('/'));
@@ -74,22 +159,50 @@ pub async fn query_prometheus_instant(
         ("timeout", &timeout),
     ];
 
-    let response = http_client
+    let request = http_client
         .get(&query_url)
         .query(&query_params)
+        .timeout(Duration::from_millis(
+            cfg.prometheus_timeout_ms as u64 + 500,
+        ));
+    let key = format!("query|{}|{}", normalize_query(&query), time);
+    send_cached(&cfg, request, key).await
+}
+
+/// The result of an instant query at a time, without the response around it. Errors are
+/// messages for clients.
+pub async fn instant_query_result(
+    cfg: &Config,
+    http_client: &Client,
+    query: &str,
+    time: u64,
+) -> Result<Value, String> {
+    let timeout = format!("{}ms", cfg.prometheus_timeout_ms);
+    let query_url = format!("{}/api/v1/query", cfg.prometheus_url.trim_end_matches('/'));
+    let response = http_client
+        .get(&query_url)
+        .query(&[
+            ("query", query),
+            ("time", &time.to_string()),
+            ("timeout", &timeout),
+        ])
         .timeout(Duration::from_millis(
             cfg.prometheus_timeout_ms as u64 + 500,
         ))
         .send()
-        .await;
-
-    match response {
-        Ok(response) => prometheus_response(response).await,
-        Err(e) => {
-            error!("Failed to query Prometheus: {}", e);
-            HttpResponse::GatewayTimeout().json("Failed to query Prometheus")
-        }
+        .await
+        .map_err(|_| "Failed to query Prometheus".to_string())?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Prometheus returned status {}",
+            response.status().as_u16()
+        ));
     }
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|_| "Failed to parse Prometheus response".to_string())?;
+    Ok(body["data"]["result"].clone())
 }
 
 pub async fn query_prometheus(
@@ -107,12 +220,7 @@ pub async fn query_prometheus(
     let start = range_query.start.to_string();
     let end = range_query
         .end
-        .unwrap_or_else(|| {
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs() as f64
-        })
+        .unwrap_or_else(|| default_time(&cfg, Some(range_query.start as u64)) as f64)
         .to_string();
 
     if end.parse::<u64>().unwrap() < start.parse::<u64>().unwrap() {
@@ -159,21 +267,76 @@ pub async fn query_prometheus(
     ];
 
     // Create an HTTP request to the Prometheus backend
-    let response = http_client
+    let request = http_client
         .get(&query_url)
         .query(&query_params)
-        .timeout(reqwest_timeout)
-        .send()
-        .await;
+        .timeout(reqwest_timeout);
+    let key = format!(
+        "query_range|{}|{}|{}|{}",
+        normalize_query(&query),
+        start,
+        end,
+        step
+    );
+    send_cached(&cfg, request, key).await
+}
 
-    // Handle the response
-    match response {
-        Ok(response) => prometheus_response(response).await,
-        Err(e) => {
-            error!("Failed to query Prometheus: {}", e);
-            HttpResponse::GatewayTimeout().json("Failed to query Prometheus")
+/// Check the parameters of a metadata request, the series and label values APIs. Every
+/// match[] selector must select the namespace, which is the only selector when there is none.
+pub fn metadata_params(
+    params: Vec<(String, String)>,
+    namespace: &String,
+    require_match: bool,
+) -> Result<Vec<(String, String)>, HttpResponse> {
+    let mut checked = vec![];
+    for (name, value) in params {
+        match name.as_str() {
+            "match[]" => expression_validator::check_series_selector(&value, namespace)?,
+            "start" | "end" | "limit" => {}
+            _ => {
+                return Err(HttpResponse::BadRequest().json(format!("Unknown parameter '{}'", name)))
+            }
         }
+        checked.push((name, value));
     }
+    if !checked.iter().any(|(name, _)| name == "match[]") {
+        if require_match {
+            return Err(
+                HttpResponse::BadRequest().json("At least one match[] selector is required")
+            );
+        }
+        checked.push((
+            "match[]".to_string(),
+            format!("{{namespace=\"{}\"}}", namespace),
+        ));
+    }
+    Ok(checked)
+}
+
+/// Proxy a request to an endpoint of the metadata API of Prometheus, with checked parameters
+pub async fn query_prometheus_metadata(
+    cfg: Data<Config>,
+    http_client: Data<Client>,
+    endpoint: &str,
+    params: &[(String, String)],
+) -> HttpResponse {
+    let query_url = format!(
+        "{}/api/v1/{}",
+        cfg.prometheus_url.trim_end_matches('/'),
+        endpoint
+    );
+    let request = http_client
+        .get(&query_url)
+        .query(params)
+        .timeout(Duration::from_millis(
+            cfg.prometheus_timeout_ms as u64 + 500,
+        ));
+    let selectors: Vec<String> = params
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, normalize_query(value)))
+        .collect();
+    let key = format!("{}|{}", endpoint, selectors.join("|"));
+    send_cached(&cfg, request, key).await
 }
 
 fn parse_duration(duration: &str) -> Result<Duration, &'static str> {
@@ -240,4 +403,72 @@ mod tests {
         assert!(parse_duration("5m6").is_err());
         assert!(parse_duration("5.5h").is_err());
     }
+
+    #[test]
+    fn test_normalize_query() {
+        assert_eq!(
+            normalize_query("sum by (namespace) (\n  up{namespace=\"org-foo-inst-bar\"}\n)"),
+            "sum by (namespace) ( up{namespace=\"org-foo-inst-bar\"} )"
+        );
+    }
+
+    #[test]
+    fn test_round_time() {
+        assert_eq!(round_time(1007, 0, None), 1007);
+        assert_eq!(round_time(1007, 5, None), 1005);
+        assert_eq!(round_time(1007, 5, Some(1000)), 1005);
+        // A start within the last TTL keeps the end at now
+        assert_eq!(round_time(1007, 5, Some(1006)), 1007);
+        assert_eq!(round_time(1007, 5, Some(1003)), 1007);
+        assert_eq!(round_time(1007, 5, Some(1002)), 1005);
+    }
+
+    #[test]
+    fn test_metadata_params() {
+        let namespace = "org-foo-inst-bar".to_string();
+        let params = vec![
+            (
+                "match[]".to_string(),
+                "cnpg_backends_total{namespace=\"org-foo-inst-bar\"}".to_string(),
+            ),
+            ("start".to_string(), "1686780828".to_string()),
+        ];
+        assert_eq!(
+            metadata_params(params.clone(), &namespace, true).unwrap(),
+            params
+        );
+
+        // Label values are limited to the namespace without a selector
+        assert_eq!(
+            metadata_params(vec![], &namespace, false).unwrap(),
+            vec![(
+                "match[]".to_string(),
+                "{namespace=\"org-foo-inst-bar\"}".to_string()
+            )]
+        );
+        assert!(metadata_params(vec![], &namespace, true).is_err());
+
+        let other_namespace = vec![(
+            "match[]".to_string(),
+            "up{namespace=~\"org-.*\"}".to_string(),
+        )];
+        assert_eq!(
+            metadata_params(other_namespace, &namespace, false)
+                .unwrap_err()
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+        let expression = vec![(
+            "match[]".to_string(),
+            "sum(up{namespace=\"org-foo-inst-bar\"})".to_string(),
+        )];
+        assert_eq!(
+            metadata_params(expression, &namespace, false)
+                .unwrap_err()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        let unknown = vec![("limit_per_metric".to_string(), "1".to_string())];
+        assert!(metadata_params(unknown, &namespace, false).is_err());
+    }
 }
@@ -0,0 +1,184 @@
+use crate::config::Config;
+use crate::metrics::instant_query_result;
+use crate::metrics::templates;
+use crate::metrics::types::{MetricsSample, StreamQuery};
+use actix_web::web::Bytes;
+use futures::future::join_all;
+use futures::Stream;
+use reqwest::Client;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+const DEFAULT_METRICS: [&str; 3] = ["connections", "tps", "cpu"];
+const DEFAULT_INTERVAL_SEC: u64 = 5;
+const MAX_INTERVAL_SEC: u64 = 300;
+
+/// Render the templates of a stream request, returns the queries by template name and the
+/// interval between samples
+pub fn parse_stream_query(
+    cfg: &Config,
+    stream_query: &StreamQuery,
+    namespace: &str,
+) -> Result<(BTreeMap<String, String>, Duration), String> {
+    let names: Vec<&str> = match &stream_query.metrics {
+        Some(metrics) => metrics
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect(),
+        None => DEFAULT_METRICS.to_vec(),
+    };
+    if names.is_empty() {
+        return Err("No metrics requested".to_string());
+    }
+
+    let mut queries = BTreeMap::new();
+    for name in names {
+        let Some(template) = cfg.metrics_query_templates.get(name) else {
+            return Err(format!("Query template '{}' not found", name));
+        };
+        queries.insert(
+            name.to_string(),
+            templates::render(template, namespace, &HashMap::new())?,
+        );
+    }
+
+    let interval = stream_query.interval.unwrap_or(DEFAULT_INTERVAL_SEC);
+    if interval < cfg.metrics_stream_min_interval_sec || interval > MAX_INTERVAL_SEC {
+        return Err(format!(
+            "Interval must be between {} and {} seconds",
+            cfg.metrics_stream_min_interval_sec, MAX_INTERVAL_SEC
+        ));
+    }
+    Ok((queries, Duration::from_secs(interval)))
+}
+
+/// Format a Server-Sent Event, the data is JSON on a single line
+pub fn sse_event(event: &str, data: &impl serde::Serialize) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_else(|_| "null".to_string());
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+// Query all metrics of a stream at the same time, a metric failing does not end the stream
+async fn sample(
+    cfg: &Config,
+    http_client: &Client,
+    queries: &BTreeMap<String, String>,
+) -> MetricsSample {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    let results = join_all(
+        queries
+            .values()
+            .map(|query| instant_query_result(cfg, http_client, query, time)),
+    )
+    .await;
+
+    let mut metrics_sample = MetricsSample {
+        time,
+        metrics: BTreeMap::new(),
+        errors: BTreeMap::new(),
+    };
+    for (name, result) in queries.keys().zip(results) {
+        match result {
+            Ok(value) => {
+                metrics_sample.metrics.insert(name.clone(), value);
+            }
+            Err(e) => {
+                metrics_sample.errors.insert(name.clone(), e);
+            }
+        }
+    }
+    metrics_sample
+}
+
+struct StreamState {
+    cfg: Config,
+    http_client: Client,
+    queries: BTreeMap<String, String>,
+    interval: Interval,
+    deadline: Instant,
+}
+
+/// A stream of `metrics` events, sampling the queries every interval until the maximum
+/// duration of a stream
+pub fn metrics_stream(
+    cfg: Config,
+    http_client: Client,
+    queries: BTreeMap<String, String>,
+    interval: Duration,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let mut interval = tokio::time::interval(interval);
+    // A slow Prometheus delays the next samples rather than bursting them
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let state = StreamState {
+        deadline: Instant::now() + Duration::from_secs(cfg.metrics_stream_max_duration_sec),
+        cfg,
+        http_client,
+        queries,
+        interval,
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        state.interval.tick().await;
+        if Instant::now() >= state.deadline {
+            return None;
+        }
+        let metrics_sample = sample(&state.cfg, &state.http_client, &state.queries).await;
+        Some((Ok(sse_event("metrics", &metrics_sample)), state))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_query(metrics: Option<&str>, interval: Option<u64>) -> StreamQuery {
+        StreamQuery {
+            metrics: metrics.map(str::to_string),
+            interval,
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_query() {
+        let cfg = Config::default();
+        let (queries, interval) =
+            parse_stream_query(&cfg, &stream_query(None, None), "org-acme-inst-db").unwrap();
+        assert_eq!(
+            queries.keys().collect::<Vec<_>>(),
+            vec!["connections", "cpu", "tps"]
+        );
+        assert!(queries["tps"].contains("namespace=\"org-acme-inst-db\""));
+        assert_eq!(interval, Duration::from_secs(5));
+
+        let (queries, interval) = parse_stream_query(
+            &cfg,
+            &stream_query(Some("memory, cpu"), Some(10)),
+            "org-acme-inst-db",
+        )
+        .unwrap();
+        assert_eq!(queries.keys().collect::<Vec<_>>(), vec!["cpu", "memory"]);
+        assert_eq!(interval, Duration::from_secs(10));
+
+        assert!(parse_stream_query(&cfg, &stream_query(Some("disk"), None), "ns").is_err());
+        assert!(parse_stream_query(&cfg, &stream_query(Some(","), None), "ns").is_err());
+        assert!(parse_stream_query(&cfg, &stream_query(None, Some(1)), "ns").is_err());
+        assert!(parse_stream_query(&cfg, &stream_query(None, Some(301)), "ns").is_err());
+    }
+
+    #[test]
+    fn test_sse_event() {
+        let metrics_sample = MetricsSample {
+            time: 1717243200,
+            metrics: BTreeMap::from([("cpu".to_string(), serde_json::json!([]))]),
+            errors: BTreeMap::new(),
+        };
+        assert_eq!(
+            sse_event("metrics", &metrics_sample),
+            Bytes::from("event: metrics\ndata: {\"time\":1717243200,\"metrics\":{\"cpu\":[]}}\n\n")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// A named PromQL query clients can run with parameters, instead of sending free-form
+/// PromQL. `$namespace` is replaced with the namespace of the request, and `$<param>`
+/// with the value of a parameter, or its default.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueryTemplate {
+    pub query: String,
+    /// The parameters of the query and their default values
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+// Parameter values end up inside of the query, they are limited to characters which can
+// not change its structure, e.g. durations like 5m or label values like a pod name
+const MAX_PARAM_LEN: usize = 64;
+
+fn is_safe_value(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_PARAM_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+}
+
+fn template(query: &str, params: &[(&str, &str)]) -> QueryTemplate {
+    QueryTemplate {
+        query: query.to_string(),
+        params: params
+            .iter()
+            .map(|(name, default)| (name.to_string(), default.to_string()))
+            .collect(),
+    }
+}
+
+/// The templates served unless they are replaced with METRICS_QUERY_TEMPLATES
+pub fn default_templates() -> BTreeMap<String, QueryTemplate> {
+    BTreeMap::from([
+        (
+            "cpu".to_string(),
+            template(
+                "sum by (namespace) (rate(container_cpu_usage_seconds_total{namespace=\"$namespace\", container=\"postgres\"}[$window]))",
+                &[("window", "5m")],
+            ),
+        ),
+        (
+            "memory".to_string(),
+            template(
+                "sum by (namespace) (container_memory_working_set_bytes{namespace=\"$namespace\", container=\"postgres\"})",
+                &[],
+            ),
+        ),
+        (
+            "connections".to_string(),
+            template(
+                "sum by (namespace, state) (cnpg_backends_total{namespace=\"$namespace\"})",
+                &[],
+            ),
+        ),
+        (
+            "tps".to_string(),
+            template(
+                "sum by (namespace) (rate(cnpg_pg_stat_database_xact_commit{namespace=\"$namespace\"}[$window]) + rate(cnpg_pg_stat_database_xact_rollback{namespace=\"$namespace\"}[$window]))",
+                &[("window", "1m")],
+            ),
+        ),
+        (
+            "replication_lag".to_string(),
+            template(
+                "max by (namespace, pod) (cnpg_pg_replication_lag{namespace=\"$namespace\"})",
+                &[],
+            ),
+        ),
+    ])
+}
+
+/// Parse the templates configured as JSON, added to the default ones. A template with the
+/// name of a default one replaces it.
+pub fn parse_templates(templates: &str) -> BTreeMap<String, QueryTemplate> {
+    let mut all = default_templates();
+    if templates.trim().is_empty() {
+        return all;
+    }
+    match serde_json::from_str::<BTreeMap<String, QueryTemplate>>(templates) {
+        Ok(configured) => all.extend(configured),
+        Err(e) => error!("METRICS_QUERY_TEMPLATES is not valid, ignoring it: {}", e),
+    }
+    all
+}
+
+/// Replace the placeholders of a template. Only the parameters of the template are
+/// accepted, and their values must not change the structure of the query.
+pub fn render(
+    template: &QueryTemplate,
+    namespace: &str,
+    params: &HashMap<String, String>,
+) -> Result<String, String> {
+    if let Some(unknown) = params
+        .keys()
+        .find(|name| !template.params.contains_key(*name))
+    {
+        return Err(format!("Unknown parameter '{}'", unknown));
+    }
+    let mut values: HashMap<&str, &str> = template
+        .params
+        .iter()
+        .map(|(name, default)| (name.as_str(), default.as_str()))
+        .collect();
+    for (name, value) in params {
+        if !is_safe_value(value) {
+            return Err(format!("Invalid value for parameter '{}'", name));
+        }
+        values.insert(name, value);
+    }
+    if !is_safe_value(namespace) {
+        return Err("Invalid namespace".to_string());
+    }
+    values.insert("namespace", namespace);
+
+    let mut query = String::with_capacity(template.query.len());
+    let mut rest = template.query.as_str();
+    while let Some(start) = rest.find('$') {
+        query.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..];
+        let len = placeholder
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(placeholder.len());
+        let name = &placeholder[..len];
+        match values.get(name) {
+            Some(value) => query.push_str(value),
+            None => return Err(format!("Template has no parameter '{}'", name)),
+        }
+        rest = &placeholder[len..];
+    }
+    query.push_str(rest);
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let templates = default_templates();
+        let cpu = &templates["cpu"];
+        assert_eq!(
+            render(cpu, "org-acme-inst-db", &HashMap::new()).unwrap(),
+            "sum by (namespace) (rate(container_cpu_usage_seconds_total{namespace=\"org-acme-inst-db\", container=\"postgres\"}[5m]))"
+        );
+
+        let params = HashMap::from([("window".to_string(), "1h".to_string())]);
+        assert!(render(cpu, "org-acme-inst-db", &params)
+            .unwrap()
+            .contains("[1h]"));
+
+        // Values which could change the query are rejected
+        let params = HashMap::from([("window".to_string(), "5m]) or up{a=\"".to_string())]);
+        assert!(render(cpu, "org-acme-inst-db", &params).is_err());
+        let params = HashMap::from([("other".to_string(), "1".to_string())]);
+        assert!(render(cpu, "org-acme-inst-db", &params).is_err());
+        assert!(render(cpu, "org-acme\"} or up{x=\"", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_parse_templates() {
+        let templates = parse_templates(
+            r#"{"commits": {"query": "sum(rate(cnpg_pg_stat_database_xact_commit{namespace=\"$namespace\"}[$window]))", "params": {"window": "5m"}}}"#,
+        );
+        assert!(templates.contains_key("commits"));
+        assert!(templates.contains_key("cpu"));
+
+        // Invalid configuration falls back to the default templates
+        assert_eq!(parse_templates("not json"), default_templates());
+    }
+}
@@ -1,4 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 #[derive(Deserialize, Clone)]
 pub struct RangeQuery {
@@ -13,3 +15,21 @@ pub struct InstantQuery {
     pub query: String,
     pub time: Option<u64>,
 }
+
+#[derive(Deserialize, Clone)]
+pub struct StreamQuery {
+    /// Comma separated names of query templates, defaults to connections, tps and cpu
+    pub metrics: Option<String>,
+    /// Seconds between samples
+    pub interval: Option<u64>,
+}
+
+/// One event of a metrics stream, the instant query result of each template
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MetricsSample {
+    pub time: u64,
+    pub metrics: BTreeMap<String, Value>,
+    /// The templates which could not be queried this time, with the reason
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
@@ -0,0 +1,228 @@
+use crate::pooler::types::{DatabaseStats, PoolStats, PoolerStats};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::ListParams;
+use kube::{Api, Client};
+use log::error;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub mod types;
+
+// The PgBouncer exporter of CNPG pooler pods, which serves the output of SHOW POOLS and
+// SHOW STATS from the admin console
+const POOLER_METRICS_PORT: u16 = 9127;
+const POOLS_PREFIX: &str = "cnpg_pgbouncer_pools_";
+const STATS_PREFIX: &str = "cnpg_pgbouncer_stats_";
+
+#[derive(Error, Debug)]
+pub enum PoolerError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Connection pooler is not enabled for {0}")]
+    NotEnabled(String),
+
+    #[error("Connection pooler stats are unavailable for {0}")]
+    Unavailable(String),
+}
+
+/// Get the pool and traffic stats of each running pooler pod of an instance
+pub async fn get_pooler_stats(
+    kubernetes_client: Client,
+    http_client: &reqwest::Client,
+    namespace: &str,
+) -> Result<Vec<PoolerStats>, PoolerError> {
+    // The pooler of an instance is named after the instance, which is named after its namespace
+    let pods: Api<Pod> = Api::namespaced(kubernetes_client, namespace);
+    let lp = ListParams::default().labels(&format!("cnpg.io/poolerName={}-pooler", namespace));
+    let pod_list = pods
+        .list(&lp)
+        .await
+        .map_err(|e| PoolerError::Kube(e.to_string()))?;
+    if pod_list.items.is_empty() {
+        return Err(PoolerError::NotEnabled(namespace.to_string()));
+    }
+
+    let mut stats = vec![];
+    for pod in pod_list.items {
+        let name = pod.metadata.name.clone().unwrap_or_default();
+        let Some(pod_ip) = pod
+            .status
+            .filter(|status| status.phase.as_deref() == Some("Running"))
+            .and_then(|status| status.pod_ip)
+        else {
+            continue;
+        };
+        let url = format!("http://{}:{}/metrics", pod_ip, POOLER_METRICS_PORT);
+        let response = http_client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let body = match response {
+            Ok(response) => response.text().await,
+            Err(e) => Err(e),
+        };
+        match body {
+            Ok(body) => stats.push(parse_pooler_metrics(&name, &body)),
+            Err(e) => error!("Failed to get pooler stats from pod {}: {}", name, e),
+        }
+    }
+    if stats.is_empty() {
+        return Err(PoolerError::Unavailable(namespace.to_string()));
+    }
+    Ok(stats)
+}
+
+/// Collect the pool and traffic stats from the Prometheus metrics of a pooler pod
+pub fn parse_pooler_metrics(pod: &str, metrics: &str) -> PoolerStats {
+    let mut pools: BTreeMap<(String, String), PoolStats> = BTreeMap::new();
+    let mut databases: BTreeMap<String, DatabaseStats> = BTreeMap::new();
+
+    for (name, labels, value) in metrics.lines().filter_map(parse_sample) {
+        let database = labels.get("database").cloned().unwrap_or_default();
+        // Counters may be exposed as floats, the stats are whole numbers
+        let value = value as i64;
+        if let Some(field) = name.strip_prefix(POOLS_PREFIX) {
+            let user = labels.get("user").cloned().unwrap_or_default();
+            let pool = pools
+                .entry((database.clone(), user.clone()))
+                .or_insert_with(|| PoolStats {
+                    database,
+                    user,
+                    ..PoolStats::default()
+                });
+            match field {
+                "cl_active" => pool.cl_active = value,
+                "cl_waiting" => pool.cl_waiting = value,
+                "sv_active" => pool.sv_active = value,
+                "sv_idle" => pool.sv_idle = value,
+                "sv_used" => pool.sv_used = value,
+                "maxwait" => pool.maxwait = value,
+                _ => {}
+            }
+        } else if let Some(field) = name.strip_prefix(STATS_PREFIX) {
+            let stats = databases
+                .entry(database.clone())
+                .or_insert_with(|| DatabaseStats {
+                    database,
+                    ..DatabaseStats::default()
+                });
+            match field {
+                "total_xact_count" => stats.total_xact_count = value,
+                "total_query_count" => stats.total_query_count = value,
+                "total_received" => stats.total_received = value,
+                "total_sent" => stats.total_sent = value,
+                "avg_xact_time" => stats.avg_xact_time = value,
+                "avg_query_time" => stats.avg_query_time = value,
+                "avg_wait_time" => stats.avg_wait_time = value,
+                _ => {}
+            }
+        }
+    }
+
+    PoolerStats {
+        pod: pod.to_string(),
+        pools: pools.into_values().collect(),
+        databases: databases.into_values().collect(),
+    }
+}
+
+// Parse a sample line of the Prometheus text format, like `name{label="value"} 1`
+fn parse_sample(line: &str) -> Option<(&str, BTreeMap<String, String>, f64)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (series, value) = line.rsplit_once(' ')?;
+    let value = value.parse::<f64>().ok()?;
+    let Some((name, labels)) = series.split_once('{') else {
+        return Some((series, BTreeMap::new(), value));
+    };
+    let labels = labels.strip_suffix('}')?;
+
+    let mut parsed = BTreeMap::new();
+    let mut rest = labels;
+    while let Some((key, after)) = rest.split_once("=\"") {
+        // Label values escape quotes and backslashes with a backslash
+        let mut label_value = String::new();
+        let mut chars = after.char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        label_value.push(if escaped == 'n' { '\n' } else { escaped });
+                    }
+                }
+                '"' => {
+                    end = Some(i);
+                    break;
+                }
+                c => label_value.push(c),
+            }
+        }
+        parsed.insert(key.trim_start_matches(',').trim().to_string(), label_value);
+        rest = &after[end? + 1..];
+    }
+    Some((name, parsed, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METRICS: &str = r#"# HELP cnpg_pgbouncer_pools_cl_active Client connections that are linked to server connection and can process queries.
+# TYPE cnpg_pgbouncer_pools_cl_active gauge
+cnpg_pgbouncer_pools_cl_active{database="app",user="app"} 4
+cnpg_pgbouncer_pools_cl_active{database="pgbouncer",user="pgbouncer"} 1
+cnpg_pgbouncer_pools_cl_waiting{database="app",user="app"} 2
+cnpg_pgbouncer_pools_sv_idle{database="app",user="app"} 6
+cnpg_pgbouncer_pools_maxwait{database="app",user="app"} 1
+cnpg_pgbouncer_stats_total_query_count{database="app"} 1.2345e+06
+cnpg_pgbouncer_stats_avg_wait_time{database="app"} 250
+cnpg_pgbouncer_up 1
+"#;
+
+    #[test]
+    fn test_parse_pooler_metrics() {
+        let stats = parse_pooler_metrics("test-pooler-abc", METRICS);
+        assert_eq!(stats.pod, "test-pooler-abc");
+        assert_eq!(stats.pools.len(), 2);
+        assert_eq!(
+            stats.pools[0],
+            PoolStats {
+                database: "app".to_string(),
+                user: "app".to_string(),
+                cl_active: 4,
+                cl_waiting: 2,
+                sv_idle: 6,
+                maxwait: 1,
+                ..PoolStats::default()
+            }
+        );
+        assert_eq!(stats.pools[1].database, "pgbouncer");
+        assert_eq!(
+            stats.databases,
+            vec![DatabaseStats {
+                database: "app".to_string(),
+                total_query_count: 1_234_500,
+                avg_wait_time: 250,
+                ..DatabaseStats::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        let (name, labels, value) =
+            parse_sample(r#"metric{database="a \"b\"",user="c,d"} 3"#).unwrap();
+        assert_eq!(name, "metric");
+        assert_eq!(labels.get("database").unwrap(), r#"a "b""#);
+        assert_eq!(labels.get("user").unwrap(), "c,d");
+        assert_eq!(value, 3.0);
+
+        assert!(parse_sample("# TYPE metric gauge").is_none());
+        assert!(parse_sample("metric{database=\"a\"} NaNx").is_none());
+    }
+}
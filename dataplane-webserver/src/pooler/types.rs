@@ -0,0 +1,49 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct PoolerStats {
+    /// The name of the pooler pod the stats are from
+    pub pod: String,
+    /// The connection pools, from SHOW POOLS
+    pub pools: Vec<PoolStats>,
+    /// The traffic per database, from SHOW STATS
+    pub databases: Vec<DatabaseStats>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    pub database: String,
+    pub user: String,
+    /// Client connections linked to a server connection
+    pub cl_active: i64,
+    /// Client connections waiting for a server connection
+    pub cl_waiting: i64,
+    /// Server connections linked to a client connection
+    pub sv_active: i64,
+    /// Server connections available for use
+    pub sv_idle: i64,
+    /// Server connections idle for longer than server_check_delay
+    pub sv_used: i64,
+    /// How long the oldest waiting client has waited, in seconds
+    pub maxwait: i64,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct DatabaseStats {
+    pub database: String,
+    /// Transactions pooled since the pooler started
+    pub total_xact_count: i64,
+    /// Queries pooled since the pooler started
+    pub total_query_count: i64,
+    /// Bytes received from clients since the pooler started
+    pub total_received: i64,
+    /// Bytes sent to clients since the pooler started
+    pub total_sent: i64,
+    /// Average transaction duration, in microseconds
+    pub avg_xact_time: i64,
+    /// Average query duration, in microseconds
+    pub avg_query_time: i64,
+    /// Average time clients waited for a server connection, in microseconds
+    pub avg_wait_time: i64,
+}
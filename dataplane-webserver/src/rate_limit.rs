@@ -0,0 +1,247 @@
+use crate::config::Config;
+use crate::routes::secrets::requester;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{Error, HttpResponse};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// Full buckets are dropped once there are this many, a full bucket is the same as none. When
+// they are all in use, the least recently used half of them are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets by requester and instance, each allowing bursts of requests which are then
+/// refilled at a steady rate
+pub struct RateLimiter {
+    requests_per_sec: f64,
+    burst: f64,
+    max_buckets: usize,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self {
+            requests_per_sec: requests_per_minute as f64 / 60.0,
+            burst: burst.max(1) as f64,
+            max_buckets: MAX_BUCKETS,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.requests_per_sec).min(self.burst)
+    }
+
+    /// Take a request from the bucket of a key, or return how long until one is available
+    pub fn check(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= self.max_buckets && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+            if buckets.len() >= self.max_buckets {
+                let mut updated: Vec<Instant> =
+                    buckets.values().map(|bucket| bucket.updated).collect();
+                let (_, cutoff, _) = updated.select_nth_unstable(self.max_buckets / 2);
+                let cutoff = *cutoff;
+                buckets.retain(|_, bucket| bucket.updated > cutoff);
+            }
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.requests_per_sec,
+            ))
+        }
+    }
+}
+
+/// The instance a request is about, as `org_id/instance_id` or a namespace, or the org ID of
+/// requests about all its instances. Requests which are not about an instance, like health
+/// checks and the API docs, are not limited.
+pub fn request_scope(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", "v1", "orgs", org_id, "instances", instance_id, ..] => {
+            Some(format!("{}/{}", org_id, instance_id))
+        }
+        ["api", "v1", "orgs", org_id, "backup-encryption"] => Some(org_id.to_string()),
+        [namespace, "metrics" | "secrets", ..] => Some(namespace.to_string()),
+        _ => None,
+    }
+}
+
+/// The bucket of a request: the requester and the instance it is about, or the instance alone
+/// for tokens without a subject. Tokens are verified by the gateway in front of the data
+/// plane, their signature is not checked again here.
+pub fn rate_limit_key(requester: Option<&str>, path: &str) -> Option<String> {
+    let scope = request_scope(path)?;
+    Some(match requester {
+        Some(requester) => format!("{}|{}", requester, scope),
+        None => scope,
+    })
+}
+
+/// Middleware answering `429 Too Many Requests` with a `Retry-After` header to clients over
+/// their rate limit, disabled when RATE_LIMIT_REQUESTS_PER_MINUTE is 0
+#[derive(Clone)]
+pub struct RateLimit {
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RateLimit {
+    /// Build the middleware once and clone it into each worker, so they share the buckets
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            limiter: (cfg.rate_limit_requests_per_minute > 0).then(|| {
+                Arc::new(RateLimiter::new(
+                    cfg.rate_limit_requests_per_minute,
+                    cfg.rate_limit_burst,
+                ))
+            }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(limiter) = &self.limiter {
+            if let Some(key) = rate_limit_key(requester(req.request()).as_deref(), req.path()) {
+                if let Err(retry_after) = limiter.check(&key, Instant::now()) {
+                    warn!("Rate limited {} {}", key, req.path());
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((RETRY_AFTER, retry_after.as_secs_f64().ceil() as u64))
+                        .json("Too many requests");
+                    return Box::pin(ready(Ok(req.into_response(response).map_into_right_body())));
+                }
+            }
+        }
+        let response = self.service.call(req);
+        Box::pin(async move { response.await.map(ServiceResponse::map_into_left_body) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(60, 2);
+        let start = Instant::now();
+        assert!(limiter.check("a", start).is_ok());
+        assert!(limiter.check("a", start).is_ok());
+        assert_eq!(limiter.check("a", start), Err(Duration::from_secs(1)));
+        // Other keys have their own bucket
+        assert!(limiter.check("b", start).is_ok());
+        // One request per second is refilled
+        assert!(limiter.check("a", start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check("a", start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_evicts_oldest() {
+        let mut limiter = RateLimiter::new(60, 1);
+        limiter.max_buckets = 4;
+        let start = Instant::now();
+        for (i, key) in ["a", "b", "c", "d"].iter().enumerate() {
+            assert!(limiter
+                .check(key, start + Duration::from_millis(i as u64))
+                .is_ok());
+        }
+        // All buckets are in use, the oldest are dropped for a new key
+        let now = start + Duration::from_millis(10);
+        assert!(limiter.check("e", now).is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.len() <= 4);
+        assert!(!buckets.contains_key("a"));
+        assert!(buckets.contains_key("d"));
+        assert!(buckets.contains_key("e"));
+    }
+
+    #[test]
+    fn test_request_scope() {
+        assert_eq!(
+            request_scope("/api/v1/orgs/org_1/instances/inst_2/backups"),
+            Some("org_1/inst_2".to_string())
+        );
+        assert_eq!(
+            request_scope("/org-foo-inst-bar/metrics/query_range"),
+            Some("org-foo-inst-bar".to_string())
+        );
+        assert_eq!(
+            request_scope("/api/v1/orgs/org_1/backup-encryption"),
+            Some("org_1".to_string())
+        );
+        assert_eq!(request_scope("/health/ready"), None);
+        assert_eq!(request_scope("/swagger-ui/index.html"), None);
+    }
+
+    #[test]
+    fn test_rate_limit_key() {
+        let path = "/api/v1/orgs/org_1/instances/inst_2/secrets";
+        assert_eq!(
+            rate_limit_key(Some("user_1"), path),
+            Some("user_1|org_1/inst_2".to_string())
+        );
+        assert_ne!(
+            rate_limit_key(Some("user_1"), path),
+            rate_limit_key(Some("user_2"), path)
+        );
+        assert_eq!(rate_limit_key(None, path), Some("org_1/inst_2".to_string()));
+        assert_eq!(rate_limit_key(Some("user_1"), "/health/ready"), None);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::exec::{psql, psql_database, PsqlError};
+use crate::roles::types::{Role, RoleCredentials, RolePrivileges, RoleRequest};
+use kube::Client;
+use lazy_static::lazy_static;
+use log::info;
+use rand::distributions::{Alphanumeric, DistString};
+use regex::Regex;
+use thiserror::Error;
+
+pub mod types;
+
+lazy_static! {
+    static ref ROLE_NAME: Regex = Regex::new(r"^[a-z_][a-z0-9_]{0,62}$").unwrap();
+}
+
+// Roles created through the data API are marked with this comment. Only these are listed and
+// can be deleted, never the roles of the instance itself.
+const MANAGED_ROLE_COMMENT: &str = "Managed by the Tembo data API";
+// The roles CNPG and Tembo create in each instance
+const RESERVED_ROLES: [&str; 5] = [
+    "postgres",
+    "app",
+    "readonly",
+    "streaming_replica",
+    "postgres_exporter",
+];
+const PASSWORD_LENGTH: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum RoleError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Invalid role name: {0}")]
+    InvalidName(String),
+
+    #[error("Invalid role request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Role already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Role not found: {0}")]
+    NotFound(String),
+
+    #[error("No running primary for {0}")]
+    Unavailable(String),
+
+    #[error("psql error: {0}")]
+    Psql(String),
+}
+
+impl From<PsqlError> for RoleError {
+    fn from(e: PsqlError) -> Self {
+        match e {
+            PsqlError::Kube(e) => RoleError::Kube(e),
+            PsqlError::Unavailable(namespace) => RoleError::Unavailable(namespace),
+            PsqlError::Psql(e) => RoleError::Psql(e),
+        }
+    }
+}
+
+pub fn is_valid_role_name(name: &str) -> bool {
+    ROLE_NAME.is_match(name) && !name.starts_with("pg_") && !RESERVED_ROLES.contains(&name)
+}
+
+fn quote_ident(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn create_role_sql(request: &RoleRequest, password: &str) -> String {
+    let role = quote_ident(&request.name);
+    // Keep the statement carrying the password out of the server log and pg_stat_statements
+    let mut statements = vec![
+        "SET log_statement = 'none';".to_string(),
+        "SET pg_stat_statements.track_utility = off;".to_string(),
+        "BEGIN;".to_string(),
+        format!(
+            "CREATE ROLE {} LOGIN PASSWORD {} CONNECTION LIMIT {};",
+            role,
+            quote_literal(password),
+            request.connection_limit.unwrap_or(-1)
+        ),
+        format!(
+            "COMMENT ON ROLE {} IS {};",
+            role,
+            quote_literal(MANAGED_ROLE_COMMENT)
+        ),
+        format!("GRANT pg_read_all_data TO {};", role),
+    ];
+    if request.privileges == RolePrivileges::ReadWrite {
+        statements.push(format!("GRANT pg_write_all_data TO {};", role));
+    }
+    statements.push("COMMIT;".to_string());
+    statements.join("\n")
+}
+
+fn list_roles_sql() -> String {
+    format!(
+        "SELECT r.rolname, pg_has_role(r.oid, 'pg_write_all_data', 'MEMBER'), r.rolconnlimit \
+         FROM pg_roles r WHERE shobj_description(r.oid, 'pg_authid') = {} ORDER BY r.rolname;",
+        quote_literal(MANAGED_ROLE_COMMENT)
+    )
+}
+
+// The databases a role can own objects in
+const LIST_DATABASES_SQL: &str =
+    "SELECT datname FROM pg_database WHERE datallowconn ORDER BY datname;";
+
+// Parse the unaligned output of the list query, one `name|writer|limit` line per role
+fn parse_roles(output: &str) -> Vec<Role> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('|');
+            let name = fields.next()?.to_string();
+            let privileges = match fields.next()? {
+                "t" => RolePrivileges::ReadWrite,
+                _ => RolePrivileges::ReadOnly,
+            };
+            let connection_limit = fields.next()?.parse().ok()?;
+            Some(Role {
+                name,
+                privileges,
+                connection_limit,
+            })
+        })
+        .collect()
+}
+
+/// List the roles of an instance managed through the data API
+pub async fn list_roles(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<Vec<Role>, RoleError> {
+    let output = psql(kubernetes_client, namespace, &list_roles_sql()).await?;
+    Ok(parse_roles(&output))
+}
+
+/// Create a login role in an instance, with a random password
+pub async fn create_role(
+    kubernetes_client: Client,
+    namespace: &str,
+    request: &RoleRequest,
+) -> Result<RoleCredentials, RoleError> {
+    if !is_valid_role_name(&request.name) {
+        return Err(RoleError::InvalidName(request.name.clone()));
+    }
+    if request.connection_limit.is_some_and(|limit| limit < 0) {
+        return Err(RoleError::InvalidRequest(
+            "The connection limit must not be negative".to_string(),
+        ));
+    }
+    let password = Alphanumeric.sample_string(&mut rand::thread_rng(), PASSWORD_LENGTH);
+    match psql(
+        kubernetes_client,
+        namespace,
+        &create_role_sql(request, &password),
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(PsqlError::Psql(e)) if e.contains("already exists") => {
+            return Err(RoleError::AlreadyExists(request.name.clone()))
+        }
+        Err(e) => return Err(e.into()),
+    }
+    info!("Created role {} in {}", request.name, namespace);
+    Ok(RoleCredentials {
+        name: request.name.clone(),
+        password,
+        privileges: request.privileges,
+    })
+}
+
+/// Delete a role of an instance managed through the data API, with the objects it owns and
+/// the privileges it was granted in each of its databases
+pub async fn delete_role(
+    kubernetes_client: Client,
+    namespace: &str,
+    name: &str,
+) -> Result<(), RoleError> {
+    if !is_valid_role_name(name) {
+        return Err(RoleError::InvalidName(name.to_string()));
+    }
+    let roles = list_roles(kubernetes_client.clone(), namespace).await?;
+    if !roles.iter().any(|role| role.name == name) {
+        return Err(RoleError::NotFound(name.to_string()));
+    }
+    let role = quote_ident(name);
+    let databases = psql(kubernetes_client.clone(), namespace, LIST_DATABASES_SQL).await?;
+    let drop_owned = format!("DROP OWNED BY {};", role);
+    for database in databases.lines().filter(|line| !line.is_empty()) {
+        psql_database(kubernetes_client.clone(), namespace, database, &drop_owned).await?;
+    }
+    psql(
+        kubernetes_client,
+        namespace,
+        &format!("DROP ROLE {};", role),
+    )
+    .await?;
+    info!("Deleted role {} of {}", name, namespace);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_role_name() {
+        assert!(is_valid_role_name("reporting"));
+        assert!(is_valid_role_name("etl_writer_2"));
+        assert!(!is_valid_role_name("postgres"));
+        assert!(!is_valid_role_name("app"));
+        assert!(!is_valid_role_name("pg_monitor"));
+        assert!(!is_valid_role_name("Reporting"));
+        assert!(!is_valid_role_name("2fast"));
+        assert!(!is_valid_role_name("a\"; DROP ROLE app; --"));
+        assert!(!is_valid_role_name(&"a".repeat(64)));
+    }
+
+    #[test]
+    fn test_create_role_sql() {
+        let request = RoleRequest {
+            name: "reporting".to_string(),
+            privileges: RolePrivileges::ReadOnly,
+            connection_limit: Some(5),
+        };
+        let sql = create_role_sql(&request, "secret");
+        let lines: Vec<&str> = sql.lines().collect();
+        assert_eq!(lines[0], "SET log_statement = 'none';");
+        assert_eq!(lines[1], "SET pg_stat_statements.track_utility = off;");
+        assert_eq!(lines[2], "BEGIN;");
+        assert!(
+            sql.contains(r#"CREATE ROLE "reporting" LOGIN PASSWORD 'secret' CONNECTION LIMIT 5;"#)
+        );
+        assert!(sql.contains(r#"GRANT pg_read_all_data TO "reporting";"#));
+        assert!(!sql.contains("pg_write_all_data"));
+
+        let request = RoleRequest {
+            privileges: RolePrivileges::ReadWrite,
+            connection_limit: None,
+            ..request
+        };
+        let sql = create_role_sql(&request, "secret");
+        assert!(sql.contains("CONNECTION LIMIT -1;"));
+        assert!(sql.contains(r#"GRANT pg_write_all_data TO "reporting";"#));
+    }
+
+    #[test]
+    fn test_parse_roles() {
+        let roles = parse_roles("etl|t|10\nreporting|f|-1\n");
+        assert_eq!(
+            roles,
+            vec![
+                Role {
+                    name: "etl".to_string(),
+                    privileges: RolePrivileges::ReadWrite,
+                    connection_limit: 10,
+                },
+                Role {
+                    name: "reporting".to_string(),
+                    privileges: RolePrivileges::ReadOnly,
+                    connection_limit: -1,
+                },
+            ]
+        );
+        assert!(parse_roles("").is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What a role can do in all the databases of the instance
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RolePrivileges {
+    /// Read all tables, views and sequences, with pg_read_all_data
+    ReadOnly,
+    /// Also write to them, with pg_write_all_data
+    ReadWrite,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct RoleRequest {
+    /// The name of the role, lower case letters, digits and underscores
+    pub name: String,
+    pub privileges: RolePrivileges,
+    /// The maximum number of concurrent connections of the role, unlimited by default
+    pub connection_limit: Option<i32>,
+}
+
+/// A role managed through the data API
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Role {
+    pub name: String,
+    pub privileges: RolePrivileges,
+    /// The maximum number of concurrent connections of the role, -1 when unlimited
+    pub connection_limit: i32,
+}
+
+/// The credentials of a new role, its password is only returned when the role is created
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct RoleCredentials {
+    pub name: String,
+    pub password: String,
+    pub privileges: RolePrivileges,
+}
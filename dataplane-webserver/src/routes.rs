@@ -1,4 +1,17 @@
+pub mod alerts;
+pub mod audit;
+pub mod backups;
+pub mod configuration;
+pub mod extensions;
 pub mod health;
+pub mod insights;
+pub mod logs;
 pub mod metrics;
+pub mod pooler;
+pub mod roles;
 pub mod root;
+// The handlers of the deprecated routes by namespace are registered by the route macros of
+// this module, remove the allow with them on 2027-04-01
+#[allow(deprecated)]
 pub mod secrets;
+pub mod status;
@@ -0,0 +1,50 @@
+use crate::alerts::types::AlertsQuery;
+use crate::alerts::{get_alerts, AlertError};
+use crate::config;
+use crate::routes::backups::find_instance_namespace;
+use actix_web::{get, web, Error, HttpResponse};
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("include_pending" = inline(Option<bool>), Query, example="true", description = "Also return alerts which are pending. Default is false."),
+    ),
+    responses(
+        (status = 200, description = "Active alerts of the instance, most severe first", body = Vec<Alert>,
+        example = json!([
+            {"name":"PgDiskFull","state":"firing","severity":"critical","labels":{"namespace":"org-myco-inst-prod","pod":"org-myco-inst-prod-1"},"active_since":"2024-06-01T11:00:00Z"},
+            {"name":"PgHighConnections","state":"firing","severity":"warning","labels":{"namespace":"org-myco-inst-prod"},"active_since":"2024-06-01T11:45:00Z"}])),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 504, description = "Request timed out on metrics backend"),
+    )
+)]
+#[get("/alerts")]
+pub async fn list_alerts(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<reqwest::Client>,
+    alerts_query: web::Query<AlertsQuery>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    match get_alerts(&cfg, &http_client, &namespace, alerts_query.include_pending).await {
+        Ok(alerts) => Ok(HttpResponse::Ok().json(alerts)),
+        Err(AlertError::Request(e)) => {
+            error!("Failed to query alerts of {}: {}", namespace, e);
+            Ok(HttpResponse::GatewayTimeout().json("Failed to query Prometheus"))
+        }
+        Err(e) => {
+            error!("Failed to query alerts of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to query alerts"))
+        }
+    }
+}
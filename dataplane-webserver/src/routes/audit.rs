@@ -0,0 +1,61 @@
+use crate::audit::types::AuditEventsQuery;
+use crate::audit::{AuditError, AuditLog};
+use crate::routes::secrets::org_role;
+use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("before" = inline(Option<i64>), Query, example="1042", description = "Only events older than the event with this ID, to page through them"),
+        ("limit" = inline(Option<i64>), Query, example="100", description = "Number of events, from 1 to 1000. Default is 100."),
+    ),
+    responses(
+        (status = 200, description = "The calls which changed the instance or read its secrets, newest first", body = Vec<AuditEvent>,
+        example = json!([
+            {"id":1043,"org_id":"org_2T7FJA0DpaNBnELVLU1IS4XzZG0","instance_id":"inst_1696253936968_TblNOY_6","subject":"user_2T7FLjuhnQqgkDGsGhbC0DzBEQO","method":"PATCH","operation":"/api/v1/orgs/{org_id}/instances/{instance_id}/secrets/{secret_name}","path":"/api/v1/orgs/org_2T7FJA0DpaNBnELVLU1IS4XzZG0/instances/inst_1696253936968_TblNOY_6/secrets/app-role","status":200,"created_at":"2024-06-01T12:00:00Z"},
+            {"id":1042,"org_id":"org_2T7FJA0DpaNBnELVLU1IS4XzZG0","instance_id":"inst_1696253936968_TblNOY_6","subject":"user_2T7FLjuhnQqgkDGsGhbC0DzBEQO","method":"DELETE","operation":"/api/v1/orgs/{org_id}/instances/{instance_id}/backups/{backup_name}","path":"/api/v1/orgs/org_2T7FJA0DpaNBnELVLU1IS4XzZG0/instances/inst_1696253936968_TblNOY_6/backups/temback-20240601T120000.tar.gz","status":409,"created_at":"2024-06-01T11:58:00Z"}])),
+        (status = 400, description = "Parameters are incorrect"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Audit logging is not enabled"),
+    )
+)]
+#[get("/audit-events")]
+pub async fn list_audit_events(
+    audit_log: web::Data<Option<AuditLog>>,
+    audit_events_query: web::Query<AuditEventsQuery>,
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let audit_log = match audit_log.as_ref() {
+        Some(audit_log) => audit_log,
+        None => return Ok(HttpResponse::NotFound().json("Audit logging is not enabled")),
+    };
+
+    match audit_log
+        .events(&org_id, &instance_id, &audit_events_query)
+        .await
+    {
+        Ok(events) => Ok(HttpResponse::Ok().json(events)),
+        Err(AuditError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(e) => {
+            error!(
+                "Failed to list audit events of {}/{}: {}",
+                org_id, instance_id, e
+            );
+            Ok(HttpResponse::InternalServerError().json("Failed to list audit events"))
+        }
+    }
+}
@@ -0,0 +1,832 @@
+use crate::backups::base_backups::list_base_backups;
+use crate::backups::encryption::{
+    check_encryption_support, delete_encryption, get_encryption, put_encryption,
+};
+use crate::backups::job::start_backup;
+use crate::backups::restore::{get_restore, restore_chain, select_backup, start_restore};
+use crate::backups::schedule::{delete_schedule, get_schedule, put_schedule};
+use crate::backups::types::{
+    BackupEncryption, BackupRequest, BackupScheduleRequest, InstanceBackups, RestoreRequest,
+};
+use crate::backups::{BackupError, BackupStore};
+use crate::config;
+use crate::routes::secrets::{is_valid_id, org_role, requester};
+use actix_web::{delete, get, post, put, web, Error, HttpRequest, HttpResponse};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::api::ListParams;
+use kube::{Api, Client};
+use log::{error, info};
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "Base backups and temback artifacts stored for this instance, newest first", body = InstanceBackups,
+        example = json!({
+            "base_backups": [
+                {"backup_id":"20240601T120000","size_bytes":52428800,"last_modified":"2024-06-01T12:10:00Z","phase":"completed","started_at":"2024-06-01T12:00:00Z","stopped_at":"2024-06-01T12:10:00Z"}],
+            "first_recoverability_point": "2024-05-25T12:10:00Z",
+            "artifacts": [
+                {"name":"temback-20240601T125000.tar.gz","size_bytes":524288,"last_modified":"2024-06-01T12:50:00Z","age_seconds":600,"incremental_base":"temback-20240601T120000.tar.gz"},
+                {"name":"temback-20240601T120000.tar.gz","size_bytes":10485760,"last_modified":"2024-06-01T12:00:00Z","age_seconds":3600,"incremental_base":null}],
+            "filtered_artifacts": [
+                {"name":"temback-20240601T130000.tar.gz","size_bytes":1048576,"last_modified":"2024-06-01T13:00:00Z","age_seconds":0,"incremental_base":null}]})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+    )
+)]
+#[get("/backups")]
+pub async fn list_backups(
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let store = match store.as_ref() {
+        Some(store) => store,
+        None => return Ok(HttpResponse::NotFound().json("Backups are not enabled")),
+    };
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let artifacts = match store.list(&namespace).await {
+        Ok(artifacts) => artifacts,
+        Err(e) => {
+            error!("Failed to list backups of {}: {}", namespace, e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to list backups"));
+        }
+    };
+    let filtered_artifacts = match store.list_filtered(&namespace).await {
+        Ok(artifacts) => artifacts,
+        Err(e) => {
+            error!("Failed to list filtered dumps of {}: {}", namespace, e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to list backups"));
+        }
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match list_base_backups(kubernetes_client, store, &namespace).await {
+        Ok((base_backups, first_recoverability_point)) => {
+            Ok(HttpResponse::Ok().json(InstanceBackups {
+                base_backups,
+                first_recoverability_point,
+                artifacts,
+                filtered_artifacts,
+            }))
+        }
+        Err(BackupError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Instance not found")),
+        Err(e) => {
+            error!("Failed to list base backups of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to list backups"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    request_body = BackupRequest,
+    responses(
+        (status = 202, description = "Backup job started, the backup is listed once it completes", body = BackupJob,
+        example = json!({"job_id":"backup-20240601120000000","namespace":"org-myco-inst-prod","database":"app","mode":"incremental","incremental_from":"temback-20240531120000.tar.gz","status":"pending","started_at":null})),
+        (status = 400, description = "Invalid database, schema or table names"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+    )
+)]
+#[post("/backups")]
+pub async fn create_backup(
+    cfg: web::Data<config::Config>,
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String)>,
+    backup_request: web::Json<BackupRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let store = match store.as_ref() {
+        Some(store) => store,
+        None => return Ok(HttpResponse::NotFound().json("Backups are not enabled")),
+    };
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    // Backups of an organization which set a key are never taken unencrypted
+    let encryption = match get_encryption(kubernetes_client.clone(), &org_id).await {
+        Ok(encryption) => encryption,
+        Err(e) => {
+            error!("Failed to get backup encryption key of {}: {}", org_id, e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to start backup"));
+        }
+    };
+    match start_backup(
+        kubernetes_client,
+        store,
+        &cfg.temback_image,
+        encryption.as_ref(),
+        &namespace,
+        &backup_request,
+    )
+    .await
+    {
+        Ok(backup_job) => Ok(HttpResponse::Accepted().json(backup_job)),
+        Err(BackupError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(e) => {
+            error!("Failed to start backup of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to start backup"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("backup_name", example="temback-20240601T120000.tar.gz", description = "Backup name"),
+    ),
+    responses(
+        (status = 200, description = "Backup and its status file deleted."),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance or backup not found"),
+        (status = 409, description = "The backup is the only remaining backup of the instance, or incremental backups build on it"),
+    )
+)]
+#[delete("/backups/{backup_name}")]
+pub async fn delete_backup(
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id, backup_name) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let store = match store.as_ref() {
+        Some(store) => store,
+        None => return Ok(HttpResponse::NotFound().json("Backups are not enabled")),
+    };
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    match store.delete(&namespace, &backup_name).await {
+        Ok(()) => {
+            info!(
+                target: "audit",
+                "Backup {} of {} deleted by {} of {}",
+                backup_name,
+                namespace,
+                requester(&req).unwrap_or_else(|| "unknown user".to_string()),
+                org_id
+            );
+            Ok(HttpResponse::Ok().json("Backup deleted successfully"))
+        }
+        Err(BackupError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Backup not found")),
+        Err(BackupError::LastBackup(_)) => Ok(HttpResponse::Conflict()
+            .json("Refusing to delete the only remaining backup of the instance")),
+        Err(BackupError::HasIncrementals(_)) => Ok(HttpResponse::Conflict()
+            .json("Refusing to delete a backup later incremental backups build on")),
+        Err(BackupError::InvalidName(_)) => {
+            Ok(HttpResponse::BadRequest().json("Invalid backup name"))
+        }
+        Err(e) => {
+            error!(
+                "Failed to delete backup {} of {}: {}",
+                backup_name, namespace, e
+            );
+            Ok(HttpResponse::InternalServerError().json("Failed to delete backup"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("backup_name", example="temback-20240601T120000.tar.gz", description = "Backup name"),
+    ),
+    responses(
+        (status = 200, description = "Time-limited URL to download the backup directly from object storage", body = BackupDownload,
+        example = json!({"name":"temback-20240601T120000.tar.gz","url":"https://backups.s3.amazonaws.com/temback/org-myco-inst-prod/temback-20240601T120000.tar.gz?X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Expires=900","expires_at":"2024-06-01T13:15:00Z","size_bytes":10485760,"etag":"9b2cf535f27731c974343645a3985328","checksum_sha256":null,"encryption":{"method":"kms","key_id":"alias/tembo-backups"}})),
+        (status = 400, description = "Invalid backup name"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance or backup not found"),
+    )
+)]
+#[get("/backups/{backup_name}/download")]
+pub async fn download_backup(
+    cfg: web::Data<config::Config>,
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id, backup_name) = path.into_inner();
+    // Backups hold all the data of the instance, only admins can download them
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let store = match store.as_ref() {
+        Some(store) => store,
+        None => return Ok(HttpResponse::NotFound().json("Backups are not enabled")),
+    };
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let expires_in = std::time::Duration::from_secs(cfg.temback_download_url_ttl_sec);
+    match store
+        .presigned_download(&namespace, &backup_name, expires_in)
+        .await
+    {
+        Ok(download) => Ok(HttpResponse::Ok().json(download)),
+        Err(BackupError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Backup not found")),
+        Err(BackupError::InvalidName(_)) => {
+            Ok(HttpResponse::BadRequest().json("Invalid backup name"))
+        }
+        Err(e) => {
+            error!(
+                "Failed to generate download URL of backup {} of {}: {}",
+                backup_name, namespace, e
+            );
+            Ok(HttpResponse::InternalServerError().json("Failed to generate download URL"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID the backup belongs to"),
+    ),
+    request_body = RestoreRequest,
+    responses(
+        (status = 202, description = "Restore job started, its progress is queried on the target instance", body = RestoreJob,
+        example = json!({"job_id":"restore-20240601120000000","backup_name":"temback-20240601T120000.tar.gz","source_namespace":"org-myco-inst-prod","target_namespace":"org-myco-inst-staging","status":"pending","started_at":null,"completed_at":null})),
+        (status = 400, description = "Neither or both of a backup and a point in time were chosen, or the backup is encrypted with a public key"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance or backup not found"),
+    )
+)]
+#[post("/restore")]
+pub async fn restore_backup(
+    cfg: web::Data<config::Config>,
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String)>,
+    restore_request: web::Json<RestoreRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let store = match store.as_ref() {
+        Some(store) => store,
+        None => return Ok(HttpResponse::NotFound().json("Backups are not enabled")),
+    };
+    let source_namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+    // The target instance must belong to the same organization
+    let target_namespace = match restore_request.target_instance_id.as_deref() {
+        Some(target_instance_id) => {
+            match find_instance_namespace(&org_id, target_instance_id).await {
+                Ok(namespace) => namespace,
+                Err(response) => return Ok(response),
+            }
+        }
+        None => source_namespace.clone(),
+    };
+
+    let artifacts = match store.list(&source_namespace).await {
+        Ok(artifacts) => artifacts,
+        Err(e) => {
+            error!("Failed to list backups of {}: {}", source_namespace, e);
+            return Ok(HttpResponse::InternalServerError().json("Failed to list backups"));
+        }
+    };
+    let artifact = match select_backup(
+        &artifacts,
+        restore_request.backup_name.as_deref(),
+        restore_request.point_in_time,
+    ) {
+        Ok(artifact) => artifact,
+        Err(BackupError::NotFound(_)) => {
+            return Ok(HttpResponse::NotFound().json("Backup not found"))
+        }
+        Err(BackupError::InvalidName(_)) => {
+            return Ok(HttpResponse::BadRequest().json("Invalid backup name"))
+        }
+        Err(e) => return Ok(HttpResponse::BadRequest().json(e.to_string())),
+    };
+    let chain = match restore_chain(&artifacts, &artifact) {
+        Ok(chain) => chain,
+        Err(e) => {
+            error!("Failed to restore backup {}: {}", artifact.name, e);
+            return Ok(HttpResponse::NotFound().json(e.to_string()));
+        }
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match start_restore(
+        kubernetes_client,
+        store,
+        &cfg.temback_image,
+        &source_namespace,
+        &target_namespace,
+        &chain,
+    )
+    .await
+    {
+        Ok(restore_job) => Ok(HttpResponse::Accepted().json(restore_job)),
+        Err(BackupError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(e) => {
+            error!(
+                "Failed to restore backup {} of {} into {}: {}",
+                artifact.name, source_namespace, target_namespace, e
+            );
+            Ok(HttpResponse::InternalServerError().json("Failed to start restore"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID the backup is restored into"),
+        ("job_id", example="restore-20240601120000000", description = "Restore job ID"),
+    ),
+    responses(
+        (status = 200, description = "Progress of the restore job", body = RestoreJob,
+        example = json!({"job_id":"restore-20240601120000000","backup_name":"temback-20240601T120000.tar.gz","source_namespace":"org-myco-inst-prod","target_namespace":"org-myco-inst-staging","status":"succeeded","started_at":"2024-06-01T12:00:01Z","completed_at":"2024-06-01T12:04:31Z"})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance or restore job not found"),
+    )
+)]
+#[get("/restore/{job_id}")]
+pub async fn get_restore_job(
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id, job_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match get_restore(kubernetes_client, &namespace, &job_id).await {
+        Ok(restore_job) => Ok(HttpResponse::Ok().json(restore_job)),
+        Err(BackupError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Restore job not found")),
+        Err(e) => {
+            error!(
+                "Failed to get restore job {} of {}: {}",
+                job_id, namespace, e
+            );
+            Ok(HttpResponse::InternalServerError().json("Failed to get restore job"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "Recurring schedule of the logical backups of this instance", body = BackupSchedule,
+        example = json!({"schedule":"0 3 * * *","last_scheduled_at":"2024-06-01T03:00:00Z","last_successful_at":"2024-06-01T03:04:12Z"})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found or no backups are scheduled"),
+    )
+)]
+#[get("/backup-schedule")]
+pub async fn get_backup_schedule(
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    if store.is_none() {
+        return Ok(HttpResponse::NotFound().json("Backups are not enabled"));
+    }
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match get_schedule(kubernetes_client, &namespace).await {
+        Ok(schedule) => Ok(HttpResponse::Ok().json(schedule)),
+        Err(BackupError::NotFound(_)) => {
+            Ok(HttpResponse::NotFound().json("No backups are scheduled"))
+        }
+        Err(e) => {
+            error!("Failed to get backup schedule of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get backup schedule"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    request_body = BackupScheduleRequest,
+    responses(
+        (status = 200, description = "Backup schedule created or updated, backups are taken from the next scheduled time", body = BackupSchedule,
+        example = json!({"schedule":"0 3 * * *","last_scheduled_at":null,"last_successful_at":null})),
+        (status = 400, description = "Invalid schedule, backups may be taken at most once an hour"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+    )
+)]
+#[put("/backup-schedule")]
+pub async fn put_backup_schedule(
+    store: web::Data<Option<BackupStore>>,
+    path: web::Path<(String, String)>,
+    schedule_request: web::Json<BackupScheduleRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    if store.is_none() {
+        return Ok(HttpResponse::NotFound().json("Backups are not enabled"));
+    }
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match put_schedule(kubernetes_client, &namespace, &schedule_request.schedule).await {
+        Ok(schedule) => Ok(HttpResponse::Ok().json(schedule)),
+        Err(BackupError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(e) => {
+            error!("Failed to set backup schedule of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to set backup schedule"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "Backup schedule removed, stored backups are kept"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found or no backups are scheduled"),
+    )
+)]
+#[delete("/backup-schedule")]
+pub async fn delete_backup_schedule(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match delete_schedule(kubernetes_client, &namespace).await {
+        Ok(()) => Ok(HttpResponse::Ok().json("Backup schedule removed successfully")),
+        Err(BackupError::NotFound(_)) => {
+            Ok(HttpResponse::NotFound().json("No backups are scheduled"))
+        }
+        Err(e) => {
+            error!("Failed to remove backup schedule of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to remove backup schedule"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+    ),
+    responses(
+        (status = 200, description = "The key backups of the organization are encrypted with", body = BackupEncryption,
+        example = json!({"kms_key_id":"arn:aws:kms:us-east-1:123456789012:alias/tembo-backups","public_key":null})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Backups of the organization are not encrypted"),
+    )
+)]
+#[get("/backup-encryption")]
+pub async fn get_backup_encryption(
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+    if !is_valid_id(&org_id) {
+        return Ok(
+            HttpResponse::BadRequest().json("org_id must be alphanumeric or underscore only")
+        );
+    }
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match get_encryption(kubernetes_client, &org_id).await {
+        Ok(Some(encryption)) => Ok(HttpResponse::Ok().json(encryption)),
+        Ok(None) => Ok(HttpResponse::NotFound().json("Backups are not encrypted")),
+        Err(e) => {
+            error!("Failed to get backup encryption key of {}: {}", org_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get backup encryption key"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+    ),
+    request_body = BackupEncryption,
+    responses(
+        (status = 200, description = "Backups of all instances of the organization are encrypted with this key from their next backup", body = BackupEncryption,
+        example = json!({"kms_key_id":null,"public_key":"age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"})),
+        (status = 400, description = "Neither or both of a KMS key and a public key were set, or the key is invalid"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 409, description = "The temback image of the data plane cannot encrypt backups"),
+    )
+)]
+#[put("/backup-encryption")]
+pub async fn put_backup_encryption(
+    cfg: web::Data<config::Config>,
+    path: web::Path<String>,
+    encryption: web::Json<BackupEncryption>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+    if !is_valid_id(&org_id) {
+        return Ok(
+            HttpResponse::BadRequest().json("org_id must be alphanumeric or underscore only")
+        );
+    }
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    if let Err(e) = check_encryption_support(&cfg.temback_image) {
+        return Ok(HttpResponse::Conflict().json(e.to_string()));
+    }
+
+    match put_encryption(kubernetes_client, &org_id, &encryption).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(encryption.into_inner())),
+        Err(BackupError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(e) => {
+            error!("Failed to set backup encryption key of {}: {}", org_id, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to set backup encryption key"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+    ),
+    responses(
+        (status = 200, description = "Backups are no longer encrypted from their next backup, stored backups stay encrypted"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Backups of the organization are not encrypted"),
+    )
+)]
+#[delete("/backup-encryption")]
+pub async fn delete_backup_encryption(
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+    if !is_valid_id(&org_id) {
+        return Ok(
+            HttpResponse::BadRequest().json("org_id must be alphanumeric or underscore only")
+        );
+    }
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match delete_encryption(kubernetes_client, &org_id).await {
+        Ok(()) => Ok(HttpResponse::Ok().json("Backup encryption key removed successfully")),
+        Err(BackupError::NotFound(_)) => {
+            Ok(HttpResponse::NotFound().json("Backups are not encrypted"))
+        }
+        Err(e) => {
+            error!(
+                "Failed to remove backup encryption key of {}: {}",
+                org_id, e
+            );
+            Ok(HttpResponse::InternalServerError().json("Failed to remove backup encryption key"))
+        }
+    }
+}
+
+// Find the namespace of an instance by its labels
+pub async fn find_instance_namespace(
+    org_id: &str,
+    instance_id: &str,
+) -> Result<String, HttpResponse> {
+    if !is_valid_id(org_id) || !is_valid_id(instance_id) {
+        return Err(HttpResponse::BadRequest()
+            .json("org_id and instance_id must be alphanumeric or underscore only"));
+    }
+
+    let kubernetes_client = Client::try_default().await.map_err(|_| {
+        error!("Failed to create Kubernetes client");
+        HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+    })?;
+
+    let namespaces: Api<Namespace> = Api::all(kubernetes_client);
+    let label_selector = format!(
+        "tembo.io/instance_id={},tembo.io/organization_id={}",
+        instance_id, org_id
+    );
+    let lp = ListParams::default().labels(&label_selector);
+    let ns_list = namespaces.list(&lp).await.map_err(|_| {
+        error!(
+            "Failed to list namespaces with label selector: {}",
+            label_selector
+        );
+        HttpResponse::InternalServerError().json("Failed to list namespaces")
+    })?;
+
+    match ns_list.items.into_iter().next() {
+        Some(namespace) => Ok(namespace
+            .metadata
+            .name
+            .expect("Namespaces always have names")),
+        None => {
+            error!("No namespace found with provided labels");
+            Err(HttpResponse::NotFound()
+                .json("Instance not found for provided org_id and instance_id"))
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::configuration::{get_configuration, ConfigurationError};
+use crate::routes::backups::find_instance_namespace;
+use actix_web::{get, web, Error, HttpResponse};
+use kube::Client;
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "The effective settings of the instance from pg_settings, with where each value comes from", body = Vec<EffectiveSetting>,
+        example = json!([
+            {"name":"checkpoint_timeout","value":"1800","unit":"s","source":"stack","configured_value":"30min","pending_restart":false},
+            {"name":"max_connections","value":"200","unit":null,"source":"user_override","configured_value":"200","pending_restart":true},
+            {"name":"wal_level","value":"logical","unit":null,"source":"operator","configured_value":null,"pending_restart":false},
+            {"name":"work_mem","value":"4096","unit":"kB","source":"postgres_default","configured_value":null,"pending_restart":false}])),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 503, description = "The instance has no running primary"),
+    )
+)]
+#[get("/configuration")]
+pub async fn get_settings(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+
+    match get_configuration(kubernetes_client, &namespace).await {
+        Ok(settings) => Ok(HttpResponse::Ok().json(settings)),
+        Err(ConfigurationError::NotFound(_)) => {
+            Ok(HttpResponse::NotFound().json("Instance not found"))
+        }
+        Err(ConfigurationError::Unavailable(_)) => {
+            Ok(HttpResponse::ServiceUnavailable().json("The instance has no running primary"))
+        }
+        Err(e) => {
+            error!("Failed to get configuration of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get configuration"))
+        }
+    }
+}
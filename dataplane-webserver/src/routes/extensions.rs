@@ -0,0 +1,138 @@
+use crate::config;
+use crate::extensions::types::EnableExtensionRequest;
+use crate::extensions::{enable_extension, get_extensions, ExtensionError};
+use crate::routes::backups::find_instance_namespace;
+use crate::routes::secrets::org_role;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use kube::Client;
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "Extensions and trunk installs of the instance, and the trunk projects which can be installed", body = InstanceExtensions,
+        example = json!({
+            "extensions": [{"name":"vector","description":"vector search","locations":[{"database":"app","schema":"public","version":null,"enabled":true,"enabled_in_status":true,"error_message":null}]}],
+            "trunk_installs": [{"name":"pgvector","version":"0.7.0","installed":true,"error_message":null}],
+            "available": [{"name":"pg_cron","description":"Job scheduler for PostgreSQL","version":"1.6.2","postgres_versions":[14,15,16],"extension_names":["pg_cron"]}]})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+    )
+)]
+#[get("/extensions")]
+pub async fn list_extensions(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<reqwest::Client>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match get_extensions(
+        kubernetes_client,
+        &http_client,
+        &cfg.trunk_registry_url,
+        &namespace,
+    )
+    .await
+    {
+        Ok(extensions) => Ok(HttpResponse::Ok().json(extensions)),
+        Err(ExtensionError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Instance not found")),
+        Err(e) => {
+            error!("Failed to get extensions of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get extensions"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    request_body = EnableExtensionRequest,
+    responses(
+        (status = 202, description = "Extension requested, the operator installs and enables it", body = InstanceExtension,
+        example = json!({"name":"pg_cron","description":null,"locations":[{"database":"postgres","schema":null,"version":null,"enabled":true,"enabled_in_status":null,"error_message":null}]})),
+        (status = 400, description = "Invalid extension, database or schema name, or unknown trunk project"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 409, description = "The instance was changed concurrently, please retry"),
+        (status = 502, description = "The trunk registry can not be reached"),
+    )
+)]
+#[post("/extensions")]
+pub async fn post_extension(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<reqwest::Client>,
+    path: web::Path<(String, String)>,
+    extension_request: web::Json<EnableExtensionRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match enable_extension(
+        kubernetes_client,
+        &http_client,
+        &cfg.trunk_registry_url,
+        &namespace,
+        &extension_request,
+    )
+    .await
+    {
+        Ok(extension) => Ok(HttpResponse::Accepted().json(extension)),
+        Err(ExtensionError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(ExtensionError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Instance not found")),
+        Err(ExtensionError::Conflict(_)) => Ok(
+            HttpResponse::Conflict().json("The instance was changed concurrently, please retry")
+        ),
+        Err(ExtensionError::Trunk(e)) => {
+            error!("Failed to list available extensions: {}", e);
+            Ok(HttpResponse::BadGateway().json("Failed to reach the trunk registry"))
+        }
+        Err(e) => {
+            error!("Failed to enable extension of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to enable extension"))
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config;
+use crate::insights::types::{QueryOrder, TopQueriesQuery};
+use crate::insights::{connection_stats, top_queries, InsightsError};
+use crate::routes::backups::find_instance_namespace;
+use actix_web::{get, web, Error, HttpResponse};
+use kube::Client;
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("order_by" = inline(Option<QueryOrder>), Query, example="mean_time", description = "Rank queries by total_time, mean_time, calls or rows. Default is total_time."),
+        ("limit" = inline(Option<i64>), Query, example="20", description = "Number of queries, from 1 to 100. Default is 20."),
+        ("redact" = inline(Option<bool>), Query, example="true", description = "Replace the literals left in the query texts and strip their comments. Utility statements like ALTER ROLE are always redacted. Default is false."),
+    ),
+    responses(
+        (status = 200, description = "The top queries of the instance, from pg_stat_statements", body = Vec<QueryStats>,
+        example = json!([
+            {"query_id":-3592844871582961411_i64,"query":"SELECT * FROM orders WHERE customer_id = $1","database":"app","user":"app","calls":184203,"total_exec_time_ms":92810.4,"mean_exec_time_ms":0.5,"rows":1842030},
+            {"query_id":1277651329840512305_i64,"query":"UPDATE inventory SET quantity = quantity - $1 WHERE sku = $2","database":"app","user":"app","calls":20391,"total_exec_time_ms":40122.9,"mean_exec_time_ms":1.97,"rows":20391}])),
+        (status = 400, description = "Parameters are incorrect"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found or pg_stat_statements not enabled"),
+        (status = 503, description = "The instance can not be queried"),
+    )
+)]
+#[get("/insights/queries")]
+pub async fn get_top_queries(
+    cfg: web::Data<config::Config>,
+    top_queries_query: web::Query<TopQueriesQuery>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+
+    match top_queries(kubernetes_client, &cfg, &namespace, &top_queries_query).await {
+        Ok(queries) => Ok(HttpResponse::Ok().json(queries)),
+        Err(InsightsError::InvalidRequest(e)) => Ok(HttpResponse::BadRequest().json(e)),
+        Err(InsightsError::NotEnabled(_)) => {
+            Ok(HttpResponse::NotFound().json("pg_stat_statements is not enabled"))
+        }
+        Err(e @ (InsightsError::Unavailable(_) | InsightsError::Database(_))) => {
+            error!("Failed to query insights of {}: {}", namespace, e);
+            Ok(HttpResponse::ServiceUnavailable().json("The instance can not be queried"))
+        }
+        Err(e) => {
+            error!("Failed to query insights of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to query insights"))
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "The current client connections of the instance by state, user and database, from pg_stat_activity, and those of its pooler", body = ConnectionStats,
+        example = json!({
+            "max_connections": 100,
+            "total": 57,
+            "groups": [
+                {"state":"idle","user":"app","database":"app","connections":41},
+                {"state":"active","user":"app","database":"app","connections":12},
+                {"state":"idle in transaction","user":"etl","database":"app","connections":4}],
+            "pooler": {"client_active":38,"client_waiting":0,"server_active":12,"server_idle":8,"server_used":0}})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 503, description = "The instance can not be queried"),
+    )
+)]
+#[get("/connections")]
+pub async fn get_connections(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<reqwest::Client>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+
+    match connection_stats(kubernetes_client, &http_client, &cfg, &namespace).await {
+        Ok(stats) => Ok(HttpResponse::Ok().json(stats)),
+        Err(e @ (InsightsError::Unavailable(_) | InsightsError::Database(_))) => {
+            error!("Failed to get connections of {}: {}", namespace, e);
+            Ok(HttpResponse::ServiceUnavailable().json("The instance can not be queried"))
+        }
+        Err(e) => {
+            error!("Failed to get connections of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get connections"))
+        }
+    }
+}
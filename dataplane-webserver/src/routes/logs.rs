@@ -0,0 +1,63 @@
+use crate::config;
+use crate::logs::{query_loki, types::LogQuery};
+use crate::routes::backups::find_instance_namespace;
+use actix_web::{get, web, Error, HttpResponse};
+use reqwest::Client;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("query" = inline(Option<String>), Query, example="{namespace=\"org-coredb-inst-control-plane-dev\"} |= \"ERROR\"", description = "LogQL log query, its stream selector must include a 'namespace' label matching the instance. Defaults to all the logs of the instance."),
+        ("container" = inline(Option<String>), Query, example="postgres", description = "Only return the logs of this container"),
+        ("start" = inline(Option<u64>), Query, example="1686780828", description = "Range start, unix timestamp. Default is an hour before the end."),
+        ("end" = inline(Option<u64>), Query, example="1686862041", description = "Range end, unix timestamp. Default is now."),
+        ("limit" = inline(Option<u32>), Query, example="100", description = "Maximum number of lines, from 1 to 5000. Default is 100."),
+        ("tail" = inline(Option<bool>), Query, example="true", description = "Return the most recent lines of the range instead of the oldest ones. Default is false."),
+    ),
+    responses(
+        (status = 200, description = "Success range query to Loki, please see Loki documentation for response format details. https://grafana.com/docs/loki/latest/reference/loki-http-api/#query-logs-within-a-range-of-time", body = Value,
+        example = json!({
+            "data": {
+                "result": [
+                    {
+                        "stream": {
+                            "namespace": "org-coredb-inst-control-plane-dev",
+                            "container": "postgres"
+                        },
+                        "values": [
+                            [
+                                "1686780830000000000",
+                                "2023-06-14 22:13:50 UTC [42]: LOG:  checkpoint starting: time"
+                            ]
+                        ]
+                    }
+                ],
+                "resultType": "streams"
+            },
+            "status": "success"
+        }),
+        ),
+        (status = 400, description = "Parameters are missing or incorrect"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 504, description = "Request timed out on logs backend"),
+    )
+)]
+#[get("/logs")]
+pub async fn query_logs(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<Client>,
+    log_query: web::Query<LogQuery>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    Ok(query_loki(cfg, http_client, log_query.into_inner(), namespace).await)
+}
@@ -1,9 +1,12 @@
 use crate::{config, metrics};
 
-use crate::metrics::types::{InstantQuery, RangeQuery};
+use crate::metrics::stream::{metrics_stream, parse_stream_query};
+use crate::metrics::templates;
+use crate::metrics::types::{InstantQuery, RangeQuery, StreamQuery};
 use actix_web::{get, web, Error, HttpRequest, HttpResponse};
 
 use reqwest::Client;
+use std::collections::HashMap;
 
 #[utoipa::path(
     context_path = "/{namespace}/metrics",
@@ -103,3 +106,272 @@ pub async fn query(
 
     Ok(metrics::query_prometheus_instant(cfg, http_client, instant_query, namespace).await)
 }
+
+#[utoipa::path(
+    context_path = "/{namespace}/metrics",
+    params(
+        ("namespace" = String, Path, example="org-coredb-inst-control-plane-dev", description = "Instance namespace"),
+    ),
+    responses(
+        (status = 200, description = "The query templates by name, with their parameters and default values", body = Value,
+        example = json!({
+            "cpu": {
+                "query": "sum by (namespace) (rate(container_cpu_usage_seconds_total{namespace=\"$namespace\", container=\"postgres\"}[$window]))",
+                "params": {
+                    "window": "5m"
+                }
+            }
+        }),
+        ),
+    )
+)]
+#[get("/templates")]
+pub async fn list_templates(cfg: web::Data<config::Config>) -> HttpResponse {
+    HttpResponse::Ok().json(&cfg.metrics_query_templates)
+}
+
+#[utoipa::path(
+    context_path = "/{namespace}/metrics",
+    params(
+        ("namespace" = String, Path, example="org-coredb-inst-control-plane-dev", description = "Instance namespace"),
+        ("name" = String, Path, example="cpu", description = "Name of the query template"),
+        ("start" = inline(u64), Query, example="1686780828", description = "Range start, unix timestamp"),
+        ("end" = inline(Option<u64>), Query, example="1686862041", description = "Range end, unix timestamp. Default is now."),
+        ("step" = inline(Option<String>), Query, example="60s", description = "Step size duration string, defaults to 60s"),
+    ),
+    responses(
+        (status = 200, description = "Success range query to Prometheus, other query parameters are passed to the template. Please see Prometheus documentation for response format details. https://prometheus.io/docs/prometheus/latest/querying/api/#range-queries", body = Value),
+        (status = 400, description = "Parameters are missing or incorrect"),
+        (status = 404, description = "Query template not found"),
+        (status = 504, description = "Request timed out on metrics backend"),
+    )
+)]
+#[get("/templates/{name}/query_range")]
+pub async fn template_query_range(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<Client>,
+    params: web::Query<HashMap<String, String>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, name) = path.into_inner();
+    let mut params = params.into_inner();
+
+    let start = match params.remove("start").map(|start| start.parse::<f64>()) {
+        Some(Ok(start)) => start,
+        _ => return Ok(HttpResponse::BadRequest().json("Invalid or missing start")),
+    };
+    let end = match params
+        .remove("end")
+        .map(|end| end.parse::<f64>())
+        .transpose()
+    {
+        Ok(end) => end,
+        Err(_) => return Ok(HttpResponse::BadRequest().json("Invalid end")),
+    };
+    let step = params.remove("step");
+    let rendered = match render_template(&cfg, &name, &namespace, &params) {
+        Ok(rendered) => rendered,
+        Err(http_response) => return Ok(http_response),
+    };
+
+    let range_query = RangeQuery {
+        query: rendered,
+        start,
+        end,
+        step,
+    };
+    Ok(metrics::query_prometheus(cfg, http_client, web::Query(range_query), namespace).await)
+}
+
+#[utoipa::path(
+    context_path = "/{namespace}/metrics",
+    params(
+        ("namespace" = String, Path, example="org-coredb-inst-control-plane-dev", description = "Instance namespace"),
+        ("name" = String, Path, example="cpu", description = "Name of the query template"),
+        ("time" = inline(Option<u64>), Query, example="1686862041", description = "Evaluation time, unix timestamp. Default is now."),
+    ),
+    responses(
+        (status = 200, description = "Success instant query to Prometheus, other query parameters are passed to the template. Please see Prometheus documentation for response format details. https://prometheus.io/docs/prometheus/latest/querying/api/#instant-queries", body = Value),
+        (status = 400, description = "Parameters are missing or incorrect"),
+        (status = 404, description = "Query template not found"),
+        (status = 504, description = "Request timed out on metrics backend"),
+    )
+)]
+#[get("/templates/{name}/query")]
+pub async fn template_query(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<Client>,
+    params: web::Query<HashMap<String, String>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, name) = path.into_inner();
+    let mut params = params.into_inner();
+
+    let time = match params
+        .remove("time")
+        .map(|time| time.parse::<u64>())
+        .transpose()
+    {
+        Ok(time) => time,
+        Err(_) => return Ok(HttpResponse::BadRequest().json("Invalid time")),
+    };
+    let rendered = match render_template(&cfg, &name, &namespace, &params) {
+        Ok(rendered) => rendered,
+        Err(http_response) => return Ok(http_response),
+    };
+
+    let instant_query = InstantQuery {
+        query: rendered,
+        time,
+    };
+    Ok(
+        metrics::query_prometheus_instant(cfg, http_client, web::Query(instant_query), namespace)
+            .await,
+    )
+}
+
+fn render_template(
+    cfg: &config::Config,
+    name: &str,
+    namespace: &str,
+    params: &HashMap<String, String>,
+) -> Result<String, HttpResponse> {
+    let Some(template) = cfg.metrics_query_templates.get(name) else {
+        return Err(HttpResponse::NotFound().json(format!("Query template '{}' not found", name)));
+    };
+    templates::render(template, namespace, params).map_err(|e| HttpResponse::BadRequest().json(e))
+}
+
+#[utoipa::path(
+    context_path = "/{namespace}/metrics",
+    params(
+        ("namespace" = String, Path, example="org-coredb-inst-control-plane-dev", description = "Instance namespace"),
+        ("metrics" = inline(Option<String>), Query, example="connections,tps,cpu", description = "Comma separated names of query templates. Default is connections, tps and cpu."),
+        ("interval" = inline(Option<u64>), Query, example="5", description = "Seconds between samples, defaults to 5"),
+    ),
+    responses(
+        (status = 200, description = "A text/event-stream of 'metrics' events, each with the instant query result of every template, and the errors of those which could not be queried. The stream is closed after METRICS_STREAM_MAX_DURATION_SEC, clients reconnect to continue.", body = Value,
+        example = json!({
+            "time": 1686862041,
+            "metrics": {
+                "connections": [{"metric": {"namespace": "org-coredb-inst-control-plane-dev", "state": "active"}, "value": [1686862041, "3"]}],
+                "cpu": [{"metric": {"namespace": "org-coredb-inst-control-plane-dev"}, "value": [1686862041, "0.12"]}]
+            },
+            "errors": {
+                "tps": "Failed to query Prometheus"
+            }
+        }),
+        ),
+        (status = 400, description = "Parameters are incorrect or a query template is not found"),
+    )
+)]
+#[get("/stream")]
+pub async fn stream(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<Client>,
+    stream_query: web::Query<StreamQuery>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace,) = path.into_inner();
+
+    let (queries, interval) = match parse_stream_query(&cfg, &stream_query, &namespace) {
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Samples are sent as they are taken, not buffered by proxies
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(metrics_stream(
+            cfg.get_ref().clone(),
+            http_client.get_ref().clone(),
+            queries,
+            interval,
+        )))
+}
+
+#[utoipa::path(
+    context_path = "/{namespace}/metrics",
+    params(
+        ("namespace" = String, Path, example="org-coredb-inst-control-plane-dev", description = "Instance namespace"),
+        ("name" = String, Path, example="pod", description = "Label name"),
+        ("match[]" = inline(Option<Vec<String>>), Query, example="cnpg_backends_total{namespace=\"org-coredb-inst-control-plane-dev\"}", description = "Series selectors, which must include a 'namespace' label matching the query path. Default is all series of the namespace."),
+        ("start" = inline(Option<u64>), Query, example="1686780828", description = "Start, unix timestamp"),
+        ("end" = inline(Option<u64>), Query, example="1686862041", description = "End, unix timestamp"),
+        ("limit" = inline(Option<u64>), Query, example="100", description = "Maximum number of values"),
+    ),
+    responses(
+        (status = 200, description = "Values of the label in the series of the namespace, please see Prometheus documentation for response format details. https://prometheus.io/docs/prometheus/latest/querying/api/#querying-label-values", body = Value,
+        example = json!({
+            "status": "success",
+            "data": ["org-coredb-inst-control-plane-dev-1", "org-coredb-inst-control-plane-dev-2"]
+        }),
+        ),
+        (status = 400, description = "Parameters are missing or incorrect"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 422, description = "Incorrectly formatted selector"),
+        (status = 504, description = "Request timed out on metrics backend"),
+    )
+)]
+#[get("/label/{name}/values")]
+pub async fn label_values(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<Client>,
+    params: web::Query<Vec<(String, String)>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, name) = path.into_inner();
+
+    let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Ok(HttpResponse::BadRequest().json("Invalid label name"));
+    }
+    let params = match metrics::metadata_params(params.into_inner(), &namespace, false) {
+        Ok(params) => params,
+        Err(http_response) => return Ok(http_response),
+    };
+    let endpoint = format!("label/{}/values", name);
+    Ok(metrics::query_prometheus_metadata(cfg, http_client, &endpoint, &params).await)
+}
+
+#[utoipa::path(
+    context_path = "/{namespace}/metrics",
+    params(
+        ("namespace" = String, Path, example="org-coredb-inst-control-plane-dev", description = "Instance namespace"),
+        ("match[]" = inline(Vec<String>), Query, example="cnpg_backends_total{namespace=\"org-coredb-inst-control-plane-dev\"}", description = "Series selectors, which must include a 'namespace' label matching the query path"),
+        ("start" = inline(Option<u64>), Query, example="1686780828", description = "Start, unix timestamp"),
+        ("end" = inline(Option<u64>), Query, example="1686862041", description = "End, unix timestamp"),
+        ("limit" = inline(Option<u64>), Query, example="100", description = "Maximum number of series"),
+    ),
+    responses(
+        (status = 200, description = "Label sets of the series matching the selectors, please see Prometheus documentation for response format details. https://prometheus.io/docs/prometheus/latest/querying/api/#finding-series-by-label-matchers", body = Value,
+        example = json!({
+            "status": "success",
+            "data": [
+                {"__name__": "cnpg_backends_total", "namespace": "org-coredb-inst-control-plane-dev", "pod": "org-coredb-inst-control-plane-dev-1", "state": "active"}
+            ]
+        }),
+        ),
+        (status = 400, description = "Parameters are missing or incorrect"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 422, description = "Incorrectly formatted selector"),
+        (status = 504, description = "Request timed out on metrics backend"),
+    )
+)]
+#[get("/series")]
+pub async fn series(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<Client>,
+    params: web::Query<Vec<(String, String)>>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace,) = path.into_inner();
+
+    let params = match metrics::metadata_params(params.into_inner(), &namespace, true) {
+        Ok(params) => params,
+        Err(http_response) => return Ok(http_response),
+    };
+    Ok(metrics::query_prometheus_metadata(cfg, http_client, "series", &params).await)
+}
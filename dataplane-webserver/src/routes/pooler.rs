@@ -0,0 +1,59 @@
+use crate::pooler::{get_pooler_stats, PoolerError};
+use crate::routes::backups::find_instance_namespace;
+use actix_web::{get, web, Error, HttpResponse};
+use kube::Client;
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "Connection pool and traffic stats of each pooler pod, from the PgBouncer admin console", body = Vec<PoolerStats>,
+        example = json!([
+            {"pod":"org-myco-inst-prod-pooler-7d9f8b6c5-x2x7q",
+            "pools":[{"database":"app","user":"app","cl_active":4,"cl_waiting":0,"sv_active":4,"sv_idle":6,"sv_used":0,"maxwait":0}],
+            "databases":[{"database":"app","total_xact_count":120394,"total_query_count":1234500,"total_received":73829102,"total_sent":982736451,"avg_xact_time":1843,"avg_query_time":912,"avg_wait_time":12}]}])),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found or connection pooler not enabled"),
+        (status = 503, description = "Connection pooler stats are unavailable"),
+    )
+)]
+#[get("/pooler/stats")]
+pub async fn get_stats(
+    http_client: web::Data<reqwest::Client>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+
+    match get_pooler_stats(kubernetes_client, &http_client, &namespace).await {
+        Ok(stats) => Ok(HttpResponse::Ok().json(stats)),
+        Err(PoolerError::NotEnabled(_)) => {
+            Ok(HttpResponse::NotFound().json("Connection pooler is not enabled"))
+        }
+        Err(PoolerError::Unavailable(_)) => {
+            Ok(HttpResponse::ServiceUnavailable().json("Connection pooler stats are unavailable"))
+        }
+        Err(e) => {
+            error!("Failed to get pooler stats of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get pooler stats"))
+        }
+    }
+}
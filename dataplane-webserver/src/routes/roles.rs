@@ -0,0 +1,167 @@
+use crate::roles::types::RoleRequest;
+use crate::roles::{create_role, delete_role, list_roles, RoleError};
+use crate::routes::backups::find_instance_namespace;
+use crate::routes::secrets::org_role;
+use actix_web::{delete, get, post, web, Error, HttpRequest, HttpResponse};
+use kube::Client;
+use log::error;
+
+fn role_error_response(namespace: &str, e: RoleError) -> HttpResponse {
+    match e {
+        RoleError::InvalidName(_) => HttpResponse::BadRequest().json(
+            "Role names must be lower case letters, digits and underscores, and not a reserved role",
+        ),
+        RoleError::InvalidRequest(e) => HttpResponse::BadRequest().json(e),
+        RoleError::AlreadyExists(_) => HttpResponse::Conflict().json("Role already exists"),
+        RoleError::NotFound(_) => HttpResponse::NotFound().json("Role not found"),
+        RoleError::Unavailable(_) => {
+            HttpResponse::ServiceUnavailable().json("The instance is not running")
+        }
+        e => {
+            error!("Failed to manage roles of {}: {}", namespace, e);
+            HttpResponse::InternalServerError().json("Failed to manage roles")
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "Roles of the instance created through the data API", body = Vec<Role>,
+        example = json!([
+            {"name":"etl","privileges":"read_write","connection_limit":10},
+            {"name":"reporting","privileges":"read_only","connection_limit":-1}])),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 503, description = "The instance is not running"),
+    )
+)]
+#[get("/roles")]
+pub async fn get_roles(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match list_roles(kubernetes_client, &namespace).await {
+        Ok(roles) => Ok(HttpResponse::Ok().json(roles)),
+        Err(e) => Ok(role_error_response(&namespace, e)),
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    request_body = RoleRequest,
+    responses(
+        (status = 201, description = "Role created, its password is only returned once", body = RoleCredentials,
+        example = json!({"name":"reporting","password":"Qw3rTy8uIoP1aSdF2gHjK4lZxCvB6nM9","privileges":"read_only"})),
+        (status = 400, description = "Invalid role name or connection limit"),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+        (status = 409, description = "Role already exists"),
+        (status = 503, description = "The instance is not running"),
+    )
+)]
+#[post("/roles")]
+pub async fn post_role(
+    path: web::Path<(String, String)>,
+    role_request: web::Json<RoleRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match create_role(kubernetes_client, &namespace, &role_request).await {
+        Ok(credentials) => Ok(HttpResponse::Created().json(credentials)),
+        Err(e) => Ok(role_error_response(&namespace, e)),
+    }
+}
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+        ("role_name", example="reporting", description = "Role name"),
+    ),
+    responses(
+        (status = 200, description = "Role deleted."),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance or role not found"),
+        (status = 503, description = "The instance is not running"),
+    )
+)]
+#[delete("/roles/{role_name}")]
+pub async fn remove_role(
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, instance_id, role_name) = path.into_inner();
+    match org_role(&req, &org_id) {
+        Ok(Some(role)) if role == "admin" => {}
+        Ok(_) => return Err(actix_web::error::ErrorForbidden("Not authorized")),
+        Err(err) => {
+            error!("Error decoding token: {:?}", err);
+            return Err(actix_web::error::ErrorBadRequest("Invalid token"));
+        }
+    }
+
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+    match delete_role(kubernetes_client, &namespace, &role_name).await {
+        Ok(()) => Ok(HttpResponse::Ok().json("Role deleted successfully")),
+        Err(e) => Ok(role_error_response(&namespace, e)),
+    }
+}
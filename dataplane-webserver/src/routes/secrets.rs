@@ -57,6 +57,36 @@ lazy_static! {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Claims {
     organizations: HashMap<String, String>,
+    #[serde(default)]
+    sub: Option<String>,
+}
+
+/// The role of the requester in an organization, from the claims of the bearer token
+pub fn org_role(
+    req: &HttpRequest,
+    org_id: &str,
+) -> Result<Option<String>, jsonwebtoken::errors::Error> {
+    Ok(bearer_claims(req)?.organizations.get(org_id).cloned())
+}
+
+/// The user ID of the requester, from the subject of the bearer token
+pub fn requester(req: &HttpRequest) -> Option<String> {
+    bearer_claims(req).ok().and_then(|claims| claims.sub)
+}
+
+fn bearer_claims(req: &HttpRequest) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .and_then(|hv| hv.to_str().ok())
+        .and_then(|hv| hv.strip_prefix("Bearer "))
+        .unwrap_or("");
+
+    let decoding_key = DecodingKey::from_secret("".as_ref());
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.insecure_disable_signature_validation();
+
+    Ok(decode::<Claims>(auth_header, &decoding_key, &validation)?.claims)
 }
 
 /// Please use /api/v1/orgs/{org_id}/instances/{instance_id}/secrets
@@ -238,7 +268,7 @@ pub async fn get_secret_v1(
     )
 }
 
-fn is_valid_id(s: &str) -> bool {
+pub fn is_valid_id(s: &str) -> bool {
     let re = Regex::new(r"^[A-Za-z0-9_]+$").unwrap();
     re.is_match(s)
 }
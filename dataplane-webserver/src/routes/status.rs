@@ -0,0 +1,62 @@
+use crate::config;
+use crate::routes::backups::find_instance_namespace;
+use crate::status::{health_summary, StatusError};
+use actix_web::{get, web, Error, HttpResponse};
+use kube::Client;
+use log::error;
+
+#[utoipa::path(
+    context_path = "/api/v1/orgs/{org_id}/instances/{instance_id}",
+    params(
+        ("org_id" = String, Path, example="org_2T7FJA0DpaNBnELVLU1IS4XzZG0", description = "Tembo Cloud Organization ID"),
+        ("instance_id" = String, Path, example="inst_1696253936968_TblNOY_6", description = "Tembo Cloud Instance ID"),
+    ),
+    responses(
+        (status = 200, description = "The health of the instance and of its cluster, replicas, pooler and backups", body = HealthSummary,
+        example = json!({
+            "status": "degraded",
+            "issues": ["1 of 2 connection pooler pods are ready"],
+            "running": true,
+            "cluster_phase": "Cluster in healthy state",
+            "ready_instances": 2,
+            "instances": 2,
+            "replication_lag_seconds": 0.4,
+            "pooler": {"ready_pods": 1, "pods": 2},
+            "last_backup_at": "2024-06-01T00:00:12Z",
+            "last_backup_age_seconds": 43188})),
+        (status = 403, description = "Not authorized for query"),
+        (status = 404, description = "Instance not found"),
+    )
+)]
+#[get("/health-summary")]
+pub async fn get_health_summary(
+    cfg: web::Data<config::Config>,
+    http_client: web::Data<reqwest::Client>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    // Requests are auth'd by org_id before entering this function
+    let (org_id, instance_id) = path.into_inner();
+    let namespace = match find_instance_namespace(&org_id, &instance_id).await {
+        Ok(namespace) => namespace,
+        Err(response) => return Ok(response),
+    };
+
+    let kubernetes_client = match Client::try_default().await {
+        Ok(client) => client,
+        Err(_) => {
+            error!("Failed to create Kubernetes client");
+            return Ok(
+                HttpResponse::InternalServerError().json("Failed to create Kubernetes client")
+            );
+        }
+    };
+
+    match health_summary(kubernetes_client, &cfg, &http_client, &namespace).await {
+        Ok(summary) => Ok(HttpResponse::Ok().json(summary)),
+        Err(StatusError::NotFound(_)) => Ok(HttpResponse::NotFound().json("Instance not found")),
+        Err(e) => {
+            error!("Failed to get health summary of {}: {}", namespace, e);
+            Ok(HttpResponse::InternalServerError().json("Failed to get health summary"))
+        }
+    }
+}
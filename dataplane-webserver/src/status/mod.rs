@@ -0,0 +1,258 @@
+use crate::config::Config;
+use crate::metrics::instant_query_result;
+use crate::status::types::{HealthStatus, HealthSummary, PoolerHealth};
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind, ListParams};
+use kube::{Api, Client};
+use log::warn;
+use serde_json::Value;
+use thiserror::Error;
+
+pub mod types;
+
+// CNPG reports this phase once all instances of a cluster are ready
+const HEALTHY_PHASE: &str = "Cluster in healthy state";
+const MAX_REPLICATION_LAG_SECONDS: f64 = 60.0;
+// Backups are taken daily, an instance missed at least one when its last is older than this
+const MAX_BACKUP_AGE_SECONDS: i64 = 48 * 3600;
+
+#[derive(Error, Debug)]
+pub enum StatusError {
+    #[error("Kubernetes error: {0}")]
+    Kube(String),
+
+    #[error("Instance not found: {0}")]
+    NotFound(String),
+}
+
+fn dynamic_api(
+    kubernetes_client: Client,
+    namespace: &str,
+    gvk: &GroupVersionKind,
+) -> Api<DynamicObject> {
+    Api::namespaced_with(kubernetes_client, namespace, &ApiResource::from_gvk(gvk))
+}
+
+/// Summarize the health of an instance from its CoreDB and CNPG cluster, the replication lag
+/// of its replicas and the pods of its pooler
+pub fn summarize(
+    coredb: &Value,
+    cluster: Option<&Value>,
+    replication_lag_seconds: Option<f64>,
+    pooler: Option<PoolerHealth>,
+    now: DateTime<Utc>,
+) -> HealthSummary {
+    let mut down = vec![];
+    let mut degraded = vec![];
+
+    let running = coredb["status"]["running"].as_bool().unwrap_or(false);
+    if !running {
+        down.push("The instance is not running".to_string());
+    }
+
+    let status = cluster.map(|cluster| &cluster["status"]);
+    let cluster_phase = status.and_then(|s| s["phase"].as_str()).map(str::to_string);
+    let ready_instances = status.and_then(|s| s["readyInstances"].as_i64());
+    let instances = status.and_then(|s| s["instances"].as_i64());
+    match (&cluster_phase, ready_instances) {
+        (None, _) => down.push("The Postgres cluster does not exist yet".to_string()),
+        (_, None | Some(0)) => down.push("No Postgres instance is ready".to_string()),
+        (Some(phase), _) if phase != HEALTHY_PHASE => {
+            degraded.push(format!("The Postgres cluster is not healthy: {}", phase))
+        }
+        _ => {}
+    }
+    if let (Some(ready), Some(total)) = (ready_instances, instances) {
+        if ready > 0 && ready < total {
+            degraded.push(format!(
+                "{} of {} Postgres instances are ready",
+                ready, total
+            ));
+        }
+    }
+
+    if let Some(lag) = replication_lag_seconds.filter(|lag| *lag > MAX_REPLICATION_LAG_SECONDS) {
+        degraded.push(format!("Replicas lag behind by {:.0} seconds", lag));
+    }
+
+    if let Some(pooler) = &pooler {
+        if pooler.ready_pods == 0 {
+            down.push("No connection pooler pod is ready".to_string());
+        } else if pooler.ready_pods < pooler.pods {
+            degraded.push(format!(
+                "{} of {} connection pooler pods are ready",
+                pooler.ready_pods, pooler.pods
+            ));
+        }
+    }
+
+    let last_backup_at = status
+        .and_then(|s| s["lastSuccessfulBackup"].as_str())
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc));
+    let last_backup_age_seconds = last_backup_at.map(|time| (now - time).num_seconds());
+    if let Some(age) = last_backup_age_seconds.filter(|age| *age > MAX_BACKUP_AGE_SECONDS) {
+        degraded.push(format!("The last backup is {} hours old", age / 3600));
+    }
+
+    let status = match (down.is_empty(), degraded.is_empty()) {
+        (false, _) => HealthStatus::Down,
+        (true, false) => HealthStatus::Degraded,
+        (true, true) => HealthStatus::Healthy,
+    };
+    down.extend(degraded);
+    HealthSummary {
+        status,
+        issues: down,
+        running,
+        cluster_phase,
+        ready_instances,
+        instances,
+        replication_lag_seconds,
+        pooler,
+        last_backup_at,
+        last_backup_age_seconds,
+    }
+}
+
+async fn pooler_health(
+    kubernetes_client: Client,
+    namespace: &str,
+) -> Result<Option<PoolerHealth>, StatusError> {
+    let pods: Api<Pod> = Api::namespaced(kubernetes_client, namespace);
+    let lp = ListParams::default().labels(&format!("cnpg.io/poolerName={}-pooler", namespace));
+    let pod_list = pods
+        .list(&lp)
+        .await
+        .map_err(|e| StatusError::Kube(e.to_string()))?;
+    if pod_list.items.is_empty() {
+        return Ok(None);
+    }
+    let ready_pods = pod_list
+        .items
+        .iter()
+        .filter(|pod| {
+            pod.status
+                .as_ref()
+                .and_then(|status| status.conditions.as_ref())
+                .is_some_and(|conditions| {
+                    conditions
+                        .iter()
+                        .any(|c| c.type_ == "Ready" && c.status == "True")
+                })
+        })
+        .count();
+    Ok(Some(PoolerHealth {
+        ready_pods: ready_pods as i64,
+        pods: pod_list.items.len() as i64,
+    }))
+}
+
+// The replication lag is best effort, the health of the instance is still summarized
+// without metrics
+async fn replication_lag(
+    cfg: &Config,
+    http_client: &reqwest::Client,
+    namespace: &str,
+) -> Option<f64> {
+    let query = format!(
+        r#"max(cnpg_pg_replication_lag{{namespace="{}"}})"#,
+        namespace
+    );
+    let now = Utc::now().timestamp() as u64;
+    match instant_query_result(cfg, http_client, &query, now).await {
+        Ok(result) => result[0]["value"][1].as_str()?.parse().ok(),
+        Err(e) => {
+            warn!("Failed to get replication lag of {}: {}", namespace, e);
+            None
+        }
+    }
+}
+
+/// The health summary of an instance, the CoreDB and CNPG cluster of an instance are named
+/// after its namespace
+pub async fn health_summary(
+    kubernetes_client: Client,
+    cfg: &Config,
+    http_client: &reqwest::Client,
+    namespace: &str,
+) -> Result<HealthSummary, StatusError> {
+    let coredb = dynamic_api(
+        kubernetes_client.clone(),
+        namespace,
+        &GroupVersionKind::gvk("coredb.io", "v1alpha1", "CoreDB"),
+    )
+    .get_opt(namespace)
+    .await
+    .map_err(|e| StatusError::Kube(e.to_string()))?
+    .ok_or_else(|| StatusError::NotFound(namespace.to_string()))?;
+    let cluster = dynamic_api(
+        kubernetes_client.clone(),
+        namespace,
+        &GroupVersionKind::gvk("postgresql.cnpg.io", "v1", "Cluster"),
+    )
+    .get_opt(namespace)
+    .await
+    .map_err(|e| StatusError::Kube(e.to_string()))?;
+    let pooler = pooler_health(kubernetes_client, namespace).await?;
+    let lag = replication_lag(cfg, http_client, namespace).await;
+
+    Ok(summarize(
+        &coredb.data,
+        cluster.as_ref().map(|cluster| &cluster.data),
+        lag,
+        pooler,
+        Utc::now(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cluster(phase: &str, ready: i64, last_backup: &str) -> Value {
+        json!({"status": {"phase": phase, "instances": 2, "readyInstances": ready,
+            "lastSuccessfulBackup": last_backup}})
+    }
+
+    #[test]
+    fn test_summarize() {
+        let now = DateTime::parse_from_rfc3339("2024-06-02T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let running = json!({"status": {"running": true}});
+
+        let healthy = cluster(HEALTHY_PHASE, 2, "2024-06-02T00:00:00Z");
+        let summary = summarize(&running, Some(&healthy), Some(0.5), None, now);
+        assert_eq!(summary.status, HealthStatus::Healthy);
+        assert!(summary.issues.is_empty());
+        assert_eq!(summary.last_backup_age_seconds, Some(12 * 3600));
+
+        let pooler = PoolerHealth {
+            ready_pods: 1,
+            pods: 2,
+        };
+        let summary = summarize(&running, Some(&healthy), Some(120.0), Some(pooler), now);
+        assert_eq!(summary.status, HealthStatus::Degraded);
+        assert_eq!(summary.issues.len(), 2);
+
+        let old_backup = cluster("Switchover in progress", 1, "2024-05-30T12:00:00Z");
+        let summary = summarize(&running, Some(&old_backup), None, None, now);
+        assert_eq!(summary.status, HealthStatus::Degraded);
+        assert_eq!(
+            summary.issues,
+            vec![
+                "The Postgres cluster is not healthy: Switchover in progress",
+                "1 of 2 Postgres instances are ready",
+                "The last backup is 72 hours old"
+            ]
+        );
+
+        let stopped = json!({"status": {"running": false}});
+        let summary = summarize(&stopped, None, None, None, now);
+        assert_eq!(summary.status, HealthStatus::Down);
+        assert_eq!(summary.issues.len(), 2);
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The overall health of an instance, from its worst component
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Healthy,
+    /// The instance serves queries, but some of its components need attention
+    Degraded,
+    /// The instance does not serve queries
+    Down,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct PoolerHealth {
+    pub ready_pods: i64,
+    pub pods: i64,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct HealthSummary {
+    pub status: HealthStatus,
+    /// Why the instance is not healthy
+    pub issues: Vec<String>,
+    /// Whether the operator reports the instance as running
+    pub running: bool,
+    /// The phase of the CNPG cluster, like "Cluster in healthy state"
+    pub cluster_phase: Option<String>,
+    pub ready_instances: Option<i64>,
+    pub instances: Option<i64>,
+    /// The largest replication lag of the replicas, in seconds, unknown when metrics are
+    /// unavailable
+    pub replication_lag_seconds: Option<f64>,
+    /// The pods of the connection pooler, when it is enabled
+    pub pooler: Option<PoolerHealth>,
+    /// The last successful physical backup
+    pub last_backup_at: Option<DateTime<Utc>>,
+    pub last_backup_age_seconds: Option<i64>,
+}
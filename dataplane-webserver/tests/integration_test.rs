@@ -49,7 +49,7 @@ mod tests {
         let query_url = Url::parse_with_params(url, &query_params)
             .expect("Failed to format query parameters")
             .to_string();
-        return query_url.trim_start_matches("http://localhost").to_string();
+        query_url.trim_start_matches("http://localhost").to_string()
     }
 
     fn format_prometheus_instant_query(url: &str, query: &str) -> String {
@@ -178,4 +178,27 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
     }
+
+    #[actix_web::test]
+    async fn test_metrics_series_other_namespace() {
+        let cfg = config::Config::default();
+        let http_client = reqwest::Client::builder()
+            .build()
+            .expect("Failed to create HTTP client");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(cfg.clone()))
+                .app_data(web::Data::new(http_client.clone()))
+                .service(web::scope("/{namespace}/metrics").service(metrics::series)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/org-coredb-inst-control-plane-dev/metrics/series?match%5B%5D=up%7Bnamespace%3D%22org-other%22%7D")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        // Series of a namespace we do not own are not listed
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+    }
 }
@@ -0,0 +1,18 @@
+-- generation requests which are processed in the background, see src/jobs.rs
+CREATE TABLE inference.jobs (
+    id text PRIMARY KEY,
+    organization_id text NOT NULL,
+    instance_id text NOT NULL,
+    path text NOT NULL,
+    request jsonb NOT NULL,
+    webhook_url text,
+    status text NOT NULL DEFAULT 'queued',
+    response jsonb,
+    error text,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    completed_at timestamp with time zone
+);
+
+CREATE INDEX jobs_organization_id_idx ON inference.jobs (organization_id, created_at);
+
+SELECT pgmq.create('inference_jobs');
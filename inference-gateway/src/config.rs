@@ -23,6 +23,8 @@ pub struct Config {
     /// Interval to refresh the billing authorization cache
     pub org_auth_cache_refresh_interval_sec: u64,
     pub run_billing_reporter: bool,
+    /// Number of workers processing queued jobs in the daemon, 0 disables them
+    pub job_workers: u16,
 }
 
 impl Config {
@@ -56,6 +58,9 @@ impl Config {
             run_billing_reporter: from_env_default("RUN_BILLING_REPORTER", "false")
                 .parse()
                 .unwrap(),
+            job_workers: from_env_default("JOB_WORKERS", "0")
+                .parse()
+                .expect("JOB_WORKERS must be an integer"),
         }
     }
 }
@@ -3,6 +3,7 @@
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 use gateway::config::Config;
 use gateway::events_reporter::run_events_reporter;
+use gateway::jobs::run_job_worker;
 use log::info;
 use std::sync::Arc;
 use std::time::Duration;
@@ -38,6 +39,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }));
     }
 
+    for worker in 0..cfg.job_workers {
+        info!("Spawning job worker {}", worker);
+
+        let cfg = cfg.clone();
+
+        background_threads_guard.push(tokio::spawn(async move {
+            loop {
+                if let Err(err) = run_job_worker(cfg.clone()).await {
+                    log::error!("Job worker {worker} error: {err}");
+                    log::info!("Restarting job worker {worker} in 30 sec");
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                }
+            }
+        }));
+    }
+
     std::mem::drop(background_threads_guard);
 
     let server_port = std::env::var("PORT")
@@ -102,7 +102,6 @@ async fn save_reporter_watermark(
     )
     .execute(inference_pool)
     .await
-    .map_err(Into::into)
     .map(|_| ())
 }
 
@@ -186,17 +185,15 @@ async fn enqueue_event(
         "Split metrics into {} chunks, each with {} results",
         batches, BATCH_SIZE
     );
-    let mut i = 1;
 
-    for event in &metrics_to_send {
+    for (i, event) in metrics_to_send.iter().enumerate() {
         queue.send(metrics_events_queue, event).await?;
         info!(
             "Enqueued batch {}/{} for {} to PGMQ",
-            i,
+            i + 1,
             batches,
             start_time.format("%Y-%m-%d %H:%M:%S %Z")
         );
-        i += 1;
     }
 
     Ok(())
@@ -0,0 +1,432 @@
+//! Generation requests which are processed in the background, so long running batch
+//! workloads do not hold HTTP connections open. Jobs are stored in `inference.jobs` and
+//! their ids are sent to a pgmq queue, which the job workers of the daemon read from.
+
+use chrono::{DateTime, Utc};
+use pgmq::PGMQueueExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Pool, Postgres};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use url::{Host, Url};
+use uuid::Uuid;
+
+use crate::config::{rewrite_model_request, Config};
+use crate::db;
+use crate::errors::PlatformError;
+use crate::routes::forward::{insert_data, Usage};
+
+pub const JOBS_QUEUE: &str = "inference_jobs";
+
+// Generations can take minutes, a job is retried once it is invisible for this long
+const JOB_VT_SEC: i32 = 900;
+// Jobs which failed this many times on errors reaching the model server or the database are
+// given up
+const MAX_ATTEMPTS: i32 = 3;
+// Jobs which failed on an error of the database are retried after this long
+const JOB_RETRY_DELAY_SEC: i32 = 30;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A job as returned to clients, and sent to their webhook once it completed
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub path: String,
+    /// The response of the model server, once the job succeeded
+    pub response: Option<Value>,
+    /// Why the job failed
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+// What a job worker needs to process a job
+#[derive(sqlx::FromRow)]
+struct JobRequest {
+    organization_id: String,
+    instance_id: String,
+    path: String,
+    request: Value,
+    webhook_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JobMessage {
+    job_id: String,
+}
+
+const JOB_COLUMNS: &str = "id, status, path, response, error, created_at, completed_at";
+
+/// Store a job and queue it for the job workers, in one transaction
+pub async fn enqueue_job(
+    pool: &PgPool,
+    organization_id: &str,
+    instance_id: &str,
+    path: &str,
+    request: &Value,
+    webhook_url: Option<&Url>,
+) -> Result<Job, PlatformError> {
+    let id = Uuid::new_v4().to_string();
+    let mut tx = pool.begin().await?;
+    let job = sqlx::query_as::<_, Job>(&format!(
+        "INSERT INTO inference.jobs (id, organization_id, instance_id, path, request, webhook_url)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING {JOB_COLUMNS}"
+    ))
+    .bind(&id)
+    .bind(organization_id)
+    .bind(instance_id)
+    .bind(path)
+    .bind(request)
+    .bind(webhook_url.map(|url| url.to_string()))
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("SELECT pgmq.send($1, $2)")
+        .bind(JOBS_QUEUE)
+        .bind(serde_json::to_value(JobMessage { job_id: id })?)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(job)
+}
+
+/// Get a job of an organization
+pub async fn get_job(
+    pool: &PgPool,
+    organization_id: &str,
+    id: &str,
+) -> Result<Option<Job>, PlatformError> {
+    let job = sqlx::query_as::<_, Job>(&format!(
+        "SELECT {JOB_COLUMNS} FROM inference.jobs WHERE id = $1 AND organization_id = $2"
+    ))
+    .bind(id)
+    .bind(organization_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(job)
+}
+
+/// Process queued jobs one at a time, until an error with the queue or the database
+pub async fn run_job_worker(cfg: Config) -> anyhow::Result<()> {
+    let pool = db::connect(&cfg.pg_conn_str, 2).await?;
+    let queue = PGMQueueExt::new(cfg.pg_conn_str.clone(), 1).await?;
+    let client = reqwest::Client::new();
+
+    loop {
+        let Some(message) = queue.read::<JobMessage>(JOBS_QUEUE, JOB_VT_SEC).await? else {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        };
+        let job_id = &message.message.job_id;
+        match process_job(&cfg, &client, &pool, job_id, message.read_ct).await {
+            Ok(true) => {
+                queue.archive(JOBS_QUEUE, message.msg_id).await?;
+            }
+            // The message becomes visible again after JOB_VT_SEC, to retry the job
+            Ok(false) => {}
+            Err(e) if message.read_ct < MAX_ATTEMPTS => {
+                log::error!("Failed to process job {}, retrying it: {}", job_id, e);
+                queue
+                    .set_vt::<JobMessage>(JOBS_QUEUE, message.msg_id, JOB_RETRY_DELAY_SEC)
+                    .await?;
+            }
+            Err(e) => {
+                log::error!("Failed to process job {}, giving up: {}", job_id, e);
+                if let Err(e) = fail_job(&pool, job_id, &e.to_string()).await {
+                    log::error!("Failed to mark job {} as failed: {}", job_id, e);
+                }
+                queue.archive(JOBS_QUEUE, message.msg_id).await?;
+            }
+        }
+    }
+}
+
+// Run a job, returns whether it is done, either succeeded or failed
+async fn process_job(
+    cfg: &Config,
+    client: &reqwest::Client,
+    pool: &PgPool,
+    job_id: &str,
+    read_ct: i32,
+) -> Result<bool, PlatformError> {
+    let Some(job) = sqlx::query_as::<_, JobRequest>(
+        "UPDATE inference.jobs SET status = 'running'
+        WHERE id = $1 AND status IN ('queued', 'running')
+        RETURNING organization_id, instance_id, path, request, webhook_url",
+    )
+    .bind(job_id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        log::warn!("Job {} does not exist or is already done", job_id);
+        return Ok(true);
+    };
+
+    let (status, response, error) = match generate(cfg, client, pool, &job).await {
+        Ok(response) => (JobStatus::Succeeded, Some(response), None),
+        // Errors reaching the model server are retried, anything else will not succeed later
+        Err(e @ PlatformError::Reqwest(_)) if read_ct < MAX_ATTEMPTS => {
+            log::warn!("Job {} attempt {} failed: {}", job_id, read_ct, e);
+            return Ok(false);
+        }
+        Err(e) => (JobStatus::Failed, None, Some(e.to_string())),
+    };
+
+    let completed = sqlx::query_as::<_, Job>(&format!(
+        "UPDATE inference.jobs SET status = $2, response = $3, error = $4, completed_at = now()
+        WHERE id = $1
+        RETURNING {JOB_COLUMNS}"
+    ))
+    .bind(job_id)
+    .bind(status)
+    .bind(response)
+    .bind(error)
+    .fetch_one(pool)
+    .await?;
+    log::info!("Job {} {:?}", job_id, completed.status);
+
+    if let Some(webhook_url) = &job.webhook_url {
+        notify_webhook(webhook_url, &completed).await;
+    }
+    Ok(true)
+}
+
+// Give up a job which could not be processed, so polling it does not show it running forever
+async fn fail_job(pool: &PgPool, job_id: &str, error: &str) -> Result<(), PlatformError> {
+    sqlx::query(
+        "UPDATE inference.jobs SET status = 'failed', error = $2, completed_at = now()
+        WHERE id = $1 AND status IN ('queued', 'running')",
+    )
+    .bind(job_id)
+    .bind(error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Send the request of a job to the model server, and log its token usage like forwarded requests
+async fn generate(
+    cfg: &Config,
+    client: &reqwest::Client,
+    pool: &Pool<Postgres>,
+    job: &JobRequest,
+) -> Result<Value, PlatformError> {
+    let rewrite_request = rewrite_model_request(job.request.clone(), cfg)?;
+    let mut url = rewrite_request.base_url;
+    url.set_path(&job.path);
+
+    let start = Instant::now();
+    let resp = client.post(url).json(&rewrite_request.body).send().await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let error = resp.text().await?;
+        return Err(PlatformError::InvalidQuery(format!(
+            "model server responded with {}: {}",
+            status, error
+        )));
+    }
+    let llm_resp = resp.json::<Value>().await?;
+    let duration = start.elapsed().as_millis() as i32;
+
+    let invalid_response =
+        || PlatformError::InvalidQuery("invalid response from model server".to_string());
+    let model = llm_resp
+        .get("model")
+        .and_then(|model| model.as_str())
+        .ok_or_else(invalid_response)?;
+    let usage: Usage =
+        serde_json::from_value(llm_resp.get("usage").ok_or_else(invalid_response)?.clone())?;
+    if let Err(e) = insert_data(
+        &job.organization_id,
+        &job.instance_id,
+        model,
+        usage,
+        duration,
+        pool,
+    )
+    .await
+    {
+        log::error!("{}", e);
+    }
+    Ok(llm_resp)
+}
+
+// Webhooks are called once, the job can still be polled when the call fails
+async fn notify_webhook(webhook_url: &str, job: &Job) {
+    let result = async {
+        let url = parse_webhook_url(webhook_url)?;
+        let client = webhook_client(&url).await?;
+        client
+            .post(url)
+            .json(job)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok::<(), PlatformError>(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!("Failed to call webhook of job {}: {}", job.id, e);
+    }
+}
+
+// A client which only connects to the public addresses the webhook host resolves to. They are
+// pinned, so the host can not resolve to an internal address for the request itself, and
+// redirects are not followed.
+async fn webhook_client(url: &Url) -> Result<reqwest::Client, PlatformError> {
+    let builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(WEBHOOK_TIMEOUT);
+    let builder = match url.host() {
+        Some(Host::Domain(domain)) => {
+            let port = url.port_or_known_default().unwrap_or(443);
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((domain, port))
+                .await
+                .map_err(|e| {
+                    PlatformError::InvalidQuery(format!(
+                        "could not resolve webhook host {}: {}",
+                        domain, e
+                    ))
+                })?
+                .collect();
+            if addrs.is_empty() || !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+                return Err(PlatformError::InvalidQuery(format!(
+                    "webhook host {} does not resolve to public addresses",
+                    domain
+                )));
+            }
+            builder.resolve_to_addrs(domain, &addrs)
+        }
+        // Addresses were checked by parse_webhook_url
+        _ => builder,
+    };
+    Ok(builder.build()?)
+}
+
+/// Check the webhook of a job, only http and https URLs of public hosts are called
+pub fn parse_webhook_url(webhook_url: &str) -> Result<Url, PlatformError> {
+    let url = Url::parse(webhook_url)
+        .map_err(|e| PlatformError::InvalidQuery(format!("invalid webhook_url: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(PlatformError::InvalidQuery(
+            "webhook_url must be an http or https URL".to_string(),
+        ));
+    }
+    let public = match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(Host::Ipv4(ip)) => is_public_ip(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public_ip(IpAddr::V6(ip)),
+        None => false,
+    };
+    if !public {
+        return Err(PlatformError::InvalidQuery(
+            "webhook_url must be the URL of a public host".to_string(),
+        ));
+    }
+    Ok(url)
+}
+
+// Whether an address is reachable on the internet, not one of the loopback, private, link-local
+// (cloud metadata included), shared or otherwise reserved ranges
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8
+        || a == 0
+        // Shared address space, 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_webhook_url() {
+        assert_eq!(
+            parse_webhook_url("https://example.com/hooks/jobs")
+                .unwrap()
+                .as_str(),
+            "https://example.com/hooks/jobs"
+        );
+        assert!(parse_webhook_url("file:///etc/passwd").is_err());
+        assert!(parse_webhook_url("not a url").is_err());
+        for internal in [
+            "http://localhost:8080/hooks",
+            "http://api.localhost/hooks",
+            "http://127.0.0.1/hooks",
+            "http://10.0.0.12/hooks",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.1.1/hooks",
+            "http://[::1]/hooks",
+            "http://[fd00::1]/hooks",
+            "http://[::ffff:192.168.1.1]/hooks",
+        ] {
+            assert!(parse_webhook_url(internal).is_err(), "{}", internal);
+        }
+        assert!(parse_webhook_url("http://93.184.216.34/hooks").is_ok());
+        assert!(parse_webhook_url("http://[2606:2800:220:1::1]/hooks").is_ok());
+    }
+
+    #[test]
+    fn test_job_serialization() {
+        let job = Job {
+            id: "abc".to_string(),
+            status: JobStatus::Queued,
+            path: "/v1/chat/completions".to_string(),
+            response: None,
+            error: None,
+            created_at: DateTime::from_timestamp(0, 0).unwrap(),
+            completed_at: None,
+        };
+        let value = serde_json::to_value(&job).unwrap();
+        assert_eq!(value["status"], "queued");
+        assert_eq!(value["path"], "/v1/chat/completions");
+    }
+}
@@ -3,5 +3,7 @@ pub mod config;
 pub mod db;
 pub mod errors;
 pub mod events_reporter;
+pub mod jobs;
+pub mod metrics;
 pub mod routes;
 pub mod server;
@@ -0,0 +1,208 @@
+use prometheus::{
+    histogram_opts, opts, proto::MetricFamily, Encoder, HistogramVec, IntCounterVec, Registry,
+    TextEncoder,
+};
+use std::fmt;
+use std::time::Duration;
+
+const LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30., 60., 120.];
+
+/// Per model and per org serving metrics, exported on /metrics
+#[derive(Clone)]
+pub struct Metrics {
+    pub registry: Registry,
+    pub requests: IntCounterVec,
+    pub errors: IntCounterVec,
+    pub tokens: IntCounterVec,
+    pub request_duration: HistogramVec,
+    pub time_to_first_token: HistogramVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let requests = IntCounterVec::new(
+            opts!(
+                "inference_gateway_requests_total",
+                "Requests forwarded to a model server"
+            ),
+            &["model", "org"],
+        )
+        .unwrap();
+        let errors = IntCounterVec::new(
+            opts!(
+                "inference_gateway_request_errors_total",
+                "Requests forwarded to a model server which failed"
+            ),
+            &["model", "org", "reason"],
+        )
+        .unwrap();
+        let tokens = IntCounterVec::new(
+            opts!(
+                "inference_gateway_tokens_total",
+                "Tokens processed by a model server"
+            ),
+            &["model", "org", "type"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            histogram_opts!(
+                "inference_gateway_request_duration_seconds",
+                "The duration of a request to a model server in seconds"
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["model", "org"],
+        )
+        .unwrap();
+        let time_to_first_token = HistogramVec::new(
+            histogram_opts!(
+                "inference_gateway_time_to_first_token_seconds",
+                "The time until a model server starts responding in seconds"
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["model", "org"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(tokens.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(time_to_first_token.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            errors,
+            tokens,
+            request_duration,
+            time_to_first_token,
+        }
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    pub fn request(&self, model: &str, org: &str) {
+        self.requests.with_label_values(&[model, org]).inc();
+    }
+
+    pub fn error(&self, model: &str, org: &str, reason: &str) {
+        self.errors.with_label_values(&[model, org, reason]).inc();
+    }
+
+    pub fn first_token(&self, model: &str, org: &str, elapsed: Duration) {
+        self.time_to_first_token
+            .with_label_values(&[model, org])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn completed(
+        &self,
+        model: &str,
+        org: &str,
+        elapsed: Duration,
+        prompt_tokens: i32,
+        completion_tokens: i32,
+    ) {
+        self.request_duration
+            .with_label_values(&[model, org])
+            .observe(elapsed.as_secs_f64());
+        self.tokens
+            .with_label_values(&[model, org, "prompt"])
+            .inc_by(prompt_tokens.max(0) as u64);
+        self.tokens
+            .with_label_values(&[model, org, "completion"])
+            .inc_by(completion_tokens.max(0) as u64);
+    }
+
+    /// Render the metrics in the Prometheus text format
+    pub fn encode(&self) -> String {
+        encode_families(&self.registry.gather())
+    }
+
+    /// Render only the metrics of one org in the Prometheus text format
+    pub fn encode_org(&self, org: &str) -> String {
+        let families = self
+            .registry
+            .gather()
+            .into_iter()
+            .filter_map(|mut family| {
+                let metrics = family
+                    .take_metric()
+                    .into_iter()
+                    .filter(|metric| {
+                        metric
+                            .get_label()
+                            .iter()
+                            .any(|label| label.get_name() == "org" && label.get_value() == org)
+                    })
+                    .collect::<Vec<_>>();
+                if metrics.is_empty() {
+                    return None;
+                }
+                family.set_metric(metrics.into());
+                Some(family)
+            })
+            .collect::<Vec<_>>();
+        encode_families(&families)
+    }
+}
+
+fn encode_families(families: &[MetricFamily]) -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(families, &mut buffer).unwrap();
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = Metrics::default();
+        metrics.request("facebook/opt-125m", "org-1");
+        metrics.first_token("facebook/opt-125m", "org-1", Duration::from_millis(200));
+        metrics.completed(
+            "facebook/opt-125m",
+            "org-1",
+            Duration::from_millis(900),
+            10,
+            25,
+        );
+        metrics.request("facebook/opt-125m", "org-2");
+        metrics.error("facebook/opt-125m", "org-2", "model_server");
+
+        let encoded = metrics.encode();
+        assert!(encoded.contains(
+            r#"inference_gateway_requests_total{model="facebook/opt-125m",org="org-1"} 1"#
+        ));
+        assert!(encoded.contains(
+            r#"inference_gateway_request_errors_total{model="facebook/opt-125m",org="org-2",reason="model_server"} 1"#
+        ));
+        assert!(encoded.contains(
+            r#"inference_gateway_tokens_total{model="facebook/opt-125m",org="org-1",type="completion"} 25"#
+        ));
+        assert!(encoded.contains(
+            r#"inference_gateway_time_to_first_token_seconds_count{model="facebook/opt-125m",org="org-1"} 1"#
+        ));
+
+        // Only the series of the requested org are rendered
+        let encoded = metrics.encode_org("org-1");
+        assert!(encoded.contains(
+            r#"inference_gateway_requests_total{model="facebook/opt-125m",org="org-1"} 1"#
+        ));
+        assert!(!encoded.contains("org-2"));
+        assert!(!encoded.contains("inference_gateway_request_errors_total"));
+    }
+}
@@ -9,6 +9,7 @@ use tokio::sync::RwLock;
 use crate::authorization;
 use crate::config::rewrite_model_request;
 use crate::errors::{AuthError, PlatformError};
+use crate::metrics::Metrics;
 
 pub async fn forward_request(
     req: HttpRequest,
@@ -17,29 +18,9 @@ pub async fn forward_request(
     client: web::Data<reqwest::Client>,
     dbclient: web::Data<Arc<PgPool>>,
     cache: web::Data<Arc<RwLock<HashMap<String, bool>>>>,
+    metrics: web::Data<Metrics>,
 ) -> Result<HttpResponse, PlatformError> {
-    let headers = req.headers();
-    let x_tembo_org = if let Some(header) = headers.get("X-TEMBO-ORG") {
-        header.to_str().unwrap()
-    } else {
-        return Err(
-            AuthError::Forbidden("Missing request header `X-TEMBO-ORG`".to_string()).into(),
-        );
-    };
-    let x_tembo_inst = if let Some(header) = headers.get("X-TEMBO-INSTANCE") {
-        header.to_str().unwrap()
-    } else {
-        return Err(
-            AuthError::Forbidden("Missing request header `X-TEMBO-INSTANCE`".to_string()).into(),
-        );
-    };
-
-    if config.org_auth_enabled {
-        let is_valid = authorization::auth_org(x_tembo_org, &cache).await;
-        if !is_valid {
-            return Err(AuthError::Forbidden("Organization is not authorized".to_string()).into());
-        }
-    }
+    let (x_tembo_org, x_tembo_inst) = authorize_request(&req, &config, &cache).await?;
 
     let path = req.uri().path();
     if path.contains("embeddings") {
@@ -52,16 +33,35 @@ pub async fn forward_request(
     new_url.set_path(path);
     new_url.set_query(req.uri().query());
 
+    let metric_model = rewrite_request.model.as_str();
+    metrics.request(metric_model, x_tembo_org);
+
     // log request duration
     let start = std::time::Instant::now();
-    let resp = client
+    let resp = match client
         .post(new_url)
         .json(&rewrite_request.body)
         .send()
-        .await?;
-    let duration = start.elapsed().as_millis() as i32;
+        .await
+    {
+        Ok(resp) => resp,
+        Err(e) => {
+            metrics.error(metric_model, x_tembo_org, "unreachable");
+            return Err(e.into());
+        }
+    };
+    // responses are not streamed, so the first token arrives with the response headers
+    metrics.first_token(metric_model, x_tembo_org, start.elapsed());
     if resp.status().is_success() {
-        let llm_resp = resp.json::<serde_json::Value>().await?;
+        let llm_resp = match resp.json::<serde_json::Value>().await {
+            Ok(llm_resp) => llm_resp,
+            Err(e) => {
+                metrics.error(metric_model, x_tembo_org, "invalid_response");
+                return Err(e.into());
+            }
+        };
+        let elapsed = start.elapsed();
+        let duration = elapsed.as_millis() as i32;
         let model = llm_resp
             .get("model")
             .ok_or_else(|| {
@@ -79,6 +79,13 @@ pub async fn forward_request(
                 })?
                 .clone(),
         )?;
+        metrics.completed(
+            metric_model,
+            x_tembo_org,
+            elapsed,
+            usage.prompt_tokens,
+            usage.completion_tokens,
+        );
         if let Err(e) =
             insert_data(x_tembo_org, x_tembo_inst, model, usage, duration, &dbclient).await
         {
@@ -86,12 +93,45 @@ pub async fn forward_request(
         }
         Ok(HttpResponse::Ok().json(llm_resp))
     } else {
+        metrics.error(metric_model, x_tembo_org, resp.status().as_str());
         let error = resp.text().await?;
         Ok(HttpResponse::BadRequest().body(error))
     }
 }
 
-async fn insert_data(
+/// Get the organization and instance of a request from its headers, and check that the
+/// organization is authorized
+pub async fn authorize_request<'a>(
+    req: &'a HttpRequest,
+    config: &crate::config::Config,
+    cache: &Arc<RwLock<HashMap<String, bool>>>,
+) -> Result<(&'a str, &'a str), PlatformError> {
+    let headers = req.headers();
+    let x_tembo_org = if let Some(header) = headers.get("X-TEMBO-ORG") {
+        header.to_str().unwrap()
+    } else {
+        return Err(
+            AuthError::Forbidden("Missing request header `X-TEMBO-ORG`".to_string()).into(),
+        );
+    };
+    let x_tembo_inst = if let Some(header) = headers.get("X-TEMBO-INSTANCE") {
+        header.to_str().unwrap()
+    } else {
+        return Err(
+            AuthError::Forbidden("Missing request header `X-TEMBO-INSTANCE`".to_string()).into(),
+        );
+    };
+
+    if config.org_auth_enabled {
+        let is_valid = authorization::auth_org(x_tembo_org, cache).await;
+        if !is_valid {
+            return Err(AuthError::Forbidden("Organization is not authorized".to_string()).into());
+        }
+    }
+    Ok((x_tembo_org, x_tembo_inst))
+}
+
+pub(crate) async fn insert_data(
     org: &str,
     isnt: &str,
     model: &str,
@@ -115,7 +155,7 @@ async fn insert_data(
 }
 
 #[derive(Serialize, Deserialize)]
-struct Usage {
-    prompt_tokens: i32,
-    completion_tokens: i32,
+pub(crate) struct Usage {
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
 }
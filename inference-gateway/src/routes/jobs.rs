@@ -0,0 +1,84 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::config::rewrite_model_request;
+use crate::errors::PlatformError;
+use crate::jobs;
+use crate::routes::forward::authorize_request;
+
+#[derive(Deserialize)]
+pub struct NewJob {
+    /// The path of the model server API the request is sent to
+    #[serde(default = "default_path")]
+    path: String,
+    /// The request body, as it would be sent to the path
+    request: serde_json::Value,
+    /// Called with the job once it succeeded or failed
+    webhook_url: Option<String>,
+}
+
+fn default_path() -> String {
+    "/v1/chat/completions".to_string()
+}
+
+#[post("/v1/jobs")]
+pub async fn create_job(
+    req: HttpRequest,
+    body: web::Json<NewJob>,
+    config: web::Data<crate::config::Config>,
+    dbclient: web::Data<Arc<PgPool>>,
+    cache: web::Data<Arc<RwLock<HashMap<String, bool>>>>,
+) -> Result<HttpResponse, PlatformError> {
+    let (x_tembo_org, x_tembo_inst) = authorize_request(&req, &config, &cache).await?;
+
+    if !body.path.starts_with("/v1/") || body.path.starts_with("/v1/jobs") {
+        return Err(PlatformError::InvalidQuery(format!(
+            "invalid path {}",
+            body.path
+        )));
+    }
+    if body.path.contains("embeddings") {
+        return Ok(HttpResponse::BadRequest().body("Embedding generation is not yet supported"));
+    }
+    // Fail right away on requests for models which are not served
+    rewrite_model_request(body.request.clone(), &config)?;
+    let webhook_url = body
+        .webhook_url
+        .as_deref()
+        .map(jobs::parse_webhook_url)
+        .transpose()?;
+
+    let job = jobs::enqueue_job(
+        &dbclient,
+        x_tembo_org,
+        x_tembo_inst,
+        &body.path,
+        &body.request,
+        webhook_url.as_ref(),
+    )
+    .await?;
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[get("/v1/jobs/{id}")]
+pub async fn get_job(
+    req: HttpRequest,
+    id: web::Path<String>,
+    config: web::Data<crate::config::Config>,
+    dbclient: web::Data<Arc<PgPool>>,
+    cache: web::Data<Arc<RwLock<HashMap<String, bool>>>>,
+) -> Result<HttpResponse, PlatformError> {
+    let (x_tembo_org, _) = authorize_request(&req, &config, &cache).await?;
+
+    match jobs::get_job(&dbclient, x_tembo_org, &id).await? {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(PlatformError::NotFoundError(format!(
+            "job {} not found",
+            id
+        ))),
+    }
+}
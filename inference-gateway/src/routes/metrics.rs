@@ -0,0 +1,22 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::errors::PlatformError;
+use crate::metrics::Metrics;
+use crate::routes::forward::authorize_request;
+
+/// The serving metrics of the organization of the request
+#[get("/metrics")]
+async fn metrics(
+    req: HttpRequest,
+    config: web::Data<crate::config::Config>,
+    cache: web::Data<Arc<RwLock<HashMap<String, bool>>>>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, PlatformError> {
+    let (x_tembo_org, _) = authorize_request(&req, &config, &cache).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.encode_org(x_tembo_org)))
+}
@@ -1,2 +1,4 @@
 pub mod forward;
 pub mod health;
+pub mod jobs;
+pub mod metrics;
@@ -1,7 +1,7 @@
 use actix_web::web;
 
 use crate::routes;
-use crate::{authorization, config, db};
+use crate::{authorization, config, db, metrics::Metrics};
 
 use sqlx::{Pool, Postgres};
 use std::collections::HashMap;
@@ -13,6 +13,9 @@ pub fn webserver_routes(configuration: &mut web::ServiceConfig) {
     configuration
         .service(routes::health::ready)
         .service(routes::health::lively)
+        .service(routes::metrics::metrics)
+        .service(routes::jobs::create_job)
+        .service(routes::jobs::get_job)
         .default_service(web::to(routes::forward::forward_request));
 }
 
@@ -22,6 +25,7 @@ pub struct ServerStartUpConfig {
     pub pool: Arc<Pool<Postgres>>,
     pub auth_cache: Arc<RwLock<HashMap<String, bool>>>,
     pub http_client: reqwest::Client,
+    pub metrics: Metrics,
 }
 
 pub async fn webserver_startup_config(cfg: config::Config) -> ServerStartUpConfig {
@@ -61,5 +65,6 @@ pub async fn webserver_startup_config(cfg: config::Config) -> ServerStartUpConfi
         pool,
         auth_cache,
         http_client,
+        metrics: Metrics::default(),
     }
 }
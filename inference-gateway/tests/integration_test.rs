@@ -5,7 +5,6 @@ use rand::prelude::*;
 use sqlx::Row;
 use util::common;
 
-use env_logger;
 use gateway::config::Config;
 use gateway::db::{self, connect};
 
@@ -57,7 +56,7 @@ async fn test_logging() {
 
     let choices = body.get("choices").unwrap().as_array().unwrap();
     assert_eq!(choices.len(), 1);
-    choices.get(0).unwrap();
+    choices.first().unwrap();
 
     let conn = connect(&config.pg_conn_str, 2)
         .await
@@ -71,7 +70,7 @@ async fn test_logging() {
 
     assert_eq!(rows.len(), 1);
 
-    let row = rows.get(0).unwrap();
+    let row = rows.first().unwrap();
     assert_eq!(row.get::<String, &str>("instance_id"), instance);
     assert_eq!(row.get::<String, &str>("organization_id"), "MY-TEST-ORG");
     assert_eq!(row.get::<String, &str>("model"), "facebook/opt-125m");
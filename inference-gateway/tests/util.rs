@@ -17,6 +17,7 @@ pub mod common {
                 .app_data(web::Data::new(startup_config.http_client.clone()))
                 .app_data(web::Data::new(startup_config.pool.clone()))
                 .app_data(web::Data::new(startup_config.auth_cache.clone()))
+                .app_data(web::Data::new(startup_config.metrics.clone()))
                 .configure(gateway::server::webserver_routes),
         )
         .await
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+pub const APPLY_LOCK_FILE_NAME: &str = ".tembo-apply.lock";
+
+/// Who is holding the apply lock of a project
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct LockHolder {
+    pub user: String,
+    pub host: String,
+    pub pid: u32,
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let host = env::var("HOSTNAME")
+            .or_else(|_| env::var("COMPUTERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        LockHolder {
+            user,
+            host,
+            pid: std::process::id(),
+            acquired_at: Utc::now(),
+        }
+    }
+}
+
+/// Advisory lock held while `tembo apply` runs, so two applies of the same tembo.toml do not
+/// interleave their updates. The lock is a file next to tembo.toml and is removed when the
+/// lock is dropped.
+pub struct ApplyLock {
+    path: PathBuf,
+}
+
+impl ApplyLock {
+    /// Acquire the lock of the project in `dir`. With `force_unlock` a lock left behind by an
+    /// interrupted apply is removed first.
+    pub fn acquire(dir: &Path, force_unlock: bool) -> Result<ApplyLock, anyhow::Error> {
+        let path = dir.join(APPLY_LOCK_FILE_NAME);
+        if force_unlock {
+            match fs::remove_file(&path) {
+                Ok(_) => log::info!("Removed apply lock {}", path.display()),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Removing {}", path.display()))
+                }
+            }
+        }
+
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                let holder = match Self::holder(dir) {
+                    Some(holder) => format!(
+                        "{}@{} (pid {}) since {}",
+                        holder.user, holder.host, holder.pid, holder.acquired_at
+                    ),
+                    None => "an unknown apply".to_string(),
+                };
+                return Err(anyhow!(
+                    "tembo apply is locked by {}. If no apply is running, rerun with --force-unlock",
+                    holder
+                ));
+            }
+            Err(err) => return Err(err).with_context(|| format!("Creating {}", path.display())),
+        };
+
+        let contents = toml::to_string(&LockHolder::current())?;
+        let lock = ApplyLock { path };
+        file.write_all(contents.as_bytes())
+            .with_context(|| format!("Writing {}", lock.path.display()))?;
+        Ok(lock)
+    }
+
+    /// The holder of the lock of the project in `dir`, None if it is not locked
+    pub fn holder(dir: &Path) -> Option<LockHolder> {
+        let contents = fs::read_to_string(dir.join(APPLY_LOCK_FILE_NAME)).ok()?;
+        toml::from_str(&contents).ok()
+    }
+}
+
+impl Drop for ApplyLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("Failed to remove {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_lock() {
+        let dir = env::temp_dir().join(format!("tembo-apply-lock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let lock = ApplyLock::acquire(&dir, false).unwrap();
+        assert_eq!(
+            ApplyLock::holder(&dir).map(|holder| holder.pid),
+            Some(std::process::id())
+        );
+        let err = ApplyLock::acquire(&dir, false).err().unwrap();
+        assert!(err.to_string().contains("--force-unlock"));
+
+        std::mem::forget(lock);
+        let lock = ApplyLock::acquire(&dir, true).unwrap();
+        drop(lock);
+        assert!(ApplyLock::holder(&dir).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
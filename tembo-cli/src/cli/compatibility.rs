@@ -0,0 +1,173 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use toml_edit::{value, DocumentMut};
+
+const COMPATIBILITY_TABLE: &str = include_str!("../../tembo/compatibility.toml");
+
+/// Deprecated tembo.toml fields and stack names, shipped with the CLI
+#[derive(Deserialize, Debug, Clone)]
+pub struct CompatibilityTable {
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default)]
+    pub stack_types: BTreeMap<String, String>,
+}
+
+impl CompatibilityTable {
+    pub fn load() -> Result<Self, anyhow::Error> {
+        Ok(toml::from_str(COMPATIBILITY_TABLE)?)
+    }
+}
+
+/// A single deprecated setting found in a section of tembo.toml
+#[derive(Debug, Clone, PartialEq)]
+pub enum Migration {
+    RenamedField {
+        section: String,
+        from: String,
+        to: String,
+    },
+    // the deprecated field was dropped, because the new field was already set
+    RemovedField {
+        section: String,
+        from: String,
+        to: String,
+    },
+    RenamedStackType {
+        section: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Migration::RenamedField { section, from, to } => write!(
+                f,
+                "[{}] field '{}' is deprecated, renamed to '{}'",
+                section, from, to
+            ),
+            Migration::RemovedField { section, from, to } => write!(
+                f,
+                "[{}] field '{}' is deprecated, removed since '{}' is already set",
+                section, from, to
+            ),
+            Migration::RenamedStackType { section, from, to } => write!(
+                f,
+                "[{}] stack_type '{}' is deprecated, renamed to '{}'",
+                section, from, to
+            ),
+        }
+    }
+}
+
+/// Rewrites deprecated fields and stack names of every instance section in place,
+/// keeping comments and formatting, and returns the migrations which were applied
+pub fn migrate(doc: &mut DocumentMut, table: &CompatibilityTable) -> Vec<Migration> {
+    let mut migrations = Vec::new();
+
+    for (section, item) in doc.iter_mut() {
+        let Some(settings) = item.as_table_like_mut() else {
+            continue;
+        };
+        let section = section.get().to_string();
+
+        for (from, to) in table.fields.iter() {
+            let Some(old_value) = settings.remove(from) else {
+                continue;
+            };
+            if settings.contains_key(to) {
+                migrations.push(Migration::RemovedField {
+                    section: section.clone(),
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            } else {
+                settings.insert(to, old_value);
+                migrations.push(Migration::RenamedField {
+                    section: section.clone(),
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+        }
+
+        let stack_type = settings
+            .get("stack_type")
+            .and_then(|stack_type| stack_type.as_str())
+            .map(|stack_type| stack_type.to_string());
+        if let Some(new_stack_type) = stack_type
+            .as_ref()
+            .and_then(|stack_type| table.stack_types.get(stack_type))
+        {
+            settings.insert("stack_type", value(new_stack_type.as_str()));
+            migrations.push(Migration::RenamedStackType {
+                section: section.clone(),
+                from: stack_type.unwrap_or_default(),
+                to: new_stack_type.clone(),
+            });
+        }
+    }
+
+    migrations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility_table_loads() {
+        let table = CompatibilityTable::load().unwrap();
+        assert_eq!(
+            table.stack_types.get("DataWarehouse"),
+            Some(&"Analytics".to_string())
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let table = CompatibilityTable::load().unwrap();
+        let mut doc: DocumentMut = r#"
+# my analytics instance
+[analytics]
+environment = "dev"
+instance_name = "analytics"
+stack = "DataWarehouse"
+postgres_version = 15
+pg_version = 16
+
+[standard]
+environment = "dev"
+instance_name = "standard"
+stack_type = "Standard"
+"#
+        .parse()
+        .unwrap();
+
+        let migrations = migrate(&mut doc, &table);
+        assert_eq!(migrations.len(), 3);
+        assert!(migrations.contains(&Migration::RemovedField {
+            section: "analytics".to_string(),
+            from: "postgres_version".to_string(),
+            to: "pg_version".to_string(),
+        }));
+        assert!(migrations.contains(&Migration::RenamedStackType {
+            section: "analytics".to_string(),
+            from: "DataWarehouse".to_string(),
+            to: "Analytics".to_string(),
+        }));
+
+        let fixed = doc.to_string();
+        assert!(fixed.contains("# my analytics instance"));
+        assert!(fixed.contains("stack_type = \"Analytics\""));
+        assert!(fixed.contains("pg_version = 16"));
+        assert!(!fixed.contains("postgres_version"));
+        assert!(!fixed.contains("stack ="));
+
+        // Running again is a no-op
+        assert!(migrate(&mut doc, &table).is_empty());
+    }
+}
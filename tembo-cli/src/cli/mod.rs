@@ -1,3 +1,5 @@
+pub mod apply_lock;
+pub mod compatibility;
 pub mod context;
 pub mod docker;
 pub mod file_utils;
@@ -49,6 +49,7 @@ use tembodataclient::apis::secrets_api::get_secret_v1;
 use tokio::runtime::Runtime;
 use toml::Value;
 
+use crate::cli::apply_lock::ApplyLock;
 use crate::cli::docker::Docker;
 use crate::cli::file_utils::FileUtils;
 use crate::cli::sqlx_utils::SqlxUtils;
@@ -80,15 +81,19 @@ pub struct ApplyCommand {
     /// Replace a specific configuration in your tembo.toml file. For example, tembo apply --set standard.cpu = 0.25
     #[clap(long, short = 's')]
     pub set: Option<String>,
+    /// Remove the apply lock left behind by an interrupted apply before applying.
+    #[clap(long)]
+    pub force_unlock: bool,
 }
 
 pub fn execute(
     verbose: bool,
     merge_path: Option<String>,
     set_arg: Option<String>,
+    force_unlock: bool,
 ) -> Result<(), anyhow::Error> {
     info!("Running validation!");
-    super::validate::execute(verbose)?;
+    super::validate::execute(verbose, false)?;
 
     if let Some(path) = &merge_path {
         validate_overlay(path)?;
@@ -98,6 +103,11 @@ pub fn execute(
     let env = get_current_context()?;
     let instance_settings = get_instance_settings(merge_path.clone(), set_arg)?;
 
+    let _lock = ApplyLock::acquire(
+        &PathBuf::from(FileUtils::get_current_working_dir()),
+        force_unlock,
+    )?;
+
     if env.target == Target::Docker.to_string() {
         return docker_apply(verbose, instance_settings);
     } else if env.target == Target::TemboCloud.to_string() {
@@ -256,7 +256,7 @@ async fn get_instance_namespace(
 
 pub fn execute(verbose: bool, top_command: TopCommand) -> Result<(), anyhow::Error> {
     println!("WARNING! EXPERIMENTAL FEATURE!!");
-    super::validate::execute(verbose)?;
+    super::validate::execute(verbose, false)?;
     let env = get_current_context().context("Failed to get current context")?;
     let profile = env
         .selected_profile
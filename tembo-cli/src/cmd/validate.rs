@@ -1,10 +1,11 @@
+use crate::cli::compatibility::{migrate, CompatibilityTable};
 use crate::cli::context::{
     list_context, list_credential_profiles, tembo_context_file_path, tembo_credentials_file_path,
     Target,
 };
 use crate::cli::file_utils::FileUtils;
 use crate::cli::tembo_config::InstanceSettings;
-use crate::tui::{self, error, info, white_confirmation};
+use crate::tui::{self, error, info, warning, white_confirmation};
 use anyhow::Error;
 use clap::Args;
 use std::{collections::HashMap, fs, path::Path, str::FromStr};
@@ -12,9 +13,13 @@ use tembo::cli::context::get_current_context;
 
 /// Validates the tembo.toml file, context file, etc.
 #[derive(Args)]
-pub struct ValidateCommand {}
+pub struct ValidateCommand {
+    /// Rewrite deprecated fields and stack names in tembo.toml to their new form
+    #[clap(long)]
+    pub fix: bool,
+}
 
-pub fn execute(verbose: bool) -> Result<(), anyhow::Error> {
+pub fn execute(verbose: bool, fix: bool) -> Result<(), anyhow::Error> {
     let mut has_error = false;
 
     if !Path::new(&tembo_context_file_path()).exists() {
@@ -50,7 +55,16 @@ pub fn execute(verbose: bool) -> Result<(), anyhow::Error> {
         let mut file_path = FileUtils::get_current_working_dir();
         file_path.push_str("/tembo.toml");
 
-        let contents = fs::read_to_string(&file_path)?;
+        let mut contents = fs::read_to_string(&file_path)?;
+        match lint_deprecations(&file_path, &contents, fix) {
+            Ok(Some(fixed)) => contents = fixed,
+            Ok(None) => (),
+            Err(e) => {
+                error(&format!("{}", e));
+                has_error = true;
+            }
+        }
+
         let config: Result<HashMap<String, InstanceSettings>, toml::de::Error> =
             toml::from_str(&contents);
 
@@ -100,6 +114,46 @@ pub fn execute(verbose: bool) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Detects deprecated fields and stack names in tembo.toml. With `fix`, the file is
+// rewritten and the new contents returned, otherwise the deprecations are only reported as
+// warnings, so commands running the validation keep working on older files.
+fn lint_deprecations(
+    file_path: &str,
+    contents: &str,
+    fix: bool,
+) -> Result<Option<String>, anyhow::Error> {
+    let table = CompatibilityTable::load()?;
+    let mut doc: toml_edit::DocumentMut = match contents.parse() {
+        Ok(doc) => doc,
+        // parse errors are reported when the config is deserialized
+        Err(_) => return Ok(None),
+    };
+
+    let migrations = migrate(&mut doc, &table);
+    if migrations.is_empty() {
+        return Ok(None);
+    }
+
+    if !fix {
+        for migration in migrations.iter() {
+            warning(&migration.to_string());
+        }
+        warning("Deprecated configuration found in tembo.toml, run `tembo validate --fix` to migrate it");
+        return Ok(None);
+    }
+
+    let fixed = doc.to_string();
+    fs::write(file_path, &fixed)?;
+    info(&format!(
+        "Migrated {} deprecated setting(s) in tembo.toml:",
+        migrations.len()
+    ));
+    for migration in migrations.iter() {
+        info(&format!("  - {}", migration));
+    }
+    Ok(Some(fixed))
+}
+
 fn validate_support(config: &HashMap<String, InstanceSettings>) -> Result<(), anyhow::Error> {
     for settings in config.values() {
         validate_stack_support(settings, 14, "VectorDB")?;
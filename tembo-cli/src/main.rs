@@ -70,10 +70,11 @@ fn main() -> Result<(), anyhow::Error> {
                 app.global_opts.verbose,
                 _apply_cmd.merge.clone(),
                 _apply_cmd.set.clone(),
+                _apply_cmd.force_unlock,
             )?;
         }
         SubCommands::Validate(_validate_cmd) => {
-            validate::execute(app.global_opts.verbose)?;
+            validate::execute(app.global_opts.verbose, _validate_cmd.fix)?;
         }
         SubCommands::Logs(_logs_cmd) => {
             logs::execute(_logs_cmd)?;
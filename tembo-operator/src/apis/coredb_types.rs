@@ -1,6 +1,7 @@
 use crate::{
     apis::postgres_parameters::{
-        merge_pg_configs, ConfigValue, MergeError, PgConfig, DISALLOWED_CONFIGS, MULTI_VAL_CONFIGS,
+        merge_pg_configs, ConfigValue, InvalidPgConfig, MergeError, PgConfig, DISALLOWED_CONFIGS,
+        MULTI_VAL_CONFIGS,
     },
     app_service::types::AppService,
     defaults,
@@ -322,6 +323,19 @@ pub struct Backup {
     #[serde(default = "defaults::default_encryption")]
     pub encryption: Option<String>,
 
+    /// The Cloud KMS key to encrypt backups in Google Cloud Storage with, for customers
+    /// requiring customer-managed encryption keys (CMEK), e.g.
+    /// `projects/<project>/locations/<location>/keyRings/<key ring>/cryptoKeys/<key>`.
+    /// Only used when `destinationPath` is a `gs://` path.
+    ///
+    /// **Default**: `None`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "kmsKeyName"
+    )]
+    pub kms_key_name: Option<String>,
+
     /// The number of days to retain backups for
     #[serde(default = "defaults::default_retention_policy")]
     pub retentionPolicy: Option<String>,
@@ -352,6 +366,97 @@ pub struct Backup {
         rename = "volumeSnapshot"
     )]
     pub volume_snapshot: Option<VolumeSnapshot>,
+
+    /// Take and wait for an on-demand backup before a destructive change is applied to the
+    /// instance, such as a Postgres major version upgrade or a storage class migration.
+    /// A failed backup is taken again twice, after that the change waits until this is set
+    /// to `false`.
+    ///
+    /// **Default**: `true`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "preOperationBackup"
+    )]
+    pub pre_operation_backup: Option<bool>,
+
+    /// A second object store the base backups and WAL archive are copied to, for example a
+    /// bucket in another region, so the instance can still be recovered when the object
+    /// store at `destinationPath` is lost.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "additionalDestination"
+    )]
+    pub additional_destination: Option<AdditionalBackupDestination>,
+}
+
+/// AdditionalBackupDestination is a second object store the backups of an instance are
+/// copied to. Everything written to `spec.backup.destinationPath` is copied on a schedule,
+/// and nothing is deleted from the additional destination, so configure retention with a
+/// lifecycle rule on its bucket.
+///
+/// **Example**: Copy backups kept in `us-east-1` to a bucket in `us-west-2`
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///  name: test-db
+/// spec:
+///   backup:
+///     destinationPath: s3://my-bucket-us-east-1/my-backups
+///     s3Credentials:
+///       inheritFromIAMRole: true
+///     additionalDestination:
+///       destinationPath: s3://my-bucket-us-west-2/my-backups
+///       s3Credentials:
+///         inheritFromIAMRole: true
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct AdditionalBackupDestination {
+    /// The object storage path to copy backups to, backups are kept under
+    /// `<destinationPath>/<instance name>` like in the primary destination
+    #[serde(rename = "destinationPath")]
+    pub destination_path: String,
+
+    /// The S3 compatable endpoint URL
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "endpointURL"
+    )]
+    pub endpoint_url: Option<String>,
+
+    /// The S3 credentials to use for the additional destination (if not using IAM Role)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "s3Credentials"
+    )]
+    pub s3_credentials: Option<S3Credentials>,
+
+    /// The Google Cloud credentials to use for the additional destination
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "googleCredentials"
+    )]
+    pub google_credentials: Option<GoogleCredentials>,
+
+    /// The Azure credentials to use for the additional destination
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "azureCredentials"
+    )]
+    pub azure_credentials: Option<AzureCredentials>,
+
+    /// How often new backups and WAL files are copied, set with cron syntax
+    ///
+    /// **Default**: `*/5 * * * *`
+    #[serde(default = "defaults::default_additional_destination_schedule")]
+    pub schedule: String,
 }
 
 /// Restore configuration provides a way to restore a database from a backup
@@ -391,6 +496,10 @@ pub struct Restore {
     /// **Example**: If you have an instance with `spec.backup.destinationPath`
     /// set to `s3://my-bucket/v2/test-db` then you would set `backupsPath` to `s3://my-bucket/v2/test-db`.
     /// And backups are saved in that bucket under `s3://my-bucket/v2/test-db/server_name`
+    ///
+    /// The path may point to another cloud provider than the one this instance runs on,
+    /// for example `s3://` from an instance on GCP. Only the credentials for the provider
+    /// of this path are used, so set `s3Credentials`, `googleCredentials` or `azureCredentials` accordingly.
     #[serde(rename = "backupsPath")]
     pub backups_path: Option<String>,
 
@@ -415,8 +524,219 @@ pub struct Restore {
     pub azure_credentials: Option<AzureCredentials>,
 
     /// volumeSnapshot is a boolean to enable restoring from a Volume Snapshot
+    /// of the instance named in `serverName`, instead of replaying WAL from object storage
+    /// from a base backup. The most recent snapshot is used, or the closest one before
+    /// `recoveryTargetTime`.
     #[serde(rename = "volumeSnapshot")]
     pub volume_snapshot: Option<bool>,
+
+    /// volumeSnapshotName is the name of the VolumeSnapshot of the instance named in
+    /// `serverName` to restore from, instead of the most recent one. Implies `volumeSnapshot`.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "volumeSnapshotName"
+    )]
+    pub volume_snapshot_name: Option<String>,
+
+    /// sample reduces the restored instance to the schema plus a sample of rows
+    /// for each table, to create small staging copies of large instances.
+    ///
+    /// **Default**: disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<RestoreSample>,
+
+    /// partial keeps only part of the restored instance, e.g. a single schema or only the
+    /// table definitions, for when only some of a large backup is needed.
+    ///
+    /// **Default**: disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialRestore>,
+}
+
+impl Restore {
+    /// Whether the instance is bootstrapped from a VolumeSnapshot
+    pub fn uses_volume_snapshot(&self) -> bool {
+        self.volume_snapshot == Some(true) || self.volume_snapshot_name.is_some()
+    }
+}
+
+/// StandbyOf makes the instance a standby of another instance, replaying the WAL archived
+/// in the other instance's backups. This uses the [replica cluster](https://cloudnative-pg.io/documentation/1.20/replica_cluster/)
+/// feature of cloudnative-pg, to keep a warm copy for disaster recovery across clusters
+/// or regions.
+///
+/// Setting `promote` turns the standby into a primary accepting writes. Promotion can not be
+/// undone, keep `standbyOf` in place with `promote: true` afterwards.
+///
+/// **Example**: A standby of `test-db`, which keeps its backups in `s3://my-bucket/v2/test-db`
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db-standby
+/// spec:
+///   standbyOf:
+///     serverName: test-db
+///     backupsPath: s3://my-bucket/v2/test-db
+///     s3Credentials:
+///       inheritFromIAMRole: true
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct StandbyOf {
+    /// The name of the instance to follow, as named in its backups
+    #[serde(rename = "serverName")]
+    pub server_name: String,
+
+    /// The object storage path of the backups of the instance to follow. Defaults to
+    /// `serverName` next to the backups of this instance.
+    #[serde(default, rename = "backupsPath")]
+    pub backups_path: Option<String>,
+
+    /// endpointURL is the S3 compatible endpoint URL
+    #[serde(default, rename = "endpointURL")]
+    pub endpoint_url: Option<String>,
+
+    /// s3Credentials is the S3 credentials to use to read the backups.
+    #[serde(default, rename = "s3Credentials")]
+    pub s3_credentials: Option<S3Credentials>,
+
+    /// googleCredentials is the Google Cloud credentials to use to read the backups.
+    #[serde(default, rename = "googleCredentials")]
+    pub google_credentials: Option<GoogleCredentials>,
+
+    /// azureCredentials is the Azure credentials to use to read the backups.
+    #[serde(default, rename = "azureCredentials")]
+    pub azure_credentials: Option<AzureCredentials>,
+
+    /// Promote the standby to a primary, stopping the replay of WAL.
+    ///
+    /// **Default**: false
+    #[serde(default)]
+    pub promote: bool,
+}
+
+impl StandbyOf {
+    /// The restore the standby is bootstrapped from, before it keeps replaying WAL
+    pub fn to_restore(&self) -> Restore {
+        Restore {
+            server_name: self.server_name.clone(),
+            backups_path: self.backups_path.clone(),
+            endpoint_url: self.endpoint_url.clone(),
+            s3_credentials: self.s3_credentials.clone(),
+            google_credentials: self.google_credentials.clone(),
+            azure_credentials: self.azure_credentials.clone(),
+            ..Restore::default()
+        }
+    }
+}
+
+/// RestoreSample trims the tables of a restored instance down to a maximum number
+/// of rows, once the restore has completed. Sampling runs only once per instance,
+/// as recorded in `status.restore_sampled_at`. Each table is sampled in its own
+/// transaction and the database is vacuumed afterwards. Rows are removed without
+/// regard for foreign keys, which are not re-validated, so referential integrity
+/// is not guaranteed in the sampled copy.
+///
+/// **Example**: Keep at most 1000 rows per table, only the schema for `public.audit_log`
+/// and all rows of `public.countries`
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db-staging
+/// spec:
+///   restore:
+///     serverName: test-db
+///     sample:
+///       defaultRowLimit: 1000
+///       tables:
+///         public.audit_log: 0
+///         public.countries: -1
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct RestoreSample {
+    /// The database to sample.
+    ///
+    /// **Default**: postgres
+    #[serde(default = "defaults::default_restore_sample_database")]
+    pub database: String,
+
+    /// The maximum number of rows to keep for tables not listed in `tables`.
+    ///
+    /// **Default**: all rows are kept
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "defaultRowLimit"
+    )]
+    pub default_row_limit: Option<i64>,
+
+    /// The maximum number of rows to keep per table, keyed by table name.
+    /// Names without a schema refer to the `public` schema. A limit of `0` keeps
+    /// only the table definition, and a negative limit keeps all rows.
+    ///
+    /// **Default**: disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tables: Option<BTreeMap<String, i64>>,
+}
+
+/// PartialRestore removes the tables which are not needed from a restored instance, once the
+/// restore has completed. It runs once per instance in a Job, so long running removals do not
+/// hold up the reconciliation of the instance.
+///
+/// **Example**: Keep only the `sales` schema, without the `sales.audit_log` table
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db-sales
+/// spec:
+///   restore:
+///     serverName: test-db
+///     partial:
+///       includeTables:
+///         - sales.*
+///       excludeTables:
+///         - sales.audit_log
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct PartialRestore {
+    /// The database to restore partially.
+    ///
+    /// **Default**: postgres
+    #[serde(default = "defaults::default_restore_sample_database")]
+    pub database: String,
+
+    /// Keep only the table definitions, removing all rows.
+    ///
+    /// **Default**: false
+    #[serde(default, rename = "schemaOnly")]
+    pub schema_only: bool,
+
+    /// The tables to keep, all others are dropped. Names without a schema refer to the
+    /// `public` schema, and `*` matches any characters, e.g. `sales.*`.
+    ///
+    /// **Default**: all tables are kept
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "includeTables"
+    )]
+    pub include_tables: Option<Vec<String>>,
+
+    /// The tables to drop, with the same patterns as `includeTables`.
+    ///
+    /// **Default**: no tables are dropped
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "excludeTables"
+    )]
+    pub exclude_tables: Option<Vec<String>>,
 }
 
 /// A connection pooler is a tool used to manage database connections, sitting
@@ -515,6 +835,85 @@ pub struct DedicatedNetworking {
     /// **Default**: LoadBalancer.
     #[serde(default = "defaults::default_service_type")]
     pub serviceType: String,
+
+    /// Provider-specific load balancer settings, for pinning stable endpoints
+    /// to the dedicated load balancer.
+    ///
+    /// **Default**: disabled
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "loadBalancer"
+    )]
+    pub load_balancer: Option<DedicatedNetworkingLoadBalancer>,
+}
+
+/// DedicatedNetworkingLoadBalancer configures the cloud provider load balancer
+/// created for dedicated networking, such as pinning a private endpoint for
+/// VPC peering.
+///
+/// **Example**: An internal AWS NLB with fixed private IP addresses
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   dedicatedNetworking:
+///     enabled: true
+///     public: false
+///     loadBalancer:
+///       subnets:
+///         - subnet-0a1b2c3d
+///         - subnet-4e5f6a7b
+///       privateIpAddresses:
+///         - 10.0.1.10
+///         - 10.0.2.10
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct DedicatedNetworkingLoadBalancer {
+    /// The subnet IDs or names to place the load balancer in (AWS).
+    ///
+    /// **Default**: subnets are auto-discovered by the load balancer controller
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subnets: Option<Vec<String>>,
+
+    /// Elastic IP allocation IDs for a public load balancer, one per subnet (AWS).
+    /// Ignored when the load balancer is private.
+    ///
+    /// **Default**: disabled
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "eipAllocations"
+    )]
+    pub eip_allocations: Option<Vec<String>>,
+
+    /// Private IPv4 addresses for a private load balancer, one per subnet (AWS).
+    /// Ignored when the load balancer is public.
+    ///
+    /// **Default**: disabled
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "privateIpAddresses"
+    )]
+    pub private_ip_addresses: Option<Vec<String>>,
+
+    /// A reserved static IP address for the load balancer, set as the Service
+    /// `loadBalancerIP` for providers that support it (GCP, Azure).
+    ///
+    /// **Default**: disabled
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "staticIp")]
+    pub static_ip: Option<String>,
+
+    /// Additional provider-specific annotations to add to the load balancer Service.
+    /// Annotations set by the operator take precedence.
+    ///
+    /// **Default**: disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
 }
 
 impl DedicatedNetworking {
@@ -523,6 +922,69 @@ impl DedicatedNetworking {
     }
 }
 
+/// CoreDBMetadata defines labels and annotations that the operator stamps on
+/// every child resource it creates for the instance, such as the CNPG Cluster,
+/// Services, Secrets, PodMonitors, appService Deployments and ingress objects.
+/// This allows cost-allocation and policy engines to track per-instance resources.
+///
+/// Labels and annotations set by the operator always take precedence, so these
+/// can never override selectors or operator managed keys.
+///
+/// **Example**:
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   metadata:
+///     commonLabels:
+///       cost-center: engineering
+///     commonAnnotations:
+///       owner: data-team@example.com
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct CoreDBMetadata {
+    /// Labels to add to all child resources
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "commonLabels"
+    )]
+    pub common_labels: Option<BTreeMap<String, String>>,
+
+    /// Annotations to add to all child resources
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "commonAnnotations"
+    )]
+    pub common_annotations: Option<BTreeMap<String, String>>,
+}
+
+impl CoreDBMetadata {
+    /// Stamp the common labels and annotations onto the metadata of a child resource.
+    /// Keys already present in the metadata are left untouched.
+    pub fn apply(&self, meta: &mut ObjectMeta) {
+        if let Some(common_labels) = self.common_labels.as_ref().filter(|l| !l.is_empty()) {
+            let labels = meta.labels.get_or_insert_with(BTreeMap::new);
+            for (key, value) in common_labels {
+                labels.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        if let Some(common_annotations) = self.common_annotations.as_ref().filter(|a| !a.is_empty())
+        {
+            let annotations = meta.annotations.get_or_insert_with(BTreeMap::new);
+            for (key, value) in common_annotations {
+                annotations
+                    .entry(key.clone())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+    }
+}
+
 /// Generate the Kubernetes wrapper struct `CoreDB` from our Spec and Status struct
 ///
 /// This provides a hook for generating the CRD yaml (in crdgen.rs)
@@ -666,6 +1128,11 @@ pub struct CoreDBSpec {
     /// The list of domains to add to the IngressRouteTCP generated in the
     /// tembo-controller to route traffic to the Postgres instance using SNI
     /// based routing of encrypted TLS traffic into the correct instance.
+    /// When operator-managed certificates are used, these domains are also
+    /// added to the server certificate as subject alternative names.
+    /// Wildcards such as `*.example.com` are not supported, as `HostSNI`
+    /// only matches exact names. Entries that are not valid DNS names are
+    /// ignored.
     ///
     /// **Default**: disabled
     pub extra_domains_rw: Option<Vec<String>>,
@@ -758,9 +1225,238 @@ pub struct CoreDBSpec {
     /// **Default**: `None`
     #[serde(rename = "topologySpreadConstraints")]
     pub topology_spread_constraints: Option<Vec<ClusterTopologySpreadConstraints>>,
+
+    /// Labels and annotations to propagate to all child resources created for
+    /// the instance.
+    ///
+    /// **Default**: `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<CoreDBMetadata>,
+
+    /// The name of a resource profile defined in the operator configuration, providing the
+    /// resources, storage and Postgres configuration of the instance. Values set in the spec
+    /// that differ from the defaults take precedence over the profile.
+    ///
+    /// **Default**: `None`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "resourceProfile"
+    )]
+    pub resource_profile: Option<String>,
+
+    /// Pause individual components of the instance while the others keep running, e.g. only
+    /// the appServices or only the pooler. Setting `stop` pauses all components.
+    ///
+    /// **Default**: `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused: Option<PausedComponents>,
+
+    /// Delete the instance this long after it was created, e.g. `30m`, `12h` or `7d`, for
+    /// short lived instances like CI previews.
+    ///
+    /// **Default**: `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+
+    /// Restart the instance on a schedule set with cron syntax, in UTC, e.g. `0 3 * * 0` for
+    /// weekly restarts. Replicas are restarted first, then the primary after a switchover.
+    ///
+    /// **Default**: `None`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "restartSchedule"
+    )]
+    pub restart_schedule: Option<String>,
+
+    /// SQL scripts from ConfigMaps or Secrets to run once after the instance is initialized,
+    /// e.g. to create schemas, roles and grants. Scripts run in order, each one only once:
+    /// applied scripts are recorded in `status.bootstrap_sql_applied` by name.
+    ///
+    /// **Default**: `None`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "bootstrapSQL"
+    )]
+    pub bootstrap_sql: Option<Vec<BootstrapSql>>,
+
+    /// Connection and resource limits of Postgres roles, applied with `ALTER ROLE`, to keep a
+    /// single application user from exhausting a shared instance. Limits which are not set are
+    /// reset to the instance defaults. Roles which do not exist are skipped.
+    ///
+    /// **Default**: `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<RolePolicy>>,
+
+    /// Run the instance as a standby of another instance, which may be in another
+    /// cluster or region, continuously replaying WAL from that instance's backups.
+    /// The standby is read only until it is promoted.
+    ///
+    /// **Default**: disabled
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "standbyOf")]
+    pub standby_of: Option<StandbyOf>,
+
+    /// Mirror the connection secrets generated for the instance into the external secret
+    /// manager configured for the operator, e.g. AWS Secrets Manager or GCP Secret Manager,
+    /// for applications which can not read Kubernetes secrets. The secrets are removed from
+    /// the secret manager when this is disabled or the instance is deleted.
+    ///
+    /// **Default**: false
+    #[serde(default, rename = "mirrorSecrets")]
+    pub mirror_secrets: bool,
+}
+
+/// BootstrapSql references a SQL script to run once after the instance is initialized
+///
+/// **Example**: Create a schema from the `init.sql` key of the `app-schema` ConfigMap
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   bootstrapSQL:
+///     - name: app-schema
+///       database: app
+///       configMapRef:
+///         name: app-schema
+///         key: init.sql
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct BootstrapSql {
+    /// The name identifying the script, a script is not run again once a script of this
+    /// name was applied
+    pub name: String,
+
+    /// The database to run the script in
+    ///
+    /// **Default**: `postgres`
+    #[serde(default = "defaults::default_bootstrap_sql_database")]
+    pub database: String,
+
+    /// A key of a ConfigMap in the namespace of the instance holding the script
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "configMapRef"
+    )]
+    pub config_map_ref: Option<BootstrapSqlKeyRef>,
+
+    /// A key of a Secret in the namespace of the instance holding the script, for scripts
+    /// containing credentials
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "secretRef")]
+    pub secret_ref: Option<BootstrapSqlKeyRef>,
+}
+
+/// BootstrapSqlKeyRef is the type for the reference to a key of a ConfigMap or Secret
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct BootstrapSqlKeyRef {
+    /// The key to select
+    pub key: String,
+    /// Name of the referent.
+    pub name: String,
+}
+
+/// RolePolicy limits the connections and resources of a Postgres role
+///
+/// **Example**: Limit the `app` role to 20 connections and 30 second statements
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   roles:
+///     - name: app
+///       connectionLimit: 20
+///       statementTimeout: 30s
+///       workMem: 16MB
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct RolePolicy {
+    /// The name of the role
+    pub name: String,
+
+    /// The maximum number of concurrent connections of the role
+    ///
+    /// **Default**: unlimited
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "connectionLimit"
+    )]
+    pub connection_limit: Option<i32>,
+
+    /// The `statement_timeout` of the role's sessions, e.g. `30s`
+    ///
+    /// **Default**: the instance's `statement_timeout`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "statementTimeout"
+    )]
+    pub statement_timeout: Option<String>,
+
+    /// The `work_mem` of the role's sessions, e.g. `16MB`
+    ///
+    /// **Default**: the instance's `work_mem`
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "workMem")]
+    pub work_mem: Option<String>,
+}
+
+/// PausedComponents lists the components of an instance to pause
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct PausedComponents {
+    /// The components to pause
+    #[serde(default)]
+    pub components: Vec<PausableComponent>,
+}
+
+/// A component of an instance which can be paused on its own
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PausableComponent {
+    /// The appService deployments are scaled to 0
+    AppServices,
+    /// The pooler is scaled to 0
+    Pooler,
+    /// The Postgres cluster is hibernated
+    Postgres,
 }
 
 impl CoreDBSpec {
+    /// Whether the instance is a standby which has not been promoted, and so is read only
+    pub fn is_standby(&self) -> bool {
+        self.standby_of
+            .as_ref()
+            .is_some_and(|standby_of| !standby_of.promote)
+    }
+
+    /// Whether a component is paused, on its own or because the instance is stopped
+    pub fn is_paused(&self, component: PausableComponent) -> bool {
+        self.stop
+            || self
+                .paused
+                .as_ref()
+                .is_some_and(|paused| paused.components.contains(&component))
+    }
+
+    /// The paused components of the instance, all of them when it is stopped
+    pub fn paused_components(&self) -> Vec<PausableComponent> {
+        [
+            PausableComponent::AppServices,
+            PausableComponent::Pooler,
+            PausableComponent::Postgres,
+        ]
+        .into_iter()
+        .filter(|component| self.is_paused(*component))
+        .collect()
+    }
+
     // extracts all Postgres configurations
     // configs can be defined in several different places (from a stack, user override, from an extension installation, user overrides, etc)
     pub fn get_pg_configs(
@@ -881,6 +1577,14 @@ impl CoreDBSpec {
         }
     }
 
+    /// Stamp the common labels and annotations from `spec.metadata`, if any, onto
+    /// the metadata of a child resource.
+    pub fn apply_common_metadata(&self, meta: &mut ObjectMeta) {
+        if let Some(metadata) = &self.metadata {
+            metadata.apply(meta);
+        }
+    }
+
     pub fn get_pg_config_by_name(
         &self,
         config_name: &str,
@@ -913,6 +1617,130 @@ pub struct CoreDBStatus {
     #[deprecated(note = "This field is deprecated and it is no longer used")]
     pub last_fully_reconciled_at: Option<DateTime<Utc>>,
     pub last_archiver_status: Option<DateTime<Utc>>,
+    /// The on-demand backup taken before the most recent destructive change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_operation_backup: Option<PreOperationBackup>,
+    /// Set while the instance is read only because its data volume is almost full
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_full_protection: Option<DiskFullProtection>,
+    /// The copies of backups to `spec.backup.additionalDestination`, reported apart from
+    /// `last_archiver_status` which covers the primary destination
+    #[serde(default)]
+    pub additional_backup_destination: Option<AdditionalBackupDestinationStatus>,
+    /// Since when the instance has had no client connections, while idle hibernation is enabled
+    #[serde(default)]
+    pub idle_since: Option<DateTime<Utc>>,
+    /// The components of the instance which are paused, None when all are running
+    #[serde(default)]
+    pub paused_components: Option<Vec<PausableComponent>>,
+    /// When the instance will be deleted because its `spec.ttl` expires
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The names of the `spec.bootstrapSQL` scripts which were applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bootstrap_sql_applied: Option<Vec<String>>,
+    /// The object storage used by the backups of the instance, while storage usage reporting
+    /// is enabled in the operator
+    #[serde(default)]
+    pub object_storage_usage: Option<ObjectStorageUsage>,
+    /// The extensions and databases where the `post_install_sql` of the extension was applied,
+    /// as `<extension>/<database>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install_sql_applied: Option<Vec<String>>,
+    /// The settings of `spec.runtime_config` which were left out of the Postgres configuration
+    /// because their value is not valid for the Postgres version, None when all are valid
+    #[serde(default)]
+    pub invalid_runtime_config: Option<Vec<InvalidPgConfig>>,
+    /// What the spec asks for which is not available for the Postgres version and left out,
+    /// None when everything is available
+    #[serde(default)]
+    pub unsupported_by_postgres_version: Option<Vec<String>>,
+    /// When the restored instance was trimmed down to `spec.restore.sample`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore_sampled_at: Option<DateTime<Utc>>,
+    /// The progress of `spec.restore.partial`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_restore: Option<PartialRestoreStatus>,
+}
+
+/// AdditionalBackupDestinationStatus reports on the copies of backups to the additional
+/// backup destination
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct AdditionalBackupDestinationStatus {
+    /// The object storage path backups are copied to
+    pub destination_path: String,
+
+    /// When the most recent copy was started
+    pub last_copy_scheduled: Option<DateTime<Utc>>,
+
+    /// When the most recent successful copy completed
+    pub last_successful_copy: Option<DateTime<Utc>>,
+}
+
+/// ObjectStorageUsage reports the size of the backups of the instance in object storage
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct ObjectStorageUsage {
+    /// The object storage path of the backups
+    pub destination_path: String,
+
+    /// The size of the base backups in bytes
+    pub base_backups_bytes: u64,
+
+    /// The size of the WAL archive in bytes
+    pub wal_bytes: u64,
+
+    /// When the size was measured
+    pub measured_at: DateTime<Utc>,
+}
+
+/// DiskFullProtection records that the instance was made read only to keep its data volume
+/// from filling up completely
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct DiskFullProtection {
+    /// When the instance was made read only
+    pub since: DateTime<Utc>,
+
+    /// The data volume usage in percent which triggered the protection
+    pub usage_percent: u8,
+
+    /// The storage size requested as an emergency increase, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_increased_to: Option<Quantity>,
+}
+
+/// PreOperationBackup records the recovery point taken before a destructive change
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct PreOperationBackup {
+    /// The destructive change the backup was taken before, e.g. `major upgrade 15 to 16`
+    pub operation: String,
+
+    /// The name of the CNPG Backup
+    pub backup_name: String,
+
+    /// The ID of the Barman backup, set once the backup completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_id: Option<String>,
+
+    /// The backup completed and the destructive change may proceed
+    #[serde(default)]
+    pub completed: bool,
+
+    /// The number of times a failed backup was taken again for the change
+    #[serde(default)]
+    pub retries: u32,
+}
+
+/// PartialRestoreStatus records the Job removing the tables which are not needed from a
+/// restored instance
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct PartialRestoreStatus {
+    /// When the Job completed, it is never run again after that
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+
+    /// The number of times a failed Job was created again
+    #[serde(default)]
+    pub retries: u32,
 }
 
 #[cfg(test)]
@@ -1182,4 +2010,87 @@ mod tests {
 
         let _deserialized_spec: CoreDBSpec = serde_json::from_str(json_str).unwrap();
     }
+
+    #[test]
+    fn test_apply_common_metadata() {
+        let spec: CoreDBSpec = serde_json::from_value(serde_json::json!({
+            "metadata": {
+                "commonLabels": {
+                    "cost-center": "engineering",
+                    "app": "should-not-override"
+                },
+                "commonAnnotations": {
+                    "owner": "data-team"
+                }
+            }
+        }))
+        .unwrap();
+
+        let mut meta = ObjectMeta {
+            labels: Some(BTreeMap::from([("app".to_string(), "coredb".to_string())])),
+            ..ObjectMeta::default()
+        };
+        spec.apply_common_metadata(&mut meta);
+
+        let labels = meta.labels.unwrap();
+        assert_eq!(labels.get("app"), Some(&"coredb".to_string()));
+        assert_eq!(labels.get("cost-center"), Some(&"engineering".to_string()));
+        assert_eq!(
+            meta.annotations.unwrap().get("owner"),
+            Some(&"data-team".to_string())
+        );
+
+        // Without spec.metadata, nothing is added
+        let mut meta = ObjectMeta::default();
+        CoreDBSpec::default().apply_common_metadata(&mut meta);
+        assert!(meta.labels.is_none());
+        assert!(meta.annotations.is_none());
+    }
+
+    #[test]
+    fn test_paused_components() {
+        let mut spec: CoreDBSpec = serde_json::from_value(serde_json::json!({
+            "paused": {
+                "components": ["appServices", "pooler"]
+            }
+        }))
+        .unwrap();
+        assert!(spec.is_paused(PausableComponent::AppServices));
+        assert!(spec.is_paused(PausableComponent::Pooler));
+        assert!(!spec.is_paused(PausableComponent::Postgres));
+        assert_eq!(
+            spec.paused_components(),
+            vec![PausableComponent::AppServices, PausableComponent::Pooler]
+        );
+
+        // Stopping the instance pauses every component
+        spec.stop = true;
+        assert_eq!(spec.paused_components().len(), 3);
+
+        assert!(CoreDBSpec::default().paused_components().is_empty());
+    }
+
+    #[test]
+    fn test_bootstrap_sql() {
+        let spec: CoreDBSpec = serde_json::from_value(serde_json::json!({
+            "bootstrapSQL": [
+                {
+                    "name": "schema",
+                    "configMapRef": {"name": "app-schema", "key": "init.sql"}
+                },
+                {
+                    "name": "roles",
+                    "database": "app",
+                    "secretRef": {"name": "app-roles", "key": "roles.sql"}
+                }
+            ]
+        }))
+        .unwrap();
+        let scripts = spec.bootstrap_sql.unwrap();
+        assert_eq!(scripts[0].database, "postgres");
+        assert_eq!(scripts[0].config_map_ref.as_ref().unwrap().key, "init.sql");
+        assert!(scripts[0].secret_ref.is_none());
+        assert_eq!(scripts[1].database, "app");
+        assert_eq!(scripts[1].secret_ref.as_ref().unwrap().name, "app-roles");
+    }
 }
@@ -400,12 +400,553 @@ impl<'de> Deserialize<'de> for PgConfig {
     }
 }
 
+/// InvalidPgConfig is a configuration setting which was left out of the Postgres
+/// configuration because its value is not valid for the Postgres version of the instance
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ToSchema)]
+pub struct InvalidPgConfig {
+    /// The name of the Postgres configuration parameter
+    pub name: String,
+
+    /// The value as set in the spec
+    pub value: String,
+
+    /// Why the value was rejected
+    pub reason: String,
+}
+
+// The type and valid values of a configuration parameter, with memory and time limits in the
+// parameter's base unit, like pg_settings reports them
+#[derive(Debug)]
+enum GucKind {
+    Bool,
+    Integer { min: i64, max: i64 },
+    Real { min: f64, max: f64 },
+    // unit is the size of the base unit in kB
+    Memory { unit: i64, min: i64, max: i64 },
+    // unit is the length of the base unit in microseconds
+    Time { unit: i64, min: i64, max: i64 },
+    Enum(&'static [&'static str]),
+}
+
+#[derive(Debug)]
+struct GucSpec {
+    name: &'static str,
+    // The first Postgres major version the spec applies to, for parameters whose type or
+    // values changed between versions
+    since: i32,
+    kind: GucKind,
+}
+
+const INT_MAX: i64 = i32::MAX as i64;
+const MS: i64 = 1_000;
+const S: i64 = 1_000_000;
+
+const fn guc(name: &'static str, kind: GucKind) -> GucSpec {
+    GucSpec {
+        name,
+        since: 0,
+        kind,
+    }
+}
+
+// The commonly tuned parameters, as found in pg_settings. Parameters which are not in the
+// catalog, like the ones of extensions, are passed to Postgres as they are.
+const GUC_CATALOG: &[GucSpec] = &[
+    guc("autovacuum", GucKind::Bool),
+    guc(
+        "autovacuum_analyze_scale_factor",
+        GucKind::Real {
+            min: 0.0,
+            max: 100.0,
+        },
+    ),
+    guc(
+        "autovacuum_max_workers",
+        GucKind::Integer {
+            min: 1,
+            max: 262143,
+        },
+    ),
+    guc(
+        "autovacuum_naptime",
+        GucKind::Time {
+            unit: S,
+            min: 1,
+            max: 2147483,
+        },
+    ),
+    guc(
+        "autovacuum_vacuum_cost_limit",
+        GucKind::Integer {
+            min: -1,
+            max: 10000,
+        },
+    ),
+    guc(
+        "autovacuum_vacuum_scale_factor",
+        GucKind::Real {
+            min: 0.0,
+            max: 100.0,
+        },
+    ),
+    guc(
+        "autovacuum_work_mem",
+        GucKind::Memory {
+            unit: 1,
+            min: -1,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "checkpoint_completion_target",
+        GucKind::Real { min: 0.0, max: 1.0 },
+    ),
+    guc(
+        "checkpoint_timeout",
+        GucKind::Time {
+            unit: S,
+            min: 30,
+            max: 86400,
+        },
+    ),
+    guc(
+        "default_statistics_target",
+        GucKind::Integer { min: 1, max: 10000 },
+    ),
+    guc(
+        "default_transaction_isolation",
+        GucKind::Enum(&[
+            "serializable",
+            "repeatable read",
+            "read committed",
+            "read uncommitted",
+        ]),
+    ),
+    guc(
+        "effective_cache_size",
+        GucKind::Memory {
+            unit: 8,
+            min: 1,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "effective_io_concurrency",
+        GucKind::Integer { min: 0, max: 1000 },
+    ),
+    guc("huge_pages", GucKind::Enum(&["off", "on", "try"])),
+    guc(
+        "idle_in_transaction_session_timeout",
+        GucKind::Time {
+            unit: MS,
+            min: 0,
+            max: INT_MAX,
+        },
+    ),
+    guc("jit", GucKind::Bool),
+    guc(
+        "lock_timeout",
+        GucKind::Time {
+            unit: MS,
+            min: 0,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "log_min_duration_statement",
+        GucKind::Time {
+            unit: MS,
+            min: -1,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "log_statement",
+        GucKind::Enum(&["none", "ddl", "mod", "all"]),
+    ),
+    guc(
+        "maintenance_work_mem",
+        GucKind::Memory {
+            unit: 1,
+            min: 1024,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "max_connections",
+        GucKind::Integer {
+            min: 1,
+            max: 262143,
+        },
+    ),
+    guc(
+        "max_parallel_maintenance_workers",
+        GucKind::Integer { min: 0, max: 1024 },
+    ),
+    guc(
+        "max_parallel_workers",
+        GucKind::Integer { min: 0, max: 1024 },
+    ),
+    guc(
+        "max_parallel_workers_per_gather",
+        GucKind::Integer { min: 0, max: 1024 },
+    ),
+    guc(
+        "max_replication_slots",
+        GucKind::Integer {
+            min: 0,
+            max: 262143,
+        },
+    ),
+    guc(
+        "max_wal_senders",
+        GucKind::Integer {
+            min: 0,
+            max: 262143,
+        },
+    ),
+    guc(
+        "max_wal_size",
+        GucKind::Memory {
+            unit: 1024,
+            min: 2,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "max_worker_processes",
+        GucKind::Integer {
+            min: 0,
+            max: 262143,
+        },
+    ),
+    guc(
+        "min_wal_size",
+        GucKind::Memory {
+            unit: 1024,
+            min: 2,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "password_encryption",
+        GucKind::Enum(&["md5", "scram-sha-256"]),
+    ),
+    guc(
+        "random_page_cost",
+        GucKind::Real {
+            min: 0.0,
+            max: f64::MAX,
+        },
+    ),
+    guc(
+        "seq_page_cost",
+        GucKind::Real {
+            min: 0.0,
+            max: f64::MAX,
+        },
+    ),
+    guc(
+        "shared_buffers",
+        GucKind::Memory {
+            unit: 8,
+            min: 16,
+            max: 1073741823,
+        },
+    ),
+    guc(
+        "statement_timeout",
+        GucKind::Time {
+            unit: MS,
+            min: 0,
+            max: INT_MAX,
+        },
+    ),
+    guc(
+        "synchronous_commit",
+        GucKind::Enum(&["local", "remote_write", "remote_apply", "on", "off"]),
+    ),
+    guc(
+        "temp_buffers",
+        GucKind::Memory {
+            unit: 8,
+            min: 100,
+            max: 1073741823,
+        },
+    ),
+    guc("track_io_timing", GucKind::Bool),
+    guc(
+        "wal_buffers",
+        GucKind::Memory {
+            unit: 8,
+            min: -1,
+            max: 262143,
+        },
+    ),
+    guc("wal_compression", GucKind::Bool),
+    GucSpec {
+        name: "wal_compression",
+        since: 15,
+        kind: GucKind::Enum(&["pglz", "lz4", "zstd", "on", "off"]),
+    },
+    guc(
+        "wal_level",
+        GucKind::Enum(&["minimal", "replica", "logical"]),
+    ),
+    guc(
+        "work_mem",
+        GucKind::Memory {
+            unit: 1,
+            min: 64,
+            max: INT_MAX,
+        },
+    ),
+];
+
+// The spec of a parameter for a Postgres major version, the most recent one when the version
+// is not known
+fn guc_spec(name: &str, major: Option<i32>) -> Option<&'static GucSpec> {
+    GUC_CATALOG
+        .iter()
+        .rfind(|spec| spec.name == name && spec.since <= major.unwrap_or(i32::MAX))
+}
+
+// Split a value like `2GB` into its number and unit
+fn split_unit(value: &str) -> Option<(f64, &str)> {
+    let index = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(index);
+    let number = number.trim().parse::<f64>().ok()?;
+    Some((number, unit.trim()))
+}
+
+fn check_range(name: &str, value: f64, min: i64, max: i64, unit: &str) -> Result<(), String> {
+    let rounded = value.round();
+    if rounded < min as f64 || rounded > max as f64 {
+        return Err(format!(
+            "{} must be between {}{} and {}{}",
+            name, min, unit, max, unit
+        ));
+    }
+    Ok(())
+}
+
+// Validate and normalize a single value, returns the value to pass to Postgres
+fn normalize_value(name: &str, value: &str, kind: &GucKind) -> Result<String, String> {
+    match kind {
+        GucKind::Bool => match value.to_lowercase().as_str() {
+            "on" | "true" | "yes" | "1" => Ok("on".to_string()),
+            "off" | "false" | "no" | "0" => Ok("off".to_string()),
+            _ => Err(format!("{} must be on or off", name)),
+        },
+        GucKind::Enum(values) => {
+            let lowercase = value.to_lowercase();
+            if values.contains(&lowercase.as_str()) {
+                Ok(lowercase)
+            } else {
+                Err(format!("{} must be one of {}", name, values.join(", ")))
+            }
+        }
+        GucKind::Integer { min, max } => {
+            let number = value
+                .parse::<f64>()
+                .map_err(|_| format!("{} must be an integer", name))?;
+            check_range(name, number, *min, *max, "")?;
+            Ok(value.to_string())
+        }
+        GucKind::Real { min, max } => {
+            let number = value
+                .parse::<f64>()
+                .map_err(|_| format!("{} must be a number", name))?;
+            if !(*min..=*max).contains(&number) {
+                return Err(format!("{} must be between {} and {}", name, min, max));
+            }
+            Ok(value.to_string())
+        }
+        GucKind::Memory { unit, min, max } => {
+            let (number, unit_str) =
+                split_unit(value).ok_or_else(|| format!("{} must be a size, like 1GB", name))?;
+            // Postgres only accepts these units as written, other cases are corrected
+            let (canonical, kb) = match unit_str.to_lowercase().as_str() {
+                "" => ("", *unit as f64),
+                "b" => ("B", 1.0 / 1024.0),
+                "kb" => ("kB", 1.0),
+                "mb" => ("MB", 1024.0),
+                "gb" => ("GB", 1024.0 * 1024.0),
+                "tb" => ("TB", 1024.0 * 1024.0 * 1024.0),
+                _ => {
+                    return Err(format!(
+                        "{} has an invalid unit {}, valid units are B, kB, MB, GB and TB",
+                        name, unit_str
+                    ))
+                }
+            };
+            // -1 and 0 are special values, they are never given with a unit
+            if canonical.is_empty() {
+                check_range(name, number, *min, *max, "")?;
+            } else {
+                check_range(name, number * kb / *unit as f64, *min, *max, "")
+                    .map_err(|_| memory_range_error(name, *unit, *min, *max))?;
+            }
+            Ok(format!(
+                "{}{}",
+                value[..value.len() - unit_str.len()].trim(),
+                canonical
+            ))
+        }
+        GucKind::Time { unit, min, max } => {
+            let (number, unit_str) = split_unit(value)
+                .ok_or_else(|| format!("{} must be a duration, like 30s", name))?;
+            let (canonical, us) = match unit_str.to_lowercase().as_str() {
+                "" => ("", *unit as f64),
+                "us" => ("us", 1.0),
+                "ms" => ("ms", MS as f64),
+                "s" => ("s", S as f64),
+                "min" => ("min", 60.0 * S as f64),
+                "h" => ("h", 3600.0 * S as f64),
+                "d" => ("d", 86400.0 * S as f64),
+                _ => {
+                    return Err(format!(
+                        "{} has an invalid unit {}, valid units are us, ms, s, min, h and d",
+                        name, unit_str
+                    ))
+                }
+            };
+            let base = if *unit == S { "s" } else { "ms" };
+            check_range(name, number * us / *unit as f64, *min, *max, base)?;
+            Ok(format!(
+                "{}{}",
+                value[..value.len() - unit_str.len()].trim(),
+                canonical
+            ))
+        }
+    }
+}
+
+fn memory_range_error(name: &str, unit: i64, min: i64, max: i64) -> String {
+    format!(
+        "{} must be between {}kB and {}kB",
+        name,
+        min * unit,
+        max * unit
+    )
+}
+
+/// Validate a configuration setting against the parameter catalog for a Postgres major
+/// version. Returns the setting with its value normalized, e.g. `on` for `true` or `GB` for
+/// `gb`, or why the value is not valid. Settings of parameters which are not in the catalog,
+/// and multi-valued settings, are returned as they are.
+pub fn validate_pg_config(config: &PgConfig, major: Option<i32>) -> Result<PgConfig, String> {
+    let (Some(spec), ConfigValue::Single(value)) = (guc_spec(&config.name, major), &config.value)
+    else {
+        return Ok(config.clone());
+    };
+    let value = normalize_value(&config.name, value.trim(), &spec.kind)?;
+    Ok(PgConfig {
+        name: config.name.clone(),
+        value: ConfigValue::Single(value),
+    })
+}
+
+/// The settings of a runtime_config which are not valid for a Postgres major version
+pub fn invalid_pg_configs(configs: &[PgConfig], major: Option<i32>) -> Vec<InvalidPgConfig> {
+    configs
+        .iter()
+        .filter_map(|config| {
+            validate_pg_config(config, major)
+                .err()
+                .map(|reason| InvalidPgConfig {
+                    name: config.name.clone(),
+                    value: config.value.to_string(),
+                    reason,
+                })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod pg_param_tests {
     use super::*;
     use crate::apis::coredb_types::{CoreDBSpec, Stack};
     use std::collections::BTreeMap;
 
+    #[test]
+    fn test_validate_pg_config() {
+        let config = |name: &str, value: &str| PgConfig {
+            name: name.to_string(),
+            value: ConfigValue::Single(value.to_string()),
+        };
+        let value = |result: Result<PgConfig, String>| result.unwrap().value.to_string();
+
+        // Units and booleans are normalized
+        assert_eq!(
+            value(validate_pg_config(
+                &config("shared_buffers", "2gb"),
+                Some(16)
+            )),
+            "2GB"
+        );
+        assert_eq!(
+            value(validate_pg_config(&config("work_mem", " 64 MB"), Some(16))),
+            "64MB"
+        );
+        assert_eq!(
+            value(validate_pg_config(&config("jit", "True"), Some(16))),
+            "on"
+        );
+        assert_eq!(
+            value(validate_pg_config(&config("wal_buffers", "-1"), Some(16))),
+            "-1"
+        );
+        assert_eq!(
+            value(validate_pg_config(
+                &config("statement_timeout", "5MIN"),
+                Some(16)
+            )),
+            "5min"
+        );
+
+        // Invalid units, out of range and unknown enum values are rejected
+        let err = validate_pg_config(&config("shared_buffers", "2GBB"), Some(16)).unwrap_err();
+        assert!(err.contains("invalid unit GBB"));
+        let err = validate_pg_config(&config("shared_buffers", "64kB"), Some(16)).unwrap_err();
+        assert_eq!(err, "shared_buffers must be between 128kB and 8589934584kB");
+        assert!(validate_pg_config(&config("max_connections", "0"), Some(16)).is_err());
+        assert!(validate_pg_config(&config("checkpoint_timeout", "10s"), Some(16)).is_err());
+        assert!(validate_pg_config(&config("wal_level", "archive"), Some(16)).is_err());
+
+        // Values can depend on the version
+        assert!(validate_pg_config(&config("wal_compression", "lz4"), Some(14)).is_err());
+        assert_eq!(
+            value(validate_pg_config(
+                &config("wal_compression", "LZ4"),
+                Some(15)
+            )),
+            "lz4"
+        );
+
+        // Parameters which are not in the catalog are left as they are
+        assert_eq!(
+            value(validate_pg_config(
+                &config("pg_stat_statements.max", "10000x"),
+                Some(16)
+            )),
+            "10000x"
+        );
+
+        let invalid = invalid_pg_configs(
+            &[
+                config("max_connections", "100"),
+                config("shared_buffers", "2GBB"),
+            ],
+            Some(16),
+        );
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].name, "shared_buffers");
+        assert_eq!(invalid[0].value, "2GBB");
+    }
+
     #[test]
     fn test_pg_config() {
         let pg_config = PgConfig {
@@ -1,4 +1,7 @@
 use crate::{
+    apis::coredb_types::CoreDB,
+    certmanager::certificates::{Certificate, CertificateIssuerRef, CertificateSpec},
+    ingress::is_valid_dns_name,
     ingress_route_crd::{
         IngressRoute, IngressRouteRoutes, IngressRouteRoutesKind, IngressRouteRoutesMiddlewares,
         IngressRouteRoutesServices, IngressRouteRoutesServicesKind, IngressRouteSpec,
@@ -13,16 +16,23 @@ use crate::{
 use k8s_openapi::apimachinery::pkg::{apis::meta::v1::OwnerReference, util::intstr::IntOrString};
 use kube::{
     api::{Api, ListParams, ObjectMeta, Patch, PatchParams},
-    Client,
+    Client, ResourceExt,
 };
 
-use std::collections::BTreeMap;
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+use std::collections::{BTreeMap, BTreeSet};
 
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 use super::{
     manager::to_delete,
-    types::{AppService, Middleware, COMPONENT_NAME},
+    types::{
+        AppService, Middleware, COMPONENT_NAME, CUSTOM_DOMAIN_COMPONENT_NAME,
+        CUSTOM_DOMAIN_HOSTNAME_ANNOTATION, CUSTOM_DOMAIN_VERIFICATION_LABEL,
+    },
 };
 
 use crate::traefik::ingress_route_tcp_crd::{
@@ -308,20 +318,366 @@ pub fn generate_ingress_tcp_routes(
     }
 }
 
-pub async fn reconcile_ingress(
+fn custom_domain_labels(coredb_name: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            "component".to_owned(),
+            CUSTOM_DOMAIN_COMPONENT_NAME.to_string(),
+        ),
+        ("coredb.io/name".to_owned(), coredb_name.to_string()),
+    ])
+}
+
+fn custom_domain_resource_name(coredb_name: &str, appsvc_name: &str) -> String {
+    format!("{}-{}-custom-domain", coredb_name, appsvc_name)
+}
+
+// returns the normalized hostname of an appService custom domain, when it is a valid DNS name
+// outside of the platform domain, which is reserved for the instances themselves
+pub fn custom_domain_hostname(appsvc: &AppService, basedomain: &str) -> Option<String> {
+    let custom_domain = appsvc.custom_domain.as_ref()?;
+    let hostname = custom_domain.hostname.trim().to_lowercase();
+    if !is_valid_dns_name(&hostname) {
+        return None;
+    }
+    let basedomain = basedomain.trim().trim_end_matches('.').to_lowercase();
+    if hostname == basedomain || hostname.ends_with(&format!(".{}", basedomain)) {
+        return None;
+    }
+    Some(hostname)
+}
+
+// the TXT record, and the value it must hold, to prove the ownership of a custom domain
+pub fn custom_domain_verification(hostname: &str, namespace: &str) -> (String, String) {
+    (
+        format!("{}.{}.", CUSTOM_DOMAIN_VERIFICATION_LABEL, hostname),
+        format!("tembo-verification={}", namespace),
+    )
+}
+
+// generates the IngressRoute exposing the http routes of an appService on its custom domain
+// named `<coredb-name>-<appservice-name>-custom-domain`
+pub fn generate_custom_domain_ingress(
+    appsvc: &AppService,
+    coredb_name: &str,
+    namespace: &str,
+    basedomain: &str,
+    oref: OwnerReference,
+) -> Option<IngressRoute> {
+    let custom_domain = appsvc.custom_domain.as_ref()?;
+    let Some(hostname) = custom_domain_hostname(appsvc, basedomain) else {
+        error!(
+            "ns: {}, invalid or reserved custom domain '{}' for AppService {}, skipping",
+            namespace, custom_domain.hostname, appsvc.name
+        );
+        return None;
+    };
+
+    let resource_name = format!("{}-{}", coredb_name, appsvc.name);
+    let routes = generate_ingress_routes(
+        appsvc,
+        &resource_name,
+        namespace,
+        format!("Host(`{}`)", hostname),
+        coredb_name,
+    )?;
+    if routes.is_empty() {
+        return None;
+    }
+
+    let mut entry_points: Vec<String> = appsvc
+        .routing
+        .iter()
+        .flatten()
+        .filter(|route| route.ingress_type == Some(IngressType::http))
+        .filter_map(|route| route.entry_points.clone())
+        .flatten()
+        .collect();
+    entry_points.sort();
+    entry_points.dedup();
+
+    let name = custom_domain_resource_name(coredb_name, &appsvc.name);
+    // use the provided certificate, or the one requested from the issuer
+    let secret_name = custom_domain.tls_secret_name.clone().or_else(|| {
+        custom_domain
+            .issuer_name
+            .as_ref()
+            .map(|_| format!("{}-tls", name))
+    });
+
+    Some(IngressRoute {
+        metadata: ObjectMeta {
+            name: Some(name),
+            namespace: Some(namespace.to_owned()),
+            owner_references: Some(vec![oref]),
+            labels: Some(custom_domain_labels(coredb_name)),
+            annotations: Some(BTreeMap::from([(
+                CUSTOM_DOMAIN_HOSTNAME_ANNOTATION.to_owned(),
+                hostname,
+            )])),
+            ..ObjectMeta::default()
+        },
+        spec: IngressRouteSpec {
+            entry_points: Some(entry_points),
+            routes,
+            tls: Some(IngressRouteTls {
+                secret_name,
+                ..IngressRouteTls::default()
+            }),
+        },
+    })
+}
+
+// generates the cert-manager Certificate for an appService custom domain,
+// only when the certificate should be requested from an issuer
+pub fn generate_custom_domain_certificate(
+    appsvc: &AppService,
+    coredb_name: &str,
+    namespace: &str,
+    basedomain: &str,
+    oref: OwnerReference,
+) -> Option<Certificate> {
+    let custom_domain = appsvc.custom_domain.as_ref()?;
+    if custom_domain.tls_secret_name.is_some() {
+        return None;
+    }
+    let issuer_name = custom_domain.issuer_name.clone()?;
+    let hostname = custom_domain_hostname(appsvc, basedomain)?;
+
+    let name = custom_domain_resource_name(coredb_name, &appsvc.name);
+    Some(Certificate {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(namespace.to_owned()),
+            owner_references: Some(vec![oref]),
+            labels: Some(custom_domain_labels(coredb_name)),
+            ..ObjectMeta::default()
+        },
+        spec: CertificateSpec {
+            secret_name: format!("{}-tls", name),
+            dns_names: Some(vec![hostname]),
+            usages: Some(vec!["server auth".to_owned()]),
+            issuer_ref: CertificateIssuerRef {
+                name: issuer_name,
+                kind: Some("ClusterIssuer".to_owned()),
+                group: Some("cert-manager.io".to_owned()),
+            },
+            ..CertificateSpec::default()
+        },
+        status: None,
+    })
+}
+
+// checks the TXT record proving the ownership of a custom domain
+async fn is_custom_domain_verified(
+    resolver: &TokioAsyncResolver,
+    hostname: &str,
+    ns: &str,
+) -> bool {
+    let (record, value) = custom_domain_verification(hostname, ns);
+    match resolver.txt_lookup(record.as_str()).await {
+        Ok(txt) => txt.iter().any(|txt| txt.to_string() == value),
+        Err(e) => {
+            debug!("ns: {}, could not look up TXT record {}: {}", ns, record, e);
+            false
+        }
+    }
+}
+
+// returns the names of the appServices whose custom domain can be routed. A hostname is routed
+// by a single IngressRoute across all namespaces, the one which claimed it first, and a new
+// hostname is only claimed once its ownership is verified. Hostnames which are already routed
+// by this instance were verified when they were claimed, so they are not looked up again.
+async fn routable_custom_domains(
     client: Client,
+    ns: &str,
     coredb_name: &str,
+    basedomain: &str,
+    appsvcs: &[AppService],
+) -> Result<BTreeSet<String>, kube::Error> {
+    let ingress_api: Api<IngressRoute> = Api::all(client);
+    let lp = ListParams::default().labels(&format!("component={}", CUSTOM_DOMAIN_COMPONENT_NAME));
+    let mut ingresses = ingress_api.list(&lp).await?.items;
+    ingresses.sort_by_key(|ingress| ingress.creation_timestamp());
+    let mut claims: BTreeMap<String, (String, String)> = BTreeMap::new();
+    for ingress in ingresses {
+        if let Some(hostname) = ingress.annotations().get(CUSTOM_DOMAIN_HOSTNAME_ANNOTATION) {
+            claims
+                .entry(hostname.clone())
+                .or_insert((ingress.namespace().unwrap_or_default(), ingress.name_any()));
+        }
+    }
+
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+        TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+    });
+    let mut routable = BTreeSet::new();
+    let mut claimed = BTreeSet::new();
+    for appsvc in appsvcs {
+        let Some(hostname) = custom_domain_hostname(appsvc, basedomain) else {
+            continue;
+        };
+        if !claimed.insert(hostname.clone()) {
+            error!(
+                "ns: {}, custom domain '{}' of AppService {} is used by another AppService",
+                ns, hostname, appsvc.name
+            );
+            continue;
+        }
+        let name = custom_domain_resource_name(coredb_name, &appsvc.name);
+        match claims.get(&hostname) {
+            Some((claim_ns, claim_name)) if claim_ns == ns && *claim_name == name => {
+                routable.insert(appsvc.name.clone());
+            }
+            Some((claim_ns, claim_name)) => {
+                error!(
+                    "ns: {}, custom domain '{}' of AppService {} is already routed by {}/{}",
+                    ns, hostname, appsvc.name, claim_ns, claim_name
+                );
+            }
+            None if is_custom_domain_verified(&resolver, &hostname, ns).await => {
+                routable.insert(appsvc.name.clone());
+            }
+            None => {
+                warn!(
+                    "ns: {}, custom domain '{}' of AppService {} is not verified yet",
+                    ns, hostname, appsvc.name
+                );
+            }
+        }
+    }
+    Ok(routable)
+}
+
+// applies the IngressRoutes and Certificates for appService custom domains,
+// and deletes the ones which are no longer desired
+pub async fn reconcile_custom_domains(
+    client: Client,
+    cdb: &CoreDB,
+    ns: &str,
+    basedomain: &str,
+    oref: OwnerReference,
+    appsvcs: &[AppService],
+) -> Result<(), kube::Error> {
+    let coredb_name = cdb.name_any();
+    let label_selector = format!(
+        "component={},coredb.io/name={}",
+        CUSTOM_DOMAIN_COMPONENT_NAME, coredb_name
+    );
+    let lp = ListParams::default().labels(&label_selector);
+
+    let routable =
+        routable_custom_domains(client.clone(), ns, &coredb_name, basedomain, appsvcs).await?;
+    let appsvcs: Vec<&AppService> = appsvcs
+        .iter()
+        .filter(|appsvc| routable.contains(&appsvc.name))
+        .collect();
+
+    let certificate_api: Api<Certificate> = Api::namespaced(client.clone(), ns);
+    let desired_certificates: Vec<Certificate> = appsvcs
+        .iter()
+        .filter_map(|appsvc| {
+            generate_custom_domain_certificate(appsvc, &coredb_name, ns, basedomain, oref.clone())
+        })
+        .collect();
+    let desired_certificate_names = desired_certificates
+        .iter()
+        .map(|cert| cert.name_any())
+        .collect::<Vec<String>>();
+    for mut certificate in desired_certificates {
+        cdb.spec.apply_common_metadata(&mut certificate.metadata);
+        let patch_parameters = PatchParams::apply("cntrlr").force();
+        certificate_api
+            .patch(
+                &certificate.name_any(),
+                &patch_parameters,
+                &Patch::Apply(&certificate),
+            )
+            .await?;
+        debug!(
+            "ns: {}, applied custom domain Certificate: {}",
+            ns,
+            certificate.name_any()
+        );
+    }
+    // cert-manager may not be installed, there is nothing to clean up then
+    match certificate_api.list(&lp).await {
+        Ok(certificates) => {
+            let actual_certificate_names = certificates
+                .iter()
+                .map(|cert| cert.name_any())
+                .collect::<Vec<String>>();
+            if let Some(to_delete) = to_delete(desired_certificate_names, actual_certificate_names)
+            {
+                for d in to_delete {
+                    certificate_api.delete(&d, &Default::default()).await?;
+                    debug!("ns: {}, deleted custom domain Certificate: {}", ns, d);
+                }
+            }
+        }
+        Err(e) => {
+            debug!(
+                "ns: {}, could not list custom domain Certificates: {}",
+                ns, e
+            );
+        }
+    }
+
+    let ingress_api: Api<IngressRoute> = Api::namespaced(client.clone(), ns);
+    let desired_ingresses: Vec<IngressRoute> = appsvcs
+        .iter()
+        .filter_map(|appsvc| {
+            generate_custom_domain_ingress(appsvc, &coredb_name, ns, basedomain, oref.clone())
+        })
+        .collect();
+    let desired_ingress_names = desired_ingresses
+        .iter()
+        .map(|ingress| ingress.name_any())
+        .collect::<Vec<String>>();
+    for mut ingress in desired_ingresses {
+        cdb.spec.apply_common_metadata(&mut ingress.metadata);
+        apply_ingress_route(ingress_api.clone(), &ingress.name_any(), &ingress).await?;
+        debug!(
+            "ns: {}, applied custom domain IngressRoute: {}",
+            ns,
+            ingress.name_any()
+        );
+    }
+    let actual_ingress_names = ingress_api
+        .list(&lp)
+        .await?
+        .iter()
+        .map(|ingress| ingress.name_any())
+        .collect::<Vec<String>>();
+    if let Some(to_delete) = to_delete(desired_ingress_names, actual_ingress_names) {
+        for d in to_delete {
+            ingress_api.delete(&d, &Default::default()).await?;
+            debug!("ns: {}, deleted custom domain IngressRoute: {}", ns, d);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn reconcile_ingress(
+    client: Client,
+    cdb: &CoreDB,
     ns: &str,
     oref: OwnerReference,
     desired_routes: Vec<IngressRouteRoutes>,
     desired_middlewares: Vec<Middleware>,
     entry_points: Vec<String>,
 ) -> Result<(), kube::Error> {
+    let coredb_name = cdb.name_any();
+    let coredb_name = coredb_name.as_str();
     let ingress_api: Api<IngressRoute> = Api::namespaced(client.clone(), ns);
 
     let middleware_api: Api<TraefikMiddleware> = Api::namespaced(client.clone(), ns);
-    let desired_middlewares =
+    let mut desired_middlewares =
         generate_middlewares(coredb_name, ns, oref.clone(), desired_middlewares);
+    for desired_mw in desired_middlewares.iter_mut() {
+        cdb.spec.apply_common_metadata(&mut desired_mw.mw.metadata);
+    }
     let actual_mw_names = get_middlewares(client.clone(), ns, coredb_name).await?;
     let desired_mw_names = desired_middlewares
         .iter()
@@ -359,7 +715,8 @@ pub async fn reconcile_ingress(
         }
     }
 
-    let ingress = generate_ingress(coredb_name, ns, oref, desired_routes.clone(), entry_points);
+    let mut ingress = generate_ingress(coredb_name, ns, oref, desired_routes.clone(), entry_points);
+    cdb.spec.apply_common_metadata(&mut ingress.metadata);
     if desired_routes.is_empty() {
         // we don't need an IngressRoute when there are no routes
         let lp = ListParams::default().labels("component=appService");
@@ -405,7 +762,7 @@ pub async fn reconcile_ingress(
 
 pub async fn reconcile_ingress_tcp(
     client: Client,
-    coredb_name: &str,
+    cdb: &CoreDB,
     ns: &str,
     oref: OwnerReference,
     desired_routes: Vec<IngressRouteTCPRoutes>,
@@ -415,10 +772,14 @@ pub async fn reconcile_ingress_tcp(
     app_name: &str,
 ) -> Result<(), kube::Error> {
     let ingress_api: Api<IngressRouteTCP> = Api::namespaced(client.clone(), ns);
-    let name = format!("{}-{}", coredb_name, app_name);
+    let name = format!("{}-{}", cdb.name_any(), app_name);
 
     let middleware_api: Api<TraefikMiddleware> = Api::namespaced(client.clone(), ns);
-    let desired_middlewares = generate_middlewares(&name, ns, oref.clone(), desired_middlewares);
+    let mut desired_middlewares =
+        generate_middlewares(&name, ns, oref.clone(), desired_middlewares);
+    for desired_mw in desired_middlewares.iter_mut() {
+        cdb.spec.apply_common_metadata(&mut desired_mw.mw.metadata);
+    }
     let actual_mw_names = get_middlewares(client.clone(), ns, &name).await?;
     let desired_mw_names = desired_middlewares
         .iter()
@@ -497,8 +858,9 @@ pub async fn reconcile_ingress_tcp(
             .iter()
             .any(|s| s.name == name)
     }) {
-        let ingress_tcp =
+        let mut ingress_tcp =
             generate_ingress_tcp(&name, ns, oref, desired_routes.clone(), entry_points_tcp);
+        cdb.spec.apply_common_metadata(&mut ingress_tcp.metadata);
         match apply_ingress_route_tcp(ingress_api, &name, &ingress_tcp).await {
             Ok(_) => {
                 debug!("Updated/applied IngressRouteTCP for {}.{}", ns, &name,);
@@ -577,3 +939,177 @@ async fn get_middlewares(
         .map(|d| d.metadata.name.to_owned().expect("no name on resource"))
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_service::types::{CustomDomain, Routing};
+
+    const BASEDOMAIN: &str = "data-1.use1.tembo.io";
+
+    fn custom_domain_appsvc(custom_domain: CustomDomain) -> AppService {
+        AppService {
+            name: "postgrest".to_string(),
+            routing: Some(vec![Routing {
+                port: 3000,
+                ingress_path: Some("/rest/v1".to_string()),
+                middlewares: None,
+                entry_points: Some(vec!["websecure".to_string()]),
+                ingress_type: Some(IngressType::http),
+            }]),
+            custom_domain: Some(custom_domain),
+            ..AppService::default()
+        }
+    }
+
+    #[test]
+    fn test_generate_custom_domain_ingress() {
+        let appsvc = custom_domain_appsvc(CustomDomain {
+            hostname: "API.example.com".to_string(),
+            tls_secret_name: None,
+            issuer_name: Some("letsencrypt".to_string()),
+        });
+        let ingress = generate_custom_domain_ingress(
+            &appsvc,
+            "test",
+            "ns",
+            BASEDOMAIN,
+            OwnerReference::default(),
+        )
+        .unwrap();
+        assert_eq!(ingress.name_any(), "test-postgrest-custom-domain");
+        assert_eq!(
+            ingress.labels().get("component"),
+            Some(&CUSTOM_DOMAIN_COMPONENT_NAME.to_string())
+        );
+        assert_eq!(
+            ingress.spec.routes[0].r#match,
+            "Host(`api.example.com`) && PathPrefix(`/rest/v1`)"
+        );
+        assert_eq!(
+            ingress.annotations().get(CUSTOM_DOMAIN_HOSTNAME_ANNOTATION),
+            Some(&"api.example.com".to_string())
+        );
+        assert_eq!(
+            ingress.spec.tls.unwrap().secret_name,
+            Some("test-postgrest-custom-domain-tls".to_string())
+        );
+
+        let certificate = generate_custom_domain_certificate(
+            &appsvc,
+            "test",
+            "ns",
+            BASEDOMAIN,
+            OwnerReference::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            certificate.spec.secret_name,
+            "test-postgrest-custom-domain-tls"
+        );
+        assert_eq!(
+            certificate.spec.dns_names,
+            Some(vec!["api.example.com".to_string()])
+        );
+        assert_eq!(certificate.spec.issuer_ref.name, "letsencrypt");
+    }
+
+    #[test]
+    fn test_generate_custom_domain_existing_secret() {
+        let appsvc = custom_domain_appsvc(CustomDomain {
+            hostname: "api.example.com".to_string(),
+            tls_secret_name: Some("my-cert".to_string()),
+            issuer_name: Some("letsencrypt".to_string()),
+        });
+        let ingress = generate_custom_domain_ingress(
+            &appsvc,
+            "test",
+            "ns",
+            BASEDOMAIN,
+            OwnerReference::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            ingress.spec.tls.unwrap().secret_name,
+            Some("my-cert".to_string())
+        );
+        // An existing secret takes precedence over the issuer
+        assert!(generate_custom_domain_certificate(
+            &appsvc,
+            "test",
+            "ns",
+            BASEDOMAIN,
+            OwnerReference::default()
+        )
+        .is_none());
+
+        // Invalid hostnames are never routed
+        let appsvc = custom_domain_appsvc(CustomDomain {
+            hostname: "api.example.com`) || Host(`other.com".to_string(),
+            tls_secret_name: None,
+            issuer_name: None,
+        });
+        assert!(generate_custom_domain_ingress(
+            &appsvc,
+            "test",
+            "ns",
+            BASEDOMAIN,
+            OwnerReference::default()
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_generate_custom_domain_rejects_basedomain() {
+        for hostname in [
+            "other-db.data-1.use1.tembo.io",
+            "Data-1.Use1.Tembo.io",
+            "api.other-db.data-1.use1.tembo.io",
+        ] {
+            let appsvc = custom_domain_appsvc(CustomDomain {
+                hostname: hostname.to_string(),
+                tls_secret_name: None,
+                issuer_name: Some("letsencrypt".to_string()),
+            });
+            assert!(custom_domain_hostname(&appsvc, BASEDOMAIN).is_none());
+            assert!(generate_custom_domain_ingress(
+                &appsvc,
+                "test",
+                "ns",
+                BASEDOMAIN,
+                OwnerReference::default()
+            )
+            .is_none());
+            assert!(generate_custom_domain_certificate(
+                &appsvc,
+                "test",
+                "ns",
+                BASEDOMAIN,
+                OwnerReference::default()
+            )
+            .is_none());
+        }
+
+        // A hostname merely ending with the platform domain is not under it
+        let appsvc = custom_domain_appsvc(CustomDomain {
+            hostname: "my-data-1.use1.tembo.io".to_string(),
+            tls_secret_name: None,
+            issuer_name: None,
+        });
+        assert_eq!(
+            custom_domain_hostname(&appsvc, BASEDOMAIN),
+            Some("my-data-1.use1.tembo.io".to_string())
+        );
+    }
+
+    #[test]
+    fn test_custom_domain_verification() {
+        assert_eq!(
+            custom_domain_verification("api.example.com", "org-acme-inst-db"),
+            (
+                "_tembo-verification.api.example.com.".to_string(),
+                "tembo-verification=org-acme-inst-db".to_string()
+            )
+        );
+    }
+}
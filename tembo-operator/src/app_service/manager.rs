@@ -37,7 +37,8 @@ use crate::{
 use tracing::{debug, error, warn};
 
 use super::{
-    ingress::{generate_ingress_routes, reconcile_ingress},
+    ingress::{generate_ingress_routes, reconcile_custom_domains, reconcile_ingress},
+    reload::{self, CONFIG_VERSIONS_ANNOTATION},
     types::{AppService, EnvVarRef, Middleware, COMPONENT_NAME},
 };
 
@@ -102,7 +103,30 @@ struct AppServiceResources {
     podmonitor: Option<podmon::PodMonitor>,
 }
 
+impl AppServiceResources {
+    // stamps the CoreDB common labels and annotations on the generated resources
+    fn apply_common_metadata(&mut self, cdb: &CoreDB) {
+        cdb.spec
+            .apply_common_metadata(&mut self.deployment.metadata);
+        if let Some(template) = self
+            .deployment
+            .spec
+            .as_mut()
+            .and_then(|spec| spec.template.metadata.as_mut())
+        {
+            cdb.spec.apply_common_metadata(template);
+        }
+        if let Some(service) = self.service.as_mut() {
+            cdb.spec.apply_common_metadata(&mut service.metadata);
+        }
+        if let Some(podmonitor) = self.podmonitor.as_mut() {
+            cdb.spec.apply_common_metadata(&mut podmonitor.metadata);
+        }
+    }
+}
+
 // generates Kubernetes Deployment and Service templates for a AppService
+#[allow(clippy::too_many_arguments)]
 fn generate_resource(
     appsvc: &AppService,
     coredb_name: &str,
@@ -111,6 +135,7 @@ fn generate_resource(
     domain: Option<String>,
     annotations: &BTreeMap<String, String>,
     placement: Option<PlacementConfig>,
+    config_versions: &str,
 ) -> AppServiceResources {
     let resource_name = format!("{}-{}", coredb_name, appsvc.name.clone());
     let service = appsvc.routing.as_ref().map(|_| {
@@ -131,6 +156,7 @@ fn generate_resource(
         oref.clone(),
         annotations,
         placement.clone(),
+        config_versions,
     );
 
     let maybe_podmonitor = generate_podmonitor(appsvc, &resource_name, namespace, annotations);
@@ -278,6 +304,7 @@ fn generate_service(
 }
 
 // templates a single Kubernetes Deployment for an AppService
+#[allow(clippy::too_many_arguments)]
 fn generate_deployment(
     appsvc: &AppService,
     coredb_name: &str,
@@ -286,6 +313,7 @@ fn generate_deployment(
     oref: OwnerReference,
     annotations: &BTreeMap<String, String>,
     placement: Option<PlacementConfig>,
+    config_versions: &str,
 ) -> Deployment {
     let mut labels: BTreeMap<String, String> = BTreeMap::new();
     labels.insert("app".to_owned(), resource_name.to_string());
@@ -587,8 +615,17 @@ fn generate_deployment(
         ..PodSpec::default()
     };
 
+    // the pods are rolled out again when a Secret or ConfigMap they read changes
+    let mut template_annotations = annotations.clone();
+    template_annotations.insert(
+        CONFIG_VERSIONS_ANNOTATION.to_string(),
+        config_versions.to_string(),
+    );
     let pod_template_spec = PodTemplateSpec {
-        metadata: Some(deployment_metadata.clone()),
+        metadata: Some(ObjectMeta {
+            annotations: Some(template_annotations),
+            ..deployment_metadata.clone()
+        }),
         spec: Some(pod_spec),
     };
 
@@ -935,11 +972,26 @@ pub async fn reconcile_app_services(
             None
         }
     };
+    let mut config_versions: HashMap<String, String> = HashMap::new();
+    for appsvc in appsvcs.iter() {
+        match reload::config_versions(&client, &ns, appsvc, &coredb_name).await {
+            Ok(versions) => {
+                config_versions.insert(appsvc.name.clone(), versions);
+            }
+            Err(e) => {
+                error!(
+                    "ns: {}, failed to get the Secrets and ConfigMaps of AppService: {}, error: {}",
+                    ns, appsvc.name, e
+                );
+                return Err(Action::requeue(Duration::from_secs(300)));
+            }
+        }
+    }
     // Iterate over each AppService and process routes
     let resources: Vec<AppServiceResources> = appsvcs
         .iter()
         .map(|appsvc| {
-            generate_resource(
+            let mut resources = generate_resource(
                 appsvc,
                 &coredb_name,
                 &ns,
@@ -947,7 +999,10 @@ pub async fn reconcile_app_services(
                 domain.to_owned(),
                 &annotations,
                 placement.clone(),
-            )
+                &config_versions[&appsvc.name],
+            );
+            resources.apply_common_metadata(cdb);
+            resources
         })
         .collect();
     let apply_errored = apply_resources(resources.clone(), &client, &ns).await;
@@ -983,10 +1038,10 @@ pub async fn reconcile_app_services(
         .collect::<Vec<String>>();
 
     // Only reconcile IngressRoute and IngressRouteTCP if DATA_PLANE_BASEDOMAIN is set
-    if domain.is_some() {
+    if let Some(basedomain) = domain.as_deref() {
         match reconcile_ingress(
             client.clone(),
-            &coredb_name,
+            cdb,
             &ns,
             oref.clone(),
             desired_routes,
@@ -1007,12 +1062,27 @@ pub async fn reconcile_app_services(
             }
         }
 
+        match reconcile_custom_domains(client.clone(), cdb, &ns, basedomain, oref.clone(), &appsvcs)
+            .await
+        {
+            Ok(_) => {
+                debug!("Updated/applied custom domains for {}.{}", ns, coredb_name);
+            }
+            Err(e) => {
+                error!(
+                    "Failed to update/apply custom domains {}.{}: {}",
+                    ns, coredb_name, e
+                );
+                has_errors = true;
+            }
+        }
+
         for appsvc in appsvcs.iter() {
             let app_name = appsvc.name.clone();
 
             match reconcile_ingress_tcp(
                 client.clone(),
-                &coredb_name,
+                cdb,
                 &ns,
                 oref.clone(),
                 desired_tcp_routes.clone(),
@@ -1074,7 +1144,7 @@ pub async fn prepare_apps_connection_secret(client: Client, cdb: &CoreDB) -> Res
         .collect();
 
     // Create a new secret with the modified data
-    let new_secret = Secret {
+    let mut new_secret = Secret {
         data: Some(encoded_secret_data),
         metadata: kube::api::ObjectMeta {
             name: Some(new_secret_name.to_string()),
@@ -1083,6 +1153,7 @@ pub async fn prepare_apps_connection_secret(client: Client, cdb: &CoreDB) -> Res
         },
         ..Default::default()
     };
+    cdb.spec.apply_common_metadata(&mut new_secret.metadata);
 
     // Apply the new secret
     let patch_params = PatchParams::apply("cntrlr").force();
@@ -1093,7 +1164,9 @@ pub async fn prepare_apps_connection_secret(client: Client, cdb: &CoreDB) -> Res
     Ok(())
 }
 
-use crate::prometheus::podmonitor_crd as podmon;
+use crate::prometheus::{
+    is_silenced, podmonitor_crd as podmon, silenced_drop_metrics, SILENCED_LABEL,
+};
 
 fn generate_podmonitor(
     appsvc: &AppService,
@@ -1110,6 +1183,35 @@ fn generate_podmonitor(
     labels.insert("component".to_owned(), COMPONENT_NAME.to_owned());
     labels.insert("coredb.io/name".to_owned(), namespace.to_owned());
 
+    // When the instance is under planned maintenance, label the PodMonitor and every
+    // series it scrapes so fleet-wide alert rules can exclude them
+    let silenced = is_silenced(annotations);
+    if silenced {
+        labels.insert(SILENCED_LABEL.to_owned(), "true".to_owned());
+    }
+    let relabelings = silenced.then(|| {
+        vec![podmon::PodMonitorPodMetricsEndpointsRelabelings {
+            action: Some(podmon::PodMonitorPodMetricsEndpointsRelabelingsAction::Replace),
+            modulus: None,
+            regex: None,
+            replacement: Some("true".to_owned()),
+            separator: None,
+            source_labels: None,
+            target_label: Some(SILENCED_LABEL.to_owned()),
+        }]
+    });
+    let metric_relabelings = silenced_drop_metrics(annotations).map(|regex| {
+        vec![podmon::PodMonitorPodMetricsEndpointsMetricRelabelings {
+            action: Some(podmon::PodMonitorPodMetricsEndpointsMetricRelabelingsAction::Drop),
+            modulus: None,
+            regex: Some(regex),
+            replacement: None,
+            separator: None,
+            source_labels: Some(vec!["__name__".to_owned()]),
+            target_label: None,
+        }]
+    });
+
     let podmon_metadata = ObjectMeta {
         name: Some(resource_name.to_string()),
         namespace: Some(namespace.to_owned()),
@@ -1121,6 +1223,8 @@ fn generate_podmonitor(
     let metrics_endpoint = podmon::PodMonitorPodMetricsEndpoints {
         path: Some(metrics.path),
         port: Some(format!("{APP_CONTAINER_PORT_PREFIX}{}", metrics.port)),
+        relabelings,
+        metric_relabelings,
         ..podmon::PodMonitorPodMetricsEndpoints::default()
     };
 
@@ -1212,6 +1316,52 @@ mod tests {
         assert_eq!(annotataions, expected_annotations);
     }
 
+    #[test]
+    fn test_generate_podmonitor_silenced() {
+        use crate::app_service::types::AppMetrics;
+        use crate::prometheus::{SILENCE_ALERTS_ANNOTATION, SILENCE_DROP_METRICS_ANNOTATION};
+
+        let appsvc = AppService {
+            name: "postgrest".to_string(),
+            metrics: Some(AppMetrics {
+                port: 3000,
+                path: "/metrics".to_string(),
+            }),
+            ..AppService::default()
+        };
+
+        // Without the annotation, nothing is silenced
+        let annotations = BTreeMap::new();
+        let pmon = generate_podmonitor(&appsvc, "test-postgrest", "default", &annotations).unwrap();
+        assert!(!pmon.labels().contains_key(SILENCED_LABEL));
+        let endpoint = &pmon.spec.pod_metrics_endpoints.unwrap()[0];
+        assert!(endpoint.relabelings.is_none());
+        assert!(endpoint.metric_relabelings.is_none());
+
+        // With the annotation, the PodMonitor and its series are labeled
+        let annotations = BTreeMap::from([
+            (SILENCE_ALERTS_ANNOTATION.to_string(), "true".to_string()),
+            (
+                SILENCE_DROP_METRICS_ANNOTATION.to_string(),
+                "http_requests_total".to_string(),
+            ),
+        ]);
+        let pmon = generate_podmonitor(&appsvc, "test-postgrest", "default", &annotations).unwrap();
+        assert_eq!(pmon.labels().get(SILENCED_LABEL), Some(&"true".to_string()));
+        let endpoint = &pmon.spec.pod_metrics_endpoints.unwrap()[0];
+        let relabelings = endpoint.relabelings.as_ref().unwrap();
+        assert_eq!(
+            relabelings[0].target_label,
+            Some(SILENCED_LABEL.to_string())
+        );
+        assert_eq!(relabelings[0].replacement, Some("true".to_string()));
+        let metric_relabelings = endpoint.metric_relabelings.as_ref().unwrap();
+        assert_eq!(
+            metric_relabelings[0].regex,
+            Some("http_requests_total".to_string())
+        );
+    }
+
     #[test]
     fn test_env_var_manager() {
         // Test new manager is empty
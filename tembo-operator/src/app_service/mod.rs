@@ -1,3 +1,4 @@
 pub mod ingress;
 pub mod manager;
+pub mod reload;
 pub mod types;
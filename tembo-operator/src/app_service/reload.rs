@@ -0,0 +1,193 @@
+use crate::{apis::coredb_types::CoreDB, app_service::types::AppService};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret, Volume};
+use kube::{Api, Client, ResourceExt};
+use std::collections::BTreeSet;
+
+// Pod template annotation holding the resource versions of the Secrets and ConfigMaps an
+// AppService reads. A change of one of them changes the pod template, so the Deployment
+// rolls out pods which read the new values.
+pub const CONFIG_VERSIONS_ANNOTATION: &str = "tembo.io/config-versions";
+
+// Label of user ConfigMaps mounted by AppServices whose changes are reconciled right away.
+// ConfigMaps are only watched with a label, so the operator does not cache every ConfigMap
+// of the cluster.
+pub const APP_CONFIG_LABEL: &str = "tembo.io/app-config";
+
+fn volume_secrets(volume: &Volume) -> Vec<String> {
+    let mut names: Vec<Option<String>> = vec![volume
+        .secret
+        .as_ref()
+        .and_then(|secret| secret.secret_name.clone())];
+    if let Some(sources) = volume
+        .projected
+        .as_ref()
+        .and_then(|projected| projected.sources.as_ref())
+    {
+        names.extend(
+            sources
+                .iter()
+                .map(|source| source.secret.as_ref().and_then(|s| s.name.clone())),
+        );
+    }
+    names.into_iter().flatten().collect()
+}
+
+fn volume_configmaps(volume: &Volume) -> Vec<String> {
+    let mut names: Vec<Option<String>> = vec![volume
+        .config_map
+        .as_ref()
+        .and_then(|config_map| config_map.name.clone())];
+    if let Some(sources) = volume
+        .projected
+        .as_ref()
+        .and_then(|projected| projected.sources.as_ref())
+    {
+        names.extend(
+            sources
+                .iter()
+                .map(|source| source.config_map.as_ref().and_then(|c| c.name.clone())),
+        );
+    }
+    names.into_iter().flatten().collect()
+}
+
+fn volumes(appsvc: &AppService) -> &[Volume] {
+    appsvc
+        .storage
+        .as_ref()
+        .and_then(|storage| storage.volumes.as_deref())
+        .unwrap_or_default()
+}
+
+/// The Secrets an AppService reads: the connection secret of the instance, and the Secrets
+/// mounted as volumes
+pub fn referenced_secrets(appsvc: &AppService, coredb_name: &str) -> BTreeSet<String> {
+    let mut names = BTreeSet::from([format!("{}-apps", coredb_name)]);
+    names.extend(volumes(appsvc).iter().flat_map(volume_secrets));
+    names
+}
+
+/// The ConfigMaps mounted as volumes of an AppService
+pub fn referenced_configmaps(appsvc: &AppService) -> BTreeSet<String> {
+    volumes(appsvc).iter().flat_map(volume_configmaps).collect()
+}
+
+/// Whether a change of the Secret has to be reconciled for the instance
+pub fn references_secret(cdb: &CoreDB, name: &str) -> bool {
+    cdb.spec
+        .app_services
+        .iter()
+        .flatten()
+        .any(|appsvc| referenced_secrets(appsvc, &cdb.name_any()).contains(name))
+}
+
+/// Whether a change of the ConfigMap has to be reconciled for the instance, because an
+/// AppService mounts it or it holds the custom metrics queries of the instance
+pub fn references_configmap(cdb: &CoreDB, name: &str) -> bool {
+    let metrics_queries = cdb
+        .spec
+        .metrics
+        .as_ref()
+        .and_then(|metrics| metrics.queries_config_map_ref.as_ref())
+        .is_some_and(|config_map_ref| config_map_ref.name == name);
+    metrics_queries
+        || cdb
+            .spec
+            .app_services
+            .iter()
+            .flatten()
+            .any(|appsvc| referenced_configmaps(appsvc).contains(name))
+}
+
+/// The value of the CONFIG_VERSIONS_ANNOTATION of an AppService. Secrets and ConfigMaps which
+/// do not exist yet are left out, the AppService is rolled out again once they are created.
+pub async fn config_versions(
+    client: &Client,
+    namespace: &str,
+    appsvc: &AppService,
+    coredb_name: &str,
+) -> Result<String, kube::Error> {
+    let secret_api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let configmap_api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let mut versions = Vec::new();
+    for name in referenced_secrets(appsvc, coredb_name) {
+        if let Some(secret) = secret_api.get_opt(&name).await? {
+            versions.push(format!(
+                "secret/{}={}",
+                name,
+                secret.resource_version().unwrap_or_default()
+            ));
+        }
+    }
+    for name in referenced_configmaps(appsvc) {
+        if let Some(config_map) = configmap_api.get_opt(&name).await? {
+            versions.push(format!(
+                "configmap/{}={}",
+                name,
+                config_map.resource_version().unwrap_or_default()
+            ));
+        }
+    }
+    Ok(versions.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::CoreDBSpec;
+
+    #[test]
+    fn test_references() {
+        let appsvc: AppService = serde_yaml::from_str(
+            r#"
+            name: postgrest
+            image: postgrest/postgrest:v12.0.2
+            storage:
+              volumes:
+                - name: api-key
+                  secret:
+                    secretName: api-key
+                - name: settings
+                  configMap:
+                    name: settings
+                - name: bundle
+                  projected:
+                    sources:
+                      - secret:
+                          name: tls
+                      - configMap:
+                          name: ca
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            referenced_secrets(&appsvc, "sample"),
+            BTreeSet::from([
+                "api-key".to_string(),
+                "sample-apps".to_string(),
+                "tls".to_string()
+            ])
+        );
+        assert_eq!(
+            referenced_configmaps(&appsvc),
+            BTreeSet::from(["ca".to_string(), "settings".to_string()])
+        );
+
+        let cdb = CoreDB::new(
+            "sample",
+            CoreDBSpec {
+                app_services: Some(vec![appsvc]),
+                ..CoreDBSpec::default()
+            },
+        );
+        assert!(references_secret(&cdb, "api-key"));
+        assert!(references_secret(&cdb, "sample-apps"));
+        assert!(!references_secret(&cdb, "sample-connection"));
+        assert!(references_configmap(&cdb, "settings"));
+        assert!(!references_configmap(&cdb, "metrics-sample"));
+        assert!(!references_secret(
+            &CoreDB::new("sample", CoreDBSpec::default()),
+            "sample-apps"
+        ));
+    }
+}
@@ -10,6 +10,11 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 pub const COMPONENT_NAME: &str = "appService";
+pub const CUSTOM_DOMAIN_COMPONENT_NAME: &str = "appServiceCustomDomain";
+// records the hostname routed by a custom domain IngressRoute
+pub const CUSTOM_DOMAIN_HOSTNAME_ANNOTATION: &str = "tembo.io/custom-domain";
+// the label of the TXT record proving the ownership of a custom domain
+pub const CUSTOM_DOMAIN_VERIFICATION_LABEL: &str = "_tembo-verification";
 
 /// StorageConfig is used to configure the storage for the appService.
 /// This uses the `Volume` and `VolumeMount` types from the Kubernetes API.
@@ -132,7 +137,72 @@ pub struct AppService {
     pub routing: Option<Vec<Routing>>,
 
     /// Defines the storage configuration for the appService.
+    /// The appService is rolled out again when a Secret or ConfigMap mounted as a volume changes,
+    /// right away for ConfigMaps labeled `tembo.io/app-config` and otherwise on the next reconcile.
     pub storage: Option<StorageConfig>,
+
+    /// Defines a custom domain to expose the http routes of the appService on,
+    /// in addition to the platform domain.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "customDomain"
+    )]
+    pub custom_domain: Option<CustomDomain>,
+}
+
+/// CustomDomain exposes the http routes of an appService on a customer owned hostname.
+/// The TLS certificate is either read from an existing Secret, or requested from a
+/// cert-manager ClusterIssuer. When neither is set, Traefik serves its default certificate.
+///
+/// The hostname is only routed once its ownership is proven with a TXT record at
+/// `_tembo-verification.<hostname>` holding `tembo-verification=<namespace>`, where
+/// `<namespace>` is the namespace of the instance. A hostname can be routed to one
+/// appService only, and hostnames under the platform domain are rejected.
+///
+/// **Example**: Expose a Postgrest appService on api.example.com, with a certificate
+/// issued by the `letsencrypt` ClusterIssuer
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   appServices:
+///     - name: postgrest
+///       image: postgrest/postgrest:v10.0.0
+///       routing:
+///         - port: 3000
+///           ingressPath: /rest/v1
+///       customDomain:
+///         hostname: api.example.com
+///         issuerName: letsencrypt
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, JsonSchema, PartialEq)]
+pub struct CustomDomain {
+    /// The hostname to route to the appService. A CNAME for this hostname
+    /// must point to the platform domain of the instance, and a TXT record at
+    /// `_tembo-verification.<hostname>` must hold `tembo-verification=<namespace>`.
+    pub hostname: String,
+
+    /// The name of an existing `kubernetes.io/tls` Secret, in the namespace of the
+    /// instance, holding the certificate for the hostname.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "tlsSecretName"
+    )]
+    pub tls_secret_name: Option<String>,
+
+    /// The name of a cert-manager ClusterIssuer to request a certificate for the
+    /// hostname from. Ignored when `tlsSecretName` is set.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "issuerName"
+    )]
+    pub issuer_name: Option<String>,
 }
 
 pub fn default_resources() -> ResourceRequirements {
@@ -0,0 +1,136 @@
+use crate::{
+    apis::coredb_types::{BootstrapSql, CoreDB},
+    patch_cdb_status_merge, Context,
+};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::{runtime::controller::Action, Api, ResourceExt};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info};
+
+// reconcile_bootstrap_sql runs the scripts of spec.bootstrapSQL which were not applied yet,
+// in order. Each applied script is recorded in status.bootstrap_sql_applied right away, so it
+// never runs again, even when a later step of the reconcile fails.
+pub async fn reconcile_bootstrap_sql(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let mut applied = cdb
+        .status
+        .as_ref()
+        .and_then(|status| status.bootstrap_sql_applied.clone())
+        .unwrap_or_default();
+    let pending = pending_scripts(
+        cdb.spec.bootstrap_sql.as_deref().unwrap_or_default(),
+        &applied,
+    );
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    for script in pending {
+        let sql = get_script(script, &namespace, ctx.clone()).await?;
+        debug!(
+            "Running bootstrap SQL {} in database {} on instance {}",
+            script.name, script.database, name
+        );
+        let result = cdb.psql(sql, script.database.clone(), ctx.clone()).await?;
+        if !result.success {
+            error!(
+                "Failed to run bootstrap SQL {} on instance {}: {:?}",
+                script.name, name, result.stderr
+            );
+            return Err(Action::requeue(Duration::from_secs(300)));
+        }
+        info!("Applied bootstrap SQL {} on instance {}", script.name, name);
+
+        applied.push(script.name.clone());
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "bootstrap_sql_applied": applied,
+            }
+        });
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+    }
+    Ok(())
+}
+
+// The scripts which were not applied yet, in the order of the spec
+fn pending_scripts<'a>(scripts: &'a [BootstrapSql], applied: &[String]) -> Vec<&'a BootstrapSql> {
+    scripts
+        .iter()
+        .filter(|script| !applied.contains(&script.name))
+        .collect()
+}
+
+// Read the script from the key of the ConfigMap or Secret it references
+async fn get_script(
+    script: &BootstrapSql,
+    namespace: &str,
+    ctx: Arc<Context>,
+) -> Result<String, Action> {
+    let sql = match (&script.config_map_ref, &script.secret_ref) {
+        (Some(key_ref), None) => {
+            let config_maps: Api<ConfigMap> = Api::namespaced(ctx.client.clone(), namespace);
+            let config_map = config_maps.get_opt(&key_ref.name).await.map_err(|e| {
+                error!("Error getting ConfigMap {}: {:?}", key_ref.name, e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
+            config_map
+                .and_then(|config_map| config_map.data)
+                .and_then(|mut data| data.remove(&key_ref.key))
+        }
+        (None, Some(key_ref)) => {
+            let secrets: Api<Secret> = Api::namespaced(ctx.client.clone(), namespace);
+            let secret = secrets.get_opt(&key_ref.name).await.map_err(|e| {
+                error!("Error getting Secret {}: {:?}", key_ref.name, e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
+            secret
+                .and_then(|secret| secret.data)
+                .and_then(|mut data| data.remove(&key_ref.key))
+                .and_then(|sql| String::from_utf8(sql.0).ok())
+        }
+        _ => {
+            error!(
+                "Bootstrap SQL {} must reference either a configMapRef or a secretRef",
+                script.name
+            );
+            return Err(Action::requeue(Duration::from_secs(300)));
+        }
+    };
+    sql.ok_or_else(|| {
+        // The script may be created after the instance, try again later
+        error!(
+            "Bootstrap SQL {} not found in namespace {}",
+            script.name, namespace
+        );
+        Action::requeue(Duration::from_secs(60))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(name: &str) -> BootstrapSql {
+        BootstrapSql {
+            name: name.to_string(),
+            ..BootstrapSql::default()
+        }
+    }
+
+    #[test]
+    fn test_pending_scripts() {
+        let scripts = vec![script("schema"), script("roles"), script("grants")];
+        let pending = pending_scripts(&scripts, &["roles".to_string()]);
+        assert_eq!(pending, vec![&scripts[0], &scripts[2]]);
+
+        let applied: Vec<String> = scripts.iter().map(|s| s.name.clone()).collect();
+        assert!(pending_scripts(&scripts, &applied).is_empty());
+        assert!(pending_scripts(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,505 @@
+use crate::{
+    apis::coredb_types::{
+        AdditionalBackupDestination, AdditionalBackupDestinationStatus, AzureCredentials, CoreDB,
+        GoogleCredentials, S3Credentials,
+    },
+    cloudnativepg::objectstore::ObjectStoreProvider,
+    config::Config,
+    Context,
+};
+use k8s_openapi::api::{
+    batch::v1::{CronJob, CronJobSpec, JobSpec, JobTemplateSpec},
+    core::v1::{Container, EnvVar, EnvVarSource, PodSpec, PodTemplateSpec, SecretKeySelector},
+};
+use kube::{
+    api::{DeleteParams, ObjectMeta, Patch, PatchParams},
+    runtime::controller::Action,
+    Api, Resource, ResourceExt,
+};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::Duration;
+use tracing::{debug, error, warn};
+
+pub(super) const RCLONE_IMAGE: &str = "rclone/rclone:1.66";
+
+// The rclone remotes are configured from the environment, see
+// https://rclone.org/docs/#config-file and https://rclone.org/docs/#environment-variables
+pub(super) const SOURCE_REMOTE: &str = "SOURCE";
+const DESTINATION_REMOTE: &str = "DESTINATION";
+
+// reconcile_additional_backup_destination copies the base backups and WAL archive of the
+// instance to spec.backup.additionalDestination with a CronJob, since the Cluster archives
+// to a single object store. The CronJob runs with the service account of the Cluster, so
+// role based credentials work for both object stores. Returns the status of the copies.
+pub async fn reconcile_additional_backup_destination(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    cfg: &Config,
+) -> Result<Option<AdditionalBackupDestinationStatus>, Action> {
+    let namespace = cdb.namespace().unwrap();
+    let cronjobs: Api<CronJob> = Api::namespaced(ctx.client.clone(), &namespace);
+    let name = cronjob_name(cdb);
+
+    let additional_destination = cdb
+        .spec
+        .backup
+        .additional_destination
+        .as_ref()
+        .filter(|_| cfg.enable_backup);
+    let cronjob = additional_destination.and_then(|destination| copy_cronjob(cdb, destination));
+    let (Some(destination), Some(cronjob)) = (additional_destination, cronjob) else {
+        delete_cronjob(&cronjobs, &name).await?;
+        return Ok(None);
+    };
+
+    let patch_params = PatchParams::apply("cntrlr").force();
+    let cronjob = cronjobs
+        .patch(&name, &patch_params, &Patch::Apply(&cronjob))
+        .await
+        .map_err(|e| {
+            error!("Error applying CronJob {} in {}: {:?}", name, namespace, e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+    debug!("Applied CronJob {} in {}", name, namespace);
+
+    let status = cronjob.status.unwrap_or_default();
+    Ok(Some(AdditionalBackupDestinationStatus {
+        destination_path: destination.destination_path.clone(),
+        last_copy_scheduled: status.last_schedule_time.map(|t| t.0),
+        last_successful_copy: status.last_successful_time.map(|t| t.0),
+    }))
+}
+
+fn cronjob_name(cdb: &CoreDB) -> String {
+    format!("{}-backup-copy", cdb.name_any())
+}
+
+pub(super) async fn delete_cronjob(cronjobs: &Api<CronJob>, name: &str) -> Result<(), Action> {
+    match cronjobs.delete(name, &DeleteParams::background()).await {
+        Ok(_) => {
+            debug!("Deleted CronJob {}", name);
+            Ok(())
+        }
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+        Err(e) => {
+            error!("Error deleting CronJob {}: {:?}", name, e);
+            Err(Action::requeue(Duration::from_secs(300)))
+        }
+    }
+}
+
+// Build the CronJob copying the backups, None when either object store can not be copied with
+fn copy_cronjob(cdb: &CoreDB, destination: &AdditionalBackupDestination) -> Option<CronJob> {
+    let name = cdb.name_any();
+    let backup = &cdb.spec.backup;
+    // The Cluster keeps its backups under <destinationPath>/<cluster name>
+    let source = rclone_remote(
+        SOURCE_REMOTE,
+        backup.destinationPath.as_deref()?,
+        &name,
+        backup.endpoint_url.as_deref(),
+        Credentials {
+            s3: backup.s3_credentials.as_ref(),
+            google: backup.google_credentials.as_ref(),
+            azure: backup.azure_credentials.as_ref(),
+        },
+    )?;
+    let target = rclone_remote(
+        DESTINATION_REMOTE,
+        &destination.destination_path,
+        &name,
+        destination.endpoint_url.as_deref(),
+        Credentials {
+            s3: destination.s3_credentials.as_ref(),
+            google: destination.google_credentials.as_ref(),
+            azure: destination.azure_credentials.as_ref(),
+        },
+    )?;
+
+    let labels = BTreeMap::from([
+        ("app".to_string(), "backup-copy".to_string()),
+        ("coredb.io/name".to_string(), name.clone()),
+    ]);
+    let env = source.env.into_iter().chain(target.env).collect();
+
+    let mut metadata = ObjectMeta {
+        name: Some(cronjob_name(cdb)),
+        namespace: cdb.namespace(),
+        labels: Some(labels.clone()),
+        owner_references: Some(vec![cdb.controller_owner_ref(&()).unwrap()]),
+        ..ObjectMeta::default()
+    };
+    cdb.spec.apply_common_metadata(&mut metadata);
+
+    Some(CronJob {
+        metadata,
+        spec: Some(CronJobSpec {
+            schedule: destination.schedule.clone(),
+            concurrency_policy: Some("Forbid".to_string()),
+            suspend: Some(cdb.spec.stop),
+            successful_jobs_history_limit: Some(1),
+            failed_jobs_history_limit: Some(3),
+            job_template: JobTemplateSpec {
+                metadata: None,
+                spec: Some(JobSpec {
+                    backoff_limit: Some(2),
+                    template: PodTemplateSpec {
+                        metadata: Some(ObjectMeta {
+                            labels: Some(labels),
+                            ..ObjectMeta::default()
+                        }),
+                        spec: Some(PodSpec {
+                            service_account_name: Some(name),
+                            restart_policy: Some("Never".to_string()),
+                            containers: vec![Container {
+                                name: "rclone".to_string(),
+                                image: Some(RCLONE_IMAGE.to_string()),
+                                args: Some(vec![
+                                    "copy".to_string(),
+                                    "--verbose".to_string(),
+                                    source.path,
+                                    target.path,
+                                ]),
+                                env: Some(env),
+                                ..Container::default()
+                            }],
+                            ..PodSpec::default()
+                        }),
+                    },
+                    ..JobSpec::default()
+                }),
+            },
+            ..CronJobSpec::default()
+        }),
+        status: None,
+    })
+}
+
+pub(super) struct Credentials<'a> {
+    pub s3: Option<&'a S3Credentials>,
+    pub google: Option<&'a GoogleCredentials>,
+    pub azure: Option<&'a AzureCredentials>,
+}
+
+// An rclone remote path and the environment configuring the remote
+#[derive(Debug)]
+pub(super) struct RcloneRemote {
+    pub path: String,
+    pub env: Vec<EnvVar>,
+}
+
+// rclone_remote configures an rclone remote for a barman destination path, None when the
+// path or the credentials are not supported
+pub(super) fn rclone_remote(
+    remote: &str,
+    destination_path: &str,
+    server_name: &str,
+    endpoint_url: Option<&str>,
+    credentials: Credentials,
+) -> Option<RcloneRemote> {
+    let provider = ObjectStoreProvider::from_destination_path(destination_path)?;
+    let (host, bucket_path) = split_destination_path(destination_path)?;
+
+    let mut env = vec![];
+    match provider {
+        ObjectStoreProvider::S3 => {
+            env.push(env_var(remote, "TYPE", "s3"));
+            match endpoint_url {
+                Some(endpoint_url) => {
+                    env.push(env_var(remote, "PROVIDER", "Other"));
+                    env.push(env_var(remote, "ENDPOINT", endpoint_url));
+                }
+                None => env.push(env_var(remote, "PROVIDER", "AWS")),
+            }
+            match credentials.s3 {
+                Some(S3Credentials {
+                    access_key_id: Some(access_key_id),
+                    secret_access_key: Some(secret_access_key),
+                    region,
+                    session_token,
+                    ..
+                }) => {
+                    env.push(secret_env_var(
+                        remote,
+                        "ACCESS_KEY_ID",
+                        &access_key_id.name,
+                        &access_key_id.key,
+                    ));
+                    env.push(secret_env_var(
+                        remote,
+                        "SECRET_ACCESS_KEY",
+                        &secret_access_key.name,
+                        &secret_access_key.key,
+                    ));
+                    if let Some(region) = region {
+                        env.push(secret_env_var(remote, "REGION", &region.name, &region.key));
+                    }
+                    if let Some(session_token) = session_token {
+                        env.push(secret_env_var(
+                            remote,
+                            "SESSION_TOKEN",
+                            &session_token.name,
+                            &session_token.key,
+                        ));
+                    }
+                }
+                // Like the Cluster, default to the IAM role of the service account
+                _ => env.push(env_var(remote, "ENV_AUTH", "true")),
+            }
+        }
+        ObjectStoreProvider::Gcs => {
+            env.push(env_var(remote, "TYPE", "google cloud storage"));
+            env.push(env_var(remote, "BUCKET_POLICY_ONLY", "true"));
+            match credentials
+                .google
+                .and_then(|google| google.application_credentials.as_ref())
+            {
+                Some(application_credentials) => env.push(secret_env_var(
+                    remote,
+                    "SERVICE_ACCOUNT_CREDENTIALS",
+                    &application_credentials.name,
+                    &application_credentials.key,
+                )),
+                None => env.push(env_var(remote, "ENV_AUTH", "true")),
+            }
+        }
+        ObjectStoreProvider::Azure => {
+            env.push(env_var(remote, "TYPE", "azureblob"));
+            let azure = credentials.azure.cloned().unwrap_or_default();
+            match (&azure.storage_account, &azure.storage_key) {
+                (Some(storage_account), Some(storage_key)) => {
+                    env.push(secret_env_var(
+                        remote,
+                        "ACCOUNT",
+                        &storage_account.name,
+                        &storage_account.key,
+                    ));
+                    env.push(secret_env_var(
+                        remote,
+                        "KEY",
+                        &storage_key.name,
+                        &storage_key.key,
+                    ));
+                }
+                _ if azure.inherit_from_azure_ad.unwrap_or(false) => {
+                    // The storage account is the first label of <account>.blob.core.windows.net
+                    let account = host.split('.').next().filter(|a| !a.is_empty())?;
+                    env.push(env_var(remote, "ACCOUNT", account));
+                    env.push(env_var(remote, "ENV_AUTH", "true"));
+                }
+                _ => {
+                    warn!(
+                        "Backups in {} can only be copied with a storage account key or Azure AD",
+                        destination_path
+                    );
+                    return None;
+                }
+            }
+        }
+    }
+
+    Some(RcloneRemote {
+        path: format!(
+            "{}:{}/{}",
+            remote.to_lowercase(),
+            bucket_path.trim_end_matches('/'),
+            server_name
+        ),
+        env,
+    })
+}
+
+// Split a destination path into its host and the bucket or container path, e.g.
+// s3://bucket/path -> ("bucket", "bucket/path") and
+// https://account.blob.core.windows.net/container/path -> ("account.blob.core.windows.net", "container/path")
+fn split_destination_path(destination_path: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = destination_path.split_once("://")?;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let bucket_path = if scheme == "https" { path } else { rest };
+    if bucket_path.trim_matches('/').is_empty() {
+        return None;
+    }
+    Some((host, bucket_path))
+}
+
+fn env_var(remote: &str, option: &str, value: &str) -> EnvVar {
+    EnvVar {
+        name: format!("RCLONE_CONFIG_{}_{}", remote, option),
+        value: Some(value.to_string()),
+        ..EnvVar::default()
+    }
+}
+
+fn secret_env_var(remote: &str, option: &str, secret_name: &str, secret_key: &str) -> EnvVar {
+    EnvVar {
+        name: format!("RCLONE_CONFIG_{}_{}", remote, option),
+        value_from: Some(EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                name: Some(secret_name.to_string()),
+                key: secret_key.to_string(),
+                ..SecretKeySelector::default()
+            }),
+            ..EnvVarSource::default()
+        }),
+        ..EnvVar::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::{
+        AzureCredentialsStorageAccount, AzureCredentialsStorageKey, S3CredentialsAccessKeyId,
+        S3CredentialsSecretAccessKey,
+    };
+
+    fn no_credentials<'a>() -> Credentials<'a> {
+        Credentials {
+            s3: None,
+            google: None,
+            azure: None,
+        }
+    }
+
+    fn env_value<'a>(remote: &'a RcloneRemote, name: &str) -> Option<&'a str> {
+        remote
+            .env
+            .iter()
+            .find(|e| e.name == name)
+            .and_then(|e| e.value.as_deref())
+    }
+
+    #[test]
+    fn test_split_destination_path() {
+        assert_eq!(
+            split_destination_path("s3://bucket/v2/"),
+            Some(("bucket", "bucket/v2/"))
+        );
+        assert_eq!(
+            split_destination_path("https://account.blob.core.windows.net/container/v2"),
+            Some(("account.blob.core.windows.net", "container/v2"))
+        );
+        assert_eq!(split_destination_path("s3://"), None);
+        assert_eq!(
+            split_destination_path("https://account.blob.core.windows.net/"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rclone_remote_s3() {
+        let remote = rclone_remote(
+            SOURCE_REMOTE,
+            "s3://bucket/v2/",
+            "test-db",
+            None,
+            no_credentials(),
+        )
+        .unwrap();
+        assert_eq!(remote.path, "source:bucket/v2/test-db");
+        assert_eq!(env_value(&remote, "RCLONE_CONFIG_SOURCE_TYPE"), Some("s3"));
+        assert_eq!(
+            env_value(&remote, "RCLONE_CONFIG_SOURCE_ENV_AUTH"),
+            Some("true")
+        );
+
+        let s3 = S3Credentials {
+            access_key_id: Some(S3CredentialsAccessKeyId {
+                name: "dr-credentials".to_string(),
+                key: "ACCESS_KEY_ID".to_string(),
+            }),
+            secret_access_key: Some(S3CredentialsSecretAccessKey {
+                name: "dr-credentials".to_string(),
+                key: "SECRET_ACCESS_KEY".to_string(),
+            }),
+            ..S3Credentials::default()
+        };
+        let remote = rclone_remote(
+            DESTINATION_REMOTE,
+            "s3://dr-bucket/v2",
+            "test-db",
+            Some("https://minio.example.com"),
+            Credentials {
+                s3: Some(&s3),
+                ..no_credentials()
+            },
+        )
+        .unwrap();
+        assert_eq!(remote.path, "destination:dr-bucket/v2/test-db");
+        assert_eq!(
+            env_value(&remote, "RCLONE_CONFIG_DESTINATION_ENDPOINT"),
+            Some("https://minio.example.com")
+        );
+        assert_eq!(
+            env_value(&remote, "RCLONE_CONFIG_DESTINATION_ENV_AUTH"),
+            None
+        );
+        let access_key_id = remote
+            .env
+            .iter()
+            .find(|e| e.name == "RCLONE_CONFIG_DESTINATION_ACCESS_KEY_ID")
+            .and_then(|e| e.value_from.as_ref())
+            .and_then(|v| v.secret_key_ref.as_ref())
+            .unwrap();
+        assert_eq!(access_key_id.name.as_deref(), Some("dr-credentials"));
+        assert_eq!(access_key_id.key, "ACCESS_KEY_ID");
+    }
+
+    #[test]
+    fn test_rclone_remote_azure() {
+        let azure = AzureCredentials {
+            inherit_from_azure_ad: Some(true),
+            ..AzureCredentials::default()
+        };
+        let remote = rclone_remote(
+            DESTINATION_REMOTE,
+            "https://account.blob.core.windows.net/container/v2",
+            "test-db",
+            None,
+            Credentials {
+                azure: Some(&azure),
+                ..no_credentials()
+            },
+        )
+        .unwrap();
+        assert_eq!(remote.path, "destination:container/v2/test-db");
+        assert_eq!(
+            env_value(&remote, "RCLONE_CONFIG_DESTINATION_ACCOUNT"),
+            Some("account")
+        );
+
+        let azure = AzureCredentials {
+            storage_account: Some(AzureCredentialsStorageAccount {
+                name: "azure-creds".to_string(),
+                key: "AZURE_STORAGE_ACCOUNT".to_string(),
+            }),
+            storage_key: Some(AzureCredentialsStorageKey {
+                name: "azure-creds".to_string(),
+                key: "AZURE_STORAGE_KEY".to_string(),
+            }),
+            ..AzureCredentials::default()
+        };
+        let remote = rclone_remote(
+            DESTINATION_REMOTE,
+            "https://account.blob.core.windows.net/container/v2",
+            "test-db",
+            None,
+            Credentials {
+                azure: Some(&azure),
+                ..no_credentials()
+            },
+        )
+        .unwrap();
+        assert_eq!(remote.env.len(), 3);
+
+        // Connection strings and SAS tokens can not be used with rclone
+        assert!(rclone_remote(
+            DESTINATION_REMOTE,
+            "https://account.blob.core.windows.net/container/v2",
+            "test-db",
+            None,
+            no_credentials(),
+        )
+        .is_none());
+    }
+}
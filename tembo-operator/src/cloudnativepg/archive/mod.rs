@@ -1 +1,3 @@
+pub(crate) mod additional_destination;
+pub(crate) mod storage_usage;
 pub(crate) mod wal;
@@ -0,0 +1,270 @@
+use crate::{
+    apis::coredb_types::{CoreDB, ObjectStorageUsage},
+    cloudnativepg::archive::additional_destination::{
+        delete_cronjob, rclone_remote, Credentials, RCLONE_IMAGE, SOURCE_REMOTE,
+    },
+    config::Config,
+    Context,
+};
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::{
+    batch::v1::{CronJob, CronJobSpec, JobSpec, JobTemplateSpec},
+    core::v1::{Container, Pod, PodSpec, PodTemplateSpec},
+};
+use kube::{
+    api::{ListParams, LogParams, ObjectMeta, Patch, PatchParams},
+    runtime::controller::Action,
+    Api, Resource, ResourceExt,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::Duration;
+use tracing::{debug, error, warn};
+
+const APP_LABEL: &str = "storage-usage";
+
+// Each line of the measurement is the prefix followed by the output of `rclone size --json`
+const MEASURE_SCRIPT: &str = r#"set -e
+echo "base $(rclone size --json "$0/base")"
+echo "wals $(rclone size --json "$0/wals")""#;
+
+// reconcile_object_storage_usage measures the size of the base backups and WAL archive of the
+// instance with a CronJob on the schedule of the operator config, so users can see the backup
+// storage they are billed for. Returns the most recent measurement.
+pub async fn reconcile_object_storage_usage(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    cfg: &Config,
+) -> Result<Option<ObjectStorageUsage>, Action> {
+    let namespace = cdb.namespace().unwrap();
+    let cronjobs: Api<CronJob> = Api::namespaced(ctx.client.clone(), &namespace);
+    let name = cronjob_name(cdb);
+
+    let cronjob = cfg
+        .storage_usage_schedule
+        .as_ref()
+        .filter(|_| cfg.enable_backup)
+        .and_then(|schedule| measure_cronjob(cdb, schedule));
+    let (Some(cronjob), Some(destination_path)) =
+        (cronjob, cdb.spec.backup.destinationPath.as_ref())
+    else {
+        delete_cronjob(&cronjobs, &name).await?;
+        return Ok(None);
+    };
+
+    let patch_params = PatchParams::apply("cntrlr").force();
+    cronjobs
+        .patch(&name, &patch_params, &Patch::Apply(&cronjob))
+        .await
+        .map_err(|e| {
+            error!("Error applying CronJob {} in {}: {:?}", name, namespace, e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+    debug!("Applied CronJob {} in {}", name, namespace);
+
+    // The Job history keeps the pod of the most recent successful measurement
+    let pods: Api<Pod> = Api::namespaced(ctx.client.clone(), &namespace);
+    let lp = ListParams::default().labels(&format!(
+        "app={},coredb.io/name={}",
+        APP_LABEL,
+        cdb.name_any()
+    ));
+    let pod_list = pods.list(&lp).await.map_err(|e| {
+        error!("Error listing storage usage pods in {}: {:?}", namespace, e);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    // Until a measurement succeeds, keep the previous one
+    let previous = cdb
+        .status
+        .as_ref()
+        .and_then(|status| status.object_storage_usage.clone());
+    let Some(pod) = pod_list
+        .items
+        .into_iter()
+        .filter(|pod| pod.status.as_ref().and_then(|s| s.phase.as_deref()) == Some("Succeeded"))
+        .max_by_key(|pod| pod.status.as_ref().and_then(|s| s.start_time.clone()))
+    else {
+        return Ok(previous);
+    };
+
+    let logs = pods
+        .logs(&pod.name_any(), &LogParams::default())
+        .await
+        .map_err(|e| {
+            error!("Error getting logs of pod {}: {:?}", pod.name_any(), e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+    let Some((base_backups_bytes, wal_bytes)) = parse_measurement(&logs) else {
+        warn!("Unexpected storage usage output of pod {}", pod.name_any());
+        return Ok(previous);
+    };
+    Ok(Some(ObjectStorageUsage {
+        destination_path: destination_path.clone(),
+        base_backups_bytes,
+        wal_bytes,
+        measured_at: finished_at(&pod).unwrap_or_else(Utc::now),
+    }))
+}
+
+fn cronjob_name(cdb: &CoreDB) -> String {
+    format!("{}-storage-usage", cdb.name_any())
+}
+
+// Build the CronJob measuring the backups, None when the object store can not be measured
+fn measure_cronjob(cdb: &CoreDB, schedule: &str) -> Option<CronJob> {
+    let name = cdb.name_any();
+    let backup = &cdb.spec.backup;
+    // The Cluster keeps its backups under <destinationPath>/<cluster name>
+    let source = rclone_remote(
+        SOURCE_REMOTE,
+        backup.destinationPath.as_deref()?,
+        &name,
+        backup.endpoint_url.as_deref(),
+        Credentials {
+            s3: backup.s3_credentials.as_ref(),
+            google: backup.google_credentials.as_ref(),
+            azure: backup.azure_credentials.as_ref(),
+        },
+    )?;
+
+    let labels = BTreeMap::from([
+        ("app".to_string(), APP_LABEL.to_string()),
+        ("coredb.io/name".to_string(), name.clone()),
+    ]);
+
+    let mut metadata = ObjectMeta {
+        name: Some(cronjob_name(cdb)),
+        namespace: cdb.namespace(),
+        labels: Some(labels.clone()),
+        owner_references: Some(vec![cdb.controller_owner_ref(&()).unwrap()]),
+        ..ObjectMeta::default()
+    };
+    cdb.spec.apply_common_metadata(&mut metadata);
+
+    Some(CronJob {
+        metadata,
+        spec: Some(CronJobSpec {
+            schedule: schedule.to_string(),
+            concurrency_policy: Some("Forbid".to_string()),
+            suspend: Some(cdb.spec.stop),
+            successful_jobs_history_limit: Some(1),
+            failed_jobs_history_limit: Some(1),
+            job_template: JobTemplateSpec {
+                metadata: None,
+                spec: Some(JobSpec {
+                    backoff_limit: Some(2),
+                    template: PodTemplateSpec {
+                        metadata: Some(ObjectMeta {
+                            labels: Some(labels),
+                            ..ObjectMeta::default()
+                        }),
+                        spec: Some(PodSpec {
+                            service_account_name: Some(name),
+                            restart_policy: Some("Never".to_string()),
+                            containers: vec![Container {
+                                name: "rclone".to_string(),
+                                image: Some(RCLONE_IMAGE.to_string()),
+                                command: Some(vec!["sh".to_string(), "-c".to_string()]),
+                                args: Some(vec![MEASURE_SCRIPT.to_string(), source.path]),
+                                env: Some(source.env),
+                                ..Container::default()
+                            }],
+                            ..PodSpec::default()
+                        }),
+                    },
+                    ..JobSpec::default()
+                }),
+            },
+            ..CronJobSpec::default()
+        }),
+        status: None,
+    })
+}
+
+#[derive(Deserialize)]
+struct RcloneSize {
+    bytes: u64,
+}
+
+// Parse the sizes of the base backups and the WAL archive from the output of MEASURE_SCRIPT
+fn parse_measurement(logs: &str) -> Option<(u64, u64)> {
+    let size = |prefix: &str| {
+        logs.lines()
+            .filter_map(|line| line.strip_prefix(prefix))
+            .find_map(|json| serde_json::from_str::<RcloneSize>(json.trim()).ok())
+            .map(|size| size.bytes)
+    };
+    Some((size("base ")?, size("wals ")?))
+}
+
+fn finished_at(pod: &Pod) -> Option<DateTime<Utc>> {
+    pod.status
+        .as_ref()?
+        .container_statuses
+        .as_ref()?
+        .iter()
+        .find_map(|status| {
+            status
+                .state
+                .as_ref()?
+                .terminated
+                .as_ref()?
+                .finished_at
+                .clone()
+        })
+        .map(|time| time.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::{Backup, CoreDBSpec};
+
+    #[test]
+    fn test_parse_measurement() {
+        let logs = r#"base {"count":12,"bytes":1073741824,"sizeless":0}
+wals {"count":340,"bytes":5704253440,"sizeless":0}
+"#;
+        assert_eq!(parse_measurement(logs), Some((1073741824, 5704253440)));
+        assert_eq!(
+            parse_measurement(r#"base {"count":0,"bytes":0,"sizeless":0}"#),
+            None
+        );
+        assert_eq!(parse_measurement("Failed to size"), None);
+    }
+
+    #[test]
+    fn test_measure_cronjob() {
+        let mut cdb = CoreDB::new(
+            "test-db",
+            CoreDBSpec {
+                backup: Backup {
+                    destinationPath: Some("s3://bucket/v2/".to_string()),
+                    ..Backup::default()
+                },
+                ..CoreDBSpec::default()
+            },
+        );
+        cdb.metadata.namespace = Some("org-test".to_string());
+        cdb.metadata.uid = Some("uid".to_string());
+
+        let cronjob = measure_cronjob(&cdb, "0 */6 * * *").unwrap();
+        let spec = cronjob.spec.unwrap();
+        assert_eq!(spec.schedule, "0 */6 * * *");
+        let container = &spec
+            .job_template
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap()
+            .containers[0];
+        assert_eq!(
+            container.args.as_ref().unwrap()[1],
+            "source:bucket/v2/test-db"
+        );
+
+        cdb.spec.backup.destinationPath = None;
+        assert!(measure_cronjob(&cdb, "0 */6 * * *").is_none());
+    }
+}
@@ -962,6 +962,13 @@ pub struct ClusterBackupBarmanObjectStoreAzureCredentialsStorageSasToken {
 /// The configuration to be used to backup the data files When not defined, base backups files will be stored uncompressed and may be unencrypted in the object store, according to the bucket default policy.
 #[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 pub struct ClusterBackupBarmanObjectStoreData {
+    /// AdditionalCommandArgs represents additional arguments that can be appended to the 'barman-cloud-backup' command-line invocation. These arguments provide flexibility to customize the backup process further according to specific requirements or configurations.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "additionalCommandArgs"
+    )]
+    pub additional_command_args: Option<Vec<String>>,
     /// Compress a backup file (a tar file per tablespace) while streaming it to the object store. Available options are empty string (no compression, default), `gzip`, `bzip2` or `snappy`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub compression: Option<ClusterBackupBarmanObjectStoreDataCompression>,
@@ -1113,6 +1120,13 @@ pub struct ClusterBackupBarmanObjectStoreS3CredentialsSessionToken {
 /// The configuration for the backup of the WAL stream. When not defined, WAL files will be stored uncompressed and may be unencrypted in the object store, according to the bucket default policy.
 #[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 pub struct ClusterBackupBarmanObjectStoreWal {
+    /// Additional arguments that can be appended to the 'barman-cloud-wal-archive' command-line invocation. These arguments provide flexibility to customize the WAL archive process further, according to specific requirements or configurations.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "archiveAdditionalCommandArgs"
+    )]
+    pub archive_additional_command_args: Option<Vec<String>>,
     /// Compress a WAL file before sending it to the object store. Available options are empty string (no compression, default), `gzip`, `bzip2` or `snappy`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub compression: Option<ClusterBackupBarmanObjectStoreWalCompression>,
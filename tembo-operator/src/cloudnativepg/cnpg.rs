@@ -15,17 +15,21 @@ use super::clusters::{
     ClusterExternalClustersBarmanObjectStoreGoogleCredentialsApplicationCredentials,
     ClusterInheritedMetadata,
 };
+use super::objectstore::{restore_credentials, ObjectStoreProvider};
 use crate::apis::coredb_types::Restore;
 use crate::apis::coredb_types::{self, AzureCredentials, GoogleCredentials};
+use crate::disk_full::disk_full_parameters;
 use crate::extensions::install::find_trunk_installs_to_pod;
 use crate::ingress_route_crd::{
     IngressRoute, IngressRouteRoutes, IngressRouteRoutesKind, IngressRouteRoutesServices,
     IngressRouteRoutesServicesKind, IngressRouteSpec, IngressRouteTls,
 };
+use crate::postgres_versions::postgres_version_of;
+use crate::snapshots::volumesnapshots::reconcile_volume_snapshot_restore;
 use crate::{
     apis::{
         coredb_types::{CoreDB, S3Credentials},
-        postgres_parameters::MergeError,
+        postgres_parameters::{validate_pg_config, MergeError},
     },
     cloudnativepg::{
         backups::Backup,
@@ -43,8 +47,9 @@ use crate::{
             ClusterBackupVolumeSnapshotOnlineConfiguration,
             ClusterBackupVolumeSnapshotSnapshotOwnerReference, ClusterBootstrap,
             ClusterBootstrapInitdb, ClusterBootstrapRecovery,
-            ClusterBootstrapRecoveryRecoveryTarget, ClusterCertificates, ClusterExternalClusters,
-            ClusterExternalClustersBarmanObjectStore,
+            ClusterBootstrapRecoveryRecoveryTarget, ClusterBootstrapRecoveryVolumeSnapshots,
+            ClusterBootstrapRecoveryVolumeSnapshotsStorage, ClusterCertificates,
+            ClusterExternalClusters, ClusterExternalClustersBarmanObjectStore,
             ClusterExternalClustersBarmanObjectStoreS3Credentials,
             ClusterExternalClustersBarmanObjectStoreS3CredentialsAccessKeyId,
             ClusterExternalClustersBarmanObjectStoreS3CredentialsRegion,
@@ -55,9 +60,12 @@ use crate::{
             ClusterExternalClustersBarmanObjectStoreWalEncryption, ClusterExternalClustersPassword,
             ClusterLogLevel, ClusterManaged, ClusterManagedRoles, ClusterManagedRolesEnsure,
             ClusterManagedRolesPasswordSecret, ClusterMonitoring,
-            ClusterMonitoringCustomQueriesConfigMap, ClusterNodeMaintenanceWindow,
-            ClusterPostgresql, ClusterPostgresqlSyncReplicaElectionConstraint,
-            ClusterPrimaryUpdateMethod, ClusterPrimaryUpdateStrategy, ClusterReplicationSlots,
+            ClusterMonitoringCustomQueriesConfigMap, ClusterMonitoringPodMonitorMetricRelabelings,
+            ClusterMonitoringPodMonitorMetricRelabelingsAction,
+            ClusterMonitoringPodMonitorRelabelings, ClusterMonitoringPodMonitorRelabelingsAction,
+            ClusterNodeMaintenanceWindow, ClusterPostgresql,
+            ClusterPostgresqlSyncReplicaElectionConstraint, ClusterPrimaryUpdateMethod,
+            ClusterPrimaryUpdateStrategy, ClusterReplica, ClusterReplicationSlots,
             ClusterReplicationSlotsHighAvailability, ClusterResources,
             ClusterServiceAccountTemplate, ClusterServiceAccountTemplateMetadata, ClusterSpec,
             ClusterStorage, ClusterSuperuserSecret,
@@ -70,6 +78,7 @@ use crate::{
             Pooler, PoolerCluster, PoolerPgbouncer, PoolerSpec, PoolerTemplate, PoolerTemplateSpec,
             PoolerTemplateSpecContainers, PoolerType,
         },
+        pre_operation_backup::reconcile_pre_operation_backup,
         scheduledbackups::{
             ScheduledBackup, ScheduledBackupBackupOwnerReference, ScheduledBackupCluster,
             ScheduledBackupMethod, ScheduledBackupSpec,
@@ -78,8 +87,13 @@ use crate::{
     config::Config,
     configmap::custom_metrics_configmap_settings,
     errors::ValueError,
+    events::{
+        publish_event, REASON_BACKUP_CONFIG_ERROR, REASON_POOLER_CREATED, REASON_POOLER_DELETED,
+        REASON_POOLER_UPDATED,
+    },
     is_postgres_ready,
-    postgres_exporter::EXPORTER_CONFIGMAP_PREFIX,
+    postgres_exporter::{user_queries_configmap_name, EXPORTER_CONFIGMAP_PREFIX, USER_QUERIES},
+    prometheus::{is_silenced, podmonitor_crd::PodMonitor, silenced_drop_metrics, SILENCED_LABEL},
     psql::PsqlOutput,
     trunk::extensions_that_require_load,
     Context,
@@ -91,7 +105,7 @@ use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::apis::meta::v1::ObjectM
 use kube::api::PostParams;
 use kube::{
     api::{DeleteParams, ListParams, Patch, PatchParams},
-    runtime::{controller::Action, wait::Condition},
+    runtime::{controller::Action, events::EventType, wait::Condition},
     Api, Resource, ResourceExt,
 };
 use std::{collections::BTreeMap, sync::Arc};
@@ -103,6 +117,22 @@ pub struct PostgresConfig {
     pub shared_preload_libraries: Option<Vec<String>>,
 }
 
+// Arguments to encrypt the base backups and WAL files written to Google Cloud Storage with
+// the customer-managed key of the instance
+fn kms_key_args(cdb: &CoreDB) -> Option<Vec<String>> {
+    let backup = &cdb.spec.backup;
+    let is_gcs = backup
+        .destinationPath
+        .as_deref()
+        .and_then(ObjectStoreProvider::from_destination_path)
+        == Some(ObjectStoreProvider::Gcs);
+    backup
+        .kms_key_name
+        .as_ref()
+        .filter(|_| is_gcs)
+        .map(|key| vec![format!("--kms-key-name={}", key)])
+}
+
 fn create_cluster_backup_barman_data(cdb: &CoreDB) -> Option<ClusterBackupBarmanObjectStoreData> {
     let encryption = match &cdb.spec.backup.encryption {
         Some(encryption) => match encryption.as_str() {
@@ -117,6 +147,7 @@ fn create_cluster_backup_barman_data(cdb: &CoreDB) -> Option<ClusterBackupBarman
         compression: Some(ClusterBackupBarmanObjectStoreDataCompression::Snappy),
         encryption,
         immediate_checkpoint: Some(true),
+        additional_command_args: kms_key_args(cdb),
         ..ClusterBackupBarmanObjectStoreData::default()
     })
 }
@@ -131,11 +162,13 @@ fn create_cluster_backup_barman_wal(cdb: &CoreDB) -> Option<ClusterBackupBarmanO
         _ => None,
     };
 
-    if encryption.is_some() {
+    let archive_additional_command_args = kms_key_args(cdb);
+    if encryption.is_some() || archive_additional_command_args.is_some() {
         Some(ClusterBackupBarmanObjectStoreWal {
             compression: Some(ClusterBackupBarmanObjectStoreWalCompression::Snappy),
             encryption,
             max_parallel: Some(8),
+            archive_additional_command_args,
         })
     } else {
         None
@@ -378,7 +411,14 @@ pub fn cnpg_cluster_bootstrap_from_cdb(
     Option<Vec<ClusterExternalClusters>>,
     Option<ClusterSuperuserSecret>,
 ) {
-    let cluster_bootstrap = if cdb.spec.restore.is_some() {
+    // A standby is bootstrapped from the backups of the instance it follows, like a restore
+    let restore = cdb.spec.restore.clone().or_else(|| {
+        cdb.spec
+            .standby_of
+            .as_ref()
+            .map(|standby_of| standby_of.to_restore())
+    });
+    let cluster_bootstrap = if restore.is_some() {
         cnpg_cluster_bootstrap(cdb, true)
     } else {
         cnpg_cluster_bootstrap(cdb, false)
@@ -392,15 +432,15 @@ pub fn cnpg_cluster_bootstrap_from_cdb(
 
     let superuser_secret_name = format!("{}-connection", cluster_name);
 
-    let coredb_cluster = if let Some(restore) = &cdb.spec.restore {
-        // This generates the default is s3_credentials was none
-        let s3_credentials = generate_s3_restore_credentials(restore.s3_credentials.as_ref());
-        let google_credentials =
-            generate_gcs_restore_credentials(restore.google_credentials.as_ref());
-        let azure_credentials =
-            generate_azure_restore_credentials(restore.azure_credentials.as_ref());
+    let coredb_cluster = if let Some(restore) = &restore {
         // Find destination_path from Backup to generate the restore destination path
         let restore_destination_path = generate_restore_destination_path(restore, &cdb.spec.backup);
+        // The backups may be kept with another cloud provider than this instance, only pass
+        // the credentials for the provider of the restore destination path
+        let credentials = restore_credentials(restore, &cdb.spec.backup, &restore_destination_path);
+        let s3_credentials = generate_s3_restore_credentials(credentials.s3_credentials);
+        let google_credentials = generate_gcs_restore_credentials(credentials.google_credentials);
+        let azure_credentials = generate_azure_restore_credentials(credentials.azure_credentials);
         ClusterExternalClusters {
             name: "tembo-recovery".to_string(),
             barman_object_store: Some(ClusterExternalClustersBarmanObjectStore {
@@ -480,8 +520,7 @@ fn cnpg_cluster_bootstrap(cdb: &CoreDB, restore: bool) -> ClusterBootstrap {
                         ..ClusterBootstrapRecoveryRecoveryTarget::default()
                     }
                 }),
-                // TODO: reenable this once we have a work around for snapshots
-                // volume_snapshots: cnpg_cluster_bootstrap_recovery_volume_snapshots(cdb),
+                volume_snapshots: cnpg_cluster_bootstrap_recovery_volume_snapshots(cdb),
                 ..ClusterBootstrapRecovery::default()
             }),
             ..ClusterBootstrap::default()
@@ -496,38 +535,75 @@ fn cnpg_cluster_bootstrap(cdb: &CoreDB, restore: bool) -> ClusterBootstrap {
     }
 }
 
-// TODO: reenable this once we have a work around for snapshots
-// fn cnpg_cluster_bootstrap_recovery_volume_snapshots(
-//     _cdb: &CoreDB,
-// ) -> Option<ClusterBootstrapRecoveryVolumeSnapshots> {
-//     if let Some(restore) = &cdb.spec.restore {
-//         if restore.volume_snapshot == Some(true) {
-//             return Some(ClusterBootstrapRecoveryVolumeSnapshots {
-//                 storage: ClusterBootstrapRecoveryVolumeSnapshotsStorage {
-//                     // todo: Work on getting this from the VolumeSnapshot we created
-//                     // during the restore process
-//                     name: format!("{}-restore-vs", cdb.name_any()),
-//                     kind: "VolumeSnapshot".to_string(),
-//                     api_group: Some("snapshot.storage.k8s.io".to_string()),
-//                 },
-//                 ..ClusterBootstrapRecoveryVolumeSnapshots::default()
-//             });
-//         }
-//     }
-//     None
-// }
+// Bootstrap the volumes from the VolumeSnapshot created by reconcile_volume_snapshot_restore,
+// WAL is replayed from the source instance's backups from there on
+fn cnpg_cluster_bootstrap_recovery_volume_snapshots(
+    cdb: &CoreDB,
+) -> Option<ClusterBootstrapRecoveryVolumeSnapshots> {
+    let restore = cdb.spec.restore.as_ref()?;
+    if !restore.uses_volume_snapshot() {
+        return None;
+    }
+    Some(ClusterBootstrapRecoveryVolumeSnapshots {
+        storage: ClusterBootstrapRecoveryVolumeSnapshotsStorage {
+            name: format!("{}-restore-vs", cdb.name_any()),
+            kind: "VolumeSnapshot".to_string(),
+            api_group: Some("snapshot.storage.k8s.io".to_string()),
+        },
+        ..ClusterBootstrapRecoveryVolumeSnapshots::default()
+    })
+}
+
+// A standby runs in replica mode, replaying WAL from the backups of the instance it follows
+// through the recovery external cluster. Disabling replica mode promotes it.
+fn cnpg_replica(cdb: &CoreDB) -> Option<ClusterReplica> {
+    let standby_of = cdb.spec.standby_of.as_ref()?;
+    Some(ClusterReplica {
+        enabled: !standby_of.promote,
+        source: "tembo-recovery".to_string(),
+    })
+}
 
 // Get PGConfig from CoreDB and convert it to a postgres_parameters and shared_preload_libraries
 fn cnpg_postgres_config(
     cdb: &CoreDB,
     requires_load: BTreeMap<String, String>,
 ) -> Result<PostgresConfig, MergeError> {
+    let postgres_version = postgres_version_of(cdb);
     match cdb.spec.get_pg_configs(requires_load) {
         Ok(Some(pg_configs)) => {
             let mut postgres_parameters: BTreeMap<String, String> = BTreeMap::new();
             let mut shared_preload_libraries: Vec<String> = Vec::new();
 
             for pg_config in pg_configs {
+                // Parameters the Postgres version does not have would keep it from starting
+                if let Some(version) = postgres_version {
+                    if !version.supports_guc(&pg_config.name) {
+                        warn!(
+                            "Leaving out {} from the configuration of {}, it is not available in Postgres {}",
+                            pg_config.name,
+                            cdb.name_any(),
+                            version.major
+                        );
+                        continue;
+                    }
+                }
+                // So would invalid values, like a typo in a unit
+                let pg_config = match validate_pg_config(
+                    &pg_config,
+                    postgres_version.map(|version| version.major),
+                ) {
+                    Ok(pg_config) => pg_config,
+                    Err(reason) => {
+                        warn!(
+                            "Leaving out {} from the configuration of {}: {}",
+                            pg_config.name,
+                            cdb.name_any(),
+                            reason
+                        );
+                        continue;
+                    }
+                };
                 match &pg_config.name[..] {
                     "shared_preload_libraries" => {
                         shared_preload_libraries.push(pg_config.value.to_string());
@@ -578,7 +654,7 @@ fn cnpg_cluster_storage(cdb: &CoreDB) -> Option<ClusterStorage> {
     })
 }
 
-fn cnpg_cluster_storage_class(cdb: &CoreDB) -> Option<String> {
+pub(crate) fn cnpg_cluster_storage_class(cdb: &CoreDB) -> Option<String> {
     match &cdb.spec.storage_class {
         Some(storage_class) if !storage_class.is_empty() => Some(storage_class.clone()),
         _ => None,
@@ -622,22 +698,73 @@ fn default_cluster_annotations(cdb: &CoreDB) -> BTreeMap<String, String> {
 // Check if the cluster has azure credentials and return the inherited metadata
 // required for workload identity
 fn inherited_metadata(cdb: &CoreDB) -> Option<ClusterInheritedMetadata> {
+    let mut metadata = ObjectMeta::default();
     if let Some(azure_creds) = &cdb.spec.backup.azure_credentials {
-        if azure_creds.inherit_from_azure_ad? {
-            return Some(ClusterInheritedMetadata {
-                annotations: None,
-                labels: Some(
-                    vec![(
-                        "azure.workload.identity/use".to_string(),
-                        "true".to_string(),
-                    )]
-                    .into_iter()
-                    .collect(),
-                ),
-            });
+        if azure_creds.inherit_from_azure_ad == Some(true) {
+            metadata.labels = Some(
+                vec![(
+                    "azure.workload.identity/use".to_string(),
+                    "true".to_string(),
+                )]
+                .into_iter()
+                .collect(),
+            );
         }
     }
-    None
+
+    // Pods and PVCs created by CNPG should also carry the common labels and annotations
+    cdb.spec.apply_common_metadata(&mut metadata);
+    if metadata.labels.is_none() && metadata.annotations.is_none() {
+        return None;
+    }
+    Some(ClusterInheritedMetadata {
+        annotations: metadata.annotations,
+        labels: metadata.labels,
+    })
+}
+
+// Build the PodMonitor relabelings for an instance that has alerts silenced. Every
+// scraped series is labeled with tembo_silenced="true" and, optionally, series matching
+// the drop regex are discarded before ingestion.
+fn cnpg_silenced_pod_monitor_relabelings(
+    cdb: &CoreDB,
+) -> (
+    Option<Vec<ClusterMonitoringPodMonitorRelabelings>>,
+    Option<Vec<ClusterMonitoringPodMonitorMetricRelabelings>>,
+) {
+    let annotations = cdb.annotations();
+    if !is_silenced(annotations) {
+        return (None, None);
+    }
+    let relabelings = vec![ClusterMonitoringPodMonitorRelabelings {
+        action: Some(ClusterMonitoringPodMonitorRelabelingsAction::Replace),
+        target_label: Some(SILENCED_LABEL.to_string()),
+        replacement: Some("true".to_string()),
+        ..ClusterMonitoringPodMonitorRelabelings::default()
+    }];
+    let metric_relabelings = silenced_drop_metrics(annotations).map(|regex| {
+        vec![ClusterMonitoringPodMonitorMetricRelabelings {
+            action: Some(ClusterMonitoringPodMonitorMetricRelabelingsAction::Drop),
+            source_labels: Some(vec!["__name__".to_string()]),
+            regex: Some(regex),
+            ..ClusterMonitoringPodMonitorMetricRelabelings::default()
+        }]
+    });
+    (Some(relabelings), metric_relabelings)
+}
+
+// CNPG generates the PodMonitor of the Cluster and offers no way to set its labels, so the
+// silenced label is merged into its metadata directly. A null value removes the label once
+// the instance is no longer silenced.
+fn cnpg_pod_monitor_labels_patch(cdb: &CoreDB) -> serde_json::Value {
+    let silenced = is_silenced(cdb.annotations()).then_some("true");
+    serde_json::json!({
+        "metadata": {
+            "labels": {
+                SILENCED_LABEL: silenced
+            }
+        }
+    })
 }
 
 #[instrument(skip(cdb), fields(trace_id, instance_name = %cdb.name_any()))]
@@ -672,6 +799,9 @@ pub fn cnpg_cluster_from_cdb(
         }
     };
 
+    // Keep the instance read only while its data volume is almost full
+    let postgres_parameters = disk_full_parameters(cdb, postgres_parameters);
+
     // Format fenced pods annotation if we have any
     if let Some(fenced_pods) = fenced_pods {
         let fenced_instances = format!("{:?}", fenced_pods);
@@ -698,6 +828,8 @@ pub fn cnpg_cluster_from_cdb(
 
     let instances = cdb.spec.replicas as i64;
     let primary_update_method = determine_primary_update_method(instances);
+    let (pod_monitor_relabelings, pod_monitor_metric_relabelings) =
+        cnpg_silenced_pod_monitor_relabelings(cdb);
 
     if cdb
         .spec
@@ -713,14 +845,30 @@ pub fn cnpg_cluster_from_cdb(
         })
     }
 
+    if cdb
+        .spec
+        .metrics
+        .as_ref()
+        .and_then(|m| m.queries_config_map_ref.as_ref())
+        .is_some()
+    {
+        metrics.push(ClusterMonitoringCustomQueriesConfigMap {
+            key: USER_QUERIES.to_string(),
+            name: user_queries_configmap_name(cdb),
+        })
+    }
+
+    let mut metadata = ObjectMeta {
+        name: Some(name.clone()),
+        namespace: Some(namespace),
+        annotations: Some(annotations),
+        owner_references: Some(vec![owner_reference]),
+        ..ObjectMeta::default()
+    };
+    cdb.spec.apply_common_metadata(&mut metadata);
+
     Cluster {
-        metadata: ObjectMeta {
-            name: Some(name.clone()),
-            namespace: Some(namespace),
-            annotations: Some(annotations),
-            owner_references: Some(vec![owner_reference]),
-            ..ObjectMeta::default()
-        },
+        metadata,
         spec: ClusterSpec {
             affinity,
             topology_spread_constraints,
@@ -743,6 +891,8 @@ pub fn cnpg_cluster_from_cdb(
                 custom_queries_config_map: Some(metrics),
                 disable_default_queries: Some(false),
                 enable_pod_monitor: Some(true),
+                pod_monitor_relabelings,
+                pod_monitor_metric_relabelings,
                 ..ClusterMonitoring::default()
             }),
             postgres_gid: Some(26),
@@ -763,6 +913,7 @@ pub fn cnpg_cluster_from_cdb(
             }),
             primary_update_method,
             primary_update_strategy: Some(ClusterPrimaryUpdateStrategy::Unsupervised),
+            replica: cnpg_replica(cdb),
             replication_slots: replication,
             resources: Some(ClusterResources {
                 claims: None,
@@ -1006,27 +1157,32 @@ pub async fn reconcile_cnpg(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Actio
     let pods_to_fence = pods_to_fence(cdb, ctx.clone()).await?;
     let requires_load = extensions_that_require_load(ctx.client.clone(), namespace).await?;
 
-    // TODO: reenable this once we have a work around for snapshots
-    // If we are restoring and have volume snapshots enabled, make sure we setup
-    // the VolumeSnapshotContent and VolumeSnapshot so that the Cluster will have
-    // something to restore from.
-    // if let Some(restore) = &cdb.spec.restore {
-    //     if restore.volume_snapshot == Some(true) {
-    //         debug!("Reconciling VolumeSnapshotContent and VolumeSnapshot for restore");
-    //         reconcile_volume_snapshot_restore(cdb, ctx.clone()).await?;
-    //     }
-    // }
+    let cluster_api: Api<Cluster> = Api::namespaced(ctx.client.clone(), namespace.as_str());
+    let maybe_cluster = cluster_api.get(&name).await;
+
+    // If we are restoring from a volume snapshot, make sure we setup the VolumeSnapshotContent
+    // and VolumeSnapshot before the Cluster is created, so that it has something to bootstrap
+    // its volumes from.
+    let restores_from_snapshot = cdb
+        .spec
+        .restore
+        .as_ref()
+        .is_some_and(|restore| restore.uses_volume_snapshot());
+    if maybe_cluster.is_err() && restores_from_snapshot {
+        debug!("Reconciling VolumeSnapshotContent and VolumeSnapshot for restore");
+        reconcile_volume_snapshot_restore(cdb, ctx.clone()).await?;
+    }
 
     debug!("Generating CNPG spec");
     let mut cluster = cnpg_cluster_from_cdb(cdb, Some(pods_to_fence), requires_load);
 
-    let cluster_api: Api<Cluster> = Api::namespaced(ctx.client.clone(), namespace.as_str());
-    let maybe_cluster = cluster_api.get(&name).await;
-
     // Check if we are updating the cluster to reboot/restart the instance, if so do that first before
     // updating the cluster spec.  Also check to see if the image is being updated.  If do
     // update the image first before updating the cluster spec.
     if let Ok(ref cluster) = maybe_cluster {
+        // Make sure there is a recovery point before a destructive change is applied
+        reconcile_pre_operation_backup(cdb, &ctx, cluster).await?;
+
         warn!("Cluster exists, checking if restart is required");
         restart_and_wait_for_restart(cdb, ctx.clone(), Some(cluster)).await?;
         is_image_updated(cdb, ctx.clone(), Some(cluster)).await?;
@@ -1166,10 +1322,35 @@ pub async fn reconcile_cnpg(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Actio
 
     reconcile_metrics_service(cdb, ctx.clone()).await?;
     reconcile_metrics_ingress_route(cdb, ctx.clone()).await?;
+    reconcile_cnpg_pod_monitor_labels(cdb, ctx.clone()).await?;
 
     Ok(())
 }
 
+// Label the PodMonitor generated by CNPG when alerts of the instance are silenced
+async fn reconcile_cnpg_pod_monitor_labels(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap();
+    let podmon_api: Api<PodMonitor> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    let patch = cnpg_pod_monitor_labels_patch(cdb);
+    match podmon_api
+        .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        Ok(_) => Ok(()),
+        // CNPG creates the PodMonitor after the Cluster, it will be labeled on a later reconcile
+        Err(kube::Error::Api(ae)) if ae.code == 404 => {
+            debug!("PodMonitor {} does not exist yet in {}", name, namespace);
+            Ok(())
+        }
+        Err(e) => {
+            error!("Error patching labels of PodMonitor {}: {}", name, e);
+            Err(Action::requeue(Duration::from_secs(300)))
+        }
+    }
+}
+
 pub async fn reconcile_metrics_ingress_route(
     cdb: &CoreDB,
     ctx: Arc<Context>,
@@ -1208,7 +1389,7 @@ pub async fn reconcile_metrics_ingress_route(
     let owner_reference = cdb.controller_owner_ref(&()).unwrap();
     let ingress_route_name = format!("{}-metrics", coredb_name);
 
-    let ingress_route = IngressRoute {
+    let mut ingress_route = IngressRoute {
         metadata: ObjectMeta {
             name: Some(ingress_route_name.clone()),
             namespace: Some(namespace.clone()),
@@ -1221,6 +1402,7 @@ pub async fn reconcile_metrics_ingress_route(
             tls: Some(IngressRouteTls::default()),
         },
     };
+    cdb.spec.apply_common_metadata(&mut ingress_route.metadata);
 
     let ingress_api: Api<IngressRoute> = Api::namespaced(client, &namespace);
     let _pp = PostParams::default();
@@ -1249,7 +1431,7 @@ pub async fn reconcile_metrics_service(cdb: &CoreDB, ctx: Arc<Context>) -> Resul
         ("role".to_string(), "primary".to_string()),
     ]);
 
-    let service = Service {
+    let mut service = Service {
         metadata: ObjectMeta {
             name: Some(name.clone()),
             namespace: Some(namespace.clone()),
@@ -1270,6 +1452,7 @@ pub async fn reconcile_metrics_service(cdb: &CoreDB, ctx: Arc<Context>) -> Resul
         }),
         ..Default::default()
     };
+    cdb.spec.apply_common_metadata(&mut service.metadata);
 
     debug!("Reconciling metrics service for {}", cdb.name_any());
     let ps = PatchParams::apply("cntrlr").force();
@@ -1308,7 +1491,7 @@ pub async fn reconcile_pooler(
     // If pooler is enabled, create or update
     if cdb.spec.connectionPooler.enabled {
         debug!("Configuraing pooler instance for {}", cdb.name_any());
-        let pooler = Pooler {
+        let mut pooler = Pooler {
             metadata: ObjectMeta {
                 name: Some(name.clone()),
                 namespace: Some(namespace.clone()),
@@ -1349,6 +1532,12 @@ pub async fn reconcile_pooler(
             },
             status: None,
         };
+        cdb.spec.apply_common_metadata(&mut pooler.metadata);
+
+        let existing_pooler = pooler_api.get_opt(&name).await.map_err(|e| {
+            error!("Error getting Pooler: {}", e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
 
         debug!("Patching Pooler {name}");
         let ps = PatchParams::apply("cntrlr").force();
@@ -1360,6 +1549,32 @@ pub async fn reconcile_pooler(
                 Action::requeue(Duration::from_secs(300))
             })?;
 
+        match existing_pooler {
+            None => {
+                publish_event(
+                    cdb,
+                    &ctx,
+                    EventType::Normal,
+                    REASON_POOLER_CREATED,
+                    "Reconciling",
+                    format!("Created connection pooler {}", name),
+                )
+                .await
+            }
+            Some(existing) if is_pooler_changed(&existing, &pooler) => {
+                publish_event(
+                    cdb,
+                    &ctx,
+                    EventType::Normal,
+                    REASON_POOLER_UPDATED,
+                    "Reconciling",
+                    format!("Updated connection pooler {}", name),
+                )
+                .await
+            }
+            Some(_) => {}
+        }
+
         // Check to see if the primary pod is ready, if it is the setup pgbouncer.  If the pod is
         // not ready then just continue on and wait for the next reconcile.
         let primary_pod = cdb.primary_pod_cnpg_ready_or_not(client.clone()).await?;
@@ -1401,12 +1616,31 @@ pub async fn reconcile_pooler(
                 error!("Error deleting Pooler: {}", e);
                 Action::requeue(Duration::from_secs(300))
             })?;
+            publish_event(
+                cdb,
+                &ctx,
+                EventType::Normal,
+                REASON_POOLER_DELETED,
+                "Reconciling",
+                format!("Deleted connection pooler {}", name),
+            )
+            .await;
         }
     }
 
     Ok(())
 }
 
+// Check if the settings the operator manages differ between the existing and desired Pooler
+fn is_pooler_changed(existing: &Pooler, desired: &Pooler) -> bool {
+    let existing_pgbouncer = &existing.spec.pgbouncer;
+    let desired_pgbouncer = &desired.spec.pgbouncer;
+    existing.spec.instances != desired.spec.instances
+        || existing_pgbouncer.parameters != desired_pgbouncer.parameters
+        || serde_json::to_value(&existing_pgbouncer.pool_mode).ok()
+            != serde_json::to_value(&desired_pgbouncer.pool_mode).ok()
+}
+
 // This function was created from the instructions that CNPG gives when you have to setup pgbouncer
 // manually.  You can read more here: https://cloudnative-pg.io/documentation/1.20/connection_pooling/#authentication
 const PGBOUNCER_SETUP_FUNCTION: &str = r#"
@@ -1638,6 +1872,15 @@ pub async fn reconcile_cnpg_scheduled_backup(
                 cdb.name_any(),
                 e
             );
+            publish_event(
+                cdb,
+                &ctx,
+                EventType::Warning,
+                REASON_BACKUP_CONFIG_ERROR,
+                "Reconciling",
+                format!("Invalid backup configuration: {}", e),
+            )
+            .await;
             return Err(Action::requeue(Duration::from_secs(300)));
         }
     };
@@ -3034,6 +3277,110 @@ mod tests {
         let _result: Cluster =
             serde_json::from_str(json_str).expect("Should be able to deserialize");
     }
+    #[test]
+    fn test_cnpg_standby_of() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test-standby
+          namespace: default
+        spec:
+          backup:
+            destinationPath: s3://aws-s3-bucket/v2/test-standby
+          standbyOf:
+            serverName: test
+            backupsPath: s3://other-region-bucket/v2/test
+            s3Credentials:
+              inheritFromIAMRole: true
+        "#;
+        let mut cdb: CoreDB = from_str(cdb_yaml).unwrap();
+        assert!(cdb.spec.is_standby());
+
+        let (bootstrap, external_clusters, _) = cnpg_cluster_bootstrap_from_cdb(&cdb);
+        let recovery = bootstrap.unwrap().recovery.unwrap();
+        assert_eq!(recovery.source, Some("tembo-recovery".to_string()));
+        let external_cluster = &external_clusters.unwrap()[0];
+        assert_eq!(external_cluster.name, "tembo-recovery");
+        let object_store = external_cluster.barman_object_store.as_ref().unwrap();
+        assert_eq!(
+            object_store.destination_path,
+            "s3://other-region-bucket/v2/test"
+        );
+        assert_eq!(object_store.server_name, Some("test".to_string()));
+
+        let replica = cnpg_replica(&cdb).unwrap();
+        assert!(replica.enabled);
+        assert_eq!(replica.source, "tembo-recovery");
+
+        // Promotion disables replica mode
+        cdb.spec.standby_of.as_mut().unwrap().promote = true;
+        assert!(!cdb.spec.is_standby());
+        assert!(!cnpg_replica(&cdb).unwrap().enabled);
+
+        cdb.spec.standby_of = None;
+        assert!(cnpg_replica(&cdb).is_none());
+    }
+
+    #[test]
+    fn test_cnpg_pod_monitor_silenced() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test-silenced
+          namespace: default
+          annotations:
+            tembo.io/silence-alerts: "true"
+        spec:
+          backup:
+            destinationPath: s3://aws-s3-bucket/v2/test-silenced
+        "#;
+        let mut cdb: CoreDB = from_str(cdb_yaml).unwrap();
+
+        let patch = cnpg_pod_monitor_labels_patch(&cdb);
+        assert_eq!(patch["metadata"]["labels"][SILENCED_LABEL], "true");
+        let (relabelings, _) = cnpg_silenced_pod_monitor_relabelings(&cdb);
+        assert_eq!(
+            relabelings.unwrap()[0].target_label,
+            Some(SILENCED_LABEL.to_string())
+        );
+
+        // Lifting the silence removes the label from the PodMonitor
+        cdb.metadata.annotations = None;
+        let patch = cnpg_pod_monitor_labels_patch(&cdb);
+        assert!(patch["metadata"]["labels"][SILENCED_LABEL].is_null());
+        assert_eq!(
+            patch["metadata"]["labels"].as_object().unwrap().len(),
+            1,
+            "the label should be removed explicitly"
+        );
+        assert!(cnpg_silenced_pod_monitor_relabelings(&cdb).0.is_none());
+    }
+
+    #[test]
+    fn test_kms_key_args() {
+        let mut cdb = CoreDB::new("test", coredb_types::CoreDBSpec::default());
+        cdb.spec.backup.destinationPath = Some("gs://backups/v2/org-acme-inst-test".to_string());
+        cdb.spec.backup.kms_key_name =
+            Some("projects/acme/locations/us/keyRings/tembo/cryptoKeys/backups".to_string());
+        let args = Some(vec![
+            "--kms-key-name=projects/acme/locations/us/keyRings/tembo/cryptoKeys/backups"
+                .to_string(),
+        ]);
+        assert_eq!(kms_key_args(&cdb), args);
+        assert_eq!(
+            create_cluster_backup_barman_wal(&cdb)
+                .unwrap()
+                .archive_additional_command_args,
+            args
+        );
+
+        // The key only applies to Google Cloud Storage
+        cdb.spec.backup.destinationPath = Some("s3://backups/v2/org-acme-inst-test".to_string());
+        assert_eq!(kms_key_args(&cdb), None);
+    }
+
     #[test]
     fn test_generate_restore_destination_path_null() {
         let backup = coredb_types::Backup {
@@ -3263,6 +3610,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cnpg_cluster_bootstrap_from_volume_snapshot() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test-clone
+          namespace: default
+        spec:
+          restore:
+            serverName: test
+            volumeSnapshotName: test-20240601120000
+        "#;
+        let cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+
+        let bootstrap = cnpg_cluster_bootstrap(&cdb, true);
+        let volume_snapshots = bootstrap
+            .recovery
+            .and_then(|recovery| recovery.volume_snapshots)
+            .expect("Bootstrap from volume snapshots");
+        assert_eq!(volume_snapshots.storage.name, "test-clone-restore-vs");
+        assert_eq!(volume_snapshots.storage.kind, "VolumeSnapshot");
+
+        // Restores without a snapshot replay WAL from object storage
+        let mut cdb = cdb;
+        cdb.spec.restore.as_mut().unwrap().volume_snapshot_name = None;
+        let bootstrap = cnpg_cluster_bootstrap(&cdb, true);
+        assert!(bootstrap.recovery.unwrap().volume_snapshots.is_none());
+    }
+
     #[test]
     fn test_cnpg_cluster_volume_snapshot() {
         let cdb_yaml = r#"
@@ -3499,6 +3876,21 @@ mod tests {
             reconcile_ttl: 30,
             reconcile_timestamp_ttl: 90,
             volume_snapshot_retention_period_days: 40,
+            watch_label_selector: None,
+            resync_jitter: 60,
+            max_concurrent_reconciles: 0,
+            error_backoff_base: 300,
+            error_backoff_max: 300,
+            enable_disk_full_protection: true,
+            disk_full_threshold_percent: 95,
+            disk_full_recovery_percent: 90,
+            disk_full_storage_increase_percent: 0,
+            idle_hibernation_after_sec: 0,
+            resource_profiles: Default::default(),
+            storage_usage_schedule: None,
+            external_secret_store: None,
+            external_secret_store_kind: "ClusterSecretStore".to_string(),
+            external_secret_key_prefix: String::new(),
         };
 
         // Test with backups enabled and valid path
@@ -3546,6 +3938,21 @@ mod tests {
             reconcile_ttl: 30,
             reconcile_timestamp_ttl: 90,
             volume_snapshot_retention_period_days: 40,
+            watch_label_selector: None,
+            resync_jitter: 60,
+            max_concurrent_reconciles: 0,
+            error_backoff_base: 300,
+            error_backoff_max: 300,
+            enable_disk_full_protection: true,
+            disk_full_threshold_percent: 95,
+            disk_full_recovery_percent: 90,
+            disk_full_storage_increase_percent: 0,
+            idle_hibernation_after_sec: 0,
+            resource_profiles: Default::default(),
+            storage_usage_schedule: None,
+            external_secret_store: None,
+            external_secret_store_kind: "ClusterSecretStore".to_string(),
+            external_secret_key_prefix: String::new(),
         };
         let (backup, template) = cnpg_backup_configuration(&cdb, &cfg_disabled);
         assert!(backup.is_none());
@@ -3695,6 +4102,21 @@ mod tests {
             reconcile_ttl: 30,
             reconcile_timestamp_ttl: 90,
             volume_snapshot_retention_period_days: 40,
+            watch_label_selector: None,
+            resync_jitter: 60,
+            max_concurrent_reconciles: 0,
+            error_backoff_base: 300,
+            error_backoff_max: 300,
+            enable_disk_full_protection: true,
+            disk_full_threshold_percent: 95,
+            disk_full_recovery_percent: 90,
+            disk_full_storage_increase_percent: 0,
+            idle_hibernation_after_sec: 0,
+            resource_profiles: Default::default(),
+            storage_usage_schedule: None,
+            external_secret_store: None,
+            external_secret_store_kind: "ClusterSecretStore".to_string(),
+            external_secret_key_prefix: String::new(),
         };
 
         // Test with backups enabled and valid path
@@ -3750,9 +4172,56 @@ mod tests {
             reconcile_ttl: 30,
             reconcile_timestamp_ttl: 90,
             volume_snapshot_retention_period_days: 40,
+            watch_label_selector: None,
+            resync_jitter: 60,
+            max_concurrent_reconciles: 0,
+            error_backoff_base: 300,
+            error_backoff_max: 300,
+            enable_disk_full_protection: true,
+            disk_full_threshold_percent: 95,
+            disk_full_recovery_percent: 90,
+            disk_full_storage_increase_percent: 0,
+            idle_hibernation_after_sec: 0,
+            resource_profiles: Default::default(),
+            storage_usage_schedule: None,
+            external_secret_store: None,
+            external_secret_store_kind: "ClusterSecretStore".to_string(),
+            external_secret_key_prefix: String::new(),
         };
         let (backup, template) = cnpg_backup_configuration(&cdb, &cfg_disabled);
         assert!(backup.is_none());
         assert!(template.is_some());
     }
+
+    #[test]
+    fn test_is_pooler_changed() {
+        use crate::cloudnativepg::poolers::PoolerPgbouncerPoolMode;
+
+        let pooler = |instances: i32, pool_mode: PoolerPgbouncerPoolMode| Pooler {
+            metadata: ObjectMeta::default(),
+            spec: PoolerSpec {
+                instances: Some(instances),
+                pgbouncer: PoolerPgbouncer {
+                    pool_mode: Some(pool_mode),
+                    ..PoolerPgbouncer::default()
+                },
+                ..PoolerSpec::default()
+            },
+            status: None,
+        };
+
+        let existing = pooler(1, PoolerPgbouncerPoolMode::Transaction);
+        assert!(!is_pooler_changed(
+            &existing,
+            &pooler(1, PoolerPgbouncerPoolMode::Transaction)
+        ));
+        assert!(is_pooler_changed(
+            &existing,
+            &pooler(2, PoolerPgbouncerPoolMode::Transaction)
+        ));
+        assert!(is_pooler_changed(
+            &existing,
+            &pooler(1, PoolerPgbouncerPoolMode::Session)
+        ));
+    }
 }
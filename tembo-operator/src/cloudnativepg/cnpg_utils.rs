@@ -1,5 +1,6 @@
+use crate::events::{publish_event, REASON_RESTARTED};
 pub use crate::{
-    apis::coredb_types::CoreDB,
+    apis::coredb_types::{CoreDB, PausableComponent},
     cloudnativepg::backups::Backup,
     cloudnativepg::clusters::{Cluster, ClusterStatusConditionsStatus},
     cloudnativepg::poolers::Pooler,
@@ -11,7 +12,7 @@ pub use crate::{
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use kube::{
     api::{DeleteParams, ListParams, Patch, PatchParams},
-    runtime::controller::Action,
+    runtime::{controller::Action, events::EventType},
     Api, ResourceExt,
 };
 use serde_json::json;
@@ -67,6 +68,15 @@ pub(crate) async fn restart_and_wait_for_restart(
 
         patch_cluster_merge(cdb, &ctx, restart_patch).await?;
         update_coredb_status(cdb, &ctx, false).await?;
+        publish_event(
+            cdb,
+            &ctx,
+            EventType::Normal,
+            REASON_RESTARTED,
+            "Restarting",
+            format!("Restarting instance requested at {}", cdb_restarted_at),
+        )
+        .await;
 
         info!(
             "Updated status.running to false in {}, requeuing 10 seconds",
@@ -207,9 +217,14 @@ pub async fn patch_pooler_merge(
 }
 
 // get_pooler_instances takes a CoreDB and returns an Option<i32> based if the CoreDB is hibernated
+// or its pooler is paused
 #[instrument(skip(cdb), fields(trace_id, instance_name = %cdb.name_any()))]
 pub fn get_pooler_instances(cdb: &CoreDB) -> Option<i32> {
-    Some(if cdb.spec.stop { 0 } else { 1 })
+    Some(if cdb.spec.is_paused(PausableComponent::Pooler) {
+        0
+    } else {
+        1
+    })
 }
 
 // cdb: the CoreDB object
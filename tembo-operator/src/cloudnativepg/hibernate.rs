@@ -1,8 +1,9 @@
-use crate::apis::coredb_types::CoreDB;
+use crate::apis::coredb_types::{CoreDB, PausableComponent};
 use crate::cloudnativepg::clusters::{ClusterStatusConditions, ClusterStatusConditionsStatus};
 use crate::cloudnativepg::cnpg::{get_cluster, get_pooler, get_scheduled_backups};
 use crate::cloudnativepg::poolers::Pooler;
 use crate::cloudnativepg::scheduledbackups::ScheduledBackup;
+use crate::events::{publish_event, REASON_HIBERNATED, REASON_WOKEN};
 use crate::ingress::{delete_ingress_route, delete_ingress_route_tcp};
 use crate::prometheus::podmonitor_crd as podmon;
 use crate::Error;
@@ -10,6 +11,7 @@ use crate::Error;
 use crate::{patch_cdb_status_merge, requeue_normal_with_jitter, Context};
 use kube::api::{DeleteParams, Patch, PatchParams};
 use kube::runtime::controller::Action;
+use kube::runtime::events::EventType;
 use kube::{Api, ResourceExt};
 use serde_json::json;
 
@@ -35,7 +37,10 @@ use tracing::{debug, error, info, warn};
 /// For the sake of consistency, it also ensures the hibernation annotation is
 /// set to "off" when the instance is not stopped and the cluster already exists.
 ///
-/// Returns a normal, jittered requeue when the instance is stopped.
+/// Components listed in spec.paused are paused the same way on their own, Postgres by
+/// hibernating only the cluster, while the other components keep running.
+///
+/// Returns a normal, jittered requeue when the instance is stopped or Postgres is paused.
 pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) -> Result<(), Action> {
     info!(
         "Reconciling hibernation for CoreDB instance {}",
@@ -194,7 +199,8 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
     };
 
     let cluster_annotations = cluster.metadata.annotations.clone().unwrap_or_default();
-    let hibernation_value = if cdb.spec.stop { "on" } else { "off" };
+    let postgres_paused = cdb.spec.is_paused(PausableComponent::Postgres);
+    let hibernation_value = if postgres_paused { "on" } else { "off" };
 
     // Build the hibernation patch we want to apply to disable the CNPG cluster.
     // This will also disable the PodMonitor for the cluster.
@@ -207,7 +213,7 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
         },
         "spec": {
             "monitoring": {
-                "enablePodMonitor": !cdb.spec.stop,
+                "enablePodMonitor": !postgres_paused,
             }
         }
     });
@@ -250,11 +256,30 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
                 "Hibernation annotation of {} already set to '{}', proceeding...",
                 name, hibernation_value
             );
-            if cdb.spec.stop {
+            if postgres_paused {
                 // Only remove stalled backups if the instance is stopped/paused
                 info!("Remove any stalled backups for paused instance {}", name);
                 removed_stalled_backups(cdb, ctx).await?;
 
+                // The rest of the reconcile is skipped, so the paused components are
+                // reported here when they change while Postgres stays paused
+                let paused_components = Some(cdb.spec.paused_components());
+                if cdb
+                    .status
+                    .as_ref()
+                    .and_then(|s| s.paused_components.clone())
+                    != paused_components
+                {
+                    let patch_status = json!({
+                        "apiVersion": "coredb.io/v1alpha1",
+                        "kind": "CoreDB",
+                        "status": {
+                            "paused_components": paused_components
+                        }
+                    });
+                    patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+                }
+
                 info!("Fully reconciled stopped instance {}", name);
                 return Err(requeue_normal_with_jitter());
             }
@@ -262,9 +287,22 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
         }
     }
 
+    // Only a change of an existing annotation is a transition, a new cluster starts out awake
+    if cluster_annotations.contains_key("cnpg.io/hibernation") {
+        let (reason, note) = if postgres_paused {
+            (REASON_HIBERNATED, format!("Hibernating instance {}", name))
+        } else {
+            (REASON_WOKEN, format!("Waking instance {}", name))
+        };
+        publish_event(cdb, ctx, EventType::Normal, reason, "Hibernation", note).await;
+    }
+
     let mut status = cdb.status.clone().unwrap_or_default();
-    status.running = !cdb.spec.stop;
+    status.running = !postgres_paused;
     status.pg_postmaster_start_time = None;
+    status.paused_components = Some(cdb.spec.paused_components()).filter(|c| !c.is_empty());
+    // Idle time is counted again from when the instance is woken
+    status.idle_since = None;
 
     let patch_status = json!({
         "apiVersion": "coredb.io/v1alpha1",
@@ -273,7 +311,7 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
     });
     patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
 
-    if cdb.spec.stop {
+    if postgres_paused {
         info!("Fully reconciled stopped instance {}", name);
         return Err(requeue_normal_with_jitter());
     }
@@ -283,7 +321,7 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
 /// Patches AppService deployments in a Kubernetes cluster by updating their replica count based on the CoreDB specification.
 ///
 /// This function performs the following operations:
-/// * Updates the replica count to 0 if CoreDB is stopped, or 1 if it's running
+/// * Updates the replica count to 0 if CoreDB is stopped or its appServices are paused, or 1 if they're running
 /// * Deletes associated PodMonitor resources when scaling down to 0 replicas
 /// * Applies patches to all relevant deployments in the specified namespace
 ///
@@ -316,7 +354,11 @@ async fn patch_appservice_deployments(
     let podmonitor_api: Api<podmon::PodMonitor> = Api::namespaced(client.clone(), namespace);
     let ps = PatchParams::apply("patch_merge").force();
 
-    let replicas = if cdb.spec.stop { 0 } else { 1 };
+    let replicas = if cdb.spec.is_paused(PausableComponent::AppServices) {
+        0
+    } else {
+        1
+    };
     let replica_patch = json!({
         "apiVersion": "apps/v1",
         "kind": "Deployment",
@@ -528,7 +570,7 @@ async fn update_scheduled_backups(
         return Ok(());
     }
 
-    let scheduled_backup_value = cdb.spec.stop;
+    let scheduled_backup_value = cdb.spec.is_paused(PausableComponent::Postgres);
 
     for sb in scheduled_backups {
         let scheduled_backup_name = sb.metadata.name.as_deref().unwrap_or(&name);
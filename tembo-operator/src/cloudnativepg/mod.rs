@@ -5,8 +5,10 @@ pub(crate) mod cnpg;
 pub(crate) mod archive;
 pub mod cnpg_utils;
 pub mod hibernate;
+pub(crate) mod objectstore;
 pub(crate) mod placement;
 pub mod poolers;
+pub(crate) mod pre_operation_backup;
 pub mod retention;
 mod scheduledbackups;
 pub const VOLUME_SNAPSHOT_CLASS_NAME: &str = "cnpg-snapshot-class";
@@ -0,0 +1,193 @@
+use crate::apis::coredb_types::{
+    AzureCredentials, Backup, GoogleCredentials, Restore, S3Credentials,
+};
+use tracing::warn;
+
+/// The object storage service a barman destination path points to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ObjectStoreProvider {
+    S3,
+    Gcs,
+    Azure,
+}
+
+impl ObjectStoreProvider {
+    // Azure Blob Storage paths are plain https URLs, e.g.
+    // https://<storage-account>.blob.core.windows.net/<container>/<path>
+    pub(crate) fn from_destination_path(path: &str) -> Option<Self> {
+        if path.starts_with("s3://") {
+            Some(ObjectStoreProvider::S3)
+        } else if path.starts_with("gs://") {
+            Some(ObjectStoreProvider::Gcs)
+        } else if path.starts_with("https://") || path.starts_with("azure://") {
+            Some(ObjectStoreProvider::Azure)
+        } else {
+            None
+        }
+    }
+}
+
+/// The credentials to read a restore's backups with, at most one provider is set when the
+/// provider of the backups path is known
+#[derive(Debug, Default)]
+pub(crate) struct RestoreCredentials<'a> {
+    pub s3_credentials: Option<&'a S3Credentials>,
+    pub google_credentials: Option<&'a GoogleCredentials>,
+    pub azure_credentials: Option<&'a AzureCredentials>,
+}
+
+impl RestoreCredentials<'_> {
+    fn is_empty(&self) -> bool {
+        self.s3_credentials.is_none()
+            && self.google_credentials.is_none()
+            && self.azure_credentials.is_none()
+    }
+}
+
+// restore_credentials selects the credentials matching the provider of the backups being
+// restored, so an instance on one cloud can restore from backups kept on another. Credentials
+// for the other providers are dropped, since CNPG only accepts one set per object store. When
+// the restore does not carry credentials for the provider and the instance's own backups are
+// kept with the same provider, the backup credentials are used instead.
+pub(crate) fn restore_credentials<'a>(
+    restore: &'a Restore,
+    backup: &'a Backup,
+    destination_path: &str,
+) -> RestoreCredentials<'a> {
+    let Some(provider) = ObjectStoreProvider::from_destination_path(destination_path) else {
+        return RestoreCredentials {
+            s3_credentials: restore.s3_credentials.as_ref(),
+            google_credentials: restore.google_credentials.as_ref(),
+            azure_credentials: restore.azure_credentials.as_ref(),
+        };
+    };
+
+    let same_provider_as_backup = backup
+        .destinationPath
+        .as_deref()
+        .and_then(ObjectStoreProvider::from_destination_path)
+        == Some(provider);
+
+    let credentials = match provider {
+        ObjectStoreProvider::S3 => RestoreCredentials {
+            s3_credentials: restore.s3_credentials.as_ref().or(backup
+                .s3_credentials
+                .as_ref()
+                .filter(|_| same_provider_as_backup)),
+            ..RestoreCredentials::default()
+        },
+        ObjectStoreProvider::Gcs => RestoreCredentials {
+            google_credentials: restore.google_credentials.as_ref().or(backup
+                .google_credentials
+                .as_ref()
+                .filter(|_| same_provider_as_backup)),
+            ..RestoreCredentials::default()
+        },
+        ObjectStoreProvider::Azure => RestoreCredentials {
+            azure_credentials: restore.azure_credentials.as_ref().or(backup
+                .azure_credentials
+                .as_ref()
+                .filter(|_| same_provider_as_backup)),
+            ..RestoreCredentials::default()
+        },
+    };
+
+    if credentials.is_empty() {
+        warn!(
+            "No {:?} credentials found to restore from {}",
+            provider, destination_path
+        );
+    }
+
+    credentials
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gcp_backup() -> Backup {
+        Backup {
+            destinationPath: Some("gs://tembo-backups/v2/test-db".to_string()),
+            google_credentials: Some(GoogleCredentials {
+                gke_environment: Some(true),
+                ..GoogleCredentials::default()
+            }),
+            ..Backup::default()
+        }
+    }
+
+    #[test]
+    fn test_from_destination_path() {
+        assert_eq!(
+            ObjectStoreProvider::from_destination_path("s3://bucket/path"),
+            Some(ObjectStoreProvider::S3)
+        );
+        assert_eq!(
+            ObjectStoreProvider::from_destination_path("gs://bucket/path"),
+            Some(ObjectStoreProvider::Gcs)
+        );
+        assert_eq!(
+            ObjectStoreProvider::from_destination_path(
+                "https://account.blob.core.windows.net/container/path"
+            ),
+            Some(ObjectStoreProvider::Azure)
+        );
+        assert_eq!(ObjectStoreProvider::from_destination_path("/path"), None);
+    }
+
+    #[test]
+    fn test_restore_credentials_from_foreign_provider() {
+        // An instance on GCP restoring from backups kept on S3
+        let backup = gcp_backup();
+        let restore = Restore {
+            server_name: "test-db".to_string(),
+            backups_path: Some("s3://aws-backups/v2/test-db".to_string()),
+            s3_credentials: Some(S3Credentials {
+                inherit_from_iam_role: Some(false),
+                ..S3Credentials::default()
+            }),
+            google_credentials: Some(GoogleCredentials {
+                gke_environment: Some(true),
+                ..GoogleCredentials::default()
+            }),
+            ..Restore::default()
+        };
+
+        let credentials = restore_credentials(&restore, &backup, "s3://aws-backups/v2/test-db");
+        assert_eq!(
+            credentials
+                .s3_credentials
+                .and_then(|c| c.inherit_from_iam_role),
+            Some(false)
+        );
+        assert!(credentials.google_credentials.is_none());
+        assert!(credentials.azure_credentials.is_none());
+
+        // The instance's own GCS credentials are never used for S3
+        let restore = Restore {
+            s3_credentials: None,
+            ..restore
+        };
+        let credentials = restore_credentials(&restore, &backup, "s3://aws-backups/v2/test-db");
+        assert!(credentials.is_empty());
+    }
+
+    #[test]
+    fn test_restore_credentials_from_same_provider() {
+        let backup = gcp_backup();
+        let restore = Restore {
+            server_name: "test-db".to_string(),
+            ..Restore::default()
+        };
+
+        let credentials = restore_credentials(&restore, &backup, "gs://tembo-backups/v2/test-db");
+        assert_eq!(
+            credentials
+                .google_credentials
+                .and_then(|c| c.gke_environment),
+            Some(true)
+        );
+        assert!(credentials.s3_credentials.is_none());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    apis::coredb_types::{CoreDB, PreOperationBackup},
+    cloudnativepg::{
+        backups::{Backup, BackupCluster, BackupMethod, BackupSpec},
+        clusters::Cluster,
+        cnpg::cnpg_cluster_storage_class,
+    },
+    config::Config,
+    defaults::parse_postgres_major_version,
+    events::{publish_event, REASON_PRE_OPERATION_BACKUP, REASON_PRE_OPERATION_BACKUP_FAILED},
+    patch_cdb_status_merge, Context,
+};
+use chrono::{DateTime, Utc};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::{controller::Action, events::EventType},
+    Api, ResourceExt,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+// How often a failed pre-operation backup is taken again, before the change is blocked
+const MAX_PRE_OPERATION_BACKUP_RETRIES: u32 = 2;
+
+// reconcile_pre_operation_backup takes an on-demand backup before a destructive change is
+// applied to the Cluster and waits for it to complete, so there is always a recovery point
+// from right before the change. The backup is recorded in status.pre_operation_backup.
+#[instrument(skip(cdb, ctx, cluster) fields(trace_id, instance_name = %cdb.name_any()))]
+pub async fn reconcile_pre_operation_backup(
+    cdb: &CoreDB,
+    ctx: &Arc<Context>,
+    cluster: &Cluster,
+) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let Some(operation) = destructive_operation(cdb, cluster) else {
+        return Ok(());
+    };
+
+    if cdb.spec.backup.pre_operation_backup == Some(false) {
+        info!(
+            "Pre-operation backup is disabled for instance {}, applying {}",
+            name, operation
+        );
+        return Ok(());
+    }
+
+    let cfg = Config::default();
+    if !cfg.enable_backup || cluster.spec.backup.is_none() {
+        warn!(
+            "Backups are not configured for instance {}, applying {} without a pre-operation backup",
+            name, operation
+        );
+        return Ok(());
+    }
+
+    let namespace = cdb.metadata.namespace.as_ref().ok_or_else(|| {
+        error!("CoreDB namespace is empty for instance: {}.", name);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    let backup_api: Api<Backup> = Api::namespaced(ctx.client.clone(), namespace);
+    let coredb_api: Api<CoreDB> = Api::namespaced(ctx.client.clone(), namespace);
+
+    // Check on the backup already started for this operation, if any
+    let recorded = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.pre_operation_backup.clone())
+        .filter(|b| b.operation == operation);
+    if let Some(recorded) = recorded {
+        if recorded.completed {
+            return Ok(());
+        }
+
+        let backup = backup_api
+            .get_opt(&recorded.backup_name)
+            .await
+            .map_err(|e| {
+                error!("Error getting backup {}: {}", recorded.backup_name, e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
+        if let Some(backup) = backup {
+            let status = backup.status.unwrap_or_default();
+            match status.phase.as_deref() {
+                Some("completed") => {
+                    let completed = PreOperationBackup {
+                        backup_id: status.backup_id,
+                        completed: true,
+                        ..recorded
+                    };
+                    patch_pre_operation_backup(&coredb_api, &name, &completed).await?;
+                    publish_event(
+                        cdb,
+                        ctx,
+                        EventType::Normal,
+                        REASON_PRE_OPERATION_BACKUP,
+                        "Backup",
+                        format!(
+                            "Backup {} completed, applying {}",
+                            completed.backup_name, operation
+                        ),
+                    )
+                    .await;
+                    info!(
+                        "Pre-operation backup {} completed for instance {}, applying {}",
+                        completed.backup_name, name, operation
+                    );
+                    return Ok(());
+                }
+                Some("failed") if recorded.retries < MAX_PRE_OPERATION_BACKUP_RETRIES => {
+                    warn!(
+                        "Pre-operation backup {} failed for instance {}, taking it again",
+                        recorded.backup_name, name
+                    );
+                    publish_event(
+                        cdb,
+                        ctx,
+                        EventType::Warning,
+                        REASON_PRE_OPERATION_BACKUP_FAILED,
+                        "Backup",
+                        format!(
+                            "Backup {} failed, taking a new backup before {}",
+                            recorded.backup_name, operation
+                        ),
+                    )
+                    .await;
+                    return start_pre_operation_backup(
+                        cdb,
+                        ctx,
+                        &backup_api,
+                        &coredb_api,
+                        operation,
+                        recorded.retries + 1,
+                    )
+                    .await;
+                }
+                Some("failed") => {
+                    error!(
+                        "Pre-operation backup {} failed for instance {}, not applying {}",
+                        recorded.backup_name, name, operation
+                    );
+                    publish_event(
+                        cdb,
+                        ctx,
+                        EventType::Warning,
+                        REASON_PRE_OPERATION_BACKUP_FAILED,
+                        "Backup",
+                        format!(
+                            "Backup {} failed, {} is blocked until a backup succeeds or spec.backup.preOperationBackup is false",
+                            recorded.backup_name, operation
+                        ),
+                    )
+                    .await;
+                    return Err(Action::requeue(Duration::from_secs(300)));
+                }
+                _ => {
+                    debug!(
+                        "Pre-operation backup {} is still running for instance {}",
+                        recorded.backup_name, name
+                    );
+                    return Err(Action::requeue(Duration::from_secs(30)));
+                }
+            }
+        }
+        warn!(
+            "Pre-operation backup {} no longer exists for instance {}, taking a new one",
+            recorded.backup_name, name
+        );
+        return start_pre_operation_backup(
+            cdb,
+            ctx,
+            &backup_api,
+            &coredb_api,
+            operation,
+            recorded.retries,
+        )
+        .await;
+    }
+
+    start_pre_operation_backup(cdb, ctx, &backup_api, &coredb_api, operation, 0).await
+}
+
+// Start an on-demand backup for the operation and record it in the status, then requeue
+// to wait for it
+async fn start_pre_operation_backup(
+    cdb: &CoreDB,
+    ctx: &Arc<Context>,
+    backup_api: &Api<Backup>,
+    coredb_api: &Api<CoreDB>,
+    operation: String,
+    retries: u32,
+) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap_or_default();
+    let backup_name = pre_operation_backup_name(&name, Utc::now());
+    let backup = Backup {
+        metadata: ObjectMeta {
+            name: Some(backup_name.clone()),
+            namespace: Some(namespace),
+            labels: Some(BTreeMap::from([(
+                String::from("cnpg.io/cluster"),
+                name.clone(),
+            )])),
+            ..ObjectMeta::default()
+        },
+        spec: BackupSpec {
+            cluster: BackupCluster { name: name.clone() },
+            method: Some(BackupMethod::BarmanObjectStore),
+            ..BackupSpec::default()
+        },
+        status: None,
+    };
+    let ps = PatchParams::apply("cntrlr").force();
+    backup_api
+        .patch(&backup_name, &ps, &Patch::Apply(&backup))
+        .await
+        .map_err(|e| {
+            error!("Error creating pre-operation backup: {}", e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+
+    let started = PreOperationBackup {
+        operation: operation.clone(),
+        backup_name: backup_name.clone(),
+        backup_id: None,
+        completed: false,
+        retries,
+    };
+    patch_pre_operation_backup(coredb_api, &name, &started).await?;
+    publish_event(
+        cdb,
+        ctx,
+        EventType::Normal,
+        REASON_PRE_OPERATION_BACKUP,
+        "Backup",
+        format!("Started backup {} before {}", backup_name, operation),
+    )
+    .await;
+    info!(
+        "Started pre-operation backup {} for instance {} before {}, requeuing in 30 seconds",
+        backup_name, name, operation
+    );
+    Err(Action::requeue(Duration::from_secs(30)))
+}
+
+async fn patch_pre_operation_backup(
+    coredb_api: &Api<CoreDB>,
+    name: &str,
+    pre_operation_backup: &PreOperationBackup,
+) -> Result<(), Action> {
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "pre_operation_backup": pre_operation_backup
+        }
+    });
+    patch_cdb_status_merge(coredb_api, name, patch_status).await
+}
+
+// destructive_operation describes the destructive change between the running Cluster and the
+// desired CoreDB, if there is one
+fn destructive_operation(cdb: &CoreDB, cluster: &Cluster) -> Option<String> {
+    let prev_major = cluster
+        .spec
+        .image_name
+        .as_deref()
+        .and_then(|image| parse_postgres_major_version(image).ok());
+    let new_major = parse_postgres_major_version(&cdb.spec.image).ok();
+    if let (Some(prev_major), Some(new_major)) = (prev_major, new_major) {
+        if prev_major != new_major {
+            return Some(format!("major upgrade {} to {}", prev_major, new_major));
+        }
+    }
+
+    let prev_storage_class = cluster
+        .spec
+        .storage
+        .as_ref()
+        .and_then(|s| s.storage_class.clone());
+    let new_storage_class = cnpg_cluster_storage_class(cdb);
+    if prev_storage_class != new_storage_class {
+        return Some(format!(
+            "storage migration {} to {}",
+            prev_storage_class.as_deref().unwrap_or("default"),
+            new_storage_class.as_deref().unwrap_or("default")
+        ));
+    }
+
+    None
+}
+
+// pre_operation_backup_name generates a Backup name from the instance name and the current time,
+// trimming the instance name so the result stays under 54 characters
+fn pre_operation_backup_name(name: &str, now: DateTime<Utc>) -> String {
+    let suffix = format!("pre-{}", now.format("%Y%m%d%H%M%S"));
+    let max_name_len = 54 - suffix.len() - 1;
+    let truncated_name = if name.len() > max_name_len {
+        &name[..max_name_len]
+    } else {
+        name
+    };
+    format!("{}-{}", truncated_name, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::coredb_types::CoreDBSpec,
+        cloudnativepg::clusters::{ClusterSpec, ClusterStorage},
+    };
+    use chrono::TimeZone;
+
+    fn cluster(image: &str, storage_class: Option<&str>) -> Cluster {
+        Cluster {
+            metadata: ObjectMeta::default(),
+            spec: ClusterSpec {
+                image_name: Some(image.to_string()),
+                storage: Some(ClusterStorage {
+                    storage_class: storage_class.map(String::from),
+                    ..ClusterStorage::default()
+                }),
+                ..ClusterSpec::default()
+            },
+            status: None,
+        }
+    }
+
+    fn coredb(image: &str, storage_class: Option<&str>) -> CoreDB {
+        CoreDB {
+            metadata: ObjectMeta::default(),
+            spec: CoreDBSpec {
+                image: image.to_string(),
+                storage_class: storage_class.map(String::from),
+                ..CoreDBSpec::default()
+            },
+            status: None,
+        }
+    }
+
+    #[test]
+    fn test_destructive_operation() {
+        let pg15 = "quay.io/tembo/standard-cnpg:15.3.0-1-0c19c7e";
+        let pg15_patch = "quay.io/tembo/standard-cnpg:15.6.0-1-a6f1a8e";
+        let pg16 = "quay.io/tembo/standard-cnpg:16.2.0-1-a6f1a8e";
+
+        assert_eq!(
+            destructive_operation(&coredb(pg15, Some("gp3")), &cluster(pg15, Some("gp3"))),
+            None
+        );
+        // Minor version updates are not destructive
+        assert_eq!(
+            destructive_operation(&coredb(pg15_patch, None), &cluster(pg15, None)),
+            None
+        );
+        assert_eq!(
+            destructive_operation(&coredb(pg16, None), &cluster(pg15, None)),
+            Some("major upgrade 15 to 16".to_string())
+        );
+        assert_eq!(
+            destructive_operation(&coredb(pg15, Some("gp3-enc")), &cluster(pg15, Some("gp3"))),
+            Some("storage migration gp3 to gp3-enc".to_string())
+        );
+        assert_eq!(
+            destructive_operation(&coredb(pg15, Some("gp3")), &cluster(pg15, None)),
+            Some("storage migration default to gp3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pre_operation_backup_name() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 27, 4, 56, 1).unwrap();
+        assert_eq!(
+            pre_operation_backup_name("org-test-inst-db", now),
+            "org-test-inst-db-pre-20240327045601"
+        );
+
+        let long_name = "a".repeat(60);
+        let name = pre_operation_backup_name(&long_name, now);
+        assert_eq!(name.len(), 54);
+        assert!(name.ends_with("-pre-20240327045601"));
+    }
+}
@@ -1,4 +1,6 @@
-use std::env;
+use crate::resource_profiles::{parse_resource_profiles, ResourceProfile};
+use kube::runtime::watcher;
+use std::{collections::BTreeMap, env, time::Duration};
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -7,6 +9,21 @@ pub struct Config {
     pub volume_snapshot_retention_period_days: u64,
     pub reconcile_timestamp_ttl: u64,
     pub reconcile_ttl: u64,
+    pub watch_label_selector: Option<String>,
+    pub resync_jitter: u64,
+    pub max_concurrent_reconciles: usize,
+    pub error_backoff_base: u64,
+    pub error_backoff_max: u64,
+    pub enable_disk_full_protection: bool,
+    pub disk_full_threshold_percent: u8,
+    pub disk_full_recovery_percent: u8,
+    pub disk_full_storage_increase_percent: u32,
+    pub idle_hibernation_after_sec: u64,
+    pub resource_profiles: BTreeMap<String, ResourceProfile>,
+    pub storage_usage_schedule: Option<String>,
+    pub external_secret_store: Option<String>,
+    pub external_secret_store_kind: String,
+    pub external_secret_key_prefix: String,
 }
 
 impl Default for Config {
@@ -26,13 +43,129 @@ impl Default for Config {
             reconcile_timestamp_ttl: from_env_default("RECONCILE_TIMESTAMP_TTL", "30")
                 .parse()
                 .unwrap(),
-            // The time to live for reconciling the entire instance
+            // The time to live for reconciling the entire instance, i.e. the periodic resync interval
             reconcile_ttl: from_env_default("RECONCILE_TTL", "90").parse().unwrap(),
+            // Only watch CoreDBs matching this label selector, to shard the fleet across operators
+            watch_label_selector: Some(from_env_default("WATCH_LABEL_SELECTOR", ""))
+                .filter(|selector| !selector.trim().is_empty()),
+            // Random jitter added to the periodic resync interval, to spread out reconciles
+            resync_jitter: from_env_default("RESYNC_JITTER", "60").parse().unwrap(),
+            // The maximum number of CoreDBs reconciled at once, 0 is unlimited
+            max_concurrent_reconciles: from_env_default("MAX_CONCURRENT_RECONCILES", "0")
+                .parse()
+                .unwrap(),
+            // The requeue delay after the first failed reconcile, doubled on each consecutive failure
+            error_backoff_base: from_env_default("ERROR_BACKOFF_BASE", "300")
+                .parse()
+                .unwrap(),
+            // The upper bound of the requeue delay after failed reconciles
+            error_backoff_max: from_env_default("ERROR_BACKOFF_MAX", "300")
+                .parse()
+                .unwrap(),
+            // Make instances read only when their data volume is about to fill up
+            enable_disk_full_protection: from_env_default("ENABLE_DISK_FULL_PROTECTION", "false")
+                .parse()
+                .unwrap(),
+            // The data volume usage at which an instance is made read only
+            disk_full_threshold_percent: from_env_default("DISK_FULL_THRESHOLD_PERCENT", "95")
+                .parse()
+                .unwrap(),
+            // The data volume usage below which an instance is made writable again
+            disk_full_recovery_percent: from_env_default("DISK_FULL_RECOVERY_PERCENT", "90")
+                .parse()
+                .unwrap(),
+            // Emergency increase of the storage size when the threshold is crossed, 0 is disabled
+            disk_full_storage_increase_percent: from_env_default(
+                "DISK_FULL_STORAGE_INCREASE_PERCENT",
+                "0",
+            )
+            .parse()
+            .unwrap(),
+            // Hibernate instances without client connections for this long, 0 is disabled
+            idle_hibernation_after_sec: from_env_default("IDLE_HIBERNATION_AFTER_SEC", "0")
+                .parse()
+                .unwrap(),
+            // Named resource profiles CoreDBs can reference with spec.resourceProfile, as YAML
+            resource_profiles: parse_resource_profiles(&from_env_default("RESOURCE_PROFILES", ""))
+                .unwrap(),
+            // Measure the object storage used by the backups of each instance on this cron
+            // schedule, disabled when empty
+            storage_usage_schedule: Some(from_env_default("STORAGE_USAGE_SCHEDULE", ""))
+                .filter(|schedule| !schedule.trim().is_empty()),
+            // The External Secrets Operator secret store which secrets of instances with
+            // spec.mirrorSecrets are pushed to, mirroring is disabled when empty
+            external_secret_store: Some(from_env_default("EXTERNAL_SECRET_STORE", ""))
+                .filter(|store| !store.trim().is_empty()),
+            // The kind of the secret store, SecretStore stores must exist in each namespace
+            external_secret_store_kind: from_env_default(
+                "EXTERNAL_SECRET_STORE_KIND",
+                "ClusterSecretStore",
+            ),
+            // Prepended to the names of the secrets in the secret manager
+            external_secret_key_prefix: from_env_default("EXTERNAL_SECRET_KEY_PREFIX", ""),
+        }
+    }
+}
+
+impl Config {
+    // The watcher config for CoreDBs, restricted to the shard when a label selector is set
+    pub fn coredb_watcher_config(&self) -> watcher::Config {
+        let watcher_config = watcher::Config::default().any_semantic();
+        match &self.watch_label_selector {
+            Some(selector) => watcher_config.labels(selector),
+            None => watcher_config,
         }
     }
+
+    // The requeue delay after a number of consecutive failed reconciles
+    pub fn error_backoff(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(32);
+        let backoff = self
+            .error_backoff_base
+            .saturating_mul(2u64.saturating_pow(exponent));
+        Duration::from_secs(backoff.min(self.error_backoff_max))
+    }
 }
 
 // Source the variable from the env - use default if not set
 fn from_env_default(var: &str, default: &str) -> String {
     env::var(var).unwrap_or_else(|_| default.to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coredb_watcher_config() {
+        let cfg = Config {
+            watch_label_selector: None,
+            ..Config::default()
+        };
+        assert_eq!(cfg.coredb_watcher_config().label_selector, None);
+
+        let cfg = Config {
+            watch_label_selector: Some("tembo.io/shard=1".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            cfg.coredb_watcher_config().label_selector,
+            Some("tembo.io/shard=1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_backoff() {
+        let cfg = Config {
+            error_backoff_base: 10,
+            error_backoff_max: 60,
+            ..Config::default()
+        };
+        assert_eq!(cfg.error_backoff(0), Duration::from_secs(10));
+        assert_eq!(cfg.error_backoff(1), Duration::from_secs(10));
+        assert_eq!(cfg.error_backoff(2), Duration::from_secs(20));
+        assert_eq!(cfg.error_backoff(3), Duration::from_secs(40));
+        assert_eq!(cfg.error_backoff(4), Duration::from_secs(60));
+        assert_eq!(cfg.error_backoff(u32::MAX), Duration::from_secs(60));
+    }
+}
@@ -2,10 +2,18 @@ use chrono::{DateTime, Utc};
 use futures::stream::StreamExt;
 
 use crate::{
-    apis::coredb_types::{CoreDB, CoreDBStatus, VolumeSnapshot},
-    app_service::manager::reconcile_app_services,
+    apis::coredb_types::{CoreDB, CoreDBStatus, PausableComponent, VolumeSnapshot},
+    apis::postgres_parameters::invalid_pg_configs,
+    app_service::{
+        manager::reconcile_app_services,
+        reload::{references_configmap, references_secret, APP_CONFIG_LABEL},
+    },
+    bootstrap_sql::reconcile_bootstrap_sql,
     cloudnativepg::{
-        archive::wal::reconcile_last_archive_status,
+        archive::{
+            additional_destination::reconcile_additional_backup_destination,
+            storage_usage::reconcile_object_storage_usage, wal::reconcile_last_archive_status,
+        },
         backups::Backup,
         cnpg::{
             cnpg_cluster_from_cdb, reconcile_cnpg, reconcile_cnpg_scheduled_backup,
@@ -17,14 +25,28 @@ use crate::{
     },
     config::Config,
     dedicated_networking::reconcile_dedicated_networking,
+    disk_full::{reconcile_disk_full_protection, DISK_FULL_REQUEUE_SEC},
+    events::{publish_event, REASON_CREATED},
     exec::{ExecCommand, ExecOutput},
     extensions::database_queries::is_not_restarting,
+    external_secrets::reconcile_external_secrets,
     heartbeat::reconcile_heartbeat,
+    idle_hibernation::reconcile_idle_hibernation,
     ingress::reconcile_postgres_ing_route_tcp,
+    metrics::{FailureReason, ReconcilePhase, FAILURE_REASON_REQUEUED},
+    partial_restore::reconcile_partial_restore,
     postgres_certificates::reconcile_certificates,
+    postgres_versions,
     psql::{PsqlCommand, PsqlOutput},
+    reconcile_report::ReconcileReport,
+    resource_profiles::apply_resource_profile,
+    restore_sample::reconcile_restore_sample,
+    role_policies::reconcile_role_policies,
+    scheduled_restart::reconcile_scheduled_restart,
     secret::{reconcile_postgres_role_secret, reconcile_secret},
-    telemetry, Error, Metrics, Result,
+    telemetry,
+    ttl::reconcile_ttl,
+    Error, Metrics, Result,
 };
 use k8s_openapi::{
     api::core::v1::{Namespace, Pod},
@@ -37,6 +59,7 @@ use kube::{
         controller::{Action, Controller},
         events::{Event, EventType, Recorder, Reporter},
         finalizer::{finalizer, Event as Finalizer},
+        reflector::{ObjectRef, Store},
         wait::Condition,
         watcher::Config as watcherConfig,
     },
@@ -50,15 +73,23 @@ use crate::{
     extensions::{database_queries::list_config_params, reconcile_extensions},
     ingress::{reconcile_extra_postgres_ing_route_tcp, reconcile_ip_allowlist_middleware},
     network_policies::reconcile_network_policies,
-    postgres_exporter::reconcile_metrics_configmap,
+    postgres_exporter::{
+        reconcile_metrics_configmap, reconcile_user_queries_configmap, USER_QUERIES_LABEL,
+    },
     trunk::{extensions_that_require_load, reconcile_trunk_configmap},
 };
-use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
 use rand::Rng;
 use serde::Serialize;
 use serde_json::json;
-use std::sync::Arc;
-use tokio::{sync::RwLock, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::{RwLock, Semaphore},
+    time::{Duration, Instant},
+};
 use tracing::*;
 
 pub static COREDB_FINALIZER: &str = "coredbs.coredb.io";
@@ -73,12 +104,20 @@ pub struct Context {
     pub diagnostics: Arc<RwLock<Diagnostics>>,
     /// Prometheus metrics
     pub metrics: Metrics,
+    /// Limits the number of concurrent reconciles, when configured
+    pub reconcile_permits: Option<Arc<Semaphore>>,
+    /// Consecutive failed reconciles per CoreDB, used for error backoff
+    pub reconcile_failures: Arc<Mutex<HashMap<String, u32>>>,
+    /// When the data volume usage of each CoreDB was last measured
+    pub disk_usage_checks: Arc<Mutex<HashMap<String, Instant>>>,
+    /// The last reconcile of each CoreDB by namespace, read by the web server
+    pub reconcile_reports: Arc<RwLock<HashMap<String, ReconcileReport>>>,
 }
 
 pub fn requeue_normal_with_jitter() -> Action {
     let cfg = Config::default();
-    // Check back every 90-150 seconds
-    let jitter = rand::thread_rng().gen_range(0..60);
+    // Check back every 90-150 seconds by default
+    let jitter = rand::thread_rng().gen_range(0..=cfg.resync_jitter);
     Action::requeue(Duration::from_secs(cfg.reconcile_ttl + jitter))
 }
 
@@ -87,6 +126,11 @@ async fn reconcile(cdb: Arc<CoreDB>, ctx: Arc<Context>) -> Result<Action> {
     let trace_id = telemetry::get_trace_id();
     Span::current().record("trace_id", field::display(&trace_id));
     let cfg = Config::default();
+    // Wait for a permit when the number of concurrent reconciles is limited
+    let _permit = match &ctx.reconcile_permits {
+        Some(permits) => Some(permits.acquire().await.expect("semaphore is never closed")),
+        None => None,
+    };
     let _timer = ctx.metrics.count_and_measure();
     ctx.diagnostics.write().await.last_event = Utc::now();
     let ns = cdb.namespace().unwrap(); // cdb is namespace scoped
@@ -95,6 +139,7 @@ async fn reconcile(cdb: Arc<CoreDB>, ctx: Arc<Context>) -> Result<Action> {
     let metadata = cdb.meta();
     // Get annotations from the metadata
     let annotations = metadata.annotations.clone().unwrap_or_default();
+    let failures_key = format!("{}/{}", ns, cdb.name_any());
 
     // Check the annotations to see if it exists and check it's value
     if let Some(value) = annotations.get(COREDB_ANNOTATION) {
@@ -105,27 +150,70 @@ async fn reconcile(cdb: Arc<CoreDB>, ctx: Arc<Context>) -> Result<Action> {
                 cdb.name_any(),
                 ns
             );
+            ctx.reconcile_failures.lock().unwrap().remove(&failures_key);
             return Ok(Action::await_change());
         }
     }
 
     debug!("Reconciling CoreDB \"{}\" in {}", cdb.name_any(), ns);
-    finalizer(&coredbs, COREDB_FINALIZER, cdb, |event| async {
+    let result = finalizer(&coredbs, COREDB_FINALIZER, cdb, |event| async {
         match event {
-            Finalizer::Apply(cdb) => match cdb.reconcile(ctx.clone(), &cfg).await {
-                Ok(action) => Ok(action),
-                Err(requeue_action) => Ok(requeue_action),
-            },
-            Finalizer::Cleanup(cdb) => cdb.cleanup(ctx.clone()).await,
+            Finalizer::Apply(cdb) => {
+                // Reconcile the instance with the defaults of its resource profile filled in
+                let cdb = match apply_resource_profile(&cdb, &cfg.resource_profiles) {
+                    Ok(cdb) => cdb,
+                    Err(e) => {
+                        error!(
+                            "Error applying resource profile to {}: {}",
+                            cdb.name_any(),
+                            e
+                        );
+                        return Ok(Action::requeue(Duration::from_secs(300)));
+                    }
+                };
+                let mut report = ReconcileReport::start(&cdb);
+                let result = cdb.reconcile(ctx.clone(), &cfg, &mut report).await;
+                report.finish(result.is_ok());
+                ctx.reconcile_reports
+                    .write()
+                    .await
+                    .insert(ns.clone(), report);
+                match result {
+                    Ok(action) => Ok(action),
+                    Err(requeue_action) => Ok(requeue_action),
+                }
+            }
+            Finalizer::Cleanup(cdb) => {
+                ctx.reconcile_reports.write().await.remove(&ns);
+                // The instance is going away, its failures must not outlive it
+                ctx.reconcile_failures.lock().unwrap().remove(&failures_key);
+                ctx.disk_usage_checks.lock().unwrap().remove(&failures_key);
+                cdb.cleanup(ctx.clone()).await
+            }
         }
     })
     .await
-    .map_err(|e| Error::FinalizerError(Box::new(e)))
+    .map_err(|e| Error::FinalizerError(Box::new(e)));
+
+    if result.is_ok() {
+        ctx.reconcile_failures.lock().unwrap().remove(&failures_key);
+    }
+    result
 }
 
 pub(crate) fn error_policy(cdb: Arc<CoreDB>, error: &Error, ctx: Arc<Context>) -> Action {
     warn!("reconcile failed: {:?}", error);
     ctx.metrics.reconcile_failure(&cdb, error);
+    let cfg = Config::default();
+
+    // Track consecutive failures so the requeue delay backs off exponentially
+    let failures = {
+        let key = format!("{}/{}", cdb.namespace().unwrap_or_default(), cdb.name_any());
+        let mut reconcile_failures = ctx.reconcile_failures.lock().unwrap();
+        let failures = reconcile_failures.entry(key).or_insert(0);
+        *failures = failures.saturating_add(1);
+        *failures
+    };
 
     // Check for 429 error code from Kubernetes API
     match error {
@@ -143,9 +231,9 @@ pub(crate) fn error_policy(cdb: Arc<CoreDB>, error: &Error, ctx: Arc<Context>) -
                 );
                 Action::requeue(backoff_with_jitter)
             }
-            _ => Action::requeue(Duration::from_secs(5 * 60)),
+            _ => Action::requeue(cfg.error_backoff(failures)),
         },
-        _ => Action::requeue(Duration::from_secs(5 * 60)),
+        _ => Action::requeue(cfg.error_backoff(failures)),
     }
 }
 
@@ -179,28 +267,94 @@ fn is_volume_snapshot_update_needed(
 
 impl CoreDB {
     // Reconcile (for non-finalizer related changes)
-    #[instrument(skip(self, ctx, cfg))]
-    async fn reconcile(&self, ctx: Arc<Context>, cfg: &Config) -> Result<Action, Action> {
+    #[instrument(skip(self, ctx, cfg, report))]
+    async fn reconcile(
+        &self,
+        ctx: Arc<Context>,
+        cfg: &Config,
+        report: &mut ReconcileReport,
+    ) -> Result<Action, Action> {
         let client = ctx.client.clone();
-        let _recorder = ctx.diagnostics.read().await.recorder(client.clone(), self);
         let ns = self.namespace().unwrap();
         let name = self.name_any();
         let coredbs: Api<CoreDB> = Api::namespaced(client.clone(), &ns);
 
+        // The status is only missing before the first reconcile of a new instance. It is set
+        // right away, so the event is not published again while the instance is starting up.
+        if self.status.is_none() {
+            publish_event(
+                self,
+                &ctx,
+                EventType::Normal,
+                REASON_CREATED,
+                "Reconciling",
+                format!("Creating instance {}", name),
+            )
+            .await;
+            let patch_status = json!({
+                "apiVersion": "coredb.io/v1alpha1",
+                "kind": "CoreDB",
+                "status": {
+                    "running": false,
+                }
+            });
+            patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+        }
+
+        // Everything the Postgres version does not support is left out further down, and
+        // reported in status. Only changes are logged, not every reconcile.
+        let unsupported = postgres_versions::validate(self).err().unwrap_or_default();
+        let recorded_unsupported = self
+            .status
+            .as_ref()
+            .and_then(|s| s.unsupported_by_postgres_version.as_ref());
+        if recorded_unsupported.is_none_or(|recorded| *recorded != unsupported) {
+            for error in &unsupported {
+                warn!("Instance {}: {}", name, error);
+            }
+        }
+        // Invalid values are left out of the Postgres configuration as well, and reported in status
+        let invalid_runtime_config = invalid_pg_configs(
+            self.spec.runtime_config.as_deref().unwrap_or_default(),
+            postgres_versions::postgres_version_of(self).map(|version| version.major),
+        );
+        for invalid in &invalid_runtime_config {
+            warn!(
+                "Instance {}: invalid value {} for {}: {}",
+                name, invalid.value, invalid.name, invalid.reason
+            );
+            report.desired.invalid_runtime_config.push(format!(
+                "{}={}: {}",
+                invalid.name, invalid.value, invalid.reason
+            ));
+        }
+
+        // Expired instances are deleted, stopped ones included
+        let expires_at = reconcile_ttl(self, ctx.clone()).await?;
+        report.applied("ttl");
+
         // If the cluster is stopped, apply hibernation and exit
         reconcile_cluster_hibernation(self, &ctx).await?;
+        report.applied("hibernation");
+
+        reconcile_scheduled_restart(self, ctx.clone()).await?;
+        report.applied("scheduled restart");
 
         // Setup Node/Pod Placement Configuration for the Pooler and App Service deployments
         let placement_config = PlacementConfig::new(self);
 
         reconcile_network_policies(ctx.client.clone(), &ns).await?;
+        report.applied("network policies");
 
         // Fetch any metadata we need from Trunk
         reconcile_trunk_configmap(ctx.client.clone(), &ns).await?;
+        report.applied("trunk configmap");
 
         reconcile_certificates(ctx.client.clone(), self, &ns).await?;
+        report.applied("certificates");
 
         // Ingress
+        let ingress_timer = ctx.metrics.measure_phase(ReconcilePhase::Ingress);
         match std::env::var("DATA_PLANE_BASEDOMAIN") {
             Ok(basedomain) => {
                 debug!(
@@ -211,6 +365,8 @@ impl CoreDB {
                 let middleware_name = reconcile_ip_allowlist_middleware(self, ctx.clone())
                     .await
                     .map_err(|e| {
+                        ctx.metrics
+                            .phase_failure(ReconcilePhase::Ingress, e.failure_reason());
                         error!("Error reconciling MiddlewareTCP for {}: {:?}", name, e);
                         Action::requeue(Duration::from_secs(300))
                     })?;
@@ -232,6 +388,8 @@ impl CoreDB {
                 )
                 .await
                 .map_err(|e| {
+                    ctx.metrics
+                        .phase_failure(ReconcilePhase::Ingress, e.failure_reason());
                     error!("Error reconciling postgres ingress route: {:?}", e);
                     // For unexpected errors, we should requeue for several minutes at least,
                     // for expected, "waiting" type of requeuing, those should be shorter, just a few seconds.
@@ -255,6 +413,8 @@ impl CoreDB {
                 )
                 .await
                 .map_err(|e| {
+                    ctx.metrics
+                        .phase_failure(ReconcilePhase::Ingress, e.failure_reason());
                     error!("Error reconciling postgres ingress route: {:?}", e);
                     // For unexpected errors, we should requeue for several minutes at least,
                     // for expected, "waiting" type of requeuing, those should be shorter, just a few seconds.
@@ -272,6 +432,8 @@ impl CoreDB {
                 )
                 .await
                 .map_err(|e| {
+                    ctx.metrics
+                        .phase_failure(ReconcilePhase::Ingress, e.failure_reason());
                     error!("Error reconciling extra postgres ingress route: {:?}", e);
                     // For unexpected errors, we should requeue for several minutes at least,
                     // for expected, "waiting" type of requeuing, those should be shorter, just a few seconds.
@@ -282,6 +444,8 @@ impl CoreDB {
                 reconcile_dedicated_networking(self, ctx.clone(), basedomain.as_str())
                     .await
                     .map_err(|e| {
+                        ctx.metrics
+                            .phase_failure(ReconcilePhase::Ingress, e.failure_reason());
                         error!("Error reconciling dedicated networking: {:?}", e);
                         Action::requeue(Duration::from_secs(300))
                     })?;
@@ -298,28 +462,44 @@ impl CoreDB {
                     name_pooler.as_str(),
                     IntOrString::Int(5432),
                     vec![middleware_name.clone()],
-                    self.spec.replicas < 1 || self.spec.stop || !self.spec.connectionPooler.enabled,
+                    self.spec.replicas < 1
+                        || self.spec.is_paused(PausableComponent::Pooler)
+                        || !self.spec.connectionPooler.enabled,
                 )
                 .await
                 .map_err(|e| {
+                    ctx.metrics
+                        .phase_failure(ReconcilePhase::Ingress, e.failure_reason());
                     error!("Error reconciling pooler ingress route: {:?}", e);
                     // For unexpected errors, we should requeue for several minutes at least,
                     // for expected, "waiting" type of requeuing, those should be shorter, just a few seconds.
                     // IngressRouteTCP does not have expected errors during reconciliation.
                     Action::requeue(Duration::from_secs(300))
                 })?;
+                report.applied("ingress");
             }
             Err(_e) => {
                 warn!(
                     "DATA_PLANE_BASEDOMAIN is not set, skipping reconciliation of IngressRouteTCP"
                 );
+                report.skipped("ingress", "DATA_PLANE_BASEDOMAIN is not set");
             }
         };
+        drop(ingress_timer);
 
         debug!("Reconciling secret");
         // Superuser connection info
         reconcile_secret(self, ctx.clone()).await?;
-        reconcile_app_services(self, ctx.clone(), placement_config.clone()).await?;
+        report.applied("secret");
+        let app_service_timer = ctx.metrics.measure_phase(ReconcilePhase::AppService);
+        reconcile_app_services(self, ctx.clone(), placement_config.clone())
+            .await
+            .inspect_err(|_| {
+                ctx.metrics
+                    .phase_failure(ReconcilePhase::AppService, FAILURE_REASON_REQUEUED);
+            })?;
+        drop(app_service_timer);
+        report.applied("app services");
 
         if self
             .spec
@@ -336,6 +516,12 @@ impl CoreDB {
                     Action::requeue(Duration::from_secs(300))
                 })?;
         }
+        reconcile_user_queries_configmap(self, client.clone(), &ns)
+            .await
+            .map_err(|e| {
+                error!("Error reconciling custom queries configmap: {:?}", e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
 
         let _ = reconcile_postgres_role_secret(
             self,
@@ -349,17 +535,37 @@ impl CoreDB {
             Action::requeue(Duration::from_secs(300))
         })?;
 
+        reconcile_external_secrets(self, ctx.clone()).await?;
+        report.applied("external secrets");
+
         reconcile_generic_metrics_configmap(self, ctx.clone()).await?;
+        report.applied("metrics configmaps");
 
         // Before we reconcile CNPG, we need to make sure that spec.backup.volumeSnapshot is
         // enabled in the CoreDB spec if cfg.enable_volume_snapshot = true.  If it's not
         // then we should enable it, otherwise it should be a no-op.
         self.enable_volume_snapshot(cfg, ctx.clone()).await?;
+        report.applied("volume snapshot setting");
 
-        reconcile_cnpg(self, ctx.clone()).await?;
+        let cnpg_timer = ctx.metrics.measure_phase(ReconcilePhase::Cnpg);
+        let cnpg_failure = |action| {
+            ctx.metrics
+                .phase_failure(ReconcilePhase::Cnpg, FAILURE_REASON_REQUEUED);
+            action
+        };
+        reconcile_cnpg(self, ctx.clone())
+            .await
+            .map_err(cnpg_failure)?;
+        report.applied("cnpg cluster");
         if cfg.enable_backup {
-            reconcile_cnpg_scheduled_backup(self, ctx.clone()).await?;
+            reconcile_cnpg_scheduled_backup(self, ctx.clone())
+                .await
+                .map_err(cnpg_failure)?;
+            report.applied("scheduled backup");
+        } else {
+            report.skipped("scheduled backup", "ENABLE_BACKUP is false");
         }
+        drop(cnpg_timer);
 
         // Cleanup old Postgres Exporter Deployments, Service, ServiceAccount, Role and RoleBinding
         crate::deployment_postgres_exporter::cleanup_postgres_exporter(self, ctx.clone())
@@ -371,6 +577,7 @@ impl CoreDB {
 
         // Reconcile Pooler resource
         reconcile_pooler(self, ctx.clone(), placement_config.clone()).await?;
+        report.applied("pooler");
 
         // Check if Postgres is already running
         let pg_postmaster_start_time = is_not_restarting(self, ctx.clone(), "postgres").await?;
@@ -384,11 +591,54 @@ impl CoreDB {
             }
         });
         patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
-        let (trunk_installs, extensions) =
-            reconcile_extensions(self, ctx.clone(), &coredbs, &name).await?;
+        report.applied("running status");
+
+        // Make the instance read only before its data volume fills up completely
+        let disk_full_protected = reconcile_disk_full_protection(self, ctx.clone(), cfg).await?;
+        report.applied("disk full protection");
+
+        // Remove the tables which are not needed from a partially restored instance
+        reconcile_partial_restore(self, ctx.clone()).await?;
+        report.applied("partial restore");
+
+        // Trim a restored instance down to a sample, before any extensions are reconciled
+        reconcile_restore_sample(self, ctx.clone()).await?;
+        report.applied("restore sample");
+
+        // A standby is read only, its schemas and roles follow the instance it replays WAL from
+        if !self.spec.is_standby() {
+            // Seed schemas, roles and grants, once per script
+            reconcile_bootstrap_sql(self, ctx.clone()).await?;
+
+            // Limit the connections and resources of application roles
+            reconcile_role_policies(self, ctx.clone()).await?;
+            report.applied("bootstrap sql and role policies");
+        } else {
+            report.skipped(
+                "bootstrap sql and role policies",
+                "the instance is a standby",
+            );
+        }
+
+        let extensions_timer = ctx.metrics.measure_phase(ReconcilePhase::Extensions);
+        let (trunk_installs, extensions) = reconcile_extensions(self, ctx.clone(), &coredbs, &name)
+            .await
+            .inspect_err(|_| {
+                ctx.metrics
+                    .phase_failure(ReconcilePhase::Extensions, FAILURE_REASON_REQUEUED);
+            })?;
+        drop(extensions_timer);
+        report.applied("extensions");
 
         let recovery_time = self.get_recovery_time(ctx.clone()).await?;
         let last_archiver_status = reconcile_last_archive_status(self, ctx.clone()).await?;
+        let additional_backup_destination =
+            reconcile_additional_backup_destination(self, ctx.clone(), cfg).await?;
+        let object_storage_usage = reconcile_object_storage_usage(self, ctx.clone(), cfg).await?;
+        ctx.metrics
+            .object_storage_usage(self, object_storage_usage.as_ref());
+        let idle_since = reconcile_idle_hibernation(self, ctx.clone(), cfg).await?;
+        report.applied("backup and usage status");
 
         let current_config_values = get_current_config_values(self, ctx.clone()).await?;
 
@@ -405,6 +655,25 @@ impl CoreDB {
             last_fully_reconciled_at: None,
             pg_postmaster_start_time,
             last_archiver_status,
+            // Not serialized when None, the recorded backup is left as is by this merge patch
+            pre_operation_backup: None,
+            // Not serialized when None, managed by reconcile_disk_full_protection
+            disk_full_protection: None,
+            additional_backup_destination,
+            idle_since,
+            paused_components: Some(self.spec.paused_components()).filter(|c| !c.is_empty()),
+            expires_at,
+            // Not serialized when None, managed by reconcile_bootstrap_sql
+            bootstrap_sql_applied: None,
+            object_storage_usage,
+            // Not serialized when None, managed by reconcile_post_install_sql
+            post_install_sql_applied: None,
+            invalid_runtime_config: Some(invalid_runtime_config).filter(|c| !c.is_empty()),
+            unsupported_by_postgres_version: Some(unsupported).filter(|u| !u.is_empty()),
+            // Not serialized when None, managed by reconcile_restore_sample
+            restore_sampled_at: None,
+            // Not serialized when None, managed by reconcile_partial_restore
+            partial_restore: None,
         };
 
         debug!("Updating CoreDB status to {:?} for {name}", new_status);
@@ -418,6 +687,7 @@ impl CoreDB {
         patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
 
         reconcile_heartbeat(self, ctx.clone()).await?;
+        report.applied("status");
 
         // Cleanup old volume snapshots that are older than the retention period
         // set in cfg.volume_snapshot_retention_period
@@ -440,9 +710,16 @@ impl CoreDB {
                     return Err(action);
                 }
             }
+            report.applied("volume snapshot cleanup");
+        } else {
+            report.skipped("volume snapshot cleanup", "ENABLE_VOLUME_SNAPSHOT is false");
         }
 
         info!("Fully reconciled {}", self.name_any());
+        if disk_full_protected {
+            // Measure the data volume usage again soon, to make the instance writable again
+            return Ok(Action::requeue(Duration::from_secs(DISK_FULL_REQUEUE_SEC)));
+        }
         Ok(requeue_normal_with_jitter())
     }
 
@@ -935,7 +1212,7 @@ impl Default for Diagnostics {
 }
 impl Diagnostics {
     #[instrument(skip(self, client))]
-    fn recorder(&self, client: Client, cdb: &CoreDB) -> Recorder {
+    pub(crate) fn recorder(&self, client: Client, cdb: &CoreDB) -> Recorder {
         Recorder::new(client, self.reporter.clone(), cdb.object_ref(&()))
     }
 }
@@ -947,6 +1224,8 @@ pub struct State {
     diagnostics: Arc<RwLock<Diagnostics>>,
     /// Metrics registry
     registry: prometheus::Registry,
+    /// The last reconcile of each CoreDB by namespace
+    reconcile_reports: Arc<RwLock<HashMap<String, ReconcileReport>>>,
 }
 
 /// State wrapper around the controller outputs for the web server
@@ -961,12 +1240,26 @@ impl State {
         self.diagnostics.read().await.clone()
     }
 
+    /// The last reconcile of the CoreDB in a namespace
+    pub async fn reconcile_report(&self, namespace: &str) -> Option<ReconcileReport> {
+        self.reconcile_reports.read().await.get(namespace).cloned()
+    }
+
     // Create a Controller Context that can update State
     pub fn create_context(&self, client: Client) -> Arc<Context> {
+        let cfg = Config::default();
+        let reconcile_permits = match cfg.max_concurrent_reconciles {
+            0 => None,
+            permits => Some(Arc::new(Semaphore::new(permits))),
+        };
         Arc::new(Context {
             client,
             metrics: Metrics::default().register(&self.registry).unwrap(),
             diagnostics: self.diagnostics.clone(),
+            reconcile_permits,
+            reconcile_failures: Arc::default(),
+            disk_usage_checks: Arc::default(),
+            reconcile_reports: self.reconcile_reports.clone(),
         })
     }
 }
@@ -980,6 +1273,7 @@ pub async fn run(state: State) {
         Err(_) => panic!("Please configure your Kubernetes Context"),
     };
 
+    let cfg = Config::default();
     let coredb = Api::<CoreDB>::all(client.clone());
     if let Err(e) = coredb.list(&ListParams::default().limit(1)).await {
         error!("CRD is not queryable; {e:?}. Is the CRD installed?");
@@ -987,10 +1281,53 @@ pub async fn run(state: State) {
         std::process::exit(1);
     }
 
-    let secret_api = Api::<Secret>::all(client.clone());
+    if let Some(selector) = &cfg.watch_label_selector {
+        let lp = ListParams::default().labels(selector).limit(1);
+        if let Err(e) = coredb.list(&lp).await {
+            error!("WATCH_LABEL_SELECTOR '{selector}' is not a valid label selector; {e:?}");
+            std::process::exit(1);
+        }
+        info!("Only watching CoreDBs matching label selector '{selector}'");
+    }
 
-    Controller::new(coredb, watcherConfig::default().any_semantic())
-        .owns(secret_api, watcherConfig::default().any_semantic())
+    let secret_api = Api::<Secret>::all(client.clone());
+    let configmap_api = Api::<ConfigMap>::all(client.clone());
+
+    let controller = Controller::new(coredb, cfg.coredb_watcher_config());
+    // Instances are reconciled when the Secrets they own change, when the Secrets read by
+    // their appServices change, and when the labeled ConfigMaps read by their appServices
+    // or custom metrics queries change
+    let secret_instances = controller.store();
+    let configmap_instances = controller.store();
+    controller
+        .watches(
+            secret_api,
+            watcherConfig::default().any_semantic(),
+            move |secret| {
+                let mut instances = owner_instances(&secret);
+                instances.extend(referencing_instances(
+                    &secret_instances,
+                    &secret,
+                    references_secret,
+                ));
+                instances
+            },
+        )
+        .watches(
+            configmap_api.clone(),
+            watcherConfig::default().labels(USER_QUERIES_LABEL),
+            |configmap| {
+                let instance = configmap.labels().get(USER_QUERIES_LABEL)?.clone();
+                Some(ObjectRef::new(&instance).within(&configmap.namespace()?))
+            },
+        )
+        .watches(
+            configmap_api,
+            watcherConfig::default().labels(APP_CONFIG_LABEL),
+            move |configmap| {
+                referencing_instances(&configmap_instances, &configmap, references_configmap)
+            },
+        )
         .shutdown_on_signal()
         .run(reconcile, error_policy, state.create_context(client))
         .filter_map(|x| async move { std::result::Result::ok(x) })
@@ -998,6 +1335,34 @@ pub async fn run(state: State) {
         .await;
 }
 
+// The instances owning a resource, which Controller::owns would reconcile
+fn owner_instances<K: Resource>(obj: &K) -> Vec<ObjectRef<CoreDB>> {
+    let Some(namespace) = obj.namespace() else {
+        return vec![];
+    };
+    obj.owner_references()
+        .iter()
+        .filter(|owner| owner.kind == CoreDB::kind(&()))
+        .map(|owner| ObjectRef::new(&owner.name).within(&namespace))
+        .collect()
+}
+
+// The instances of the namespace of a Secret or ConfigMap which read it
+fn referencing_instances<K: Resource>(
+    store: &Store<CoreDB>,
+    obj: &K,
+    references: fn(&CoreDB, &str) -> bool,
+) -> Vec<ObjectRef<CoreDB>> {
+    let namespace = obj.namespace();
+    let name = obj.name_any();
+    store
+        .state()
+        .iter()
+        .filter(|cdb| cdb.namespace() == namespace && references(cdb, &name))
+        .map(|cdb| ObjectRef::from_obj(cdb.as_ref()))
+        .collect()
+}
+
 // Tests rely on fixtures.rs
 #[cfg(test)]
 mod test {
@@ -1257,6 +1622,10 @@ mod test {
             client: client.clone(),
             metrics: Default::default(),
             diagnostics: Default::default(),
+            reconcile_permits: None,
+            reconcile_failures: Default::default(),
+            disk_usage_checks: Default::default(),
+            reconcile_reports: Default::default(),
         });
 
         // setup the mock response 429 too many requests
@@ -1321,6 +1690,10 @@ mod test {
             client: client.clone(),
             metrics: Default::default(),
             diagnostics: Default::default(),
+            reconcile_permits: None,
+            reconcile_failures: Default::default(),
+            disk_usage_checks: Default::default(),
+            reconcile_reports: Default::default(),
         });
 
         // setup the mock response 404 Not Found
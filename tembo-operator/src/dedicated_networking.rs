@@ -1,16 +1,22 @@
-use crate::{apis::coredb_types::CoreDB, Context};
+use crate::{
+    apis::coredb_types::{CoreDB, DedicatedNetworkingLoadBalancer},
+    cloudnativepg::objectstore::ObjectStoreProvider,
+    Context,
+};
 use crate::{errors::OperatorError, network_policies::apply_network_policy};
 use k8s_openapi::api::core::v1::Service;
 use k8s_openapi::api::networking::v1::NetworkPolicy;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::Resource;
 use kube::{
     api::{Api, Patch, PatchParams, ResourceExt},
     client::Client,
 };
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::env;
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// Reconcile dedicated networking resources for the CoreDB instance.
 ///
@@ -394,12 +400,45 @@ async fn reconcile_dedicated_networking_service(
         ),
     ]);
 
+    let load_balancer = cdb
+        .spec
+        .dedicated_networking
+        .as_ref()
+        .and_then(|dn| dn.load_balancer.as_ref());
+    let provider = cdb
+        .spec
+        .backup
+        .destinationPath
+        .as_deref()
+        .and_then(ObjectStoreProvider::from_destination_path);
+
+    // Add the provider specific load balancer annotations without overriding the ones set above
+    for (key, value) in load_balancer_annotations(load_balancer, provider, is_public, is_standby) {
+        annotations
+            .entry(key)
+            .or_insert(serde_json::Value::String(value));
+    }
+
     let mut labels = serde_json::Map::new();
     labels.insert(
         "cnpg.io/cluster".to_string(),
         serde_json::Value::String(cdb_name.to_string()),
     );
 
+    // Add the common labels and annotations without overriding the ones set above
+    let mut common_metadata = ObjectMeta::default();
+    cdb.spec.apply_common_metadata(&mut common_metadata);
+    for (key, value) in common_metadata.labels.unwrap_or_default() {
+        labels
+            .entry(key)
+            .or_insert(serde_json::Value::String(value));
+    }
+    for (key, value) in common_metadata.annotations.unwrap_or_default() {
+        annotations
+            .entry(key)
+            .or_insert(serde_json::Value::String(value));
+    }
+
     let mut service_spec = serde_json::Map::new();
     service_spec.insert(
         "ports".to_string(),
@@ -440,6 +479,13 @@ async fn reconcile_dedicated_networking_service(
         );
     }
 
+    // A static IP can only be bound to one load balancer, so it is pinned to the primary
+    if let Some(static_ip) = load_balancer.and_then(|lb| lb.static_ip.as_ref()) {
+        if service_type == "LoadBalancer" && !is_standby {
+            service_spec.insert("loadBalancerIP".to_string(), json!(static_ip));
+        }
+    }
+
     let service = json!({
         "apiVersion": "v1",
         "kind": "Service",
@@ -476,6 +522,96 @@ async fn reconcile_dedicated_networking_service(
     Ok(())
 }
 
+/// Build the provider-specific annotations for the dedicated load balancer.
+///
+/// Elastic IPs are only valid for public load balancers and private IP addresses
+/// only for internal ones. Both pin addresses, so they only apply to the primary service.
+///
+/// Nothing is added unless a load balancer is configured, so the Services of existing
+/// instances are left untouched.
+///
+/// # Parameters
+/// - `load_balancer`: The load balancer settings from the CoreDB spec.
+/// - `provider`: The cloud provider, detected from the backups path of the instance.
+/// - `is_public`: Whether the service is public or private.
+/// - `is_standby`: Whether the service is for a standby (read-only) instance.
+fn load_balancer_annotations(
+    load_balancer: Option<&DedicatedNetworkingLoadBalancer>,
+    provider: Option<ObjectStoreProvider>,
+    is_public: bool,
+    is_standby: bool,
+) -> BTreeMap<String, String> {
+    let mut annotations = BTreeMap::new();
+    let Some(load_balancer) = load_balancer else {
+        return annotations;
+    };
+
+    // AKS and GKE only create an internal load balancer when asked to
+    if !is_public {
+        match provider {
+            Some(ObjectStoreProvider::Azure) => {
+                annotations.insert(
+                    "service.beta.kubernetes.io/azure-load-balancer-internal".to_string(),
+                    "true".to_string(),
+                );
+            }
+            Some(ObjectStoreProvider::Gcs) => {
+                annotations.insert(
+                    "networking.gke.io/load-balancer-type".to_string(),
+                    "Internal".to_string(),
+                );
+            }
+            Some(ObjectStoreProvider::S3) | None => {}
+        }
+    }
+
+    if let Some(subnets) = load_balancer.subnets.as_ref().filter(|s| !s.is_empty()) {
+        annotations.insert(
+            "service.beta.kubernetes.io/aws-load-balancer-subnets".to_string(),
+            subnets.join(","),
+        );
+    }
+
+    if !is_standby {
+        if let Some(eips) = load_balancer
+            .eip_allocations
+            .as_ref()
+            .filter(|e| !e.is_empty())
+        {
+            if is_public {
+                annotations.insert(
+                    "service.beta.kubernetes.io/aws-load-balancer-eip-allocations".to_string(),
+                    eips.join(","),
+                );
+            } else {
+                warn!("Ignoring eipAllocations for a private dedicated load balancer");
+            }
+        }
+
+        if let Some(ips) = load_balancer
+            .private_ip_addresses
+            .as_ref()
+            .filter(|i| !i.is_empty())
+        {
+            if !is_public {
+                annotations.insert(
+                    "service.beta.kubernetes.io/aws-load-balancer-private-ipv4-addresses"
+                        .to_string(),
+                    ips.join(","),
+                );
+            } else {
+                warn!("Ignoring privateIpAddresses for a public dedicated load balancer");
+            }
+        }
+    }
+
+    for (key, value) in load_balancer.annotations.clone().unwrap_or_default() {
+        annotations.entry(key).or_insert(value);
+    }
+
+    annotations
+}
+
 /// Delete the Service resource for dedicated networking.
 ///
 /// This function deletes the Service resource for either the primary or standby service.
@@ -534,3 +670,96 @@ async fn delete_dedicated_networking_service(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_balancer_annotations() {
+        assert!(load_balancer_annotations(None, None, false, false).is_empty());
+        // Existing instances without a load balancer are left untouched on every provider
+        assert!(
+            load_balancer_annotations(None, Some(ObjectStoreProvider::Azure), false, false)
+                .is_empty()
+        );
+
+        let load_balancer = DedicatedNetworkingLoadBalancer {
+            subnets: Some(vec!["subnet-a".to_string(), "subnet-b".to_string()]),
+            eip_allocations: Some(vec!["eipalloc-a".to_string()]),
+            private_ip_addresses: Some(vec!["10.0.1.10".to_string(), "10.0.2.10".to_string()]),
+            static_ip: None,
+            annotations: Some(BTreeMap::from([(
+                "service.beta.kubernetes.io/aws-load-balancer-subnets".to_string(),
+                "ignored".to_string(),
+            )])),
+        };
+
+        // Private primary gets the private IPs, but never the EIPs
+        let annotations = load_balancer_annotations(Some(&load_balancer), None, false, false);
+        assert_eq!(
+            annotations.get("service.beta.kubernetes.io/aws-load-balancer-subnets"),
+            Some(&"subnet-a,subnet-b".to_string())
+        );
+        assert_eq!(
+            annotations.get("service.beta.kubernetes.io/aws-load-balancer-private-ipv4-addresses"),
+            Some(&"10.0.1.10,10.0.2.10".to_string())
+        );
+        assert!(!annotations
+            .contains_key("service.beta.kubernetes.io/aws-load-balancer-eip-allocations"));
+
+        // Public primary gets the EIPs, but never the private IPs
+        let annotations = load_balancer_annotations(Some(&load_balancer), None, true, false);
+        assert_eq!(
+            annotations.get("service.beta.kubernetes.io/aws-load-balancer-eip-allocations"),
+            Some(&"eipalloc-a".to_string())
+        );
+        assert!(!annotations
+            .contains_key("service.beta.kubernetes.io/aws-load-balancer-private-ipv4-addresses"));
+
+        // Standby does not pin any addresses
+        let annotations = load_balancer_annotations(Some(&load_balancer), None, false, true);
+        assert_eq!(annotations.len(), 1);
+    }
+
+    #[test]
+    fn test_load_balancer_annotations_internal() {
+        let load_balancer = DedicatedNetworkingLoadBalancer::default();
+        let azure_internal = "service.beta.kubernetes.io/azure-load-balancer-internal";
+        let gke_internal = "networking.gke.io/load-balancer-type";
+
+        let annotations = load_balancer_annotations(
+            Some(&load_balancer),
+            Some(ObjectStoreProvider::Azure),
+            false,
+            false,
+        );
+        assert_eq!(annotations.get(azure_internal), Some(&"true".to_string()));
+        assert!(!annotations.contains_key(gke_internal));
+
+        let annotations = load_balancer_annotations(
+            Some(&load_balancer),
+            Some(ObjectStoreProvider::Gcs),
+            false,
+            true,
+        );
+        assert_eq!(annotations.get(gke_internal), Some(&"Internal".to_string()));
+        assert!(!annotations.contains_key(azure_internal));
+
+        // Neither applies on AWS, nor to a public load balancer
+        let annotations = load_balancer_annotations(
+            Some(&load_balancer),
+            Some(ObjectStoreProvider::S3),
+            false,
+            false,
+        );
+        assert!(annotations.is_empty());
+        let annotations = load_balancer_annotations(
+            Some(&load_balancer),
+            Some(ObjectStoreProvider::Azure),
+            true,
+            false,
+        );
+        assert!(annotations.is_empty());
+    }
+}
@@ -127,6 +127,10 @@ pub fn default_postgres_exporter_target_databases() -> Vec<String> {
     vec!["postgres".to_owned()]
 }
 
+pub fn default_queries_config_map_key() -> String {
+    "queries".to_owned()
+}
+
 pub fn default_extensions() -> Vec<Extension> {
     vec![]
 }
@@ -155,6 +159,10 @@ pub fn default_extensions_updating() -> bool {
     false
 }
 
+pub fn default_bootstrap_sql_database() -> String {
+    "postgres".to_owned()
+}
+
 pub fn default_service_account_template() -> ServiceAccountTemplate {
     ServiceAccountTemplate { metadata: None }
 }
@@ -187,6 +195,11 @@ pub fn default_backup_schedule() -> Option<String> {
     Some("0 0 * * *".to_owned())
 }
 
+pub fn default_additional_destination_schedule() -> String {
+    // Every 5 minutes
+    "*/5 * * * *".to_owned()
+}
+
 pub fn default_conn_pooler() -> ConnectionPooler {
     ConnectionPooler {
         enabled: default_conn_pooler_enabled(),
@@ -198,6 +211,10 @@ pub fn default_conn_pooler_enabled() -> bool {
     false
 }
 
+pub fn default_restore_sample_database() -> String {
+    "postgres".to_owned()
+}
+
 pub fn default_pool_mode() -> PoolerPgbouncerPoolMode {
     PoolerPgbouncerPoolMode::Transaction
 }
@@ -0,0 +1,343 @@
+use crate::{
+    apis::coredb_types::{CoreDB, DiskFullProtection},
+    config::Config,
+    events::{
+        publish_event, REASON_DISK_FULL_PROTECTION_CLEARED, REASON_DISK_FULL_PROTECTION_ENABLED,
+    },
+    patch_cdb_status_merge, Context,
+};
+use chrono::Utc;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::{controller::Action, events::EventType},
+    Api, ResourceExt,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+const PGDATA: &str = "/var/lib/postgresql/data";
+
+// How often the instance is reconciled again while it is read only
+pub const DISK_FULL_REQUEUE_SEC: u64 = 60;
+
+// How often the data volume usage is measured, the check execs into the primary
+const DISK_USAGE_CHECK_INTERVAL_SEC: u64 = 300;
+
+// reconcile_disk_full_protection makes the instance read only once its data volume usage
+// crosses the critical threshold, so Postgres does not run out of space for WAL, and makes
+// it writable again once usage falls below the recovery threshold. The read only setting is
+// applied to the Cluster from status.disk_full_protection, see disk_full_parameters.
+// Returns whether the instance is read only, so the caller can reconcile it again sooner.
+pub async fn reconcile_disk_full_protection(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    cfg: &Config,
+) -> Result<bool, Action> {
+    let name = cdb.name_any();
+    let protection = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.disk_full_protection.clone());
+    if cdb.spec.stop || (!cfg.enable_disk_full_protection && protection.is_none()) {
+        return Ok(false);
+    }
+    if !usage_check_due(cdb, &ctx, protection.is_some()) {
+        return Ok(protection.is_some());
+    }
+
+    // An unknown usage does not make the instance read only, a protected instance stays so
+    let Some(usage_percent) = data_volume_usage_percent(cdb, ctx.clone()).await else {
+        return Ok(protection.is_some());
+    };
+    debug!("Data volume usage of {} is {}%", name, usage_percent);
+
+    let namespace = cdb.namespace().unwrap();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    match protection {
+        None if usage_percent >= cfg.disk_full_threshold_percent => {
+            warn!(
+                "Data volume of {} is {}% full, making the instance read only",
+                name, usage_percent
+            );
+            let storage_increased_to = increase_storage(cdb, cfg, &coredbs).await?;
+            let protection = DiskFullProtection {
+                since: Utc::now(),
+                usage_percent,
+                storage_increased_to: storage_increased_to.clone(),
+            };
+            let patch_status = json!({
+                "apiVersion": "coredb.io/v1alpha1",
+                "kind": "CoreDB",
+                "status": {
+                    "disk_full_protection": protection
+                }
+            });
+            patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+
+            let mut note = format!(
+                "Data volume is {}% full, the instance is read only until usage is below {}%",
+                usage_percent, cfg.disk_full_recovery_percent
+            );
+            if let Some(storage) = storage_increased_to {
+                note.push_str(&format!(", storage is increased to {}", storage.0));
+            }
+            publish_event(
+                cdb,
+                &ctx,
+                EventType::Warning,
+                REASON_DISK_FULL_PROTECTION_ENABLED,
+                "DiskFullProtection",
+                note,
+            )
+            .await;
+            Ok(true)
+        }
+        None => Ok(false),
+        Some(_) if usage_percent < cfg.disk_full_recovery_percent => {
+            info!(
+                "Data volume of {} is {}% full, making the instance writable again",
+                name, usage_percent
+            );
+            // A null value removes the field with a merge patch
+            let patch_status = json!({
+                "apiVersion": "coredb.io/v1alpha1",
+                "kind": "CoreDB",
+                "status": {
+                    "disk_full_protection": null
+                }
+            });
+            patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+            publish_event(
+                cdb,
+                &ctx,
+                EventType::Normal,
+                REASON_DISK_FULL_PROTECTION_CLEARED,
+                "DiskFullProtection",
+                format!(
+                    "Data volume is {}% full, the instance is writable again",
+                    usage_percent
+                ),
+            )
+            .await;
+            Ok(false)
+        }
+        Some(_) => {
+            warn!(
+                "Data volume of {} is still {}% full, the instance stays read only",
+                name, usage_percent
+            );
+            Ok(true)
+        }
+    }
+}
+
+// Whether the data volume usage should be measured again, while the instance is read only
+// it is measured on every requeue so it is made writable again soon after space is freed
+fn usage_check_due(cdb: &CoreDB, ctx: &Context, protected: bool) -> bool {
+    let interval = if protected {
+        DISK_FULL_REQUEUE_SEC / 2
+    } else {
+        DISK_USAGE_CHECK_INTERVAL_SEC
+    };
+    let key = format!("{}/{}", cdb.namespace().unwrap_or_default(), cdb.name_any());
+    let now = Instant::now();
+    let mut checks = ctx.disk_usage_checks.lock().unwrap();
+    match checks.get(&key) {
+        Some(last) if now.duration_since(*last) < Duration::from_secs(interval) => false,
+        _ => {
+            checks.insert(key, now);
+            true
+        }
+    }
+}
+
+// disk_full_parameters adds the protective Postgres parameters to the Cluster while
+// status.disk_full_protection is set
+pub(crate) fn disk_full_parameters(
+    cdb: &CoreDB,
+    postgres_parameters: Option<BTreeMap<String, String>>,
+) -> Option<BTreeMap<String, String>> {
+    let protected = cdb
+        .status
+        .as_ref()
+        .is_some_and(|s| s.disk_full_protection.is_some());
+    if !protected {
+        return postgres_parameters;
+    }
+
+    let mut postgres_parameters = postgres_parameters.unwrap_or_default();
+    postgres_parameters.insert(
+        "default_transaction_read_only".to_string(),
+        "on".to_string(),
+    );
+    Some(postgres_parameters)
+}
+
+// Measure the usage of the data volume on the primary, None when it can not be measured
+async fn data_volume_usage_percent(cdb: &CoreDB, ctx: Arc<Context>) -> Option<u8> {
+    let pod = cdb.primary_pod_cnpg(ctx.client.clone()).await.ok()?;
+    let pod_name = pod.metadata.name?;
+    let command = vec!["df".to_string(), "-P".to_string(), PGDATA.to_string()];
+    match cdb.exec(pod_name, ctx.client.clone(), &command).await {
+        Ok(output) if output.success => {
+            let usage = output.stdout.as_deref().and_then(parse_df_usage_percent);
+            if usage.is_none() {
+                warn!(
+                    "Could not parse data volume usage of {}: {:?}",
+                    cdb.name_any(),
+                    output.stdout
+                );
+            }
+            usage
+        }
+        Ok(output) => {
+            warn!(
+                "Failed to measure data volume usage of {}: {:?}",
+                cdb.name_any(),
+                output.stderr
+            );
+            None
+        }
+        Err(e) => {
+            warn!(
+                "Failed to measure data volume usage of {}: {:?}",
+                cdb.name_any(),
+                e
+            );
+            None
+        }
+    }
+}
+
+// Parse the capacity column of `df -P`, e.g.
+// Filesystem     1024-blocks    Used Available Capacity Mounted on
+// /dev/nvme1n1      10218772 9731276    471112      96% /var/lib/postgresql/data
+fn parse_df_usage_percent(stdout: &str) -> Option<u8> {
+    stdout
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(4)?
+        .trim_end_matches('%')
+        .parse()
+        .ok()
+}
+
+// Patch spec.storage with the emergency increase, when enabled
+async fn increase_storage(
+    cdb: &CoreDB,
+    cfg: &Config,
+    coredbs: &Api<CoreDB>,
+) -> Result<Option<Quantity>, Action> {
+    if cfg.disk_full_storage_increase_percent == 0 {
+        return Ok(None);
+    }
+    let name = cdb.name_any();
+    let Some(storage) =
+        increased_storage(&cdb.spec.storage, cfg.disk_full_storage_increase_percent)
+    else {
+        warn!(
+            "Could not increase storage {} of {}",
+            cdb.spec.storage.0, name
+        );
+        return Ok(None);
+    };
+
+    let patch = json!({
+        "spec": {
+            "storage": storage
+        }
+    });
+    let patch_params = PatchParams {
+        field_manager: Some("cntrlr".to_string()),
+        ..PatchParams::default()
+    };
+    match coredbs
+        .patch(&name, &patch_params, &Patch::Merge(patch))
+        .await
+    {
+        Ok(_) => {
+            info!(
+                "Increased storage of {} from {} to {}",
+                name, cdb.spec.storage.0, storage.0
+            );
+            Ok(Some(storage))
+        }
+        Err(e) => {
+            error!("Error increasing storage of {}: {:?}", name, e);
+            Err(Action::requeue(Duration::from_secs(10)))
+        }
+    }
+}
+
+// Increase a storage size like `10Gi` by a percentage, rounded up to a whole unit
+fn increased_storage(storage: &Quantity, percent: u32) -> Option<Quantity> {
+    let split = storage
+        .0
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(storage.0.len());
+    let (value, unit) = storage.0.split_at(split);
+    if !["", "Ki", "Mi", "Gi", "Ti", "k", "M", "G", "T"].contains(&unit) {
+        return None;
+    }
+    let value: u64 = value.parse().ok()?;
+    let increased = (value * (100 + percent as u64)).div_ceil(100);
+    Some(Quantity(format!("{}{}", increased, unit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::{CoreDBSpec, CoreDBStatus};
+
+    #[test]
+    fn test_parse_df_usage_percent() {
+        let stdout = "Filesystem     1024-blocks    Used Available Capacity Mounted on\n/dev/nvme1n1      10218772 9731276    471112      96% /var/lib/postgresql/data\n";
+        assert_eq!(parse_df_usage_percent(stdout), Some(96));
+        assert_eq!(parse_df_usage_percent(""), None);
+        assert_eq!(parse_df_usage_percent("Filesystem\n"), None);
+    }
+
+    #[test]
+    fn test_increased_storage() {
+        assert_eq!(
+            increased_storage(&Quantity("10Gi".to_string()), 20),
+            Some(Quantity("12Gi".to_string()))
+        );
+        assert_eq!(
+            increased_storage(&Quantity("15Gi".to_string()), 10),
+            Some(Quantity("17Gi".to_string()))
+        );
+        assert_eq!(increased_storage(&Quantity("1.5Gi".to_string()), 10), None);
+    }
+
+    #[test]
+    fn test_disk_full_parameters() {
+        let mut cdb = CoreDB::new("test", CoreDBSpec::default());
+        let parameters = BTreeMap::from([("work_mem".to_string(), "4MB".to_string())]);
+        assert_eq!(
+            disk_full_parameters(&cdb, Some(parameters.clone())),
+            Some(parameters.clone())
+        );
+
+        cdb.status = Some(CoreDBStatus {
+            disk_full_protection: Some(DiskFullProtection {
+                since: Utc::now(),
+                usage_percent: 96,
+                storage_increased_to: None,
+            }),
+            ..CoreDBStatus::default()
+        });
+        let parameters = disk_full_parameters(&cdb, Some(parameters)).unwrap();
+        assert_eq!(
+            parameters.get("default_transaction_read_only"),
+            Some(&"on".to_string())
+        );
+        assert_eq!(parameters.get("work_mem"), Some(&"4MB".to_string()));
+        assert!(disk_full_parameters(&cdb, None).is_some());
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{apis::coredb_types::CoreDB, Context};
+use kube::{
+    runtime::events::{Event, EventType},
+    ResourceExt,
+};
+use tracing::warn;
+
+pub const REASON_CREATED: &str = "Created";
+pub const REASON_EXTENSION_INSTALLED: &str = "ExtensionInstalled";
+pub const REASON_EXTENSION_INSTALL_FAILED: &str = "ExtensionInstallFailed";
+pub const REASON_POOLER_CREATED: &str = "PoolerCreated";
+pub const REASON_POOLER_UPDATED: &str = "PoolerUpdated";
+pub const REASON_POOLER_DELETED: &str = "PoolerDeleted";
+pub const REASON_HIBERNATED: &str = "Hibernated";
+pub const REASON_WOKEN: &str = "Woken";
+pub const REASON_BACKUP_CONFIG_ERROR: &str = "BackupConfigurationError";
+pub const REASON_RESTARTED: &str = "Restarted";
+pub const REASON_PRE_OPERATION_BACKUP: &str = "PreOperationBackup";
+pub const REASON_PRE_OPERATION_BACKUP_FAILED: &str = "PreOperationBackupFailed";
+pub const REASON_DISK_FULL_PROTECTION_ENABLED: &str = "DiskFullProtectionEnabled";
+pub const REASON_DISK_FULL_PROTECTION_CLEARED: &str = "DiskFullProtectionCleared";
+pub const REASON_IDLE_HIBERNATED: &str = "IdleHibernated";
+pub const REASON_TTL_EXPIRED: &str = "TTLExpired";
+
+// Record a lifecycle transition as a Kubernetes Event on the CoreDB, so it shows up in
+// `kubectl describe coredb`. Events are best effort, a failure to publish is only logged.
+pub(crate) async fn publish_event(
+    cdb: &CoreDB,
+    ctx: &Context,
+    type_: EventType,
+    reason: &str,
+    action: &str,
+    note: String,
+) {
+    let recorder = ctx
+        .diagnostics
+        .read()
+        .await
+        .recorder(ctx.client.clone(), cdb);
+    let event = Event {
+        type_,
+        reason: reason.into(),
+        note: Some(note),
+        action: action.into(),
+        secondary: None,
+    };
+    if let Err(e) = recorder.publish(event).await {
+        warn!(
+            "Failed to publish {} event for {}: {:?}",
+            reason,
+            cdb.name_any(),
+            e
+        );
+    }
+}
@@ -1,20 +1,27 @@
 use crate::{
     apis::coredb_types::CoreDB,
     cloudnativepg::cnpg::{get_fenced_pods, unfence_pod},
+    events::{publish_event, REASON_EXTENSION_INSTALLED, REASON_EXTENSION_INSTALL_FAILED},
     extensions::{
         kubernetes_queries::{add_trunk_install_to_status, remove_trunk_installs_from_status},
-        types::{TrunkInstall, TrunkInstallStatus},
+        types::{TrunkInstall, TrunkInstallProvenance, TrunkInstallStatus},
+    },
+    trunk::{
+        get_latest_trunk_project_version, get_trunk_project_artifact_sha256, trunk_registry_url,
     },
-    trunk::get_latest_trunk_project_version,
     Context,
 };
 use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::apis::meta::v1::ObjectMeta};
-use kube::{runtime::controller::Action, Api, ResourceExt};
+use kube::{
+    runtime::{controller::Action, events::EventType},
+    Api, ResourceExt,
+};
 use std::{collections::HashSet, sync::Arc, time::Duration};
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::apis::coredb_types::CoreDBStatus;
 use crate::defaults::postgres_major_version_from_cdb;
+use crate::postgres_versions::postgres_version_of;
 
 // Syncroniously merge and deduplicate pods
 #[instrument(skip(non_fenced_pods, fenced_names) fields(trace_id))]
@@ -132,9 +139,19 @@ pub fn find_trunk_installs_to_pod<'a>(cdb: &'a CoreDB, pod_name: &str) -> Vec<&'
 
     let pod_name = pod_name.to_owned();
     let mut trunk_installs_to_install = Vec::new();
+    let postgres_version = postgres_version_of(cdb);
 
     // Get extensions in spec.trunk_install that are not in status.trunk_install
     for ext in &cdb.spec.trunk_installs {
+        if postgres_version.is_some_and(|version| !version.supports_extension(&ext.name)) {
+            warn!(
+                "Not installing {} into {}, it is not available for its Postgres version",
+                ext.name,
+                cdb.name_any()
+            );
+            continue;
+        }
+
         // All TrunkInstallStatus in CDB spec
         let trunk_install_statuses = cdb
             .status
@@ -322,6 +339,7 @@ async fn execute_extension_install_command(
                         loading: false,
                         error_message: Some("Missing version".to_string()),
                         installed_to_pods: Some(vec![pod_name.to_string()]),
+                        provenance: None,
                     });
                 }
             }
@@ -329,13 +347,14 @@ async fn execute_extension_install_command(
         Some(version) => version.clone(),
     };
 
+    let registry = trunk_registry_url();
     let cmd = vec![
         "trunk".to_owned(),
         "install".to_owned(),
-        "-r https://registry.pgtrunk.io".to_owned(),
+        format!("-r {}", registry),
         ext.name.clone(),
         "--version".to_owned(),
-        version,
+        version.clone(),
     ];
 
     // If the pod is not up yet, do not try and install the extension
@@ -369,6 +388,19 @@ async fn execute_extension_install_command(
                     "Installed extension {} into {} for {}",
                     &ext.name, pod_name, coredb_name
                 );
+                publish_event(
+                    cdb,
+                    &ctx,
+                    EventType::Normal,
+                    REASON_EXTENSION_INSTALLED,
+                    "InstallExtension",
+                    format!(
+                        "Installed extension {} version {} into {}",
+                        ext.name, version, pod_name
+                    ),
+                )
+                .await;
+                let provenance = trunk_install_provenance(cdb, &ext.name, version, registry).await;
                 TrunkInstallStatus {
                     name: ext.name.clone(),
                     version: ext.version.clone(),
@@ -376,12 +408,25 @@ async fn execute_extension_install_command(
                     loading: false,
                     error_message: None,
                     installed_to_pods: Some(vec![pod_name.to_string()]),
+                    provenance: Some(provenance),
                 }
             } else {
                 error!(
                     "Failed to install extension {} into {}:\n{}",
                     &ext.name, pod_name, output
                 );
+                publish_event(
+                    cdb,
+                    &ctx,
+                    EventType::Warning,
+                    REASON_EXTENSION_INSTALL_FAILED,
+                    "InstallExtension",
+                    format!(
+                        "Failed to install extension {} version {} into {}",
+                        ext.name, version, pod_name
+                    ),
+                )
+                .await;
                 TrunkInstallStatus {
                     name: ext.name.clone(),
                     version: ext.version.clone(),
@@ -389,6 +434,7 @@ async fn execute_extension_install_command(
                     error_message: Some(output),
                     loading: false,
                     installed_to_pods: Some(vec![pod_name.to_string()]),
+                    provenance: None,
                 }
             };
 
@@ -404,6 +450,36 @@ async fn execute_extension_install_command(
     }
 }
 
+// Record which trunk artifact was installed. A failure to look up the checksum does not
+// fail the install, the provenance is recorded without it.
+async fn trunk_install_provenance(
+    cdb: &CoreDB,
+    trunk_project: &str,
+    trunk_project_version: String,
+    registry: String,
+) -> TrunkInstallProvenance {
+    let pg_version = postgres_major_version_from_cdb(cdb).unwrap_or(15);
+    let sha256 =
+        match get_trunk_project_artifact_sha256(trunk_project, &trunk_project_version, pg_version)
+            .await
+        {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                warn!(
+                    "Failed to get artifact checksum for {} version {}: {:?}",
+                    trunk_project, trunk_project_version, e
+                );
+                None
+            }
+        };
+
+    TrunkInstallProvenance {
+        trunk_project_version,
+        registry,
+        sha256,
+    }
+}
+
 // Check if <extension_name>.so file exists for a given extension in /var/lib/postgresql/data/tembo/15/lib
 #[instrument(skip(cdb, ctx, pod_name) fields(trace_id))]
 pub async fn check_for_so_files(
@@ -621,6 +697,7 @@ mod tests {
             error_message: None,
             loading: false,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
         };
 
         let trunk_install_status2 = TrunkInstallStatus {
@@ -630,6 +707,7 @@ mod tests {
             error_message: None,
             loading: false,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
         };
 
         let trunk_install_status3 = TrunkInstallStatus {
@@ -639,6 +717,7 @@ mod tests {
             loading: false,
             error_message: None,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
         };
 
         let cdb = CoreDB {
@@ -691,6 +770,7 @@ mod tests {
             error_message: None,
             loading: false,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
         };
 
         let cdb = CoreDB {
@@ -741,6 +821,7 @@ mod tests {
             loading: false,
             error_message: None,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
         };
 
         let trunk_install_status2 = TrunkInstallStatus {
@@ -750,6 +831,7 @@ mod tests {
             loading: false,
             error_message: None,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
         };
 
         let cdb_with_status = CoreDB {
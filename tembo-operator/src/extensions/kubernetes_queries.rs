@@ -273,6 +273,9 @@ fn update_trunk_installs(
                     installed_to_pods.dedup();
                 }
             }
+            if new_trunk_install.provenance.is_some() {
+                update_status.provenance = new_trunk_install.provenance.clone();
+            }
             updated_trunk_installs.push(update_status);
         } else {
             updated_trunk_installs.push(existing_status.clone());
@@ -305,6 +308,7 @@ mod tests {
             error_message: None,
             loading: false,
             installed_to_pods: None,
+            provenance: None,
         }];
         let new_trunk_install = TrunkInstallStatus {
             name: "pg_stat_statements".to_string(),
@@ -313,6 +317,7 @@ mod tests {
             error_message: None,
             loading: false,
             installed_to_pods: Some(vec!["pod-1".to_string(), "pod-2".to_string()]),
+            provenance: None,
         };
 
         let updated_trunk_installs =
@@ -334,6 +339,7 @@ mod tests {
         let initial_trunk_installs = vec![TrunkInstallStatus {
             error: false,
             installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+            provenance: None,
             name: "test_name".to_string(),
             version: Some("1.0.0".to_string()),
             loading: false,
@@ -343,6 +349,7 @@ mod tests {
         let new_trunk_install = TrunkInstallStatus {
             error: false,
             installed_to_pods: Some(vec!["test-coredb-24631-2".to_string()]),
+            provenance: None,
             name: "test_name".to_string(),
             version: Some("1.0.0".to_string()),
             loading: false,
@@ -370,6 +377,7 @@ mod tests {
                     "test-coredb-24631-1".to_string(),
                     "test-coredb-24631-2".to_string(),
                 ]),
+                provenance: None,
                 name: "test_name".to_string(),
                 version: Some("1.0.0".to_string()),
                 loading: false,
@@ -378,6 +386,7 @@ mod tests {
             TrunkInstallStatus {
                 error: false,
                 installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+                provenance: None,
                 name: "test_name2".to_string(),
                 version: Some("1.0.0".to_string()),
                 loading: false,
@@ -388,6 +397,7 @@ mod tests {
         let new_trunk_install = TrunkInstallStatus {
             error: false,
             installed_to_pods: Some(vec!["test-coredb-24631-2".to_string()]),
+            provenance: None,
             name: "test_name2".to_string(),
             version: Some("1.0.0".to_string()),
             loading: false,
@@ -419,6 +429,7 @@ mod tests {
             TrunkInstallStatus {
                 error: false,
                 installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+                provenance: None,
                 name: "pg_partman".to_string(),
                 version: Some("4.7.3".to_string()),
                 error_message: None,
@@ -427,6 +438,7 @@ mod tests {
             TrunkInstallStatus {
                 error: false,
                 installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+                provenance: None,
                 name: "pg_stat_statements".to_string(),
                 version: Some("1.10.0".to_string()),
                 error_message: None,
@@ -435,6 +447,7 @@ mod tests {
             TrunkInstallStatus {
                 error: false,
                 installed_to_pods: Some(vec!["test-coredb-24631-1".to_string()]),
+                provenance: None,
                 name: "pgmq".to_string(),
                 version: Some("0.10.0".to_string()),
                 error_message: None,
@@ -445,6 +458,7 @@ mod tests {
         let new_trunk_install = TrunkInstallStatus {
             error: false,
             installed_to_pods: Some(vec!["test-coredb-24631-2".to_string()]),
+            provenance: None,
             name: "pg_partman".to_string(),
             version: Some("4.7.3".to_string()),
             error_message: None,
@@ -467,6 +481,7 @@ mod tests {
         let new_trunk_install = TrunkInstallStatus {
             error: false,
             installed_to_pods: Some(vec!["test-coredb-24631-2".to_string()]),
+            provenance: None,
             name: "pg_stat_statements".to_string(),
             version: Some("1.10.0".to_string()),
             error_message: None,
@@ -489,6 +504,7 @@ mod tests {
         let new_trunk_install = TrunkInstallStatus {
             error: false,
             installed_to_pods: Some(vec!["test-coredb-24631-2".to_string()]),
+            provenance: None,
             name: "pgmq".to_string(),
             version: Some("0.10.0".to_string()),
             error_message: None,
@@ -1,6 +1,7 @@
 pub mod database_queries;
 pub mod install;
 pub mod kubernetes_queries;
+pub mod post_install;
 pub mod toggle;
 pub mod types;
 
@@ -35,8 +36,22 @@ pub async fn reconcile_extensions(
         return Err(Action::requeue(Duration::from_secs(5)));
     }
 
+    // A standby can not create extensions, they are replicated from the instance it follows.
+    // The files of trunk installs are still needed, to replay WAL and for promotion.
+    if coredb.spec.is_standby() {
+        let extension_statuses = coredb
+            .status
+            .as_ref()
+            .and_then(|status| status.extensions.clone())
+            .unwrap_or_default();
+        return Ok((trunk_installs, extension_statuses));
+    }
+
     // Toggles require postgres is ready
     debug!("Reconciling extension statuses: {}", coredb_name);
     let extension_statuses = toggle::reconcile_extension_toggle_state(coredb, ctx.clone()).await?;
+
+    // Setup some extensions need once they are enabled
+    post_install::reconcile_post_install_sql(coredb, ctx.clone(), &extension_statuses).await?;
     Ok((trunk_installs, extension_statuses))
 }
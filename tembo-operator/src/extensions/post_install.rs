@@ -0,0 +1,182 @@
+use crate::{
+    apis::coredb_types::CoreDB,
+    extensions::types::{Extension, ExtensionStatus},
+    patch_cdb_status_merge, Context,
+};
+use kube::{runtime::controller::Action, Api, ResourceExt};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info};
+
+// reconcile_post_install_sql runs the post_install_sql of each extension once in every database
+// where the extension was enabled, recording each one in status.post_install_sql_applied right
+// away. Locations where the extension is no longer enabled are forgotten, so the SQL runs again
+// when the extension is installed again.
+pub async fn reconcile_post_install_sql(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    extension_statuses: &[ExtensionStatus],
+) -> Result<(), Action> {
+    let previously_applied = cdb
+        .status
+        .as_ref()
+        .and_then(|status| status.post_install_sql_applied.clone())
+        .unwrap_or_default();
+    let enabled = enabled_locations(extension_statuses);
+    let mut applied: Vec<String> = previously_applied
+        .iter()
+        .filter(|location| enabled.contains(location))
+        .cloned()
+        .collect();
+    let forgotten = applied.len() != previously_applied.len();
+    let pending = pending_post_install_sql(&cdb.spec.extensions, &enabled, &applied);
+    if pending.is_empty() && !forgotten {
+        return Ok(());
+    }
+
+    let name = cdb.name_any();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &cdb.namespace().unwrap());
+    if forgotten {
+        patch_post_install_sql_applied(&coredbs, &name, &applied).await?;
+    }
+    for (extension, database) in pending {
+        let Some(sql) = extension.post_install_sql.clone() else {
+            continue;
+        };
+        debug!(
+            "Running post install SQL of extension {} in database {} on instance {}",
+            extension.name, database, name
+        );
+        let result = cdb.psql(sql, database.to_string(), ctx.clone()).await?;
+        if !result.success {
+            error!(
+                "Failed to run post install SQL of extension {} in database {} on instance {}: {:?}",
+                extension.name, database, name, result.stderr
+            );
+            return Err(Action::requeue(Duration::from_secs(300)));
+        }
+        info!(
+            "Applied post install SQL of extension {} in database {} on instance {}",
+            extension.name, database, name
+        );
+        applied.push(location_key(&extension.name, database));
+        patch_post_install_sql_applied(&coredbs, &name, &applied).await?;
+    }
+    Ok(())
+}
+
+async fn patch_post_install_sql_applied(
+    coredbs: &Api<CoreDB>,
+    name: &str,
+    applied: &[String],
+) -> Result<(), Action> {
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "post_install_sql_applied": applied,
+        }
+    });
+    patch_cdb_status_merge(coredbs, name, patch_status).await
+}
+
+fn location_key(extension: &str, database: &str) -> String {
+    format!("{}/{}", extension, database)
+}
+
+// The locations where an extension is enabled without errors, as `<extension>/<database>`
+fn enabled_locations(extension_statuses: &[ExtensionStatus]) -> Vec<String> {
+    extension_statuses
+        .iter()
+        .flat_map(|extension| {
+            extension
+                .locations
+                .iter()
+                .filter(|location| location.enabled == Some(true) && location.error != Some(true))
+                .map(|location| location_key(&extension.name, &location.database))
+        })
+        .collect()
+}
+
+// The extensions and databases where the post install SQL still has to run, in the order of the spec
+fn pending_post_install_sql<'a>(
+    extensions: &'a [Extension],
+    enabled: &[String],
+    applied: &[String],
+) -> Vec<(&'a Extension, &'a str)> {
+    extensions
+        .iter()
+        .filter(|extension| extension.post_install_sql.is_some())
+        .flat_map(|extension| {
+            extension
+                .locations
+                .iter()
+                .filter(|location| location.enabled)
+                .map(move |location| (extension, location.database.as_str()))
+        })
+        .filter(|(extension, database)| {
+            let key = location_key(&extension.name, database);
+            enabled.contains(&key) && !applied.contains(&key)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::types::{ExtensionInstallLocation, ExtensionInstallLocationStatus};
+
+    fn location_status(database: &str, enabled: bool) -> ExtensionInstallLocationStatus {
+        ExtensionInstallLocationStatus {
+            database: database.to_string(),
+            schema: None,
+            version: None,
+            enabled: Some(enabled),
+            error: Some(false),
+            error_message: None,
+        }
+    }
+
+    #[test]
+    fn test_pending_post_install_sql() {
+        let extensions = vec![Extension {
+            name: "pg_partman".to_string(),
+            description: None,
+            locations: vec![
+                ExtensionInstallLocation {
+                    enabled: true,
+                    database: "app".to_string(),
+                    ..ExtensionInstallLocation::default()
+                },
+                ExtensionInstallLocation {
+                    enabled: true,
+                    database: "postgres".to_string(),
+                    ..ExtensionInstallLocation::default()
+                },
+            ],
+            post_install_sql: Some(
+                "SELECT partman.create_parent('public.events', 'created_at', '1 day');".to_string(),
+            ),
+        }];
+        let statuses = vec![ExtensionStatus {
+            name: "pg_partman".to_string(),
+            description: None,
+            locations: vec![
+                location_status("app", true),
+                location_status("postgres", false),
+            ],
+        }];
+
+        let enabled = enabled_locations(&statuses);
+        assert_eq!(enabled, vec!["pg_partman/app".to_string()]);
+
+        // Not yet enabled in postgres, so it only runs in app
+        let pending = pending_post_install_sql(&extensions, &enabled, &[]);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1, "app");
+
+        let applied = vec!["pg_partman/app".to_string()];
+        assert!(pending_post_install_sql(&extensions, &enabled, &applied).is_empty());
+    }
+}
@@ -62,6 +62,31 @@ pub struct TrunkInstallStatus {
     pub loading: bool,
     pub error_message: Option<String>,
     pub installed_to_pods: Option<Vec<String>>,
+    /// Where the installed binaries came from, recorded after a successful install
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<TrunkInstallProvenance>,
+}
+
+/// TrunkInstallProvenance records exactly which trunk artifact was installed, so the
+/// binaries loaded into an instance can be audited.
+///
+/// ```yaml
+/// provenance:
+///   trunk_project_version: 1.1.1
+///   registry: https://registry.pgtrunk.io
+///   sha256: 1b3a8e4c0b2f5d6e7a8c9d0e1f2a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c
+/// ```
+#[derive(Clone, Debug, Deserialize, Eq, Hash, JsonSchema, Serialize, PartialEq, ToSchema)]
+pub struct TrunkInstallProvenance {
+    /// The trunk project version which was installed
+    pub trunk_project_version: String,
+
+    /// The trunk registry the artifact was downloaded from
+    pub registry: String,
+
+    /// The sha256 checksum of the artifact published to the registry, when available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
 /// Extension lets you define a list of extensions to enable on the instance. To enable
@@ -100,6 +125,12 @@ pub struct Extension {
 
     /// A list of locations (databases) to enabled the extension on.
     pub locations: Vec<ExtensionInstallLocation>,
+
+    /// SQL to run once in each database after the extension is enabled on it, for setup
+    /// the extension needs, e.g. creating the parent tables of pg_partman. It runs again
+    /// when the extension is disabled and enabled again. (Optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install_sql: Option<String>,
 }
 
 impl Default for Extension {
@@ -111,6 +142,7 @@ impl Default for Extension {
                     .to_owned(),
             ),
             locations: vec![ExtensionInstallLocation::default()],
+            post_install_sql: None,
         }
     }
 }
@@ -127,6 +159,7 @@ impl From<ExtensionStatus> for Extension {
             name: status.name,
             description: status.description,
             locations,
+            post_install_sql: None,
         }
     }
 }
@@ -337,6 +370,7 @@ mod tests {
                     name: extension_name.to_owned(),
                     description: None,
                     locations: vec![location.clone()],
+                    post_install_sql: None,
                 }],
                 ..CoreDBSpec::default()
             },
@@ -394,6 +428,7 @@ mod tests {
             Extension {
                 name: "ext3".to_string(),
                 description: None,
+                post_install_sql: None,
                 locations: vec![ExtensionInstallLocation {
                     enabled: true,
                     schema: None,
@@ -404,6 +439,7 @@ mod tests {
             Extension {
                 name: "ext1".to_string(),
                 description: None,
+                post_install_sql: None,
                 locations: vec![
                     // Requesting to enable a currently disabled extension
                     ExtensionInstallLocation {
@@ -468,6 +504,7 @@ mod tests {
             Extension {
                 name: "ext2".to_string(),
                 description: None,
+                post_install_sql: None,
                 locations: vec![ExtensionInstallLocation {
                     enabled: false,
                     schema: None,
@@ -900,6 +937,7 @@ mod tests {
             error: false,
             error_message: None,
             installed_to_pods: None,
+            provenance: None,
             loading: false,
         };
         let trunk_install: TrunkInstall = status.into();
@@ -0,0 +1,180 @@
+pub mod push_secret_crd;
+
+use crate::{
+    apis::coredb_types::CoreDB,
+    config::Config,
+    external_secrets::push_secret_crd::{
+        PushSecret, PushSecretData, PushSecretDataMatch, PushSecretDataMatchRemoteRef,
+        PushSecretSecretStoreRefs, PushSecretSelector, PushSecretSelectorSecret, PushSecretSpec,
+    },
+    Context,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{
+    api::{DeleteParams, Patch, PatchParams},
+    runtime::controller::Action,
+    Api, Resource, ResourceExt,
+};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info};
+
+// How often the External Secrets Operator pushes the secrets again, so rotated
+// passwords and changes made in the secret manager are reconciled
+const REFRESH_INTERVAL: &str = "1h";
+
+/// The secrets generated for an instance which are mirrored to the secret manager
+pub fn mirrored_secret_names(cdb: &CoreDB) -> Vec<String> {
+    let name = cdb.name_any();
+    vec![format!("{}-connection", name), format!("{}-ro", name)]
+}
+
+// The name of the secret in the secret manager. AWS Secrets Manager and GCP Secret Manager
+// both accept alphanumeric characters, dashes and underscores.
+fn remote_key(prefix: &str, namespace: &str, secret_name: &str) -> String {
+    format!("{}{}-{}", prefix, namespace, secret_name)
+}
+
+fn push_secret_name(secret_name: &str) -> String {
+    format!("{}-mirror", secret_name)
+}
+
+fn push_secret(cdb: &CoreDB, cfg: &Config, store: &str, secret_name: &str) -> PushSecret {
+    let namespace = cdb.namespace().unwrap();
+    let mut push_secret = PushSecret {
+        metadata: ObjectMeta {
+            name: Some(push_secret_name(secret_name)),
+            namespace: Some(namespace.clone()),
+            owner_references: Some(vec![cdb.controller_owner_ref(&()).unwrap()]),
+            ..ObjectMeta::default()
+        },
+        spec: PushSecretSpec {
+            // Remove the secret from the secret manager when the instance is deleted or opts out
+            deletion_policy: Some("Delete".to_string()),
+            refresh_interval: Some(REFRESH_INTERVAL.to_string()),
+            secret_store_refs: vec![PushSecretSecretStoreRefs {
+                kind: Some(cfg.external_secret_store_kind.clone()),
+                name: Some(store.to_string()),
+            }],
+            selector: PushSecretSelector {
+                secret: PushSecretSelectorSecret {
+                    name: secret_name.to_string(),
+                },
+            },
+            // Without a secret key, all keys of the secret are pushed as one JSON secret
+            data: Some(vec![PushSecretData {
+                r#match: PushSecretDataMatch {
+                    secret_key: None,
+                    remote_ref: PushSecretDataMatchRemoteRef {
+                        remote_key: remote_key(
+                            &cfg.external_secret_key_prefix,
+                            &namespace,
+                            secret_name,
+                        ),
+                        property: None,
+                    },
+                },
+            }]),
+        },
+    };
+    cdb.spec.apply_common_metadata(&mut push_secret.metadata);
+    push_secret
+}
+
+/// Mirror the generated secrets of the instance into the external secret manager behind
+/// the secret store configured with EXTERNAL_SECRET_STORE, for instances which opt in with
+/// `spec.mirrorSecrets`. The secrets are pushed by the External Secrets Operator.
+pub async fn reconcile_external_secrets(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let cfg = Config::default();
+    let Some(store) = cfg.external_secret_store.as_deref() else {
+        if cdb.spec.mirror_secrets {
+            debug!(
+                "EXTERNAL_SECRET_STORE is not set, not mirroring secrets of {}",
+                cdb.name_any()
+            );
+        }
+        return Ok(());
+    };
+    let namespace = cdb.namespace().unwrap();
+    let push_secret_api: Api<PushSecret> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    for secret_name in mirrored_secret_names(cdb) {
+        let name = push_secret_name(&secret_name);
+        if cdb.spec.mirror_secrets {
+            let push_secret = push_secret(cdb, &cfg, store, &secret_name);
+            let ps = PatchParams::apply("cntrlr").force();
+            if let Err(e) = push_secret_api
+                .patch(&name, &ps, &Patch::Apply(&push_secret))
+                .await
+            {
+                error!(
+                    "Failed to apply PushSecret {} in namespace {}: {}",
+                    name, namespace, e
+                );
+                return Err(Action::requeue(Duration::from_secs(300)));
+            }
+        } else {
+            match push_secret_api
+                .delete(&name, &DeleteParams::default())
+                .await
+            {
+                Ok(_) => info!("Deleted PushSecret {} in namespace {}", name, namespace),
+                Err(kube::Error::Api(e)) if e.code == 404 => {}
+                Err(e) => {
+                    error!(
+                        "Failed to delete PushSecret {} in namespace {}: {}",
+                        name, namespace, e
+                    );
+                    return Err(Action::requeue(Duration::from_secs(300)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::CoreDBSpec;
+
+    #[test]
+    fn test_push_secret() {
+        let mut cdb = CoreDB::new(
+            "sample",
+            CoreDBSpec {
+                mirror_secrets: true,
+                ..CoreDBSpec::default()
+            },
+        );
+        cdb.metadata.namespace = Some("org-acme-inst-sample".to_string());
+        cdb.metadata.uid = Some("1234".to_string());
+        let cfg = Config {
+            external_secret_store: Some("aws-secrets-manager".to_string()),
+            external_secret_store_kind: "ClusterSecretStore".to_string(),
+            external_secret_key_prefix: "tembo-".to_string(),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            mirrored_secret_names(&cdb),
+            vec!["sample-connection", "sample-ro"]
+        );
+        let push_secret = push_secret(&cdb, &cfg, "aws-secrets-manager", "sample-connection");
+        assert_eq!(
+            push_secret.metadata.name.as_deref(),
+            Some("sample-connection-mirror")
+        );
+        assert_eq!(push_secret.spec.selector.secret.name, "sample-connection");
+        assert_eq!(
+            push_secret.spec.secret_store_refs[0].name.as_deref(),
+            Some("aws-secrets-manager")
+        );
+        let data = push_secret.spec.data.unwrap();
+        assert_eq!(
+            data[0].r#match.remote_ref.remote_key,
+            "tembo-org-acme-inst-sample-sample-connection"
+        );
+        assert_eq!(data[0].r#match.secret_key, None);
+    }
+}
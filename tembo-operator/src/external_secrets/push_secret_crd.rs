@@ -0,0 +1,75 @@
+// The PushSecret resource of the External Secrets Operator, limited to the fields the
+// operator sets. See https://external-secrets.io/latest/api/pushsecret/
+
+use kube::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize, Clone, Debug, Default)]
+#[kube(
+    group = "external-secrets.io",
+    version = "v1alpha1",
+    kind = "PushSecret",
+    plural = "pushsecrets"
+)]
+#[kube(namespaced)]
+#[kube(schema = "disabled")]
+pub struct PushSecretSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<PushSecretData>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "deletionPolicy"
+    )]
+    pub deletion_policy: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "refreshInterval"
+    )]
+    pub refresh_interval: Option<String>,
+    #[serde(rename = "secretStoreRefs")]
+    pub secret_store_refs: Vec<PushSecretSecretStoreRefs>,
+    pub selector: PushSecretSelector,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushSecretData {
+    #[serde(rename = "match")]
+    pub r#match: PushSecretDataMatch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushSecretDataMatch {
+    #[serde(rename = "remoteRef")]
+    pub remote_ref: PushSecretDataMatchRemoteRef,
+    /// The key of the secret to push, the whole secret is pushed as JSON when it is not set
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "secretKey")]
+    pub secret_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushSecretDataMatchRemoteRef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    #[serde(rename = "remoteKey")]
+    pub remote_key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushSecretSecretStoreRefs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushSecretSelector {
+    pub secret: PushSecretSelectorSecret,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushSecretSelectorSecret {
+    pub name: String,
+}
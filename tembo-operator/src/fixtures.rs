@@ -208,6 +208,10 @@ impl Context {
                 client: mock_client,
                 metrics: Metrics::default().register(&registry).unwrap(),
                 diagnostics: Arc::default(),
+                reconcile_permits: None,
+                reconcile_failures: Arc::default(),
+                disk_usage_checks: Arc::default(),
+                reconcile_reports: Arc::default(),
             }),
             ApiServerVerifier(handle),
             registry,
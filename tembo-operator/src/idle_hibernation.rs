@@ -0,0 +1,157 @@
+use crate::{
+    apis::coredb_types::CoreDB,
+    config::Config,
+    events::{publish_event, REASON_IDLE_HIBERNATED},
+    Context,
+};
+use chrono::{DateTime, Utc};
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::{controller::Action, events::EventType},
+    Api, ResourceExt,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+
+// Client connections, leaving out the operator and CNPG connecting over the local socket,
+// replication, the metrics exporter and the pooler's auth queries
+const CLIENT_CONNECTIONS_QUERY: &str = r#"
+SELECT count(*)
+FROM pg_stat_activity
+WHERE backend_type = 'client backend'
+  AND pid <> pg_backend_pid()
+  AND client_addr IS NOT NULL
+  AND usename NOT IN ('streaming_replica', 'postgres_exporter', 'cnpg_pooler_pgbouncer');
+"#;
+
+// reconcile_idle_hibernation stops instances which have had no client connections for
+// IDLE_HIBERNATION_AFTER_SEC, by setting spec.stop like a user stopping the instance would.
+// The instance is woken the same way, by setting spec.stop back to false.
+// Returns since when the instance is idle, to be recorded in status.idle_since.
+pub async fn reconcile_idle_hibernation(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    cfg: &Config,
+) -> Result<Option<DateTime<Utc>>, Action> {
+    if cfg.idle_hibernation_after_sec == 0 || cdb.spec.stop {
+        return Ok(None);
+    }
+    let name = cdb.name_any();
+    let current_idle_since = cdb.status.as_ref().and_then(|s| s.idle_since);
+
+    let result = cdb
+        .psql(
+            CLIENT_CONNECTIONS_QUERY.to_string(),
+            "postgres".to_string(),
+            ctx.clone(),
+        )
+        .await?;
+    let connections = result
+        .get_field(0)
+        .filter(|_| result.success)
+        .and_then(|count| count.parse::<u64>().ok());
+    let Some(connections) = connections else {
+        warn!(
+            "Could not count client connections of {}: {:?}",
+            name, result.stderr
+        );
+        return Ok(current_idle_since);
+    };
+    debug!("Instance {} has {} client connections", name, connections);
+
+    let now = Utc::now();
+    let idle_since = next_idle_since(current_idle_since, connections, now);
+    match idle_since {
+        Some(since) if is_idle_for(since, now, cfg.idle_hibernation_after_sec) => {
+            hibernate(cdb, ctx, since).await?;
+            Ok(None)
+        }
+        _ => Ok(idle_since),
+    }
+}
+
+// The instance stays idle since the first check without client connections
+fn next_idle_since(
+    current_idle_since: Option<DateTime<Utc>>,
+    connections: u64,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    if connections > 0 {
+        None
+    } else {
+        Some(current_idle_since.unwrap_or(now))
+    }
+}
+
+fn is_idle_for(idle_since: DateTime<Utc>, now: DateTime<Utc>, seconds: u64) -> bool {
+    (now - idle_since).num_seconds() >= seconds as i64
+}
+
+async fn hibernate(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    idle_since: DateTime<Utc>,
+) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    let patch = json!({
+        "spec": {
+            "stop": true
+        }
+    });
+    let patch_params = PatchParams {
+        field_manager: Some("cntrlr".to_string()),
+        ..PatchParams::default()
+    };
+    coredbs
+        .patch(&name, &patch_params, &Patch::Merge(patch))
+        .await
+        .map_err(|e| {
+            error!("Error stopping idle instance {}: {:?}", name, e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+    info!("Stopped instance {}, idle since {}", name, idle_since);
+
+    publish_event(
+        cdb,
+        &ctx,
+        EventType::Normal,
+        REASON_IDLE_HIBERNATED,
+        "IdleHibernation",
+        format!(
+            "Hibernating instance {}, it had no client connections since {}",
+            name,
+            idle_since.to_rfc3339()
+        ),
+    )
+    .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_next_idle_since() {
+        let now = Utc::now();
+        let since = now - Duration::hours(3);
+        assert_eq!(next_idle_since(None, 0, now), Some(now));
+        assert_eq!(next_idle_since(Some(since), 0, now), Some(since));
+        assert_eq!(next_idle_since(Some(since), 2, now), None);
+        assert_eq!(next_idle_since(None, 1, now), None);
+    }
+
+    #[test]
+    fn test_is_idle_for() {
+        let now = Utc::now();
+        assert!(is_idle_for(now - Duration::hours(2), now, 3600));
+        assert!(is_idle_for(now - Duration::hours(1), now, 3600));
+        assert!(!is_idle_for(now - Duration::minutes(59), now, 3600));
+    }
+}
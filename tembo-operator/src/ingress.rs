@@ -22,6 +22,10 @@ use crate::{
 };
 use tracing::{debug, error, info};
 
+// Wildcards are rejected, since a HostSNI matcher only matches the exact name
+pub const VALID_DNS_NAME: &str =
+    "^([a-z0-9]([-a-z0-9]{0,61}[a-z0-9])?\\.)+[a-z]([-a-z0-9]{0,61}[a-z0-9])?$";
+
 pub const VALID_IPV4_CIDR_BLOCK: &str = "^((25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\\.){3}(25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)(/(3[0-2]|2[0-9]|1[0-9]|[0-9]))?$";
 
 fn postgres_ingress_route_tcp(
@@ -85,9 +89,7 @@ pub async fn reconcile_extra_postgres_ing_route_tcp(
     port: IntOrString,
     middleware_names: Vec<String>,
 ) -> Result<(), OperatorError> {
-    let mut extra_domain_names = cdb.spec.extra_domains_rw.clone().unwrap_or_default();
-    // Ensure always same order
-    extra_domain_names.sort();
+    let extra_domain_names = valid_extra_domains(cdb);
     let matchers = extra_domain_names
         .iter()
         .map(|domain_name| format!("HostSNI(`{}`)", domain_name))
@@ -96,7 +98,7 @@ pub async fn reconcile_extra_postgres_ing_route_tcp(
     let ingress_route_tcp_name = format!("extra-{}-rw", cdb.name_any());
     let owner_reference = cdb.controller_owner_ref(&()).unwrap();
 
-    let ingress_route_tcp_to_apply = postgres_ingress_route_tcp(
+    let mut ingress_route_tcp_to_apply = postgres_ingress_route_tcp(
         ingress_route_tcp_name.clone(),
         namespace.to_string(),
         owner_reference,
@@ -105,6 +107,8 @@ pub async fn reconcile_extra_postgres_ing_route_tcp(
         middleware_names,
         port,
     );
+    cdb.spec
+        .apply_common_metadata(&mut ingress_route_tcp_to_apply.metadata);
     let ingress_route_tcp_api: Api<IngressRouteTCP> =
         Api::namespaced(ctx.client.clone(), namespace);
     if !extra_domain_names.is_empty() {
@@ -308,7 +312,7 @@ pub async fn reconcile_postgres_ing_route_tcp(
         return Ok(());
     }
 
-    let ingress_route_tcp_to_apply = postgres_ingress_route_tcp(
+    let mut ingress_route_tcp_to_apply = postgres_ingress_route_tcp(
         ingress_route_tcp_name.clone(),
         namespace.to_string(),
         owner_reference.clone(),
@@ -317,6 +321,8 @@ pub async fn reconcile_postgres_ing_route_tcp(
         middleware_names.clone(),
         port.clone(),
     );
+    cdb.spec
+        .apply_common_metadata(&mut ingress_route_tcp_to_apply.metadata);
 
     // Apply this ingress route tcp
     apply_ingress_route_tcp(
@@ -356,13 +362,16 @@ fn generate_ip_allow_list_middleware_tcp(cdb: &CoreDB) -> MiddlewareTCP {
 
     let owner_references = cdb.controller_owner_ref(&()).map(|oref| vec![oref]);
 
+    let mut metadata = ObjectMeta {
+        name: Some(cdb.name_any()),
+        namespace: cdb.namespace(),
+        owner_references,
+        ..Default::default()
+    };
+    cdb.spec.apply_common_metadata(&mut metadata);
+
     MiddlewareTCP {
-        metadata: ObjectMeta {
-            name: Some(cdb.name_any()),
-            namespace: cdb.namespace(),
-            owner_references,
-            ..Default::default()
-        },
+        metadata,
         spec: MiddlewareTCPSpec {
             ip_allow_list: Some(MiddlewareTCPIpAllowList {
                 source_range: Some(valid_ips),
@@ -372,6 +381,35 @@ fn generate_ip_allow_list_middleware_tcp(cdb: &CoreDB) -> MiddlewareTCP {
     }
 }
 
+// Returns the sorted, de-duplicated extra domains which are valid DNS names.
+// An invalid entry would otherwise break the whole IngressRouteTCP match,
+// or the issuance of the server certificate.
+pub fn valid_extra_domains(cdb: &CoreDB) -> Vec<String> {
+    let mut domains = Vec::new();
+    for domain in cdb.spec.extra_domains_rw.clone().unwrap_or_default() {
+        let domain = domain.trim().to_lowercase();
+        if is_valid_dns_name(&domain) {
+            domains.push(domain);
+        } else {
+            error!(
+                "Invalid extra domain '{}' on DB {}, skipping",
+                domain,
+                cdb.name_any()
+            );
+        }
+    }
+    // Ensure always same order
+    domains.sort();
+    domains.dedup();
+    domains
+}
+
+// Checks a lowercase, fully qualified DNS name
+pub fn is_valid_dns_name(name: &str) -> bool {
+    let dns_regex = Regex::new(VALID_DNS_NAME).expect("Failed to compile regex for DNS names");
+    name.len() <= 253 && dns_regex.is_match(name)
+}
+
 pub fn valid_cidrs(source_range: &[String]) -> Vec<String> {
     // Validate each IP address or CIDR block against the regex
     let cidr_regex =
@@ -391,7 +429,7 @@ pub fn valid_cidrs(source_range: &[String]) -> Vec<String> {
 mod tests {
     use crate::{
         apis::coredb_types::{CoreDB, CoreDBSpec},
-        ingress::generate_ip_allow_list_middleware_tcp,
+        ingress::{generate_ip_allow_list_middleware_tcp, valid_extra_domains},
     };
     use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 
@@ -487,4 +525,50 @@ mod tests {
             source_range
         );
     }
+
+    #[test]
+    fn test_valid_extra_domains() {
+        let cdb = CoreDB {
+            metadata: ObjectMeta::default(),
+            spec: CoreDBSpec {
+                extra_domains_rw: Some(vec![
+                    "db.example.com".to_string(),
+                    "Analytics.Example.com".to_string(),
+                    "db.example.com".to_string(),
+                    "*.tenant.example.com".to_string(),
+                    "*.*.example.com".to_string(),
+                    "db.*.example.com".to_string(),
+                    "not a domain".to_string(),
+                    "`) || HostSNI(`*".to_string(),
+                    "localhost".to_string(),
+                ]),
+                ..CoreDBSpec::default()
+            },
+            status: None,
+        };
+        assert_eq!(
+            valid_extra_domains(&cdb),
+            vec![
+                "analytics.example.com".to_string(),
+                "db.example.com".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_valid_extra_domains_rejects_wildcards() {
+        let cdb = CoreDB {
+            metadata: ObjectMeta::default(),
+            spec: CoreDBSpec {
+                extra_domains_rw: Some(vec![
+                    "*.tenant.example.com".to_string(),
+                    "*".to_string(),
+                    "*example.com".to_string(),
+                ]),
+                ..CoreDBSpec::default()
+            },
+            status: None,
+        };
+        assert!(valid_extra_domains(&cdb).is_empty());
+    }
 }
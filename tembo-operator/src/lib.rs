@@ -4,13 +4,18 @@ pub use crate::controller::*;
 pub mod apis;
 
 pub mod app_service;
+pub mod bootstrap_sql;
 pub mod configmap;
 pub mod dedicated_networking;
+pub mod disk_full;
 pub mod extensions;
+pub mod external_secrets;
+pub mod idle_hibernation;
 pub mod postgres_exporter;
 /// Log and trace integrations
 pub mod telemetry;
 
+pub mod events;
 mod exec;
 /// Metrics
 mod metrics;
@@ -30,13 +35,21 @@ pub mod traefik;
 pub use traefik::ingress_route_crd;
 mod certmanager;
 mod network_policies;
+pub mod partial_restore;
 pub mod postgres_certificates;
+pub mod postgres_versions;
 pub mod psql;
 mod rbac;
+pub mod reconcile_report;
+pub mod resource_profiles;
+pub mod restore_sample;
+pub mod role_policies;
+pub mod scheduled_restart;
 mod secret;
 mod service;
 pub mod snapshots;
 mod trunk;
+pub mod ttl;
 
 pub const RESTARTED_AT: &str = "kubectl.kubernetes.io/restartedAt";
 
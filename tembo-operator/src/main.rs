@@ -1,5 +1,6 @@
 use actix_web::{
-    get, middleware, web::Data, App, HttpRequest, HttpResponse, HttpServer, Responder,
+    get, http::header, middleware, web, web::Data, App, HttpRequest, HttpResponse, HttpServer,
+    Responder,
 };
 pub use controller::{self, telemetry, State};
 use prometheus::{Encoder, TextEncoder};
@@ -24,6 +25,37 @@ async fn index(c: Data<State>, _req: HttpRequest) -> impl Responder {
     HttpResponse::Ok().json(&d)
 }
 
+// The bearer token of the debug endpoint, which is disabled when it is not set
+#[derive(Clone)]
+struct DebugToken(Option<String>);
+
+// The last reconcile of the CoreDB in a namespace: its desired state, and the steps which were
+// applied and skipped
+#[get("/debug/{namespace}")]
+async fn debug(
+    c: Data<State>,
+    token: Data<DebugToken>,
+    namespace: web::Path<String>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(expected) = &token.0 else {
+        return HttpResponse::NotFound().finish();
+    };
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|provided| provided == expected);
+    if !authorized {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match c.reconcile_report(&namespace).await {
+        Some(report) => HttpResponse::Ok().json(&report),
+        None => HttpResponse::NotFound().json("no reconcile recorded for this namespace"),
+    }
+}
+
 async fn app_main() -> anyhow::Result<()> {
     telemetry::init().await;
 
@@ -35,15 +67,22 @@ async fn app_main() -> anyhow::Result<()> {
         .unwrap_or_else(|_| String::from("8080"))
         .parse::<u16>()
         .unwrap_or(8080);
+    let debug_token = DebugToken(
+        std::env::var("DEBUG_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty()),
+    );
 
     // Start web server
     let server = HttpServer::new(move || {
         App::new()
             .app_data(Data::new(state.clone()))
+            .app_data(Data::new(debug_token.clone()))
             .wrap(middleware::Logger::default().exclude("/health"))
             .service(index)
             .service(health)
             .service(metrics)
+            .service(debug)
     })
     .bind(("0.0.0.0", server_port))?
     .shutdown_timeout(5);
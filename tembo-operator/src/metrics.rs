@@ -1,6 +1,12 @@
-use crate::{apis::coredb_types::CoreDB, Error};
+use crate::{
+    apis::coredb_types::{CoreDB, ObjectStorageUsage},
+    errors::OperatorError,
+    Error,
+};
 use kube::ResourceExt;
-use prometheus::{histogram_opts, opts, HistogramVec, IntCounter, IntCounterVec, Registry};
+use prometheus::{
+    histogram_opts, opts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Registry,
+};
 use tokio::time::Instant;
 
 #[derive(Clone)]
@@ -8,6 +14,68 @@ pub struct Metrics {
     pub reconciliations: IntCounter,
     pub failures: IntCounterVec,
     pub reconcile_duration: HistogramVec,
+    pub phase_duration: HistogramVec,
+    pub phase_failures: IntCounterVec,
+    pub object_storage_bytes: IntGaugeVec,
+}
+
+/// The subsystems reconciled for each CoreDB, timed separately so slow phases stand out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconcilePhase {
+    Cnpg,
+    Extensions,
+    AppService,
+    Ingress,
+}
+
+impl ReconcilePhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReconcilePhase::Cnpg => "cnpg",
+            ReconcilePhase::Extensions => "extensions",
+            ReconcilePhase::AppService => "appService",
+            ReconcilePhase::Ingress => "ingress",
+        }
+    }
+}
+
+/// Label for a phase which returned a requeue Action instead of a typed error
+pub const FAILURE_REASON_REQUEUED: &str = "requeued";
+
+/// A bounded label describing why a reconcile failed
+pub trait FailureReason {
+    fn failure_reason(&self) -> &'static str;
+}
+
+impl FailureReason for Error {
+    fn failure_reason(&self) -> &'static str {
+        match self {
+            Error::KubeExecError(_) => "kube_exec",
+            Error::SerializationError(_) | Error::YamlSerializationError(_) => "serialization",
+            Error::KubeError(kube::Error::Api(_)) => "kube_api",
+            Error::KubeError(_) => "kube",
+            Error::FinalizerError(_) => "finalizer",
+            Error::PodError(_) => "pod",
+            Error::MissingSecretError(_) => "missing_secret",
+            Error::InvalidErr(_) => "invalid_data",
+        }
+    }
+}
+
+impl FailureReason for OperatorError {
+    fn failure_reason(&self) -> &'static str {
+        match self {
+            OperatorError::IngressRouteTCPName | OperatorError::IngressRouteTcpError => {
+                "ingress_route_tcp"
+            }
+            OperatorError::IngressRouteError => "ingress_route",
+            OperatorError::NetworkPolicyError(_) => "network_policy",
+            OperatorError::ServiceError(_) => "service",
+            OperatorError::KubeErr(kube::Error::Api(_)) => "kube_api",
+            OperatorError::KubeErr(_) => "kube",
+            OperatorError::ValueError(_) => "invalid_value",
+        }
+    }
 }
 
 impl Default for Metrics {
@@ -31,10 +99,38 @@ impl Default for Metrics {
         .unwrap();
         let reconciliations =
             IntCounter::new("cdb_controller_reconciliations_total", "reconciliations").unwrap();
+        let phase_duration = HistogramVec::new(
+            histogram_opts!(
+                "cdb_controller_reconcile_phase_duration_seconds",
+                "The duration of a reconcile phase to complete in seconds"
+            )
+            .buckets(vec![0.01, 0.1, 0.25, 0.5, 1., 5., 15., 60.]),
+            &["phase"],
+        )
+        .unwrap();
+        let phase_failures = IntCounterVec::new(
+            opts!(
+                "cdb_controller_reconcile_phase_failures_total",
+                "reconcile phase failures by reason",
+            ),
+            &["phase", "reason"],
+        )
+        .unwrap();
+        let object_storage_bytes = IntGaugeVec::new(
+            opts!(
+                "cdb_object_storage_bytes",
+                "The object storage used by the backups of an instance in bytes",
+            ),
+            &["instance", "prefix"],
+        )
+        .unwrap();
         Metrics {
             reconciliations,
             failures,
             reconcile_duration,
+            phase_duration,
+            phase_failures,
+            object_storage_bytes,
         }
     }
 }
@@ -45,20 +141,63 @@ impl Metrics {
         registry.register(Box::new(self.reconcile_duration.clone()))?;
         registry.register(Box::new(self.failures.clone()))?;
         registry.register(Box::new(self.reconciliations.clone()))?;
+        registry.register(Box::new(self.phase_duration.clone()))?;
+        registry.register(Box::new(self.phase_failures.clone()))?;
+        registry.register(Box::new(self.object_storage_bytes.clone()))?;
         Ok(self)
     }
 
     pub fn reconcile_failure(&self, cdb: &CoreDB, e: &Error) {
         self.failures
             .with_label_values(&[cdb.name_any().as_ref(), e.metric_label().as_ref()])
+            .inc();
+        self.phase_failures
+            .with_label_values(&["reconcile", e.failure_reason()])
+            .inc()
+    }
+
+    pub fn phase_failure(&self, phase: ReconcilePhase, reason: &str) {
+        self.phase_failures
+            .with_label_values(&[phase.as_str(), reason])
             .inc()
     }
 
+    /// Report the most recent object storage measurement of the instance, the series are
+    /// removed when the instance is no longer measured
+    pub fn object_storage_usage(&self, cdb: &CoreDB, usage: Option<&ObjectStorageUsage>) {
+        let instance = cdb.name_any();
+        for (prefix, bytes) in [
+            ("base", usage.map(|u| u.base_backups_bytes)),
+            ("wals", usage.map(|u| u.wal_bytes)),
+        ] {
+            match bytes {
+                Some(bytes) => self
+                    .object_storage_bytes
+                    .with_label_values(&[&instance, prefix])
+                    .set(bytes.try_into().unwrap_or(i64::MAX)),
+                None => {
+                    let _ = self
+                        .object_storage_bytes
+                        .remove_label_values(&[&instance, prefix]);
+                }
+            }
+        }
+    }
+
+    pub fn measure_phase(&self, phase: ReconcilePhase) -> ReconcileMeasurer {
+        ReconcileMeasurer {
+            start: Instant::now(),
+            metric: self.phase_duration.clone(),
+            labels: vec![phase.as_str()],
+        }
+    }
+
     pub fn count_and_measure(&self) -> ReconcileMeasurer {
         self.reconciliations.inc();
         ReconcileMeasurer {
             start: Instant::now(),
             metric: self.reconcile_duration.clone(),
+            labels: vec![],
         }
     }
 }
@@ -69,12 +208,48 @@ impl Metrics {
 pub struct ReconcileMeasurer {
     start: Instant,
     metric: HistogramVec,
+    labels: Vec<&'static str>,
 }
 
 impl Drop for ReconcileMeasurer {
     fn drop(&mut self) {
         #[allow(clippy::cast_precision_loss)]
         let duration = self.start.elapsed().as_millis() as f64 / 1000.0;
-        self.metric.with_label_values(&[]).observe(duration);
+        self.metric
+            .with_label_values(&self.labels)
+            .observe(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_metrics() {
+        let metrics = Metrics::default();
+        drop(metrics.measure_phase(ReconcilePhase::Cnpg));
+        drop(metrics.measure_phase(ReconcilePhase::Cnpg));
+        metrics.phase_failure(ReconcilePhase::Ingress, FAILURE_REASON_REQUEUED);
+        metrics.phase_failure(
+            ReconcilePhase::Ingress,
+            OperatorError::IngressRouteTcpError.failure_reason(),
+        );
+
+        assert_eq!(
+            metrics
+                .phase_duration
+                .with_label_values(&["cnpg"])
+                .get_sample_count(),
+            2
+        );
+        assert_eq!(
+            metrics
+                .phase_failures
+                .with_label_values(&["ingress", "ingress_route_tcp"])
+                .get(),
+            1
+        );
+        assert_eq!(Error::PodError("pod".to_string()).failure_reason(), "pod");
     }
 }
@@ -222,6 +222,25 @@ pub async fn reconcile_network_policies(client: Client, namespace: &str) -> Resu
                   "port": 8443
                 }
               ]
+            },
+            // The in-cluster trunk cache of data planes without egress to the internet
+            {
+              "to": [
+                {
+                  "namespaceSelector": {},
+                  "podSelector": {
+                    "matchLabels": {
+                      "app.kubernetes.io/name": "trunk-cache"
+                    }
+                  }
+                }
+              ],
+              "ports": [
+                {
+                  "protocol": "TCP",
+                  "port": 8080
+                }
+              ]
             }
           ]
         }
@@ -0,0 +1,357 @@
+use crate::{
+    apis::coredb_types::{CoreDB, PartialRestore},
+    cloudnativepg::clusters::Cluster,
+    patch_cdb_status_merge,
+    restore_sample::{qualified_table_name, quote_literal},
+    Context,
+};
+use chrono::Utc;
+use k8s_openapi::api::{
+    batch::v1::{Job, JobSpec, JobStatus},
+    core::v1::{Container, EnvVar, EnvVarSource, PodSpec, PodTemplateSpec, SecretKeySelector},
+};
+use kube::{
+    api::{DeleteParams, ObjectMeta, PostParams},
+    runtime::controller::Action,
+    Api, Resource, ResourceExt,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+
+// The phase of a CNPG Cluster with all its instances ready
+const CLUSTER_HEALTHY_PHASE: &str = "Cluster in healthy state";
+
+// How many times a failed Job is created again, after its own pod retries
+const MAX_PARTIAL_RESTORE_RETRIES: u32 = 3;
+
+// reconcile_partial_restore starts the Job removing the tables which are not needed from a
+// restored instance, as set in spec.restore.partial, once the Cluster is healthy. A failed Job
+// is created again a few times. When the Job completed, status.partial_restore.completed_at
+// is set, so it never runs twice on an instance.
+pub async fn reconcile_partial_restore(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let Some(partial) = cdb
+        .spec
+        .restore
+        .as_ref()
+        .and_then(|restore| restore.partial.as_ref())
+    else {
+        return Ok(());
+    };
+    let recorded = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.partial_restore.clone())
+        .unwrap_or_default();
+    if recorded.completed_at.is_some() {
+        return Ok(());
+    }
+
+    let name = job_name(cdb);
+    let namespace = cdb.namespace().unwrap();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    let jobs: Api<Job> = Api::namespaced(ctx.client.clone(), &namespace);
+    let existing = jobs.get_opt(&name).await.map_err(|e| {
+        error!("Error getting Job {} in {}: {:?}", name, namespace, e);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    if let Some(job) = existing {
+        let status = job.status.unwrap_or_default();
+        if status.succeeded.unwrap_or(0) > 0 {
+            info!(
+                "Partial restore of database {} on instance {} completed",
+                partial.database,
+                cdb.name_any()
+            );
+            let patch_status = json!({
+                "apiVersion": "coredb.io/v1alpha1",
+                "kind": "CoreDB",
+                "status": {
+                    "partial_restore": {
+                        "completed_at": Utc::now()
+                    }
+                }
+            });
+            return patch_cdb_status_merge(&coredbs, &cdb.name_any(), patch_status).await;
+        }
+        if !is_job_failed(&status) {
+            return Ok(());
+        }
+        if recorded.retries >= MAX_PARTIAL_RESTORE_RETRIES {
+            debug!(
+                "Partial restore of instance {} failed {} times, see the logs of Job {}",
+                cdb.name_any(),
+                recorded.retries + 1,
+                name
+            );
+            return Ok(());
+        }
+        warn!(
+            "Partial restore of instance {} failed, see the logs of Job {}, running it again",
+            cdb.name_any(),
+            name
+        );
+        // The pods of the failed Job are removed with it
+        jobs.delete(&name, &DeleteParams::background())
+            .await
+            .map_err(|e| {
+                error!("Error deleting Job {} in {}: {:?}", name, namespace, e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "partial_restore": {
+                    "retries": recorded.retries + 1
+                }
+            }
+        });
+        // The Job is created again by the next reconcile, once the deletion went through
+        return patch_cdb_status_merge(&coredbs, &cdb.name_any(), patch_status).await;
+    }
+
+    // The Job connects to the primary, the restore must have completed first
+    let clusters: Api<Cluster> = Api::namespaced(ctx.client.clone(), &namespace);
+    let cluster = clusters.get_opt(&cdb.name_any()).await.map_err(|e| {
+        error!(
+            "Error getting Cluster {} in {}: {:?}",
+            cdb.name_any(),
+            namespace,
+            e
+        );
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    let phase = cluster
+        .and_then(|cluster| cluster.status)
+        .and_then(|status| status.phase);
+    if phase.as_deref() != Some(CLUSTER_HEALTHY_PHASE) {
+        debug!(
+            "Waiting for Cluster {} to be healthy before its partial restore, it is {:?}",
+            cdb.name_any(),
+            phase
+        );
+        return Ok(());
+    }
+
+    jobs.create(&PostParams::default(), &partial_restore_job(cdb, partial))
+        .await
+        .map_err(|e| {
+            error!("Error creating Job {} in {}: {:?}", name, namespace, e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+    info!(
+        "Started partial restore of database {} on instance {}",
+        partial.database,
+        cdb.name_any()
+    );
+    Ok(())
+}
+
+// A Job failed once it ran out of pod retries
+fn is_job_failed(status: &JobStatus) -> bool {
+    status
+        .conditions
+        .iter()
+        .flatten()
+        .any(|condition| condition.type_ == "Failed" && condition.status == "True")
+}
+
+fn job_name(cdb: &CoreDB) -> String {
+    format!("{}-partial-restore", cdb.name_any())
+}
+
+// The Job runs the partial restore SQL with psql from the Postgres image of the instance,
+// connecting to the primary with the credentials of the instance's connection secret
+fn partial_restore_job(cdb: &CoreDB, partial: &PartialRestore) -> Job {
+    let name = cdb.name_any();
+    let labels = BTreeMap::from([
+        ("app".to_string(), "partial-restore".to_string()),
+        ("coredb.io/name".to_string(), name.clone()),
+    ]);
+
+    let mut metadata = ObjectMeta {
+        name: Some(job_name(cdb)),
+        namespace: cdb.namespace(),
+        labels: Some(labels.clone()),
+        owner_references: Some(vec![cdb.controller_owner_ref(&()).unwrap()]),
+        ..ObjectMeta::default()
+    };
+    cdb.spec.apply_common_metadata(&mut metadata);
+
+    Job {
+        metadata,
+        spec: Some(JobSpec {
+            backoff_limit: Some(2),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels),
+                    ..ObjectMeta::default()
+                }),
+                spec: Some(PodSpec {
+                    restart_policy: Some("Never".to_string()),
+                    containers: vec![Container {
+                        name: "psql".to_string(),
+                        image: Some(cdb.spec.image.clone()),
+                        command: Some(vec!["sh".to_string(), "-c".to_string()]),
+                        args: Some(vec![
+                            r#"psql "$RW_URI" -v ON_ERROR_STOP=1 -c "$0""#.to_string(),
+                            partial_restore_query(partial),
+                        ]),
+                        env: Some(vec![
+                            EnvVar {
+                                name: "RW_URI".to_string(),
+                                value_from: Some(EnvVarSource {
+                                    secret_key_ref: Some(SecretKeySelector {
+                                        name: Some(format!("{}-connection", name)),
+                                        key: "rw_uri".to_string(),
+                                        ..SecretKeySelector::default()
+                                    }),
+                                    ..EnvVarSource::default()
+                                }),
+                                ..EnvVar::default()
+                            },
+                            // The connection URI has no database, it is taken from PGDATABASE
+                            EnvVar {
+                                name: "PGDATABASE".to_string(),
+                                value: Some(partial.database.clone()),
+                                ..EnvVar::default()
+                            },
+                        ]),
+                        ..Container::default()
+                    }],
+                    ..PodSpec::default()
+                }),
+            },
+            ..JobSpec::default()
+        }),
+        status: None,
+    }
+}
+
+// Turn table patterns into a SQL array of LIKE patterns on schema qualified table names,
+// where `*` matches any characters
+fn like_patterns(tables: &[String]) -> String {
+    let patterns = tables
+        .iter()
+        .map(|table| {
+            let pattern = qualified_table_name(table)
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+                .replace('*', "%");
+            quote_literal(&pattern)
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!("ARRAY[{}]::text[]", patterns)
+}
+
+// partial_restore_query builds an anonymous block which drops the tables that are not included
+// or are excluded, and removes the rows of the others for schema only restores. Partitioned
+// tables are handled through their parent, and tables owned by extensions are skipped.
+fn partial_restore_query(partial: &PartialRestore) -> String {
+    let included = match partial.include_tables.as_deref() {
+        Some(tables) if !tables.is_empty() => {
+            format!("table_name LIKE ANY ({})", like_patterns(tables))
+        }
+        _ => "true".to_owned(),
+    };
+    let excluded = match partial.exclude_tables.as_deref() {
+        Some(tables) if !tables.is_empty() => {
+            format!("table_name LIKE ANY ({})", like_patterns(tables))
+        }
+        _ => "false".to_owned(),
+    };
+    let schema_only = partial.schema_only;
+
+    format!(
+        r#"
+DO $$
+DECLARE
+    t RECORD;
+    table_name TEXT;
+BEGIN
+    FOR t IN
+        SELECT n.nspname AS schemaname, c.relname AS tablename
+        FROM pg_class c
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE c.relkind IN ('r', 'p')
+          AND NOT c.relispartition
+          AND n.nspname <> 'information_schema'
+          AND n.nspname NOT LIKE 'pg\_%'
+          AND NOT EXISTS (
+              SELECT 1 FROM pg_depend d
+              WHERE d.classid = 'pg_class'::regclass AND d.objid = c.oid AND d.deptype = 'e'
+          )
+    LOOP
+        table_name := t.schemaname || '.' || t.tablename;
+        IF NOT ({included}) OR ({excluded}) THEN
+            EXECUTE format('DROP TABLE IF EXISTS %I.%I CASCADE', t.schemaname, t.tablename);
+        ELSIF {schema_only} THEN
+            EXECUTE format('TRUNCATE %I.%I CASCADE', t.schemaname, t.tablename);
+        END IF;
+    END LOOP;
+END;
+$$;
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_job_failed() {
+        use k8s_openapi::api::batch::v1::JobCondition;
+
+        let condition = |type_: &str, status: &str| JobCondition {
+            type_: type_.to_owned(),
+            status: status.to_owned(),
+            ..JobCondition::default()
+        };
+        assert!(!is_job_failed(&JobStatus::default()));
+        assert!(!is_job_failed(&JobStatus {
+            failed: Some(1),
+            active: Some(1),
+            ..JobStatus::default()
+        }));
+        assert!(is_job_failed(&JobStatus {
+            failed: Some(3),
+            conditions: Some(vec![condition("Failed", "True")]),
+            ..JobStatus::default()
+        }));
+        assert!(!is_job_failed(&JobStatus {
+            conditions: Some(vec![condition("Complete", "True")]),
+            ..JobStatus::default()
+        }));
+    }
+
+    #[test]
+    fn test_partial_restore_query() {
+        let partial = PartialRestore {
+            database: "postgres".to_owned(),
+            schema_only: false,
+            include_tables: Some(vec!["sales.*".to_owned(), "countries".to_owned()]),
+            exclude_tables: Some(vec!["sales.audit_log".to_owned()]),
+        };
+        let query = partial_restore_query(&partial);
+        assert!(query.contains(
+            r"IF NOT (table_name LIKE ANY (ARRAY['sales.%', 'public.countries']::text[])) OR (table_name LIKE ANY (ARRAY['sales.audit\_log']::text[])) THEN"
+        ));
+        assert!(query.contains("ELSIF false THEN"));
+
+        // Schema only, without patterns every table is kept
+        let partial = PartialRestore {
+            database: "postgres".to_owned(),
+            schema_only: true,
+            include_tables: None,
+            exclude_tables: Some(vec![]),
+        };
+        let query = partial_restore_query(&partial);
+        assert!(query.contains("IF NOT (true) OR (false) THEN"));
+        assert!(query.contains("ELSIF true THEN"));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     apis::coredb_types::CoreDB,
     certmanager::certificates::Certificate,
+    ingress::valid_extra_domains,
     secret::{b64_encode, fetch_all_decoded_data_from_secret},
 };
 use k8s_openapi::api::core::v1::Secret;
@@ -122,6 +123,12 @@ pub async fn reconcile_certificates(
             debug!("DATA_PLANE_BASEDOMAIN not set, not adding custom DNS name");
         }
     };
+    // End-user provided domains routed to this instance by SNI
+    for extra_domain_name in valid_extra_domains(coredb) {
+        if !dns_names.contains(&extra_domain_name) {
+            dns_names.push(extra_domain_name);
+        }
+    }
 
     // Create the first Certificate
     let server_certificate = json!({
@@ -1,13 +1,21 @@
 use crate::{apis::coredb_types::CoreDB, defaults, Error};
-use kube::Client;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{Api, DeleteParams},
+    Client, ResourceExt,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use tracing::debug;
+use tracing::{debug, error, warn};
 
 pub const QUERIES: &str = "tembo-queries";
 pub const EXPORTER_VOLUME: &str = "postgres-exporter";
 pub const EXPORTER_CONFIGMAP_PREFIX: &str = "metrics-";
+pub const USER_QUERIES: &str = "user-queries";
+pub const USER_QUERIES_CONFIGMAP_PREFIX: &str = "metrics-user-";
+// Label of user ConfigMaps with queries, set to the name of the instance using them
+pub const USER_QUERIES_LABEL: &str = "tembo.io/metrics-queries";
 
 /// PostgresExporter is the configuration for the postgres-exporter to expose
 /// custom metrics from the database.
@@ -67,6 +75,29 @@ pub struct PostgresMetrics {
     /// **Default**: `None`
     #[schemars(schema_with = "preserve_arbitrary")]
     pub queries: Option<QueryConfig>,
+
+    /// A reference to a ConfigMap in the namespace of the instance holding
+    /// additional queries, in the same format as `queries`. Changes to the
+    /// ConfigMap are reloaded by the exporter without a restart. Label the
+    /// ConfigMap with `tembo.io/metrics-queries: <instance name>` to have them
+    /// picked up right away instead of on the next reconcile.
+    ///
+    /// **Default**: `None`
+    #[serde(default, rename = "queriesConfigMapRef")]
+    pub queries_config_map_ref: Option<QueriesConfigMapRef>,
+}
+
+/// QueriesConfigMapRef references the key of a ConfigMap holding custom queries
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct QueriesConfigMapRef {
+    /// The name of the ConfigMap
+    pub name: String,
+
+    /// The key of the ConfigMap holding the queries
+    ///
+    /// **Default**: `queries`
+    #[serde(default = "defaults::default_queries_config_map_key")]
+    pub key: String,
 }
 
 #[derive(Clone, Debug, JsonSchema, PartialEq, Serialize, Deserialize)]
@@ -218,6 +249,71 @@ pub async fn reconcile_metrics_configmap(
     Ok(())
 }
 
+// reconcile_user_queries_configmap copies the queries of the ConfigMap referenced in
+// spec.metrics.queriesConfigMapRef to a ConfigMap labeled for CNPG to reload, so the exporter
+// picks up changes without a restart. Queries which do not parse keep the previous copy in
+// place, so a typo does not break the metrics of the instance.
+pub async fn reconcile_user_queries_configmap(
+    cdb: &CoreDB,
+    client: Client,
+    ns: &str,
+) -> Result<(), Error> {
+    let name = user_queries_configmap_name(cdb);
+    let cm_api: Api<ConfigMap> = Api::namespaced(client.clone(), ns);
+    let Some(config_map_ref) = cdb
+        .spec
+        .metrics
+        .as_ref()
+        .and_then(|m| m.queries_config_map_ref.as_ref())
+    else {
+        if cm_api.get_opt(&name).await?.is_some() {
+            cm_api.delete(&name, &DeleteParams::default()).await?;
+            debug!("Deleted user queries configmap {}", name);
+        }
+        return Ok(());
+    };
+
+    let Some(user_config_map) = cm_api.get_opt(&config_map_ref.name).await? else {
+        warn!(
+            "ConfigMap {} with custom queries not found in namespace {}",
+            config_map_ref.name, ns
+        );
+        return Ok(());
+    };
+    let Some(queries) = user_config_map
+        .data
+        .and_then(|mut data| data.remove(&config_map_ref.key))
+    else {
+        warn!(
+            "Key {} not found in ConfigMap {} with custom queries",
+            config_map_ref.key, config_map_ref.name
+        );
+        return Ok(());
+    };
+    let queries = match parse_user_queries(&queries) {
+        Ok(queries) => queries,
+        Err(e) => {
+            error!(
+                "Invalid custom queries in ConfigMap {}, keeping the previous queries: {}",
+                config_map_ref.name, e
+            );
+            return Ok(());
+        }
+    };
+    let data = BTreeMap::from([(USER_QUERIES.to_string(), queries)]);
+    apply_configmap(client, ns, &name, data).await
+}
+
+pub fn user_queries_configmap_name(cdb: &CoreDB) -> String {
+    format!("{}{}", USER_QUERIES_CONFIGMAP_PREFIX, cdb.name_any())
+}
+
+// Validate the queries of a user ConfigMap, returning them in the format of the exporter
+fn parse_user_queries(queries: &str) -> Result<String, Error> {
+    let queries: QueryConfig = serde_yaml::from_str(queries)?;
+    Ok(serde_yaml::to_string(&queries)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,4 +416,29 @@ pg_postmaster:
         // formmatted correctly as yaml (for configmap)
         assert_eq!(yaml, data);
     }
+
+    #[test]
+    fn test_parse_user_queries() {
+        let queries = r#"
+orders:
+  query: select count(*) as pending from orders where status = 'pending'
+  master: true
+  metrics:
+    - pending:
+        usage: GAUGE
+        description: Pending orders
+"#;
+        let parsed = parse_user_queries(queries).unwrap();
+        assert!(parsed.contains("target_databases:\n  - postgres\n"));
+
+        let invalid = r#"
+orders:
+  query: select 1
+  metrics:
+    - one:
+        usage: SUM
+        description: One
+"#;
+        assert!(parse_user_queries(invalid).is_err());
+    }
 }
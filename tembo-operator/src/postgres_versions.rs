@@ -0,0 +1,215 @@
+use crate::{apis::coredb_types::CoreDB, defaults::postgres_major_version_from_cdb};
+use std::collections::HashSet;
+
+/// What differs between the Postgres major versions the operator supports. Reconcile logic
+/// consults these capabilities instead of comparing version numbers, so supporting a new major
+/// version means adding an entry to `POSTGRES_VERSIONS` and its image to the defaults.
+#[derive(Debug, PartialEq)]
+pub struct PostgresVersion {
+    pub major: i32,
+    /// Configuration parameters which do not exist in this version, they are left out of the
+    /// Postgres configuration
+    pub unavailable_gucs: &'static [&'static str],
+    /// Extensions which are not available for this version, they are not installed
+    pub unavailable_extensions: &'static [&'static str],
+    /// Logical replication slots can be synchronized to standbys with `sync_replication_slots`
+    pub failover_slots: bool,
+}
+
+/// The Postgres major versions the operator supports, oldest first
+pub const POSTGRES_VERSIONS: &[PostgresVersion] = &[
+    PostgresVersion {
+        major: 14,
+        unavailable_gucs: &[
+            "allow_alter_system",
+            "debug_parallel_query",
+            "icu_validation_level",
+            "io_combine_limit",
+            "log_startup_progress_interval",
+            "recovery_prefetch",
+            "summarize_wal",
+            "sync_replication_slots",
+            "transaction_timeout",
+        ],
+        unavailable_extensions: &[],
+        failover_slots: false,
+    },
+    PostgresVersion {
+        major: 15,
+        unavailable_gucs: &[
+            "allow_alter_system",
+            "debug_parallel_query",
+            "icu_validation_level",
+            "io_combine_limit",
+            "stats_temp_directory",
+            "summarize_wal",
+            "sync_replication_slots",
+            "transaction_timeout",
+        ],
+        unavailable_extensions: &[],
+        failover_slots: false,
+    },
+    PostgresVersion {
+        major: 16,
+        unavailable_gucs: &[
+            "allow_alter_system",
+            "force_parallel_mode",
+            "io_combine_limit",
+            "promote_trigger_file",
+            "stats_temp_directory",
+            "summarize_wal",
+            "sync_replication_slots",
+            "transaction_timeout",
+            "vacuum_defer_cleanup_age",
+        ],
+        unavailable_extensions: &[],
+        failover_slots: false,
+    },
+    PostgresVersion {
+        major: 17,
+        unavailable_gucs: &[
+            "db_user_namespace",
+            "force_parallel_mode",
+            "old_snapshot_threshold",
+            "promote_trigger_file",
+            "stats_temp_directory",
+            "trace_recovery_messages",
+            "vacuum_defer_cleanup_age",
+        ],
+        unavailable_extensions: &["adminpack", "old_snapshot"],
+        failover_slots: true,
+    },
+];
+
+impl PostgresVersion {
+    pub fn supports_guc(&self, name: &str) -> bool {
+        !self.unavailable_gucs.contains(&name)
+    }
+
+    pub fn supports_extension(&self, name: &str) -> bool {
+        !self.unavailable_extensions.contains(&name)
+    }
+}
+
+/// The capabilities of a Postgres major version, None when the operator does not support it
+pub fn postgres_version(major: i32) -> Option<&'static PostgresVersion> {
+    POSTGRES_VERSIONS
+        .iter()
+        .find(|version| version.major == major)
+}
+
+/// The capabilities of the Postgres version of the instance's image, None when the version
+/// can not be determined or is not supported
+pub fn postgres_version_of(cdb: &CoreDB) -> Option<&'static PostgresVersion> {
+    postgres_major_version_from_cdb(cdb)
+        .ok()
+        .and_then(postgres_version)
+}
+
+/// Validate a CoreDB against the capabilities of its Postgres version, returns everything in
+/// the spec the version does not support
+pub fn validate(cdb: &CoreDB) -> Result<(), Vec<String>> {
+    let major = postgres_major_version_from_cdb(cdb).map_err(|e| vec![e])?;
+    let Some(version) = postgres_version(major) else {
+        let supported: Vec<i32> = POSTGRES_VERSIONS.iter().map(|v| v.major).collect();
+        return Err(vec![format!(
+            "Postgres {} is not supported, supported versions are {:?}",
+            major, supported
+        )]);
+    };
+
+    let mut errors = vec![];
+    for config in cdb.spec.runtime_config.iter().flatten() {
+        if !version.supports_guc(&config.name) {
+            errors.push(format!(
+                "Parameter {} is not available in Postgres {}",
+                config.name, major
+            ));
+        }
+    }
+    let extensions = cdb
+        .spec
+        .trunk_installs
+        .iter()
+        .map(|install| &install.name)
+        .chain(cdb.spec.extensions.iter().map(|extension| &extension.name));
+    for name in extensions {
+        if !version.supports_extension(name) {
+            errors.push(format!(
+                "Extension {} is not available for Postgres {}",
+                name, major
+            ));
+        }
+    }
+    // An extension can be both installed and enabled
+    let mut seen = HashSet::new();
+    errors.retain(|error| seen.insert(error.clone()));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::CoreDBSpec;
+
+    fn coredb(spec: serde_json::Value) -> CoreDB {
+        let spec: CoreDBSpec = serde_json::from_value(spec).unwrap();
+        CoreDB::new("test", spec)
+    }
+
+    #[test]
+    fn test_postgres_versions() {
+        assert_eq!(
+            POSTGRES_VERSIONS
+                .iter()
+                .map(|v| v.major)
+                .collect::<Vec<_>>(),
+            vec![14, 15, 16, 17]
+        );
+        assert!(postgres_version(13).is_none());
+
+        let pg16 = postgres_version(16).unwrap();
+        assert!(pg16.supports_guc("max_connections"));
+        assert!(!pg16.supports_guc("vacuum_defer_cleanup_age"));
+        assert!(!pg16.failover_slots);
+        assert!(postgres_version(17).unwrap().failover_slots);
+    }
+
+    #[test]
+    fn test_validate() {
+        let cdb = coredb(serde_json::json!({
+            "image": "quay.io/tembo/standard-cnpg:17-a0a5ab5",
+            "runtime_config": [
+                {"name": "max_connections", "value": "100"},
+                {"name": "old_snapshot_threshold", "value": "1h"}
+            ],
+            "trunk_installs": [
+                {"name": "adminpack"},
+                {"name": "pgmq"}
+            ],
+            "extensions": [
+                {"name": "adminpack", "locations": []}
+            ]
+        }));
+        let errors = validate(&cdb).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("old_snapshot_threshold"));
+        assert!(errors[1].contains("adminpack"));
+
+        let cdb = coredb(serde_json::json!({
+            "image": "quay.io/tembo/standard-cnpg:16-a0a5ab5",
+            "trunk_installs": [{"name": "adminpack"}]
+        }));
+        assert!(validate(&cdb).is_ok());
+
+        let cdb = coredb(serde_json::json!({
+            "image": "quay.io/tembo/standard-cnpg:13-a0a5ab5"
+        }));
+        assert!(validate(&cdb).is_err());
+    }
+}
@@ -1 +1,67 @@
 pub mod podmonitor_crd;
+
+use std::collections::BTreeMap;
+
+/// Setting this annotation to "true" on a CoreDB marks the instance as being under
+/// planned maintenance. Every PodMonitor generated for the instance then stamps its
+/// series with `tembo_silenced="true"`, so fleet-wide alert rules can exclude them.
+pub const SILENCE_ALERTS_ANNOTATION: &str = "tembo.io/silence-alerts";
+
+/// Optional regex of metric names to drop entirely while an instance is silenced.
+/// Useful for series which feed alerts that do not filter on `tembo_silenced`.
+pub const SILENCE_DROP_METRICS_ANNOTATION: &str = "tembo.io/silence-alerts-drop-metrics";
+
+/// The label added to silenced PodMonitors and their scraped series
+pub const SILENCED_LABEL: &str = "tembo_silenced";
+
+/// Returns true when the silence annotation is present and set to "true"
+pub fn is_silenced(annotations: &BTreeMap<String, String>) -> bool {
+    annotations
+        .get(SILENCE_ALERTS_ANNOTATION)
+        .is_some_and(|value| value == "true")
+}
+
+/// Returns the regex of metric names to drop, only when the instance is silenced
+pub fn silenced_drop_metrics(annotations: &BTreeMap<String, String>) -> Option<String> {
+    if !is_silenced(annotations) {
+        return None;
+    }
+    annotations
+        .get(SILENCE_DROP_METRICS_ANNOTATION)
+        .filter(|regex| !regex.is_empty())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_silenced() {
+        let mut annotations = BTreeMap::new();
+        assert!(!is_silenced(&annotations));
+
+        annotations.insert(SILENCE_ALERTS_ANNOTATION.to_string(), "false".to_string());
+        assert!(!is_silenced(&annotations));
+
+        annotations.insert(SILENCE_ALERTS_ANNOTATION.to_string(), "true".to_string());
+        assert!(is_silenced(&annotations));
+    }
+
+    #[test]
+    fn test_silenced_drop_metrics() {
+        let mut annotations = BTreeMap::new();
+        annotations.insert(
+            SILENCE_DROP_METRICS_ANNOTATION.to_string(),
+            "cnpg_pg_replication_lag".to_string(),
+        );
+        // Not silenced, nothing should be dropped
+        assert_eq!(silenced_drop_metrics(&annotations), None);
+
+        annotations.insert(SILENCE_ALERTS_ANNOTATION.to_string(), "true".to_string());
+        assert_eq!(
+            silenced_drop_metrics(&annotations),
+            Some("cnpg_pg_replication_lag".to_string())
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::apis::coredb_types::{CoreDB, PausableComponent};
+use chrono::{DateTime, Utc};
+use k8s_openapi::{
+    api::core::v1::ResourceRequirements, apimachinery::pkg::api::resource::Quantity,
+};
+use serde::Serialize;
+
+/// Summary of the state the operator computed for an instance from its spec
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct DesiredState {
+    pub image: String,
+    pub replicas: i32,
+    pub stop: bool,
+    pub storage: Quantity,
+    pub resources: ResourceRequirements,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_profile: Option<String>,
+    pub paused_components: Vec<PausableComponent>,
+    pub standby: bool,
+    pub extensions: Vec<String>,
+    pub app_services: Vec<String>,
+    /// Runtime config which is left out of the Postgres configuration, with the reason
+    pub invalid_runtime_config: Vec<String>,
+}
+
+impl DesiredState {
+    pub fn from_cdb(cdb: &CoreDB) -> Self {
+        DesiredState {
+            image: cdb.spec.image.clone(),
+            replicas: cdb.spec.replicas,
+            stop: cdb.spec.stop,
+            storage: cdb.spec.storage.clone(),
+            resources: cdb.spec.resources.clone(),
+            resource_profile: cdb.spec.resource_profile.clone(),
+            paused_components: cdb.spec.paused_components(),
+            standby: cdb.spec.is_standby(),
+            extensions: cdb
+                .spec
+                .extensions
+                .iter()
+                .map(|extension| extension.name.clone())
+                .collect(),
+            app_services: cdb
+                .spec
+                .app_services
+                .iter()
+                .flatten()
+                .map(|appsvc| appsvc.name.clone())
+                .collect(),
+            invalid_runtime_config: vec![],
+        }
+    }
+}
+
+/// A step of the reconcile which was not run
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SkippedStep {
+    pub step: String,
+    pub reason: String,
+}
+
+/// What the last reconcile of an instance decided, served by the debug endpoint of the web
+/// server so it is not necessary to search the logs to see why a change did not take effect.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct ReconcileReport {
+    pub started_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub desired: DesiredState,
+    /// The steps which were applied, in order
+    pub applied: Vec<String>,
+    pub skipped: Vec<SkippedStep>,
+    /// How the reconcile ended, e.g. the step it was requeued at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+}
+
+impl ReconcileReport {
+    pub fn start(cdb: &CoreDB) -> Self {
+        ReconcileReport {
+            started_at: Utc::now(),
+            desired: DesiredState::from_cdb(cdb),
+            ..ReconcileReport::default()
+        }
+    }
+
+    pub fn applied(&mut self, step: &str) {
+        self.applied.push(step.to_string());
+    }
+
+    pub fn skipped(&mut self, step: &str, reason: &str) {
+        self.skipped.push(SkippedStep {
+            step: step.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Record how the reconcile ended. A requeue before the end of the reconcile happened at
+    /// the step after the last applied one.
+    pub fn finish(&mut self, fully_reconciled: bool) {
+        self.finished_at = Some(Utc::now());
+        self.outcome = Some(match (fully_reconciled, self.applied.last()) {
+            (true, _) => "fully reconciled".to_string(),
+            (false, Some(step)) => format!("requeued after {}", step),
+            (false, None) => "requeued before any step was applied".to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::CoreDBSpec;
+
+    #[test]
+    fn test_reconcile_report() {
+        let cdb = CoreDB::new(
+            "sample",
+            CoreDBSpec {
+                replicas: 2,
+                ..CoreDBSpec::default()
+            },
+        );
+        let mut report = ReconcileReport::start(&cdb);
+        assert_eq!(report.desired.replicas, 2);
+
+        report.finish(false);
+        assert_eq!(
+            report.outcome.as_deref(),
+            Some("requeued before any step was applied")
+        );
+
+        report.applied("network policies");
+        report.skipped("ingress", "DATA_PLANE_BASEDOMAIN is not set");
+        report.finish(false);
+        assert_eq!(
+            report.outcome.as_deref(),
+            Some("requeued after network policies")
+        );
+        assert_eq!(report.skipped[0].step, "ingress");
+
+        report.finish(true);
+        assert_eq!(report.outcome.as_deref(), Some("fully reconciled"));
+    }
+}
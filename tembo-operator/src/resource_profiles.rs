@@ -0,0 +1,153 @@
+use crate::{
+    apis::{coredb_types::CoreDB, postgres_parameters::PgConfig},
+    defaults,
+};
+use k8s_openapi::{
+    api::core::v1::ResourceRequirements, apimachinery::pkg::api::resource::Quantity,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A named preset of resources, storage and Postgres configuration, which CoreDBs reference
+/// with spec.resourceProfile
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ResourceProfile {
+    #[serde(default)]
+    pub resources: Option<ResourceRequirements>,
+    #[serde(default)]
+    pub storage: Option<Quantity>,
+    #[serde(default)]
+    pub runtime_config: Option<Vec<PgConfig>>,
+}
+
+// Parse the profiles from YAML (or JSON), keyed by profile name, e.g.
+// small:
+//   resources:
+//     limits: {cpu: "1", memory: 2Gi}
+//   storage: 10Gi
+//   runtime_config:
+//     - name: max_connections
+//       value: "100"
+pub fn parse_resource_profiles(
+    profiles: &str,
+) -> Result<BTreeMap<String, ResourceProfile>, String> {
+    if profiles.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    serde_yaml::from_str(profiles).map_err(|e| e.to_string())
+}
+
+// apply_resource_profile returns the CoreDB with the defaults of its spec.resourceProfile
+// filled in. Resources and storage set to anything but the spec defaults are kept, as are
+// runtime_config entries set in the spec. Errors when the profile is not defined.
+pub fn apply_resource_profile(
+    cdb: &CoreDB,
+    profiles: &BTreeMap<String, ResourceProfile>,
+) -> Result<CoreDB, String> {
+    let Some(profile_name) = cdb.spec.resource_profile.as_ref() else {
+        return Ok(cdb.clone());
+    };
+    let profile = profiles
+        .get(profile_name)
+        .ok_or_else(|| format!("Resource profile {} is not defined", profile_name))?;
+
+    let mut cdb = cdb.clone();
+    if let Some(resources) = &profile.resources {
+        if cdb.spec.resources == defaults::default_resources() {
+            cdb.spec.resources = resources.clone();
+        }
+    }
+    if let Some(storage) = &profile.storage {
+        if cdb.spec.storage == defaults::default_storage() {
+            cdb.spec.storage = storage.clone();
+        }
+    }
+    if let Some(profile_configs) = &profile.runtime_config {
+        let mut runtime_config = cdb.spec.runtime_config.take().unwrap_or_default();
+        for config in profile_configs {
+            if !runtime_config.iter().any(|c| c.name == config.name) {
+                runtime_config.push(config.clone());
+            }
+        }
+        cdb.spec.runtime_config = Some(runtime_config);
+    }
+    Ok(cdb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::{coredb_types::CoreDBSpec, postgres_parameters::ConfigValue};
+
+    const PROFILES: &str = r#"
+small:
+  resources:
+    limits:
+      cpu: "1"
+      memory: 2Gi
+    requests:
+      cpu: 250m
+      memory: 1Gi
+  storage: 20Gi
+  runtime_config:
+    - name: max_connections
+      value: "100"
+    - name: work_mem
+      value: 4MB
+"#;
+
+    fn runtime_config_value(cdb: &CoreDB, name: &str) -> Option<String> {
+        cdb.spec
+            .runtime_config
+            .iter()
+            .flatten()
+            .find(|c| c.name == name)
+            .map(|c| c.value.to_string())
+    }
+
+    #[test]
+    fn test_apply_resource_profile() {
+        let profiles = parse_resource_profiles(PROFILES).unwrap();
+        let small = profiles.get("small").unwrap();
+
+        let cdb = CoreDB::new(
+            "test",
+            CoreDBSpec {
+                resources: defaults::default_resources(),
+                storage: defaults::default_storage(),
+                resource_profile: Some("small".to_string()),
+                runtime_config: Some(vec![PgConfig {
+                    name: "work_mem".to_string(),
+                    value: ConfigValue::Single("16MB".to_string()),
+                }]),
+                ..CoreDBSpec::default()
+            },
+        );
+        let applied = apply_resource_profile(&cdb, &profiles).unwrap();
+        assert_eq!(Some(&applied.spec.resources), small.resources.as_ref());
+        assert_eq!(applied.spec.storage, Quantity("20Gi".to_string()));
+        assert_eq!(
+            runtime_config_value(&applied, "max_connections"),
+            Some("100".to_string())
+        );
+        // Explicit spec values take precedence over the profile
+        assert_eq!(
+            runtime_config_value(&applied, "work_mem"),
+            Some("16MB".to_string())
+        );
+
+        let mut cdb = cdb;
+        cdb.spec.storage = Quantity("50Gi".to_string());
+        let applied = apply_resource_profile(&cdb, &profiles).unwrap();
+        assert_eq!(applied.spec.storage, Quantity("50Gi".to_string()));
+
+        cdb.spec.resource_profile = Some("large".to_string());
+        assert!(apply_resource_profile(&cdb, &profiles).is_err());
+    }
+
+    #[test]
+    fn test_parse_resource_profiles() {
+        assert!(parse_resource_profiles("").unwrap().is_empty());
+        assert!(parse_resource_profiles("small: [").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    apis::coredb_types::{CoreDB, RestoreSample},
+    patch_cdb_status_merge, Context,
+};
+use chrono::Utc;
+use kube::{runtime::controller::Action, Api, ResourceExt};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info};
+
+// reconcile_restore_sample trims the tables of a restored instance down to the row limits
+// in spec.restore.sample. When it is done, status.restore_sampled_at is set, so the sampling
+// only ever runs once per instance.
+pub async fn reconcile_restore_sample(coredb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let sample = match coredb
+        .spec
+        .restore
+        .as_ref()
+        .and_then(|restore| restore.sample.as_ref())
+    {
+        Some(sample) => sample,
+        None => return Ok(()),
+    };
+    if coredb
+        .status
+        .as_ref()
+        .is_some_and(|status| status.restore_sampled_at.is_some())
+    {
+        return Ok(());
+    }
+
+    let name = coredb.name_any();
+    debug!(
+        "Sampling restored database {} on instance {}",
+        sample.database, name
+    );
+    let result = coredb
+        .psql(
+            restore_sample_query(sample),
+            sample.database.clone(),
+            ctx.clone(),
+        )
+        .await?;
+
+    if !result.success {
+        error!(
+            "Failed to sample restored database {} on instance {}: {:?}",
+            sample.database, name, result.stderr
+        );
+        return Err(Action::requeue(Duration::from_secs(300)));
+    }
+
+    // Reclaim the space of the removed rows and refresh the planner statistics. VACUUM can not
+    // run inside the sampling block, and a retry skips the tables which are already sampled.
+    let result = coredb
+        .psql(
+            RESTORE_SAMPLE_VACUUM_QUERY.to_owned(),
+            sample.database.clone(),
+            ctx.clone(),
+        )
+        .await?;
+    if !result.success {
+        error!(
+            "Failed to vacuum sampled database {} on instance {}: {:?}",
+            sample.database, name, result.stderr
+        );
+        return Err(Action::requeue(Duration::from_secs(300)));
+    }
+
+    info!(
+        "Restored database {} is sampled on instance {}",
+        sample.database, name
+    );
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &coredb.namespace().unwrap());
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "restore_sampled_at": Utc::now(),
+        }
+    });
+    patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+    Ok(())
+}
+
+// Table names without a schema refer to the public schema
+pub(crate) fn qualified_table_name(table: &str) -> String {
+    if table.contains('.') {
+        table.to_owned()
+    } else {
+        format!("public.{}", table)
+    }
+}
+
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+const RESTORE_SAMPLE_VACUUM_QUERY: &str = "VACUUM (ANALYZE);";
+
+// restore_sample_query builds an anonymous block which trims each table to its limit, committing
+// after every table so a large database is not sampled in one long transaction. The sampled rows
+// are copied aside with TABLESAMPLE, then the table is emptied and the rows are copied back.
+// Foreign key triggers are disabled for the session, so tables can be trimmed in any order, and
+// foreign keys are not re-validated afterwards. Partitions are trimmed individually, and tables
+// owned by extensions are skipped.
+fn restore_sample_query(sample: &RestoreSample) -> String {
+    let default_row_limit = sample
+        .default_row_limit
+        .map(|limit| limit.to_string())
+        .unwrap_or_else(|| "NULL".to_owned());
+
+    let tables = sample.tables.clone().unwrap_or_default();
+    let row_limit = if tables.is_empty() {
+        default_row_limit
+    } else {
+        let cases = tables
+            .iter()
+            .map(|(table, limit)| {
+                format!(
+                    "            WHEN {} THEN {}",
+                    quote_literal(&qualified_table_name(table)),
+                    limit
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "CASE t.schemaname || '.' || t.tablename\n{}\n            ELSE {}\n        END",
+            cases, default_row_limit
+        )
+    };
+
+    format!(
+        r#"
+DO $$
+DECLARE
+    t RECORD;
+    row_limit BIGINT;
+    kept_rows BIGINT;
+    sampled_rows BIGINT;
+    sample_percent FLOAT8;
+    column_list TEXT;
+BEGIN
+    SET session_replication_role = replica;
+
+    FOR t IN
+        SELECT c.oid, n.nspname AS schemaname, c.relname AS tablename, c.reltuples
+        FROM pg_class c
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE c.relkind = 'r'
+          AND n.nspname <> 'information_schema'
+          AND n.nspname NOT LIKE 'pg\_%'
+          AND NOT EXISTS (
+              SELECT 1 FROM pg_depend d
+              WHERE d.classid = 'pg_class'::regclass AND d.objid = c.oid AND d.deptype = 'e'
+          )
+        ORDER BY n.nspname, c.relname
+    LOOP
+        row_limit := {row_limit};
+        IF row_limit IS NULL OR row_limit < 0 THEN
+            CONTINUE;
+        END IF;
+
+        -- Tables within their limit are left alone, so a retry skips the sampled tables
+        EXECUTE format(
+            'SELECT count(*) FROM (SELECT 1 FROM ONLY %I.%I LIMIT %s) s',
+            t.schemaname, t.tablename, row_limit + 1
+        ) INTO kept_rows;
+        IF kept_rows <= row_limit THEN
+            CONTINUE;
+        END IF;
+
+        -- Read about twice the blocks needed for the limit, or all of them without statistics
+        sample_percent := CASE
+            WHEN t.reltuples > 0 THEN least(100, 200.0 * row_limit / t.reltuples)
+            ELSE 100
+        END;
+        EXECUTE format(
+            'CREATE TEMP TABLE tembo_sample ON COMMIT DROP AS '
+            'SELECT * FROM ONLY %I.%I TABLESAMPLE SYSTEM (%s) LIMIT %s',
+            t.schemaname, t.tablename, sample_percent, row_limit
+        );
+        GET DIAGNOSTICS sampled_rows = ROW_COUNT;
+        -- Sampled blocks can come up short on small or sparse tables
+        IF sampled_rows < row_limit THEN
+            TRUNCATE tembo_sample;
+            EXECUTE format(
+                'INSERT INTO tembo_sample SELECT * FROM ONLY %I.%I LIMIT %s',
+                t.schemaname, t.tablename, row_limit
+            );
+        END IF;
+
+        -- TRUNCATE is refused for tables referenced by a foreign key
+        IF EXISTS (SELECT 1 FROM pg_constraint WHERE contype = 'f' AND confrelid = t.oid) THEN
+            EXECUTE format('DELETE FROM ONLY %I.%I', t.schemaname, t.tablename);
+        ELSE
+            EXECUTE format('TRUNCATE ONLY %I.%I', t.schemaname, t.tablename);
+        END IF;
+
+        -- Generated columns are computed again when the rows are copied back
+        SELECT string_agg(quote_ident(a.attname), ', ' ORDER BY a.attnum) INTO column_list
+        FROM pg_attribute a
+        WHERE a.attrelid = t.oid AND a.attnum > 0 AND NOT a.attisdropped AND a.attgenerated = '';
+        EXECUTE format(
+            'INSERT INTO %I.%I (%s) OVERRIDING SYSTEM VALUE SELECT %s FROM tembo_sample',
+            t.schemaname, t.tablename, column_list, column_list
+        );
+        COMMIT;
+    END LOOP;
+
+    RESET session_replication_role;
+END;
+$$;
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_restore_sample_query() {
+        let sample = RestoreSample {
+            database: "postgres".to_owned(),
+            default_row_limit: Some(1000),
+            tables: Some(BTreeMap::from([
+                ("audit_log".to_owned(), 0),
+                ("sales.o'brien".to_owned(), -1),
+            ])),
+        };
+        let query = restore_sample_query(&sample);
+        assert!(query.contains("WHEN 'public.audit_log' THEN 0"));
+        assert!(query.contains("WHEN 'sales.o''brien' THEN -1"));
+        assert!(query.contains("ELSE 1000"));
+        // Every table is sampled in its own transaction
+        assert!(query.contains("TABLESAMPLE SYSTEM (%s) LIMIT %s"));
+        assert!(query.contains("COMMIT;\n    END LOOP;"));
+        assert!(!query.contains("SET LOCAL"));
+
+        // Without any per-table limits there is no CASE expression
+        let sample = RestoreSample {
+            database: "postgres".to_owned(),
+            default_row_limit: None,
+            tables: None,
+        };
+        let query = restore_sample_query(&sample);
+        assert!(query.contains("row_limit := NULL;"));
+        assert!(!query.contains("CASE t.schemaname"));
+    }
+}
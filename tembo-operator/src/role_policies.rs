@@ -0,0 +1,120 @@
+use crate::{
+    apis::coredb_types::{CoreDB, RolePolicy},
+    restore_sample::quote_literal,
+    Context,
+};
+use kube::{runtime::controller::Action, ResourceExt};
+use std::sync::Arc;
+use tracing::{debug, error};
+
+// reconcile_role_policies applies the connection and resource limits of spec.roles with
+// ALTER ROLE. Roles are only altered when their settings differ, so reconciling an instance
+// does not rewrite the catalog. Invalid limits are logged without failing the reconcile, so
+// a typo in one policy does not hold up the rest of the instance.
+pub async fn reconcile_role_policies(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let Some(roles) = cdb.spec.roles.as_ref().filter(|roles| !roles.is_empty()) else {
+        return Ok(());
+    };
+
+    let result = cdb
+        .psql(role_policies_query(roles), "postgres".to_owned(), ctx)
+        .await?;
+    if !result.success {
+        error!(
+            "Failed to apply role policies on instance {}: {:?}",
+            cdb.name_any(),
+            result.stderr
+        );
+    } else {
+        debug!("Applied role policies on instance {}", cdb.name_any());
+    }
+    Ok(())
+}
+
+fn quote_ident(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+// The statements setting or resetting a role level configuration parameter, when it differs
+fn role_setting(role: &str, parameter: &str, value: Option<&str>) -> String {
+    let settings = format!(
+        "SELECT unnest(s.setconfig) FROM pg_db_role_setting s JOIN pg_roles r ON r.oid = s.setrole \
+         WHERE r.rolname = {} AND s.setdatabase = 0",
+        quote_literal(role)
+    );
+    match value {
+        Some(value) => format!(
+            "IF {} NOT IN ({}) THEN\n            ALTER ROLE {} SET {} = {};\n        END IF;",
+            quote_literal(&format!("{}={}", parameter, value)),
+            settings,
+            quote_ident(role),
+            parameter,
+            quote_literal(value)
+        ),
+        None => format!(
+            "IF EXISTS (SELECT 1 FROM ({}) c(setting) WHERE setting LIKE {}) THEN\n            ALTER ROLE {} RESET {};\n        END IF;",
+            settings,
+            quote_literal(&format!("{}=%", parameter)),
+            quote_ident(role),
+            parameter
+        ),
+    }
+}
+
+fn role_policies_query(roles: &[RolePolicy]) -> String {
+    let statements = roles
+        .iter()
+        .map(|role| {
+            let connection_limit = role.connection_limit.unwrap_or(-1);
+            format!(
+                r#"    IF EXISTS (SELECT 1 FROM pg_roles WHERE rolname = {name}) THEN
+        IF (SELECT rolconnlimit FROM pg_roles WHERE rolname = {name}) <> {connection_limit} THEN
+            ALTER ROLE {ident} CONNECTION LIMIT {connection_limit};
+        END IF;
+        {statement_timeout}
+        {work_mem}
+    END IF;"#,
+                name = quote_literal(&role.name),
+                ident = quote_ident(&role.name),
+                connection_limit = connection_limit,
+                statement_timeout = role_setting(
+                    &role.name,
+                    "statement_timeout",
+                    role.statement_timeout.as_deref()
+                ),
+                work_mem = role_setting(&role.name, "work_mem", role.work_mem.as_deref()),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!("DO $$\nBEGIN\n{}\nEND;\n$$;\n", statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_policies_query() {
+        let roles = vec![
+            RolePolicy {
+                name: "app".to_owned(),
+                connection_limit: Some(20),
+                statement_timeout: Some("30s".to_owned()),
+                work_mem: None,
+            },
+            RolePolicy {
+                name: "o\"brien".to_owned(),
+                ..RolePolicy::default()
+            },
+        ];
+        let query = role_policies_query(&roles);
+        assert!(query.contains("ALTER ROLE \"app\" CONNECTION LIMIT 20;"));
+        assert!(query.contains("IF 'statement_timeout=30s' NOT IN ("));
+        assert!(query.contains("ALTER ROLE \"app\" SET statement_timeout = '30s';"));
+        assert!(query.contains("ALTER ROLE \"app\" RESET work_mem;"));
+        // Limits which are not set are reset
+        assert!(query.contains("ALTER ROLE \"o\"\"brien\" CONNECTION LIMIT -1;"));
+        assert!(query.contains("ALTER ROLE \"o\"\"brien\" RESET statement_timeout;"));
+    }
+}
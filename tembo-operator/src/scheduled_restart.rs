@@ -0,0 +1,307 @@
+use crate::{
+    apis::coredb_types::{CoreDB, PausableComponent},
+    Context, RESTARTED_AT,
+};
+use chrono::{DateTime, Datelike, Duration, DurationRound, NaiveDate, Utc};
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::controller::Action,
+    Api, ResourceExt,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+// Scheduled restarts missed by more than this, e.g. while the operator was down, are skipped
+// until the next scheduled time
+const MISSED_RESTART_GRACE_MINUTES: i64 = 60;
+
+// reconcile_scheduled_restart restarts the instance at the times of spec.restartSchedule, by
+// setting the restartedAt annotation like a user requested restart. CNPG then restarts the
+// replicas first and switches over to a restarted replica before restarting the primary.
+pub async fn reconcile_scheduled_restart(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let Some(schedule) = cdb.spec.restart_schedule.as_ref() else {
+        return Ok(());
+    };
+    if cdb.spec.is_paused(PausableComponent::Postgres) {
+        return Ok(());
+    }
+    let name = cdb.name_any();
+    let schedule = match CronSchedule::parse(schedule) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            warn!("Ignoring invalid restartSchedule of {}: {}", name, e);
+            return Ok(());
+        }
+    };
+
+    let now = Utc::now();
+    let Some(scheduled_at) = schedule.last_before(now) else {
+        return Ok(());
+    };
+    let last_restart = cdb
+        .annotations()
+        .get(RESTARTED_AT)
+        .and_then(|restarted_at| DateTime::parse_from_rfc3339(restarted_at).ok())
+        .map(|restarted_at| restarted_at.with_timezone(&Utc));
+    if !is_restart_due(scheduled_at, last_restart, now) {
+        return Ok(());
+    }
+
+    info!(
+        "Restarting instance {} as scheduled at {}",
+        name,
+        scheduled_at.to_rfc3339()
+    );
+    let patch = json!({
+        "metadata": {
+            "annotations": {
+                RESTARTED_AT: scheduled_at.to_rfc3339(),
+            }
+        }
+    });
+    let patch_params = PatchParams {
+        field_manager: Some("cntrlr".to_string()),
+        ..PatchParams::default()
+    };
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &cdb.namespace().unwrap());
+    coredbs
+        .patch(&name, &patch_params, &Patch::Merge(patch))
+        .await
+        .map_err(|e| {
+            error!("Error scheduling restart of {}: {:?}", name, e);
+            Action::requeue(std::time::Duration::from_secs(300))
+        })?;
+    // The restart is performed by the reconcile of the patched CoreDB
+    Err(Action::requeue(std::time::Duration::from_secs(1)))
+}
+
+// A restart is due when the scheduled time is recent and the instance was not restarted since
+fn is_restart_due(
+    scheduled_at: DateTime<Utc>,
+    last_restart: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    now - scheduled_at <= Duration::minutes(MISSED_RESTART_GRACE_MINUTES)
+        && last_restart.is_none_or(|last_restart| last_restart < scheduled_at)
+}
+
+/// A schedule in cron syntax with minute, hour, day of month, month and day of week fields,
+/// evaluated in UTC
+#[derive(Debug, PartialEq)]
+struct CronSchedule {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days_of_month: Vec<u32>,
+    months: Vec<u32>,
+    days_of_week: Vec<u32>,
+    // Like cron, a day matches either field when both days are restricted
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    fn parse(schedule: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = schedule.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "Expected 5 fields in schedule {}, found {}",
+                schedule,
+                fields.len()
+            ));
+        };
+        // Sunday is both 0 and 7
+        let mut days_of_week = parse_field(day_of_week, 0, 7)?;
+        if days_of_week.contains(&7) {
+            days_of_week.retain(|day| *day != 7);
+            if !days_of_week.contains(&0) {
+                days_of_week.insert(0, 0);
+            }
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days_of_month: parse_field(day_of_month, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            days_of_week,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+
+    // Whether the schedule runs at any time of the day
+    fn matches_day(&self, day: NaiveDate) -> bool {
+        let day_of_month = self.days_of_month.contains(&day.day());
+        let day_of_week = self
+            .days_of_week
+            .contains(&day.weekday().num_days_from_sunday());
+        let day_matches = match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+        day_matches && self.months.contains(&day.month())
+    }
+
+    // The most recent scheduled time at or before now, within the last year. Days are
+    // searched backwards, only the times of a matching day are looked at.
+    fn last_before(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let now = now.duration_trunc(Duration::minutes(1)).ok()?;
+        let earliest = (now - Duration::days(366)).date_naive();
+        let mut day = now.date_naive();
+        while day > earliest {
+            if self.matches_day(day) {
+                let latest = self
+                    .hours
+                    .iter()
+                    .rev()
+                    .flat_map(|hour| {
+                        self.minutes
+                            .iter()
+                            .rev()
+                            .map(move |minute| (*hour, *minute))
+                    })
+                    .filter_map(|(hour, minute)| day.and_hms_opt(hour, minute, 0))
+                    .map(|time| time.and_utc())
+                    .find(|time| *time <= now);
+                if latest.is_some() {
+                    return latest;
+                }
+            }
+            day = day.pred_opt()?;
+        }
+        None
+    }
+}
+
+// Parse a cron field like `*`, `5`, `1-5`, `*/15`, `0-30/10` or a comma separated list of them
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values = vec![];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("Invalid step in {}", field))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, field)?, parse_value(end, field)?)
+        } else {
+            let value = parse_value(range, field)?;
+            // A single value with a step runs from the value to the maximum, like cron
+            (value, if part.contains('/') { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return Err(format!(
+                "Values in {} must be between {} and {}",
+                field, min, max
+            ));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn parse_value(value: &str, field: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {} in {}", value, field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_cron_schedule() {
+        let schedule = CronSchedule::parse("0 3 * * 0").unwrap();
+        assert_eq!(schedule.minutes, vec![0]);
+        assert_eq!(schedule.hours, vec![3]);
+        assert_eq!(schedule.days_of_month.len(), 31);
+        assert_eq!(schedule.days_of_week, vec![0]);
+
+        let schedule = CronSchedule::parse("*/15 1-3,22 * * 7").unwrap();
+        assert_eq!(schedule.minutes, vec![0, 15, 30, 45]);
+        assert_eq!(schedule.hours, vec![1, 2, 3, 22]);
+        assert_eq!(schedule.days_of_week, vec![0]);
+
+        assert!(CronSchedule::parse("0 3 * *").is_err());
+        assert!(CronSchedule::parse("60 3 * * *").is_err());
+        assert!(CronSchedule::parse("0 3 * * mon").is_err());
+        assert!(CronSchedule::parse("*/0 3 * * *").is_err());
+    }
+
+    #[test]
+    fn test_last_before() {
+        // Sundays at 03:00
+        let schedule = CronSchedule::parse("0 3 * * 0").unwrap();
+        // Wednesday
+        let now = Utc.with_ymd_and_hms(2024, 6, 5, 12, 30, 15).unwrap();
+        assert_eq!(
+            schedule.last_before(now),
+            Some(Utc.with_ymd_and_hms(2024, 6, 2, 3, 0, 0).unwrap())
+        );
+
+        // The 1st of the month, or Mondays
+        let schedule = CronSchedule::parse("30 4 1 * 1").unwrap();
+        assert_eq!(
+            schedule.last_before(now),
+            Some(Utc.with_ymd_and_hms(2024, 6, 3, 4, 30, 0).unwrap())
+        );
+
+        // Only in January
+        let schedule = CronSchedule::parse("0 0 1 1 *").unwrap();
+        assert_eq!(
+            schedule.last_before(now),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+        );
+
+        // Earlier today, not later today
+        let schedule = CronSchedule::parse("0,45 9,13 * * *").unwrap();
+        assert_eq!(
+            schedule.last_before(now),
+            Some(Utc.with_ymd_and_hms(2024, 6, 5, 9, 45, 0).unwrap())
+        );
+        assert_eq!(
+            schedule.last_before(Utc.with_ymd_and_hms(2024, 6, 5, 13, 0, 0).unwrap()),
+            Some(Utc.with_ymd_and_hms(2024, 6, 5, 13, 0, 0).unwrap())
+        );
+
+        // Never, February 30th
+        let schedule = CronSchedule::parse("0 0 30 2 *").unwrap();
+        assert_eq!(schedule.last_before(now), None);
+    }
+
+    #[test]
+    fn test_is_restart_due() {
+        let scheduled_at = Utc.with_ymd_and_hms(2024, 6, 2, 3, 0, 0).unwrap();
+        let now = scheduled_at + Duration::minutes(5);
+        assert!(is_restart_due(scheduled_at, None, now));
+        assert!(is_restart_due(
+            scheduled_at,
+            Some(scheduled_at - Duration::days(7)),
+            now
+        ));
+        // Already restarted, as scheduled or by a user
+        assert!(!is_restart_due(scheduled_at, Some(scheduled_at), now));
+        assert!(!is_restart_due(
+            scheduled_at,
+            Some(scheduled_at + Duration::minutes(1)),
+            now
+        ));
+        // Missed by too long
+        assert!(!is_restart_due(
+            scheduled_at,
+            None,
+            scheduled_at + Duration::hours(2)
+        ));
+    }
+}
@@ -67,7 +67,7 @@ pub async fn reconcile_secret(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Act
 
     let data = secret_data(cdb, &ns, password);
 
-    let secret: Secret = Secret {
+    let mut secret: Secret = Secret {
         metadata: ObjectMeta {
             name: Some(name.to_owned()),
             namespace: Some(ns.to_owned()),
@@ -78,6 +78,7 @@ pub async fn reconcile_secret(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Act
         data: Some(data),
         ..Secret::default()
     };
+    cdb.spec.apply_common_metadata(&mut secret.metadata);
 
     let ps = PatchParams::apply("cntrlr").force();
     let patch_status = Patch::Apply(&secret);
@@ -197,7 +198,7 @@ pub async fn reconcile_postgres_role_secret(
     // generate secret data
     let (data, secret_data) = generate_role_secret_data(role_name);
 
-    let secret: Secret = Secret {
+    let mut secret: Secret = Secret {
         metadata: ObjectMeta {
             name: Some(name.to_owned()),
             namespace: Some(ns.to_owned()),
@@ -208,6 +209,7 @@ pub async fn reconcile_postgres_role_secret(
         data: Some(data),
         ..Secret::default()
     };
+    cdb.spec.apply_common_metadata(&mut secret.metadata);
 
     let ps = PatchParams::apply("cntrlr").force();
     let _o = secret_api
@@ -424,6 +424,49 @@ async fn lookup_volume_snapshot(cdb: &CoreDB, client: &Client) -> Result<VolumeS
     let volume_snapshot_api: Api<VolumeSnapshot> =
         Api::namespaced(client.clone(), &og_instance_name);
 
+    // A snapshot requested by name is used as long as it is ready to use
+    if let Some(snapshot_name) = cdb
+        .spec
+        .restore
+        .as_ref()
+        .and_then(|r| r.volume_snapshot_name.as_ref())
+    {
+        let snapshot = volume_snapshot_api
+            .get_opt(snapshot_name)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Error getting VolumeSnapshot {} of instance {}: {}",
+                    snapshot_name, og_instance_name, e
+                );
+                Action::requeue(tokio::time::Duration::from_secs(300))
+            })?;
+        return match snapshot {
+            Some(vs)
+                if vs
+                    .status
+                    .as_ref()
+                    .is_some_and(|s| s.ready_to_use.unwrap_or(false)) =>
+            {
+                Ok(vs)
+            }
+            Some(_) => {
+                warn!(
+                    "VolumeSnapshot {} of instance {} is not ready yet",
+                    snapshot_name, og_instance_name
+                );
+                Err(Action::requeue(tokio::time::Duration::from_secs(30)))
+            }
+            None => {
+                error!(
+                    "VolumeSnapshot {} of instance {} not found",
+                    snapshot_name, og_instance_name
+                );
+                Err(Action::requeue(tokio::time::Duration::from_secs(300)))
+            }
+        };
+    }
+
     let label_selector = format!("cnpg.io/cluster={}", og_instance_name);
     let lp = ListParams::default().labels(&label_selector);
     let result = volume_snapshot_api.list(&lp).await.map_err(|e| {
@@ -200,9 +200,7 @@ async fn requires_load_list_from_trunk() -> Result<Vec<String>, TrunkError> {
 
 // Get all trunk projects
 pub async fn get_trunk_projects() -> Result<Vec<TrunkProjectMetadata>, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-    let url = format!("https://{}/api/v1/trunk-projects", domain);
+    let url = format!("{}/api/v1/trunk-projects", trunk_registry_url());
 
     let response = reqwest::get(&url).await?;
 
@@ -219,9 +217,7 @@ pub async fn get_trunk_projects() -> Result<Vec<TrunkProjectMetadata>, TrunkErro
 
 // Get all trunk project names
 pub async fn get_trunk_project_names() -> Result<Vec<String>, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-    let url = format!("https://{}/api/v1/trunk-projects", domain);
+    let url = format!("{}/api/v1/trunk-projects", trunk_registry_url());
 
     let response = reqwest::get(&url).await?;
 
@@ -244,9 +240,7 @@ pub async fn get_trunk_project_names() -> Result<Vec<String>, TrunkError> {
 async fn get_latest_trunk_project_metadata(
     trunk_project: &str,
 ) -> Result<TrunkProjectMetadata, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-    let url = format!("https://{}/api/v1/trunk-projects", domain);
+    let url = format!("{}/api/v1/trunk-projects", trunk_registry_url());
 
     let response = reqwest::get(&url).await?;
 
@@ -274,8 +268,7 @@ pub async fn get_trunk_project_metadata_for_version(
     trunk_project_name: &str,
     version: Version<'_>,
 ) -> Result<TrunkProjectMetadata, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
+    let registry = trunk_registry_url();
 
     let converted_semver;
 
@@ -289,10 +282,10 @@ pub async fn get_trunk_project_metadata_for_version(
 
     let url = match version {
         Version::TrunkProject(trunk_project_version) => format!(
-            "https://{domain}/api/v1/trunk-projects/{trunk_project_name}/version/{trunk_project_version}"
+            "{registry}/api/v1/trunk-projects/{trunk_project_name}/version/{trunk_project_version}"
         ),
         Version::Extension(_extension_version) => {
-            format!("https://{domain}/api/v1/trunk-projects/{trunk_project_name}")
+            format!("{registry}/api/v1/trunk-projects/{trunk_project_name}")
         }
     };
 
@@ -348,6 +341,56 @@ pub async fn get_trunk_project_metadata_for_version(
     Ok(trunk_project)
 }
 
+// The registry trunk metadata and installs are pulled from, including the scheme.
+// TRUNK_REGISTRY_URL points the operator at an in-cluster cache of the registry, for data
+// planes without egress to the internet, and takes precedence over TRUNK_REGISTRY_DOMAIN.
+pub fn trunk_registry_url() -> String {
+    registry_url(
+        env::var("TRUNK_REGISTRY_URL").ok(),
+        env::var("TRUNK_REGISTRY_DOMAIN").ok(),
+    )
+}
+
+fn registry_url(url: Option<String>, domain: Option<String>) -> String {
+    if let Some(url) = url.filter(|url| !url.is_empty()) {
+        return url.trim_end_matches('/').to_string();
+    }
+    let domain = domain.unwrap_or_else(|| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
+    format!("https://{}", domain)
+}
+
+// Get the sha256 of the artifact published for a trunk project version and Postgres major version
+pub async fn get_trunk_project_artifact_sha256(
+    trunk_project_name: &str,
+    trunk_project_version: &str,
+    pg_version: i32,
+) -> Result<Option<String>, TrunkError> {
+    let project_metadata = get_trunk_project_metadata_for_version(
+        trunk_project_name,
+        Version::TrunkProject(trunk_project_version),
+    )
+    .await?;
+
+    Ok(artifact_sha256(&project_metadata, pg_version))
+}
+
+// Find the sha256 of the download matching the Postgres major version, preferring
+// linux/amd64 when artifacts were published for more than one platform
+fn artifact_sha256(project_metadata: &TrunkProjectMetadata, pg_version: i32) -> Option<String> {
+    let downloads: Vec<&TrunkDownloadMetadata> = project_metadata
+        .downloads
+        .iter()
+        .flatten()
+        .filter(|download| download.pg_version == pg_version)
+        .collect();
+
+    downloads
+        .iter()
+        .find(|download| download.platform == "linux/amd64")
+        .or_else(|| downloads.first())
+        .map(|download| download.sha256.clone())
+}
+
 // Check if extension name is in list of trunk project names
 pub async fn extension_name_matches_trunk_project(extension_name: String) -> Result<bool, Action> {
     let trunk_project_names = match get_trunk_project_names().await {
@@ -528,6 +571,30 @@ pub enum TrunkError {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_registry_url() {
+        assert_eq!(
+            registry_url(None, None),
+            format!("https://{}", DEFAULT_TRUNK_REGISTRY_DOMAIN)
+        );
+        assert_eq!(
+            registry_url(None, Some("registry.example.com".to_string())),
+            "https://registry.example.com"
+        );
+        // The in-cluster cache takes precedence over the registry domain
+        assert_eq!(
+            registry_url(
+                Some("http://trunk-cache.tembo-system.svc:8080/".to_string()),
+                Some("registry.example.com".to_string())
+            ),
+            "http://trunk-cache.tembo-system.svc:8080"
+        );
+        assert_eq!(
+            registry_url(Some("".to_string()), None),
+            format!("https://{}", DEFAULT_TRUNK_REGISTRY_DOMAIN)
+        );
+    }
+
     #[tokio::test]
     async fn test_get_trunk_projects() {
         let result = get_trunk_projects().await;
@@ -702,6 +769,50 @@ mod tests {
         assert_eq!(result, "1.2.0".to_string());
     }
 
+    #[test]
+    fn test_artifact_sha256() {
+        let download = |pg_version: i32, platform: &str, sha256: &str| {
+            TrunkDownloadMetadata {
+            link: format!("https://cdb-plat-use1-prod-pgtrunkio.s3.amazonaws.com/extensions/pgmq/pgmq-pg{pg_version}-1.1.1.tar.gz"),
+            pg_version,
+            platform: platform.to_string(),
+            sha256: sha256.to_string(),
+        }
+        };
+        let mut project_metadata = TrunkProjectMetadata {
+            name: "pgmq".to_string(),
+            description: None,
+            documentation_link: None,
+            repository_link: None,
+            version: "1.1.1".to_string(),
+            postgres_versions: Some(vec![14, 15, 16]),
+            extensions: vec![],
+            downloads: Some(vec![
+                download(14, "linux/amd64", "aaa"),
+                download(15, "linux/arm64", "bbb"),
+                download(15, "linux/amd64", "ccc"),
+                download(16, "linux/arm64", "ddd"),
+            ]),
+        };
+
+        assert_eq!(
+            artifact_sha256(&project_metadata, 14),
+            Some("aaa".to_string())
+        );
+        assert_eq!(
+            artifact_sha256(&project_metadata, 15),
+            Some("ccc".to_string())
+        );
+        assert_eq!(
+            artifact_sha256(&project_metadata, 16),
+            Some("ddd".to_string())
+        );
+        assert_eq!(artifact_sha256(&project_metadata, 17), None);
+
+        project_metadata.downloads = None;
+        assert_eq!(artifact_sha256(&project_metadata, 15), None);
+    }
+
     #[tokio::test]
     async fn test_requires_load_list_from_trunk() {
         // To ensure backwards compatibility with the older endpoint, let's ensure
@@ -0,0 +1,132 @@
+use crate::{
+    apis::coredb_types::CoreDB,
+    events::{publish_event, REASON_TTL_EXPIRED},
+    Context,
+};
+use chrono::{DateTime, Duration, Utc};
+use kube::{
+    api::DeleteParams,
+    runtime::{controller::Action, events::EventType},
+    Api, ResourceExt,
+};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+// reconcile_ttl deletes instances once spec.ttl has passed since they were created.
+// Returns when the instance expires, to be recorded in status.expires_at.
+pub async fn reconcile_ttl(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+) -> Result<Option<DateTime<Utc>>, Action> {
+    let Some(ttl) = cdb.spec.ttl.as_ref() else {
+        return Ok(None);
+    };
+    let name = cdb.name_any();
+    let ttl = match parse_ttl(ttl) {
+        Ok(ttl) => ttl,
+        Err(e) => {
+            warn!("Ignoring invalid ttl of {}: {}", name, e);
+            return Ok(None);
+        }
+    };
+    let Some(created_at) = cdb.creation_timestamp() else {
+        return Ok(None);
+    };
+    let Some(expires_at) = created_at.0.checked_add_signed(ttl) else {
+        warn!(
+            "Ignoring ttl of {} which expires too far in the future",
+            name
+        );
+        return Ok(None);
+    };
+    if Utc::now() < expires_at {
+        return Ok(Some(expires_at));
+    }
+
+    info!("Instance {} expired at {}, deleting it", name, expires_at);
+    publish_event(
+        cdb,
+        &ctx,
+        EventType::Normal,
+        REASON_TTL_EXPIRED,
+        "Deleting",
+        format!(
+            "Deleting instance {}, its ttl of {} expired at {}",
+            name,
+            cdb.spec.ttl.as_deref().unwrap_or_default(),
+            expires_at.to_rfc3339()
+        ),
+    )
+    .await;
+
+    let namespace = cdb.namespace().unwrap();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    match coredbs.delete(&name, &DeleteParams::default()).await {
+        // The deletion is handled by the finalizer from here on
+        Ok(_) => Err(Action::await_change()),
+        Err(e) => {
+            error!("Error deleting expired instance {}: {:?}", name, e);
+            Err(Action::requeue(std::time::Duration::from_secs(300)))
+        }
+    }
+}
+
+// Parse a duration like `90s`, `30m`, `12h`, `7d` or a combination like `1d12h`
+pub fn parse_ttl(ttl: &str) -> Result<Duration, String> {
+    let too_long = || format!("Invalid ttl {}, it is too long", ttl);
+    let mut total_seconds: i64 = 0;
+    let mut number = String::new();
+    for c in ttl.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number
+            .parse()
+            .map_err(|_| format!("Invalid ttl {}, expected e.g. 12h", ttl))?;
+        let unit_seconds = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("Invalid unit {} in ttl {}", c, ttl)),
+        };
+        total_seconds = value
+            .checked_mul(unit_seconds)
+            .and_then(|seconds| total_seconds.checked_add(seconds))
+            .ok_or_else(too_long)?;
+        number.clear();
+    }
+    if !number.is_empty() || total_seconds <= 0 {
+        return Err(format!("Invalid ttl {}, expected e.g. 12h", ttl));
+    }
+    // Durations are limited to i64::MAX milliseconds
+    if total_seconds.checked_mul(1000).is_none() {
+        return Err(too_long());
+    }
+    Ok(Duration::seconds(total_seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("90s"), Ok(Duration::seconds(90)));
+        assert_eq!(parse_ttl("30m"), Ok(Duration::minutes(30)));
+        assert_eq!(parse_ttl("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_ttl("7d"), Ok(Duration::days(7)));
+        assert_eq!(
+            parse_ttl("1d12h"),
+            Ok(Duration::days(1) + Duration::hours(12))
+        );
+        assert!(parse_ttl("").is_err());
+        assert!(parse_ttl("12").is_err());
+        assert!(parse_ttl("h").is_err());
+        assert!(parse_ttl("0h").is_err());
+        assert!(parse_ttl("2w").is_err());
+        assert!(parse_ttl("99999999999999999d").is_err());
+        assert!(parse_ttl("9223372036854775807s").is_err());
+    }
+}
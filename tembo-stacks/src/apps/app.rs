@@ -127,7 +127,7 @@ pub fn merge_app_reqs(
                     // no extensions or trunk installs
                 }
                 AppType::Custom(custom_app) => {
-                    user_app_services.push(custom_app);
+                    user_app_services.push(*custom_app);
                 }
             }
         }
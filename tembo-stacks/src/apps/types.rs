@@ -40,7 +40,7 @@ pub enum AppType {
     #[serde(rename = "sqlrunner")]
     SqlRunner(Option<AppConfig>),
     #[serde(rename = "custom")]
-    Custom(AppService),
+    Custom(Box<AppService>),
 }
 
 // public facing interface for supported appService modes
@@ -75,7 +75,7 @@ impl TryFrom<AppService> for AppType {
             "sqlrunner" => Ok(AppType::SqlRunner(app_config)),
             _ => {
                 // everything else is a custom app
-                Ok(AppType::Custom(app_service))
+                Ok(AppType::Custom(Box::new(app_service)))
             }
         }
     }
@@ -1,6 +1,6 @@
 use clap::Parser;
 use tembo_controller::apis::coredb_types::CoreDBSpec;
-use tembo_stacks::stacks::types::StackType;
+use tembo_stacks::stacks::{compatibility::compatibility_matrix, types::StackType};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -8,8 +8,12 @@ struct Args {
     #[arg(long, default_value_t = 16)]
     pg_version: i32,
 
+    #[arg(long, required_unless_present = "compatibility_matrix")]
+    stack: Option<StackType>,
+
+    /// Write the stack x Postgres version x extension version compatibility matrix instead
     #[arg(long)]
-    stack: StackType,
+    compatibility_matrix: bool,
 
     #[arg(long)]
     name: Option<String>,
@@ -17,12 +21,21 @@ struct Args {
 
 fn main() {
     let args = Args::parse();
+    if args.compatibility_matrix {
+        let json = serde_json::to_string_pretty(&compatibility_matrix(None)).unwrap();
+        let filename = "compatibility-matrix.json";
+        std::fs::write(filename, json).expect("Unable to write to file");
+        println!("Wrote compatibility matrix: {}", filename);
+        return;
+    }
+
+    let stack_type = args.stack.expect("--stack is required");
     let resource_name = match args.name {
         Some(name) => name.to_lowercase(),
-        None => args.stack.to_string().to_lowercase(),
+        None => stack_type.to_string().to_lowercase(),
     };
-    let stack_name = args.stack.to_string();
-    let stack = tembo_stacks::stacks::get_stack(args.stack);
+    let stack_name = stack_type.to_string();
+    let stack = tembo_stacks::stacks::get_stack(stack_type);
     let coredb = stack.to_coredb("1".to_string(), "1Gi".to_string(), "10Gi".to_string());
     let json = generate_spec(&coredb, &resource_name);
     // writing to json because not an easy way to string quote nested postgres config values in yaml
@@ -0,0 +1,210 @@
+use crate::stacks::{get_stack, types::StackType};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use strum::IntoEnumIterator;
+use tembo_controller::defaults::ImagePerPgVersion;
+use utoipa::ToSchema;
+
+/// The Postgres major versions stacks are built for
+pub const PG_MAJOR_VERSIONS: [i32; 4] = [14, 15, 16, 17];
+
+/// Which extension versions every stack installs on each Postgres major version
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, ToSchema)]
+pub struct CompatibilityMatrix {
+    pub entries: Vec<StackCompatibility>,
+}
+
+/// A stack on a Postgres major version
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, ToSchema)]
+pub struct StackCompatibility {
+    pub stack: StackType,
+    pub pg_version: i32,
+    /// The image of the stack for this Postgres version, None when the stack does not support it
+    pub image: Option<String>,
+    pub extensions: Vec<ExtensionCompatibility>,
+}
+
+/// A trunk install of a stack on a Postgres major version
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, ToSchema)]
+pub struct ExtensionCompatibility {
+    pub name: String,
+    pub version: Option<String>,
+    /// Whether trunk publishes this version for the Postgres version, None when unknown
+    pub available: Option<bool>,
+}
+
+/// The Postgres major versions each trunk project version is published for, as listed by
+/// the Trunk registry. Keyed by project name, then version.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, ToSchema)]
+pub struct TrunkMetadata {
+    #[schema(value_type = Object)]
+    pub projects: BTreeMap<String, BTreeMap<String, Vec<i32>>>,
+}
+
+impl TrunkMetadata {
+    pub fn add(&mut self, name: &str, version: &str, pg_versions: Vec<i32>) {
+        self.projects
+            .entry(name.to_string())
+            .or_default()
+            .insert(version.to_string(), pg_versions);
+    }
+
+    // None when the project, or the requested version of it, is not in the metadata
+    fn is_available(&self, name: &str, version: Option<&str>, pg_version: i32) -> Option<bool> {
+        let versions = self.projects.get(name)?;
+        match version {
+            Some(version) => versions
+                .get(version)
+                .map(|pg_versions| pg_versions.contains(&pg_version)),
+            // Without a version the latest is installed, any published version will do
+            None => Some(
+                versions
+                    .values()
+                    .any(|pg_versions| pg_versions.contains(&pg_version)),
+            ),
+        }
+    }
+}
+
+/// Generate the compatibility matrix of all stacks from their specs. Extension availability
+/// is only known when trunk metadata is provided.
+pub fn compatibility_matrix(trunk: Option<&TrunkMetadata>) -> CompatibilityMatrix {
+    let mut entries = vec![];
+    for stack_type in StackType::iter() {
+        let stack = get_stack(stack_type.clone());
+        for pg_version in PG_MAJOR_VERSIONS {
+            let extensions = stack
+                .trunk_installs
+                .iter()
+                .flatten()
+                .map(|install| ExtensionCompatibility {
+                    name: install.name.clone(),
+                    version: install.version.clone(),
+                    available: trunk.and_then(|trunk| {
+                        trunk.is_available(&install.name, install.version.as_deref(), pg_version)
+                    }),
+                })
+                .collect();
+            entries.push(StackCompatibility {
+                stack: stack_type.clone(),
+                pg_version,
+                image: image_for_pg_version(&stack.images, pg_version),
+                extensions,
+            });
+        }
+    }
+    CompatibilityMatrix { entries }
+}
+
+impl CompatibilityMatrix {
+    pub fn get(&self, stack: &StackType, pg_version: i32) -> Option<&StackCompatibility> {
+        self.entries
+            .iter()
+            .find(|entry| &entry.stack == stack && entry.pg_version == pg_version)
+    }
+
+    /// Validate that a stack can be provisioned on a Postgres major version, returns the
+    /// reasons it can not be
+    pub fn validate(&self, stack: &StackType, pg_version: i32) -> Result<(), Vec<String>> {
+        let Some(entry) = self.get(stack, pg_version) else {
+            return Err(vec![format!(
+                "Postgres {} is not supported, supported versions are {:?}",
+                pg_version, PG_MAJOR_VERSIONS
+            )]);
+        };
+        if entry.image.is_none() {
+            return Err(vec![format!(
+                "The {} stack is not available for Postgres {}",
+                stack, pg_version
+            )]);
+        }
+
+        let errors: Vec<String> = entry
+            .extensions
+            .iter()
+            .filter(|extension| extension.available == Some(false))
+            .map(|extension| {
+                format!(
+                    "Extension {} {} of the {} stack is not available for Postgres {}",
+                    extension.name,
+                    extension.version.as_deref().unwrap_or("latest"),
+                    stack,
+                    pg_version
+                )
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn image_for_pg_version(images: &ImagePerPgVersion, pg_version: i32) -> Option<String> {
+    match pg_version {
+        14 => images.pg14.clone(),
+        15 => images.pg15.clone(),
+        16 => images.pg16.clone(),
+        17 => images.pg17.clone(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility_matrix() {
+        let matrix = compatibility_matrix(None);
+        assert_eq!(
+            matrix.entries.len(),
+            StackType::iter().count() * PG_MAJOR_VERSIONS.len()
+        );
+
+        // Every stack supports the default Postgres version
+        for stack in StackType::iter() {
+            let entry = matrix.get(&stack, 16).expect("missing matrix entry");
+            assert!(entry.image.is_some(), "{} has no image for 16", stack);
+            assert!(entry.extensions.iter().all(|e| e.available.is_none()));
+            assert!(matrix.validate(&stack, 16).is_ok());
+        }
+        assert!(matrix.validate(&StackType::Standard, 13).is_err());
+    }
+
+    #[test]
+    fn test_compatibility_matrix_with_trunk_metadata() {
+        let mq = get_stack(StackType::MessageQueue);
+        let pgmq = mq
+            .trunk_installs
+            .unwrap()
+            .into_iter()
+            .find(|install| install.name == "pgmq")
+            .expect("missing pgmq trunk install");
+
+        let mut trunk = TrunkMetadata::default();
+        trunk.add("pgmq", pgmq.version.as_deref().unwrap(), vec![14, 15, 16]);
+        let matrix = compatibility_matrix(Some(&trunk));
+
+        let pgmq_on = |pg_version| {
+            matrix
+                .get(&StackType::MessageQueue, pg_version)
+                .unwrap()
+                .extensions
+                .iter()
+                .find(|e| e.name == "pgmq")
+                .unwrap()
+                .available
+        };
+        assert_eq!(pgmq_on(16), Some(true));
+        assert_eq!(pgmq_on(17), Some(false));
+        assert!(matrix.validate(&StackType::MessageQueue, 16).is_ok());
+        let errors = matrix
+            .validate(&StackType::MessageQueue, 17)
+            .expect_err("pgmq is not available for 17");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("pgmq"));
+    }
+}